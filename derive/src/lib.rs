@@ -21,6 +21,10 @@ fn impl_identifier(ast: &syn::DeriveInput) -> TokenStream {
             fn new(id: Id) -> Self {
                 Self(id)
             }
+
+            fn id(&self) -> Id {
+                self.0
+            }
         }
     };
     gen.into()
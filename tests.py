@@ -232,7 +232,7 @@ def build_tests(base, tests) -> List[TestBuild]:
         out_path = reduce(Path.joinpath, test_file.parts[depth:], out_dir).with_suffix(".wasm")
         out_path.parent.mkdir(parents=True, exist_ok=True)
 
-        command = [str(zephyr), str(test_file), '-o', str(out_path)]
+        command = [str(zephyr), 'build', str(test_file), '-o', str(out_path)]
         completed = subprocess.run(
             command,
             stdout=subprocess.PIPE,
@@ -4,10 +4,15 @@ use super::ast::AsmStatement;
 use super::opcode_to_asm::{opcode_to_asm, Argument};
 use crate::ctx::ModId;
 use crate::error::{ErrorHandler, Location};
+use crate::resolver::FileId;
+
+use std::collections::HashMap;
 
 enum Declaration {
     Expose(ast::Expose),
     Fun(ast::Function),
+    Memory(ast::Memory),
+    Macro(String, Vec<AsmStatement>),
 }
 
 /// Zephyr assembly parser, it consumes tokens to produces MIR.
@@ -16,15 +21,36 @@ pub struct Parser<'err, E: ErrorHandler> {
     tokens: Vec<Token>,
     current: usize, // current token index
     mod_id: ModId,
+    f_id: FileId,
+    next_node: u32, // index of the next node ID
+    macros: HashMap<String, Vec<AsmStatement>>,
 }
 
 impl<'err, E: ErrorHandler> Parser<'err, E> {
-    pub fn new(tokens: Vec<Token>, mod_id: ModId, error_handler: &'err mut E) -> Self {
+    pub fn new(
+        tokens: Vec<Token>,
+        f_id: FileId,
+        mod_id: ModId,
+        error_handler: &'err mut E,
+    ) -> Self {
         Parser {
             err: error_handler,
             tokens,
             current: 0,
             mod_id,
+            f_id,
+            next_node: 0,
+            macros: HashMap::new(),
+        }
+    }
+
+    /// Returns a fresh node ID.
+    fn node_id(&mut self) -> ast::NodeId {
+        let index = self.next_node;
+        self.next_node += 1;
+        ast::NodeId {
+            f_id: self.f_id,
+            index,
         }
     }
 
@@ -32,6 +58,7 @@ impl<'err, E: ErrorHandler> Parser<'err, E> {
     pub fn parse(&mut self) -> ast::Program {
         let mut funs = Vec::new();
         let mut exposed = Vec::new();
+        let mut memories = Vec::new();
 
         let module = match self.module() {
             Ok(pkg) => pkg,
@@ -52,6 +79,10 @@ impl<'err, E: ErrorHandler> Parser<'err, E> {
                 Ok(decl) => match decl {
                     Declaration::Expose(e) => exposed.push(e),
                     Declaration::Fun(fun) => funs.push(fun),
+                    Declaration::Memory(memory) => memories.push(memory),
+                    Declaration::Macro(ident, stmts) => {
+                        self.macros.insert(ident, stmts);
+                    }
                 },
                 Err(()) => self.err.silent_report(),
             }
@@ -64,6 +95,9 @@ impl<'err, E: ErrorHandler> Parser<'err, E> {
             structs: vec![],
             imports: vec![],
             used: vec![],
+            inits: vec![],
+            memories,
+            attributes: vec![],
         }
     }
 
@@ -90,6 +124,11 @@ impl<'err, E: ErrorHandler> Parser<'err, E> {
         token
     }
 
+    /// Shows the previous token (already consumed)
+    fn previous(&self) -> &Token {
+        &self.tokens[self.current.saturating_sub(1)]
+    }
+
     fn back(&mut self) {
         if self.current > 0 {
             self.current -= 1;
@@ -107,6 +146,18 @@ impl<'err, E: ErrorHandler> Parser<'err, E> {
         }
     }
 
+    /// Same as `next_match` for the words which are keywords only where they are expected, such
+    /// as `memory` at the start of a declaration, and identifiers everywhere else.
+    fn next_match_contextual(&mut self, keyword: &str) -> bool {
+        match self.peek().t {
+            TokenType::Identifier(ref ident) if ident == keyword => {
+                self.advance();
+                true
+            }
+            _ => false,
+        }
+    }
+
     /// Same as `next_match` but report an error if the token doesn't match
     fn next_match_report(&mut self, t: TokenType, err: &str) -> bool {
         if self.peek().t == t {
@@ -148,6 +199,11 @@ impl<'err, E: ErrorHandler> Parser<'err, E> {
         } else {
             ast::ModuleType::Standard
         };
+        let module_kind = if self.next_match_contextual("runtime") {
+            ast::ModuleKind::Runtime
+        } else {
+            ast::ModuleKind::Module
+        };
         if !self.next_match_report(
             TokenType::Module,
             "File must start with a 'module' declaration.",
@@ -172,7 +228,7 @@ impl<'err, E: ErrorHandler> Parser<'err, E> {
             name,
             loc,
             t: module_type,
-            kind: ast::ModuleKind::Module,
+            kind: module_kind,
         })
     }
 
@@ -184,10 +240,21 @@ impl<'err, E: ErrorHandler> Parser<'err, E> {
         if self.next_match(TokenType::Expose) {
             return Ok(Declaration::Expose(self.expose()?));
         }
+        // Memory declaration
+        let loc = self.peek().loc;
+        if self.next_match_contextual("memory") {
+            return Ok(Declaration::Memory(self.memory(loc)?));
+        }
+        // Macro declaration
+        if self.next_match(TokenType::Macro) {
+            let (ident, stmts) = self.macro_()?;
+            return Ok(Declaration::Macro(ident, stmts));
+        }
         // Fun declaration
         let is_pub = self.next_match(TokenType::Pub);
+        let keyword_loc = self.peek().loc;
         if self.next_match(TokenType::Fun) {
-            let mut fun = self.function()?;
+            let mut fun = self.function(keyword_loc)?;
             fun.is_pub = is_pub;
             return Ok(Declaration::Fun(fun));
         }
@@ -195,7 +262,7 @@ impl<'err, E: ErrorHandler> Parser<'err, E> {
         let loc = self.peek().loc;
         self.err.report(
             loc,
-            String::from("Expected a top level declaration: `expose` or `fun`"),
+            String::from("Expected a top level declaration: `expose`, `memory`, `macro` or `fun`"),
         );
         self.synchronize();
         Err(())
@@ -213,18 +280,25 @@ impl<'err, E: ErrorHandler> Parser<'err, E> {
             // Check for `as` keyword
             let alias = if self.next_match(TokenType::As) {
                 let token = self.peek();
-                if let TokenType::Identifier(ref as_ident) = token.t {
-                    let as_ident = as_ident.clone();
-                    self.advance();
-                    Some(as_ident)
-                } else {
-                    let loc = token.loc;
-                    self.err.report(
-                        loc,
-                        String::from("Expected an identifier after 'as' keyword."),
-                    );
-                    self.synchronize();
-                    return Err(());
+                match token.t {
+                    TokenType::Identifier(ref as_ident) | TokenType::StringLit(ref as_ident)
+                        if !as_ident.is_empty() =>
+                    {
+                        let as_ident = as_ident.clone();
+                        self.advance();
+                        Some(as_ident)
+                    }
+                    _ => {
+                        let loc = token.loc;
+                        self.err.report(
+                            loc,
+                            String::from(
+                                "Expected an identifier or a non-empty string after 'as' keyword.",
+                            ),
+                        );
+                        self.synchronize();
+                        return Err(());
+                    }
                 }
             } else {
                 None
@@ -233,7 +307,10 @@ impl<'err, E: ErrorHandler> Parser<'err, E> {
             return Ok(ast::Expose {
                 ident: fun_name,
                 alias,
+                attributes: Vec::new(),
+                id: self.node_id(),
                 loc,
+                ident_loc: loc,
             });
         }
         self.err.report(
@@ -244,9 +321,48 @@ impl<'err, E: ErrorHandler> Parser<'err, E> {
         Err(())
     }
 
+    /// Parses the 'memory' grammar element: the initial number of pages, followed by an
+    /// optional maximum and the `shared` keyword.
+    /// The `memory` keyword must have been consumed.
+    fn memory(&mut self, loc: Location) -> Result<ast::Memory, ()> {
+        let initial = match self.page_count() {
+            Some(initial) => initial,
+            None => {
+                let loc = self.peek().loc;
+                self.err.report(
+                    loc,
+                    String::from("Expected a number of pages after 'memory' keyword."),
+                );
+                self.synchronize();
+                return Err(());
+            }
+        };
+        let max = self.page_count();
+        let shared = self.next_match_contextual("shared");
+        self.consume_semi_colon();
+        Ok(ast::Memory {
+            initial,
+            max,
+            shared,
+            loc,
+        })
+    }
+
+    /// Consumes a number of memory pages, if any.
+    fn page_count(&mut self) -> Option<u32> {
+        if let TokenType::NumberLit(n) = self.peek().t {
+            if n <= u32::MAX as u64 {
+                self.advance();
+                return Some(n as u32);
+            }
+        }
+        None
+    }
+
     /// Parses the 'function' grammar element
-    /// The `Pub` (if any) and `Fun` tokens must have been consumed.
-    fn function(&mut self) -> Result<ast::Function, ()> {
+    /// The `Pub` (if any) and `Fun` tokens must have been consumed, `keyword_loc` is the location
+    /// of the `Fun` token.
+    fn function(&mut self, keyword_loc: Location) -> Result<ast::Function, ()> {
         let loc = self.peek().loc;
         let token = self.advance();
         let ident = match token.t {
@@ -282,6 +398,7 @@ impl<'err, E: ErrorHandler> Parser<'err, E> {
         };
 
         let stmts = self.block()?;
+        let span = keyword_loc.merge(self.previous().loc);
         self.consume_semi_colon();
 
         Ok(ast::Function {
@@ -289,11 +406,42 @@ impl<'err, E: ErrorHandler> Parser<'err, E> {
             params,
             result,
             body: ast::Body::Asm(stmts),
+            attributes: Vec::new(),
             is_pub: false, // handled by the called who may have consumed the "pub" keyword
-            loc,           // location of the identifier
+            id: self.node_id(),
+            loc, // location of the identifier
+            keyword_loc,
+            span,
         })
     }
 
+    /// Parses the 'macro' grammar element
+    /// The `Macro` token must have been consumed.
+    fn macro_(&mut self) -> Result<(String, Vec<AsmStatement>), ()> {
+        let token = self.advance();
+        let loc = token.loc;
+        let ident = match token.t {
+            TokenType::Identifier(ref x) => x.clone(),
+            _ => {
+                self.err.report(
+                    loc,
+                    String::from("Identifier expected after `macro` keyword."),
+                );
+                self.synchronize();
+                return Err(());
+            }
+        };
+        if self.macros.contains_key(&ident) {
+            self.err
+                .report(loc, format!("Macro '{}' is already defined.", ident));
+            self.synchronize();
+            return Err(());
+        }
+        let stmts = self.block()?;
+        self.consume_semi_colon();
+        Ok((ident, stmts))
+    }
+
     /// Parses the 'parameters' grammar element
     fn parameters(&mut self) -> Result<Vec<ast::Parameter>, ()> {
         let mut params = Vec::new();
@@ -325,11 +473,8 @@ impl<'err, E: ErrorHandler> Parser<'err, E> {
 
             params.push(ast::Parameter {
                 ident,
-                t: ast::Type::Simple(ast::Path {
-                    root: t,
-                    path: vec![],
-                    loc,
-                }),
+                t: self.simple_type(t, loc),
+                id: self.node_id(),
                 loc: var_loc,
             });
             if !self.next_match(TokenType::Comma) {
@@ -353,10 +498,18 @@ impl<'err, E: ErrorHandler> Parser<'err, E> {
             return Err(());
         }
         // Statements
-        while let TokenType::Opcode(_) = self.peek().t {
-            match self.statement() {
-                Ok(stmt) => stmts.push(stmt),
-                Err(_) => self.synchronize(),
+        loop {
+            match self.peek().t {
+                // The statement ender is consumed even if the statement is invalid
+                TokenType::Opcode(_) => match self.statement() {
+                    Ok(stmt) => stmts.push(stmt),
+                    Err(_) => self.err.silent_report(),
+                },
+                TokenType::Identifier(_) => match self.macro_expansion() {
+                    Ok(expansion) => stmts.extend(expansion),
+                    Err(_) => self.synchronize(),
+                },
+                _ => break,
             }
         }
         // Right brace
@@ -383,7 +536,22 @@ impl<'err, E: ErrorHandler> Parser<'err, E> {
                 let token = self.peek();
                 let arg_loc = token.loc;
                 let arg = match token.t {
-                    TokenType::NumberLit(n) => Some(Argument::Integer(n, arg_loc)),
+                    TokenType::NumberLit(n) => Some(Argument::Integer(n as i128, arg_loc)),
+                    TokenType::Minus => {
+                        self.advance(); // Consume the minus sign
+                        let token = self.peek();
+                        let number_loc = token.loc;
+                        if let TokenType::NumberLit(n) = token.t {
+                            Some(Argument::Integer(-(n as i128), arg_loc.merge(number_loc)))
+                        } else {
+                            self.err.report(
+                                number_loc,
+                                String::from("Expected a number after a minus sign."),
+                            );
+                            self.synchronize();
+                            return Err(());
+                        }
+                    }
                     TokenType::Identifier(ref s) => {
                         let ident = s.clone();
                         Some(Argument::Identifier(ident, arg_loc))
@@ -411,6 +579,35 @@ impl<'err, E: ErrorHandler> Parser<'err, E> {
         }
     }
 
+    /// Expands a macro invocation into the statements of its body. The instructions of the body
+    /// keep their location so that errors point inside the macro definition.
+    fn macro_expansion(&mut self) -> Result<Vec<AsmStatement>, ()> {
+        let token = self.advance();
+        let loc = token.loc;
+        let ident = match token.t {
+            TokenType::Identifier(ref x) => x.clone(),
+            _ => {
+                self.err.report(loc, String::from("Expected a macro name."));
+                return Err(());
+            }
+        };
+        let stmts = match self.macros.get(&ident) {
+            Some(stmts) => stmts.clone(),
+            None => {
+                self.err.report(
+                    loc,
+                    format!(
+                        "Unknown macro '{}', macros must be defined before use.",
+                        ident
+                    ),
+                );
+                return Err(());
+            }
+        };
+        self.consume_semi_colon();
+        Ok(stmts)
+    }
+
     /// Parses the 'type' grammar element.
     ///
     /// TODO: can probably be re-factored
@@ -420,11 +617,10 @@ impl<'err, E: ErrorHandler> Parser<'err, E> {
             let token = self.advance();
             let loc = token.loc;
             let t = match &token.t {
-                TokenType::Identifier(ident) => ast::Type::Simple(ast::Path {
-                    root: ident.clone(),
-                    path: vec![],
-                    loc,
-                }),
+                TokenType::Identifier(ident) => {
+                    let root = ident.clone();
+                    self.simple_type(root, loc)
+                }
                 _ => {
                     self.err.report(loc, String::from("Expected type"));
                     self.back();
@@ -436,11 +632,10 @@ impl<'err, E: ErrorHandler> Parser<'err, E> {
                 let token = self.advance();
                 let loc = token.loc;
                 let t = match &token.t {
-                    TokenType::Identifier(ident) => ast::Type::Simple(ast::Path {
-                        root: ident.clone(),
-                        path: vec![],
-                        loc,
-                    }),
+                    TokenType::Identifier(ident) => {
+                        let root = ident.clone();
+                        self.simple_type(root, loc)
+                    }
                     _ => {
                         self.err.report(loc, String::from("Expected type"));
                         self.back();
@@ -455,11 +650,10 @@ impl<'err, E: ErrorHandler> Parser<'err, E> {
             let token = self.advance();
             let loc = token.loc;
             match &token.t {
-                TokenType::Identifier(ident) => Ok(ast::Type::Simple(ast::Path {
-                    root: ident.clone(),
-                    path: vec![],
-                    loc,
-                })),
+                TokenType::Identifier(ident) => {
+                    let root = ident.clone();
+                    Ok(self.simple_type(root, loc))
+                }
                 _ => {
                     self.back();
                     self.err.report(loc, String::from("Expected type"));
@@ -468,4 +662,14 @@ impl<'err, E: ErrorHandler> Parser<'err, E> {
             }
         }
     }
+
+    /// Returns a type made of a single identifier.
+    fn simple_type(&mut self, root: String, loc: Location) -> ast::Type {
+        ast::Type::Simple(ast::Path {
+            root,
+            path: vec![],
+            id: self.node_id(),
+            loc,
+        })
+    }
 }
@@ -1,4 +1,5 @@
 use super::asm_tokens::*;
+use super::termination::{TerminationPolicy, Terminator, TokenClass};
 use crate::error::{ErrorHandler, Location};
 use crate::resolver::FileId;
 use std::collections::HashMap;
@@ -6,25 +7,23 @@ use std::collections::HashMap;
 const RADIX: u32 = 10;
 
 /// Zephyr Assembly Scanner, it produces tokens from source code.
-pub struct Scanner<'err, E: ErrorHandler> {
+///
+/// The code is walked by byte offsets (`start` and `current`), always kept on char boundaries,
+/// which are also used for locations.
+pub struct Scanner<'err, 'code, E: ErrorHandler> {
     err: &'err mut E,
     f_id: FileId,
-    code: Vec<char>,
+    code: &'code str,
     start: usize,
     current: usize,
     keywords: HashMap<String, TokenType>,
-    stmt_ender: bool,
+    terminator: Terminator,
 }
 
-impl<'err, E: ErrorHandler> Scanner<'err, E> {
-    // f_id MUST exist, no check performed.
-    pub fn new(f_id: FileId, error_handler: &'err mut E) -> Self {
+impl<'err, 'code, E: ErrorHandler> Scanner<'err, 'code, E> {
+    pub fn new(f_id: FileId, code: &'code str, error_handler: &'err mut E) -> Self {
         let keywords = get_keyword_map();
 
-        // f_id MUST exist
-        let code = error_handler.get_file(f_id).unwrap();
-        let code = code.chars().collect(); // TODO: remove this copy
-
         Scanner {
             err: error_handler,
             f_id,
@@ -32,7 +31,7 @@ impl<'err, E: ErrorHandler> Scanner<'err, E> {
             start: 0,
             current: 0,
             keywords,
-            stmt_ender: false,
+            terminator: Terminator::new(TerminationPolicy::EveryLine),
         }
     }
 
@@ -56,6 +55,7 @@ impl<'err, E: ErrorHandler> Scanner<'err, E> {
             '}' => self.add_token(tokens, TokenType::RightBrace),
             ':' => self.add_token(tokens, TokenType::Colon),
             ',' => self.add_token(tokens, TokenType::Comma),
+            '-' => self.add_token(tokens, TokenType::Minus),
             '/' => {
                 // Ignore comments
                 if self.next_match('/') {
@@ -68,10 +68,9 @@ impl<'err, E: ErrorHandler> Scanner<'err, E> {
                 }
             }
             '\n' => {
-                if self.stmt_ender {
+                if self.terminator.line_break(&self.code[self.current..]) {
                     self.add_token(tokens, TokenType::SemiColon)
                 }
-                self.stmt_ender = false;
             }
             ' ' | '\t' | '\r' => (),
             c => {
@@ -107,13 +106,15 @@ impl<'err, E: ErrorHandler> Scanner<'err, E> {
     /// Advance the cursor by one character and return it.
     fn advance(&mut self) -> char {
         let c = self.peek();
-        self.current += 1;
+        if !self.is_at_end() {
+            self.current += c.len_utf8();
+        }
         c
     }
 
-    /// Return the next character without consuming it.
+    /// Return the next character without consuming it, or '\0' at the end of the file.
     fn peek(&self) -> char {
-        self.code[self.current]
+        self.code[self.current..].chars().next().unwrap_or('\0')
     }
 
     /// If the next character match `c`, consume it and return true.
@@ -121,28 +122,26 @@ impl<'err, E: ErrorHandler> Scanner<'err, E> {
     fn next_match(&mut self, c: char) -> bool {
         if self.is_at_end() {
             false
-        } else if self.code[self.current] != c {
+        } else if self.peek() != c {
             false
         } else {
-            self.current += 1;
+            self.advance();
             true
         }
     }
 
     /// Add a fresh token of type `t`.
     fn add_token(&mut self, tokens: &mut Vec<Token>, t: TokenType) {
-        match t {
-            TokenType::SemiColon => self.stmt_ender = false,
-            TokenType::LeftBrace => self.stmt_ender = false,
-            _ => self.stmt_ender = true,
-        }
+        let class = match t {
+            TokenType::SemiColon => TokenClass::Separator,
+            TokenType::LeftBrace => TokenClass::OpenBlock,
+            TokenType::RightBrace => TokenClass::CloseBlock,
+            _ => TokenClass::End,
+        };
+        self.terminator.token(class);
         let token = Token {
             t,
-            loc: Location {
-                pos: self.start as u32,
-                len: (self.current - self.start) as u32,
-                f_id: self.f_id,
-            },
+            loc: self.get_loc(),
         };
         tokens.push(token);
     }
@@ -150,23 +149,21 @@ impl<'err, E: ErrorHandler> Scanner<'err, E> {
     /// Parse a number
     fn number(&mut self, tokens: &mut Vec<Token>) {
         let mut radix = RADIX;
+        let mut digits_start = self.start;
         if self.peek() == 'x' {
             radix = 16;
             self.advance();
-            self.start = self.current;
+            digits_start = self.current;
         } else if self.peek() == 'b' {
             radix = 2;
             self.advance();
-            self.start = self.current;
+            digits_start = self.current;
         }
-        while !self.is_at_end() && self.peek().is_digit(RADIX) {
+        while !self.is_at_end() && self.peek().is_digit(radix) {
             self.advance();
         }
-        let str_val = self.code[self.start..self.current]
-            .iter()
-            .cloned()
-            .collect::<String>();
-        match u64::from_str_radix(&str_val, radix) {
+        let str_val = &self.code[digits_start..self.current];
+        match u64::from_str_radix(str_val, radix) {
             Ok(n) => self.add_token(tokens, TokenType::NumberLit(n)),
             Err(_) => self.err.report(
                 self.get_loc(),
@@ -177,14 +174,23 @@ impl<'err, E: ErrorHandler> Scanner<'err, E> {
 
     /// Parse a string
     fn string(&mut self, tokens: &mut Vec<Token>) {
-        while !self.is_at_end() && self.peek() != '"' {
+        while !self.is_at_end() && self.peek() != '"' && self.peek() != '\n' {
             self.advance();
         }
-        self.advance(); // Consume the closing '"'
-        let str_val = self.code[(self.start + 1)..(self.current - 1)]
-            .iter()
-            .cloned()
-            .collect::<String>();
+        let end = self.current;
+        if !self.next_match('"') {
+            // The line break, if any, is left for the statement ender.
+            let loc = Location {
+                pos: self.start as u32,
+                len: 1,
+                f_id: self.f_id,
+            };
+            self.err.report(
+                loc,
+                String::from("Unterminated string literal, strings must end on the same line"),
+            );
+        }
+        let str_val = self.code[(self.start + 1)..end].to_string();
         self.add_token(tokens, TokenType::StringLit(str_val));
     }
 
@@ -195,16 +201,13 @@ impl<'err, E: ErrorHandler> Scanner<'err, E> {
         {
             self.advance();
         }
-        let ident = self.code[self.start..self.current]
-            .iter()
-            .cloned()
-            .collect::<String>();
-        match self.keywords.get(&ident) {
+        let ident = &self.code[self.start..self.current];
+        match self.keywords.get(ident) {
             Some(t) => {
                 let t = t.clone();
                 self.add_token(tokens, t);
             }
-            None => self.add_token(tokens, TokenType::Identifier(ident)),
+            None => self.add_token(tokens, TokenType::Identifier(ident.to_string())),
         }
     }
 }
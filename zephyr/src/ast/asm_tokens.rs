@@ -1,4 +1,5 @@
 use crate::error::Location;
+use crate::mir::{RmwOp, Shape, Type};
 use std::collections::HashMap;
 use std::fmt;
 
@@ -11,6 +12,7 @@ pub enum TokenType {
     RightBrace,
     Comma,
     Colon,
+    Minus,
     // Literals
     Identifier(String),
     StringLit(String),
@@ -24,6 +26,7 @@ pub enum TokenType {
     Pub,
     Module,
     Standalone,
+    Macro,
 
     // Other
     SemiColon,
@@ -56,6 +59,22 @@ pub enum Opcode {
     F32Store,
     F64Load,
     F64Store,
+    V128Load,
+    V128Store,
+    // SIMD
+    Splat(Shape),
+    ExtractLane(Shape),
+    ReplaceLane(Shape),
+    // Atomics, on `i32` or `i64`
+    AtomicLoad(Type),
+    AtomicStore(Type),
+    AtomicRmw(RmwOp, Type),
+    AtomicCmpxchg(Type),
+    AtomicNotify,
+    AtomicWait(Type),
+    AtomicFence,
+    // Raw bytes
+    Raw,
 }
 
 pub struct Token {
@@ -73,6 +92,7 @@ pub fn get_keyword_map() -> HashMap<String, TokenType> {
         (String::from("pub"), TokenType::Pub),
         (String::from("module"), TokenType::Module),
         (String::from("standalone"), TokenType::Standalone),
+        (String::from("macro"), TokenType::Macro),
         // Opcodes
         (String::from("drop"), to_token(Opcode::Drop)),
         (String::from("return"), to_token(Opcode::Return)),
@@ -93,9 +113,40 @@ pub fn get_keyword_map() -> HashMap<String, TokenType> {
         (String::from("f32.store"), to_token(Opcode::F32Store)),
         (String::from("f64.load"), to_token(Opcode::F64Load)),
         (String::from("f64.store"), to_token(Opcode::F64Store)),
+        (String::from("v128.load"), to_token(Opcode::V128Load)),
+        (String::from("v128.store"), to_token(Opcode::V128Store)),
+        (
+            String::from("memory.atomic.notify"),
+            to_token(Opcode::AtomicNotify),
+        ),
+        (String::from("atomic.fence"), to_token(Opcode::AtomicFence)),
+        (String::from("raw"), to_token(Opcode::Raw)),
     ]
     .iter()
     .cloned()
+    .chain(Shape::ALL.iter().flat_map(|shape| {
+        [
+            Opcode::Splat(*shape),
+            Opcode::ExtractLane(*shape),
+            Opcode::ReplaceLane(*shape),
+        ]
+        .iter()
+        .map(|op| (op.to_string(), to_token(*op)))
+        .collect::<Vec<_>>()
+    }))
+    .chain([Type::I32, Type::I64].iter().flat_map(|t| {
+        [
+            Opcode::AtomicLoad(*t),
+            Opcode::AtomicStore(*t),
+            Opcode::AtomicCmpxchg(*t),
+            Opcode::AtomicWait(*t),
+        ]
+        .iter()
+        .copied()
+        .chain(RmwOp::ALL.iter().map(|op| Opcode::AtomicRmw(*op, *t)))
+        .map(|op| (op.to_string(), to_token(op)))
+        .collect::<Vec<_>>()
+    }))
     .collect()
 }
 
@@ -114,6 +165,7 @@ impl fmt::Display for Token {
             TokenType::RightBrace => write!(f, "}}"),
             TokenType::Comma => write!(f, ","),
             TokenType::Colon => write!(f, ":"),
+            TokenType::Minus => write!(f, "-"),
             TokenType::SemiColon => write!(f, ";"),
             TokenType::EOF => write!(f, "EOF"),
             // Keywords
@@ -123,6 +175,7 @@ impl fmt::Display for Token {
             TokenType::Pub => write!(f, "pub"),
             TokenType::Module => write!(f, "module"),
             TokenType::Standalone => write!(f, "standalone"),
+            TokenType::Macro => write!(f, "macro"),
             // Literals
             TokenType::Identifier(ref ident) => write!(f, "'{}'", ident),
             TokenType::StringLit(ref s) => write!(f, "\"{}\"", s),
@@ -155,6 +208,25 @@ impl fmt::Display for Opcode {
             Opcode::F32Store => write!(f, "f32.store"),
             Opcode::F64Load => write!(f, "f64.load"),
             Opcode::F64Store => write!(f, "f64.store"),
+            Opcode::V128Load => write!(f, "v128.load"),
+            Opcode::V128Store => write!(f, "v128.store"),
+            Opcode::Splat(shape) => write!(f, "{}.splat", shape),
+            Opcode::ExtractLane(shape) => match shape {
+                Shape::I8x16 | Shape::I16x8 => write!(f, "{}.extract_lane_u", shape),
+                _ => write!(f, "{}.extract_lane", shape),
+            },
+            Opcode::ReplaceLane(shape) => write!(f, "{}.replace_lane", shape),
+            Opcode::AtomicLoad(t) => write!(f, "{}.atomic.load", t),
+            Opcode::AtomicStore(t) => write!(f, "{}.atomic.store", t),
+            Opcode::AtomicRmw(op, t) => write!(f, "{}.atomic.rmw.{}", t, op.name()),
+            Opcode::AtomicCmpxchg(t) => write!(f, "{}.atomic.rmw.cmpxchg", t),
+            Opcode::AtomicNotify => write!(f, "memory.atomic.notify"),
+            Opcode::AtomicWait(t) => match t {
+                Type::I64 => write!(f, "memory.atomic.wait64"),
+                _ => write!(f, "memory.atomic.wait32"),
+            },
+            Opcode::AtomicFence => write!(f, "atomic.fence"),
+            Opcode::Raw => write!(f, "raw"),
         }
     }
 }
@@ -1,12 +1,25 @@
 use std::fmt;
 
+use super::scan::is_identifier;
 pub use crate::ctx::ModId;
 use crate::error::Location;
-use crate::mir::Value as MirValue;
+use crate::mir::{Atomic as MirAtomic, Simd as MirSimd, Value as MirValue};
+use crate::resolver::FileId;
 pub use crate::resolver::ModulePath;
 
 // ——————————————————————————————— Zephyr AST —————————————————————————————— //
 
+/// Identifies a node of the AST, the IDs are given by the parser and unique across the files of a
+/// compilation.
+///
+/// Tools map the nodes to the HIR entities they declare or refer to through the node map built
+/// during name resolution, see `Ctx::get_node_at`.
+#[derive(Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Copy, Clone)]
+pub struct NodeId {
+    pub f_id: FileId,
+    pub index: u32,
+}
+
 /// A module type describes how the module is organised in the filesystem.
 #[derive(Clone)]
 pub enum ModuleType {
@@ -24,18 +37,22 @@ pub enum ModuleKind {
 pub enum Value {
     Integer {
         val: u64,
+        id: NodeId,
         loc: Location,
     },
     Float {
         val: f64,
+        id: NodeId,
         loc: Location,
     },
     Boolean {
         val: bool,
+        id: NodeId,
         loc: Location,
     },
     Str {
         val: String,
+        id: NodeId,
         loc: Location,
     },
     Struct {
@@ -43,10 +60,12 @@ pub enum Value {
         namespace: Option<ModId>,
         ident: String,
         fields: Vec<FieldValue>,
+        id: NodeId,
         loc: Location,
     },
     Tuple {
         values: Vec<Expression>,
+        id: NodeId,
         loc: Location,
     },
 }
@@ -54,6 +73,7 @@ pub enum Value {
 pub struct FieldValue {
     pub ident: String,
     pub expr: Expression,
+    pub id: NodeId,
     pub loc: Location,
 }
 
@@ -81,11 +101,17 @@ pub enum BinaryOperator {
 pub enum UnaryOperator {
     Minus,
     Not,
+    /// `&x`, the address of a place.
+    Reference,
+    /// `*p`, the value behind a reference.
+    Dereference,
 }
 
+#[derive(Clone)]
 pub struct Parameter {
     pub ident: String,
     pub t: Type,
+    pub id: NodeId,
     pub loc: Location,
 }
 
@@ -93,7 +119,9 @@ pub struct Variable {
     /// Namespaces are reserver for HIR resolution
     pub namespace: Option<ModId>,
     pub ident: String,
-    pub t: Option<String>,
+    /// The type annotation of a variable declared by a let statement, if any.
+    pub t: Option<Type>,
+    pub id: NodeId,
     pub loc: Location,
 }
 
@@ -104,19 +132,87 @@ pub enum Expression {
         expr_left: Box<Expression>,
         binop: BinaryOperator,
         expr_right: Box<Expression>,
+        id: NodeId,
+        loc: Location,
     },
     Unary {
         unop: UnaryOperator,
         expr: Box<Expression>,
+        id: NodeId,
+        loc: Location,
     },
     Call {
         fun: Box<Expression>,
         args: Vec<Expression>,
+        id: NodeId,
+        loc: Location,
     },
     Access {
         namespace: Box<Expression>,
         field: Box<Expression>,
+        id: NodeId,
+        loc: Location,
     },
+    Lambda(Lambda),
+}
+
+impl Value {
+    pub fn id(&self) -> NodeId {
+        match self {
+            Value::Integer { id, .. }
+            | Value::Float { id, .. }
+            | Value::Boolean { id, .. }
+            | Value::Str { id, .. }
+            | Value::Struct { id, .. }
+            | Value::Tuple { id, .. } => *id,
+        }
+    }
+
+    pub fn get_loc(&self) -> Location {
+        match self {
+            Value::Integer { loc, .. }
+            | Value::Float { loc, .. }
+            | Value::Boolean { loc, .. }
+            | Value::Str { loc, .. }
+            | Value::Struct { loc, .. }
+            | Value::Tuple { loc, .. } => *loc,
+        }
+    }
+}
+
+impl Expression {
+    pub fn id(&self) -> NodeId {
+        match self {
+            Expression::Variable(var) => var.id,
+            Expression::Literal(value) => value.id(),
+            Expression::Binary { id, .. }
+            | Expression::Unary { id, .. }
+            | Expression::Call { id, .. }
+            | Expression::Access { id, .. } => *id,
+            Expression::Lambda(lambda) => lambda.id,
+        }
+    }
+
+    pub fn get_loc(&self) -> Location {
+        match self {
+            Expression::Variable(var) => var.loc,
+            Expression::Literal(value) => value.get_loc(),
+            Expression::Binary { loc, .. }
+            | Expression::Unary { loc, .. }
+            | Expression::Call { loc, .. }
+            | Expression::Access { loc, .. } => *loc,
+            Expression::Lambda(lambda) => lambda.loc,
+        }
+    }
+}
+
+/// An anonymous function, such as `fun(x: i32): i32 { return x + n }`.
+pub struct Lambda {
+    pub params: Vec<Parameter>,
+    pub result: Option<Type>,
+    pub body: Block,
+    pub id: NodeId,
+    pub loc: Location,
 }
 
 pub enum Statement {
@@ -142,6 +238,10 @@ pub enum Statement {
         expr: Option<Expression>,
         loc: Location,
     },
+    DeferStmt {
+        expr: Expression,
+        loc: Location,
+    },
 }
 
 pub enum Declaration {
@@ -150,6 +250,7 @@ pub enum Declaration {
     Expose(Expose),
     Imports(Imports),
     Struct(Struct),
+    Init(Init),
 }
 
 pub struct Program {
@@ -161,6 +262,12 @@ pub struct Program {
     ///Functions imported from the host runtime.
     pub imports: Vec<Imports>,
     pub used: Vec<Use>,
+    /// Initialization blocks, run once before any exposed function is called.
+    pub inits: Vec<Init>,
+    /// Memory declarations of runtime modules.
+    pub memories: Vec<Memory>,
+    /// Attributes of the module declaration, such as `#[export_memory("memory")]`.
+    pub attributes: Vec<Attribute>,
 }
 
 impl Program {
@@ -175,6 +282,9 @@ impl Program {
         self.exposed.extend(other.exposed);
         self.imports.extend(other.imports);
         self.used.extend(other.used);
+        self.inits.extend(other.inits);
+        self.memories.extend(other.memories);
+        self.attributes.extend(other.attributes);
     }
 }
 
@@ -187,23 +297,30 @@ pub struct Module {
     pub kind: ModuleKind,
 }
 
+#[derive(Clone)]
 pub struct Imports {
     pub from: String,
     pub prototypes: Vec<FunctionPrototype>,
+    pub attributes: Vec<Attribute>,
     pub loc: Location,
 }
 
+#[derive(Clone)]
 pub struct Struct {
     pub ident: String,
     pub fields: Vec<StructField>,
     pub is_pub: bool,
+    pub attributes: Vec<Attribute>,
+    pub id: NodeId,
     pub loc: Location,
 }
 
+#[derive(Clone)]
 pub struct StructField {
     pub is_pub: bool,
     pub ident: String,
     pub t: Type,
+    pub id: NodeId,
     pub loc: Location,
 }
 
@@ -213,21 +330,59 @@ pub struct Function {
     pub result: Option<Type>,
     pub body: Body,
     pub is_pub: bool,
+    pub attributes: Vec<Attribute>,
+    pub id: NodeId,
     pub loc: Location,
+    /// Location of the `fun` keyword, `pub` goes right before it.
+    pub keyword_loc: Location,
+    /// Location of the whole function, from the `fun` keyword to the closing brace.
+    pub span: Location,
 }
 
+/// An attribute such as `#[inline]`, arguments are string literals.
+#[derive(Clone)]
+pub struct Attribute {
+    pub ident: String,
+    pub args: Vec<String>,
+    /// Arguments of the form `key = "value"`, such as in `#[cfg(target = "wasi")]`.
+    pub named_args: Vec<(String, String)>,
+    pub loc: Location,
+}
+
+#[derive(Clone)]
 pub struct FunctionPrototype {
     pub ident: String,
     pub alias: Option<String>,
     pub params: Vec<Parameter>,
     pub result: Option<Type>,
     pub is_pub: bool,
+    pub id: NodeId,
     pub loc: Location,
 }
 
 pub struct Expose {
     pub ident: String,
+    /// The name under which the function is exported, either an identifier or any string.
     pub alias: Option<String>,
+    pub attributes: Vec<Attribute>,
+    pub id: NodeId,
+    pub loc: Location,
+    pub ident_loc: Location,
+}
+
+pub struct Init {
+    pub block: Block,
+    pub attributes: Vec<Attribute>,
+    pub id: NodeId,
+    pub loc: Location,
+}
+
+/// Limits of the linear memory, in pages.
+#[derive(Clone)]
+pub struct Memory {
+    pub initial: u32,
+    pub max: Option<u32>,
+    pub shared: bool,
     pub loc: Location,
 }
 
@@ -235,18 +390,50 @@ pub struct Expose {
 pub struct Use {
     pub path: ModulePath,
     pub alias: Option<String>,
+    /// The declarations imported from the module, if any, in which case the module itself is not
+    /// brought in scope.
+    pub items: Option<UseItems>,
+    pub attributes: Vec<Attribute>,
+    pub id: NodeId,
+    pub loc: Location,
+}
+
+/// The declarations imported by a `use` statement.
+#[derive(Clone)]
+pub enum UseItems {
+    /// `use pkg.mod.{sin, cos}`
+    Selected(Vec<UseItem>),
+    /// `use pkg.mod.*`
+    Glob,
+}
+
+#[derive(Clone)]
+pub struct UseItem {
+    pub ident: String,
+    pub id: NodeId,
     pub loc: Location,
 }
 
+#[derive(Clone)]
 pub struct Path {
     pub root: String,
     pub path: Vec<String>,
+    pub id: NodeId,
     pub loc: Location,
 }
 
+#[derive(Clone)]
 pub enum Type {
     Simple(Path),
     Tuple(Vec<Type>, Location),
+    /// The type of functions, such as `fun(i32, i32): i32`.
+    Fun {
+        params: Vec<Type>,
+        result: Option<Box<Type>>,
+        loc: Location,
+    },
+    /// The type of references, such as `&i32`.
+    Ref(Box<Type>, Location),
 }
 
 impl Type {
@@ -254,6 +441,8 @@ impl Type {
         match self {
             Type::Simple(path) => path.loc,
             Type::Tuple(_, loc) => *loc,
+            Type::Fun { loc, .. } => *loc,
+            Type::Ref(_, loc) => *loc,
         }
     }
 }
@@ -269,19 +458,25 @@ pub enum Body {
 
 // ——————————————————————————————— Zephyr ASM —————————————————————————————— //
 
+#[derive(Clone)]
 pub enum AsmStatement {
     Local { local: AsmLocal, loc: Location },
     Const { val: MirValue, loc: Location },
     Control { cntrl: AsmControl, loc: Location },
     Parametric { param: AsmParametric, loc: Location },
     Memory { mem: AsmMemory, loc: Location },
+    Simd { simd: MirSimd, loc: Location },
+    Atomic { atomic: MirAtomic, loc: Location },
+    Raw { bytes: Vec<u8>, loc: Location },
 }
 
+#[derive(Clone)]
 pub enum AsmLocal {
     Get { ident: String, loc: Location },
     Set { ident: String, loc: Location },
 }
 
+#[derive(Clone)]
 pub enum AsmMemory {
     Size,
     Grow,
@@ -295,13 +490,17 @@ pub enum AsmMemory {
     F32Store { align: u32, offset: u32 },
     F64Store { align: u32, offset: u32 },
     I32Store8 { align: u32, offset: u32 },
+    V128Load { align: u32, offset: u32 },
+    V128Store { align: u32, offset: u32 },
 }
 
+#[derive(Clone)]
 pub enum AsmControl {
     Return,
     Unreachable,
 }
 
+#[derive(Clone)]
 pub enum AsmParametric {
     Drop,
 }
@@ -316,6 +515,14 @@ impl fmt::Display for Program {
         // Use
         for is_used in &self.used {
             program.push_str(&format!("use \"{}\"", is_used.path));
+            match is_used.items {
+                Some(UseItems::Selected(ref items)) => {
+                    let items: Vec<&str> = items.iter().map(|item| item.ident.as_str()).collect();
+                    program.push_str(&format!(".{{{}}}", items.join(", ")));
+                }
+                Some(UseItems::Glob) => program.push_str(".*"),
+                None => (),
+            }
             if let Some(ref alias) = is_used.alias {
                 program.push_str(&format!(" as {}", alias));
             }
@@ -327,14 +534,20 @@ impl fmt::Display for Program {
         // Expose
         for expose in &self.exposed {
             program.push_str(&format!("expose {}", expose.ident));
-            if let Some(ref alias) = expose.alias {
-                program.push_str(&format!(" as {}", alias));
+            match &expose.alias {
+                Some(alias) if is_identifier(alias) => program.push_str(&format!(" as {}", alias)),
+                Some(alias) => program.push_str(&format!(" as {:?}", alias)),
+                None => (),
             }
             program.push_str(";\n");
         }
         if self.exposed.len() > 0 {
             program.push_str("\n");
         }
+        // Init
+        for init in &self.inits {
+            program.push_str(&format!("init {};\n\n", init.block));
+        }
         // Fun
         for stmt in &self.funs {
             program.push_str(&format!("{}\n", stmt));
@@ -345,7 +558,14 @@ impl fmt::Display for Program {
 
 impl fmt::Display for Function {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let prefix = if self.is_pub { "pub " } else { "" };
+        let mut prefix = self
+            .attributes
+            .iter()
+            .map(|attribute| format!("{}\n", attribute))
+            .collect::<String>();
+        if self.is_pub {
+            prefix.push_str("pub ");
+        }
         let params = self
             .params
             .iter()
@@ -370,6 +590,27 @@ impl fmt::Display for Function {
     }
 }
 
+impl fmt::Display for Attribute {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.args.is_empty() && self.named_args.is_empty() {
+            write!(f, "#[{}]", self.ident)
+        } else {
+            let named_args = self
+                .named_args
+                .iter()
+                .map(|(key, value)| format!("{} = {:?}", key, value));
+            let args = self
+                .args
+                .iter()
+                .map(|arg| format!("{:?}", arg))
+                .chain(named_args)
+                .collect::<Vec<String>>()
+                .join(", ");
+            write!(f, "#[{}({})]", self.ident, args)
+        }
+    }
+}
+
 impl fmt::Display for Body {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -436,7 +677,7 @@ impl fmt::Display for Expression {
                         .join(", ")
                 ),
             },
-            Expression::Call { fun, args } => write!(
+            Expression::Call { fun, args, .. } => write!(
                 f,
                 "{}({})",
                 fun,
@@ -445,15 +686,34 @@ impl fmt::Display for Expression {
                     .collect::<Vec<String>>()
                     .join(", ")
             ),
-            Expression::Access { namespace, field } => write!(f, "({}.{})", namespace, field),
-            Expression::Unary { unop, expr } => match unop {
+            Expression::Access {
+                namespace, field, ..
+            } => write!(f, "({}.{})", namespace, field),
+            Expression::Lambda(lambda) => {
+                let params = lambda
+                    .params
+                    .iter()
+                    .map(|param| format!("{} {}", param.ident, param.t))
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                let result_type = if let Some(ref t) = lambda.result {
+                    format!("{} ", t)
+                } else {
+                    String::from("")
+                };
+                write!(f, "fun({}) {}{}", params, result_type, lambda.body)
+            }
+            Expression::Unary { unop, expr, .. } => match unop {
                 UnaryOperator::Not => write!(f, "!{}", expr),
                 UnaryOperator::Minus => write!(f, "-{}", expr),
+                UnaryOperator::Reference => write!(f, "&{}", expr),
+                UnaryOperator::Dereference => write!(f, "*{}", expr),
             },
             Expression::Binary {
                 expr_left,
                 binop,
                 expr_right,
+                ..
             } => {
                 let op = match binop {
                     BinaryOperator::And => "&&",
@@ -483,7 +743,10 @@ impl fmt::Display for Statement {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Statement::ExprStmt(expr) => write!(f, "{};", expr),
-            Statement::LetStmt { var, expr } => write!(f, "let {} = {};", var.ident, expr),
+            Statement::LetStmt { var, expr } => match &var.t {
+                Some(t) => write!(f, "let {}: {} = {};", var.ident, t, expr),
+                None => write!(f, "let {} = {};", var.ident, expr),
+            },
             Statement::AssignStmt { target, expr } => write!(f, "{} = {};", target, expr),
             Statement::IfStmt {
                 expr,
@@ -501,6 +764,7 @@ impl fmt::Display for Statement {
                 Some(e) => write!(f, "return {};", e),
                 None => write!(f, "return;"),
             },
+            Statement::DeferStmt { expr, .. } => write!(f, "defer {};", expr),
         }
     }
 }
@@ -528,6 +792,18 @@ impl fmt::Display for Type {
                     .join(", ");
                 write!(f, "({})", types)
             }
+            Type::Fun { params, result, .. } => {
+                let params = params
+                    .iter()
+                    .map(|t| format!("{}", t))
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                match result {
+                    Some(t) => write!(f, "fun({}): {}", params, t),
+                    None => write!(f, "fun({})", params),
+                }
+            }
+            Type::Ref(t, _) => write!(f, "&{}", t),
         }
     }
 }
@@ -540,6 +816,12 @@ impl fmt::Display for AsmStatement {
             AsmStatement::Control { cntrl, .. } => write!(f, "{}", cntrl),
             AsmStatement::Parametric { param, .. } => write!(f, "{}", param),
             AsmStatement::Memory { mem, .. } => write!(f, "{}", mem),
+            AsmStatement::Simd { simd, .. } => write!(f, "{}", simd),
+            AsmStatement::Atomic { atomic, .. } => write!(f, "{}", atomic),
+            AsmStatement::Raw { bytes, .. } => {
+                let bytes: Vec<String> = bytes.iter().map(|b| format!("{:#04x}", b)).collect();
+                write!(f, "raw {}", bytes.join(" "))
+            }
         }
     }
 }
@@ -587,6 +869,8 @@ impl fmt::Display for AsmMemory {
             AsmMemory::F32Store { align, offset } => write!(f, "f32.store {}, {}", align, offset),
             AsmMemory::F64Store { align, offset } => write!(f, "f64.store {}, {}", align, offset),
             AsmMemory::I32Store8 { align, offset } => write!(f, "i32.store8 {}, {}", align, offset),
+            AsmMemory::V128Load { align, offset } => write!(f, "v128.load {}, {}", align, offset),
+            AsmMemory::V128Store { align, offset } => write!(f, "v128.store {}, {}", align, offset),
         }
     }
 }
@@ -0,0 +1,64 @@
+//! Experimental Features
+//!
+//! Language constructs which are still in progress are gated behind features: using them is an
+//! error unless their feature is enabled (see `Ctx::enable_feature`), so that programs do not
+//! start relying on them by accident.
+
+use std::collections::HashSet;
+use std::fmt;
+
+/// The set of enabled features.
+pub type Features = HashSet<Feature>;
+
+/// An experimental feature of the language.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Feature {
+    /// The `v128` type and the SIMD intrinsics and instructions operating on it.
+    Simd,
+    /// Atomic memory accesses, along with the wait and notify operations used to synchronize
+    /// threads sharing the memory.
+    Threads,
+}
+
+impl Feature {
+    pub const ALL: [Feature; 2] = [Feature::Simd, Feature::Threads];
+
+    /// The name used to enable the feature from the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            Feature::Simd => "simd",
+            Feature::Threads => "threads",
+        }
+    }
+
+    /// Returns the feature with the given name, if any.
+    pub fn from_name(name: &str) -> Option<Feature> {
+        Feature::ALL
+            .iter()
+            .copied()
+            .find(|feature| feature.name() == name)
+    }
+
+    /// The constructs gated behind the feature, as used in diagnostics.
+    fn constructs(self) -> &'static str {
+        match self {
+            Feature::Simd => "SIMD vectors",
+            Feature::Threads => "Atomic operations",
+        }
+    }
+
+    /// The error reported when the feature is used without being enabled.
+    pub fn error_message(self) -> String {
+        format!(
+            "{} are experimental, enable them with '--feature {}'",
+            self.constructs(),
+            self.name()
+        )
+    }
+}
+
+impl fmt::Display for Feature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
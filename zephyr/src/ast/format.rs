@@ -0,0 +1,268 @@
+//! Code formatter
+//!
+//! The code is parsed into a concrete syntax tree which, unlike the AST, retains comments and line
+//! breaks. The tree is then printed with a canonical style: line breaks are kept (with at most one
+//! blank line in a row), spacing between tokens is normalized and lines are indented by four
+//! spaces per level of nesting.
+
+use super::tokens::{Token, TokenType};
+use crate::error::Location;
+
+const INDENT: &str = "    ";
+
+/// A node of the concrete syntax tree.
+enum Node<'code> {
+    Token(Lexeme<'code>),
+    Comment(&'code str),
+    Newline,
+    /// Nodes surrounded by delimiters: parenthesis or braces.
+    Group {
+        open: Lexeme<'code>,
+        nodes: Vec<Node<'code>>,
+        close: Option<Lexeme<'code>>,
+    },
+}
+
+/// A token together with its text in the original source.
+struct Lexeme<'code> {
+    t: TokenType,
+    text: &'code str,
+}
+
+/// Builds the concrete syntax tree of a file from tokens scanned with trivia.
+struct TreeBuilder<'code> {
+    code: &'code str,
+}
+
+impl<'code> TreeBuilder<'code> {
+    fn new(code: &'code str) -> Self {
+        Self { code }
+    }
+
+    fn build(&self, tokens: Vec<Token>) -> Vec<Node<'code>> {
+        let mut tokens = tokens.into_iter();
+        self.nodes(&mut tokens, None).0
+    }
+
+    /// Collects nodes up to the closing delimiter, if any, or the end of file.
+    fn nodes(
+        &self,
+        tokens: &mut impl Iterator<Item = Token>,
+        closing: Option<TokenType>,
+    ) -> (Vec<Node<'code>>, Option<Lexeme<'code>>) {
+        let mut nodes = Vec::new();
+        while let Some(token) = tokens.next() {
+            let node = match token.t {
+                TokenType::EOF => break,
+                TokenType::Newline => Node::Newline,
+                TokenType::Comment(_) => Node::Comment(self.text(token.loc).trim_end()),
+                TokenType::LeftPar | TokenType::LeftBrace => {
+                    let closing = if token.t == TokenType::LeftPar {
+                        TokenType::RightPar
+                    } else {
+                        TokenType::RightBrace
+                    };
+                    let open = self.lexeme(token);
+                    let (group, close) = self.nodes(tokens, Some(closing));
+                    Node::Group {
+                        open,
+                        nodes: group,
+                        close,
+                    }
+                }
+                _ if Some(&token.t) == closing.as_ref() => {
+                    return (nodes, Some(self.lexeme(token)));
+                }
+                _ => Node::Token(self.lexeme(token)),
+            };
+            nodes.push(node);
+        }
+        (nodes, None)
+    }
+
+    fn lexeme(&self, token: Token) -> Lexeme<'code> {
+        Lexeme {
+            text: self.text(token.loc),
+            t: token.t,
+        }
+    }
+
+    fn text(&self, loc: Location) -> &'code str {
+        let start = loc.pos as usize;
+        &self.code[start..(start + loc.len as usize)]
+    }
+}
+
+/// Prints a concrete syntax tree with the canonical style.
+struct Printer {
+    out: String,
+    /// The last token printed on the current line, if any.
+    previous: Option<TokenType>,
+    /// The last token printed, regardless of line breaks.
+    last: Option<TokenType>,
+    /// True if the last token is an unary operator.
+    is_unary: bool,
+    line_breaks: usize,
+}
+
+impl Printer {
+    fn new() -> Self {
+        Self {
+            out: String::new(),
+            previous: None,
+            last: None,
+            is_unary: false,
+            line_breaks: 0,
+        }
+    }
+
+    fn print(mut self, nodes: &[Node]) -> String {
+        self.nodes(nodes, 0);
+        self.out.truncate(self.out.trim_end().len());
+        self.out.push('\n');
+        self.out
+    }
+
+    fn nodes(&mut self, nodes: &[Node], depth: usize) {
+        for node in nodes {
+            match node {
+                Node::Newline => self.line_breaks += 1,
+                Node::Comment(comment) => {
+                    self.break_lines(depth, false);
+                    if self.previous.is_some() {
+                        self.out.push(' ');
+                    }
+                    self.out.push_str(comment);
+                    self.previous = Some(TokenType::Comment(String::new()));
+                }
+                Node::Token(lexeme) => self.token(lexeme, depth, false),
+                Node::Group { open, nodes, close } => {
+                    self.token(open, depth, false);
+                    self.nodes(nodes, depth + 1);
+                    if let Some(close) = close {
+                        self.token(close, depth, true);
+                    }
+                }
+            }
+        }
+    }
+
+    fn token(&mut self, lexeme: &Lexeme, depth: usize, is_closing: bool) {
+        self.break_lines(depth, is_closing);
+        if let Some(previous) = &self.previous {
+            if !self.is_unary && needs_space(previous, &lexeme.t) {
+                self.out.push(' ');
+            }
+        }
+        self.out.push_str(lexeme.text);
+        // A minus or a star starting a line begins a new statement, hence is a unary operator
+        let starts_line = self.previous.is_none();
+        self.is_unary = match lexeme.t {
+            TokenType::Bang => true,
+            TokenType::Minus | TokenType::Star => {
+                starts_line || !self.last.as_ref().is_some_and(ends_operand)
+            }
+            TokenType::And => !self.last.as_ref().is_some_and(ends_operand),
+            _ => false,
+        };
+        self.previous = Some(lexeme.t.clone());
+        self.last = Some(lexeme.t.clone());
+    }
+
+    /// Emits the pending line breaks, if any, and indents the new line.
+    fn break_lines(&mut self, depth: usize, is_closing: bool) {
+        if self.line_breaks == 0 {
+            return;
+        }
+        if !self.out.is_empty() {
+            self.out.truncate(self.out.trim_end_matches(' ').len());
+            self.out.push('\n');
+            let is_opening = matches!(
+                self.previous,
+                Some(TokenType::LeftBrace) | Some(TokenType::LeftPar)
+            );
+            if self.line_breaks > 1 && !is_opening && !is_closing {
+                self.out.push('\n');
+            }
+            for _ in 0..depth {
+                self.out.push_str(INDENT);
+            }
+        }
+        self.line_breaks = 0;
+        self.previous = None;
+    }
+}
+
+/// Returns true if a space is expected between two tokens on the same line.
+fn needs_space(previous: &TokenType, next: &TokenType) -> bool {
+    use TokenType::*;
+    !matches!(
+        (previous, next),
+        (LeftPar, _)
+            | (LeftBracket, _)
+            | (Hash, _)
+            | (_, RightBracket)
+            | (Dot, _)
+            | (_, Dot)
+            | (_, RightPar)
+            | (_, Comma)
+            | (_, Colon)
+            | (_, SemiColon)
+            | (LeftBrace, RightBrace)
+            | (Identifier(_), LeftPar)
+            | (RightPar, LeftPar)
+    )
+}
+
+/// Returns true if the token ends an operand, a following minus, star or ampersand is then a
+/// binary operator.
+fn ends_operand(t: &TokenType) -> bool {
+    use TokenType::*;
+    matches!(
+        t,
+        Identifier(_)
+            | IntegerLit(_)
+            | FloatLit(_)
+            | BooleanLit(_)
+            | StringLit(_)
+            | RightPar
+            | RightBrace
+    )
+}
+
+/// Returns the code formatted with the canonical style, `tokens` must have been scanned with
+/// trivia.
+pub fn format(code: &str, tokens: Vec<Token>) -> String {
+    let tree = TreeBuilder::new(code).build(tokens);
+    Printer::new().print(&tree)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::scan::Scanner;
+    use super::*;
+    use crate::error::DummyHandler;
+    use crate::resolver::FileId;
+
+    fn format_str(code: &str) -> String {
+        let mut err = DummyHandler::new();
+        let mut scanner = Scanner::new(FileId(0), code, &mut err);
+        scanner.keep_trivia();
+        format(code, scanner.scan())
+    }
+
+    #[test]
+    fn canonical_style() {
+        let code = "\n\nfun  add( a:i32,b :i32 ):i32{\n\n  // Sum\n\treturn a+ -b   // done\n\n\n}\n\n\nfun main() { return add(1 , 2) }";
+        let expected = "fun add(a: i32, b: i32): i32 {\n    // Sum\n    return a + -b // done\n}\n\nfun main() { return add(1, 2) }\n";
+        assert_eq!(format_str(code), expected);
+        assert_eq!(format_str(expected), expected);
+    }
+
+    #[test]
+    fn references() {
+        let code = "fun f(p:& i32) {\n    let q = & p\n    * p = ** q* 2 & 1\n}\n";
+        let expected = "fun f(p: &i32) {\n    let q = &p\n    *p = **q * 2 & 1\n}\n";
+        assert_eq!(format_str(code), expected);
+    }
+}
@@ -0,0 +1,104 @@
+//! Syntax highlighting
+//!
+//! Classifies the tokens of a source file so that editors can highlight Zephyr code without
+//! re-implementing the scanner. Unlike the locations used by the compiler, spans are expressed as
+//! byte ranges, which is what most editors and the browser expect.
+
+use std::ops::Range;
+
+use super::scan::Scanner;
+use super::tokens::TokenType;
+use crate::error::DummyHandler;
+use crate::resolver::FileId;
+
+/// The class of a highlighted span.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SpanKind {
+    Keyword,
+    Literal,
+    Identifier,
+    Comment,
+    Operator,
+    /// Delimiters and separators: parenthesis, braces, commas, colons and dots.
+    Punctuation,
+}
+
+/// A classified section of the source code.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Span {
+    pub kind: SpanKind,
+    /// Byte range of the span within the source code.
+    pub range: Range<usize>,
+}
+
+/// Returns the classified spans of a Zephyr source, sorted by position.
+///
+/// Highlighting never fails: characters that can not be scanned are not part of any span.
+pub fn highlight(code: &str) -> Vec<Span> {
+    let mut err = DummyHandler::new();
+    let mut scanner = Scanner::new(FileId(0), code, &mut err);
+    scanner.keep_trivia();
+    let tokens = scanner.scan();
+
+    tokens
+        .into_iter()
+        .filter_map(|token| {
+            let kind = classify(&token.t)?;
+            let start = token.loc.pos as usize;
+            let end = start + token.loc.len as usize;
+            Some(Span {
+                kind,
+                range: start..end,
+            })
+        })
+        .collect()
+}
+
+fn classify(t: &TokenType) -> Option<SpanKind> {
+    use TokenType::*;
+    let kind = match t {
+        As | Defer | Else | Expose | False | From | Fun | If | Import | Init | Let | Module
+        | Pub | Return | Runtime | Standalone | Struct | True | Use | Var | While => {
+            SpanKind::Keyword
+        }
+        IntegerLit(_) | FloatLit(_) | BooleanLit(_) | StringLit(_) => SpanKind::Literal,
+        Identifier(_) => SpanKind::Identifier,
+        Comment(_) => SpanKind::Comment,
+        Minus | Plus | Slash | Star | Percent | Bang | Equal | Greater | Less | And | Or | Hat
+        | BangEqual | EqualEqual | GreaterEqual | LessEqual | AndAnd | OrOr => SpanKind::Operator,
+        LeftPar | RightPar | LeftBrace | RightBrace | LeftBracket | RightBracket | Hash | Comma
+        | Colon | Dot => SpanKind::Punctuation,
+        Newline | SemiColon | EOF => return None,
+    };
+    Some(kind)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classified_spans() {
+        let code = "let é = 0x2A // ü\nfoo(é, true)";
+        let spans = highlight(code)
+            .into_iter()
+            .map(|span| (span.kind, &code[span.range]))
+            .collect::<Vec<(SpanKind, &str)>>();
+        assert_eq!(
+            spans,
+            vec![
+                (SpanKind::Keyword, "let"),
+                (SpanKind::Identifier, "é"),
+                (SpanKind::Operator, "="),
+                (SpanKind::Literal, "0x2A"),
+                (SpanKind::Comment, "// ü"),
+                (SpanKind::Identifier, "foo"),
+                (SpanKind::Punctuation, "("),
+                (SpanKind::Identifier, "é"),
+                (SpanKind::Punctuation, ","),
+                (SpanKind::Literal, "true"),
+                (SpanKind::Punctuation, ")"),
+            ]
+        );
+    }
+}
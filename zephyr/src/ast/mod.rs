@@ -1,96 +1,144 @@
-use crate::ctx::ModId;
-use crate::error::ErrorHandler;
-use crate::resolver::{FileId, FileKind};
+use log::debug;
+use std::time::Instant;
+
+use crate::ctx::{ModId, Phase, Timings};
+use crate::error::{ErrorHandler, SourceFile};
+use crate::resolver::FileKind;
 
 mod asm_parse;
 mod asm_scan;
 mod asm_tokens;
 mod ast;
+mod features;
+mod format;
+mod highlight;
 mod opcode_to_asm;
 mod parse;
 mod scan;
+mod termination;
 mod tokens;
 
 pub use ast::*;
+pub use features::{Feature, Features};
+pub use highlight::{highlight, Span, SpanKind};
+pub use scan::is_identifier;
 pub use tokens::*;
 
-/// Returns the file AST.
-/// The file content corresponding to `f_id` must be owned by the error_handler.
+/// Returns the file AST, or an error if the file could not be scanned or parsed.
 pub fn get_ast(
-    f_id: FileId,
+    file: &SourceFile,
     mod_id: ModId,
     kind: FileKind,
     error_handler: &mut impl ErrorHandler,
-    verbose: bool,
-) -> ast::Program {
+    timings: Option<&Timings>,
+) -> Result<ast::Program, ()> {
     match kind {
-        FileKind::Zephyr => get_zephyr_ast(f_id, mod_id, error_handler, verbose),
-        FileKind::Asm => get_asm_ast(f_id, mod_id, error_handler, verbose),
+        FileKind::Zephyr => get_zephyr_ast(file, mod_id, error_handler, timings),
+        FileKind::Asm => get_asm_ast(file, mod_id, error_handler, timings),
+    }
+}
+
+/// Returns the code of a Zephyr file formatted with the canonical style.
+///
+/// Files that can not be parsed are not formatted.
+pub fn format_code(file: &SourceFile, error_handler: &mut impl ErrorHandler) -> Result<String, ()> {
+    get_zephyr_ast(file, ModId(0), error_handler, None)?;
+
+    let (f_id, code) = (file.f_id, file.code());
+    let mut scanner = scan::Scanner::new(f_id, code, error_handler);
+    scanner.keep_trivia();
+    let formatted = format::format(code, scanner.scan());
+
+    // Formatting must not change the tokens seen by the parser.
+    let original_tokens = scan::Scanner::new(f_id, code, error_handler).scan();
+    let formatted_tokens = scan::Scanner::new(f_id, &formatted, error_handler).scan();
+    let is_equivalent = original_tokens
+        .iter()
+        .map(|token| &token.t)
+        .eq(formatted_tokens.iter().map(|token| &token.t));
+    if !is_equivalent {
+        error_handler.report_internal_no_loc(String::from(
+            "The formatter would change the meaning of the code.",
+        ));
+        return Err(());
     }
+    Ok(formatted)
 }
 
 fn get_zephyr_ast(
-    f_id: FileId,
+    file: &SourceFile,
     mod_id: ModId,
     error_handler: &mut impl ErrorHandler,
-    verbose: bool,
-) -> ast::Program {
-    if verbose {
-        println!("\n/// Scanning ///\n");
-    }
+    timings: Option<&Timings>,
+) -> Result<ast::Program, ()> {
+    debug!("\n/// Scanning ///\n");
 
-    let mut scanner = scan::Scanner::new(f_id, error_handler);
+    let start = Instant::now();
+    let mut scanner = scan::Scanner::new(file.f_id, file.code(), error_handler);
     let tokens = scanner.scan();
+    record(timings, Phase::Scan, start);
 
-    if verbose {
-        for token in tokens.iter() {
-            print!("{}", token);
-        }
-        println!("");
-        println!("\n/// Parsing ///\n");
-    }
+    debug!(
+        "{}",
+        tokens.iter().map(ToString::to_string).collect::<String>()
+    );
+    debug!("\n/// Parsing ///\n");
 
-    let mut parser = parse::Parser::new(tokens, mod_id, error_handler);
+    let start = Instant::now();
+    let mut parser = parse::Parser::new(tokens, file.f_id, mod_id, error_handler);
     let ast_program = parser.parse();
+    record(timings, Phase::Parse, start);
 
-    if verbose {
-        println!("{}", ast_program);
-    }
+    debug!("{}", ast_program);
 
-    error_handler.flush_and_exit_if_err();
-    ast_program
+    if error_handler.has_error() {
+        return Err(());
+    }
+    Ok(ast_program)
 }
 
 fn get_asm_ast(
-    f_id: FileId,
+    file: &SourceFile,
     mod_id: ModId,
     error_handler: &mut impl ErrorHandler,
-    verbose: bool,
-) -> ast::Program {
-    if verbose {
-        println!("\n/// Scanning ///\n");
-    }
+    timings: Option<&Timings>,
+) -> Result<ast::Program, ()> {
+    debug!("\n/// Scanning ///\n");
 
-    let mut scanner = asm_scan::Scanner::new(f_id, error_handler);
+    let start = Instant::now();
+    let mut scanner = asm_scan::Scanner::new(file.f_id, file.code(), error_handler);
     let tokens = scanner.scan();
-
-    if verbose {
-        for token in tokens.iter() {
-            print!("{} ", token);
-        }
-        println!("\n");
-        println!("\n/// Parsing ///\n");
+    record(timings, Phase::Scan, start);
+
+    debug!(
+        "{}\n",
+        tokens
+            .iter()
+            .map(|token| format!("{} ", token))
+            .collect::<String>()
+    );
+    debug!("\n/// Parsing ///\n");
+
+    if error_handler.has_error() {
+        return Err(());
     }
 
-    error_handler.flush_and_exit_if_err();
-
-    let mut parser = asm_parse::Parser::new(tokens, mod_id, error_handler);
+    let start = Instant::now();
+    let mut parser = asm_parse::Parser::new(tokens, file.f_id, mod_id, error_handler);
     let ast_program = parser.parse();
+    record(timings, Phase::Parse, start);
 
-    if verbose {
-        println!("{}", ast_program);
+    debug!("{}", ast_program);
+
+    if error_handler.has_error() {
+        return Err(());
     }
+    Ok(ast_program)
+}
 
-    error_handler.flush_and_exit_if_err();
-    ast_program
+/// Adds the time elapsed since `start` to the duration of `phase`, if timings are recorded.
+fn record(timings: Option<&Timings>, phase: Phase, start: Instant) {
+    if let Some(timings) = timings {
+        timings.record(phase, start);
+    }
 }
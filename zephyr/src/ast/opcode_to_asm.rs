@@ -4,8 +4,11 @@ use crate::error::Location;
 use crate::mir;
 
 /// Opcode argument.
+///
+/// Integers are stored on 128 bits so that both the whole unsigned 64 bits range and negative
+/// values can be represented, they are then checked against the range of the target type.
 pub enum Argument {
-    Integer(u64, Location),
+    Integer(i128, Location),
     Identifier(String, Location),
 }
 
@@ -39,14 +42,20 @@ pub fn opcode_to_asm(
             })
         }
         // Constants
-        Opcode::I32Const => Ok(AsmStatement::Const {
-            val: mir::Value::I32(integer(args, "i32.const", loc)? as i32),
-            loc,
-        }),
-        Opcode::I64Const => Ok(AsmStatement::Const {
-            val: mir::Value::I64(integer(args, "i64.const", loc)?),
-            loc,
-        }),
+        Opcode::I32Const => {
+            let n = integer(args, "i32.const", loc, i32::MIN as i128, u32::MAX as i128)?;
+            Ok(AsmStatement::Const {
+                val: mir::Value::I32(n as i32),
+                loc,
+            })
+        }
+        Opcode::I64Const => {
+            let n = integer(args, "i64.const", loc, i64::MIN as i128, u64::MAX as i128)?;
+            Ok(AsmStatement::Const {
+                val: mir::Value::I64(n as i64),
+                loc,
+            })
+        }
         // Locals
         Opcode::LocalGet => {
             let (ident, arg_loc) = identifier(args, "local.get", loc)?;
@@ -149,9 +158,120 @@ pub fn opcode_to_asm(
                 loc,
             })
         }
+        Opcode::V128Load => {
+            let (align, offset) = memarg(args, "v128.load", loc)?;
+            Ok(AsmStatement::Memory {
+                mem: AsmMemory::V128Load { align, offset },
+                loc,
+            })
+        }
+        Opcode::V128Store => {
+            let (align, offset) = memarg(args, "v128.store", loc)?;
+            Ok(AsmStatement::Memory {
+                mem: AsmMemory::V128Store { align, offset },
+                loc,
+            })
+        }
+        // SIMD
+        Opcode::Splat(shape) => {
+            no_arg(args, &op.to_string())?;
+            Ok(AsmStatement::Simd {
+                simd: mir::Simd::Splat(shape),
+                loc,
+            })
+        }
+        Opcode::ExtractLane(shape) => {
+            let lane = integer(args, &op.to_string(), loc, 0, shape.lanes() as i128 - 1)?;
+            Ok(AsmStatement::Simd {
+                simd: mir::Simd::ExtractLane(shape, lane as u8),
+                loc,
+            })
+        }
+        Opcode::ReplaceLane(shape) => {
+            let lane = integer(args, &op.to_string(), loc, 0, shape.lanes() as i128 - 1)?;
+            Ok(AsmStatement::Simd {
+                simd: mir::Simd::ReplaceLane(shape, lane as u8),
+                loc,
+            })
+        }
+        // Atomics
+        Opcode::AtomicLoad(t) => atomic(args, op, loc, |offset| mir::Atomic::Load { t, offset }),
+        Opcode::AtomicStore(t) => atomic(args, op, loc, |offset| mir::Atomic::Store { t, offset }),
+        Opcode::AtomicRmw(rmw, t) => atomic(args, op, loc, |offset| mir::Atomic::Rmw {
+            op: rmw,
+            t,
+            offset,
+        }),
+        Opcode::AtomicCmpxchg(t) => {
+            atomic(args, op, loc, |offset| mir::Atomic::Cmpxchg { t, offset })
+        }
+        Opcode::AtomicNotify => atomic(args, op, loc, |offset| mir::Atomic::Notify { offset }),
+        Opcode::AtomicWait(t) => atomic(args, op, loc, |offset| mir::Atomic::Wait { t, offset }),
+        Opcode::AtomicFence => {
+            no_arg(args, "atomic.fence")?;
+            Ok(AsmStatement::Atomic {
+                atomic: mir::Atomic::Fence,
+                loc,
+            })
+        }
+        Opcode::Raw => {
+            let bytes = raw_bytes(args, loc)?;
+            Ok(AsmStatement::Raw { bytes, loc })
+        }
     }
 }
 
+/// Expects one or more bytes. The immediates of wasm instructions are LEB128 encoded, the last
+/// byte can not have its continuation bit set as it would swallow the following instruction.
+fn raw_bytes(args: Vec<Argument>, loc: Location) -> Result<Vec<u8>, (String, Location)> {
+    let mut bytes = Vec::with_capacity(args.len());
+    for arg in &args {
+        match arg {
+            Argument::Integer(n, _) if *n >= 0 && *n <= u8::MAX as i128 => bytes.push(*n as u8),
+            _ => {
+                return Err((
+                    String::from("`raw` expects bytes as arguments."),
+                    arg.get_loc(),
+                ))
+            }
+        }
+    }
+    match (bytes.last(), args.last()) {
+        (Some(byte), Some(arg)) if byte & 0x80 != 0 => Err((
+            format!(
+                "`raw` can not end with {:#04x}, an unterminated LEB128 byte.",
+                byte
+            ),
+            arg.get_loc(),
+        )),
+        (Some(_), _) => Ok(bytes),
+        _ => Err((String::from("`raw` expects at least one byte."), loc)),
+    }
+}
+
+/// Expects the memory argument of an atomic operation, whose alignment must be the natural one.
+fn atomic(
+    args: Vec<Argument>,
+    op: Opcode,
+    loc: Location,
+    atomic: impl FnOnce(u32) -> mir::Atomic,
+) -> Result<AsmStatement, (String, Location)> {
+    let (align, offset) = memarg(args, &op.to_string(), loc)?;
+    let atomic = atomic(offset);
+    if align != atomic.align() {
+        return Err((
+            format!(
+                "`{}` expects the natural alignment {}, got {}.",
+                op,
+                atomic.align(),
+                align
+            ),
+            loc,
+        ));
+    }
+    Ok(AsmStatement::Atomic { atomic, loc })
+}
+
 /// Raises an error if there is one or more argument.
 fn no_arg(args: Vec<Argument>, opcode: &str) -> Result<(), (String, Location)> {
     if let Some(arg) = args.first() {
@@ -161,8 +281,17 @@ fn no_arg(args: Vec<Argument>, opcode: &str) -> Result<(), (String, Location)> {
     }
 }
 
-/// Raises an error if the argument is not an integer.
-fn integer(args: Vec<Argument>, opcode: &str, loc: Location) -> Result<i64, (String, Location)> {
+/// Raises an error if the argument is not an integer within `min..=max`.
+///
+/// As in the Wasm text format, constants are allowed to be written either as signed or unsigned
+/// integers, the caller is responsible for the final (wrapping) conversion.
+fn integer(
+    args: Vec<Argument>,
+    opcode: &str,
+    loc: Location,
+    min: i128,
+    max: i128,
+) -> Result<i128, (String, Location)> {
     if args.len() > 1 {
         return Err((
             format!("Too many arguments: expected 1, got {}.", args.len()),
@@ -171,7 +300,19 @@ fn integer(args: Vec<Argument>, opcode: &str, loc: Location) -> Result<i64, (Str
     }
     if let Some(arg) = args.first() {
         match arg {
-            Argument::Integer(n, _) => Ok(*n as i64),
+            Argument::Integer(n, arg_loc) => {
+                if *n < min || *n > max {
+                    Err((
+                        format!(
+                            "`{}` expects an integer between {} and {}.",
+                            opcode, min, max
+                        ),
+                        *arg_loc,
+                    ))
+                } else {
+                    Ok(*n)
+                }
+            }
             _ => Err((format!("`{}` expects an integer.", opcode), arg.get_loc())),
         }
     } else {
@@ -196,29 +337,34 @@ fn memarg(
             loc,
         ));
     }
-    let arg_1 = &args[0];
-    let arg_1 = match arg_1 {
-        Argument::Integer(n, _) => *n as u32,
-        _ => {
-            return Err((
-                format!("`{}` expects an integer as first argument.", opcode),
-                arg_1.get_loc(),
-            ))
-        }
-    };
-    let arg_2 = &args[1];
-    let arg_2 = match arg_2 {
-        Argument::Integer(n, _) => *n as u32,
-        _ => {
-            return Err((
-                format!("`{}` expects an integer as second argument.", opcode),
-                arg_2.get_loc(),
-            ))
-        }
-    };
+    let arg_1 = memarg_integer(&args[0], opcode, "first")?;
+    let arg_2 = memarg_integer(&args[1], opcode, "second")?;
     Ok((arg_1, arg_2))
 }
 
+/// Expects an unsigned 32 bits integer, rises an error otherwise.
+fn memarg_integer(arg: &Argument, opcode: &str, position: &str) -> Result<u32, (String, Location)> {
+    match arg {
+        Argument::Integer(n, loc) => {
+            if *n < 0 || *n > u32::MAX as i128 {
+                Err((
+                    format!(
+                        "`{}` expects an unsigned 32 bits integer as {} argument.",
+                        opcode, position
+                    ),
+                    *loc,
+                ))
+            } else {
+                Ok(*n as u32)
+            }
+        }
+        _ => Err((
+            format!("`{}` expects an integer as {} argument.", opcode, position),
+            arg.get_loc(),
+        )),
+    }
+}
+
 /// Raises an error if the argument is not an identifier.
 fn identifier(
     args: Vec<Argument>,
@@ -8,17 +8,27 @@ pub struct Parser<'err, E: ErrorHandler> {
     tokens: Vec<Token>,
     current: usize,
     mod_id: ModId,
+    f_id: FileId,
+    /// Index of the next node ID.
+    next_node: u32,
 }
 
 /// Works on a list of tokens and converts it into an Abstract Syntax Tree,
 /// following the grammar of the language (defined in 'grammar.md')
 impl<'err, E: ErrorHandler> Parser<'err, E> {
-    pub fn new(tokens: Vec<Token>, mod_id: ModId, error_handler: &'err mut E) -> Self {
+    pub fn new(
+        tokens: Vec<Token>,
+        f_id: FileId,
+        mod_id: ModId,
+        error_handler: &'err mut E,
+    ) -> Self {
         Parser {
             err: error_handler,
             tokens,
             current: 0,
             mod_id,
+            f_id,
+            next_node: 0,
         }
     }
 
@@ -30,7 +40,15 @@ impl<'err, E: ErrorHandler> Parser<'err, E> {
         let mut exposed = Vec::new();
         let mut imports = Vec::new();
         let mut used = Vec::new();
+        let mut inits = Vec::new();
 
+        let attributes = match self.attributes() {
+            Ok(attributes) => attributes,
+            Err(()) => {
+                self.err.silent_report();
+                Vec::new()
+            }
+        };
         let module = match self.module() {
             Ok(pkg) => pkg,
             Err(()) => {
@@ -59,6 +77,7 @@ impl<'err, E: ErrorHandler> Parser<'err, E> {
                     Declaration::Use(uses) => used.push(uses),
                     Declaration::Expose(expose) => exposed.push(expose),
                     Declaration::Imports(import) => imports.push(import),
+                    Declaration::Init(init) => inits.push(init),
                 },
                 Err(()) => self.err.silent_report(),
             }
@@ -71,6 +90,19 @@ impl<'err, E: ErrorHandler> Parser<'err, E> {
             exposed,
             imports,
             used,
+            inits,
+            memories: vec![],
+            attributes,
+        }
+    }
+
+    /// Returns a fresh node ID.
+    fn node_id(&mut self) -> NodeId {
+        let index = self.next_node;
+        self.next_node += 1;
+        NodeId {
+            f_id: self.f_id,
+            index,
         }
     }
 
@@ -221,6 +253,28 @@ impl<'err, E: ErrorHandler> Parser<'err, E> {
         }
     }
 
+    /// Expects the current token to be a non-empty string and consumes it, synchronize to the next
+    /// declaration if it's not a string.
+    fn expect_name_string(&mut self, error_message: &str) -> Result<String, ()> {
+        let token = self.advance();
+        match token.t {
+            TokenType::StringLit(ref name) => {
+                let (name, loc) = (name.clone(), token.loc);
+                if name.is_empty() {
+                    self.err
+                        .report(loc, String::from("Import names can not be empty"));
+                }
+                Ok(name)
+            }
+            _ => {
+                let loc = token.loc;
+                self.err.report(loc, String::from(error_message));
+                self.synchronize_decl();
+                Err(())
+            }
+        }
+    }
+
     /* All the following functions try to parse a grammar element, and
     recursively parse all sub-elements as defined in the gammar of the
     language */
@@ -265,22 +319,26 @@ impl<'err, E: ErrorHandler> Parser<'err, E> {
         }
     }
 
-    /// Parses a 'declaration' that can be either a 'use', 'expose', 'import' or 'fun'
+    /// Parses a 'declaration' that can be either a 'use', 'expose', 'import', 'init' or 'fun',
+    /// preceded by attributes
     fn declaration(&mut self) -> Result<Declaration, ()> {
+        let attributes = self.attributes()?;
         match self.peek().t {
-            TokenType::Fun => Ok(Declaration::Function(self.function()?)),
-            TokenType::Use => Ok(Declaration::Use(self._use()?)),
-            TokenType::Expose => Ok(Declaration::Expose(self.expose()?)),
-            TokenType::From => Ok(Declaration::Imports(self.imports()?)),
-            TokenType::Struct => Ok(Declaration::Struct(self._struct()?)),
+            TokenType::Fun => Ok(Declaration::Function(self.function(attributes)?)),
+            TokenType::Use => Ok(Declaration::Use(self._use(attributes)?)),
+            TokenType::Expose => Ok(Declaration::Expose(self.expose(attributes)?)),
+            TokenType::From => Ok(Declaration::Imports(self.imports(attributes)?)),
+            TokenType::Import => Ok(Declaration::Imports(self.single_import(attributes)?)),
+            TokenType::Struct => Ok(Declaration::Struct(self._struct(attributes)?)),
+            TokenType::Init => Ok(Declaration::Init(self.init(attributes)?)),
             TokenType::Pub => match self.peekpeek().t {
-                TokenType::Fun => Ok(Declaration::Function(self.function()?)),
-                TokenType::Struct => Ok(Declaration::Struct(self._struct()?)),
+                TokenType::Fun => Ok(Declaration::Function(self.function(attributes)?)),
+                TokenType::Struct => Ok(Declaration::Struct(self._struct(attributes)?)),
                 _ => {
                     self.err.report(
                         self.peekpeek().loc,
                         String::from(
-                            "Top level declaration must be one of 'function', 'use', 'expose' or 'from ... import'.",
+                            "Top level declaration must be one of 'function', 'use', 'expose', 'init' or 'from ... import'.",
                         ),
                     );
                     self.synchronize();
@@ -291,7 +349,7 @@ impl<'err, E: ErrorHandler> Parser<'err, E> {
                 self.err.report(
                     self.peek().loc,
                     String::from(
-                        "Top level declaration must be one of 'function', 'use', 'expose' or 'from ... import'.",
+                        "Top level declaration must be one of 'function', 'use', 'expose', 'init' or 'from ... import'.",
                     ),
                 );
                 self.synchronize();
@@ -301,10 +359,22 @@ impl<'err, E: ErrorHandler> Parser<'err, E> {
     }
 
     /// Parses the 'use' grammar element
-    fn _use(&mut self) -> Result<Use, ()> {
+    fn _use(&mut self, attributes: Vec<Attribute>) -> Result<Use, ()> {
         let start = self.peek().loc;
         self.next_match_report(TokenType::Use, "Use statement must start by 'use' keyword")?;
-        let path = self.path()?;
+        let root = self.use_identifier("Expected an identifier")?;
+        let mut path = Vec::new();
+        let mut items = None;
+        while self.next_match(TokenType::Dot) {
+            if self.next_match(TokenType::Star) {
+                items = Some(UseItems::Glob);
+                break;
+            } else if self.next_match(TokenType::LeftBrace) {
+                items = Some(UseItems::Selected(self.use_items()?));
+                break;
+            }
+            path.push(self.use_identifier("Expected an identifier, '{' or '*'")?);
+        }
         let alias = if self.next_match(TokenType::As) {
             let token = self.advance();
             if let TokenType::Identifier(ref ident) = token.t {
@@ -320,37 +390,92 @@ impl<'err, E: ErrorHandler> Parser<'err, E> {
         } else {
             None
         };
-        let end = self.peek().loc;
+        let end = self.previous().loc;
+        if alias.is_some() && items.is_some() {
+            self.err.report(
+                start.merge(end),
+                String::from("Only modules can be renamed with 'as', not imported declarations"),
+            );
+        }
         self.consume_semi_colon();
         Ok(Use {
+            id: self.node_id(),
             loc: start.merge(end),
-            path: ModulePath {
-                root: path.root,
-                path: path.path,
-            },
+            path: ModulePath { root, path },
             alias,
+            items,
+            attributes,
         })
     }
 
+    /// Parses the declarations imported by a 'use' statement, the opening brace has already been
+    /// consumed.
+    fn use_items(&mut self) -> Result<Vec<UseItem>, ()> {
+        let mut items = Vec::new();
+        while !self.next_match(TokenType::RightBrace) {
+            let loc = self.peek().loc;
+            let ident = self.use_identifier("Expected the name of a declaration to import")?;
+            items.push(UseItem {
+                ident,
+                id: self.node_id(),
+                loc,
+            });
+            // A line break before the closing brace inserts a statement ender.
+            self.next_match(TokenType::SemiColon);
+            if !self.next_match(TokenType::Comma) {
+                self.next_match_report_synchronize(
+                    TokenType::RightBrace,
+                    "Imported declarations must be separated by commas and closed by '}'",
+                )?;
+                break;
+            }
+        }
+        Ok(items)
+    }
+
+    /// Consumes an identifier of a 'use' statement, or reports an error and synchronizes to the
+    /// next statement.
+    fn use_identifier(&mut self, error_message: &str) -> Result<String, ()> {
+        if let TokenType::Identifier(ref ident) = self.peek().t {
+            let ident = ident.clone();
+            self.advance();
+            Ok(ident)
+        } else {
+            let loc = self.peek().loc;
+            self.err.report(loc, String::from(error_message));
+            self.synchronize();
+            Err(())
+        }
+    }
+
     /// Parses the 'expose' grammar element
-    fn expose(&mut self) -> Result<Expose, ()> {
+    fn expose(&mut self, attributes: Vec<Attribute>) -> Result<Expose, ()> {
         let start = self.peek().loc;
         self.next_match_report(
             TokenType::Expose,
             "Expose statement must start with 'expose' keyword",
         )?;
+        let ident_loc = self.peek().loc;
         let ident = self.expect_identifier("'expose' keyword must be followed by an identifier")?;
         let alias = if self.next_match(TokenType::As) {
             let token = self.advance();
-            if let TokenType::Identifier(ref alias_ident) = token.t {
-                Some(alias_ident.clone())
-            } else {
-                let loc = token.loc;
-                self.err.report(
-                    loc,
-                    String::from("'as' should be followed by an identifier"),
-                );
-                return Err(());
+            match token.t {
+                TokenType::Identifier(ref alias) => Some(alias.clone()),
+                TokenType::StringLit(ref alias) if !alias.is_empty() => Some(alias.clone()),
+                TokenType::StringLit(_) => {
+                    let loc = token.loc;
+                    self.err
+                        .report(loc, String::from("Export names can not be empty"));
+                    None
+                }
+                _ => {
+                    let loc = token.loc;
+                    self.err.report(
+                        loc,
+                        String::from("'as' should be followed by an identifier or a string"),
+                    );
+                    return Err(());
+                }
             }
         } else {
             None
@@ -358,14 +483,36 @@ impl<'err, E: ErrorHandler> Parser<'err, E> {
         let end = self.peek().loc;
         self.consume_semi_colon();
         Ok(Expose {
+            id: self.node_id(),
             loc: start.merge(end),
+            ident_loc,
             ident,
             alias,
+            attributes,
+        })
+    }
+
+    /// Parses the 'init' grammar element
+    fn init(&mut self, attributes: Vec<Attribute>) -> Result<Init, ()> {
+        let start = self.peek().loc;
+        self.next_match_report(TokenType::Init, "Init block must start with 'init' keyword")?;
+        self.next_match_report_synchronize_decl(
+            TokenType::LeftBrace,
+            "Expected a left brace '{' to open init block",
+        )?;
+        let block = self.block()?;
+        let end = self.previous().loc;
+        self.consume_semi_colon();
+        Ok(Init {
+            block,
+            attributes,
+            id: self.node_id(),
+            loc: start.merge(end),
         })
     }
 
     /// Parses the 'imports' grammar element
-    fn imports(&mut self) -> Result<Imports, ()> {
+    fn imports(&mut self, attributes: Vec<Attribute>) -> Result<Imports, ()> {
         self.next_match_report_synchronize_decl(
             TokenType::From,
             "Expected 'from' to start an import declaration",
@@ -382,6 +529,37 @@ impl<'err, E: ErrorHandler> Parser<'err, E> {
         Ok(Imports {
             from,
             prototypes,
+            attributes,
+            loc,
+        })
+    }
+
+    /// Parses the 'single_import' grammar element, which names the imported module and function
+    /// independently of the identifier of the function.
+    fn single_import(&mut self, attributes: Vec<Attribute>) -> Result<Imports, ()> {
+        let start = self.peek().loc;
+        self.next_match_report_synchronize_decl(
+            TokenType::Import,
+            "Expected 'import' to start an import declaration",
+        )?;
+        let from =
+            self.expect_name_string("Expected the name of the host module after 'import'")?;
+        let field = self.expect_name_string("Expected the name of the imported function")?;
+        let loc = start.merge(self.previous().loc);
+        let mut prototype = self.import()?;
+        if prototype.alias.is_some() {
+            self.err.report(
+                prototype.loc,
+                String::from("The function is already named by the import, 'as' is not allowed"),
+            );
+        }
+        if prototype.ident != field {
+            prototype.alias = Some(std::mem::replace(&mut prototype.ident, field));
+        }
+        Ok(Imports {
+            from,
+            prototypes: vec![prototype],
+            attributes,
             loc,
         })
     }
@@ -455,12 +633,13 @@ impl<'err, E: ErrorHandler> Parser<'err, E> {
             result,
             alias,
             is_pub,
+            id: self.node_id(),
             loc: loc.merge(end),
         })
     }
 
     /// Parses the 'struct" grammar element
-    fn _struct(&mut self) -> Result<Struct, ()> {
+    fn _struct(&mut self, attributes: Vec<Attribute>) -> Result<Struct, ()> {
         let is_pub = self.next_match(TokenType::Pub);
         self.next_match_report_synchronize_decl(
             TokenType::Struct,
@@ -475,6 +654,8 @@ impl<'err, E: ErrorHandler> Parser<'err, E> {
             ident,
             fields,
             is_pub,
+            attributes,
+            id: self.node_id(),
             loc,
         })
     }
@@ -527,13 +708,15 @@ impl<'err, E: ErrorHandler> Parser<'err, E> {
             is_pub,
             ident,
             t,
+            id: self.node_id(),
             loc,
         })
     }
 
     /// Parses the 'function' grammar element
-    fn function(&mut self) -> Result<Function, ()> {
+    fn function(&mut self, attributes: Vec<Attribute>) -> Result<Function, ()> {
         let is_pub = self.next_match(TokenType::Pub);
+        let keyword_loc = self.peek().loc;
         self.next_match_report_synchronize_decl(
             TokenType::Fun,
             "Unexpected top level declaration",
@@ -557,6 +740,7 @@ impl<'err, E: ErrorHandler> Parser<'err, E> {
         };
         self.next_match_report_synchronize_decl(TokenType::LeftBrace, error)?;
         let block = self.block()?;
+        let span = keyword_loc.merge(self.previous().loc);
         self.consume_semi_colon();
         Ok(Function {
             ident,
@@ -564,10 +748,68 @@ impl<'err, E: ErrorHandler> Parser<'err, E> {
             result,
             body: Body::Zephyr(block),
             is_pub,
+            attributes,
+            id: self.node_id(),
             loc,
+            keyword_loc,
+            span,
         })
     }
 
+    /// Parses a sequence of 'attribute' grammar elements
+    fn attributes(&mut self) -> Result<Vec<Attribute>, ()> {
+        let mut attributes = Vec::new();
+        while self.peek().t == TokenType::Hash {
+            let start = self.advance().loc;
+            self.next_match_report_synchronize_decl(
+                TokenType::LeftBracket,
+                "Expected a left bracket '[' after '#'",
+            )?;
+            let ident = self.expect_identifier("Expected an attribute name")?;
+            let mut args = Vec::new();
+            let mut named_args = Vec::new();
+            if self.next_match(TokenType::LeftPar) {
+                loop {
+                    match (self.peek().t.clone(), self.peekpeek().t.clone()) {
+                        (TokenType::StringLit(arg), _) => {
+                            args.push(arg);
+                            self.advance();
+                        }
+                        (TokenType::Identifier(key), TokenType::Equal) => {
+                            self.advance();
+                            self.advance();
+                            match self.peek().t.clone() {
+                                TokenType::StringLit(value) => named_args.push((key, value)),
+                                _ => break,
+                            }
+                            self.advance();
+                        }
+                        _ => break,
+                    }
+                    if !self.next_match(TokenType::Comma) {
+                        break;
+                    }
+                }
+                self.next_match_report_synchronize_decl(
+                    TokenType::RightPar,
+                    "Attribute arguments must be string literals or 'key = \"value\"' pairs, followed by a parenthesis ')'",
+                )?;
+            }
+            let end = self.peek().loc;
+            self.next_match_report_synchronize_decl(
+                TokenType::RightBracket,
+                "Expected a right bracket ']' to close the attribute",
+            )?;
+            attributes.push(Attribute {
+                ident,
+                args,
+                named_args,
+                loc: start.merge(end),
+            });
+        }
+        Ok(attributes)
+    }
+
     /// Parses the 'parameters' grammar element
     fn parameters(&mut self) -> Vec<Parameter> {
         let mut params = Vec::new();
@@ -595,6 +837,7 @@ impl<'err, E: ErrorHandler> Parser<'err, E> {
             params.push(Parameter {
                 ident,
                 t,
+                id: self.node_id(),
                 loc: var_loc,
             });
             if !self.next_match(TokenType::Comma) {
@@ -633,6 +876,10 @@ impl<'err, E: ErrorHandler> Parser<'err, E> {
                 self.advance();
                 self.return_stmt()
             }
+            TokenType::Defer => {
+                self.advance();
+                self.defer_stmt()
+            }
             _ => self.expr_or_assign_stmt(),
         }
     }
@@ -673,6 +920,11 @@ impl<'err, E: ErrorHandler> Parser<'err, E> {
                 return Err(());
             }
         };
+        let t = if self.next_match(TokenType::Colon) {
+            Some(self.type_()?)
+        } else {
+            None
+        };
         self.next_match_report_synchronize(
             TokenType::Equal,
             "Let statement requires an \"=\" after the identifier",
@@ -682,8 +934,9 @@ impl<'err, E: ErrorHandler> Parser<'err, E> {
         Ok(Statement::LetStmt {
             var: Variable {
                 namespace: None,
-                t: None,
+                t,
                 ident,
+                id: self.node_id(),
                 loc,
             },
             expr,
@@ -758,6 +1011,16 @@ impl<'err, E: ErrorHandler> Parser<'err, E> {
         }
     }
 
+    /// Parses the 'defer_stmt' grammar element (assuming the `defer` token has
+    /// been consumed )
+    fn defer_stmt(&mut self) -> Result<Statement, ()> {
+        // The `defer` token must have been consumed
+        let loc = self.previous().loc;
+        let expr = self.expression(true)?;
+        self.consume_semi_colon();
+        Ok(Statement::DeferStmt { expr, loc })
+    }
+
     /// Parses the 'block' grammar element (assuming the `{` token has been
     /// consumed )
     fn block(&mut self) -> Result<Block, ()> {
@@ -773,235 +1036,115 @@ impl<'err, E: ErrorHandler> Parser<'err, E> {
         Ok(Block { stmts })
     }
 
-    /// Parses the 'expression' grammar element. As the grammar is unambiguous,
-    /// precedence are directly baked into the grammar: the lowest priority
-    /// elements are processed first, and will recursively call elements of
-    /// higher priority
+    /// Parses the 'expression' grammar element. Binary operations are parsed by precedence
+    /// climbing, driven by the operator table of `binary_operator`.
     ///
     /// Struct literals may be disallowed inside some expressions to remove ambiguity (consider `if
     /// x == MyStruct {} {}`), in that case `struct_lit` should be set to `false`.
     fn expression(&mut self, struct_lit: bool) -> Result<Expression, ()> {
-        self.logical_or(struct_lit)
-    }
-
-    fn logical_or(&mut self, struct_lit: bool) -> Result<Expression, ()> {
-        let mut left_and = self.logical_and(struct_lit)?;
-
-        while self.next_match(TokenType::OrOr) {
-            let right_and = self.logical_and(struct_lit)?;
-            left_and = Expression::Binary {
-                expr_left: Box::new(left_and),
-                binop: BinaryOperator::Or,
-                expr_right: Box::new(right_and),
-            }
-        }
-        Ok(left_and)
+        self.binary(0, struct_lit)
     }
 
-    fn logical_and(&mut self, struct_lit: bool) -> Result<Expression, ()> {
-        let mut left_eq = self.equality(struct_lit)?;
+    /// Parses a sequence of binary operations whose operators have a precedence of at least
+    /// `min_precedence`.
+    fn binary(&mut self, min_precedence: u8, struct_lit: bool) -> Result<Expression, ()> {
+        let mut left = self.unary(struct_lit)?;
+        let mut previous = None;
 
-        while self.next_match(TokenType::AndAnd) {
-            let right_eq = self.logical_and(struct_lit)?;
-            left_eq = Expression::Binary {
-                expr_left: Box::new(left_eq),
-                binop: BinaryOperator::And,
-                expr_right: Box::new(right_eq),
-            }
-        }
-        Ok(left_eq)
-    }
-
-    fn equality(&mut self, struct_lit: bool) -> Result<Expression, ()> {
-        let mut left_comp = self.comparison(struct_lit)?;
-
-        loop {
-            let binop = match self.peek().t {
-                TokenType::EqualEqual => BinaryOperator::Equal,
-                TokenType::BangEqual => BinaryOperator::NotEqual,
-                _ => break,
-            };
-            self.advance();
-            let right_comp = self.comparison(struct_lit)?;
-            left_comp = Expression::Binary {
-                expr_left: Box::new(left_comp),
-                binop,
-                expr_right: Box::new(right_comp),
-            }
-        }
-        Ok(left_comp)
-    }
-
-    fn comparison(&mut self, struct_lit: bool) -> Result<Expression, ()> {
-        let mut left_b_or = self.bitwise_or(struct_lit)?;
-
-        loop {
-            let binop = match self.peek().t {
-                TokenType::Less => BinaryOperator::Less,
-                TokenType::LessEqual => BinaryOperator::LessEqual,
-                TokenType::Greater => BinaryOperator::Greater,
-                TokenType::GreaterEqual => BinaryOperator::GreaterEqual,
-                _ => break,
-            };
-            self.advance();
-            let right_b_or = self.bitwise_or(struct_lit)?;
-            left_b_or = Expression::Binary {
-                expr_left: Box::new(left_b_or),
-                binop,
-                expr_right: Box::new(right_b_or),
-            }
-        }
-        Ok(left_b_or)
-    }
-
-    fn bitwise_or(&mut self, struct_lit: bool) -> Result<Expression, ()> {
-        let mut left_b_and = self.bitwise_xor(struct_lit)?;
-
-        while self.next_match(TokenType::Or) {
-            let right_b_and = self.bitwise_xor(struct_lit)?;
-            left_b_and = Expression::Binary {
-                expr_left: Box::new(left_b_and),
-                binop: BinaryOperator::BitwiseOr,
-                expr_right: Box::new(right_b_and),
-            }
-        }
-        Ok(left_b_and)
-    }
-
-    fn bitwise_xor(&mut self, struct_lit: bool) -> Result<Expression, ()> {
-        let mut left_b_and = self.bitwise_and(struct_lit)?;
-
-        while self.next_match(TokenType::Hat) {
-            let right_b_and = self.bitwise_and(struct_lit)?;
-            left_b_and = Expression::Binary {
-                expr_left: Box::new(left_b_and),
-                binop: BinaryOperator::BitwiseXor,
-                expr_right: Box::new(right_b_and),
+        while let Some((binop, precedence, assoc)) = binary_operator(&self.peek().t) {
+            if precedence < min_precedence {
+                break;
             }
-        }
-        Ok(left_b_and)
-    }
-
-    fn bitwise_and(&mut self, struct_lit: bool) -> Result<Expression, ()> {
-        let mut left_add = self.addition(struct_lit)?;
-
-        while self.next_match(TokenType::And) {
-            let right_add = self.addition(struct_lit)?;
-            left_add = Expression::Binary {
-                expr_left: Box::new(left_add),
-                binop: BinaryOperator::BitwiseAnd,
-                expr_right: Box::new(right_add),
+            let loc = self.advance().loc;
+            if assoc == Associativity::None && previous == Some(precedence) {
+                self.err.report(
+                    loc,
+                    String::from("Comparison operators can not be chained, add parentheses"),
+                );
             }
-        }
-        Ok(left_add)
-    }
-
-    fn addition(&mut self, struct_lit: bool) -> Result<Expression, ()> {
-        let mut left_mult = self.multiplication(struct_lit)?;
-
-        loop {
-            let binop = match self.peek().t {
-                TokenType::Plus => BinaryOperator::Plus,
-                TokenType::Minus => BinaryOperator::Minus,
-                _ => break,
-            };
-            self.advance();
-            let right_mult = self.multiplication(struct_lit)?;
-            left_mult = Expression::Binary {
-                expr_left: Box::new(left_mult),
+            // Operands of left associative operators only contain operators binding tighter
+            let right = self.binary(precedence + 1, struct_lit)?;
+            left = Expression::Binary {
+                loc: left.get_loc().merge(right.get_loc()),
+                expr_left: Box::new(left),
                 binop,
-                expr_right: Box::new(right_mult),
-            }
-        }
-        Ok(left_mult)
-    }
-
-    fn multiplication(&mut self, struct_lit: bool) -> Result<Expression, ()> {
-        let mut left_unary = self.unary(struct_lit)?;
-
-        loop {
-            let binop = match self.peek().t {
-                TokenType::Star => BinaryOperator::Multiply,
-                TokenType::Slash => BinaryOperator::Divide,
-                TokenType::Percent => BinaryOperator::Remainder,
-                _ => break,
+                expr_right: Box::new(right),
+                id: self.node_id(),
             };
-            self.advance();
-            let right_unary = self.unary(struct_lit)?;
-            left_unary = Expression::Binary {
-                expr_left: Box::new(left_unary),
-                binop,
-                expr_right: Box::new(right_unary),
-            }
+            previous = Some(precedence);
         }
-        Ok(left_unary)
+        Ok(left)
     }
 
     fn unary(&mut self, struct_lit: bool) -> Result<Expression, ()> {
-        let token = self.peek();
-
-        match token.t {
-            TokenType::Bang => {
-                self.advance();
-                Ok(Expression::Unary {
-                    unop: UnaryOperator::Not,
-                    expr: Box::new(self.unary(struct_lit)?),
-                })
-            }
-            TokenType::Minus => {
-                self.advance();
-                Ok(Expression::Unary {
-                    unop: UnaryOperator::Minus,
-                    expr: Box::new(self.unary(struct_lit)?),
-                })
-            }
+        match self.peek().t {
+            TokenType::Bang => self.unary_operation(UnaryOperator::Not, struct_lit),
+            TokenType::Minus => self.unary_operation(UnaryOperator::Minus, struct_lit),
+            TokenType::And => self.unary_operation(UnaryOperator::Reference, struct_lit),
+            TokenType::Star => self.unary_operation(UnaryOperator::Dereference, struct_lit),
             _ => self.call(struct_lit),
         }
     }
 
-    fn call(&mut self, struct_lit: bool) -> Result<Expression, ()> {
-        let mut expr = self.access(struct_lit)?;
-        while self.next_match(TokenType::LeftPar) {
-            let args = self.arguments();
-            self.next_match_report_synchronize(
-                TokenType::RightPar,
-                "Expected a closing parenthesis `)` to function call",
-            )?;
-            expr = Expression::Call {
-                fun: Box::new(expr),
-                args,
-            };
-        }
-        Ok(expr)
+    /// Parses a unary operation, the operator token is consumed here.
+    fn unary_operation(&mut self, unop: UnaryOperator, struct_lit: bool) -> Result<Expression, ()> {
+        let start = self.advance().loc;
+        let expr = self.unary(struct_lit)?;
+        Ok(Expression::Unary {
+            unop,
+            loc: start.merge(expr.get_loc()),
+            expr: Box::new(expr),
+            id: self.node_id(),
+        })
     }
 
-    fn access(&mut self, struct_lit: bool) -> Result<Expression, ()> {
-        let mut namespace = self.primary(struct_lit)?;
-
+    fn call(&mut self, struct_lit: bool) -> Result<Expression, ()> {
+        let mut expr = self.primary(struct_lit)?;
         loop {
-            if self.next_match(TokenType::Dot) {
+            if self.next_match(TokenType::LeftPar) {
+                let args = self.arguments();
+                self.next_match_report_synchronize(
+                    TokenType::RightPar,
+                    "Expected a closing parenthesis `)` to function call",
+                )?;
+                expr = Expression::Call {
+                    loc: expr.get_loc().merge(self.previous().loc),
+                    fun: Box::new(expr),
+                    args,
+                    id: self.node_id(),
+                };
+            } else if self.next_match(TokenType::Dot) {
                 let field = self.primary(struct_lit)?;
-                namespace = Expression::Access {
-                    namespace: Box::new(namespace),
+                expr = Expression::Access {
+                    loc: expr.get_loc().merge(field.get_loc()),
+                    namespace: Box::new(expr),
                     field: Box::new(field),
-                }
+                    id: self.node_id(),
+                };
             } else {
                 break;
             }
         }
-        Ok(namespace)
+        Ok(expr)
     }
 
     fn primary(&mut self, struct_lit: bool) -> Result<Expression, ()> {
+        // Parenthesized expressions keep the ID of the inner expression, leaving this one unused
+        let id = self.node_id();
         let token = self.advance();
         let loc = token.loc;
 
         match &token.t {
-            TokenType::IntegerLit(n) => Ok(Expression::Literal(Value::Integer { val: *n, loc })),
-            TokenType::FloatLit(x) => Ok(Expression::Literal(Value::Float { val: *x, loc })),
-            TokenType::BooleanLit(b) => Ok(Expression::Literal(Value::Boolean { val: *b, loc })),
+            TokenType::IntegerLit(n) => {
+                Ok(Expression::Literal(Value::Integer { val: *n, id, loc }))
+            }
+            TokenType::FloatLit(x) => Ok(Expression::Literal(Value::Float { val: *x, id, loc })),
+            TokenType::BooleanLit(b) => {
+                Ok(Expression::Literal(Value::Boolean { val: *b, id, loc }))
+            }
             TokenType::StringLit(ref s) => Ok(Expression::Literal(Value::Str {
                 val: s.clone(),
+                id,
                 loc,
             })),
             TokenType::Identifier(ref x) => {
@@ -1019,6 +1162,7 @@ impl<'err, E: ErrorHandler> Parser<'err, E> {
                         namespace: None,
                         ident,
                         fields,
+                        id,
                         loc,
                     }))
                 } else {
@@ -1027,6 +1171,7 @@ impl<'err, E: ErrorHandler> Parser<'err, E> {
                         namespace: None,
                         t: None,
                         ident,
+                        id,
                         loc,
                     }))
                 }
@@ -1054,12 +1199,39 @@ impl<'err, E: ErrorHandler> Parser<'err, E> {
                     }
                 }
                 let loc = loc.merge(self.previous().loc);
-                Ok(Expression::Literal(Value::Tuple { values, loc }))
+                Ok(Expression::Literal(Value::Tuple { values, id, loc }))
             }
+            TokenType::Fun => self.lambda(id, loc),
             _ => Err(()),
         }
     }
 
+    /// Parses the 'lambda' grammar element (assuming the `fun` token has been consumed)
+    fn lambda(&mut self, id: NodeId, loc: Location) -> Result<Expression, ()> {
+        self.next_match_report(
+            TokenType::LeftPar,
+            "Parenthesis are expected after 'fun' in anonymous function",
+        )?;
+        let params = self.parameters();
+        self.next_match_report(
+            TokenType::RightPar,
+            "Expected a closing parenthesis ')' after the parameters",
+        )?;
+        let result = self.result();
+        self.next_match_report(
+            TokenType::LeftBrace,
+            "A left brace '{' is expected at the beginning of the function body.",
+        )?;
+        let body = self.block()?;
+        Ok(Expression::Lambda(Lambda {
+            params,
+            result,
+            body,
+            id,
+            loc,
+        }))
+    }
+
     fn arguments(&mut self) -> Vec<Expression> {
         let mut args = Vec::new();
         while let Ok(expr) = self.expression(true) {
@@ -1118,11 +1290,17 @@ impl<'err, E: ErrorHandler> Parser<'err, E> {
                 ident: ident.clone(),
                 namespace: None,
                 t: None,
+                id: self.node_id(),
                 loc,
             })
         };
 
-        return Ok(Some(FieldValue { ident, expr, loc }));
+        return Ok(Some(FieldValue {
+            ident,
+            expr,
+            id: self.node_id(),
+            loc,
+        }));
     }
 
     fn path(&mut self) -> Result<Path, ()> {
@@ -1142,6 +1320,7 @@ impl<'err, E: ErrorHandler> Parser<'err, E> {
         let mut path = Path {
             root: ident,
             path: Vec::new(),
+            id: self.node_id(),
             loc,
         };
         while self.next_match(TokenType::Dot) {
@@ -1184,6 +1363,35 @@ impl<'err, E: ErrorHandler> Parser<'err, E> {
                 "Expected a right parenthesis ')'",
             )?;
             Ok(Type::Tuple(paths, tuple_loc))
+        } else if self.next_match(TokenType::And) {
+            // Reference type
+            let t = self.type_()?;
+            let loc = loc.merge(t.get_loc());
+            Ok(Type::Ref(Box::new(t), loc))
+        } else if self.next_match(TokenType::Fun) {
+            // Function type
+            self.next_match_report(TokenType::LeftPar, "Expected a parenthesis '('")?;
+            let mut params = vec![];
+            while !self.next_match(TokenType::RightPar) {
+                params.push(self.type_()?);
+                if !self.next_match(TokenType::Comma) {
+                    self.next_match_report_synchronize(
+                        TokenType::RightPar,
+                        "Expected a right parenthesis ')'",
+                    )?;
+                    break;
+                }
+            }
+            let result = self.result().map(Box::new);
+            let loc = match &result {
+                Some(t) => loc.merge(t.get_loc()),
+                None => loc.merge(self.previous().loc),
+            };
+            Ok(Type::Fun {
+                params,
+                result,
+                loc,
+            })
         } else {
             // Simple type
             Ok(Type::Simple(self.path()?))
@@ -1203,3 +1411,39 @@ impl<'err, E: ErrorHandler> Parser<'err, E> {
         }
     }
 }
+
+/// Associativity of binary operators.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Associativity {
+    /// `a op b op c` is parsed as `(a op b) op c`.
+    Left,
+    /// The operator can not be chained with operators of the same precedence.
+    None,
+}
+
+/// The table of binary operators: returns the operator corresponding to a token along with its
+/// precedence, operators with a higher precedence bind tighter, and its associativity.
+fn binary_operator(t: &TokenType) -> Option<(BinaryOperator, u8, Associativity)> {
+    use Associativity::*;
+    use BinaryOperator as Op;
+    let operator = match t {
+        TokenType::OrOr => (Op::Or, 1, Left),
+        TokenType::AndAnd => (Op::And, 2, Left),
+        TokenType::EqualEqual => (Op::Equal, 3, None),
+        TokenType::BangEqual => (Op::NotEqual, 3, None),
+        TokenType::Less => (Op::Less, 4, None),
+        TokenType::LessEqual => (Op::LessEqual, 4, None),
+        TokenType::Greater => (Op::Greater, 4, None),
+        TokenType::GreaterEqual => (Op::GreaterEqual, 4, None),
+        TokenType::Or => (Op::BitwiseOr, 5, Left),
+        TokenType::Hat => (Op::BitwiseXor, 6, Left),
+        TokenType::And => (Op::BitwiseAnd, 7, Left),
+        TokenType::Plus => (Op::Plus, 8, Left),
+        TokenType::Minus => (Op::Minus, 8, Left),
+        TokenType::Star => (Op::Multiply, 9, Left),
+        TokenType::Slash => (Op::Divide, 9, Left),
+        TokenType::Percent => (Op::Remainder, 9, Left),
+        _ => return Option::None,
+    };
+    Some(operator)
+}
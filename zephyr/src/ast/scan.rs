@@ -1,29 +1,32 @@
 use std::collections::HashMap;
 
+use super::termination::{TerminationPolicy, Terminator, TokenClass};
 use super::tokens::*;
-use crate::error::{ErrorHandler, Location};
+use crate::error::{DummyHandler, ErrorHandler, Location};
 use crate::resolver::FileId;
 
 const RADIX: u32 = 10;
 
-/// Stores source code as a vector of chars and provides functions to convert
-/// the source code to a list of tokens.
-pub struct Scanner<'a, E: ErrorHandler> {
+/// Borrows the source code and provides functions to convert it to a list of tokens.
+///
+/// The code is walked by byte offsets (`start` and `current`), always kept on char boundaries,
+/// which are also used for locations.
+pub struct Scanner<'a, 'code, E: ErrorHandler> {
     err: &'a mut E,
     f_id: FileId,
-    code: Vec<char>,
+    code: &'code str,
     start: usize,
     current: usize,
     keywords: HashMap<String, TokenType>,
-    stmt_ender: bool,
-    parenthesis_count: i32,
+    terminator: Terminator,
+    keep_trivia: bool,
 }
 
-impl<'a, E: ErrorHandler> Scanner<'a, E> {
-    // f_id MUST exist, no check performed.
-    pub fn new(f_id: FileId, error_handler: &'a mut E) -> Self {
+impl<'a, 'code, E: ErrorHandler> Scanner<'a, 'code, E> {
+    pub fn new(f_id: FileId, code: &'code str, error_handler: &'a mut E) -> Self {
         let keywords: HashMap<String, TokenType> = [
             (String::from("as"), TokenType::As),
+            (String::from("defer"), TokenType::Defer),
             (String::from("else"), TokenType::Else),
             (String::from("expose"), TokenType::Expose),
             (String::from("false"), TokenType::False),
@@ -31,6 +34,7 @@ impl<'a, E: ErrorHandler> Scanner<'a, E> {
             (String::from("fun"), TokenType::Fun),
             (String::from("if"), TokenType::If),
             (String::from("import"), TokenType::Import),
+            (String::from("init"), TokenType::Init),
             (String::from("let"), TokenType::Let),
             (String::from("module"), TokenType::Module),
             (String::from("pub"), TokenType::Pub),
@@ -47,21 +51,24 @@ impl<'a, E: ErrorHandler> Scanner<'a, E> {
         .cloned()
         .collect();
 
-        // f_id MUST exist
-        let code = error_handler.get_file(f_id).unwrap();
-
         Scanner {
-            code: code.chars().collect(), // TODO: remove this copy
+            code,
             err: error_handler,
             f_id,
             start: 0,
             current: 0,
             keywords,
-            stmt_ender: false,
-            parenthesis_count: 0,
+            terminator: Terminator::new(TerminationPolicy::Automatic),
+            keep_trivia: false,
         }
     }
 
+    /// Emit comments and line breaks as tokens instead of inserting statement enders, this is
+    /// used by tools which need to preserve the layout of the code, such as the formatter.
+    pub fn keep_trivia(&mut self) {
+        self.keep_trivia = true;
+    }
+
     /// Main function starting the conversion to tokens
     pub fn scan(&mut self) -> Vec<Token> {
         let mut tokens = Vec::new();
@@ -69,7 +76,7 @@ impl<'a, E: ErrorHandler> Scanner<'a, E> {
             self.scan_token(&mut tokens);
             self.start = self.current;
         }
-        if self.stmt_ender {
+        if self.terminator.end_of_file() && !self.keep_trivia {
             self.add_token(&mut tokens, TokenType::SemiColon);
         }
         self.add_token(&mut tokens, TokenType::EOF);
@@ -85,14 +92,18 @@ impl<'a, E: ErrorHandler> Scanner<'a, E> {
             ')' => self.add_token(tokens, TokenType::RightPar),
             '{' => self.add_token(tokens, TokenType::LeftBrace),
             '}' => self.add_token(tokens, TokenType::RightBrace),
+            '[' => self.add_token(tokens, TokenType::LeftBracket),
+            ']' => self.add_token(tokens, TokenType::RightBracket),
             ',' => self.add_token(tokens, TokenType::Comma),
             ':' => self.add_token(tokens, TokenType::Colon),
+            ';' => self.add_token(tokens, TokenType::SemiColon),
             '.' => self.add_token(tokens, TokenType::Dot),
             '-' => self.add_token(tokens, TokenType::Minus),
             '+' => self.add_token(tokens, TokenType::Plus),
             '*' => self.add_token(tokens, TokenType::Star),
             '%' => self.add_token(tokens, TokenType::Percent),
             '^' => self.add_token(tokens, TokenType::Hat),
+            '#' => self.add_token(tokens, TokenType::Hash),
             '!' => {
                 if self.next_match('=') {
                     self.add_token(tokens, TokenType::BangEqual)
@@ -141,12 +152,20 @@ impl<'a, E: ErrorHandler> Scanner<'a, E> {
                     while !self.is_at_end() && self.peek() != '\n' {
                         self.advance();
                     }
+                    if self.keep_trivia {
+                        let comment = self.code[self.start..self.current].to_string();
+                        self.add_token(tokens, TokenType::Comment(comment));
+                    }
+                } else if self.next_match('*') {
+                    self.block_comment(tokens);
                 } else {
                     self.add_token(tokens, TokenType::Slash)
                 }
             }
             '\n' => {
-                if self.stmt_ender {
+                if self.keep_trivia {
+                    self.add_token(tokens, TokenType::Newline)
+                } else if self.terminator.line_break(&self.code[self.current..]) {
                     self.add_token(tokens, TokenType::SemiColon)
                 }
             }
@@ -167,54 +186,49 @@ impl<'a, E: ErrorHandler> Scanner<'a, E> {
         }
     }
 
-    /// Checks if a statement ender (;) should be added in the next carriage
-    /// return
-    fn check_stmt_ender(&mut self, token: &Token) {
-        let candidate = match token.t {
-            TokenType::IntegerLit(_) => true,
-            TokenType::FloatLit(_) => true,
-            TokenType::BooleanLit(_) => true,
-            TokenType::StringLit(_) => true,
-            TokenType::Identifier(_) => true,
-            TokenType::Return => true,
-            TokenType::RightBrace => true,
-            TokenType::RightPar => {
-                self.parenthesis_count -= 1;
-                true
-            }
-            TokenType::LeftPar => {
-                self.parenthesis_count += 1;
-                false
-            }
-            _ => false,
-        };
-        self.stmt_ender = candidate && self.parenthesis_count <= 0
+    /// Returns the role of a token regarding the insertion of statement enders (;) at line
+    /// breaks, see the `termination` module.
+    fn classify(t: &TokenType, previous: Option<&TokenType>) -> TokenClass {
+        let follows_dot = previous == Some(&TokenType::Dot);
+        match t {
+            TokenType::IntegerLit(_)
+            | TokenType::FloatLit(_)
+            | TokenType::BooleanLit(_)
+            | TokenType::StringLit(_)
+            | TokenType::Identifier(_)
+            | TokenType::Return => TokenClass::End,
+            // The star of a glob import, such as `use pkg.mod.*`
+            TokenType::Star if follows_dot => TokenClass::End,
+            TokenType::LeftPar => TokenClass::OpenGroup,
+            TokenType::RightPar => TokenClass::CloseGroup,
+            TokenType::LeftBrace => TokenClass::OpenBlock,
+            TokenType::RightBrace => TokenClass::CloseBlock,
+            TokenType::SemiColon => TokenClass::Separator,
+            _ => TokenClass::Continue,
+        }
     }
 
     /// Checks if the next character is a given char and consumes it if true.
     fn next_match(&mut self, c: char) -> bool {
         if self.is_at_end() {
             false
-        } else if self.code[self.current] != c {
+        } else if self.peek() != c {
             false
         } else {
-            self.current += 1;
+            self.advance();
             true
         }
     }
 
     /// Wrapper to initialize a token while keeping track of its location, and
-    /// sets the statement ender flag
+    /// of the statement termination
     fn add_token(&mut self, tokens: &mut Vec<Token>, t: TokenType) {
         let token = Token {
             t,
-            loc: Location {
-                pos: self.start as u32,
-                len: (self.current - self.start) as u32,
-                f_id: self.f_id,
-            },
+            loc: self.get_loc(),
         };
-        self.check_stmt_ender(&token);
+        let class = Self::classify(&token.t, tokens.last().map(|last| &last.t));
+        self.terminator.token(class);
         tokens.push(token);
     }
 
@@ -227,15 +241,12 @@ impl<'a, E: ErrorHandler> Scanner<'a, E> {
         }
     }
 
-    /// Get the location of a previous groups of character.
-    ///
-    /// params:
-    ///  - back: how many character since the start of the location.
-    ///  - len: the number of characters after the start of the location.
-    fn get_previous_loc(&self, back: u32, len: u32) -> Location {
+    /// Get the location from the byte offset `start`, within the current token, up to the
+    /// current position.
+    fn get_loc_from(&self, start: usize) -> Location {
         Location {
-            pos: (self.current as u32).checked_sub(back).unwrap(),
-            len,
+            pos: start as u32,
+            len: (self.current - start) as u32,
             f_id: self.f_id,
         }
     }
@@ -245,47 +256,50 @@ impl<'a, E: ErrorHandler> Scanner<'a, E> {
         self.current >= self.code.len()
     }
 
-    /// Move the cursor one position to the right and return the character
+    /// Move the cursor one character to the right and return the character
     fn advance(&mut self) -> char {
         let c = self.peek();
-        self.current += 1;
+        if !self.is_at_end() {
+            self.current += c.len_utf8();
+        }
         c
     }
 
-    /// Returns the current character
+    /// Returns the current character, or '\0' at the end of the file
     fn peek(&self) -> char {
-        self.code[self.current]
+        self.code[self.current..].chars().next().unwrap_or('\0')
     }
 
     /// Consumes consecutive digit characters and push a number token
     fn number(&mut self, tokens: &mut Vec<Token>) {
         let mut is_integer = true;
         let mut radix = RADIX;
+        // The location of the token includes the radix prefix, if any.
+        let mut digits_start = self.start;
         if self.peek() == 'x' {
             radix = 16;
             self.advance();
-            self.start = self.current;
+            digits_start = self.current;
         } else if self.peek() == 'b' {
             radix = 2;
             self.advance();
-            self.start = self.current;
+            digits_start = self.current;
         }
         while !self.is_at_end() && self.peek().is_digit(radix) {
             self.advance();
         }
-        if self.peek() == '.' {
+        // A number following a dot is a tuple field, as in `t.0.1`, and is never a float
+        let is_field = tokens.last().map(|token| &token.t) == Some(&TokenType::Dot);
+        if self.peek() == '.' && !is_field {
             self.advance();
             is_integer = false;
             while !self.is_at_end() && self.peek().is_digit(radix) {
                 self.advance();
             }
         }
-        let str_val = self.code[self.start..self.current]
-            .iter()
-            .cloned()
-            .collect::<String>();
+        let str_val = &self.code[digits_start..self.current];
         if is_integer {
-            match u64::from_str_radix(&str_val, radix) {
+            match u64::from_str_radix(str_val, radix) {
                 Ok(n) => self.add_token(tokens, TokenType::IntegerLit(n)),
                 Err(_) => self.err.report(
                     self.get_loc(),
@@ -310,11 +324,50 @@ impl<'a, E: ErrorHandler> Scanner<'a, E> {
         }
     }
 
+    /// Consumes a comment up to the closing `*/`, which may span multiple lines.
+    fn block_comment(&mut self, tokens: &mut Vec<Token>) {
+        loop {
+            if self.is_at_end() {
+                // Report the opening delimiter, the end of file tells nothing about the comment
+                let loc = Location {
+                    pos: self.start as u32,
+                    len: 2,
+                    f_id: self.f_id,
+                };
+                self.err
+                    .report(loc, String::from("Unterminated block comment"));
+                return;
+            }
+            if self.advance() == '*' && self.next_match('/') {
+                break;
+            }
+        }
+        if self.keep_trivia {
+            let comment = self.code[self.start..self.current].to_string();
+            self.add_token(tokens, TokenType::Comment(comment));
+        }
+    }
+
     /// Consumes any (non carriage-return) characters between double quotes
     fn string(&mut self, tokens: &mut Vec<Token>) {
         let mut str_val = String::new();
         // Consume until the next char is a double quote
-        while !self.is_at_end() {
+        loop {
+            if self.is_at_end() || self.peek() == '\n' {
+                // The literal is kept so that parsing can go on, the line break is left for the
+                // statement ender.
+                let loc = Location {
+                    pos: self.start as u32,
+                    len: 1,
+                    f_id: self.f_id,
+                };
+                self.err.report(
+                    loc,
+                    String::from("Unterminated string literal, strings must end on the same line"),
+                );
+                break;
+            }
+            let char_start = self.current;
             match self.advance() {
                 '\\' => match self.advance() {
                     '\\' => str_val.push('\\'),
@@ -324,21 +377,13 @@ impl<'a, E: ErrorHandler> Scanner<'a, E> {
                     '0' => str_val.push('\0'),
                     '"' => str_val.push('"'),
                     c => {
-                        let loc = self.get_previous_loc(2, 2);
+                        let loc = self.get_loc_from(char_start);
                         self.err
                             .warn(loc, format!("Invalid escape sequence: '\\{}'", c));
                         str_val.push(c);
                     }
                 },
                 '"' => break, // End of string
-                '\n' => {
-                    // Exit if the double quote is not found on this line
-                    self.err.report(
-                        self.get_loc(),
-                        String::from("string literal should start and end on the same line"),
-                    );
-                    break;
-                }
                 c => str_val.push(c),
             }
         }
@@ -353,12 +398,8 @@ impl<'a, E: ErrorHandler> Scanner<'a, E> {
         while !self.is_at_end() && self.peek().is_alphanumeric() || self.peek() == '_' {
             self.advance();
         }
-        // Convert that sequence of chars to a string
-        let ident = self.code[self.start..self.current]
-            .iter()
-            .cloned()
-            .collect::<String>();
-        match self.keywords.get(&ident) {
+        let ident = &self.code[self.start..self.current];
+        match self.keywords.get(ident) {
             // Check if the string is a keyword
             Some(t) => match t {
                 // Booleans are literals but included in the keywords hashmap
@@ -371,7 +412,143 @@ impl<'a, E: ErrorHandler> Scanner<'a, E> {
                 }
             },
             // If it's not a keyword, it's an identifier
-            None => self.add_token(tokens, TokenType::Identifier(ident)),
+            None => self.add_token(tokens, TokenType::Identifier(ident.to_string())),
+        }
+    }
+}
+
+/// Returns true if `name` is scanned as a single identifier, keywords are not identifiers.
+pub fn is_identifier(name: &str) -> bool {
+    let mut err = DummyHandler::new();
+    let tokens = Scanner::new(FileId(0), name, &mut err).scan();
+    let mut tokens = tokens
+        .iter()
+        .filter(|token| !matches!(token.t, TokenType::SemiColon | TokenType::EOF));
+    match (tokens.next(), tokens.next()) {
+        (Some(token), None) => {
+            matches!(token.t, TokenType::Identifier(_))
+                && token.loc.len as usize == name.len()
+                && !err.has_error()
         }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::DummyHandler;
+
+    #[test]
+    fn utf8_locations() {
+        let mut err = DummyHandler::new();
+        let f_id = FileId(0);
+        let code = "let héllo = \"ü\"";
+        let tokens = Scanner::new(f_id, code, &mut err).scan();
+
+        assert!(!err.has_error());
+        assert_eq!(tokens[1].t, TokenType::Identifier(String::from("héllo")));
+        // Locations are expressed in bytes, not chars
+        assert_eq!((tokens[1].loc.pos, tokens[1].loc.len), (4, 6));
+        assert_eq!(tokens[3].t, TokenType::StringLit(String::from("ü")));
+        assert_eq!((tokens[3].loc.pos, tokens[3].loc.len), (13, 4));
+    }
+    #[test]
+    fn unterminated_literals() {
+        let f_id = FileId(0);
+        let mut err = DummyHandler::new();
+        let tokens = Scanner::new(f_id, "let a = \"abc\nlet b = 1", &mut err).scan();
+        let types = tokens.iter().map(|t| t.t.clone()).collect::<Vec<_>>();
+        assert!(err.has_error());
+        // The string is closed at the end of the line, the next statement is still scanned
+        assert_eq!(types[3], TokenType::StringLit(String::from("abc")));
+        assert_eq!(types[4], TokenType::SemiColon);
+        assert_eq!(types[5], TokenType::Let);
+
+        let mut err = DummyHandler::new();
+        let tokens = Scanner::new(f_id, "let a = 1 /* a\ncomment", &mut err).scan();
+        assert!(err.has_error());
+        assert_eq!(tokens.last().map(|t| &t.t), Some(&TokenType::EOF));
+
+        let mut err = DummyHandler::new();
+        let tokens = Scanner::new(f_id, "let /* a\n* comment */ a = \"*/\"", &mut err).scan();
+        assert!(!err.has_error());
+        assert_eq!(tokens[1].t, TokenType::Identifier(String::from("a")));
+        assert_eq!(tokens[3].t, TokenType::StringLit(String::from("*/")));
+    }
+
+    #[test]
+    fn identifiers() {
+        assert!(is_identifier("héllo"));
+        assert!(is_identifier("_a1"));
+        assert!(!is_identifier("let"));
+        assert!(!is_identifier("true"));
+        assert!(!is_identifier("a b"));
+        assert!(!is_identifier(" a"));
+        assert!(!is_identifier("1a"));
+        assert!(!is_identifier(""));
+    }
+
+    #[test]
+    fn tuple_fields() {
+        let mut err = DummyHandler::new();
+        let tokens = Scanner::new(FileId(0), "t.0.1 + 0.5", &mut err).scan();
+        let types = tokens.iter().map(|t| t.t.clone()).collect::<Vec<_>>();
+        assert!(!err.has_error());
+        // Numbers following a dot are tuple fields, not floats
+        assert_eq!(types[2], TokenType::IntegerLit(0));
+        assert_eq!(types[3], TokenType::Dot);
+        assert_eq!(types[4], TokenType::IntegerLit(1));
+        assert_eq!(types[6], TokenType::FloatLit(0.5));
+    }
+
+    /// Returns the scanned tokens separated by spaces, with statement enders as `;`.
+    fn terminated(code: &str) -> String {
+        let mut err = DummyHandler::new();
+        let tokens = Scanner::new(FileId(0), code, &mut err).scan();
+        assert!(!err.has_error());
+        let lexemes = tokens.iter().filter_map(|token| match token.t {
+            TokenType::EOF => None,
+            TokenType::SemiColon => Some(";"),
+            _ => {
+                let start = token.loc.pos as usize;
+                Some(&code[start..(start + token.loc.len as usize)])
+            }
+        });
+        lexemes.collect::<Vec<_>>().join(" ")
+    }
+
+    #[test]
+    fn statement_termination() {
+        assert_eq!(terminated("let x = a\nreturn\n"), "let x = a ; return ;");
+        // Explicit semicolons, a line break does not terminate the statement twice
+        assert_eq!(terminated("f(); g()\nh();\n"), "f ( ) ; g ( ) ; h ( ) ;");
+        // Wrapping after a binary operator or within parentheses
+        assert_eq!(
+            terminated("let x = a +\n    b\nf(a,\n  b\n)\n"),
+            "let x = a + b ; f ( a , b ) ;"
+        );
+        // Wrapping before an operator which can not start a statement
+        assert_eq!(
+            terminated("let x = a\n    && b\n    || c.d\n    .e == 1\nx = y\n"),
+            "let x = a && b || c . d . e == 1 ; x = y ;"
+        );
+        // A minus or a bang may start a new statement
+        assert_eq!(terminated("a\n-b\n!c\n"), "a ; - b ; ! c ;");
+        // Blocks nested in parentheses, such as closures, terminate their statements
+        assert_eq!(
+            terminated("f(fun(x: i32) {\n    g(x)\n    h(\n        x)\n})\n"),
+            "f ( fun ( x : i32 ) { g ( x ) ; h ( x ) ; } ) ;"
+        );
+        // Attributes do not terminate statements
+        assert_eq!(
+            terminated("#[inline]\nfun f() {}\n"),
+            "# [ inline ] fun f ( ) { } ;"
+        );
+        // The star of a glob import ends the statement, unlike a multiplication
+        assert_eq!(
+            terminated("use a.b.*\nuse a.{\n    c,\n    d\n}\nx = y *\n    z\n"),
+            "use a . b . * ; use a . { c , d ; } ; x = y * z ;"
+        );
     }
 }
@@ -0,0 +1,148 @@
+//! # Statement Termination
+//!
+//! Statements are terminated by semicolons, which can be written explicitly but are usually
+//! inserted by the scanners at line breaks. This module holds the rules deciding whether a line
+//! break terminates a statement, shared by the Zephyr and assembly scanners which follow
+//! different policies:
+//!
+//! - `Automatic` (Zephyr): similarly to Go, a line break terminates the statement if the last
+//!   token of the line may end a statement (a literal, an identifier, `return`, the `*` of a glob
+//!   import or a closing parenthesis or brace). Line breaks within parentheses are ignored, unless they are nested in
+//!   a block (e.g. the body of a closure passed as argument). An expression can be wrapped after
+//!   a binary operator, or before one if the next line starts with an operator that can not
+//!   start a statement (such as `+`, `&&` or `.`, but not `-` or `*`).
+//! - `EveryLine` (assembly): any line break following a token terminates the statement, except
+//!   after an opening brace.
+
+/// The policy deciding which line breaks terminate statements.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TerminationPolicy {
+    /// Line breaks terminate statements following the Zephyr rules.
+    Automatic,
+    /// All line breaks following a token terminate statements.
+    EveryLine,
+}
+
+/// The role of a token regarding statement termination.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TokenClass {
+    /// A token which may end a statement, such as a literal or an identifier.
+    End,
+    /// A token after which the statement continues on the next line, such as a binary operator.
+    Continue,
+    /// An opening parenthesis.
+    OpenGroup,
+    /// A closing parenthesis.
+    CloseGroup,
+    /// An opening brace.
+    OpenBlock,
+    /// A closing brace.
+    CloseBlock,
+    /// An explicit semicolon.
+    Separator,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Delimiter {
+    Group,
+    Block,
+}
+
+/// Keeps track of the tokens scanned so far to decide whether a line break terminates the
+/// current statement.
+pub struct Terminator {
+    policy: TerminationPolicy,
+    /// The delimiters enclosing the current token, innermost last.
+    delimiters: Vec<Delimiter>,
+    /// Whether the last token may end a statement.
+    can_end: bool,
+}
+
+impl Terminator {
+    pub fn new(policy: TerminationPolicy) -> Self {
+        Self {
+            policy,
+            delimiters: Vec::new(),
+            can_end: false,
+        }
+    }
+
+    /// Registers a new token, in the order of the source code.
+    pub fn token(&mut self, class: TokenClass) {
+        match class {
+            TokenClass::OpenGroup => self.delimiters.push(Delimiter::Group),
+            TokenClass::OpenBlock => self.delimiters.push(Delimiter::Block),
+            TokenClass::CloseGroup | TokenClass::CloseBlock => {
+                self.delimiters.pop();
+            }
+            _ => (),
+        }
+        self.can_end = match self.policy {
+            TerminationPolicy::Automatic => matches!(
+                class,
+                TokenClass::End | TokenClass::CloseGroup | TokenClass::CloseBlock
+            ),
+            TerminationPolicy::EveryLine => {
+                !matches!(class, TokenClass::OpenBlock | TokenClass::Separator)
+            }
+        };
+    }
+
+    /// Returns true if a semicolon must be inserted at the line break preceding `next`, the
+    /// remaining source code.
+    ///
+    /// The caller is expected to register the inserted semicolon as a `Separator`.
+    pub fn line_break(&mut self, next: &str) -> bool {
+        match self.policy {
+            TerminationPolicy::Automatic => {
+                self.can_end
+                    && self.delimiters.last() != Some(&Delimiter::Group)
+                    && !starts_with_operator(next)
+            }
+            TerminationPolicy::EveryLine => {
+                let terminate = self.can_end;
+                self.can_end = false;
+                terminate
+            }
+        }
+    }
+
+    /// Returns true if a semicolon must be inserted at the end of the file.
+    pub fn end_of_file(&self) -> bool {
+        self.policy == TerminationPolicy::Automatic && self.can_end
+    }
+}
+
+/// Returns true if the code starts with an operator which can not start a statement, once
+/// blanks are skipped.
+fn starts_with_operator(code: &str) -> bool {
+    let code = code.trim_start();
+    let mut chars = code.chars();
+    match (chars.next(), chars.next()) {
+        (Some('/'), Some('/')) | (Some('/'), Some('*')) => false,
+        (Some('='), next) | (Some('!'), next) => next == Some('='),
+        (Some(c), _) => matches!(c, '+' | '/' | '%' | '&' | '|' | '^' | '<' | '>' | '.'),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn operators() {
+        assert!(starts_with_operator("  + b"));
+        assert!(starts_with_operator("\n\t&& b"));
+        assert!(starts_with_operator(".field"));
+        assert!(starts_with_operator("== b"));
+        assert!(starts_with_operator("!= b"));
+        assert!(!starts_with_operator("-b"));
+        assert!(!starts_with_operator("*p = 1"));
+        assert!(!starts_with_operator("!b"));
+        assert!(!starts_with_operator("= b"));
+        assert!(!starts_with_operator("// comment"));
+        assert!(!starts_with_operator("let x = 1"));
+        assert!(!starts_with_operator(""));
+    }
+}
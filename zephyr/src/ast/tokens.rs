@@ -8,6 +8,8 @@ pub enum TokenType {
     RightPar,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
     Comma,
     Colon,
     Dot,
@@ -23,6 +25,7 @@ pub enum TokenType {
     And,
     Or,
     Hat,
+    Hash,
 
     // Two characters
     BangEqual,
@@ -41,6 +44,7 @@ pub enum TokenType {
 
     // Keywords
     As,
+    Defer,
     Else,
     Expose,
     False,
@@ -48,6 +52,7 @@ pub enum TokenType {
     Fun,
     If,
     Import,
+    Init,
     Let,
     Module,
     Pub,
@@ -60,6 +65,10 @@ pub enum TokenType {
     Var,
     While,
 
+    // Trivia, only produced when requested (see `Scanner::keep_trivia`)
+    Comment(String),
+    Newline,
+
     // Other
     SemiColon,
     EOF,
@@ -1,22 +1,84 @@
 //! The Compilation Context
-use std::cell::Cell;
-use std::collections::{HashMap, HashSet};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::time::Instant;
 
+use super::graph::{DotWriter, GraphKind};
 use super::known_functions;
 use super::known_functions::{
     KnownFunctionPaths, KnownFunctions, KnownStructPaths, KnownStructs, KnownValues,
 };
+use super::metrics::{FunctionMetrics, ModuleMetrics};
+use super::target::Target;
+use super::timings::{ModuleStats, Phase, Timings};
 use super::utils::ModuleDeclarations;
 use crate::ast;
-use crate::error::ErrorHandler;
+use crate::error::{ErrorHandler, Location, SourceMap};
 use crate::hir;
 use crate::mir;
-use crate::resolver::{ModuleKind, ModulePath, PreparedFile, Resolver};
+#[cfg(feature = "cranelift")]
+use crate::native;
+use crate::resolver::{FileId, FileKind, ModuleKind, ModulePath, PreparedFile, Resolver};
 use crate::wasm;
 
+/// Prefix of the names under which test functions are exposed, see `Ctx::set_tests`.
+pub const TEST_PREFIX: &str = "test:";
+
+/// A transformation applied to the generated WebAssembly, see `Ctx::set_post_process`.
+pub type PostProcess = Box<dyn FnMut(Vec<u8>) -> Result<Vec<u8>, String>>;
+
 #[derive(Hash, Eq, PartialEq, Copy, Clone, Debug)]
 pub struct ModId(pub u32);
 
+impl ModId {
+    /// Derives a module ID from a hash of `key`, `attempt` is incremented to rehash colliding
+    /// keys. The module ID 0 is reserved and the two upper bits are left clear, they are used by
+    /// the IDs derived from the ones of a module (see `hir::known_ids`).
+    fn from_hash(key: &str, attempt: u32) -> ModId {
+        let mut bytes = key.as_bytes().to_vec();
+        if attempt > 0 {
+            bytes.extend(&attempt.to_le_bytes());
+        }
+        let hash = wasm::content_hash(&bytes);
+        ModId((hash ^ (hash >> 32)) as u32 & 0x3fff_ffff)
+    }
+}
+
+/// The intermediate representations of a module, as text. See `Ctx::set_snapshot`.
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct Snapshot {
+    pub ast: String,
+    pub hir: String,
+    pub mir: String,
+    pub wat: String,
+}
+
+/// The outputs of a compilation to WebAssembly, see `Ctx::compile`.
+#[derive(Debug)]
+pub struct Artifacts {
+    /// The binary module.
+    pub wasm: Vec<u8>,
+    /// The module in the WebAssembly text format.
+    pub wat: String,
+    /// An ES module wrapping the module along with its TypeScript declarations, only generated
+    /// when JavaScript bindings are enabled.
+    pub js_bindings: Option<(String, String)>,
+    pub metadata: BuildMetadata,
+}
+
+/// A description of a build, meant to be consumed by build systems.
+#[derive(Debug)]
+pub struct BuildMetadata {
+    /// Version of the compiler.
+    pub compiler: String,
+    pub package: String,
+    pub target: Target,
+    /// Hash of the binary module, see `custom_sections`.
+    pub hash: String,
+    /// Names of the exposed functions, sorted.
+    pub exports: Vec<String>,
+}
+
 type StructMap = HashMap<hir::StructId, hir::Struct>;
 type TupleMap = HashMap<hir::TupleId, hir::Tuple>;
 type DataMap = HashMap<hir::DataId, hir::Data>;
@@ -34,16 +96,64 @@ pub struct Ctx {
     types: TypeMap,
     data: DataMap,
     funs: FunMap,
+    /// The module declaring each function.
+    fun_mods: HashMap<hir::FunId, ModId>,
+    inits: Vec<hir::FunId>,
+    memories: Vec<ast::Memory>,
+    exports: Vec<hir::ModuleExports>,
     mods: ModMap,
     mods_ids: ReverseModMap,
+    /// The modules used by each module.
+    mod_deps: HashMap<ModId, Vec<ModId>>,
     public_decls: DeclMap,
+    /// Modules declared along with the other modules of their import cycle, and not yet resolved.
+    declared: HashSet<ModulePath>,
+    /// Signatures of the functions of the declared modules.
+    signatures: HashMap<hir::FunId, hir::FunctionPrototype>,
+    /// Parsed modules waiting to be resolved.
+    asts: HashMap<ModulePath, ast::Program>,
+    /// Names of the parsed files, used to locate assertions at runtime.
+    file_names: HashMap<FileId, String>,
+    /// Code of the parsed files.
+    sources: SourceMap,
     imports: Vec<hir::Import>,
     packages: Vec<hir::Module>,
+    symbols: Vec<hir::Symbol>,
+    /// The AST nodes of all modules mapped to the HIR entities they declare or refer to.
+    nodes: hir::NodeMap,
+    /// The scopes of all modules, the nodes refer to them.
+    scopes: hir::ScopeMap,
 
     // Configuration
     knwon_values: KnownValues,
-    mod_id: Cell<ModId>,
-    verbose: bool,
+    /// The key each module ID has been derived from, see `stable_mod_id`.
+    mod_id_keys: RefCell<HashMap<ModId, String>>,
+    overflow_checks: bool,
+    trap_handler: Option<(ModulePath, String)>,
+    panic_handler: Option<(ModulePath, String)>,
+    /// Name of the package replacing the default runtime, resolved as `core`.
+    runtime: Option<String>,
+    /// Compile without any runtime, `core` is never loaded.
+    no_runtime: bool,
+    validate: bool,
+    memory_initial: Option<u32>,
+    memory_max: Option<u32>,
+    shared_memory: bool,
+    metadata: Option<String>,
+    fuel: Option<u64>,
+    inline_threshold: usize,
+    optimize: bool,
+    opt_size: bool,
+    gc: mir::GcStrategy,
+    custom_sections: Vec<(String, Vec<u8>)>,
+    post_process: Option<PostProcess>,
+    cfg: hir::Cfg,
+    target: Target,
+    features: ast::Features,
+    js_bindings: bool,
+    tests: Option<String>,
+    snapshot: Option<(ModulePath, Snapshot)>,
+    timings: Option<Timings>,
 }
 
 impl Ctx {
@@ -54,20 +164,253 @@ impl Ctx {
             types: HashMap::new(),
             data: HashMap::new(),
             funs: HashMap::new(),
+            fun_mods: HashMap::new(),
+            inits: Vec::new(),
+            memories: Vec::new(),
+            exports: Vec::new(),
             mods: HashMap::new(),
             mods_ids: HashMap::new(),
+            mod_deps: HashMap::new(),
             imports: Vec::new(),
             packages: Vec::new(),
+            symbols: Vec::new(),
+            nodes: hir::NodeMap::new(),
+            scopes: hir::ScopeMap::new(),
             public_decls: HashMap::new(),
-            knwon_values: KnownValues::uninitialized(),
-            mod_id: Cell::new(ModId(1)), // ModId 0 is reserverd
-            verbose: false,
+            declared: HashSet::new(),
+            signatures: HashMap::new(),
+            asts: HashMap::new(),
+            file_names: HashMap::new(),
+            sources: SourceMap::new(),
+            knwon_values: KnownValues::empty(),
+            mod_id_keys: RefCell::new(HashMap::new()),
+            overflow_checks: false,
+            trap_handler: None,
+            panic_handler: None,
+            runtime: None,
+            no_runtime: false,
+            validate: false,
+            memory_initial: None,
+            memory_max: None,
+            shared_memory: false,
+            metadata: None,
+            fuel: None,
+            inline_threshold: 0,
+            optimize: false,
+            opt_size: false,
+            gc: mir::GcStrategy::None,
+            custom_sections: Vec::new(),
+            post_process: None,
+            cfg: hir::Cfg::new(),
+            target: Target::default(),
+            features: ast::Features::new(),
+            js_bindings: false,
+            tests: None,
+            snapshot: None,
+            timings: None,
+        }
+    }
+
+    /// Toggle overflow checks, default to `false`.
+    ///
+    /// When enabled, signed integer overflows trap instead of wrapping around.
+    pub fn set_overflow_checks(&mut self, overflow_checks: bool) {
+        self.overflow_checks = overflow_checks;
+    }
+
+    /// Toggle the validation of the generated WebAssembly, default to `false`.
+    ///
+    /// When enabled, an invalid module is reported as an internal error.
+    pub fn set_validate(&mut self, validate: bool) {
+        self.validate = validate;
+    }
+
+    /// Set the initial number of memory pages, overriding the memory declared by runtime modules.
+    pub fn set_memory_initial(&mut self, pages: Option<u32>) {
+        self.memory_initial = pages;
+    }
+
+    /// Set the maximum number of memory pages, overriding the memory declared by runtime modules.
+    pub fn set_memory_max(&mut self, pages: Option<u32>) {
+        self.memory_max = pages;
+    }
+
+    /// Toggle shared memory, default to `false` unless declared by a runtime module.
+    ///
+    /// A shared memory requires a maximum number of pages.
+    pub fn set_shared_memory(&mut self, shared: bool) {
+        self.shared_memory = shared;
+    }
+
+    /// Embed build metadata into the generated WebAssembly, default to `None`.
+    ///
+    /// When set, the compiler version, the name of the package and a hash of the module are
+    /// stored in custom sections.
+    pub fn set_metadata(&mut self, package: Option<String>) {
+        self.metadata = package;
+    }
+
+    /// Add a custom section to the generated WebAssembly, sections are emitted in order.
+    pub fn add_custom_section(&mut self, name: String, content: Vec<u8>) {
+        self.custom_sections.push((name, content));
+    }
+
+    /// Set the target the package is compiled for, default to `wasm32-wasi`.
+    pub fn set_target(&mut self, target: Target) {
+        self.target = target;
+    }
+
+    /// Add a `key = "value"` pair to the configuration, declarations marked with
+    /// `#[cfg(key = "value")]` are only compiled if the pair is set.
+    pub fn add_cfg(&mut self, key: String, value: String) {
+        self.cfg.insert((key, value));
+    }
+
+    /// Enable an experimental feature of the language, using a feature which is not enabled is an
+    /// error.
+    pub fn enable_feature(&mut self, feature: ast::Feature) {
+        self.features.insert(feature);
+    }
+
+    /// Returns true if the experimental feature is enabled.
+    pub fn has_feature(&self, feature: ast::Feature) -> bool {
+        self.features.contains(&feature)
+    }
+
+    /// Pass the generated WebAssembly through an external transformation, such as an optimizer,
+    /// default to `None`.
+    ///
+    /// The transformation runs before custom sections are added, its result is always validated
+    /// and an invalid module is reported as an error. It returns an error message on failure.
+    pub fn set_post_process(&mut self, post_process: Option<PostProcess>) {
+        self.post_process = post_process;
+    }
+
+    /// Prepare the generated WebAssembly for JavaScript bindings, default to `false`.
+    ///
+    /// When enabled, the memory allocator is exported so that bindings can pass strings to the
+    /// module (see `get_js_bindings`).
+    pub fn set_js_bindings(&mut self, js_bindings: bool) {
+        self.js_bindings = js_bindings;
+    }
+
+    /// Expose the functions of `package` marked with `#[test]`, default to `None`.
+    ///
+    /// Test functions are exposed under their path prefixed by `TEST_PREFIX`, for instance
+    /// `test:math.sum_of_squares` for the function `sum_of_squares` of the `math` module.
+    pub fn set_tests(&mut self, package: Option<String>) {
+        self.tests = package;
+    }
+
+    /// Record the AST and HIR of `module` as it is added, and the MIR and WebAssembly of the
+    /// program as it is compiled to WebAssembly, default to `None`.
+    ///
+    /// The recorded representations are retrieved with `take_snapshot`, this is used by snapshot
+    /// tests.
+    pub fn set_snapshot(&mut self, module: Option<ModulePath>) {
+        self.snapshot = module.map(|module| (module, Snapshot::default()));
+    }
+
+    /// Returns the representations recorded since `set_snapshot` was called, if any.
+    pub fn take_snapshot(&mut self) -> Option<Snapshot> {
+        self.snapshot.take().map(|(_, snapshot)| snapshot)
+    }
+
+    /// Record the duration of the compilation phases and statistics about the modules as they
+    /// are added, default to `false`.
+    ///
+    /// The recorded timings are retrieved with `take_timings`.
+    pub fn set_timings(&mut self, timings: bool) {
+        self.timings = if timings { Some(Timings::new()) } else { None };
+    }
+
+    /// Returns the timings recorded since `set_timings` was called, if any.
+    pub fn take_timings(&mut self) -> Option<Timings> {
+        self.timings.take()
+    }
+
+    /// Adds the time elapsed since `start` to the duration of `phase`, if timings are recorded.
+    pub(crate) fn record_phase(&self, phase: Phase, start: Instant) {
+        if let Some(timings) = &self.timings {
+            timings.record(phase, start);
         }
     }
 
-    /// Toggle verbose mode, default to `false`.
-    pub fn set_verbose(&mut self, verbose: bool) {
-        self.verbose = verbose;
+    /// Records a pass over the MIR which started at `start`, if timings are recorded.
+    pub(crate) fn record_pass(&self, pass: &'static str, function: Option<&str>, start: Instant) {
+        if let Some(timings) = &self.timings {
+            timings.record_pass(pass, function, start);
+        }
+    }
+
+    /// Limit the number of MIR statements executed by the interpreter, default to `None`.
+    ///
+    /// When the limit is reached the execution is interrupted and reported as a runtime error.
+    pub fn set_fuel(&mut self, fuel: Option<u64>) {
+        self.fuel = fuel;
+    }
+
+    /// Inline functions of at most `threshold` MIR statements into their callers, default to `0`.
+    ///
+    /// A threshold of `0` disables inlining.
+    pub fn set_inline_threshold(&mut self, threshold: usize) {
+        self.inline_threshold = threshold;
+    }
+
+    /// Toggle the optimization passes over the MIR, default to `false`.
+    pub fn set_optimize(&mut self, optimize: bool) {
+        self.optimize = optimize;
+    }
+
+    /// Toggle the passes reducing the size of the module, default to `false`.
+    pub fn set_opt_size(&mut self, opt_size: bool) {
+        self.opt_size = opt_size;
+    }
+
+    /// Set how the memory of heap objects is reclaimed, default to `GcStrategy::None`.
+    ///
+    /// With reference counting, structs passed by the host must be preceded by a header holding
+    /// their reference count (see `core.mem`), which the JavaScript bindings take care of.
+    pub fn set_gc(&mut self, gc: mir::GcStrategy) {
+        self.gc = gc;
+    }
+
+    /// Set the function called with an error code before trapping on integer divisions by zero.
+    ///
+    /// The function must be public, take a single `i32` and return nothing.
+    pub fn set_trap_handler(&mut self, module: ModulePath, fun: String) {
+        self.trap_handler = Some((module, fun));
+    }
+
+    /// Set the function called in place of the default panic handler of `core`, which prints the
+    /// message and exits through WASI, or traps on targets without WASI.
+    ///
+    /// The function must be public, take a single `Str` and return nothing.
+    pub fn set_panic_handler(&mut self, module: ModulePath, fun: String) {
+        self.panic_handler = Some((module, fun));
+    }
+
+    /// Set the name of the package replacing the default runtime, the resolver is expected to
+    /// resolve it as the `core` package.
+    ///
+    /// The declarations expected by the compiler are checked before they are used, and missing
+    /// ones are reported against the runtime.
+    pub fn set_runtime(&mut self, runtime: Option<String>) {
+        self.runtime = runtime;
+    }
+
+    /// Compile without any runtime, default to `false`.
+    ///
+    /// The module has no allocator and no implicit import or export, the memory is only exported
+    /// if requested. Strings, assertions, panics and heap allocations are reported as errors, as
+    /// are the options relying on the runtime.
+    pub fn set_no_runtime(&mut self, no_runtime: bool) {
+        self.no_runtime = no_runtime;
+    }
+
+    /// Returns true unless compiling without a runtime.
+    pub fn has_runtime(&self) -> bool {
+        !self.no_runtime
     }
 
     /// Get a structure from its ID.
@@ -90,6 +433,30 @@ impl Ctx {
         self.funs.get(&fun_id)
     }
 
+    /// Get a function of a module which is not public, if the module is resolved.
+    pub fn get_private_fun(&self, mod_id: ModId, ident: &str) -> Option<&hir::Function> {
+        self.funs.iter().find_map(|(fun_id, fun)| match fun {
+            hir::FunKind::Fun(fun)
+                if !fun.is_pub
+                    && fun.ident == ident
+                    && self.fun_mods.get(fun_id) == Some(&mod_id) =>
+            {
+                Some(fun)
+            }
+            _ => None,
+        })
+    }
+
+    /// Get the type and location of a function from its ID, this includes the functions of modules
+    /// which are declared but not yet resolved.
+    pub fn get_fun_signature(&self, fun_id: hir::FunId) -> Option<(&hir::FunctionType, Location)> {
+        match self.funs.get(&fun_id) {
+            Some(hir::FunKind::Fun(fun)) => Some((&fun.t, fun.loc)),
+            Some(hir::FunKind::Extern(fun)) => Some((&fun.t, fun.loc)),
+            None => self.signatures.get(&fun_id).map(|fun| (&fun.t, fun.loc)),
+        }
+    }
+
     /// Get module declarations from the module ID.
     pub fn get_mod_from_id(&self, mod_id: ModId) -> Option<&ModuleDeclarations> {
         let mod_path = self.mods.get(&mod_id)?;
@@ -114,6 +481,11 @@ impl Ctx {
         &self.funs
     }
 
+    /// Returns the init functions, modules are initialized after their dependencies.
+    pub fn hir_inits(&self) -> &Vec<hir::FunId> {
+        &self.inits
+    }
+
     pub fn hir_structs(&self) -> &StructMap {
         &self.structs
     }
@@ -126,10 +498,235 @@ impl Ctx {
         &self.imports
     }
 
+    /// Returns the name of a parsed file, if any.
+    pub fn get_file_name(&self, f_id: FileId) -> Option<&str> {
+        self.file_names.get(&f_id).map(|name| name.as_str())
+    }
+
+    /// Returns the code of the parsed files, error handlers need it to display diagnostics.
+    pub fn sources(&self) -> &SourceMap {
+        &self.sources
+    }
+
+    /// Returns the IDs of the functions expected by the compiler, if known.
+    pub fn hir_known_funs(&self) -> Option<&KnownFunctions> {
+        self.knwon_values.funs.as_ref()
+    }
+
+    /// Returns the IDs of the structs expected by the compiler, if known.
+    pub fn hir_known_structs(&self) -> Option<&KnownStructs> {
+        self.knwon_values.structs.as_ref()
+    }
+
+    /// Returns true if `s_id` is the `Str` struct of the runtime.
+    fn is_str(&self, s_id: hir::StructId) -> bool {
+        self.hir_known_structs()
+            .map_or(false, |structs| structs.str == s_id)
+    }
+
     pub fn hir_data(&self) -> &DataMap {
         &self.data
     }
 
+    /// Returns the innermost symbol (declaration or reference) containing the position `pos` of
+    /// the file `f_id`, if any.
+    pub fn get_symbol_at(&self, f_id: FileId, pos: u32) -> Option<&hir::Symbol> {
+        self.symbols
+            .iter()
+            .filter(|s| s.loc.f_id == f_id && s.loc.pos <= pos && pos <= s.loc.pos + s.loc.len)
+            .min_by_key(|s| s.loc.len)
+    }
+
+    /// Returns the innermost AST node containing the position `pos` of the file `f_id`, if any.
+    pub fn get_node_at(&self, f_id: FileId, pos: u32) -> Option<&hir::Node> {
+        let first = ast::NodeId { f_id, index: 0 };
+        let last = ast::NodeId {
+            f_id,
+            index: u32::MAX,
+        };
+        self.nodes
+            .range(first..=last)
+            .map(|(_, node)| node)
+            .filter(|n| n.loc.pos <= pos && pos <= n.loc.pos + n.loc.len)
+            .min_by_key(|n| n.loc.len)
+    }
+
+    /// Returns the node declaring an entity, if any.
+    pub fn get_declaration(&self, entity: &hir::Entity) -> Option<&hir::Node> {
+        self.nodes
+            .values()
+            .find(|n| n.is_declaration && &n.entity == entity)
+    }
+
+    /// Returns the nodes declaring or referring to an entity, ordered by location.
+    pub fn find_references(&self, entity: &hir::Entity) -> Vec<&hir::Node> {
+        let mut nodes = self
+            .nodes
+            .values()
+            .filter(|n| &n.entity == entity)
+            .collect::<Vec<_>>();
+        nodes.sort_by_key(|n| n.loc);
+        nodes
+    }
+
+    /// Returns the edits renaming an entity to `new_name`, that is the locations of its name with
+    /// the text to replace them with. The uses of an alias of the entity are left untouched.
+    ///
+    /// Reports an error if the entity can not be renamed, or if the new name collides with
+    /// another name in one of the scopes where the entity is declared or used.
+    pub fn rename(
+        &self,
+        entity: &hir::Entity,
+        new_name: &str,
+        err: &mut impl ErrorHandler,
+    ) -> Result<Vec<(Location, String)>, ()> {
+        let declaration = match (self.get_declaration(entity), entity) {
+            (Some(declaration), _) => declaration,
+            (None, hir::Entity::Module(_)) => {
+                let message = "Modules are named after their directory, they can not be renamed";
+                err.report_no_loc(String::from(message));
+                return Err(());
+            }
+            (None, _) => {
+                let message = "Only the declarations of a module can be renamed";
+                err.report_no_loc(String::from(message));
+                return Err(());
+            }
+        };
+        let old_name = match self.get_text(declaration.name_loc) {
+            Some(name) if ast::is_identifier(&name) => name,
+            _ => {
+                let message = String::from("Only named declarations can be renamed");
+                err.report(declaration.loc, message);
+                return Err(());
+            }
+        };
+        if !ast::is_identifier(new_name) {
+            let message = format!("'{}' is not a valid identifier", new_name);
+            err.report(declaration.name_loc, message);
+            return Err(());
+        }
+        if let hir::Entity::Function(fun_id) = entity {
+            match self.funs.get(fun_id) {
+                Some(hir::FunKind::Extern(_)) => {
+                    let message = format!("'{}' is imported, its name can not change", old_name);
+                    err.report(declaration.name_loc, message);
+                    return Err(());
+                }
+                Some(hir::FunKind::Fun(fun)) if fun.exposed.as_ref() == Some(&old_name) => {
+                    let message = format!(
+                        "'{}' is exposed under its name, expose it with an alias before renaming it",
+                        old_name
+                    );
+                    err.report(declaration.name_loc, message);
+                    return Err(());
+                }
+                _ => (),
+            }
+        }
+
+        let mut edits = Vec::new();
+        let mut collides = false;
+        for node in self.find_references(entity) {
+            if self.get_text(node.name_loc).as_ref() != Some(&old_name) {
+                continue; // An alias
+            }
+            if let Some(other) = self.find_collision(entity, node, new_name) {
+                let message = format!("'{}' can not be renamed to '{}'", old_name, new_name);
+                match self.get_declaration(&other) {
+                    Some(decl) => err.report_with_note(
+                        node.name_loc,
+                        message,
+                        decl.name_loc,
+                        format!("'{}' is already declared here", new_name),
+                    ),
+                    None => err.report(node.name_loc, message),
+                }
+                collides = true;
+                continue;
+            }
+            // The name of a field initialized with a variable of the same name, as in
+            // `Point { x }`, refers to both: the other one keeps its name.
+            let is_shared = self
+                .nodes
+                .values()
+                .any(|n| n.name_loc == node.name_loc && &n.entity != entity);
+            let replacement = match (is_shared, entity) {
+                (false, _) => new_name.to_owned(),
+                (true, hir::Entity::Field(_, _)) => format!("{}: {}", new_name, old_name),
+                (true, _) => format!("{}: {}", old_name, new_name),
+            };
+            edits.push((node.name_loc, replacement));
+        }
+        if collides {
+            return Err(());
+        }
+        Ok(edits)
+    }
+
+    /// Returns the entity named `name` visible from a node of `entity`, if any other than
+    /// `entity` itself. The fields of a struct are visible from all the nodes of its fields.
+    fn find_collision(
+        &self,
+        entity: &hir::Entity,
+        node: &hir::Node,
+        name: &str,
+    ) -> Option<hir::Entity> {
+        if let hir::Entity::Field(s_id, _) = entity {
+            let field = hir::Entity::Field(*s_id, name.to_owned());
+            return match self.get_declaration(&field) {
+                Some(_) if &field != entity => Some(field),
+                _ => None,
+            };
+        }
+        let mut scope_id = node.scope;
+        while let Some(id) = scope_id {
+            let scope = self.scopes.get(&id)?;
+            match scope.names.get(name) {
+                Some(other) if other != entity => return Some(other.clone()),
+                _ => scope_id = scope.parent,
+            }
+        }
+        None
+    }
+
+    /// Returns the code at a location.
+    fn get_text(&self, loc: Location) -> Option<String> {
+        let file = self.sources.get(loc.f_id)?;
+        let start = loc.pos as usize;
+        let end = start + loc.len as usize;
+        file.code().get(start..end).map(str::to_owned)
+    }
+
+    /// Returns a human readable representation of a type, using the names of structs.
+    pub fn display_type(&self, t: &hir::Type) -> String {
+        match t {
+            hir::Type::Scalar(scalar) => format!("{}", scalar),
+            hir::Type::Struct(s_id) => match self.get_struct(*s_id) {
+                Some(struc) => struc.ident.clone(),
+                None => format!("{}", t),
+            },
+            hir::Type::Tuple(tup_id) => match self.get_tuple(*tup_id) {
+                Some(tup) => format!("({})", self.display_types(&tup.types)),
+                None => format!("{}", t),
+            },
+            hir::Type::Fun(fun) => format!(
+                "fun ({}): {}",
+                self.display_types(&fun.params),
+                self.display_type(&fun.ret)
+            ),
+            hir::Type::Ref(t) => format!("&{}", self.display_type(t)),
+        }
+    }
+
+    fn display_types(&self, types: &[hir::Type]) -> String {
+        types
+            .iter()
+            .map(|t| self.display_type(t))
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+
     /// Given a list of files return the corresponding module.
     pub fn get_module_name(
         &mut self,
@@ -137,7 +734,8 @@ impl Ctx {
         err: &mut impl ErrorHandler,
     ) -> Result<String, ()> {
         let is_single_file = files.len() == 1;
-        let ast_programs = self.parse_files(files, err)?;
+        // The ASTs are only used to find the name, they can use the reserved module ID
+        let ast_programs = self.parse_files(files, ModId(0), err)?;
         let mut module: Option<String> = None;
         let mut other_modules = HashSet::new();
         for (ast_program, _, _) in ast_programs {
@@ -167,54 +765,875 @@ impl Ctx {
                     }
                 }
             }
-        }
-        if let Some(mod_name) = module {
-            Ok(mod_name)
-        } else {
-            err.report_no_loc(String::from("Could not find standard module"));
-            Err(())
-        }
+        }
+        if let Some(mod_name) = module {
+            Ok(mod_name)
+        } else {
+            err.report_no_loc(String::from("Could not find standard module"));
+            Err(())
+        }
+    }
+
+    /// Given a single file, return the kind of module it declares.
+    pub fn get_module_kind(
+        &self,
+        file: PreparedFile,
+        err: &mut impl ErrorHandler,
+    ) -> Result<ModuleKind, ()> {
+        let ast_programs = self.parse_files(vec![file], ModId(0), err)?;
+        match ast_programs.first() {
+            Some((ast_program, _, _)) => match ast_program.module.t {
+                ast::ModuleType::Standalone => Ok(ModuleKind::Standalone),
+                ast::ModuleType::Standard => Ok(ModuleKind::Standard),
+            },
+            None => Err(()),
+        }
+    }
+
+    /// Add a module to the context.
+    pub fn add_module(
+        &mut self,
+        module: ModulePath,
+        err: &mut impl ErrorHandler,
+        resolver: &impl Resolver,
+    ) -> Result<(), ()> {
+        self.initialize_known_values(err, resolver)?;
+        let hir = self.get_hir(&module, HashSet::new(), true, err, resolver)?;
+        self.extend_hir(hir, module);
+        self.order_inits(err)
+    }
+
+    /// Checks the HIR in the current compilation context without generating any code.
+    ///
+    /// The modules are type checked as they are added, this reports the remaining errors that
+    /// would otherwise only be found when lowering to MIR, such as an invalid handler or
+    /// conflicting memory declarations.
+    pub fn check(
+        &mut self,
+        err: &mut impl ErrorHandler,
+        resolver: &impl Resolver,
+    ) -> Result<(), ()> {
+        self.initialize_known_values(err, resolver)?;
+        self.get_lowering_options(err, resolver)?;
+        Ok(())
+    }
+
+    /// Returns a graph of a package in the DOT language.
+    ///
+    /// Only the modules of the package are expanded, the modules and functions of other packages
+    /// are included when used by the package. In the call graph, the entry points (exposed
+    /// functions, init blocks and tests) are drawn with a double border and the functions of the
+    /// package unreachable from them are grayed out.
+    pub fn get_graph(&self, kind: GraphKind, package: &str) -> String {
+        match kind {
+            GraphKind::Modules => self.get_module_graph(package),
+            GraphKind::Calls => self.get_call_graph(package),
+        }
+    }
+
+    /// Returns the metrics of the functions of a package, grouped by module.
+    ///
+    /// The HIR is lowered to MIR as done by `get_wasm`, the functions are then compiled to
+    /// estimate their size.
+    pub fn get_metrics(
+        &mut self,
+        package: &str,
+        err: &mut impl ErrorHandler,
+        resolver: &impl Resolver,
+    ) -> Result<Vec<ModuleMetrics>, ()> {
+        let mir = self.get_mir(err, resolver)?;
+        let mut statements = HashMap::new();
+        for fun in &mir.funs {
+            let mut count = 0;
+            fun.body.visit_statements(&mut |_| count += 1);
+            statements.insert(fun.fun_id, count);
+        }
+        let sizes: HashMap<_, _> = wasm::code_sizes(mir, err)?.into_iter().collect();
+        let mut modules = Vec::new();
+        for mod_id in self.get_package_mods(package) {
+            let mut funs: Vec<&hir::Function> = self
+                .funs
+                .iter()
+                .filter(|(fun_id, _)| self.fun_mods.get(fun_id) == Some(&mod_id))
+                .filter_map(|(_, fun)| match fun {
+                    hir::FunKind::Fun(fun) => Some(fun),
+                    hir::FunKind::Extern(_) => None,
+                })
+                .collect();
+            funs.sort_by_key(|fun| (fun.span.f_id, fun.span.pos));
+            let functions = funs
+                .into_iter()
+                .map(|fun| FunctionMetrics {
+                    name: fun.ident.clone(),
+                    lines: self.count_lines(fun.span),
+                    locals: fun.params.len() + fun.locals.len(),
+                    statements: statements.get(&fun.fun_id).copied(),
+                    wasm_size: sizes.get(&fun.fun_id).copied(),
+                })
+                .collect();
+            modules.push(ModuleMetrics {
+                module: self.display_mod(mod_id),
+                functions,
+            });
+        }
+        Ok(modules)
+    }
+
+    /// Generate WebAssembly from the HIR in the current compilation context.
+    pub fn get_wasm(
+        &mut self,
+        err: &mut impl ErrorHandler,
+        resolver: &impl Resolver,
+    ) -> Result<Vec<u8>, ()> {
+        let mir = self.get_mir(err, resolver)?;
+        if let Some((_, snapshot)) = &mut self.snapshot {
+            snapshot.mir = mir.to_string();
+        }
+        let start = Instant::now();
+        let mut module = wasm::to_wasm(mir, err, self.validate)?;
+        if let Some(post_process) = &mut self.post_process {
+            module = match post_process(module) {
+                Ok(module) => module,
+                Err(message) => {
+                    err.report_no_loc(format!("Post-processing failed: {}", message));
+                    return Err(());
+                }
+            };
+            if let Err(e) = wasm::validate(&module) {
+                let location = match e.fun_idx {
+                    Some(idx) => format!(" (function {})", idx),
+                    None => String::new(),
+                };
+                err.report_no_loc(format!(
+                    "Post-processing produced an invalid module{}: {}",
+                    location, e.message
+                ));
+                return Err(());
+            }
+        }
+        self.record_phase(Phase::Encode, start);
+        for (name, content) in &self.custom_sections {
+            module.extend(wasm::encode_custom_section(name, content));
+        }
+        if let Some(package) = &self.metadata {
+            let version = env!("CARGO_PKG_VERSION");
+            for (name, content) in &[
+                (wasm::COMPILER_SECTION, version),
+                (wasm::PACKAGE_SECTION, package),
+            ] {
+                module.extend(wasm::encode_custom_section(name, content.as_bytes()));
+            }
+            // The hash covers everything before its own section
+            let hash = format!("{:016x}", wasm::content_hash(&module));
+            let hash_section = wasm::encode_custom_section(wasm::HASH_SECTION, hash.as_bytes());
+            module.extend(hash_section);
+        }
+        if let Some((_, snapshot)) = &mut self.snapshot {
+            match wasm::to_wat(&module) {
+                Ok(wat) => snapshot.wat = wat,
+                Err(message) => {
+                    err.report_internal_no_loc(format!("Can not print the module: {}", message));
+                    return Err(());
+                }
+            }
+        }
+        Ok(module)
+    }
+
+    /// Lowers the HIR to MIR, starting from the exposed functions along with the tests and the
+    /// memory allocator when they are exposed.
+    fn get_mir(
+        &mut self,
+        err: &mut impl ErrorHandler,
+        resolver: &impl Resolver,
+    ) -> Result<mir::Program, ()> {
+        self.initialize_known_values(err, resolver)?;
+        let options = self.get_lowering_options(err, resolver)?;
+        let malloc = self.hir_known_funs().map(|known_funs| known_funs.malloc);
+        let mut roots = Vec::new();
+        if self.js_bindings {
+            roots.extend(malloc);
+        }
+        let tests = self.get_tests();
+        roots.extend(tests.iter().map(|(fun_id, _)| *fun_id));
+        let start = Instant::now();
+        let mut mir = mir::to_mir(&self, &roots, options, err)?;
+        self.record_phase(Phase::HirToMir, start);
+        if self.js_bindings {
+            for fun in &mut mir.funs {
+                if Some(fun.fun_id) == malloc && fun.exposed.is_none() {
+                    fun.exposed = Some(String::from(wasm::MALLOC_EXPORT));
+                }
+            }
+        }
+        for fun in &mut mir.funs {
+            if let Some((_, name)) = tests.iter().find(|(fun_id, _)| *fun_id == fun.fun_id) {
+                fun.exposed = Some(name.clone());
+            }
+        }
+        Ok(mir)
+    }
+
+    /// Returns the test functions to expose along with their exposed name, sorted by name.
+    fn get_tests(&self) -> Vec<(hir::FunId, String)> {
+        let mut tests = Vec::new();
+        let package = match &self.tests {
+            Some(package) => package,
+            None => return tests,
+        };
+        for fun in self.funs.values() {
+            let fun = match fun {
+                hir::FunKind::Fun(fun) if fun.is_test => fun,
+                _ => continue,
+            };
+            let module = self
+                .fun_mods
+                .get(&fun.fun_id)
+                .and_then(|mod_id| self.mods.get(mod_id));
+            if let Some(module) = module.filter(|module| &module.root == package) {
+                let name = format!("{}{}.{}", TEST_PREFIX, module, fun.ident);
+                tests.push((fun.fun_id, name));
+            }
+        }
+        tests.sort_by(|(_, a), (_, b)| a.cmp(b));
+        tests
+    }
+
+    /// Generate an ES module wrapping the exposed functions of the WebAssembly module produced by
+    /// `get_wasm`, which must have been called with JavaScript bindings enabled, along with its
+    /// TypeScript declarations.
+    ///
+    /// Strings, booleans and 64 bits integers are converted, functions taking or returning
+    /// tuples are passed through as is.
+    pub fn get_js_bindings(
+        &mut self,
+        err: &mut impl ErrorHandler,
+        resolver: &impl Resolver,
+    ) -> Result<(String, String), ()> {
+        self.initialize_known_values(err, resolver)?;
+        let memory = self
+            .get_exports(err)?
+            .memory
+            .unwrap_or_else(|| String::from("memory"));
+        let malloc = self.hir_known_funs().map(|known_funs| known_funs.malloc);
+        let malloc = match malloc.and_then(|malloc| self.funs.get(&malloc)) {
+            Some(hir::FunKind::Fun(fun)) => fun.exposed.clone(),
+            _ => None,
+        };
+        let malloc = malloc.unwrap_or_else(|| String::from(wasm::MALLOC_EXPORT));
+        let mut funs = Vec::new();
+        for fun_kind in self.funs.values() {
+            let fun = match fun_kind {
+                hir::FunKind::Fun(fun) => fun,
+                hir::FunKind::Extern(_) => continue,
+            };
+            let name = match &fun.exposed {
+                Some(name) => name.clone(),
+                None => continue,
+            };
+            let params = fun.t.params.iter().map(|t| self.get_js_type(t));
+            let params: Option<Vec<wasm::JsType>> = params.collect();
+            let signature = match (params, self.get_js_type(&fun.t.ret)) {
+                (Some(params), Some(ret)) => Some((params, ret)),
+                _ => {
+                    err.warn(
+                        fun.loc,
+                        format!(
+                            "Function '{}' uses tuples, which JavaScript bindings pass as is",
+                            name
+                        ),
+                    );
+                    None
+                }
+            };
+            funs.push(wasm::JsFunction { name, signature });
+        }
+        funs.sort_by(|a, b| a.name.cmp(&b.name));
+        let header = match self.gc {
+            mir::GcStrategy::None => 0,
+            mir::GcStrategy::ReferenceCounting => mir::RC_HEADER_SIZE,
+        };
+        let bindings = wasm::js_bindings(&funs, &memory, &malloc, header);
+        Ok((bindings, wasm::ts_declarations(&funs)))
+    }
+
+    /// Compiles the program to WebAssembly and returns the module along with the artifacts
+    /// derived from it, as described by `Artifacts`.
+    ///
+    /// JavaScript bindings are only generated if enabled with `set_js_bindings`.
+    pub fn compile(
+        &mut self,
+        package: &str,
+        err: &mut impl ErrorHandler,
+        resolver: &impl Resolver,
+    ) -> Result<Artifacts, ()> {
+        let wasm = self.get_wasm(err, resolver)?;
+        let wat = match wasm::to_wat(&wasm) {
+            Ok(wat) => wat,
+            Err(message) => {
+                err.report_internal_no_loc(format!("Can not print the module: {}", message));
+                return Err(());
+            }
+        };
+        let js_bindings = if self.js_bindings {
+            Some(self.get_js_bindings(err, resolver)?)
+        } else {
+            None
+        };
+        let mut exports: Vec<String> = self
+            .funs
+            .values()
+            .filter_map(|fun| match fun {
+                hir::FunKind::Fun(fun) => fun.exposed.clone(),
+                hir::FunKind::Extern(_) => None,
+            })
+            .collect();
+        exports.sort();
+        let metadata = BuildMetadata {
+            compiler: String::from(env!("CARGO_PKG_VERSION")),
+            package: String::from(package),
+            target: self.target,
+            hash: format!("{:016x}", wasm::content_hash(&wasm)),
+            exports,
+        };
+        Ok(Artifacts {
+            wasm,
+            wat,
+            js_bindings,
+            metadata,
+        })
+    }
+
+    /// Returns the JavaScript representation of a type, if it has one.
+    fn get_js_type(&self, t: &hir::Type) -> Option<wasm::JsType> {
+        let js_type = match t {
+            hir::Type::Scalar(hir::ScalarType::I64) => wasm::JsType::BigInt,
+            hir::Type::Scalar(hir::ScalarType::Bool) => wasm::JsType::Boolean,
+            hir::Type::Scalar(hir::ScalarType::Null) => wasm::JsType::Void,
+            hir::Type::Scalar(_) | hir::Type::Fun(_) => wasm::JsType::Number,
+            hir::Type::Struct(s_id) if self.is_str(*s_id) => wasm::JsType::Str,
+            // Structs and references are passed as addresses
+            hir::Type::Struct(_) | hir::Type::Ref(_) => wasm::JsType::Number,
+            hir::Type::Tuple(_) => return None,
+        };
+        Some(js_type)
+    }
+
+    /// Describes the imports and exposed functions of the program in the WIT format of the
+    /// component model, as a world named after `module`.
+    ///
+    /// Functions whose names or types can not be described are omitted with a warning.
+    pub fn get_wit(
+        &mut self,
+        module: &str,
+        err: &mut impl ErrorHandler,
+        resolver: &impl Resolver,
+    ) -> Result<String, ()> {
+        self.initialize_known_values(err, resolver)?;
+        let world = self.get_wit_world(module, false, err)?;
+        Ok(world.to_string())
+    }
+
+    /// Wraps the program into a component of the component model, exposing the world described
+    /// by `get_wit`. Imported functions can only take and return scalars.
+    pub fn get_component(
+        &mut self,
+        module: &str,
+        err: &mut impl ErrorHandler,
+        resolver: &impl Resolver,
+    ) -> Result<Vec<u8>, ()> {
+        if self.no_runtime {
+            err.report_no_loc(String::from(
+                "Components can not be built without a runtime, the canonical ABI relies on its \
+                 allocator",
+            ));
+            return Err(());
+        }
+        self.initialize_known_values(err, resolver)?;
+        let world = self.get_wit_world(module, true, err)?;
+        let mut options = self.get_lowering_options(err, resolver)?;
+        options.component = true;
+        let memory = options
+            .exports
+            .memory
+            .clone()
+            .unwrap_or_else(|| String::from("memory"));
+        let roots = match self.hir_known_funs() {
+            Some(known_funs) => vec![known_funs.malloc],
+            None => Vec::new(),
+        };
+        let start = Instant::now();
+        let mir = mir::to_mir(self, &roots, options, err)?;
+        self.record_phase(Phase::HirToMir, start);
+        let start = Instant::now();
+        let core = wasm::to_wasm(mir, err, self.validate)?;
+        self.record_phase(Phase::Encode, start);
+        match wasm::to_component(&core, &world, &memory) {
+            Ok(component) => Ok(component),
+            Err(message) => {
+                err.report_no_loc(message);
+                Err(())
+            }
+        }
+    }
+
+    /// Returns the WIT world of the program. Items which can not be described are omitted with a
+    /// warning, or reported as errors if `strict` is set.
+    fn get_wit_world(
+        &self,
+        module: &str,
+        strict: bool,
+        err: &mut impl ErrorHandler,
+    ) -> Result<wasm::WitWorld, ()> {
+        let name = match wasm::wit_name(module) {
+            Some(name) => name,
+            None => {
+                err.report_no_loc(format!("Module '{}' has no valid WIT name", module));
+                return Err(());
+            }
+        };
+        let mut has_error = false;
+        let mut omit = |loc, name: &str| {
+            if strict {
+                has_error = true;
+                err.report(loc, format!("'{}' can not be passed to a component", name));
+            } else {
+                err.warn(loc, wit_omitted(name));
+            }
+        };
+        let mut records = Vec::new();
+        let mut imports = Vec::new();
+        for import in &self.imports {
+            let mut funs = Vec::new();
+            for fun_id in &import.prototypes {
+                if let Some(hir::FunKind::Extern(proto)) = self.funs.get(fun_id) {
+                    let fun = self.get_wit_function(&proto.ident, &proto.t, &mut records);
+                    match fun {
+                        Some(fun) => funs.push(fun),
+                        None => omit(proto.loc, &proto.ident),
+                    }
+                }
+            }
+            let module = match wasm::wit_name(&import.from) {
+                Some(module) => module,
+                None => {
+                    omit(import.loc, &import.from);
+                    continue;
+                }
+            };
+            match imports
+                .iter_mut()
+                .find(|i: &&mut wasm::WitImport| i.module == module)
+            {
+                Some(wit_import) => wit_import.funs.extend(funs),
+                None => imports.push(wasm::WitImport {
+                    module,
+                    core_module: import.from.clone(),
+                    funs,
+                }),
+            }
+        }
+        let mut exposed = self
+            .funs
+            .values()
+            .filter_map(|fun_kind| match fun_kind {
+                hir::FunKind::Fun(fun) => fun.exposed.as_ref().map(|name| (name, fun)),
+                hir::FunKind::Extern(_) => None,
+            })
+            .collect::<Vec<_>>();
+        exposed.sort_by_key(|(name, _)| *name);
+        let mut exports = Vec::new();
+        for (name, fun) in exposed {
+            match self.get_wit_function(name, &fun.t, &mut records) {
+                Some(fun) => exports.push(fun),
+                None => omit(fun.loc, name),
+            }
+        }
+        if has_error {
+            return Err(());
+        }
+        let records = records
+            .into_iter()
+            .filter_map(|(_, record)| record)
+            .collect();
+        Ok(wasm::WitWorld {
+            name,
+            records,
+            imports,
+            exports,
+        })
+    }
+
+    /// Returns the WIT description of a function, if it has one.
+    fn get_wit_function(
+        &self,
+        name: &str,
+        t: &hir::FunctionType,
+        records: &mut Vec<(hir::StructId, Option<wasm::WitRecord>)>,
+    ) -> Option<wasm::WitFunction> {
+        let mut params = Vec::with_capacity(t.params.len());
+        for param in &t.params {
+            params.push(self.get_wit_type(param, records)?);
+        }
+        let ret = match *t.ret {
+            hir::Type::Scalar(hir::ScalarType::Null) => None,
+            ref ret => Some(self.get_wit_type(ret, records)?),
+        };
+        Some(wasm::WitFunction {
+            name: wasm::wit_name(name)?,
+            core_name: String::from(name),
+            params,
+            ret,
+        })
+    }
+
+    /// Returns the WIT equivalent of a type, if any, and collects the records it uses.
+    ///
+    /// Records are registered before their fields are converted, recursive structures have no
+    /// WIT equivalent.
+    fn get_wit_type(
+        &self,
+        t: &hir::Type,
+        records: &mut Vec<(hir::StructId, Option<wasm::WitRecord>)>,
+    ) -> Option<wasm::WitType> {
+        let wit_type = match t {
+            hir::Type::Scalar(hir::ScalarType::I32) => wasm::WitType::S32,
+            hir::Type::Scalar(hir::ScalarType::I64) => wasm::WitType::S64,
+            hir::Type::Scalar(hir::ScalarType::F32) => wasm::WitType::F32,
+            hir::Type::Scalar(hir::ScalarType::F64) => wasm::WitType::F64,
+            hir::Type::Scalar(hir::ScalarType::Bool) => wasm::WitType::Bool,
+            hir::Type::Scalar(hir::ScalarType::Null | hir::ScalarType::V128)
+            | hir::Type::Ref(_) => return None,
+            // Functions are passed as indices into the table
+            hir::Type::Fun(_) => wasm::WitType::S32,
+            hir::Type::Tuple(tup_id) => {
+                let tuple = self.tuples.get(tup_id)?;
+                let mut types = Vec::with_capacity(tuple.types.len());
+                for t in &tuple.types {
+                    types.push(self.get_wit_type(t, records)?);
+                }
+                wasm::WitType::Tuple(types)
+            }
+            hir::Type::Struct(s_id) if self.is_str(*s_id) => wasm::WitType::String,
+            hir::Type::Struct(s_id) => {
+                if let Some((_, record)) = records.iter().find(|(id, _)| id == s_id) {
+                    let record = record.as_ref()?;
+                    return Some(wasm::WitType::Record(record.name.clone()));
+                }
+                let s = self.structs.get(s_id)?;
+                let name = wasm::wit_name(&s.ident)?;
+                let is_taken = |(_, r): &(_, Option<wasm::WitRecord>)| match r {
+                    Some(r) => r.name == name,
+                    None => false,
+                };
+                if records.iter().any(is_taken) {
+                    return None;
+                }
+                let idx = records.len();
+                records.push((*s_id, None));
+                let mut fields = s.fields.iter().collect::<Vec<_>>();
+                fields.sort_by_key(|(field, _)| *field);
+                let mut wit_fields = Vec::with_capacity(fields.len());
+                for (field, t) in fields {
+                    let t = self.get_wit_type(&t.t, records);
+                    match (wasm::wit_name(field), t) {
+                        (Some(field), Some(t)) => wit_fields.push((field, t)),
+                        _ => {
+                            records.truncate(idx);
+                            return None;
+                        }
+                    }
+                }
+                records[idx].1 = Some(wasm::WitRecord {
+                    name: name.clone(),
+                    fields: wit_fields,
+                });
+                wasm::WitType::Record(name)
+            }
+        };
+        Some(wit_type)
     }
 
-    /// Add a module to the context.
-    pub fn add_module(
+    /// Compiles the program to a native object file for the host.
+    #[cfg(feature = "cranelift")]
+    pub fn get_object(
         &mut self,
-        module: ModulePath,
         err: &mut impl ErrorHandler,
         resolver: &impl Resolver,
-    ) -> Result<(), ()> {
+    ) -> Result<Vec<u8>, ()> {
         self.initialize_known_values(err, resolver)?;
-        let hir = self.get_hir(&module, HashSet::new(), err, resolver)?;
-        self.extend_hir(hir, module);
-        Ok(())
+        let options = self.get_lowering_options(err, resolver)?;
+        let start = Instant::now();
+        let mir = mir::to_mir(&self, &[], options, err)?;
+        self.record_phase(Phase::HirToMir, start);
+        let start = Instant::now();
+        let object = native::to_native(mir, err);
+        self.record_phase(Phase::Encode, start);
+        object
     }
 
-    /// Generate WebAssembly from the HIR in the current compilation context.
-    pub fn get_wasm(
+    /// Runs a public function of a module with the MIR interpreter and returns its results.
+    ///
+    /// The function must not take any argument.
+    pub fn interpret(
         &mut self,
+        module: &ModulePath,
+        fun: &str,
         err: &mut impl ErrorHandler,
         resolver: &impl Resolver,
-    ) -> Result<Vec<u8>, ()> {
+    ) -> Result<Vec<mir::RuntimeValue>, ()> {
         self.initialize_known_values(err, resolver)?;
-        let known_funs = self.get_known_functions(err, resolver)?;
-        let mir = mir::to_mir(&self, &known_funs, err, self.verbose);
-        Ok(wasm::to_wasm(mir, err, self.verbose))
+        let public_decls = self.get_public_decls(module, err, resolver)?.clone();
+        let fun_id = match self.get_fun_from_decls(&public_decls, fun, module, err)? {
+            hir::FunKind::Fun(fun) if fun.params.is_empty() => fun.fun_id,
+            _ => {
+                err.report_no_loc(format!(
+                    "Function '{}' at '{}' can not be interpreted",
+                    fun, module
+                ));
+                return Err(());
+            }
+        };
+        let options = self.get_lowering_options(err, resolver)?;
+        let mir = mir::to_mir(self, &[fun_id], options, err)?;
+        mir::interpret(&mir, fun_id, Vec::new(), self.fuel, err)
+    }
+
+    /// Return the options used to lower HIR to MIR, resolving the trap and panic handlers if any.
+    fn get_lowering_options(
+        &mut self,
+        err: &mut impl ErrorHandler,
+        resolver: &impl Resolver,
+    ) -> Result<mir::LoweringOptions, ()> {
+        if self.no_runtime {
+            let mut unsupported = Vec::new();
+            if self.overflow_checks {
+                unsupported.push("Overflow checks");
+            }
+            if self.gc != mir::GcStrategy::None {
+                unsupported.push("Reference counting");
+            }
+            if self.panic_handler.is_some() {
+                unsupported.push("A panic handler");
+            }
+            if self.js_bindings {
+                unsupported.push("JavaScript bindings");
+            }
+            for what in &unsupported {
+                err.report_no_loc(format!("{} can not be used without a runtime", what));
+            }
+            if !unsupported.is_empty() {
+                return Err(());
+            }
+        }
+        let trap_handler = if let Some(handler) = self.trap_handler.clone() {
+            let param = hir::TYPE_I32;
+            Some(self.get_handler("Trap", &handler, param, "'i32'", err, resolver)?)
+        } else {
+            None
+        };
+        let str_t = self
+            .hir_known_structs()
+            .map(|structs| hir::Type::Struct(structs.str));
+        let panic_handler = match (self.panic_handler.clone(), str_t) {
+            (Some(handler), Some(param)) => {
+                Some(self.get_handler("Panic", &handler, param, "'Str'", err, resolver)?)
+            }
+            _ => None,
+        };
+        Ok(mir::LoweringOptions {
+            overflow_checks: self.overflow_checks,
+            trap_handler,
+            panic_handler,
+            memory: self.get_memory_limits(err)?,
+            exports: self.get_exports(err)?,
+            inline_threshold: self.inline_threshold,
+            optimize: self.optimize,
+            opt_size: self.opt_size,
+            component: false,
+            gc: self.gc,
+            target: self.target,
+            entry: self.get_entry_point(err)?,
+            runtime: !self.no_runtime,
+        })
+    }
+
+    /// Returns the function marked `#[entry]`, if any, there can be only one and it must not
+    /// clash with a function exposed under the entry point name of the target.
+    fn get_entry_point(&self, err: &mut impl ErrorHandler) -> Result<Option<hir::FunId>, ()> {
+        let entry_name = self.target.entry_point();
+        let mut entries = Vec::new();
+        let mut exposed = None;
+        for fun in self.funs.values() {
+            if let hir::FunKind::Fun(fun) = fun {
+                if fun.is_entry {
+                    entries.push(fun);
+                } else if entry_name.is_some() && fun.exposed.as_deref() == entry_name {
+                    exposed = Some(fun);
+                }
+            }
+        }
+        entries.sort_by_key(|fun| fun.fun_id);
+        let entry = match entries.first() {
+            Some(entry) => *entry,
+            None => return Ok(None),
+        };
+        for other in &entries[1..] {
+            err.report_with_note(
+                other.loc,
+                format!(
+                    "'{}' is marked '#[entry]' but there can be only one entry point",
+                    other.ident
+                ),
+                entry.loc,
+                format!("'{}' is the entry point", entry.ident),
+            );
+        }
+        if let Some(fun) = exposed {
+            err.report_with_note(
+                fun.loc,
+                format!(
+                    "'{}' is exposed as '{}', which is the name of the entry point",
+                    fun.ident,
+                    entry_name.unwrap_or_default()
+                ),
+                entry.loc,
+                format!("'{}' is the entry point", entry.ident),
+            );
+        }
+        if entries.len() > 1 || exposed.is_some() {
+            return Err(());
+        }
+        Ok(Some(entry.fun_id))
+    }
+
+    /// Return the ID of a handler, that is a public function taking a single parameter of type
+    /// `param` and returning nothing.
+    fn get_handler(
+        &mut self,
+        kind: &str,
+        (module, fun): &(ModulePath, String),
+        param: hir::Type,
+        param_name: &str,
+        err: &mut impl ErrorHandler,
+        resolver: &impl Resolver,
+    ) -> Result<hir::FunId, ()> {
+        let public_decls = self.get_public_decls(module, err, resolver)?.clone();
+        let (fun_id, t) = match self.get_fun_from_decls(&public_decls, fun, module, err)? {
+            hir::FunKind::Fun(fun) => (fun.fun_id, &fun.t),
+            hir::FunKind::Extern(fun) => (fun.fun_id, &fun.t),
+        };
+        let null = hir::Type::Scalar(hir::ScalarType::Null);
+        if t.params != [param] || *t.ret != null {
+            err.report_no_loc(format!(
+                "{} handler '{}' at '{}' must take an {} and return nothing",
+                kind, fun, module, param_name
+            ));
+            return Err(());
+        }
+        Ok(fun_id)
+    }
+
+    /// Returns the limits of the memory declared by runtime modules, if any, with the limits
+    /// passed as options taking precedence.
+    fn get_memory_limits(&self, err: &mut impl ErrorHandler) -> Result<mir::MemoryLimits, ()> {
+        let mut limits = mir::MemoryLimits {
+            initial: self.target.default_memory_pages(),
+            ..mir::MemoryLimits::default()
+        };
+        if let Some(memory) = self.memories.first() {
+            if self.memories.len() > 1 {
+                err.report_no_loc(String::from(
+                    "Memory is declared by more than one runtime module",
+                ));
+                return Err(());
+            }
+            limits = mir::MemoryLimits {
+                initial: memory.initial,
+                max: memory.max,
+                shared: memory.shared,
+            };
+        }
+        if let Some(initial) = self.memory_initial {
+            limits.initial = initial;
+        }
+        if let Some(max) = self.memory_max {
+            limits.max = Some(max);
+        }
+        limits.shared |= self.shared_memory;
+        if let Err(e) = limits.check() {
+            err.report_no_loc(e);
+            return Err(());
+        }
+        Ok(limits)
+    }
+
+    /// Returns the export names of the memory and the table, which can be set by any module as
+    /// long as they agree. The memory is exported as `memory` by default, unless compiling without
+    /// a runtime.
+    fn get_exports(&self, err: &mut impl ErrorHandler) -> Result<hir::ModuleExports, ()> {
+        let mut exports = hir::ModuleExports::default();
+        for module_exports in &self.exports {
+            for (export, name, item) in [
+                (&mut exports.memory, &module_exports.memory, "Memory"),
+                (&mut exports.table, &module_exports.table, "Table"),
+            ] {
+                match (&export, name) {
+                    (Some(previous), Some(name)) if previous != name => {
+                        err.report_no_loc(format!(
+                            "{} is exported as both '{}' and '{}'",
+                            item, previous, name
+                        ));
+                        return Err(());
+                    }
+                    (None, Some(name)) => *export = Some(name.clone()),
+                    _ => (),
+                }
+            }
+        }
+        if exports.memory.is_none() && !self.no_runtime {
+            exports.memory = Some(String::from("memory"));
+        }
+        Ok(exports)
     }
 
     /// Parses a module and return its AST (abstract syntax tree).
     fn get_ast(
-        &self,
+        &mut self,
         module: &ModulePath,
         err: &mut impl ErrorHandler,
         resolver: &impl Resolver,
     ) -> Result<ast::Program, ()> {
+        let start = Instant::now();
         let (files, module_kind) = resolver.resolve_module(module, err)?;
-        let ast_programs = self.parse_files(files, err)?;
+        self.record_phase(Phase::Resolve, start);
+        if let Some(timings) = &mut self.timings {
+            timings.modules.push(ModuleStats {
+                module: module.to_string(),
+                files: files.len(),
+                lines: files.iter().map(|file| file.code.lines().count()).sum(),
+                functions: None,
+            });
+        }
+        for file in &files {
+            let extension = match file.kind {
+                FileKind::Zephyr => "zph",
+                FileKind::Asm => "zasm",
+            };
+            let file_name = format!("{}.{}", file.file_name, extension);
+            self.file_names.insert(file.f_id, file_name);
+        }
+        let mod_id = self.stable_mod_id(&module.to_string());
+        let ast_programs = self.parse_files(files, mod_id, err)?;
         let mut package: Option<ast::Program> = None;
         let mut package_definition: Option<ast::Module> = None;
 
         // Iterate over ast_program of all zephyr files in the folder
-        for (ast, mut err_handler, file_name) in ast_programs {
+        for (mut ast, mut err_handler, file_name) in ast_programs {
+            if !hir::filter_declarations(&mut ast, &self.cfg, &mut err_handler) {
+                err.merge(err_handler);
+                continue;
+            }
             match ast.module.t {
                 ast::ModuleType::Standard => {
                     if module_kind == ModuleKind::Standalone {
@@ -270,56 +1689,217 @@ impl Ctx {
     /// params:
     ///  - module: the path of the module of interest.
     ///  - imported: a set of already imported modules.
+    ///  - lint: whether to report lints, only modules of the same package are linted.
     ///  - err: and error handler.
     ///  - resolver: a path resolver.
     fn get_hir(
         &mut self,
         module: &ModulePath,
         imported: HashSet<ModulePath>,
+        lint: bool,
         err: &mut impl ErrorHandler,
         resolver: &impl Resolver,
     ) -> Result<hir::Program, ()> {
         // Get AST
-        let mut pkg_ast = self.get_ast(module, err, resolver)?;
+        let mut pkg_ast = if self.declared.contains(module) {
+            match self.asts.remove(module) {
+                Some(ast) => ast,
+                None => {
+                    err.report_internal_no_loc(format!("Module '{}' is not declared", module));
+                    return Err(());
+                }
+            }
+        } else {
+            let ast = match self.asts.remove(module) {
+                Some(ast) => ast,
+                None => self.get_ast(module, err, resolver)?,
+            };
+            self.declare_import_cycle(module, ast, &imported, lint, err, resolver)?
+        };
+        let is_snapshot = matches!(&self.snapshot, Some((path, _)) if path == module);
+        if is_snapshot {
+            if let Some((_, snapshot)) = &mut self.snapshot {
+                snapshot.ast = pkg_ast.to_string();
+            }
+        }
         // Prepare HIR
         let mut namespaces = HashMap::new();
         let mut package_import = HashSet::new();
+        let mut deps = Vec::new();
         // Collect dependencies
         for used in pkg_ast.used.iter_mut() {
-            self.detect_circular_imports(&used.path, &imported, err)?;
-            self.detect_multiple_imports(&used.path, &package_import, err);
-            package_import.insert(used.path.clone());
+            if !self.declared.contains(&used.path) {
+                self.detect_circular_imports(&used.path, &imported, err)?;
+            }
+            if used.items.is_none() {
+                // Declarations can be imported from the same module by several statements.
+                self.detect_multiple_imports(&used.path, &package_import, err);
+                package_import.insert(used.path.clone());
+            }
             // Collect dependencies
             // TODO: Should we hide exposed declarations of imported packages?
-            let mod_id = if let Some(pub_decls) = self.public_decls.get(&used.path) {
-                // Already processed and cached.
+            let mod_id = if let Some(pub_decls) = self
+                .public_decls
+                .get(&used.path)
+                .filter(|_| !self.asts.contains_key(&used.path))
+            {
+                // Already processed and cached, or declared and being resolved.
                 pub_decls.mod_id
             } else {
                 let mut imported = imported.clone();
                 imported.insert(used.path.clone());
-                let module_hir = self.get_hir(&used.path, imported, err, resolver)?;
+                let lint = lint && used.path.root == module.root;
+                let module_hir = self.get_hir(&used.path, imported, lint, err, resolver)?;
                 // Merge package content
                 let mod_id = module_hir.module.id;
                 self.extend_hir(module_hir, used.path.clone());
                 mod_id
             };
-            if let Some(alias) = &used.alias {
-                namespaces.insert(alias.clone(), mod_id);
-            } else {
-                namespaces.insert(used.path.alias().to_owned(), mod_id);
+            if used.items.is_none() {
+                namespaces.insert(namespace_ident(used), mod_id);
+            }
+            if !deps.contains(&mod_id) {
+                deps.push(mod_id);
+            }
+        }
+        self.mod_deps.insert(pkg_ast.module.id, deps);
+        let hir_program = hir::to_hir(pkg_ast, namespaces, &self, &self.knwon_values, err, lint)?;
+        if is_snapshot {
+            if let Some((_, snapshot)) = &mut self.snapshot {
+                snapshot.hir = hir_program.to_string();
+            }
+        }
+        if let Some(timings) = &mut self.timings {
+            let name = module.to_string();
+            if let Some(stats) = timings
+                .modules
+                .iter_mut()
+                .find(|stats| stats.module == name)
+            {
+                stats.functions = Some(hir_program.funs.len());
             }
         }
-        let hir_program = hir::to_hir(
-            pkg_ast,
-            namespaces,
-            &self,
-            &self.knwon_values,
-            err,
-            self.verbose,
-        );
         Ok(hir_program)
     }
 
+    /// Declares the modules of the import cycle `module` belongs to, if any, and returns the AST
+    /// of `module`.
+    ///
+    /// Modules of the same package can import each others: the structs and function signatures
+    /// of all the modules of the cycle are collected before any function body is resolved.
+    ///
+    /// params:
+    ///  - module: the path of the module of interest.
+    ///  - ast: the AST of the module.
+    ///  - imported: a set of already imported modules.
+    ///  - lint: whether to report lints.
+    ///  - err: and error handler.
+    ///  - resolver: a path resolver.
+    fn declare_import_cycle(
+        &mut self,
+        module: &ModulePath,
+        ast: ast::Program,
+        imported: &HashSet<ModulePath>,
+        lint: bool,
+        err: &mut impl ErrorHandler,
+        resolver: &impl Resolver,
+    ) -> Result<ast::Program, ()> {
+        // Collect the unprocessed modules of the package reachable from `module`
+        self.asts.insert(module.clone(), ast);
+        let mut deps: HashMap<ModulePath, Vec<ModulePath>> = HashMap::new();
+        let mut order = Vec::new();
+        let mut to_visit = vec![module.clone()];
+        while let Some(path) = to_visit.pop() {
+            if deps.contains_key(&path) {
+                continue;
+            }
+            if !self.asts.contains_key(&path) {
+                let ast = self.get_ast(&path, err, resolver)?;
+                self.asts.insert(path.clone(), ast);
+            }
+            let used: Vec<ModulePath> = self.asts[&path]
+                .used
+                .iter()
+                .map(|used| used.path.clone())
+                .filter(|used| used.root == module.root && !self.public_decls.contains_key(used))
+                .collect();
+            to_visit.extend(used.iter().cloned());
+            deps.insert(path.clone(), used);
+            order.push(path);
+        }
+
+        // The cycle is made of the modules from which `module` is reachable
+        let mut cycle = HashSet::new();
+        let mut to_visit = vec![module.clone()];
+        while let Some(path) = to_visit.pop() {
+            for (other, used) in &deps {
+                if used.contains(&path) && cycle.insert(other.clone()) {
+                    to_visit.push(other.clone());
+                }
+            }
+        }
+        let cycle: Vec<ModulePath> = order.into_iter().filter(|m| cycle.contains(m)).collect();
+        if cycle.is_empty() {
+            return Ok(self.asts.remove(module).unwrap());
+        }
+
+        // Declare the structs first, as they may be used by the signatures of other modules
+        for path in &cycle {
+            let decls = hir::declare_structs(&self.asts[path]);
+            self.mods.insert(decls.mod_id, path.clone());
+            self.mods_ids.insert(path.clone(), decls.mod_id);
+            self.public_decls.insert(path.clone(), decls);
+            self.declared.insert(path.clone());
+        }
+
+        // Then the function signatures and struct fields, the modules used from outside the
+        // cycle are processed beforehand
+        for path in &cycle {
+            let used: Vec<ModulePath> = self.asts[path]
+                .used
+                .iter()
+                .map(|used| used.path.clone())
+                .collect();
+            for used in used {
+                if self.public_decls.contains_key(&used) {
+                    continue;
+                }
+                self.detect_circular_imports(&used, imported, err)?;
+                let mut imported = imported.clone();
+                imported.insert(used.clone());
+                let lint = lint && used.root == module.root;
+                let module_hir = self.get_hir(&used, imported, lint, err, resolver)?;
+                self.extend_hir(module_hir, used);
+            }
+        }
+        for path in &cycle {
+            let ast = &self.asts[path];
+            let mut namespaces = HashMap::new();
+            for used in &ast.used {
+                if let Some(pub_decls) = self
+                    .public_decls
+                    .get(&used.path)
+                    .filter(|_| used.items.is_none())
+                {
+                    namespaces.insert(namespace_ident(used), pub_decls.mod_id);
+                }
+            }
+            let decls = hir::to_declarations(ast, namespaces, self, &self.knwon_values, err)?;
+            for (s_id, struc) in decls.structs {
+                self.structs.insert(s_id, struc);
+            }
+            for (tup_id, tup) in decls.tuples {
+                self.tuples.insert(tup_id, tup);
+            }
+            for fun in decls.funs {
+                self.signatures.insert(fun.fun_id, fun);
+            }
+            self.public_decls.insert(path.clone(), decls.pub_decls);
+        }
+
+        Ok(self.asts.remove(module).unwrap())
+    }
+
     /// Initialize the known values (values such as `Str` or `malloc`) if they are not yet defined,
     /// otherwise return imediately.
     fn initialize_known_values(
@@ -327,17 +1907,72 @@ impl Ctx {
         err: &mut impl ErrorHandler,
         resolver: &impl Resolver,
     ) -> Result<(), ()> {
-        if self.knwon_values.is_initialized() {
+        if self.knwon_values.is_initialized() || self.no_runtime {
             return Ok(());
         }
-        let funs = self.get_known_functions(err, resolver)?;
-        let structs = self.get_known_structs(err, resolver)?;
-        self.knwon_values = KnownValues { funs, structs };
+        let runtime = self
+            .runtime
+            .clone()
+            .unwrap_or_else(|| String::from(known_functions::CORE));
+        let decls = known_functions::RUNTIME_DECLARATIONS;
+        self.validate_runtime(&runtime, decls, err, resolver)?;
+        self.knwon_values.structs = Some(self.get_known_structs(err, resolver)?);
+        // The `panic` module uses string literals, it can only be compiled once `Str` is known
+        let decls = known_functions::RUNTIME_PANIC_DECLARATIONS;
+        self.validate_runtime(&runtime, decls, err, resolver)?;
+        self.knwon_values.funs = Some(self.get_known_functions(err, resolver)?);
         Ok(())
     }
 
+    /// Checks that a runtime replacing `core` provides the modules and declarations of `decls`,
+    /// all the missing ones are reported.
+    fn validate_runtime(
+        &mut self,
+        runtime: &str,
+        decls: &[(&str, &[&str])],
+        err: &mut impl ErrorHandler,
+        resolver: &impl Resolver,
+    ) -> Result<(), ()> {
+        // Modules are resolved first, as resolution stops after any error
+        let mut modules = Vec::with_capacity(decls.len());
+        for (module, idents) in decls {
+            let module = ModulePath {
+                root: String::from(known_functions::CORE),
+                path: vec![String::from(*module)],
+            };
+            if self.get_public_decls(&module, err, resolver).is_err() {
+                err.report_no_loc(format!(
+                    "The runtime '{}' does not provide a valid '{}' module",
+                    runtime, module
+                ));
+                return Err(());
+            }
+            modules.push((module, *idents));
+        }
+        let mut missing = false;
+        for (module, idents) in modules {
+            let decls = &self.public_decls[&module];
+            for ident in idents {
+                let ident = *ident;
+                if !decls.val_decls.contains_key(ident) && !decls.type_decls.contains_key(ident) {
+                    err.report_no_loc(format!(
+                        "The runtime '{}' does not declare '{}' in '{}'",
+                        runtime, ident, module
+                    ));
+                    missing = true;
+                }
+            }
+        }
+        if missing {
+            Err(())
+        } else {
+            Ok(())
+        }
+    }
+
     /// Return the IDs of the known functions, that is functions that are known to the compiler and
-    /// needed for the latter phases of the compilation.
+    /// needed for the latter phases of the compilation. They are looked up by name in the runtime
+    /// and their signature is checked, the known structs must be registered first.
     fn get_known_functions(
         &mut self,
         err: &mut impl ErrorHandler,
@@ -349,7 +1984,103 @@ impl Ctx {
             .clone();
         let malloc = self.get_fun_from_decls(&malloc_decl, "malloc", &modules.malloc, err)?;
         let malloc = known_functions::validate_malloc(malloc, err)?;
-        Ok(KnownFunctions { malloc })
+
+        // Functions used to manage heap objects with reference counting
+        let mut get_mem_fun = |ident: &str, params: Vec<hir::Type>, ret: hir::Type| {
+            let fun = self.get_fun_from_decls(&malloc_decl, ident, &modules.malloc, err)?;
+            let t = hir::FunctionType {
+                params,
+                ret: Box::new(ret),
+            };
+            known_functions::validate_signature(fun, ident, t, err)
+        };
+        let null = hir::Type::Scalar(hir::ScalarType::Null);
+        let bool_t = hir::Type::Scalar(hir::ScalarType::Bool);
+        let free = get_mem_fun("free", vec![hir::TYPE_I32], null.clone())?;
+        let rc_alloc = get_mem_fun("rc_alloc", vec![hir::TYPE_I32], hir::TYPE_I32)?;
+        let rc_retain = get_mem_fun("rc_retain", vec![hir::TYPE_I32], null)?;
+        let rc_release = get_mem_fun("rc_release", vec![hir::TYPE_I32], bool_t)?;
+
+        // String functions used to lower `format`
+        let structs = match self.knwon_values.structs.clone() {
+            Some(structs) => structs,
+            None => {
+                err.report_internal_no_loc(String::from(
+                    "Known functions are looked up before the known structs",
+                ));
+                return Err(());
+            }
+        };
+        let str_decl = self.get_public_decls(&modules.str, err, resolver)?.clone();
+        let string_t = hir::Type::Struct(structs.string);
+        let str_t = hir::Type::Struct(structs.str);
+        let mut get_str_fun = |ident: &str, params: Vec<hir::Type>, ret: &hir::Type| {
+            let fun = self.get_fun_from_decls(&str_decl, ident, &modules.str, err)?;
+            let t = hir::FunctionType {
+                params,
+                ret: Box::new(ret.clone()),
+            };
+            known_functions::validate_signature(fun, ident, t, err)
+        };
+        let new_string = get_str_fun("new_string", vec![], &string_t)?;
+        let params = vec![string_t.clone(), str_t.clone()];
+        let append_str = get_str_fun("append_str", params, &string_t)?;
+        let params = vec![string_t.clone(), hir::Type::Scalar(hir::ScalarType::I32)];
+        let append_i32 = get_str_fun("append_i32", params, &string_t)?;
+        let params = vec![string_t.clone(), hir::Type::Scalar(hir::ScalarType::I64)];
+        let append_i64 = get_str_fun("append_i64", params, &string_t)?;
+        let params = vec![string_t.clone(), hir::Type::Scalar(hir::ScalarType::Bool)];
+        let append_bool = get_str_fun("append_bool", params, &string_t)?;
+        let as_str = get_str_fun("as_str", vec![string_t.clone()], &str_t)?;
+
+        // Function used to lower `assert` and `assert_eq`
+        let assert_decl = self
+            .get_public_decls(&modules.assert, err, resolver)?
+            .clone();
+        let assert = self.get_fun_from_decls(&assert_decl, "check", &modules.assert, err)?;
+        let t = hir::FunctionType {
+            params: vec![
+                hir::Type::Scalar(hir::ScalarType::Bool),
+                str_t,
+                hir::TYPE_I32,
+            ],
+            ret: Box::new(hir::Type::Scalar(hir::ScalarType::Null)),
+        };
+        let assert = known_functions::validate_signature(assert, "check", t, err)?;
+
+        // Functions used to lower `panic` and overflow checks
+        let panic_decl = self
+            .get_public_decls(&modules.panic, err, resolver)?
+            .clone();
+        let null = Box::new(hir::Type::Scalar(hir::ScalarType::Null));
+        let panic = self.get_fun_from_decls(&panic_decl, "panic", &modules.panic, err)?;
+        let t = hir::FunctionType {
+            params: vec![hir::Type::Struct(structs.str)],
+            ret: null.clone(),
+        };
+        let panic = known_functions::validate_signature(panic, "panic", t, err)?;
+        let overflow = self.get_fun_from_decls(&panic_decl, "overflow", &modules.panic, err)?;
+        let t = hir::FunctionType {
+            params: vec![],
+            ret: null,
+        };
+        let overflow = known_functions::validate_signature(overflow, "overflow", t, err)?;
+        Ok(KnownFunctions {
+            malloc,
+            free,
+            rc_alloc,
+            rc_retain,
+            rc_release,
+            new_string,
+            append_str,
+            append_i32,
+            append_i64,
+            append_bool,
+            as_str,
+            assert,
+            panic,
+            overflow,
+        })
     }
 
     /// Return the IDs of known structs.
@@ -362,7 +2093,11 @@ impl Ctx {
         let str_decl = self.get_public_decls(&modules.str, err, resolver)?.clone();
         let str = self.get_struct_from_decl(&str_decl, "Str", &modules.str, err)?;
         let str = known_functions::validate_str(str, err)?;
-        Ok(KnownStructs { str })
+        let string = self.get_struct_from_decl(&str_decl, "String", &modules.str, err)?;
+        Ok(KnownStructs {
+            str,
+            string: string.s_id,
+        })
     }
 
     /// Return the public declarations of a given module, will build HIR if not already in Ctx.
@@ -378,7 +2113,7 @@ impl Ctx {
         resolver: &impl Resolver,
     ) -> Result<&ModuleDeclarations, ()> {
         if self.public_decls.get(module).is_none() {
-            let hir = self.get_hir(module, HashSet::new(), err, resolver)?;
+            let hir = self.get_hir(module, HashSet::new(), false, err, resolver)?;
             self.extend_hir(hir, module.clone());
         }
         Ok(&self.public_decls[module])
@@ -454,18 +2189,39 @@ impl Ctx {
     ///  - hir: The hir package to add
     ///  - module: the import path of the hir module, can be use to get public declarations.
     fn extend_hir(&mut self, hir: hir::Program, module: ModulePath) {
+        // The declarations of a module are superseded by its HIR
+        let is_declared = self.declared.remove(&module);
+        if is_declared {
+            for fun in &hir.funs {
+                self.signatures.remove(&fun.fun_id);
+            }
+            for import in &hir.imports {
+                for fun in &import.prototypes {
+                    self.signatures.remove(&fun.fun_id);
+                }
+            }
+        }
+        if let Some(timings) = &mut self.timings {
+            timings.record_store("data", hir.data.stats());
+            timings.record_store("structs", hir.structs.stats());
+            timings.record_store("tuples", hir.tuples.stats());
+        }
         for (s_id, struc) in hir.structs {
             let prev = self.structs.insert(s_id, struc);
-            debug_assert!(prev.is_none()); // s_id must be unique
+            debug_assert!(prev.is_none() || is_declared); // s_id must be unique
         }
         for (tup_id, tup) in hir.tuples {
             let prev = self.tuples.insert(tup_id, tup);
             debug_assert!(prev.is_none()); // tup_id must be unique
         }
         for fun in hir.funs {
+            self.fun_mods.insert(fun.fun_id, hir.module.id);
             let prev = self.funs.insert(fun.fun_id, hir::FunKind::Fun(fun));
             assert!(prev.is_none()); // fun_id must be unique
         }
+        self.inits.extend(hir.inits);
+        self.memories.extend(hir.memory);
+        self.exports.push(hir.exports);
         for (d_id, data) in hir.data {
             let prev = self.data.insert(d_id, data);
             debug_assert!(prev.is_none()); // d_id must be unique
@@ -474,6 +2230,7 @@ impl Ctx {
             let mut prototypes = Vec::new();
             for fun in import.prototypes {
                 prototypes.push(fun.fun_id);
+                self.fun_mods.insert(fun.fun_id, hir.module.id);
                 let prev = self.funs.insert(fun.fun_id, hir::FunKind::Extern(fun));
                 debug_assert!(prev.is_none()); // fun_id must be unique
             }
@@ -487,28 +2244,276 @@ impl Ctx {
         self.mods_ids.insert(module.clone(), hir.module.id);
         self.packages.push(hir.module);
         self.public_decls.insert(module, hir.pub_decls);
+        self.symbols.extend(hir.symbols);
+        self.nodes.extend(hir.nodes);
+        self.scopes.extend(hir.scopes);
+    }
+
+    /// Orders the init functions such that modules are initialized after the modules used by
+    /// their init blocks, that is the modules of the functions they call directly or not.
+    ///
+    /// Modules are otherwise initialized after their dependencies. Init blocks depending on each
+    /// others can not be ordered, an error is reported for each init block of such a cycle.
+    fn order_inits(&mut self, err: &mut impl ErrorHandler) -> Result<(), ()> {
+        // Group the init functions by module, in initialization order
+        let mut mods = Vec::new();
+        let mut inits: HashMap<ModId, Vec<hir::FunId>> = HashMap::new();
+        for fun_id in &self.inits {
+            let mod_id = match self.fun_mods.get(fun_id) {
+                Some(mod_id) => *mod_id,
+                None => {
+                    err.report_internal_no_loc(format!("No module for init function {}", fun_id));
+                    return Err(());
+                }
+            };
+            if !inits.contains_key(&mod_id) {
+                mods.push(mod_id);
+            }
+            inits.entry(mod_id).or_default().push(*fun_id);
+        }
+
+        // Collect the modules initialized before each module, along with the init function
+        // requiring them
+        let mut deps: HashMap<ModId, Vec<(ModId, hir::FunId)>> = HashMap::new();
+        for mod_id in &mods {
+            let mut mod_deps = Vec::new();
+            for init in &inits[mod_id] {
+                let used_mods = self.get_used_mods(*init);
+                for dep in &mods {
+                    if dep != mod_id && used_mods.contains(dep) {
+                        mod_deps.push((*dep, *init));
+                    }
+                }
+            }
+            deps.insert(*mod_id, mod_deps);
+        }
+
+        // Sort the modules topologically, keeping the current order when possible
+        let mut sorter = InitSorter {
+            deps: &deps,
+            visited: HashSet::new(),
+            path: Vec::new(),
+            order: Vec::with_capacity(mods.len()),
+            cycles: Vec::new(),
+        };
+        for mod_id in &mods {
+            sorter.visit(*mod_id);
+        }
+        let InitSorter { order, cycles, .. } = sorter;
+        for cycle in &cycles {
+            for (idx, (mod_id, init)) in cycle.iter().enumerate() {
+                let (dep, _) = cycle[(idx + 1) % cycle.len()];
+                let loc = match self.funs.get(init) {
+                    Some(hir::FunKind::Fun(fun)) => fun.loc,
+                    _ => continue,
+                };
+                err.report(
+                    loc,
+                    format!(
+                        "Initialization cycle: the init block of '{}' uses module '{}'",
+                        self.display_mod(*mod_id),
+                        self.display_mod(dep)
+                    ),
+                );
+            }
+        }
+        if !cycles.is_empty() {
+            return Err(());
+        }
+        self.inits = order
+            .into_iter()
+            .flat_map(|mod_id| inits.remove(&mod_id).unwrap_or_default())
+            .collect();
+        Ok(())
+    }
+
+    /// Returns the modules of the functions reachable from `fun_id`.
+    fn get_used_mods(&self, fun_id: hir::FunId) -> HashSet<ModId> {
+        let mut reachable = HashSet::new();
+        let mut used_mods = HashSet::new();
+        let mut to_visit = vec![fun_id];
+        while let Some(fun_id) = to_visit.pop() {
+            if !reachable.insert(fun_id) {
+                continue;
+            }
+            if let Some(mod_id) = self.fun_mods.get(&fun_id) {
+                used_mods.insert(*mod_id);
+            }
+            if let Some(hir::FunKind::Fun(fun)) = self.funs.get(&fun_id) {
+                to_visit.extend(fun.callees());
+            }
+        }
+        used_mods
+    }
+
+    /// Returns the modules of a package, sorted by ID.
+    fn get_package_mods(&self, package: &str) -> Vec<ModId> {
+        let mut mods: Vec<ModId> = self
+            .mods
+            .iter()
+            .filter(|(_, path)| path.root == package)
+            .map(|(mod_id, _)| *mod_id)
+            .collect();
+        mods.sort_by_key(|mod_id| mod_id.0);
+        mods
+    }
+
+    fn get_module_graph(&self, package: &str) -> String {
+        let mods = self.get_package_mods(package);
+        let mut nodes = BTreeSet::new();
+        let mut edges = Vec::new();
+        for mod_id in &mods {
+            nodes.insert(mod_id.0);
+            let mut deps: Vec<ModId> = self.mod_deps.get(mod_id).cloned().unwrap_or_default();
+            deps.sort_by_key(|mod_id| mod_id.0);
+            for dep in deps {
+                nodes.insert(dep.0);
+                edges.push((mod_id.0, dep.0));
+            }
+        }
+        let mut graph = DotWriter::new(package);
+        for node in nodes {
+            let mod_id = ModId(node);
+            let label = self.display_mod(mod_id);
+            if mods.contains(&mod_id) {
+                graph.node(&format!("m{}", node), &label, &[]);
+            } else {
+                graph.node(&format!("m{}", node), &label, &[("style", "dashed")]);
+            }
+        }
+        for (from, to) in edges {
+            graph.edge(&format!("m{}", from), &format!("m{}", to));
+        }
+        graph.finish()
+    }
+
+    fn get_call_graph(&self, package: &str) -> String {
+        let mods = self.get_package_mods(package);
+        let is_root = |fun: &hir::Function| {
+            fun.exposed.is_some() || fun.is_test || fun.is_entry || self.inits.contains(&fun.fun_id)
+        };
+        // Functions of the package and their callees, grouped by module.
+        let mut clusters: BTreeMap<u32, BTreeSet<hir::FunId>> = BTreeMap::new();
+        let mut edges = BTreeSet::new();
+        let mut roots = Vec::new();
+        for (fun_id, fun) in &self.funs {
+            let mod_id = match self.fun_mods.get(fun_id) {
+                Some(mod_id) if mods.contains(mod_id) => mod_id,
+                _ => continue,
+            };
+            clusters.entry(mod_id.0).or_default().insert(*fun_id);
+            let fun = match fun {
+                hir::FunKind::Fun(fun) => fun,
+                hir::FunKind::Extern(_) => continue,
+            };
+            if is_root(fun) {
+                roots.push(*fun_id);
+            }
+            for callee in fun.callees() {
+                if let Some(mod_id) = self.fun_mods.get(&callee) {
+                    clusters.entry(mod_id.0).or_default().insert(callee);
+                    edges.insert((*fun_id, callee));
+                }
+            }
+        }
+        let mut reachable = HashSet::new();
+        while let Some(fun_id) = roots.pop() {
+            if !reachable.insert(fun_id) {
+                continue;
+            }
+            if let Some(hir::FunKind::Fun(fun)) = self.funs.get(&fun_id) {
+                roots.extend(fun.callees());
+            }
+        }
+        let mut graph = DotWriter::new(package);
+        for (mod_id, funs) in clusters {
+            graph.start_cluster(&mod_id.to_string(), &self.display_mod(ModId(mod_id)));
+            for fun_id in funs {
+                let mut attributes = Vec::new();
+                let ident = match self.funs.get(&fun_id) {
+                    Some(hir::FunKind::Fun(fun)) => {
+                        if is_root(fun) {
+                            attributes.push(("peripheries", "2"));
+                        }
+                        &fun.ident
+                    }
+                    Some(hir::FunKind::Extern(proto)) => {
+                        attributes.push(("style", "dashed"));
+                        proto.alias.as_ref().unwrap_or(&proto.ident)
+                    }
+                    None => continue,
+                };
+                if !reachable.contains(&fun_id) {
+                    attributes.push(("color", "gray"));
+                    attributes.push(("fontcolor", "gray"));
+                }
+                graph.node(&format!("f{}", fun_id), ident, &attributes);
+            }
+            graph.end_cluster();
+        }
+        for (from, to) in edges {
+            graph.edge(&format!("f{}", from), &format!("f{}", to));
+        }
+        graph.finish()
+    }
+
+    /// Returns the number of lines spanned by a location.
+    fn count_lines(&self, loc: Location) -> usize {
+        match self.sources.get(loc.f_id) {
+            Some(file) => {
+                let lines = file.line_index();
+                let end = loc.pos + loc.len.saturating_sub(1);
+                lines.line_of(end) - lines.line_of(loc.pos) + 1
+            }
+            None => 0,
+        }
+    }
+
+    /// Returns the path of a module, as displayed to the user.
+    fn display_mod(&self, mod_id: ModId) -> String {
+        match self.mods.get(&mod_id) {
+            Some(path) => path.to_string(),
+            None => format!("{:?}", mod_id),
+        }
     }
 
-    /// Parses files and return the a tuple (AST, error_handler, file_name) per file.
+    /// Parses the files of the module `mod_id` and return the a tuple (AST, error_handler,
+    /// file_name) per file.
+    ///
+    /// All the files are parsed even if some of them contain errors, the errors of those files are
+    /// merged into `err`.
     fn parse_files<E: ErrorHandler>(
         &self,
         files: Vec<PreparedFile>,
-        _err: &mut E,
+        mod_id: ModId,
+        err: &mut E,
     ) -> Result<Vec<(ast::Program, E, String)>, ()> {
         let mut ast_programs = Vec::with_capacity(files.len());
-        let mod_id = self.fresh_mod_id();
+        let mut has_error = false;
         for file in files.into_iter() {
-            let mut error_handler = E::new(file.code, file.f_id);
-            let ast_program = ast::get_ast(
-                file.f_id,
+            let source = self.sources.add(file.f_id, file.code);
+            let mut error_handler = err.sink();
+            match ast::get_ast(
+                &source,
                 mod_id,
                 file.kind,
                 &mut error_handler,
-                self.verbose,
-            );
-            ast_programs.push((ast_program, error_handler, file.file_name));
+                self.timings.as_ref(),
+            ) {
+                Ok(ast_program) => {
+                    ast_programs.push((ast_program, error_handler, file.file_name));
+                }
+                Err(()) => {
+                    err.merge(error_handler);
+                    has_error = true;
+                }
+            }
+        }
+        if has_error {
+            Err(())
+        } else {
+            Ok(ast_programs)
         }
-        Ok(ast_programs)
     }
 
     /// Raises an error if an AST package declare different package names or kinds.
@@ -589,10 +2594,66 @@ impl Ctx {
         }
     }
 
-    /// Generates a fresh (unique) module ID.
-    fn fresh_mod_id(&self) -> ModId {
-        let mod_id = self.mod_id.get();
-        self.mod_id.set(ModId(mod_id.0 + 1));
-        mod_id
+    /// Returns the module ID derived from `key`, typically the path of a module.
+    ///
+    /// A key always maps to the same ID, so that the IDs of a module and of its items do not
+    /// depend on the other modules of the program and are stable across builds. Keys colliding
+    /// with a previous one are rehashed, their IDs then depend on the order they are seen in.
+    pub(crate) fn stable_mod_id(&self, key: &str) -> ModId {
+        let mut keys = self.mod_id_keys.borrow_mut();
+        let mut attempt = 0;
+        loop {
+            let mod_id = ModId::from_hash(key, attempt);
+            match keys.get(&mod_id) {
+                Some(other) if other == key => return mod_id,
+                None if mod_id.0 != 0 => {
+                    keys.insert(mod_id, key.to_owned());
+                    return mod_id;
+                }
+                _ => attempt += 1,
+            }
+        }
+    }
+}
+
+/// A depth first traversal of the dependencies between init blocks, see `Ctx::order_inits`.
+struct InitSorter<'a> {
+    deps: &'a HashMap<ModId, Vec<(ModId, hir::FunId)>>,
+    visited: HashSet<ModId>,
+    /// The modules being visited, along with the init function requiring the next one.
+    path: Vec<(ModId, hir::FunId)>,
+    order: Vec<ModId>,
+    cycles: Vec<Vec<(ModId, hir::FunId)>>,
+}
+
+impl<'a> InitSorter<'a> {
+    fn visit(&mut self, mod_id: ModId) {
+        if !self.visited.insert(mod_id) {
+            return;
+        }
+        for (dep, init) in &self.deps[&mod_id] {
+            self.path.push((mod_id, *init));
+            if let Some(start) = self.path.iter().position(|(m, _)| m == dep) {
+                self.cycles.push(self.path[start..].to_vec());
+            } else {
+                self.visit(*dep);
+            }
+            self.path.pop();
+        }
+        self.order.push(mod_id);
+    }
+}
+
+/// Returns the identifier under which a used module is accessible.
+fn namespace_ident(used: &ast::Use) -> String {
+    if let Some(alias) = &used.alias {
+        alias.clone()
+    } else {
+        used.path.alias().to_owned()
     }
 }
+
+/// Warning reported for items left out of a WIT description.
+fn wit_omitted(name: &str) -> String {
+    format!("'{}' can not be described in WIT, it is omitted", name)
+}
@@ -0,0 +1,101 @@
+//! Graphs
+//!
+//! Writes the module dependency graph and the call graph of a package in the DOT language of
+//! Graphviz, see `Ctx::get_graph`.
+
+/// The graphs of a package.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GraphKind {
+    /// The modules, and the modules they use.
+    Modules,
+    /// The functions, and the functions they call or refer to, grouped by module.
+    Calls,
+}
+
+/// A directed graph being written in the DOT language.
+pub struct DotWriter {
+    dot: String,
+    depth: usize,
+}
+
+impl DotWriter {
+    pub fn new(name: &str) -> Self {
+        let mut writer = Self {
+            dot: format!("digraph {} {{\n", quote(name)),
+            depth: 1,
+        };
+        writer.line("node [shape=box];");
+        writer
+    }
+
+    /// Starts a cluster, the nodes declared until the cluster ends are drawn together.
+    pub fn start_cluster(&mut self, id: &str, label: &str) {
+        self.line(&format!("subgraph cluster_{} {{", id));
+        self.depth += 1;
+        self.line(&format!("label={};", quote(label)));
+    }
+
+    pub fn end_cluster(&mut self) {
+        self.depth -= 1;
+        self.line("}");
+    }
+
+    /// Declares a node, the attributes are pairs of names and values.
+    pub fn node(&mut self, id: &str, label: &str, attributes: &[(&str, &str)]) {
+        let mut attrs = vec![format!("label={}", quote(label))];
+        attrs.extend(
+            attributes
+                .iter()
+                .map(|(name, value)| format!("{}={}", name, quote(value))),
+        );
+        self.line(&format!("{} [{}];", id, attrs.join(", ")));
+    }
+
+    pub fn edge(&mut self, from: &str, to: &str) {
+        self.line(&format!("{} -> {};", from, to));
+    }
+
+    pub fn finish(mut self) -> String {
+        self.dot.push_str("}\n");
+        self.dot
+    }
+
+    fn line(&mut self, line: &str) {
+        for _ in 0..self.depth {
+            self.dot.push_str("    ");
+        }
+        self.dot.push_str(line);
+        self.dot.push('\n');
+    }
+}
+
+/// Returns a quoted DOT string.
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dot() {
+        let mut writer = DotWriter::new("calls");
+        writer.start_cluster("1", "pkg");
+        writer.node("f1", "main", &[("peripheries", "2")]);
+        writer.node("f2", "say \"hi\"", &[]);
+        writer.end_cluster();
+        writer.edge("f1", "f2");
+        let expected = r#"digraph "calls" {
+    node [shape=box];
+    subgraph cluster_1 {
+        label="pkg";
+        f1 [label="main", peripheries="2"];
+        f2 [label="say \"hi\""];
+    }
+    f1 -> f2;
+}
+"#;
+        assert_eq!(writer.finish(), expected);
+    }
+}
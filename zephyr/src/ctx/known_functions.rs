@@ -8,32 +8,99 @@
 //!
 //! ```ignore
 //! malloc: i32 -> i32
+//! free: i32 -> ()
+//! rc_alloc: i32 -> i32
+//! rc_retain: i32 -> ()
+//! rc_release: i32 -> bool
+//! new_string: () -> String
+//! append_str: (String, Str) -> String
+//! append_i32: (String, i32) -> String
+//! append_i64: (String, i64) -> String
+//! append_bool: (String, bool) -> String
+//! as_str: String -> Str
+//! assert: (bool, Str, i32) -> ()
+//! panic: Str -> ()
+//! overflow: () -> ()
 //! ```
+//!
+//! The string functions are used to lower the `format` built-in, `assert` is used to lower the
+//! `assert` and `assert_eq` built-ins, `panic` lowers the `panic` built-in and `overflow` is
+//! called when an overflow check fails. The `rc_*` functions, along with `free`, manage the
+//! heap objects when compiling with reference counting.
+//!
+//! The `core` package can be replaced by another runtime, which must then provide the same
+//! declarations (see `RUNTIME_DECLARATIONS`). Mismatching declarations are thus reported as
+//! errors of the program rather than internal errors.
 use crate::resolver::ModulePath;
 use crate::error::ErrorHandler;
-use crate::hir::known_ids::*;
-use crate::hir::{FunId, FunKind, ScalarType, Struct, StructId, Type};
+use crate::hir::{FunId, FunKind, FunctionType, ScalarType, Struct, StructId, Type};
+
+pub const CORE: &str = "core";
 
-const CORE: &str = "core";
+/// The public declarations expected by the compiler, by module of the runtime.
+pub const RUNTIME_DECLARATIONS: &[(&str, &[&str])] = &[
+    (
+        "mem",
+        &["malloc", "free", "rc_alloc", "rc_retain", "rc_release"],
+    ),
+    (
+        "str",
+        &[
+            "Str",
+            "String",
+            "new_string",
+            "append_str",
+            "append_i32",
+            "append_i64",
+            "append_bool",
+            "as_str",
+        ],
+    ),
+    ("assert", &["check"]),
+];
 
-/// A bunch of IDs for values expected by the compiler.
+/// The declarations expected in the `panic` module of the runtime, which uses string literals and
+/// can thus only be checked once the other known values are initialized.
+pub const RUNTIME_PANIC_DECLARATIONS: &[(&str, &[&str])] = &[("panic", &["panic", "overflow"])];
+
+/// A registry of the values expected by the compiler, looked up by name in the runtime as its
+/// modules are compiled.
 pub struct KnownValues {
-    pub funs: KnownFunctions,
-    pub structs: KnownStructs,
+    pub funs: Option<KnownFunctions>,
+    pub structs: Option<KnownStructs>,
 }
 
 /// An inventory of functions expected by the compiler.
+#[derive(Clone)]
 pub struct KnownFunctions {
     pub malloc: FunId,
+    pub free: FunId,
+    pub rc_alloc: FunId,
+    pub rc_retain: FunId,
+    pub rc_release: FunId,
+    pub new_string: FunId,
+    pub append_str: FunId,
+    pub append_i32: FunId,
+    pub append_i64: FunId,
+    pub append_bool: FunId,
+    pub as_str: FunId,
+    pub assert: FunId,
+    pub panic: FunId,
+    pub overflow: FunId,
 }
 
 pub struct KnownFunctionPaths {
     pub malloc: ModulePath,
+    pub str: ModulePath,
+    pub assert: ModulePath,
+    pub panic: ModulePath,
 }
 
 /// A bunch of structs expected by the compiler.
+#[derive(Clone)]
 pub struct KnownStructs {
     pub str: StructId,
+    pub string: StructId,
 }
 
 pub struct KnownStructPaths {
@@ -41,22 +108,22 @@ pub struct KnownStructPaths {
 }
 
 impl KnownValues {
-    /// Return uninitialized values, where IDs does not map to anything.
-    /// This is useful for first building the HIR of the `core` package which is the one supposed
-    /// to defines those values.
+    /// Returns an empty registry, values are registered once the modules of the runtime declaring
+    /// them are compiled.
     ///
-    /// Using uninitialized values while parsing a module other than `core` is undefined behaviors.
-    pub fn uninitialized() -> Self {
-        // The module ID 0 is reserved, thus a value ID of 0 will never collide.
+    /// The `core` package is compiled with an empty registry (or with only the structs for its
+    /// `panic` module), the language features relying on the missing values are then reported as
+    /// errors. The registry stays empty when compiling without a runtime.
+    pub fn empty() -> Self {
         Self {
-            funs: KnownFunctions { malloc: MALLOC_ID },
-            structs: KnownStructs { str: STR_ID },
+            funs: None,
+            structs: None,
         }
     }
 
-    /// Check if the IDs are initialized.
+    /// Check if all the values are registered.
     pub fn is_initialized(&self) -> bool {
-        self.funs.malloc != MALLOC_ID
+        self.funs.is_some()
     }
 }
 
@@ -67,6 +134,18 @@ impl KnownFunctionPaths {
                 root: CORE.to_owned(),
                 path: vec!["mem".to_owned()],
             },
+            str: ModulePath {
+                root: CORE.to_owned(),
+                path: vec!["str".to_owned()],
+            },
+            assert: ModulePath {
+                root: CORE.to_owned(),
+                path: vec!["assert".to_owned()],
+            },
+            panic: ModulePath {
+                root: CORE.to_owned(),
+                path: vec!["panic".to_owned()],
+            },
         }
     }
 }
@@ -90,11 +169,33 @@ pub fn validate_malloc(fun: &FunKind, err: &mut impl ErrorHandler) -> Result<Fun
         FunKind::Extern(fun) => (fun.fun_id, fun.loc, &fun.t.params, &fun.t.ret),
     };
     if params != &vec![Type::Scalar(ScalarType::I32)] {
-        err.report_internal(loc, String::from("Unexpected types for malloc parameters"));
+        err.report(loc, String::from("Unexpected types for malloc parameters"));
         return Err(());
     }
     if ret.as_ref() != &Type::Scalar(ScalarType::I32) {
-        err.report_internal(loc, String::from("Unexpected return value in malloc"));
+        err.report(loc, String::from("Unexpected return value in malloc"));
+        return Err(());
+    }
+    Ok(fun_id)
+}
+
+/// Validates the signature of a known function.
+pub fn validate_signature(
+    fun: &FunKind,
+    ident: &str,
+    expected: FunctionType,
+    err: &mut impl ErrorHandler,
+) -> Result<FunId, ()> {
+    let (fun_id, loc, t) = match fun {
+        FunKind::Fun(fun) => (fun.fun_id, fun.loc, &fun.t),
+        FunKind::Extern(fun) => (fun.fun_id, fun.loc, &fun.t),
+    };
+    if t.params != expected.params {
+        err.report(loc, format!("Unexpected types for {} parameters", ident));
+        return Err(());
+    }
+    if t.ret != expected.ret {
+        err.report(loc, format!("Unexpected return value in {}", ident));
         return Err(());
     }
     Ok(fun_id)
@@ -103,7 +204,7 @@ pub fn validate_malloc(fun: &FunKind, err: &mut impl ErrorHandler) -> Result<Fun
 pub fn validate_str(struc: &Struct, err: &mut impl ErrorHandler) -> Result<StructId, ()> {
     let loc = struc.loc;
     if struc.fields.len() != 2 {
-        err.report_internal(
+        err.report(
             loc,
             String::from("Str must have exactly two fields: 'len' and 'start'"),
         );
@@ -111,19 +212,19 @@ pub fn validate_str(struc: &Struct, err: &mut impl ErrorHandler) -> Result<Struc
     }
     if let Some(len) = struc.fields.get("len") {
         if len.t != Type::Scalar(ScalarType::I32) {
-            err.report_internal(len.loc, String::from("Str.len must have type i32"));
+            err.report(len.loc, String::from("Str.len must have type i32"));
             return Err(());
         }
     } else {
-        err.report_internal(loc, String::from("Str must have a 'len' field"));
+        err.report(loc, String::from("Str must have a 'len' field"));
         return Err(());
     };
     if let Some(start) = struc.fields.get("start") {
         if start.t != Type::Scalar(ScalarType::I32) {
-            err.report_internal(start.loc, String::from("Str.start must have type i32"));
+            err.report(start.loc, String::from("Str.start must have type i32"));
         }
     } else {
-        err.report_internal(loc, String::from("Str must have a 'start' field"));
+        err.report(loc, String::from("Str must have a 'start' field"));
         return Err(());
     };
     Ok(struc.s_id)
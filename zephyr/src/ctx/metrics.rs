@@ -0,0 +1,27 @@
+//! Code Metrics
+//!
+//! Size metrics of the functions of a package, computed from the HIR and the MIR by
+//! `Ctx::get_metrics` to find the functions weighing the most on the final module.
+
+/// Metrics of a function, closures and init blocks included.
+#[derive(Clone, Debug)]
+pub struct FunctionMetrics {
+    pub name: String,
+    /// Number of lines spanned by the function.
+    pub lines: usize,
+    /// Number of local variables, parameters included.
+    pub locals: usize,
+    /// Number of MIR statements, including the statements of nested blocks. `None` if the
+    /// function is not part of the final module, because it is never used or always inlined.
+    pub statements: Option<usize>,
+    /// Size in bytes of the code of the function once compiled to WebAssembly, `None` if the
+    /// function is not part of the final module.
+    pub wasm_size: Option<usize>,
+}
+
+/// Metrics of the functions of a module, in order of declaration.
+#[derive(Clone, Debug)]
+pub struct ModuleMetrics {
+    pub module: String,
+    pub functions: Vec<FunctionMetrics>,
+}
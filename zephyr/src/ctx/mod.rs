@@ -3,10 +3,18 @@
 //! This module contains the Ctx, that is the compilation context. The Ctx can be use either to
 //! compile Zephyr code down to wasm, or to query information about the code itself.
 mod ctx;
+mod graph;
 mod known_functions;
+mod metrics;
+mod target;
+mod timings;
 mod utils;
 
-pub use ctx::{Ctx, ModId};
+pub use ctx::{Artifacts, BuildMetadata, Ctx, ModId, PostProcess, Snapshot, TEST_PREFIX};
+pub use graph::GraphKind;
+pub use metrics::{FunctionMetrics, ModuleMetrics};
+pub use target::{Arch, System, Target};
+pub use timings::{Event, ModuleStats, Phase, StoreStats, Timings};
 pub use known_functions::{KnownFunctions, KnownStructs, KnownValues};
 pub use utils::{
     ModuleDeclarations, ValueDeclaration, KnownPackage,
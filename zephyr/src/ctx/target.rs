@@ -0,0 +1,110 @@
+//! Compilation Targets
+//!
+//! The WebAssembly variant a package is compiled for, described by a triple such as
+//! `wasm32-wasi`. The target decides of the conventions of the host the module runs on: the width
+//! of pointers, the default size of the memory and how the entry point of the program is called.
+
+use std::fmt;
+
+/// The architecture of a target.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Arch {
+    /// WebAssembly with 32 bits memory addresses.
+    Wasm32,
+}
+
+/// The system interface provided by the host.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum System {
+    /// The WebAssembly System Interface, which calls the function exported as `_start`.
+    Wasi,
+    /// No particular interface, such as a browser, the host calls the exposed functions itself.
+    Unknown,
+}
+
+/// A target triple, omitting the vendor.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Target {
+    pub arch: Arch,
+    pub system: System,
+}
+
+impl Target {
+    pub const WASM32_WASI: Target = Target {
+        arch: Arch::Wasm32,
+        system: System::Wasi,
+    };
+    pub const WASM32_UNKNOWN: Target = Target {
+        arch: Arch::Wasm32,
+        system: System::Unknown,
+    };
+
+    /// Parses a triple such as `wasm32-wasi` or `wasm32-unknown-unknown`.
+    pub fn from_triple(triple: &str) -> Result<Target, String> {
+        let mut parts = triple.split('-');
+        let arch = match parts.next() {
+            Some("wasm32") => Arch::Wasm32,
+            Some("wasm64") => {
+                return Err(String::from("The wasm64 architecture is not supported yet"))
+            }
+            _ => return Err(format!("Unknown architecture in target '{}'", triple)),
+        };
+        let system = match (parts.next(), parts.next(), parts.next()) {
+            (Some("wasi"), None, _) | (Some("unknown"), Some("wasi"), None) => System::Wasi,
+            (Some("unknown"), None, _) | (Some("unknown"), Some("unknown"), None) => {
+                System::Unknown
+            }
+            _ => return Err(format!("Unknown system in target '{}'", triple)),
+        };
+        Ok(Target { arch, system })
+    }
+
+    /// The size of pointers, in bytes.
+    pub fn pointer_size(self) -> u32 {
+        match self.arch {
+            Arch::Wasm32 => 4,
+        }
+    }
+
+    /// The initial number of memory pages, unless a runtime module or an option decides otherwise.
+    pub fn default_memory_pages(self) -> u32 {
+        match self.arch {
+            Arch::Wasm32 => 1,
+        }
+    }
+
+    /// The name under which the host expects the entry point of a program, if any.
+    ///
+    /// The function exposed under that name calls the initializers of the modules, which would
+    /// otherwise run in the WebAssembly start function.
+    pub fn entry_point(self) -> Option<&'static str> {
+        match self.system {
+            System::Wasi => Some("_start"),
+            System::Unknown => None,
+        }
+    }
+
+    /// Whether the host provides the WASI imports, used by the default panic handler.
+    pub fn has_wasi(self) -> bool {
+        self.system == System::Wasi
+    }
+}
+
+impl Default for Target {
+    fn default() -> Self {
+        Target::WASM32_WASI
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let arch = match self.arch {
+            Arch::Wasm32 => "wasm32",
+        };
+        let system = match self.system {
+            System::Wasi => "wasi",
+            System::Unknown => "unknown",
+        };
+        write!(f, "{}-{}", arch, system)
+    }
+}
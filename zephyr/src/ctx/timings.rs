@@ -0,0 +1,183 @@
+//! Compilation Timings
+//!
+//! Durations of the compilation phases and statistics about the compiled modules, recorded when
+//! enabled with `Ctx::set_timings`. Phases are timed as a whole: the duration of a phase is the
+//! sum of the time spent in that phase across all the modules. The size of the HIR stores of
+//! each kind of items is summed the same way.
+//!
+//! Each phase and each pass run over the MIR is also recorded as an event, in order, to
+//! reconstruct a trace of the compilation.
+use std::cell::{Cell, RefCell};
+use std::time::{Duration, Instant};
+
+/// A phase of the compilation.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Phase {
+    /// Locating and reading the files of the modules.
+    Resolve,
+    Scan,
+    Parse,
+    NameResolution,
+    /// Type checking, validation of the assembly and lints.
+    TypeCheck,
+    /// Lowering to HIR, then to MIR, including the optimizations.
+    HirToMir,
+    /// Generation of the final module or object file.
+    Encode,
+}
+
+impl Phase {
+    pub const ALL: [Phase; 7] = [
+        Phase::Resolve,
+        Phase::Scan,
+        Phase::Parse,
+        Phase::NameResolution,
+        Phase::TypeCheck,
+        Phase::HirToMir,
+        Phase::Encode,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Phase::Resolve => "resolve",
+            Phase::Scan => "scan",
+            Phase::Parse => "parse",
+            Phase::NameResolution => "name-resolution",
+            Phase::TypeCheck => "typecheck",
+            Phase::HirToMir => "hir-to-mir",
+            Phase::Encode => "encode",
+        }
+    }
+}
+
+/// Statistics about a compiled module.
+#[derive(Clone, Debug)]
+pub struct ModuleStats {
+    pub module: String,
+    pub files: usize,
+    pub lines: usize,
+    /// Number of functions of the module, `None` if the module was not lowered to HIR.
+    pub functions: Option<usize>,
+}
+
+/// Statistics about the stores of a kind of items, summed over the modules.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct StoreStats {
+    pub items: usize,
+    /// Number of items the stores can hold without re-allocating.
+    pub capacity: usize,
+    /// Number of items replaced by an item with the same ID when merging stores.
+    pub collisions: usize,
+    /// Estimate of the memory held by the stores, in bytes.
+    pub bytes: usize,
+}
+
+impl std::ops::AddAssign for StoreStats {
+    fn add_assign(&mut self, other: Self) {
+        self.items += other.items;
+        self.capacity += other.capacity;
+        self.collisions += other.collisions;
+        self.bytes += other.bytes;
+    }
+}
+
+/// A span of time spent in a phase or a pass.
+#[derive(Clone, Debug)]
+pub struct Event {
+    /// Name of the phase or the pass.
+    pub name: &'static str,
+    /// Whether the event is a phase or a MIR pass.
+    pub category: &'static str,
+    /// The function the pass was run on, if it runs on a single function.
+    pub function: Option<String>,
+    /// Start of the event, relative to the creation of the timings.
+    pub start: Duration,
+    pub duration: Duration,
+}
+
+/// The timings and statistics of a compilation.
+#[derive(Debug)]
+pub struct Timings {
+    origin: Instant,
+    durations: [Cell<Duration>; Phase::ALL.len()],
+    events: RefCell<Vec<Event>>,
+    pub modules: Vec<ModuleStats>,
+    /// The statistics of the HIR stores by kind of items, in the order they were first recorded.
+    pub stores: Vec<(&'static str, StoreStats)>,
+}
+
+impl Timings {
+    pub fn new() -> Self {
+        Self {
+            origin: Instant::now(),
+            durations: Default::default(),
+            events: RefCell::new(Vec::new()),
+            modules: Vec::new(),
+            stores: Vec::new(),
+        }
+    }
+
+    /// Adds the time elapsed since `start` to the duration of `phase`.
+    pub fn record(&self, phase: Phase, start: Instant) {
+        let elapsed = start.elapsed();
+        let duration = &self.durations[phase as usize];
+        duration.set(duration.get() + elapsed);
+        self.push_event(phase.name(), "phase", None, start, elapsed);
+    }
+
+    /// Records a pass over the MIR which started at `start`, either run over a single function
+    /// or over the whole program.
+    pub fn record_pass(&self, pass: &'static str, function: Option<&str>, start: Instant) {
+        let function = function.map(String::from);
+        self.push_event(pass, "pass", function, start, start.elapsed());
+    }
+
+    /// Adds the statistics of a store to the ones of its kind.
+    pub fn record_store(&mut self, kind: &'static str, stats: StoreStats) {
+        match self.stores.iter_mut().find(|(k, _)| *k == kind) {
+            Some((_, total)) => *total += stats,
+            None => self.stores.push((kind, stats)),
+        }
+    }
+
+    /// Returns the recorded events, in the order they ended.
+    pub fn events(&self) -> Vec<Event> {
+        self.events.borrow().clone()
+    }
+
+    /// Returns the duration of each phase, in order.
+    pub fn phases(&self) -> Vec<(Phase, Duration)> {
+        Phase::ALL
+            .iter()
+            .map(|phase| (*phase, self.durations[*phase as usize].get()))
+            .collect()
+    }
+
+    /// Returns the sum of the durations of all phases.
+    pub fn total(&self) -> Duration {
+        self.durations.iter().map(Cell::get).sum()
+    }
+
+    fn push_event(
+        &self,
+        name: &'static str,
+        category: &'static str,
+        function: Option<String>,
+        start: Instant,
+        duration: Duration,
+    ) {
+        self.events.borrow_mut().push(Event {
+            name,
+            category,
+            function,
+            start: start.saturating_duration_since(self.origin),
+            duration,
+        });
+    }
+}
+
+impl Default for Timings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
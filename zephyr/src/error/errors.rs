@@ -2,6 +2,8 @@ use std::cmp::Ordering;
 
 use crate::resolver::FileId;
 
+/// A span of source code, `pos` and `len` are expressed in bytes of the UTF-8 source. Lines and
+/// columns can be recovered with a `LineIndex`.
 #[derive(Debug, Copy, Clone, Ord, Eq, PartialEq, PartialOrd)]
 pub struct Location {
     pub pos: u32,
@@ -15,6 +17,7 @@ pub struct Error {
     pub message: String,
 }
 
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum Level {
     /// The compilation failed.
     Error,
@@ -1,12 +1,19 @@
 use super::errors::{Level, Location};
-use crate::resolver::FileId;
-
+use super::lints::{Lint, LintLevel};
+use super::suggestions::Suggestion;
+
+/// A sink collecting the diagnostics of the compiler.
+///
+/// Handlers do not own the code of the files, which is stored in a shared `SourceMap`. A pass
+/// can therefore collect its diagnostics in a handler of its own, obtained with `sink`, and merge
+/// it back once done: passes working on different files do not need to share a handler.
 pub trait ErrorHandler {
-    fn new(code: String, f_id: FileId) -> Self;
-    fn new_no_file() -> Self;
-    fn get_file(&self, f_id: FileId) -> Option<&str>;
+    /// Returns an empty handler, with the same configuration, whose diagnostics are meant to be
+    /// merged back into this one.
+    fn sink(&self) -> Self;
     fn has_error(&self) -> bool;
     fn silent_report(&mut self);
+    /// Takes ownership of the diagnostics of another handler.
     fn merge(&mut self, other: Self);
     fn flush(&mut self);
 
@@ -29,6 +36,53 @@ pub trait ErrorHandler {
         self.log(message, Level::Error, Some(loc));
     }
 
+    /// Report an error along with a note pointing at a related location, such as the declaration
+    /// explaining why the error occurred.
+    ///
+    /// Handlers that can not display notes only report the error.
+    fn report_with_note(
+        &mut self,
+        loc: Location,
+        message: String,
+        note_loc: Location,
+        note: String,
+    ) {
+        let _ = (note_loc, note);
+        self.report(loc, message);
+    }
+
+    /// Report an error along with a suggestion to fix it.
+    ///
+    /// Handlers that can not display suggestions only report the error.
+    fn report_with_suggestion(&mut self, loc: Location, message: String, suggestion: Suggestion) {
+        let _ = suggestion;
+        self.report(loc, message);
+    }
+
+    /// Log a lint, that is a warning which the user may silence or turn into an error.
+    ///
+    /// Lints are reported as warnings by default, unless they are allowed by default.
+    fn lint(&mut self, lint: Lint, loc: Location, message: String) {
+        if lint.default_level() != LintLevel::Allow {
+            self.warn(loc, message);
+        }
+    }
+
+    /// Log a lint along with a note pointing at a related location.
+    ///
+    /// Handlers that can not display notes only log the lint.
+    fn lint_with_note(
+        &mut self,
+        lint: Lint,
+        loc: Location,
+        message: String,
+        note_loc: Location,
+        note: String,
+    ) {
+        let _ = (note_loc, note);
+        self.lint(lint, loc, message);
+    }
+
     fn report_internal(&mut self, loc: Location, message: String) {
         self.log(message, Level::Internal, Some(loc));
     }
@@ -57,17 +111,15 @@ pub struct DummyHandler {
     has_error: bool,
 }
 
-impl ErrorHandler for DummyHandler {
-    fn new(_code: String, _f_id: FileId) -> Self {
-        Self { has_error: false }
-    }
-
-    fn new_no_file() -> Self {
+impl DummyHandler {
+    pub fn new() -> Self {
         Self { has_error: false }
     }
+}
 
-    fn get_file(&self, _f_id: FileId) -> Option<&str> {
-        None
+impl ErrorHandler for DummyHandler {
+    fn sink(&self) -> Self {
+        Self::new()
     }
 
     fn has_error(&self) -> bool {
@@ -0,0 +1,123 @@
+use std::borrow::Cow;
+
+/// Maps the byte offsets used by locations to lines and columns of a source file.
+///
+/// Lines and columns are 0-based, columns are counted in chars unless stated otherwise.
+pub struct LineIndex<'code> {
+    code: &'code str,
+    /// Byte offset of the beginning of each line.
+    starts: Cow<'code, [u32]>,
+}
+
+impl<'code> LineIndex<'code> {
+    pub fn new(code: &'code str) -> Self {
+        let starts = Cow::Owned(line_starts(code));
+        LineIndex { code, starts }
+    }
+
+    /// Creates an index from the line starts computed by `line_starts`.
+    pub(super) fn with_starts(code: &'code str, starts: &'code [u32]) -> Self {
+        let starts = Cow::Borrowed(starts);
+        LineIndex { code, starts }
+    }
+
+    /// Returns the line containing the byte offset `pos`, offsets past the end of the code are
+    /// attributed to the last line.
+    pub fn line_of(&self, pos: u32) -> usize {
+        match self.starts.binary_search(&pos) {
+            Ok(line) => line,
+            Err(next_line) => next_line - 1,
+        }
+    }
+
+    /// Returns the content of a line, without its line break.
+    pub fn line(&self, line: usize) -> &'code str {
+        let (start, end) = self.bounds(line);
+        &self.code[start..end]
+    }
+
+    /// Returns the line and column of a byte offset.
+    pub fn line_col(&self, pos: u32) -> (usize, usize) {
+        let line = self.line_of(pos);
+        (line, self.prefix(line, pos).chars().count())
+    }
+
+    /// Returns the line and column of a byte offset, with the column counted in UTF-16 code
+    /// units as expected by editors.
+    pub fn line_col_utf16(&self, pos: u32) -> (usize, usize) {
+        let line = self.line_of(pos);
+        let col = self.prefix(line, pos).chars().map(char::len_utf16).sum();
+        (line, col)
+    }
+
+    /// Returns the byte offset of a line and a column counted in UTF-16 code units, the column
+    /// is clamped to the end of the line.
+    pub fn offset_utf16(&self, line: usize, col: usize) -> u32 {
+        if line >= self.starts.len() {
+            return self.code.len() as u32;
+        }
+        let (start, end) = self.bounds(line);
+        let mut current_col = 0;
+        for (idx, c) in self.code[start..end].char_indices() {
+            if current_col >= col {
+                return (start + idx) as u32;
+            }
+            current_col += c.len_utf16();
+        }
+        end as u32
+    }
+
+    /// Returns the byte range of a line, excluding its line break.
+    fn bounds(&self, line: usize) -> (usize, usize) {
+        let start = self.starts[line] as usize;
+        let end = match self.starts.get(line + 1) {
+            Some(next) => *next as usize - 1,
+            None => self.code.len(),
+        };
+        (start, end)
+    }
+
+    /// Returns the part of `line` preceding the byte offset `pos`.
+    fn prefix(&self, line: usize, pos: u32) -> &'code str {
+        let (start, end) = self.bounds(line);
+        let pos = std::cmp::min(pos as usize, end);
+        self.code.get(start..pos).unwrap_or("")
+    }
+}
+
+/// Returns the byte offset of the beginning of each line.
+pub(super) fn line_starts(code: &str) -> Vec<u32> {
+    let mut starts = vec![0];
+    for (idx, b) in code.bytes().enumerate() {
+        if b == b'\n' {
+            starts.push(idx as u32 + 1);
+        }
+    }
+    starts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_and_columns() {
+        let code = "let a = 1\nlet 😀 = é\n";
+        let index = LineIndex::new(code);
+        assert_eq!(index.line(0), "let a = 1");
+        assert_eq!(index.line(1), "let 😀 = é");
+        assert_eq!(index.line(2), "");
+
+        let emoji = code.find('😀').unwrap() as u32;
+        let accent = code.find('é').unwrap() as u32;
+        assert_eq!(index.line_col(4), (0, 4));
+        assert_eq!(index.line_col(emoji), (1, 4));
+        assert_eq!(index.line_col(accent), (1, 8));
+        assert_eq!(index.line_col_utf16(accent), (1, 9));
+        assert_eq!(index.line_col(code.len() as u32), (2, 0));
+
+        assert_eq!(index.offset_utf16(1, 4), emoji);
+        assert_eq!(index.offset_utf16(1, 9), accent);
+        assert_eq!(index.offset_utf16(0, 42), 9); // Clamped to the end of the line.
+    }
+}
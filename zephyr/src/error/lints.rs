@@ -0,0 +1,71 @@
+use std::fmt;
+
+/// A kind of warning which can be toggled by the user.
+#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq)]
+pub enum Lint {
+    /// A local variable that is never read.
+    UnusedVariable,
+    /// A private function that is never called.
+    UnusedFunction,
+    /// A module imported with `use` that is never referenced.
+    UnusedImport,
+    /// A statement that can never be executed.
+    UnreachableCode,
+    /// A number literal whose type could not be inferred and falls back to the default one.
+    ImplicitDefault,
+    /// A variable shadowing a declaration from an enclosing scope.
+    Shadowing,
+}
+
+/// How a lint is reported.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum LintLevel {
+    /// The lint is silenced.
+    Allow,
+    /// The lint is reported as a warning.
+    Warn,
+    /// The lint is reported as an error.
+    Deny,
+}
+
+impl Lint {
+    pub const ALL: [Lint; 6] = [
+        Lint::UnusedVariable,
+        Lint::UnusedFunction,
+        Lint::UnusedImport,
+        Lint::UnreachableCode,
+        Lint::ImplicitDefault,
+        Lint::Shadowing,
+    ];
+
+    /// The name used to refer to the lint from the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            Lint::UnusedVariable => "unused-variable",
+            Lint::UnusedFunction => "unused-function",
+            Lint::UnusedImport => "unused-import",
+            Lint::UnreachableCode => "unreachable-code",
+            Lint::ImplicitDefault => "implicit-default",
+            Lint::Shadowing => "shadowing",
+        }
+    }
+
+    /// Returns the lint with the given name, if any.
+    pub fn from_name(name: &str) -> Option<Lint> {
+        Lint::ALL.iter().copied().find(|lint| lint.name() == name)
+    }
+
+    /// The level of the lint, unless configured otherwise.
+    pub fn default_level(self) -> LintLevel {
+        match self {
+            Lint::ImplicitDefault | Lint::Shadowing => LintLevel::Allow,
+            _ => LintLevel::Warn,
+        }
+    }
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
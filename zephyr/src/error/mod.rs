@@ -1,6 +1,15 @@
 mod errors;
 mod handler;
+mod line_index;
+mod lints;
+mod source_map;
+mod suggestions;
 
 pub use errors::{Level, Location};
 pub use handler::ErrorHandler;
+pub use line_index::LineIndex;
+pub use lints::{Lint, LintLevel};
+pub use source_map::{SourceFile, SourceMap};
+pub use suggestions::Suggestion;
 pub(crate) use handler::DummyHandler;
+pub(crate) use suggestions::similar_name;
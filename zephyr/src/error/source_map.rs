@@ -0,0 +1,83 @@
+use super::line_index::{line_starts, LineIndex};
+use crate::resolver::FileId;
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+/// The code of a source file, along with the start of its lines.
+pub struct SourceFile {
+    pub f_id: FileId,
+    code: String,
+    line_starts: Vec<u32>,
+}
+
+impl SourceFile {
+    pub fn new(f_id: FileId, code: String) -> Self {
+        let line_starts = line_starts(&code);
+        Self {
+            f_id,
+            code,
+            line_starts,
+        }
+    }
+
+    pub fn code(&self) -> &str {
+        &self.code
+    }
+
+    /// Returns an index of the lines of the file, computed once when the file is added.
+    pub fn line_index(&self) -> LineIndex<'_> {
+        LineIndex::with_starts(&self.code, &self.line_starts)
+    }
+}
+
+/// The source files of a compilation, shared by the passes and the error handlers which only need
+/// the code to display diagnostics.
+///
+/// Files can not be modified once added. Cloning a source map is cheap and the clones share the
+/// same files, they can be sent to other threads.
+#[derive(Clone, Default)]
+pub struct SourceMap {
+    files: Arc<RwLock<HashMap<FileId, Arc<SourceFile>>>>,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a file to the map, replacing the file with the same ID if any.
+    pub fn add(&self, f_id: FileId, code: String) -> Arc<SourceFile> {
+        let file = Arc::new(SourceFile::new(f_id, code));
+        let mut files = self.files.write().unwrap_or_else(|e| e.into_inner());
+        files.insert(f_id, Arc::clone(&file));
+        file
+    }
+
+    pub fn get(&self, f_id: FileId) -> Option<Arc<SourceFile>> {
+        let files = self.files.read().unwrap_or_else(|e| e.into_inner());
+        files.get(&f_id).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shared_files() {
+        let sources = SourceMap::new();
+        let clone = sources.clone();
+        let file = sources.add(FileId(1), String::from("let a = 1\nlet b = 2\n"));
+        assert_eq!(file.line_index().line(1), "let b = 2");
+
+        // Files added from another thread are visible from all the clones
+        std::thread::spawn(move || {
+            clone.add(FileId(2), String::from("fun main() {}"));
+        })
+        .join()
+        .unwrap();
+        assert_eq!(sources.get(FileId(2)).map(|f| f.f_id), Some(FileId(2)));
+        assert!(sources.get(FileId(3)).is_none());
+    }
+}
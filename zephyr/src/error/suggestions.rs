@@ -0,0 +1,74 @@
+use super::errors::Location;
+
+/// A fix for an error: the code spanned by `loc` is replaced by `replacement`. Insertions are
+/// expressed with an empty location.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Suggestion {
+    pub loc: Location,
+    pub replacement: String,
+    /// Describes the fix, such as "did you mean 'foo'?".
+    pub message: String,
+}
+
+impl Suggestion {
+    /// Suggests to replace a misspelled identifier by a similar one.
+    pub fn typo(loc: Location, ident: &str) -> Self {
+        Self {
+            loc,
+            replacement: ident.to_owned(),
+            message: format!("did you mean '{}'?", ident),
+        }
+    }
+}
+
+/// Returns the candidate closest to `ident`, if any is close enough to be a likely typo. A
+/// candidate sharing no character with `ident`, such as `x` for `y`, is never suggested.
+///
+/// Candidates at the same distance are ordered alphabetically for the suggestion to be stable.
+pub fn similar_name<'a>(ident: &str, candidates: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    let len = ident.chars().count();
+    let max_distance = std::cmp::max(1, len / 3).min(len.saturating_sub(1));
+    candidates
+        .filter(|candidate| *candidate != ident)
+        .map(|candidate| (edit_distance(ident, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+/// The Levenshtein distance between two strings, in characters.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut distances: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut previous = distances[0];
+        distances[0] = i + 1;
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous + if a_char == *b_char { 0 } else { 1 };
+            previous = distances[j + 1];
+            distances[j + 1] = substitution.min(distances[j] + 1).min(previous + 1);
+        }
+    }
+    distances[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn similar_names() {
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("abc", "abc"), 0);
+
+        let candidates = ["counter", "count", "main", "x"];
+        let similar = |ident| similar_name(ident, candidates.iter().copied());
+        assert_eq!(similar("cont"), Some("count"));
+        assert_eq!(similar("counte"), Some("count"));
+        assert_eq!(similar("mian"), None);
+        assert_eq!(similar("man"), Some("main"));
+        assert_eq!(similar("main"), None);
+        assert_eq!(similar("y"), None);
+    }
+}
@@ -5,7 +5,7 @@ use super::names::{
 };
 use super::type_check::TypeChecker;
 use crate::error::{ErrorHandler, Location};
-use crate::mir::Value as MirValue;
+use crate::mir::{Type as MirType, Value as MirValue};
 
 use std::collections::HashMap;
 use std::fmt;
@@ -16,6 +16,7 @@ enum Type {
     I64,
     F32,
     F64,
+    V128,
 }
 
 pub struct AsmValidator<'err, 'a, 'ctx, 'ty, E: ErrorHandler> {
@@ -58,14 +59,19 @@ impl<'err, 'a, 'ctx, 'ty, E: ErrorHandler> AsmValidator<'err, 'a, 'ctx, 'ty, E>
             _ => return Ok(()),
         };
         // TODO: skip type checking if 'unreachable' is found.
-        let stack = self.interprete(stmts)?;
+        let stack = match self.interprete(stmts)? {
+            Some(stack) => stack,
+            None => return Ok(()),
+        };
         let return_type = self.get_fun_type(fun)?;
 
         if let Some(return_type) = return_type {
             if let Some(actual_return_type) = stack.last() {
                 if &return_type != actual_return_type {
+                    // Point at the instruction leaving the value on the stack, if any
+                    let loc = stmts.last().map(|stmt| stmt.get_loc()).unwrap_or(fun.loc);
                     self.err.report(
-                        fun.loc,
+                        loc,
                         format!(
                             "Wrong return type: expected {} got {}.",
                             return_type, actual_return_type
@@ -85,9 +91,10 @@ impl<'err, 'a, 'ctx, 'ty, E: ErrorHandler> AsmValidator<'err, 'a, 'ctx, 'ty, E>
         Ok(())
     }
 
-    /// Interprete the assembly using an abstract stack and return it.
+    /// Interprete the assembly using an abstract stack and return it, or `None` if the stack can
+    /// not be tracked because of raw instructions.
     /// Raise an error in case of stack malformation.
-    fn interprete(&mut self, stmts: &Vec<AsmStatement>) -> Result<Vec<Type>, ()> {
+    fn interprete(&mut self, stmts: &Vec<AsmStatement>) -> Result<Option<Vec<Type>>, ()> {
         let mut stack = Vec::new();
         for stmt in stmts {
             match stmt {
@@ -96,11 +103,13 @@ impl<'err, 'a, 'ctx, 'ty, E: ErrorHandler> AsmValidator<'err, 'a, 'ctx, 'ty, E>
                     MirValue::I64(_) => stack.push(Type::I64),
                     MirValue::F32(_) => stack.push(Type::F32),
                     MirValue::F64(_) => stack.push(Type::F64),
+                    MirValue::V128(_) => stack.push(Type::V128),
                     MirValue::DataPointer(_) => stack.push(Type::I32),
+                    MirValue::TableIndex(_) => stack.push(Type::I32),
                 },
                 AsmStatement::Control { cntrl, .. } => match cntrl {
-                    AsmControl::Return => return Ok(stack),
-                    AsmControl::Unreachable => return Ok(stack), // TODO: add an "unreachable" flag
+                    AsmControl::Return => return Ok(Some(stack)),
+                    AsmControl::Unreachable => return Ok(Some(stack)), // TODO: add an "unreachable" flag
                 },
                 AsmStatement::Parametric { param, loc } => match param {
                     AsmParametric::Drop => self.drop(&mut stack, loc),
@@ -163,10 +172,32 @@ impl<'err, 'a, 'ctx, 'ty, E: ErrorHandler> AsmValidator<'err, 'a, 'ctx, 'ty, E>
                         self.pop_t(&mut stack, Type::I32, loc);
                         self.pop_t(&mut stack, Type::I32, loc);
                     }
+                    AsmMemory::V128Load { .. } => {
+                        self.pop_t(&mut stack, Type::I32, loc);
+                        stack.push(Type::V128);
+                    }
+                    AsmMemory::V128Store { .. } => {
+                        self.pop_t(&mut stack, Type::V128, loc);
+                        self.pop_t(&mut stack, Type::I32, loc);
+                    }
                 },
+                AsmStatement::Simd { simd, loc } => {
+                    for t in simd.get_params().into_iter().rev() {
+                        self.pop_t(&mut stack, t.into(), loc);
+                    }
+                    stack.push(simd.get_t().into());
+                }
+                AsmStatement::Atomic { atomic, loc } => {
+                    for t in atomic.get_params().into_iter().rev() {
+                        self.pop_t(&mut stack, t.into(), loc);
+                    }
+                    stack.extend(atomic.get_t().into_iter().map(Type::from));
+                }
+                // The effect of raw bytes on the stack is unknown
+                AsmStatement::Raw { .. } => return Ok(None),
             }
         }
-        Ok(stack)
+        Ok(Some(stack))
     }
 
     /// Drop the value on top of the stack. Raise an error if no value is found.
@@ -211,6 +242,7 @@ impl<'err, 'a, 'ctx, 'ty, E: ErrorHandler> AsmValidator<'err, 'a, 'ctx, 'ty, E>
             HirType::Scalar(HirScalar::F32) => Ok(Type::F32),
             HirType::Scalar(HirScalar::F64) => Ok(Type::F64),
             HirType::Scalar(HirScalar::Bool) => Ok(Type::I32),
+            HirType::Scalar(HirScalar::V128) => Ok(Type::V128),
             _ => {
                 self.err
                     .report(*loc, String::from("Invalid type in assembly function."));
@@ -250,6 +282,7 @@ impl<'err, 'a, 'ctx, 'ty, E: ErrorHandler> AsmValidator<'err, 'a, 'ctx, 'ty, E>
             HirScalar::F32 => Ok(Some(Type::F32)),
             HirScalar::F64 => Ok(Some(Type::F64)),
             HirScalar::Bool => Ok(Some(Type::I32)),
+            HirScalar::V128 => Ok(Some(Type::V128)),
             HirScalar::Null => Ok(None),
         }
     }
@@ -262,6 +295,19 @@ impl fmt::Display for Type {
             Type::I64 => write!(f, "i64"),
             Type::F32 => write!(f, "f32"),
             Type::F64 => write!(f, "f64"),
+            Type::V128 => write!(f, "v128"),
+        }
+    }
+}
+
+impl From<MirType> for Type {
+    fn from(t: MirType) -> Self {
+        match t {
+            MirType::I32 => Type::I32,
+            MirType::I64 => Type::I64,
+            MirType::F32 => Type::F32,
+            MirType::F64 => Type::F64,
+            MirType::V128 => Type::V128,
         }
     }
 }
@@ -1,15 +1,17 @@
+use super::flow::ControlFlow;
 use super::hir::*;
 use super::names::{
-    Block as NameBlock, Body as NameBody, Expression as Expr, FunId, Function as NameFun,
-    FunctionPrototype as NameFunProto, Imports as NameImports, NameStore, ResolvedProgram,
-    Statement as S, Struct as NameStruct, TypeVar, Value as V, ValueDeclaration,
+    Block as NameBlock, Body as NameBody, DeclaredProgram, Expression as Expr, FunId,
+    Function as NameFun, FunctionPrototype as NameFunProto, Imports as NameImports, NameStore,
+    ResolvedProgram, Statement as S, Struct as NameStruct, TypeVar, Value as V, ValueDeclaration,
     Variable as NameVariable,
 };
 use super::store::Store;
+use super::symbols::Symbol;
 use super::type_check::TypeChecker;
 
 use crate::ast::{BinaryOperator as ASTBinop, UnaryOperator as ASTUnop};
-use crate::ctx::{ModId, ModuleDeclarations};
+use crate::ctx::{KnownValues, ModId, ModuleDeclarations};
 use crate::error::{ErrorHandler, Location};
 
 use std::collections::HashMap;
@@ -18,6 +20,12 @@ struct State<'checker, 'ty> {
     pub names: NameStore,
     pub fun_types: HashMap<FunId, TypeVar>,
     pub checker: &'checker mut TypeChecker<'ty, 'ty>,
+    /// The expressions deferred by the blocks enclosing the current statement, innermost last.
+    pub defers: Vec<Vec<Expr>>,
+    /// The return type of the function being lowered.
+    pub ret_t: Type,
+    /// Locals introduced while lowering the function, such as the ones holding returned values.
+    pub fresh_locals: Vec<LocalVariable>,
 }
 
 impl<'checker, 'ty> State<'checker, 'ty> {
@@ -30,36 +38,61 @@ impl<'checker, 'ty> State<'checker, 'ty> {
             names,
             fun_types,
             checker,
+            defers: Vec::new(),
+            ret_t: Type::Scalar(ScalarType::Null),
+            fresh_locals: Vec::new(),
         }
     }
 }
 
 pub struct HirProducer<'a, E: ErrorHandler> {
     err: &'a mut E,
+    known_values: &'a KnownValues,
+    flow: ControlFlow,
 }
 
 impl<'a, E: ErrorHandler> HirProducer<'a, E> {
-    pub fn new(error_handler: &'a mut E) -> Self {
-        Self { err: error_handler }
+    pub fn new(error_handler: &'a mut E, known_values: &'a KnownValues) -> Self {
+        Self {
+            err: error_handler,
+            known_values,
+            flow: ControlFlow::new(known_values),
+        }
     }
 
     /// Lower a typed program to HIR
-    pub fn reduce(&mut self, prog: ResolvedProgram, mut checker: TypeChecker<'a, 'a>) -> Program {
+    pub fn reduce(
+        &mut self,
+        prog: ResolvedProgram,
+        mut checker: TypeChecker<'a, 'a>,
+        symbols: Vec<Symbol>,
+    ) -> Program {
         let mut state = State::new(prog.names, prog.fun_types, &mut checker);
         let mut funs = Vec::with_capacity(prog.funs.len());
         let mut imports = Vec::with_capacity(prog.imports.len());
 
         for fun in prog.funs {
+            let loc = fun.loc;
+            let fall_through = match &fun.body {
+                NameBody::Zephyr(block) => self.flow.fall_through(block, loc),
+                NameBody::Asm(_) => None,
+            };
             match self.reduce_fun(fun, &mut state) {
-                Ok(fun) => funs.push(fun),
-                Err(err) => self.err.report_internal_no_loc(err),
+                Ok(fun) => {
+                    if let Some(fall_through) = fall_through {
+                        self.check_returns(&fun, fall_through);
+                    }
+                    funs.push(fun)
+                }
+                Err(err) => self.err.report_internal(loc, err),
             }
         }
 
         for import in prog.imports {
+            let loc = import.loc;
             match self.reduce_import(import, &mut state) {
                 Ok(proto) => imports.push(proto),
-                Err(err) => self.err.report_internal_no_loc(err),
+                Err(err) => self.err.report_internal(loc, err),
             }
         }
 
@@ -72,45 +105,116 @@ impl<'a, E: ErrorHandler> HirProducer<'a, E> {
                     None
                 }
             });
-        let pub_decls = self.get_pub_decls(prog.module.id, &funs, &imports, &structs);
+        let pub_decls = self.get_pub_decls(
+            prog.module.id,
+            funs.iter().map(|fun| (&fun.ident, fun.fun_id, fun.is_pub)),
+            &imports,
+            &structs,
+        );
 
         Program {
             funs,
+            inits: prog.inits,
+            memory: prog.memory,
+            exports: prog.exports,
             imports,
             structs,
             pub_decls,
             tuples: checker.get_tuples(),
             data: prog.data,
+            symbols,
+            nodes: prog.nodes,
+            scopes: prog.scopes,
             module: prog.module,
         }
     }
 
+    /// Lower the declarations of a typed program to HIR, see `reduce`.
+    pub fn reduce_declarations(
+        &mut self,
+        prog: DeclaredProgram,
+        mut checker: TypeChecker<'a, 'a>,
+    ) -> Declarations {
+        let mut state = State::new(NameStore::new(), prog.fun_types, &mut checker);
+        let mut funs = Vec::with_capacity(prog.funs.len());
+        let mut imports = Vec::with_capacity(prog.imports.len());
+
+        for fun in prog.funs {
+            match self.fun_t(fun.fun_id, &mut state) {
+                Ok(t) => funs.push(FunctionPrototype {
+                    ident: fun.ident,
+                    alias: None,
+                    is_pub: fun.is_pub,
+                    loc: fun.loc,
+                    fun_id: fun.fun_id,
+                    t,
+                }),
+                Err(err) => self.err.report_internal(fun.loc, err),
+            }
+        }
+
+        for import in prog.imports {
+            let loc = import.loc;
+            match self.reduce_import(import, &mut state) {
+                Ok(proto) => imports.push(proto),
+                Err(err) => self.err.report_internal(loc, err),
+            }
+        }
+
+        let structs = prog
+            .structs
+            .transmute(|struc| match self.reduce_struct(struc, &mut state) {
+                Ok(s) => Some(s),
+                Err(err) => {
+                    self.err.report_internal_no_loc(err);
+                    None
+                }
+            });
+        let pub_decls = self.get_pub_decls(
+            prog.module.id,
+            funs.iter().map(|fun| (&fun.ident, fun.fun_id, fun.is_pub)),
+            &imports,
+            &structs,
+        );
+        for import in imports {
+            funs.extend(import.prototypes);
+        }
+
+        Declarations {
+            funs,
+            structs,
+            tuples: checker.get_tuples(),
+            pub_decls,
+        }
+    }
+
     /// Returns the public declarations of the module, this include public functions and
     /// imported runtime module.
-    fn get_pub_decls(
+    fn get_pub_decls<'f>(
         &mut self,
         mod_id: ModId,
-        funs: &Vec<Function>,
+        funs: impl Iterator<Item = (&'f String, FunId, bool)>,
         imports: &Vec<Imports>,
         structs: &Store<StructId, Struct>,
     ) -> ModuleDeclarations {
         let mut pub_decls = ModuleDeclarations::new(mod_id);
-        for fun in funs {
-            if fun.is_pub {
+        for (ident, fun_id, is_pub) in funs {
+            if is_pub {
                 pub_decls
                     .val_decls
-                    .insert(fun.ident.clone(), ValueDeclaration::Function(fun.fun_id));
+                    .insert(ident.clone(), ValueDeclaration::Function(fun_id));
             }
         }
         for import in imports {
             pub_decls.runtime_modules.insert(import.from.clone());
             for fun in &import.prototypes {
+                let ident = fun.alias.as_ref().unwrap_or(&fun.ident);
                 pub_decls
                     .val_decls
-                    .insert(fun.ident.clone(), ValueDeclaration::Function(fun.fun_id));
+                    .insert(ident.clone(), ValueDeclaration::Function(fun.fun_id));
             }
         }
-        for (s_id, s) in structs.iter() {
+        for (s_id, s) in structs.iter_sorted() {
             if s.is_pub {
                 pub_decls
                     .type_decls
@@ -135,29 +239,63 @@ impl<'a, E: ErrorHandler> HirProducer<'a, E> {
             _ => return Err(String::from("Function does not have function type")),
         };
         let params = fun.params.iter().map(|p| p.n_id).collect();
-        let locals = self.get_locals(&fun, s)?;
+        let mut locals = self.get_locals(&fun.locals, s)?;
+        let captures = match &fun.captures {
+            Some(captures) => Some(self.get_locals(captures, s)?),
+            None => None,
+        };
+        s.defers.clear();
+        s.ret_t = (*t.ret).clone();
         let body = match fun.body {
             NameBody::Zephyr(block) => Body::Zephyr(self.reduce_block(block, s)?),
             NameBody::Asm(stmts) => Body::Asm(stmts),
         };
+        locals.append(&mut s.fresh_locals);
 
         Ok(Function {
             ident: fun.ident,
             params,
             locals,
+            captures,
             body,
             t,
             loc: fun.loc,
+            keyword_loc: fun.keyword_loc,
+            span: fun.span,
             is_pub: fun.is_pub,
+            inline: fun.inline,
+            is_test: fun.is_test,
+            is_entry: fun.is_entry,
             exposed: fun.exposed,
             fun_id: fun.fun_id,
         })
     }
 
-    /// Return a vector of local variables given a function name.
-    fn get_locals(&mut self, fun: &NameFun, s: &mut State) -> Result<Vec<LocalVariable>, String> {
+    /// Reports functions returning a value through which a path falls off the end of the body
+    /// without reaching a return, `fall_through` is the last statement executed by that path.
+    fn check_returns(&mut self, fun: &Function, fall_through: Location) {
+        if matches!(*fun.t.ret, Type::Scalar(ScalarType::Null)) {
+            return;
+        }
+        self.err.report_with_note(
+            fall_through,
+            format!(
+                "Missing return, function '{}' must return a value of type {} on every path",
+                fun.ident, fun.t.ret
+            ),
+            fun.loc,
+            format!("function '{}' declared here", fun.ident),
+        );
+    }
+
+    /// Return a vector of local variables given their names.
+    fn get_locals(
+        &mut self,
+        names: &[NameId],
+        s: &mut State,
+    ) -> Result<Vec<LocalVariable>, String> {
         let mut locals = Vec::new();
-        for local_name in &fun.locals {
+        for local_name in names {
             let local = s.names.get(*local_name);
             let t_var = local.t_var;
             let loc = local.loc;
@@ -175,31 +313,45 @@ impl<'a, E: ErrorHandler> HirProducer<'a, E> {
         Ok(locals)
     }
 
+    /// Lower a block, the expressions it defers are executed when falling through its end.
     fn reduce_block(&mut self, block: NameBlock, s: &mut State) -> Result<Block, String> {
         let mut stmts = Vec::new();
+        s.defers.push(Vec::new());
         for stmt in block.stmts {
-            stmts.push(self.reduce_stmt(stmt, s)?);
+            self.reduce_stmt(stmt, &mut stmts, s)?;
+        }
+        let deferred = s.defers.pop().unwrap_or_default();
+        if !matches!(stmts.last(), Some(Statement::ReturnStmt { .. })) {
+            for expr in deferred.into_iter().rev() {
+                stmts.push(Statement::ExprStmt(self.reduce_expr(expr, s)?));
+            }
         }
         let reduced_block = Block { stmts };
         Ok(reduced_block)
     }
 
-    fn reduce_stmt(&mut self, stmt: S, s: &mut State) -> Result<Statement, String> {
-        match stmt {
+    /// Lower a statement, pushing the resulting statements to `stmts`.
+    fn reduce_stmt(
+        &mut self,
+        stmt: S,
+        stmts: &mut Vec<Statement>,
+        s: &mut State,
+    ) -> Result<(), String> {
+        let stmt = match stmt {
             S::AssignStmt { target, expr } => {
                 let expr = self.reduce_expr(expr, s)?;
                 let target = self.reduce_expr(target, s)?;
                 let target = self.as_place(target)?;
-                Ok(Statement::AssignStmt { target, expr })
+                Statement::AssignStmt { target, expr }
             }
             S::LetStmt { var, expr } => {
                 let expr = self.reduce_expr(expr, s)?;
                 let var = self.reduce_var(var, s)?;
-                Ok(Statement::LetStmt { expr, var })
+                Statement::LetStmt { expr, var }
             }
             S::ExprStmt(expr) => {
                 let expr = self.reduce_expr(expr, s)?;
-                Ok(Statement::ExprStmt(expr))
+                Statement::ExprStmt(expr)
             }
             S::ReturnStmt { expr, loc } => {
                 let expr = if let Some(expr) = expr {
@@ -207,12 +359,58 @@ impl<'a, E: ErrorHandler> HirProducer<'a, E> {
                 } else {
                     None
                 };
-                Ok(Statement::ReturnStmt { expr, loc })
+                if s.defers.iter().all(|deferred| deferred.is_empty()) {
+                    stmts.push(Statement::ReturnStmt { expr, loc });
+                    return Ok(());
+                }
+                // The returned value is computed before executing the deferred expressions of all
+                // the enclosing blocks, innermost first.
+                let expr = if let Some(expr) = expr {
+                    let t = s.ret_t.clone();
+                    let ident = String::from("return");
+                    let t_var = s.checker.lift_t(&t);
+                    let n_id = s.names.fresh(ident.clone(), loc, t_var);
+                    s.fresh_locals.push(LocalVariable {
+                        id: n_id,
+                        t: t.clone(),
+                        loc,
+                    });
+                    let var = |t| Variable {
+                        ident: ident.clone(),
+                        loc,
+                        n_id,
+                        t,
+                    };
+                    stmts.push(Statement::LetStmt {
+                        var: var(t.clone()),
+                        expr,
+                    });
+                    Some(Expression::Variable(var(t)))
+                } else {
+                    None
+                };
+                let deferred: Vec<Expr> = s
+                    .defers
+                    .iter()
+                    .rev()
+                    .flat_map(|deferred| deferred.iter().rev())
+                    .cloned()
+                    .collect();
+                for deferred_expr in deferred {
+                    stmts.push(Statement::ExprStmt(self.reduce_expr(deferred_expr, s)?));
+                }
+                Statement::ReturnStmt { expr, loc }
+            }
+            S::DeferStmt { expr, .. } => {
+                if let Some(deferred) = s.defers.last_mut() {
+                    deferred.push(expr);
+                }
+                return Ok(());
             }
             S::WhileStmt { expr, block } => {
                 let expr = self.reduce_expr(expr, s)?;
                 let block = self.reduce_block(block, s)?;
-                Ok(Statement::WhileStmt { expr, block })
+                Statement::WhileStmt { expr, block }
             }
             S::IfStmt {
                 expr,
@@ -226,29 +424,21 @@ impl<'a, E: ErrorHandler> HirProducer<'a, E> {
                 } else {
                     None
                 };
-                Ok(Statement::IfStmt {
+                Statement::IfStmt {
                     expr,
                     block,
                     else_block,
-                })
+                }
             }
-        }
+        };
+        stmts.push(stmt);
+        Ok(())
     }
 
     fn reduce_expr(&mut self, expression: Expr, s: &mut State) -> Result<Expression, String> {
         match expression {
             Expr::Literal(value) => Ok(Expression::Literal(match value {
-                V::Integer { val, t_var, loc } => {
-                    match s
-                        .checker
-                        .get_t(t_var)
-                        .ok_or(format!("Invalid t_id '{}'", t_var))?
-                    {
-                        Type::Scalar(ScalarType::I32) => Value::I32(val as i32, loc),
-                        Type::Scalar(ScalarType::I64) => Value::I64(val as i64, loc),
-                        _ => return Err(String::from("Integer constant of non integer type.")),
-                    }
-                }
+                V::Integer { val, t_var, loc } => self.reduce_integer(val, false, t_var, loc, s)?,
                 V::Float { val, t_var, loc } => match s
                     .checker
                     .get_t(t_var)
@@ -268,7 +458,6 @@ impl<'a, E: ErrorHandler> HirProducer<'a, E> {
                 },
                 V::Str {
                     data_id,
-                    len,
                     loc,
                     t_var,
                 } => match s
@@ -276,24 +465,8 @@ impl<'a, E: ErrorHandler> HirProducer<'a, E> {
                     .get_t(t_var)
                     .ok_or(format!("Invalid t_id '{}'", t_var))?
                 {
-                    Type::Struct(struct_id) => {
-                        let len = FieldValue {
-                            ident: String::from("len"),
-                            expr: Box::new(Expression::Literal(Value::I32(len as i32, loc))),
-                            loc,
-                        };
-                        let start = FieldValue {
-                            ident: String::from("start"),
-                            expr: Box::new(Expression::Literal(Value::DataPointer(data_id, loc))),
-                            loc,
-                        };
-                        let fields = vec![len, start];
-                        Value::Struct {
-                            struct_id,
-                            fields,
-                            loc,
-                        }
-                    }
+                    // String literals are constant structs stored in a data segment
+                    Type::Struct(_) => Value::DataPointer(data_id, loc),
                     _ => return Err(String::from("Str literal of non struct type.")),
                 },
                 V::Struct {
@@ -354,9 +527,14 @@ impl<'a, E: ErrorHandler> HirProducer<'a, E> {
                     t,
                 }))
             }
-            Expr::Function { .. } => Err(String::from(
-                "Function as expression are not yet supported.",
-            )),
+            Expr::Function { fun_id, loc, t_var } => {
+                let t = s
+                    .checker
+                    .get_t(t_var)
+                    .ok_or(format!("Invalid t_id '{}'", t_var))?;
+                let t = t.to_fun().ok_or("Expected a function")?;
+                Ok(Expression::Function { fun_id, t, loc })
+            }
             Expr::Binary {
                 expr_left,
                 binop,
@@ -372,6 +550,14 @@ impl<'a, E: ErrorHandler> HirProducer<'a, E> {
                 let t = t.to_scalar().ok_or("Expected a scalar")?;
                 let expr_left = Box::new(self.reduce_expr(*expr_left, s)?);
                 let expr_right = Box::new(self.reduce_expr(*expr_right, s)?);
+                if let (
+                    ASTBinop::Divide | ASTBinop::Remainder,
+                    Expression::Literal(Value::I32(0, zero_loc) | Value::I64(0, zero_loc)),
+                ) = (&binop, &*expr_right)
+                {
+                    self.err
+                        .report(*zero_loc, String::from("Integer division by zero."));
+                }
                 Ok(Expression::Binary {
                     expr_right,
                     expr_left,
@@ -408,6 +594,13 @@ impl<'a, E: ErrorHandler> HirProducer<'a, E> {
                 op_t_var,
                 loc,
             } => {
+                // Negative integer constants are folded, so that their range can be checked.
+                if let (ASTUnop::Minus, Expr::Literal(V::Integer { val, t_var, .. })) =
+                    (&unop, &*expr)
+                {
+                    let val = self.reduce_integer(*val, true, *t_var, loc, s)?;
+                    return Ok(Expression::Literal(val));
+                }
                 let t = s
                     .checker
                     .get_t(op_t_var)
@@ -422,10 +615,33 @@ impl<'a, E: ErrorHandler> HirProducer<'a, E> {
                             Unop::Not
                         }
                         ASTUnop::Minus => Unop::Neg(self.t_as_numeric(&t, loc)),
+                        ASTUnop::Reference | ASTUnop::Dereference => {
+                            return Err(String::from("Unexpected reference operator"))
+                        }
                     },
                     loc,
                 })
             }
+            Expr::Reference { expr, loc, t_var } => {
+                let t = match s.checker.get_t(t_var) {
+                    Some(Type::Ref(t)) => *t,
+                    _ => return Err(format!("Invalid reference t_var '{}'", t_var)),
+                };
+                let expr = self.reduce_expr(*expr, s)?;
+                Ok(Expression::Reference {
+                    place: Box::new(self.as_place(expr)?),
+                    t,
+                    loc,
+                })
+            }
+            Expr::Dereference { expr, loc, t_var } => {
+                let t = s
+                    .checker
+                    .get_t(t_var)
+                    .ok_or(format!("Invalid t_var '{}'", t_var))?;
+                let expr = Box::new(self.reduce_expr(*expr, s)?);
+                Ok(Expression::Dereference { expr, t, loc })
+            }
             Expr::CallDirect {
                 fun_id,
                 args,
@@ -449,8 +665,111 @@ impl<'a, E: ErrorHandler> HirProducer<'a, E> {
                     args: hir_args,
                 })
             }
-            Expr::CallIndirect { .. } => {
-                Err(String::from("Indirect calls are not yet implemented."))
+            Expr::CallIndirect {
+                fun,
+                args,
+                loc,
+                fun_t_var,
+                ..
+            } => {
+                let t = s
+                    .checker
+                    .get_t(fun_t_var)
+                    .ok_or(format!("Invalid t_id '{}'", fun_t_var))?;
+                let t = t.to_fun().ok_or("Expected a function")?;
+                let fun = Box::new(self.reduce_expr(*fun, s)?);
+                let mut hir_args = Vec::new();
+                for arg in args {
+                    hir_args.push(self.reduce_expr(arg, s)?);
+                }
+                Ok(Expression::CallIndirect {
+                    fun,
+                    t,
+                    loc,
+                    args: hir_args,
+                })
+            }
+            Expr::Closure {
+                fun_id,
+                captures,
+                loc,
+                t_var,
+            } => {
+                let t = s
+                    .checker
+                    .get_t(t_var)
+                    .ok_or(format!("Invalid t_id '{}'", t_var))?;
+                let t = t.to_fun().ok_or("Expected a function")?;
+                let mut hir_captures = Vec::with_capacity(captures.len());
+                for var in captures {
+                    hir_captures.push(self.reduce_var(var, s)?);
+                }
+                Ok(Expression::Closure {
+                    fun_id,
+                    captures: hir_captures,
+                    t,
+                    loc,
+                })
+            }
+            Expr::Format { parts, loc, .. } => {
+                let (funs, structs) = match self.known_values {
+                    KnownValues {
+                        funs: Some(funs),
+                        structs: Some(structs),
+                    } => (funs, structs),
+                    _ => return Err(String::from("Format without the values of the runtime")),
+                };
+                let string_t = Type::Struct(structs.string);
+                let str_t = Type::Struct(structs.str);
+                let mut string = Expression::CallDirect {
+                    fun_id: funs.new_string,
+                    t: FunctionType {
+                        params: vec![],
+                        ret: Box::new(string_t.clone()),
+                    },
+                    args: vec![],
+                    loc,
+                };
+                for (part, t_var) in parts {
+                    let part_t = s
+                        .checker
+                        .get_t(t_var)
+                        .ok_or(format!("Invalid t_var '{}'", t_var))?;
+                    let fun_id = match &part_t {
+                        Type::Scalar(ScalarType::I32) => funs.append_i32,
+                        Type::Scalar(ScalarType::I64) => funs.append_i64,
+                        Type::Scalar(ScalarType::Bool) => funs.append_bool,
+                        Type::Struct(s_id) if *s_id == structs.str => funs.append_str,
+                        _ => {
+                            let t = s.checker.display_t(t_var);
+                            let message = format!(
+                                "Values of type {} can not be formatted, expected i32, i64, bool or Str",
+                                t
+                            );
+                            self.err.report(part.get_loc(), message);
+                            continue;
+                        }
+                    };
+                    let part = self.reduce_expr(part, s)?;
+                    string = Expression::CallDirect {
+                        fun_id,
+                        t: FunctionType {
+                            params: vec![string_t.clone(), part_t],
+                            ret: Box::new(string_t.clone()),
+                        },
+                        args: vec![string, part],
+                        loc,
+                    };
+                }
+                Ok(Expression::CallDirect {
+                    fun_id: funs.as_str,
+                    t: FunctionType {
+                        params: vec![string_t],
+                        ret: Box::new(str_t),
+                    },
+                    args: vec![string],
+                    loc,
+                })
             }
             Expr::Access {
                 expr,
@@ -479,7 +798,8 @@ impl<'a, E: ErrorHandler> HirProducer<'a, E> {
                     Ok(Expression::Access {
                         expr,
                         kind: AccessKind::Tuple {
-                            index: get_tuple_field(&field).unwrap(),
+                            index: get_tuple_field(&field)
+                                .ok_or_else(|| format!("Invalid tuple field '{}'", field))?,
                             tup_id,
                         },
                         t,
@@ -489,10 +809,63 @@ impl<'a, E: ErrorHandler> HirProducer<'a, E> {
                     Err(String::from("Access of a non struct type"))
                 }
             }
+            Expr::Intrinsic {
+                intrinsic,
+                args,
+                loc,
+            } => {
+                let mut hir_args = Vec::new();
+                for arg in args {
+                    hir_args.push(self.reduce_expr(arg, s)?);
+                }
+                Ok(Expression::Intrinsic {
+                    intrinsic,
+                    args: hir_args,
+                    loc,
+                })
+            }
             Expr::Namespace { loc, .. } => Ok(Expression::Nop { loc }),
         }
     }
 
+    /// Lowers an integer constant, raises an error if the constant does not fit in its type.
+    ///
+    /// Constants can be written either as signed or unsigned integers, hence the accepted range
+    /// spans from the smallest signed value to the largest unsigned one.
+    fn reduce_integer(
+        &mut self,
+        val: u64,
+        negative: bool,
+        t_var: TypeVar,
+        loc: Location,
+        s: &mut State,
+    ) -> Result<Value, String> {
+        let t = s
+            .checker
+            .get_t(t_var)
+            .ok_or(format!("Invalid t_id '{}'", t_var))?;
+        let val = if negative {
+            -(val as i128)
+        } else {
+            val as i128
+        };
+        let (min, max) = match t {
+            Type::Scalar(ScalarType::I32) => (i32::MIN as i128, u32::MAX as i128),
+            Type::Scalar(ScalarType::I64) => (i64::MIN as i128, u64::MAX as i128),
+            _ => return Err(String::from("Integer constant of non integer type.")),
+        };
+        if val < min || val > max {
+            self.err.report(
+                loc,
+                format!("Integer constant out of range for {}: {}.", t, val),
+            );
+        }
+        match t {
+            Type::Scalar(ScalarType::I32) => Ok(Value::I32(val as i32, loc)),
+            _ => Ok(Value::I64(val as i64, loc)),
+        }
+    }
+
     fn reduce_var(&self, var: NameVariable, s: &mut State) -> Result<Variable, String> {
         let name = s.names.get(var.n_id);
         let t = s
@@ -524,19 +897,7 @@ impl<'a, E: ErrorHandler> HirProducer<'a, E> {
         proto: NameFunProto,
         s: &mut State,
     ) -> Result<FunctionPrototype, String> {
-        let fun_t_var = *s
-            .fun_types
-            .get(&proto.fun_id)
-            .ok_or(format!("No t_var for fun_id '{}'", proto.fun_id))?;
-        let fun_t = s
-            .checker
-            .get_t(fun_t_var)
-            .ok_or(format!("No type ofr t_id '{}'", fun_t_var))?;
-        let t = match fun_t.to_fun() {
-            Some(t) => t,
-            _ => return Err(String::from("Function does not have function type")),
-        };
-
+        let t = self.fun_t(proto.fun_id, s)?;
         Ok(FunctionPrototype {
             ident: proto.ident,
             alias: proto.alias,
@@ -547,6 +908,22 @@ impl<'a, E: ErrorHandler> HirProducer<'a, E> {
         })
     }
 
+    /// Returns the type of a function declared by the module.
+    fn fun_t(&mut self, fun_id: FunId, s: &mut State) -> Result<FunctionType, String> {
+        let fun_t_var = *s
+            .fun_types
+            .get(&fun_id)
+            .ok_or(format!("No t_var for fun_id '{}'", fun_id))?;
+        let fun_t = s
+            .checker
+            .get_t(fun_t_var)
+            .ok_or(format!("No type ofr t_id '{}'", fun_t_var))?;
+        match fun_t.to_fun() {
+            Some(t) => Ok(t),
+            _ => Err(String::from("Function does not have function type")),
+        }
+    }
+
     fn reduce_struct(&mut self, struc: NameStruct, s: &mut State) -> Result<Struct, String> {
         let mut fields = HashMap::with_capacity(struc.fields.len());
         for (f_name, field) in struc.fields {
@@ -583,6 +960,9 @@ impl<'a, E: ErrorHandler> HirProducer<'a, E> {
                 t,
                 loc,
             }),
+            Expression::Dereference { expr, t, loc } => {
+                Ok(PlaceExpression::Dereference { expr, t, loc })
+            }
             _ => Err(String::from("Expected a place expression")),
         }
     }
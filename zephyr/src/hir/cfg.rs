@@ -0,0 +1,90 @@
+//! Conditional Compilation
+//!
+//! Declarations marked with `#[cfg(key = "value")]` are only compiled if the configuration holds
+//! the `key = "value"` pair (see `Ctx::add_cfg`), all the pairs of the attribute must be set. A
+//! file whose module declaration is marked is compiled only if the pairs are set as well.
+//!
+//! The declarations which are not enabled are removed from the AST before name resolution, they
+//! are neither resolved nor type checked.
+
+use std::collections::HashSet;
+
+use crate::ast;
+use crate::error::ErrorHandler;
+
+/// The `key = "value"` pairs set by the configuration.
+pub type Cfg = HashSet<(String, String)>;
+
+const CFG: &str = "cfg";
+
+/// Removes the declarations of a file which are not enabled, returns `false` if the whole file is
+/// disabled by the attributes of its module declaration.
+///
+/// The `cfg` attributes are removed as well. Other attributes only apply to functions and module
+/// declarations, they are ignored with a warning on other declarations.
+pub fn filter_declarations(
+    program: &mut ast::Program,
+    cfg: &Cfg,
+    err: &mut impl ErrorHandler,
+) -> bool {
+    if !is_enabled(&mut program.attributes, cfg, err) {
+        return false;
+    }
+    program
+        .funs
+        .retain_mut(|fun| is_enabled(&mut fun.attributes, cfg, err));
+    program
+        .structs
+        .retain_mut(|s| is_enabled_decl(&mut s.attributes, cfg, err));
+    program
+        .used
+        .retain_mut(|u| is_enabled_decl(&mut u.attributes, cfg, err));
+    program
+        .exposed
+        .retain_mut(|e| is_enabled_decl(&mut e.attributes, cfg, err));
+    program
+        .imports
+        .retain_mut(|i| is_enabled_decl(&mut i.attributes, cfg, err));
+    program
+        .inits
+        .retain_mut(|i| is_enabled_decl(&mut i.attributes, cfg, err));
+    true
+}
+
+/// Returns true if the `cfg` attributes are satisfied, then removes them from the attributes.
+fn is_enabled(
+    attributes: &mut Vec<ast::Attribute>,
+    cfg: &Cfg,
+    err: &mut impl ErrorHandler,
+) -> bool {
+    let mut is_enabled = true;
+    for attribute in attributes.iter().filter(|attribute| attribute.ident == CFG) {
+        if !attribute.args.is_empty() || attribute.named_args.is_empty() {
+            err.report(
+                attribute.loc,
+                String::from(
+                    "'cfg' expects 'key = \"value\"' pairs, such as 'cfg(target = \"wasi\")'",
+                ),
+            );
+        }
+        is_enabled &= attribute.named_args.iter().all(|pair| cfg.contains(pair));
+    }
+    attributes.retain(|attribute| attribute.ident != CFG);
+    is_enabled
+}
+
+/// Same as `is_enabled` for declarations which do not take other attributes.
+fn is_enabled_decl(
+    attributes: &mut Vec<ast::Attribute>,
+    cfg: &Cfg,
+    err: &mut impl ErrorHandler,
+) -> bool {
+    let is_enabled = is_enabled(attributes, cfg, err);
+    for attribute in attributes.drain(..) {
+        err.warn(
+            attribute.loc,
+            format!("Unknown attribute '{}', it is ignored", attribute.ident),
+        );
+    }
+    is_enabled
+}
@@ -0,0 +1,110 @@
+//! Control Flow
+//!
+//! Finds the statements after which the execution of a block never continues. This is used both
+//! to report the functions whose paths fall off the end of their body without returning a value
+//! and to lint the code which can not be reached.
+
+use super::hir::Intrinsic;
+use super::names::{Block, Expression, FunId, Statement};
+use crate::ctx::KnownValues;
+use crate::error::Location;
+
+/// A statement after which the execution never continues.
+#[derive(Clone, Copy, Debug)]
+pub enum Terminator {
+    Return(Location),
+    /// A call which never returns, such as `panic`.
+    Abort(Location),
+    /// An if statement whose branches all terminate.
+    Branches(Location),
+}
+
+impl Terminator {
+    pub fn loc(self) -> Location {
+        match self {
+            Terminator::Return(loc) | Terminator::Abort(loc) | Terminator::Branches(loc) => loc,
+        }
+    }
+
+    /// Explains why the code following the terminator is unreachable.
+    pub fn note(self) -> &'static str {
+        match self {
+            Terminator::Return(_) => "any code following this return is unreachable",
+            Terminator::Abort(_) => "any code following this call is unreachable",
+            Terminator::Branches(_) => "every branch of this if returns or aborts",
+        }
+    }
+}
+
+pub struct ControlFlow {
+    panic: Option<FunId>,
+}
+
+impl ControlFlow {
+    pub fn new(known_values: &KnownValues) -> Self {
+        Self {
+            panic: known_values.funs.as_ref().map(|funs| funs.panic),
+        }
+    }
+
+    /// Returns the first statement of the block after which the execution never continues, along
+    /// with its index.
+    pub fn terminator(&self, block: &Block) -> Option<(usize, Terminator)> {
+        block
+            .stmts
+            .iter()
+            .enumerate()
+            .find_map(|(idx, stmt)| Some((idx, self.terminates(stmt)?)))
+    }
+
+    /// Returns the location of the last statement executed by a path falling through the end of
+    /// the block, `end` if the block is empty, or `None` if every path ends with a terminator.
+    pub fn fall_through(&self, block: &Block, end: Location) -> Option<Location> {
+        let mut last = end;
+        for stmt in &block.stmts {
+            if self.terminates(stmt).is_some() {
+                return None;
+            }
+            last = match stmt {
+                Statement::IfStmt {
+                    expr,
+                    block,
+                    else_block: Some(else_block),
+                } => {
+                    let loc = expr.get_loc();
+                    self.fall_through(block, loc)
+                        .or_else(|| self.fall_through(else_block, loc))
+                        .unwrap_or(loc)
+                }
+                _ => stmt.get_loc(),
+            };
+        }
+        Some(last)
+    }
+
+    fn terminates(&self, stmt: &Statement) -> Option<Terminator> {
+        match stmt {
+            Statement::ReturnStmt { .. } => Some(Terminator::Return(stmt.get_loc())),
+            Statement::ExprStmt(expr) if self.never_returns(expr) => {
+                Some(Terminator::Abort(expr.get_loc()))
+            }
+            Statement::IfStmt {
+                block,
+                else_block: Some(else_block),
+                ..
+            } if self.terminator(block).is_some() && self.terminator(else_block).is_some() => {
+                Some(Terminator::Branches(stmt.get_loc()))
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether evaluating the expression always aborts the execution.
+    fn never_returns(&self, expr: &Expression) -> bool {
+        match expr {
+            Expression::CallDirect { fun_id, .. } => Some(*fun_id) == self.panic,
+            Expression::Intrinsic { intrinsic, .. } => *intrinsic == Intrinsic::Unreachable,
+            _ => false,
+        }
+    }
+}
@@ -1,13 +1,15 @@
 #![allow(dead_code)] // Call::Indirect
 use super::names::{AsmStatement, DataStore};
 use super::store::Store;
+use super::symbols::{NodeMap, ScopeMap, Symbol};
 use crate::ctx::ModuleDeclarations;
 use crate::error::Location;
+use crate::mir::{RmwOp, Shape};
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 
-pub use super::names::{DataId, FunId, NameId, StructId, TupleId};
+pub use super::names::{DataId, FunId, Inline, ModuleExports, NameId, StructId, TupleId};
 pub use crate::ast::Module;
 
 pub type LocalId = usize; // For now NameId are used as LocalId
@@ -30,6 +32,7 @@ pub enum Type {
     Fun(FunctionType),
     Tuple(TupleId),
     Struct(StructId),
+    Ref(Box<Type>),
 }
 
 // The order of scalars is important, the first (smallest) will be picked when more than one are
@@ -41,10 +44,11 @@ pub enum ScalarType {
     F32,
     F64,
     Bool,
+    V128,
     Null,
 }
 
-#[derive(Hash, Clone, Copy, Eq, PartialEq)]
+#[derive(Hash, Clone, Copy, Eq, PartialEq, Debug)]
 pub enum IntegerType {
     I32,
     I64,
@@ -136,11 +140,20 @@ impl NonNullScalarType {
 
 pub struct Program {
     pub funs: Vec<Function>,
+    /// Functions synthesized from `init` blocks, in declaration order.
+    pub inits: Vec<FunId>,
+    /// Memory declared by a runtime module.
+    pub memory: Option<crate::ast::Memory>,
+    /// Export names set by the module attributes.
+    pub exports: ModuleExports,
     pub imports: Vec<Imports>,
     pub data: DataStore,
     pub structs: StructStore,
     pub tuples: TupleStore,
     pub pub_decls: ModuleDeclarations,
+    pub symbols: Vec<Symbol>,
+    pub nodes: NodeMap,
+    pub scopes: ScopeMap,
     pub module: Module,
 }
 
@@ -149,11 +162,28 @@ impl Program {
     /// declarations before building the MIR.
     pub fn merge(&mut self, other: Self) {
         self.funs.extend(other.funs);
+        self.inits.extend(other.inits);
+        self.memory = self.memory.take().or(other.memory);
+        self.exports.memory = self.exports.memory.take().or(other.exports.memory);
+        self.exports.table = self.exports.table.take().or(other.exports.table);
         self.imports.extend(other.imports);
         self.structs.extend(other.structs);
+        self.symbols.extend(other.symbols);
+        self.nodes.extend(other.nodes);
+        self.scopes.extend(other.scopes);
     }
 }
 
+/// The declarations of a module whose function bodies are not resolved yet, see
+/// `to_declarations`.
+pub struct Declarations {
+    /// The signatures of the functions declared or imported by the module.
+    pub funs: Vec<FunctionPrototype>,
+    pub structs: StructStore,
+    pub tuples: TupleStore,
+    pub pub_decls: ModuleDeclarations,
+}
+
 pub struct Imports {
     pub from: String,
     pub prototypes: Vec<FunctionPrototype>,
@@ -178,9 +208,23 @@ pub struct Function {
     pub params: Vec<LocalId>,
     pub t: FunctionType, // TODO: should we keep the type in a type store?
     pub locals: Vec<LocalVariable>,
+    /// The variables captured by a closure, loaded from its environment when called. `None` if
+    /// the function is not a closure.
+    pub captures: Option<Vec<LocalVariable>>,
     pub body: Body,
     pub loc: Location,
+    /// Location of the `fun` keyword, see `ast::Function`.
+    pub keyword_loc: Location,
+    /// Location of the whole function, see `ast::Function`.
+    pub span: Location,
     pub is_pub: bool,
+    pub inline: Inline,
+    /// Whether the function is marked with `#[test]`, test functions are only lowered when the
+    /// tests are exposed (see `Ctx::set_tests`).
+    pub is_test: bool,
+    /// Whether the function is marked with `#[entry]`, the entry point is exposed under the name
+    /// expected by the target (see `Target::entry_point`).
+    pub is_entry: bool,
     pub exposed: Option<String>,
     pub fun_id: FunId,
 }
@@ -295,6 +339,37 @@ pub enum Expression {
         t: Type,
         loc: Location,
     },
+    /// The address of a place, `t` is the type of the value stored in the place.
+    Reference {
+        place: Box<PlaceExpression>,
+        t: Type,
+        loc: Location,
+    },
+    /// The value stored at the address produced by `expr`.
+    Dereference {
+        expr: Box<Expression>,
+        t: Type,
+        loc: Location,
+    },
+    /// A named function used as a value.
+    Function {
+        fun_id: FunId,
+        t: FunctionType,
+        loc: Location,
+    },
+    /// A closure, that is a function along with the values of the variables it captures.
+    Closure {
+        fun_id: FunId,
+        captures: Vec<Variable>,
+        t: FunctionType,
+        loc: Location,
+    },
+    /// A call to an intrinsic, lowered to a single instruction.
+    Intrinsic {
+        intrinsic: Intrinsic,
+        args: Vec<Expression>,
+        loc: Location,
+    },
     Nop {
         loc: Location,
     },
@@ -315,6 +390,12 @@ pub enum PlaceExpression {
         t: Type,
         loc: Location,
     },
+    /// The slot at the address produced by `expr`.
+    Dereference {
+        expr: Box<Expression>,
+        t: Type,
+        loc: Location,
+    },
 }
 
 pub enum Local {
@@ -422,6 +503,99 @@ pub enum Memory {
     F64Store { align: u32, offset: u32 },
 }
 
+/// Functions known from the compiler which are lowered directly to a WebAssembly instruction,
+/// they are called like any other function but are shadowed by values of the same name.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum Intrinsic {
+    I32Clz,
+    I32Ctz,
+    I32Popcnt,
+    I32Abs,
+    I32Min,
+    I32Max,
+    I64Clz,
+    I64Ctz,
+    I64Popcnt,
+    I64Abs,
+    I64Min,
+    I64Max,
+    F32Abs,
+    F32Sqrt,
+    F32Ceil,
+    F32Floor,
+    F32Trunc,
+    F32Nearest,
+    F32Min,
+    F32Max,
+    F32Copysign,
+    F64Abs,
+    F64Sqrt,
+    F64Ceil,
+    F64Floor,
+    F64Trunc,
+    F64Nearest,
+    F64Min,
+    F64Max,
+    F64Copysign,
+    I32TruncSatF32S,
+    I32TruncSatF32U,
+    I32TruncSatF64S,
+    I32TruncSatF64U,
+    I64TruncSatF32S,
+    I64TruncSatF32U,
+    I64TruncSatF64S,
+    I64TruncSatF64U,
+    I32ReinterpretF32,
+    I64ReinterpretF64,
+    F32ReinterpretI32,
+    F64ReinterpretI64,
+    MemorySize,
+    MemoryGrow,
+    Unreachable,
+    V128Load,
+    V128Store,
+    V128Not,
+    V128And,
+    V128Or,
+    V128Xor,
+    Splat(Shape),
+    /// Takes the lane index as its second argument, which must be an integer literal.
+    ExtractLane(Shape),
+    /// Takes the lane index as its second argument, which must be an integer literal.
+    ReplaceLane(Shape),
+    SimdAdd(Shape),
+    SimdSub(Shape),
+    /// There is no `i8x16` multiplication.
+    SimdMul(Shape),
+    /// Only floating point vectors can be divided.
+    SimdDiv(Shape),
+    AtomicLoad(IntegerType),
+    AtomicStore(IntegerType),
+    /// Returns the value in memory before the operation.
+    AtomicRmw(RmwOp, IntegerType),
+    /// Returns the value in memory before the exchange.
+    AtomicCmpxchg(IntegerType),
+    AtomicNotify,
+    AtomicWait(IntegerType),
+    AtomicFence,
+}
+
+impl Statement {
+    pub fn get_loc(&self) -> Location {
+        match self {
+            Statement::ExprStmt(expr) => expr.get_loc(),
+            Statement::LetStmt { var, expr } => var.loc.merge(expr.get_loc()),
+            Statement::AssignStmt { expr, .. } => expr.get_loc(),
+            Statement::IfStmt { expr, .. } => expr.get_loc(),
+            Statement::WhileStmt { expr, .. } => expr.get_loc(),
+            Statement::ReturnStmt { expr, loc } => match expr {
+                Some(expr) => loc.merge(expr.get_loc()),
+                None => *loc,
+            },
+        }
+    }
+}
+
 impl Expression {
     pub fn get_loc(&self) -> Location {
         match self {
@@ -441,11 +615,231 @@ impl Expression {
             Expression::CallDirect { loc, .. } => *loc,
             Expression::CallIndirect { loc, .. } => *loc,
             Expression::Access { loc, .. } => *loc,
+            Expression::Reference { loc, .. } => *loc,
+            Expression::Dereference { loc, .. } => *loc,
+            Expression::Function { loc, .. } => *loc,
+            Expression::Closure { loc, .. } => *loc,
+            Expression::Intrinsic { loc, .. } => *loc,
             Expression::Nop { loc } => *loc,
         }
     }
 }
 
+impl Function {
+    /// Returns the functions called or used as values by the function, closures included.
+    pub fn callees(&self) -> HashSet<FunId> {
+        let mut callees = HashSet::new();
+        if let Body::Zephyr(block) = &self.body {
+            block.visit(&mut |expr| match expr {
+                Expression::CallDirect { fun_id, .. }
+                | Expression::Function { fun_id, .. }
+                | Expression::Closure { fun_id, .. } => {
+                    callees.insert(*fun_id);
+                }
+                _ => (),
+            });
+        }
+        callees
+    }
+
+    /// Returns the local variables whose address is taken, those must live in memory.
+    pub fn referenced_locals(&self) -> HashSet<LocalId> {
+        let mut locals = HashSet::new();
+        if let Body::Zephyr(block) = &self.body {
+            block.visit(&mut |expr| {
+                if let Expression::Reference { place, .. } = expr {
+                    if let Some(var) = place.referenced_local() {
+                        locals.insert(var.n_id);
+                    }
+                }
+            });
+        }
+        locals
+    }
+
+    /// Returns the local variables initialized with a struct literal whose reference never
+    /// escapes the function, those structs can be allocated in the frame of the function.
+    ///
+    /// A reference escapes as soon as the variable is used for anything else than accessing the
+    /// fields of the struct, for instance when it is passed to a function, returned, stored
+    /// elsewhere or captured by a closure.
+    pub fn non_escaping_structs(&self) -> HashSet<LocalId> {
+        let mut candidates = HashSet::new();
+        let mut escaping = HashSet::new();
+        // Number of uses of each variable, and how many of them are field accesses
+        let mut uses: HashMap<LocalId, (usize, usize)> = HashMap::new();
+        if let Body::Zephyr(block) = &self.body {
+            block.struct_lets(&mut candidates);
+            block.visit(&mut |expr| match expr {
+                Expression::Variable(var) => uses.entry(var.n_id).or_default().0 += 1,
+                Expression::Access {
+                    expr,
+                    kind: AccessKind::Struct { .. },
+                    ..
+                } => {
+                    if let Expression::Variable(var) = expr.as_ref() {
+                        uses.entry(var.n_id).or_default().1 += 1;
+                    }
+                }
+                Expression::Reference { place, .. } => {
+                    if let Some(var) = place.root_local() {
+                        escaping.insert(var.n_id);
+                    }
+                }
+                Expression::Closure { captures, .. } => {
+                    escaping.extend(captures.iter().map(|var| var.n_id));
+                }
+                _ => (),
+            });
+        }
+        candidates.retain(|id| {
+            let (nb_uses, nb_accesses) = uses.get(id).copied().unwrap_or_default();
+            !escaping.contains(id) && nb_uses == nb_accesses
+        });
+        candidates
+    }
+}
+
+impl Block {
+    /// Collects the variables declared with a struct literal as value.
+    fn struct_lets(&self, vars: &mut HashSet<LocalId>) {
+        for stmt in &self.stmts {
+            match stmt {
+                Statement::LetStmt {
+                    var,
+                    expr: Expression::Literal(Value::Struct { .. }),
+                } => {
+                    vars.insert(var.n_id);
+                }
+                Statement::IfStmt {
+                    block, else_block, ..
+                } => {
+                    block.struct_lets(vars);
+                    if let Some(else_block) = else_block {
+                        else_block.struct_lets(vars);
+                    }
+                }
+                Statement::WhileStmt { block, .. } => block.struct_lets(vars),
+                _ => (),
+            }
+        }
+    }
+
+    /// Calls `f` on all the expressions of the block, sub-expressions included.
+    fn visit(&self, f: &mut impl FnMut(&Expression)) {
+        for stmt in &self.stmts {
+            match stmt {
+                Statement::ExprStmt(expr) | Statement::LetStmt { expr, .. } => expr.visit(f),
+                Statement::AssignStmt { target, expr } => {
+                    target.visit(f);
+                    expr.visit(f);
+                }
+                Statement::IfStmt {
+                    expr,
+                    block,
+                    else_block,
+                } => {
+                    expr.visit(f);
+                    block.visit(f);
+                    if let Some(else_block) = else_block {
+                        else_block.visit(f);
+                    }
+                }
+                Statement::WhileStmt { expr, block } => {
+                    expr.visit(f);
+                    block.visit(f);
+                }
+                Statement::ReturnStmt { expr, .. } => {
+                    if let Some(expr) = expr {
+                        expr.visit(f);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Expression {
+    /// Calls `f` on the expression and all its sub-expressions.
+    fn visit(&self, f: &mut impl FnMut(&Expression)) {
+        f(self);
+        match self {
+            Expression::Variable(_)
+            | Expression::Nop { .. }
+            | Expression::Function { .. }
+            | Expression::Closure { .. } => (),
+            Expression::Literal(Value::Struct { fields, .. }) => {
+                for field in fields {
+                    field.expr.visit(f);
+                }
+            }
+            Expression::Literal(Value::Tuple { values, .. }) => {
+                for value in values {
+                    value.visit(f);
+                }
+            }
+            Expression::Literal(_) => (),
+            Expression::Binary {
+                expr_left,
+                expr_right,
+                ..
+            } => {
+                expr_left.visit(f);
+                expr_right.visit(f);
+            }
+            Expression::Unary { expr, .. }
+            | Expression::Access { expr, .. }
+            | Expression::Dereference { expr, .. } => expr.visit(f),
+            Expression::Reference { place, .. } => place.visit(f),
+            Expression::CallDirect { args, .. } | Expression::Intrinsic { args, .. } => {
+                for arg in args {
+                    arg.visit(f);
+                }
+            }
+            Expression::CallIndirect { fun, args, .. } => {
+                fun.visit(f);
+                for arg in args {
+                    arg.visit(f);
+                }
+            }
+        }
+    }
+}
+
+impl PlaceExpression {
+    /// Calls `f` on the expressions the place depends on.
+    fn visit(&self, f: &mut impl FnMut(&Expression)) {
+        match self {
+            PlaceExpression::Variable(_) => (),
+            PlaceExpression::Access { expr, .. } => expr.visit(f),
+            PlaceExpression::Dereference { expr, .. } => expr.visit(f),
+        }
+    }
+
+    /// Returns the local variable the place is part of or reachable from, such as `a` in `a.b.c`.
+    fn root_local(&self) -> Option<&Variable> {
+        match self {
+            PlaceExpression::Variable(var) => Some(var),
+            PlaceExpression::Access { expr, .. } => expr.root_local(),
+            PlaceExpression::Dereference { .. } => None,
+        }
+    }
+
+    /// Returns the local variable holding the place, if any. Struct fields and dereferences are
+    /// not part of a local variable, as structs are represented by pointers.
+    pub fn referenced_local(&self) -> Option<&Variable> {
+        match self {
+            PlaceExpression::Variable(var) => Some(var),
+            PlaceExpression::Access {
+                expr,
+                kind: AccessKind::Tuple { .. },
+                ..
+            } => expr.referenced_local(),
+            PlaceExpression::Access { .. } | PlaceExpression::Dereference { .. } => None,
+        }
+    }
+}
+
 impl Unop {
     pub fn get_t(&self) -> ScalarType {
         match self {
@@ -455,6 +849,372 @@ impl Unop {
     }
 }
 
+impl Intrinsic {
+    /// The intrinsics with a fixed name, the others are named after the shape of vector or the
+    /// type they operate on.
+    pub const ALL: [Intrinsic; 55] = [
+        Intrinsic::I32Clz,
+        Intrinsic::I32Ctz,
+        Intrinsic::I32Popcnt,
+        Intrinsic::I32Abs,
+        Intrinsic::I32Min,
+        Intrinsic::I32Max,
+        Intrinsic::I64Clz,
+        Intrinsic::I64Ctz,
+        Intrinsic::I64Popcnt,
+        Intrinsic::I64Abs,
+        Intrinsic::I64Min,
+        Intrinsic::I64Max,
+        Intrinsic::F32Abs,
+        Intrinsic::F32Sqrt,
+        Intrinsic::F32Ceil,
+        Intrinsic::F32Floor,
+        Intrinsic::F32Trunc,
+        Intrinsic::F32Nearest,
+        Intrinsic::F32Min,
+        Intrinsic::F32Max,
+        Intrinsic::F32Copysign,
+        Intrinsic::F64Abs,
+        Intrinsic::F64Sqrt,
+        Intrinsic::F64Ceil,
+        Intrinsic::F64Floor,
+        Intrinsic::F64Trunc,
+        Intrinsic::F64Nearest,
+        Intrinsic::F64Min,
+        Intrinsic::F64Max,
+        Intrinsic::F64Copysign,
+        Intrinsic::I32TruncSatF32S,
+        Intrinsic::I32TruncSatF32U,
+        Intrinsic::I32TruncSatF64S,
+        Intrinsic::I32TruncSatF64U,
+        Intrinsic::I64TruncSatF32S,
+        Intrinsic::I64TruncSatF32U,
+        Intrinsic::I64TruncSatF64S,
+        Intrinsic::I64TruncSatF64U,
+        Intrinsic::I32ReinterpretF32,
+        Intrinsic::I64ReinterpretF64,
+        Intrinsic::F32ReinterpretI32,
+        Intrinsic::F64ReinterpretI64,
+        Intrinsic::MemorySize,
+        Intrinsic::MemoryGrow,
+        Intrinsic::Unreachable,
+        Intrinsic::V128Load,
+        Intrinsic::V128Store,
+        Intrinsic::V128Not,
+        Intrinsic::V128And,
+        Intrinsic::V128Or,
+        Intrinsic::V128Xor,
+        Intrinsic::AtomicNotify,
+        Intrinsic::AtomicWait(IntegerType::I32),
+        Intrinsic::AtomicWait(IntegerType::I64),
+        Intrinsic::AtomicFence,
+    ];
+
+    /// The name under which the intrinsic is called.
+    pub fn name(self) -> String {
+        let name = match self {
+            Intrinsic::I32Clz => "i32_clz",
+            Intrinsic::I32Ctz => "i32_ctz",
+            Intrinsic::I32Popcnt => "i32_popcnt",
+            Intrinsic::I32Abs => "i32_abs",
+            Intrinsic::I32Min => "i32_min",
+            Intrinsic::I32Max => "i32_max",
+            Intrinsic::I64Clz => "i64_clz",
+            Intrinsic::I64Ctz => "i64_ctz",
+            Intrinsic::I64Popcnt => "i64_popcnt",
+            Intrinsic::I64Abs => "i64_abs",
+            Intrinsic::I64Min => "i64_min",
+            Intrinsic::I64Max => "i64_max",
+            Intrinsic::F32Abs => "f32_abs",
+            Intrinsic::F32Sqrt => "f32_sqrt",
+            Intrinsic::F32Ceil => "f32_ceil",
+            Intrinsic::F32Floor => "f32_floor",
+            Intrinsic::F32Trunc => "f32_trunc",
+            Intrinsic::F32Nearest => "f32_nearest",
+            Intrinsic::F32Min => "f32_min",
+            Intrinsic::F32Max => "f32_max",
+            Intrinsic::F32Copysign => "f32_copysign",
+            Intrinsic::F64Abs => "f64_abs",
+            Intrinsic::F64Sqrt => "f64_sqrt",
+            Intrinsic::F64Ceil => "f64_ceil",
+            Intrinsic::F64Floor => "f64_floor",
+            Intrinsic::F64Trunc => "f64_trunc",
+            Intrinsic::F64Nearest => "f64_nearest",
+            Intrinsic::F64Min => "f64_min",
+            Intrinsic::F64Max => "f64_max",
+            Intrinsic::F64Copysign => "f64_copysign",
+            Intrinsic::I32TruncSatF32S => "i32_trunc_sat_f32_s",
+            Intrinsic::I32TruncSatF32U => "i32_trunc_sat_f32_u",
+            Intrinsic::I32TruncSatF64S => "i32_trunc_sat_f64_s",
+            Intrinsic::I32TruncSatF64U => "i32_trunc_sat_f64_u",
+            Intrinsic::I64TruncSatF32S => "i64_trunc_sat_f32_s",
+            Intrinsic::I64TruncSatF32U => "i64_trunc_sat_f32_u",
+            Intrinsic::I64TruncSatF64S => "i64_trunc_sat_f64_s",
+            Intrinsic::I64TruncSatF64U => "i64_trunc_sat_f64_u",
+            Intrinsic::I32ReinterpretF32 => "i32_reinterpret_f32",
+            Intrinsic::I64ReinterpretF64 => "i64_reinterpret_f64",
+            Intrinsic::F32ReinterpretI32 => "f32_reinterpret_i32",
+            Intrinsic::F64ReinterpretI64 => "f64_reinterpret_i64",
+            Intrinsic::MemorySize => "memory_size",
+            Intrinsic::MemoryGrow => "memory_grow",
+            Intrinsic::Unreachable => "unreachable",
+            Intrinsic::V128Load => "v128_load",
+            Intrinsic::V128Store => "v128_store",
+            Intrinsic::V128Not => "v128_not",
+            Intrinsic::V128And => "v128_and",
+            Intrinsic::V128Or => "v128_or",
+            Intrinsic::V128Xor => "v128_xor",
+            Intrinsic::Splat(shape) => return format!("{}_splat", shape),
+            Intrinsic::ExtractLane(shape) => return format!("{}_extract_lane", shape),
+            Intrinsic::ReplaceLane(shape) => return format!("{}_replace_lane", shape),
+            Intrinsic::SimdAdd(shape) => return format!("{}_add", shape),
+            Intrinsic::SimdSub(shape) => return format!("{}_sub", shape),
+            Intrinsic::SimdMul(shape) => return format!("{}_mul", shape),
+            Intrinsic::SimdDiv(shape) => return format!("{}_div", shape),
+            Intrinsic::AtomicLoad(t) => return format!("{}_atomic_load", t),
+            Intrinsic::AtomicStore(t) => return format!("{}_atomic_store", t),
+            Intrinsic::AtomicRmw(op, t) => return format!("{}_atomic_rmw_{}", t, op.name()),
+            Intrinsic::AtomicCmpxchg(t) => return format!("{}_atomic_rmw_cmpxchg", t),
+            Intrinsic::AtomicNotify => "memory_atomic_notify",
+            Intrinsic::AtomicWait(IntegerType::I32) => "memory_atomic_wait32",
+            Intrinsic::AtomicWait(IntegerType::I64) => "memory_atomic_wait64",
+            Intrinsic::AtomicFence => "atomic_fence",
+        };
+        String::from(name)
+    }
+
+    /// Returns the intrinsic with the given name, if any.
+    pub fn from_name(name: &str) -> Option<Intrinsic> {
+        if let Some(intrinsic) = Intrinsic::ALL
+            .iter()
+            .copied()
+            .find(|intrinsic| intrinsic.name() == name)
+        {
+            return Some(intrinsic);
+        }
+        let (prefix, op) = name.split_once('_')?;
+        // Atomic operations are named after the type they access, such as `i32_atomic_load`
+        let t = match prefix {
+            "i32" => Some(IntegerType::I32),
+            "i64" => Some(IntegerType::I64),
+            _ => None,
+        };
+        if let Some(t) = t {
+            let intrinsic = match op.strip_prefix("atomic_")? {
+                "load" => Intrinsic::AtomicLoad(t),
+                "store" => Intrinsic::AtomicStore(t),
+                "rmw_cmpxchg" => Intrinsic::AtomicCmpxchg(t),
+                op => {
+                    let op = op.strip_prefix("rmw_")?;
+                    let op = RmwOp::ALL.iter().find(|rmw| rmw.name() == op)?;
+                    Intrinsic::AtomicRmw(*op, t)
+                }
+            };
+            return Some(intrinsic);
+        }
+        // Vector operations are named after their shape, such as `i32x4_add`
+        let shape = Shape::from_name(prefix)?;
+        let intrinsic = match op {
+            "splat" => Intrinsic::Splat(shape),
+            "extract_lane" => Intrinsic::ExtractLane(shape),
+            "replace_lane" => Intrinsic::ReplaceLane(shape),
+            "add" => Intrinsic::SimdAdd(shape),
+            "sub" => Intrinsic::SimdSub(shape),
+            "mul" if shape != Shape::I8x16 => Intrinsic::SimdMul(shape),
+            "div" if shape.is_float() => Intrinsic::SimdDiv(shape),
+            _ => return None,
+        };
+        Some(intrinsic)
+    }
+
+    /// Whether the intrinsic operates on `v128` vectors, which requires the `simd` feature.
+    pub fn is_simd(self) -> bool {
+        matches!(
+            self,
+            Intrinsic::V128Load
+                | Intrinsic::V128Store
+                | Intrinsic::V128Not
+                | Intrinsic::V128And
+                | Intrinsic::V128Or
+                | Intrinsic::V128Xor
+                | Intrinsic::Splat(_)
+                | Intrinsic::ExtractLane(_)
+                | Intrinsic::ReplaceLane(_)
+                | Intrinsic::SimdAdd(_)
+                | Intrinsic::SimdSub(_)
+                | Intrinsic::SimdMul(_)
+                | Intrinsic::SimdDiv(_)
+        )
+    }
+
+    /// Whether the intrinsic is an atomic operation, which requires the `threads` feature.
+    pub fn is_atomic(self) -> bool {
+        matches!(
+            self,
+            Intrinsic::AtomicLoad(_)
+                | Intrinsic::AtomicStore(_)
+                | Intrinsic::AtomicRmw(_, _)
+                | Intrinsic::AtomicCmpxchg(_)
+                | Intrinsic::AtomicNotify
+                | Intrinsic::AtomicWait(_)
+                | Intrinsic::AtomicFence
+        )
+    }
+
+    /// The types of the parameters of the intrinsic.
+    pub fn get_params(self) -> &'static [ScalarType] {
+        match self {
+            Intrinsic::I32Min | Intrinsic::I32Max => &[ScalarType::I32, ScalarType::I32],
+            Intrinsic::I64Min | Intrinsic::I64Max => &[ScalarType::I64, ScalarType::I64],
+            Intrinsic::F32Min | Intrinsic::F32Max | Intrinsic::F32Copysign => {
+                &[ScalarType::F32, ScalarType::F32]
+            }
+            Intrinsic::F64Min | Intrinsic::F64Max | Intrinsic::F64Copysign => {
+                &[ScalarType::F64, ScalarType::F64]
+            }
+            Intrinsic::I32TruncSatF32S
+            | Intrinsic::I32TruncSatF32U
+            | Intrinsic::I64TruncSatF32S
+            | Intrinsic::I64TruncSatF32U
+            | Intrinsic::I32ReinterpretF32 => &[ScalarType::F32],
+            Intrinsic::I32TruncSatF64S
+            | Intrinsic::I32TruncSatF64U
+            | Intrinsic::I64TruncSatF64S
+            | Intrinsic::I64TruncSatF64U
+            | Intrinsic::I64ReinterpretF64 => &[ScalarType::F64],
+            Intrinsic::F32ReinterpretI32 => &[ScalarType::I32],
+            Intrinsic::F64ReinterpretI64 => &[ScalarType::I64],
+            Intrinsic::MemorySize | Intrinsic::Unreachable | Intrinsic::AtomicFence => &[],
+            Intrinsic::MemoryGrow | Intrinsic::V128Load | Intrinsic::AtomicLoad(_) => {
+                &[ScalarType::I32]
+            }
+            Intrinsic::AtomicStore(IntegerType::I32)
+            | Intrinsic::AtomicRmw(_, IntegerType::I32)
+            | Intrinsic::AtomicNotify => &[ScalarType::I32, ScalarType::I32],
+            Intrinsic::AtomicStore(IntegerType::I64)
+            | Intrinsic::AtomicRmw(_, IntegerType::I64) => &[ScalarType::I32, ScalarType::I64],
+            Intrinsic::AtomicCmpxchg(IntegerType::I32) => {
+                &[ScalarType::I32, ScalarType::I32, ScalarType::I32]
+            }
+            Intrinsic::AtomicCmpxchg(IntegerType::I64) => {
+                &[ScalarType::I32, ScalarType::I64, ScalarType::I64]
+            }
+            // The timeout is in nanoseconds, negative values wait forever
+            Intrinsic::AtomicWait(IntegerType::I32) => {
+                &[ScalarType::I32, ScalarType::I32, ScalarType::I64]
+            }
+            Intrinsic::AtomicWait(IntegerType::I64) => {
+                &[ScalarType::I32, ScalarType::I64, ScalarType::I64]
+            }
+            Intrinsic::V128Store => &[ScalarType::I32, ScalarType::V128],
+            Intrinsic::V128Not => &[ScalarType::V128],
+            Intrinsic::V128And
+            | Intrinsic::V128Or
+            | Intrinsic::V128Xor
+            | Intrinsic::SimdAdd(_)
+            | Intrinsic::SimdSub(_)
+            | Intrinsic::SimdMul(_)
+            | Intrinsic::SimdDiv(_) => &[ScalarType::V128, ScalarType::V128],
+            Intrinsic::Splat(shape) => match shape {
+                Shape::I64x2 => &[ScalarType::I64],
+                Shape::F32x4 => &[ScalarType::F32],
+                Shape::F64x2 => &[ScalarType::F64],
+                _ => &[ScalarType::I32],
+            },
+            Intrinsic::ExtractLane(_) => &[ScalarType::V128, ScalarType::I32],
+            Intrinsic::ReplaceLane(shape) => match shape {
+                Shape::I64x2 => &[ScalarType::V128, ScalarType::I32, ScalarType::I64],
+                Shape::F32x4 => &[ScalarType::V128, ScalarType::I32, ScalarType::F32],
+                Shape::F64x2 => &[ScalarType::V128, ScalarType::I32, ScalarType::F64],
+                _ => &[ScalarType::V128, ScalarType::I32, ScalarType::I32],
+            },
+            // The other intrinsics take a single operand of the type they return
+            _ => match self.get_t() {
+                ScalarType::I32 => &[ScalarType::I32],
+                ScalarType::I64 => &[ScalarType::I64],
+                ScalarType::F32 => &[ScalarType::F32],
+                _ => &[ScalarType::F64],
+            },
+        }
+    }
+
+    /// The type returned by the intrinsic, `Null` if it returns nothing.
+    pub fn get_t(self) -> ScalarType {
+        match self {
+            Intrinsic::I32Clz
+            | Intrinsic::I32Ctz
+            | Intrinsic::I32Popcnt
+            | Intrinsic::I32Abs
+            | Intrinsic::I32Min
+            | Intrinsic::I32Max
+            | Intrinsic::I32TruncSatF32S
+            | Intrinsic::I32TruncSatF32U
+            | Intrinsic::I32TruncSatF64S
+            | Intrinsic::I32TruncSatF64U
+            | Intrinsic::I32ReinterpretF32 => ScalarType::I32,
+            Intrinsic::I64Clz
+            | Intrinsic::I64Ctz
+            | Intrinsic::I64Popcnt
+            | Intrinsic::I64Abs
+            | Intrinsic::I64Min
+            | Intrinsic::I64Max
+            | Intrinsic::I64TruncSatF32S
+            | Intrinsic::I64TruncSatF32U
+            | Intrinsic::I64TruncSatF64S
+            | Intrinsic::I64TruncSatF64U
+            | Intrinsic::I64ReinterpretF64 => ScalarType::I64,
+            Intrinsic::F32Abs
+            | Intrinsic::F32Sqrt
+            | Intrinsic::F32Ceil
+            | Intrinsic::F32Floor
+            | Intrinsic::F32Trunc
+            | Intrinsic::F32Nearest
+            | Intrinsic::F32Min
+            | Intrinsic::F32Max
+            | Intrinsic::F32Copysign
+            | Intrinsic::F32ReinterpretI32 => ScalarType::F32,
+            Intrinsic::F64Abs
+            | Intrinsic::F64Sqrt
+            | Intrinsic::F64Ceil
+            | Intrinsic::F64Floor
+            | Intrinsic::F64Trunc
+            | Intrinsic::F64Nearest
+            | Intrinsic::F64Min
+            | Intrinsic::F64Max
+            | Intrinsic::F64Copysign
+            | Intrinsic::F64ReinterpretI64 => ScalarType::F64,
+            Intrinsic::MemorySize
+            | Intrinsic::MemoryGrow
+            | Intrinsic::AtomicNotify
+            | Intrinsic::AtomicWait(_) => ScalarType::I32,
+            Intrinsic::Unreachable
+            | Intrinsic::V128Store
+            | Intrinsic::AtomicStore(_)
+            | Intrinsic::AtomicFence => ScalarType::Null,
+            Intrinsic::AtomicLoad(t) | Intrinsic::AtomicRmw(_, t) | Intrinsic::AtomicCmpxchg(t) => {
+                t.get_t()
+            }
+            Intrinsic::ExtractLane(shape) => match shape {
+                Shape::I64x2 => ScalarType::I64,
+                Shape::F32x4 => ScalarType::F32,
+                Shape::F64x2 => ScalarType::F64,
+                _ => ScalarType::I32,
+            },
+            Intrinsic::V128Load
+            | Intrinsic::V128Not
+            | Intrinsic::V128And
+            | Intrinsic::V128Or
+            | Intrinsic::V128Xor
+            | Intrinsic::Splat(_)
+            | Intrinsic::ReplaceLane(_)
+            | Intrinsic::SimdAdd(_)
+            | Intrinsic::SimdSub(_)
+            | Intrinsic::SimdMul(_)
+            | Intrinsic::SimdDiv(_) => ScalarType::V128,
+        }
+    }
+}
+
 impl Binop {
     pub fn get_t(&self) -> ScalarType {
         match self {
@@ -504,6 +1264,16 @@ impl fmt::Display for Type {
             Type::Fun(t) => write!(f, "{}", t),
             Type::Struct(s_id) => write!(f, "struct #{}", s_id),
             Type::Tuple(tup_id) => write!(f, "tuple #{}", tup_id,),
+            Type::Ref(t) => write!(f, "&{}", t),
+        }
+    }
+}
+
+impl fmt::Display for IntegerType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IntegerType::I32 => write!(f, "i32"),
+            IntegerType::I64 => write!(f, "i64"),
         }
     }
 }
@@ -516,6 +1286,7 @@ impl fmt::Display for ScalarType {
             ScalarType::I64 => write!(f, "i64"),
             ScalarType::I32 => write!(f, "i32"),
             ScalarType::Bool => write!(f, "bool"),
+            ScalarType::V128 => write!(f, "v128"),
             ScalarType::Null => write!(f, "null"),
         }
     }
@@ -652,6 +1423,32 @@ impl fmt::Display for Expression {
                 ..
             } => write!(f, "({} {} {})", expr_left, binop, expr_right),
             Expression::Access { expr, kind, .. } => write!(f, "{}.{}", expr, kind),
+            Expression::Reference { place, .. } => write!(f, "&{}", place),
+            Expression::Dereference { expr, .. } => write!(f, "*{}", expr),
+            Expression::Function { fun_id, .. } => write!(f, "(fun {})", fun_id),
+            Expression::Closure {
+                fun_id, captures, ..
+            } => write!(
+                f,
+                "(closure {})[{}]",
+                fun_id,
+                captures
+                    .iter()
+                    .map(|var| var.ident.clone())
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+            Expression::Intrinsic {
+                intrinsic, args, ..
+            } => write!(
+                f,
+                "{}({})",
+                intrinsic,
+                args.iter()
+                    .map(|arg| format!("{}", arg))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
             Expression::Nop { .. } => write!(f, "nop"),
         }
     }
@@ -671,6 +1468,7 @@ impl fmt::Display for PlaceExpression {
         match self {
             PlaceExpression::Variable(v) => write!(f, "{}", v.ident),
             PlaceExpression::Access { expr, kind, .. } => write!(f, "{}.{}", expr, kind),
+            PlaceExpression::Dereference { expr, .. } => write!(f, "*{}", expr),
         }
     }
 }
@@ -817,6 +1615,12 @@ impl fmt::Display for LocalVariable {
     }
 }
 
+impl fmt::Display for Intrinsic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
 impl fmt::Display for Memory {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -0,0 +1,262 @@
+//! Lints
+//!
+//! Detects code which is valid but likely to be a mistake. Lints are reported through the error
+//! handler, which decides whether they are silenced, reported as warnings or as errors.
+
+use std::collections::{HashMap, HashSet};
+
+use super::flow::{ControlFlow, Terminator};
+use super::names::{Block, Body, Expression, FunId, ResolvedProgram, Statement, Value};
+use super::type_check::{TypeChecker, TypeVar};
+use crate::ctx::KnownValues;
+use crate::error::{ErrorHandler, Lint, Location};
+
+/// Reports the lints of a resolved program, must be called after type checking.
+pub fn check(
+    program: &ResolvedProgram,
+    checker: &mut TypeChecker,
+    known_values: &KnownValues,
+    err: &mut impl ErrorHandler,
+) {
+    for (ident, loc) in &program.unused_mods {
+        err.lint(
+            Lint::UnusedImport,
+            *loc,
+            format!("Module '{}' is imported but never used", ident),
+        );
+    }
+
+    unused_variables(program, err);
+    shadowed_variables(program, err);
+
+    let mut linter = Linter {
+        checker,
+        err,
+        flow: ControlFlow::new(known_values),
+        callees: HashSet::new(),
+        is_dead: false,
+    };
+    let mut call_graph = HashMap::new();
+    for fun in &program.funs {
+        if let Body::Zephyr(block) = &fun.body {
+            linter.block(block);
+        }
+        call_graph.insert(fun.fun_id, std::mem::take(&mut linter.callees));
+    }
+    unused_functions(program, &call_graph, err);
+}
+
+/// Reports the parameters and local variables which are never read.
+fn unused_variables(program: &ResolvedProgram, err: &mut impl ErrorHandler) {
+    for fun in &program.funs {
+        let params = fun.params.iter().map(|param| param.n_id);
+        for n_id in params.chain(fun.locals.iter().copied()) {
+            let name = program.names.get(n_id);
+            if name.name.starts_with('_') || program.read_names.contains(&n_id) {
+                continue;
+            }
+            err.lint(
+                Lint::UnusedVariable,
+                name.loc,
+                format!("Variable '{}' is never read", name.name),
+            );
+        }
+    }
+}
+
+/// Reports the variables shadowing a declaration from an enclosing scope.
+fn shadowed_variables(program: &ResolvedProgram, err: &mut impl ErrorHandler) {
+    for n_id in &program.shadowings {
+        let name = program.names.get(*n_id);
+        err.lint(
+            Lint::Shadowing,
+            name.loc,
+            format!("Variable '{}' shadows a previous declaration", name.name),
+        );
+    }
+}
+
+/// Reports the private functions which can not be reached from a public, exposed, test or entry
+/// function, or from an init block. Closures are not reported, as their enclosing function already is.
+fn unused_functions(
+    program: &ResolvedProgram,
+    call_graph: &HashMap<FunId, HashSet<FunId>>,
+    err: &mut impl ErrorHandler,
+) {
+    let mut reachable = HashSet::new();
+    let mut to_visit = program
+        .funs
+        .iter()
+        .filter(|fun| fun.is_pub || fun.is_test || fun.is_entry || fun.exposed.is_some())
+        .map(|fun| fun.fun_id)
+        .chain(program.inits.iter().copied())
+        .collect::<Vec<FunId>>();
+    while let Some(fun_id) = to_visit.pop() {
+        if !reachable.insert(fun_id) {
+            continue;
+        }
+        if let Some(callees) = call_graph.get(&fun_id) {
+            to_visit.extend(callees.iter().copied());
+        }
+    }
+    for fun in &program.funs {
+        if fun.ident.starts_with('_') || fun.captures.is_some() || reachable.contains(&fun.fun_id) {
+            continue;
+        }
+        err.lint(
+            Lint::UnusedFunction,
+            fun.loc,
+            format!("Function '{}' is never called", fun.ident),
+        );
+    }
+}
+
+struct Linter<'a, 'ctx, 'ty, E: ErrorHandler> {
+    checker: &'a mut TypeChecker<'ctx, 'ty>,
+    err: &'a mut E,
+    flow: ControlFlow,
+    /// The functions referenced by the function being linted.
+    callees: HashSet<FunId>,
+    /// Whether the statements being linted are unreachable, in which case the unreachable code
+    /// they contain is not reported again.
+    is_dead: bool,
+}
+
+impl<'a, 'ctx, 'ty, E: ErrorHandler> Linter<'a, 'ctx, 'ty, E> {
+    fn block(&mut self, block: &Block) {
+        let was_dead = self.is_dead;
+        let live = match self.flow.terminator(block) {
+            Some((idx, terminator)) => {
+                if let Some(stmt) = block.stmts.get(idx + 1) {
+                    self.unreachable(stmt.get_loc(), terminator);
+                }
+                idx + 1
+            }
+            None => block.stmts.len(),
+        };
+        for (idx, stmt) in block.stmts.iter().enumerate() {
+            self.is_dead = was_dead || idx >= live;
+            self.statement(stmt);
+        }
+        self.is_dead = was_dead;
+    }
+
+    /// Reports the code starting at `loc`, which can not be reached because of the preceding
+    /// `terminator`.
+    fn unreachable(&mut self, loc: Location, terminator: Terminator) {
+        if self.is_dead {
+            return;
+        }
+        self.err.lint_with_note(
+            Lint::UnreachableCode,
+            loc,
+            String::from("Unreachable code"),
+            terminator.loc(),
+            String::from(terminator.note()),
+        );
+    }
+
+    fn statement(&mut self, stmt: &Statement) {
+        match stmt {
+            Statement::ExprStmt(expr) => self.expression(expr),
+            Statement::LetStmt { expr, .. } => self.expression(expr),
+            Statement::AssignStmt { target, expr } => {
+                self.expression(target);
+                self.expression(expr);
+            }
+            Statement::IfStmt {
+                expr,
+                block,
+                else_block,
+            } => {
+                self.expression(expr);
+                self.block(block);
+                if let Some(else_block) = else_block {
+                    self.block(else_block);
+                }
+            }
+            Statement::WhileStmt { expr, block } => {
+                self.expression(expr);
+                self.block(block);
+            }
+            Statement::ReturnStmt { expr, .. } => {
+                if let Some(expr) = expr {
+                    self.expression(expr);
+                }
+            }
+            Statement::DeferStmt { expr, .. } => self.expression(expr),
+        }
+    }
+
+    fn expression(&mut self, expr: &Expression) {
+        match expr {
+            Expression::Literal(value) => match value {
+                Value::Integer { loc, t_var, .. } | Value::Float { loc, t_var, .. } => {
+                    self.implicit_default(*t_var, *loc)
+                }
+                Value::Struct { fields, .. } => {
+                    for field in fields {
+                        self.expression(&field.expr);
+                    }
+                }
+                Value::Tuple { values, .. } => {
+                    for value in values {
+                        self.expression(value);
+                    }
+                }
+                Value::Boolean { .. } | Value::Str { .. } => (),
+            },
+            Expression::Access { expr, .. } => self.expression(expr),
+            Expression::Binary {
+                expr_left,
+                expr_right,
+                ..
+            } => {
+                self.expression(expr_left);
+                self.expression(expr_right);
+            }
+            Expression::Unary { expr, .. }
+            | Expression::Reference { expr, .. }
+            | Expression::Dereference { expr, .. } => self.expression(expr),
+            Expression::Function { fun_id, .. } | Expression::Closure { fun_id, .. } => {
+                self.callees.insert(*fun_id);
+            }
+            Expression::CallDirect { fun_id, args, .. } => {
+                self.callees.insert(*fun_id);
+                for arg in args {
+                    self.expression(arg);
+                }
+            }
+            Expression::CallIndirect { fun, args, .. } => {
+                self.expression(fun);
+                for arg in args {
+                    self.expression(arg);
+                }
+            }
+            Expression::Intrinsic { args, .. } => {
+                for arg in args {
+                    self.expression(arg);
+                }
+            }
+            Expression::Format { parts, .. } => {
+                for (part, _) in parts {
+                    self.expression(part);
+                }
+            }
+            Expression::Variable(_) | Expression::Namespace { .. } => (),
+        }
+    }
+
+    fn implicit_default(&mut self, t_var: TypeVar, loc: Location) {
+        if !self.checker.is_defaulted(t_var) {
+            return;
+        }
+        if let Some(t) = self.checker.get_t(t_var) {
+            self.err.lint(
+                Lint::ImplicitDefault,
+                loc,
+                format!("Type of literal can not be inferred, defaulting to '{}'", t),
+            );
+        }
+    }
+}
@@ -1,24 +1,33 @@
+use log::debug;
 use std::collections::HashMap;
+use std::time::Instant;
 
 use crate::ast;
-use crate::ctx::{Ctx, KnownValues, ModId};
+use crate::ctx::{Ctx, KnownValues, ModId, Phase};
 use crate::error::ErrorHandler;
 
 pub use self::names::{
     AsmControl, AsmLocal, AsmMemory, AsmParametric, AsmStatement, NameId, TypeId, ValueDeclaration,
 };
 pub use crate::ast::Module;
+pub use cfg::{filter_declarations, Cfg};
 pub use hir::*;
-pub use names::{Data, DataId};
+pub use names::{Constant, Data, DataId};
+pub use resolver::declare_structs;
 pub use store::known_ids;
 pub use store::Identifier;
+pub use symbols::{Entity, Node, NodeMap, ScopeMap, Symbol};
 
 mod asm_validate;
 mod ast_to_hir;
+mod cfg;
+mod flow;
 mod hir;
+mod lints;
 mod names;
 mod resolver;
 mod store;
+mod symbols;
 mod type_check;
 
 pub fn to_hir(
@@ -27,43 +36,89 @@ pub fn to_hir(
     ctx: &Ctx,
     known_values: &KnownValues,
     error_handler: &mut impl ErrorHandler,
-    verbose: bool,
-) -> hir::Program {
+    lint: bool,
+) -> Result<hir::Program, ()> {
+    let start = Instant::now();
     let store = type_check::TyStore::new();
     let mut checker = type_check::TypeChecker::new(ctx, &store, ast_program.module.id);
     let mut name_resolver = resolver::NameResolver::new(error_handler);
-    let program = name_resolver.resolve(ast_program, namespace, ctx, &mut checker, known_values);
+    let mut program =
+        name_resolver.resolve(ast_program, namespace, ctx, &mut checker, known_values);
+    ctx.record_phase(Phase::NameResolution, start);
 
-    if verbose {
-        println!("\n/// Name Resolution ///\n");
-        println!("{}\n", program.names);
-        println!("{}\n", checker);
-        println!("\n/// Type Checking ///\n");
-    }
+    debug!("\n/// Name Resolution ///\n");
+    debug!("{}\n", program.names);
+    debug!("{}\n", checker);
+    debug!("\n/// Type Checking ///\n");
 
+    let start = Instant::now();
     let _ = checker.type_check(&program.structs, error_handler);
 
-    if verbose {
-        println!("{}", checker);
-        println!("\n/// Asm Validation ///\n");
-    }
+    debug!("{}", checker);
+    debug!("\n/// Asm Validation ///\n");
 
     let mut asm_validator = asm_validate::AsmValidator::new(&program, &mut checker, error_handler);
     asm_validator.validate_asm();
 
-    error_handler.flush_and_exit_if_err();
+    if lint && !error_handler.has_error() {
+        lints::check(&program, &mut checker, known_values, error_handler);
+    }
+    ctx.record_phase(Phase::TypeCheck, start);
 
-    if verbose {
-        println!("\n/// HIR Production ///\n");
+    if error_handler.has_error() {
+        return Err(());
     }
 
-    let mut hir_producer = ast_to_hir::HirProducer::new(error_handler);
-    let hir = hir_producer.reduce(program, checker);
+    let start = Instant::now();
+    let symbols = symbols::collect_symbols(&program, &mut checker, ctx);
+    symbols::collect_field_nodes(&mut program, &mut checker);
+
+    debug!("\n/// HIR Production ///\n");
+
+    let mut hir_producer = ast_to_hir::HirProducer::new(error_handler, known_values);
+    let hir = hir_producer.reduce(program, checker, symbols);
+    ctx.record_phase(Phase::HirToMir, start);
 
-    if verbose {
-        println!("{}", hir);
+    debug!("{}", hir);
+
+    if error_handler.has_error() {
+        return Err(());
     }
+    Ok(hir)
+}
 
-    error_handler.flush_and_exit_if_err();
-    hir
+/// Produces the declarations of a module (structs and function signatures) without resolving its
+/// function bodies.
+///
+/// The IDs are the ones given by `to_hir`, this is used to declare the modules of an import cycle
+/// before resolving any of them.
+pub fn to_declarations(
+    ast_program: &ast::Program,
+    namespace: HashMap<String, ModId>,
+    ctx: &Ctx,
+    known_values: &KnownValues,
+    error_handler: &mut impl ErrorHandler,
+) -> Result<hir::Declarations, ()> {
+    let start = Instant::now();
+    let store = type_check::TyStore::new();
+    // Tuples are given IDs distinct from the ones allocated when the module is resolved, under a
+    // module ID derived from the one of the module
+    let mod_id = ctx.stable_mod_id(&format!("{}#declarations", ast_program.module.id.0));
+    let mut checker = type_check::TypeChecker::new(ctx, &store, mod_id);
+    let mut name_resolver = resolver::NameResolver::new(error_handler);
+    let program = name_resolver.declare(ast_program, namespace, ctx, &mut checker, known_values);
+    ctx.record_phase(Phase::NameResolution, start);
+    let start = Instant::now();
+    let _ = checker.type_check(&program.structs, error_handler);
+    ctx.record_phase(Phase::TypeCheck, start);
+    if error_handler.has_error() {
+        return Err(());
+    }
+
+    let mut hir_producer = ast_to_hir::HirProducer::new(error_handler, known_values);
+    let declarations = hir_producer.reduce_declarations(program, checker);
+    if error_handler.has_error() {
+        return Err(());
+    }
+    Ok(declarations)
 }
@@ -1,10 +1,12 @@
+use super::hir::Intrinsic;
 use super::store::Store;
+use super::symbols::{FieldAccess, NodeMap, ScopeMap};
 use crate::ast;
-use crate::ast::{BinaryOperator, Module, UnaryOperator};
+use crate::ast::{BinaryOperator, Memory, Module, UnaryOperator};
 use crate::ctx::ModId;
 use crate::error::Location;
-use crate::mir::Value as MirValue;
-use std::collections::HashMap;
+use crate::mir::{Atomic as MirAtomic, Simd as MirSimd, Value as MirValue};
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 
 pub use super::store::{DataId, FunId, StructId, TupleId, TypeId};
@@ -19,18 +21,52 @@ pub type FunStore = Store<FunId, Function>;
 /// A resolved program, ready to be typechecked.
 pub struct ResolvedProgram {
     pub funs: Vec<Function>,
+    /// Functions synthesized from `init` blocks, in declaration order.
+    pub inits: Vec<FunId>,
+    pub memory: Option<Memory>,
+    pub exports: ModuleExports,
     pub data: DataStore,
     pub structs: StructStore,
     pub fun_types: HashMap<FunId, TypeVar>,
     pub imports: Vec<Imports>,
     pub names: NameStore,
+    pub references: Vec<Reference>,
+    pub nodes: NodeMap,
+    pub scopes: ScopeMap,
+    /// Field accesses to be mapped to nodes once the program is type checked.
+    pub field_accesses: Vec<FieldAccess>,
+    /// Modules imported with `use` but never referenced.
+    pub unused_mods: Vec<(String, Location)>,
+    /// Names which are read at least once, assignments do not count as reads.
+    pub read_names: HashSet<NameId>,
+    /// Names shadowing a declaration from an enclosing scope.
+    pub shadowings: Vec<NameId>,
     pub module: Module,
 }
 
+/// The declarations of a module, function bodies are left unresolved.
+pub struct DeclaredProgram {
+    pub funs: Vec<FunctionSignature>,
+    pub imports: Vec<Imports>,
+    pub structs: StructStore,
+    pub fun_types: HashMap<FunId, TypeVar>,
+    pub module: Module,
+}
+
+/// A function declared by a module, whose type is in `DeclaredProgram::fun_types`.
+pub struct FunctionSignature {
+    pub ident: String,
+    pub fun_id: FunId,
+    pub is_pub: bool,
+    pub loc: Location,
+}
+
 /// All the kind of values that can be found in the Value Namespace.
 pub enum ValueKind {
     Function(FunId, TypeVar),
     Module(ModId),
+    /// A declaration of another module, imported with `use`.
+    Imported(ModId),
 }
 
 /// All the kind of types that can be found in the Type Namespace.
@@ -39,8 +75,22 @@ pub enum TypeKind {
     Struct(StructId),
 }
 
+/// Compile-time constants placed in data segments, data can point to other data.
 pub enum Data {
-    Str(DataId, Vec<u8>),
+    /// Raw bytes, such as the content of a string literal.
+    Bytes(Vec<u8>),
+    /// An immutable struct whose fields are constants.
+    Struct {
+        struct_id: StructId,
+        fields: Vec<(String, Constant)>,
+    },
+}
+
+/// A constant field of a data.
+pub enum Constant {
+    I32(i32),
+    /// The address of another data, known once data are laid out in memory.
+    DataPointer(DataId),
 }
 
 pub struct Imports {
@@ -53,22 +103,60 @@ pub struct Function {
     pub ident: String,
     pub params: Vec<Variable>,
     pub locals: Vec<NameId>,
+    /// The variables captured from the enclosing functions, `None` if the function is not a
+    /// closure.
+    pub captures: Option<Vec<NameId>>,
     pub body: Body,
     pub is_pub: bool,
+    pub inline: Inline,
+    /// Whether the function is marked with `#[test]`.
+    pub is_test: bool,
+    /// Whether the function is marked with `#[entry]`.
+    pub is_entry: bool,
     pub exposed: Option<String>,
     pub loc: Location,
+    /// Location of the `fun` keyword, see `ast::Function`.
+    pub keyword_loc: Location,
+    pub span: Location,
     pub fun_id: FunId,
 }
 
 pub struct DeclaredFunction {
     pub ident: String,
     pub params: Vec<(ast::Parameter, TypeVar)>,
+    /// The type of the values returned by the function, return statements are checked against it.
+    pub ret: TypeVar,
     pub body: ast::Body,
     pub is_pub: bool,
+    pub inline: Inline,
+    pub is_test: bool,
+    pub is_entry: bool,
     pub loc: Location,
+    pub keyword_loc: Location,
+    pub span: Location,
     pub fun_id: FunId,
 }
 
+/// Names under which the memory and the function table are exported to the host, set with the
+/// `#[export_memory("name")]` and `#[export_table("name")]` module attributes.
+///
+/// The memory is exported as "memory" by default unless compiling without a runtime, the table is
+/// not exported by default.
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct ModuleExports {
+    pub memory: Option<String>,
+    pub table: Option<String>,
+}
+
+/// Inlining preference of a function, set with the `#[inline]` and `#[no_inline]` attributes.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Inline {
+    /// Let the compiler decide.
+    Auto,
+    Always,
+    Never,
+}
+
 pub struct FunctionPrototype {
     pub ident: String,
     pub n_id: NameId,
@@ -130,14 +218,38 @@ pub enum Statement {
         expr: Option<Expression>,
         loc: Location,
     },
+    /// An expression executed when leaving the enclosing block.
+    DeferStmt {
+        expr: Expression,
+        loc: Location,
+    },
+}
+
+impl Statement {
+    pub fn get_loc(&self) -> Location {
+        match self {
+            Statement::ExprStmt(expr) => expr.get_loc(),
+            Statement::LetStmt { var, expr } => var.loc.merge(expr.get_loc()),
+            Statement::AssignStmt { target, expr } => target.get_loc().merge(expr.get_loc()),
+            Statement::IfStmt { expr, .. } => expr.get_loc(),
+            Statement::WhileStmt { expr, .. } => expr.get_loc(),
+            Statement::ReturnStmt { expr, loc } => match expr {
+                Some(expr) => loc.merge(expr.get_loc()),
+                None => *loc,
+            },
+            Statement::DeferStmt { expr, loc } => loc.merge(expr.get_loc()),
+        }
+    }
 }
 
+#[derive(Clone)]
 pub struct Variable {
     pub ident: String,
     pub loc: Location,
     pub n_id: NameId,
 }
 
+#[derive(Clone)]
 pub enum Value {
     Integer {
         val: u64,
@@ -155,8 +267,8 @@ pub enum Value {
         t_var: TypeVar,
     },
     Str {
+        /// The constant `Str` struct.
         data_id: DataId,
-        len: u64,
         loc: Location,
         t_var: TypeVar,
     },
@@ -173,6 +285,7 @@ pub enum Value {
     },
 }
 
+#[derive(Clone)]
 pub struct FieldValue {
     pub ident: String,
     pub expr: Box<Expression>,
@@ -180,12 +293,14 @@ pub struct FieldValue {
     pub t_var: TypeVar,
 }
 
+#[derive(Clone)]
 pub enum Expression {
     Variable(Variable),
     Literal(Value),
     Function {
         fun_id: FunId,
         loc: Location,
+        t_var: TypeVar,
     },
     Access {
         expr: Box<Expression>,
@@ -212,6 +327,18 @@ pub enum Expression {
         loc: Location,
         op_t_var: TypeVar,
     },
+    /// `&place`, `t_var` is the type of the reference.
+    Reference {
+        expr: Box<Expression>,
+        loc: Location,
+        t_var: TypeVar,
+    },
+    /// `*expr`, `t_var` is the type of the referenced value.
+    Dereference {
+        expr: Box<Expression>,
+        loc: Location,
+        t_var: TypeVar,
+    },
     CallDirect {
         fun_id: FunId,
         args: Vec<Expression>,
@@ -227,6 +354,26 @@ pub enum Expression {
         fun_t_var: TypeVar,
         ret_t_var: TypeVar,
     },
+    /// An anonymous function along with the variables it captures.
+    Closure {
+        fun_id: FunId,
+        captures: Vec<Variable>,
+        loc: Location,
+        t_var: TypeVar,
+    },
+    /// A call to the `format` built-in, the parts are the pieces of the format string and the
+    /// arguments in order. They are appended to a string depending on their types once known.
+    Format {
+        parts: Vec<(Expression, TypeVar)>,
+        loc: Location,
+    },
+    /// A call to an intrinsic, whose arguments and result have the scalar types expected by the
+    /// intrinsic.
+    Intrinsic {
+        intrinsic: Intrinsic,
+        args: Vec<Expression>,
+        loc: Location,
+    },
 }
 
 impl Expression {
@@ -245,9 +392,14 @@ impl Expression {
             Expression::Access { loc, .. } => *loc,
             Expression::Namespace { loc, .. } => *loc,
             Expression::Unary { loc, .. } => *loc,
+            Expression::Reference { loc, .. } => *loc,
+            Expression::Dereference { loc, .. } => *loc,
             Expression::Binary { loc, .. } => *loc,
             Expression::CallDirect { loc, .. } => *loc,
             Expression::CallIndirect { loc, .. } => *loc,
+            Expression::Closure { loc, .. } => *loc,
+            Expression::Format { loc, .. } => *loc,
+            Expression::Intrinsic { loc, .. } => *loc,
         }
     }
 }
@@ -258,6 +410,9 @@ pub enum AsmStatement {
     Control { cntrl: AsmControl, loc: Location },
     Parametric { param: AsmParametric, loc: Location },
     Memory { mem: AsmMemory, loc: Location },
+    Simd { simd: MirSimd, loc: Location },
+    Atomic { atomic: MirAtomic, loc: Location },
+    Raw { bytes: Vec<u8>, loc: Location },
 }
 
 pub enum AsmLocal {
@@ -266,13 +421,16 @@ pub enum AsmLocal {
 }
 
 impl AsmStatement {
-    pub fn _get_loc(&self) -> Location {
+    pub fn get_loc(&self) -> Location {
         match self {
             AsmStatement::Local { loc, .. } => *loc,
             AsmStatement::Const { loc, .. } => *loc,
             AsmStatement::Control { loc, .. } => *loc,
             AsmStatement::Parametric { loc, .. } => *loc,
             AsmStatement::Memory { loc, .. } => *loc,
+            AsmStatement::Simd { loc, .. } => *loc,
+            AsmStatement::Atomic { loc, .. } => *loc,
+            AsmStatement::Raw { loc, .. } => *loc,
         }
     }
 }
@@ -285,6 +443,12 @@ impl fmt::Display for AsmStatement {
             AsmStatement::Control { cntrl, .. } => write!(f, "{}", cntrl),
             AsmStatement::Parametric { param, .. } => write!(f, "{}", param),
             AsmStatement::Memory { mem, .. } => write!(f, "{}", mem),
+            AsmStatement::Simd { simd, .. } => write!(f, "{}", simd),
+            AsmStatement::Atomic { atomic, .. } => write!(f, "{}", atomic),
+            AsmStatement::Raw { bytes, .. } => {
+                let bytes: Vec<String> = bytes.iter().map(|b| format!("{:#04x}", b)).collect();
+                write!(f, "raw {}", bytes.join(" "))
+            }
         }
     }
 }
@@ -306,6 +470,20 @@ pub struct Name {
     pub t_var: TypeVar,
 }
 
+/// A use of a name or function, kept around for editor tooling.
+pub struct Reference {
+    pub ident: String,
+    pub loc: Location,
+    pub decl: Declaration,
+    pub t_var: TypeVar,
+}
+
+/// The declaration a reference points to.
+pub enum Declaration {
+    Name(NameId),
+    Function(FunId),
+}
+
 pub struct NameStore {
     names: Vec<Name>,
 }
@@ -330,6 +508,10 @@ impl NameStore {
         self.names.push(n);
         id
     }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Name> {
+        self.names.iter()
+    }
 }
 
 impl fmt::Display for NameStore {
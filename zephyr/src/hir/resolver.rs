@@ -1,13 +1,17 @@
 use super::hir;
-use super::hir::{FunKind, ScalarType};
+use super::hir::{Intrinsic, ScalarType};
 use super::names::*;
 use super::store::Store;
+use super::symbols::{Entity, FieldAccess, Node, NodeMap, Scope, ScopeId, ScopeMap};
 use super::type_check::{TypeChecker, TypeVar};
 use crate::ast;
-use crate::ctx::{Ctx, KnownValues, ModId, ModuleDeclarations, ValueDeclaration};
-use crate::error::{ErrorHandler, Location};
+use crate::ctx::{
+    Ctx, KnownFunctions, KnownStructs, KnownValues, ModId, ModuleDeclarations, ValueDeclaration,
+};
+use crate::error::{similar_name, ErrorHandler, Location, Suggestion};
 
-use std::collections::HashMap;
+use std::collections::btree_map::Entry;
+use std::collections::{hash_map, HashMap, HashSet};
 
 type ValueNamespace = HashMap<String, ValueKind>;
 type TypeNamespace = HashMap<String, TypeVar>;
@@ -15,12 +19,35 @@ type TypeNamespace = HashMap<String, TypeVar>;
 struct State<'a, 'ctx, 'ty> {
     names: NameStore,
     data: DataStore,
+    /// Data of the string literals, identical literals share the same data.
+    strs: HashMap<String, DataId>,
     funs: FunStore,
     fun_types: HashMap<FunId, TypeVar>,
+    references: Vec<Reference>,
+    nodes: NodeMap,
+    scopes: ScopeMap,
+    field_accesses: Vec<FieldAccess>,
     contexts: Vec<HashMap<String, usize>>,
+    /// The scope of each context.
+    context_scopes: Vec<ScopeId>,
+    /// The closures being resolved, from the outermost to the innermost.
+    closures: Vec<ClosureScope>,
+    /// The functions synthesized from lambda expressions.
+    lambdas: Vec<Function>,
     value_namespace: ValueNamespace,
     type_namespace: TypeNamespace,
     imported_modules: HashMap<String, ModId>,
+    used_mods: Vec<(String, Location)>,
+    /// Modules imported under an alias, the name they would have without it maps to the alias.
+    aliased_mods: HashMap<String, String>,
+    referenced_mods: HashSet<String>,
+    /// Declarations imported with `use pkg.mod.{a, b}` or `use pkg.mod.*`.
+    imported_items: HashMap<String, ImportedItem>,
+    /// Names imported by several glob imports, they map to the last of these imports.
+    ambiguous_items: HashMap<String, Location>,
+    read_names: HashSet<NameId>,
+    /// Names shadowing a declaration from an enclosing scope.
+    shadowings: Vec<NameId>,
     checker: &'a mut TypeChecker<'ctx, 'ty>,
     known_values: &'a KnownValues,
     mod_id: ModId,
@@ -36,45 +63,123 @@ impl<'a, 'ctx, 'ty> State<'a, 'ctx, 'ty> {
         known_values: &'a KnownValues,
     ) -> Self {
         let contexts = vec![HashMap::new()];
+        let top_level = ScopeId { mod_id, index: 0 };
+        let mut scopes = ScopeMap::new();
+        scopes.insert(
+            top_level,
+            Scope {
+                parent: None,
+                names: HashMap::new(),
+            },
+        );
         Self {
             data: Store::new(mod_id),
+            strs: HashMap::new(),
             funs: Store::new(mod_id),
             names: NameStore::new(),
             fun_types: HashMap::new(),
+            references: Vec::new(),
+            nodes: NodeMap::new(),
+            scopes,
+            field_accesses: Vec::new(),
             value_namespace: HashMap::new(),
             type_namespace: HashMap::new(),
             checker,
             contexts,
+            context_scopes: vec![top_level],
+            closures: Vec::new(),
+            lambdas: Vec::new(),
             imported_modules,
+            used_mods: Vec::new(),
+            aliased_mods: HashMap::new(),
+            referenced_mods: HashSet::new(),
+            imported_items: HashMap::new(),
+            ambiguous_items: HashMap::new(),
+            read_names: HashSet::new(),
+            shadowings: Vec::new(),
             known_values,
             mod_id,
             ctx,
         }
     }
 
+    /// Registers a string literal as a constant `Str` struct pointing to the bytes of the string,
+    /// returns the data of the struct.
+    pub fn declare_str(&mut self, val: String, str_s_id: StructId) -> DataId {
+        let entry = match self.strs.entry(val) {
+            hash_map::Entry::Occupied(entry) => return *entry.get(),
+            hash_map::Entry::Vacant(entry) => entry,
+        };
+        let len = entry.key().len() as i32;
+        let bytes_id = self.data.fresh_id();
+        self.data
+            .insert(bytes_id, Data::Bytes(entry.key().clone().into_bytes()));
+        let data_id = self.data.fresh_id();
+        self.data.insert(
+            data_id,
+            Data::Struct {
+                struct_id: str_s_id,
+                fields: vec![
+                    (String::from("len"), Constant::I32(len)),
+                    (String::from("start"), Constant::DataPointer(bytes_id)),
+                ],
+            },
+        );
+        *entry.insert(data_id)
+    }
+
+    /// Returns the modules imported with `use` which have never been referenced.
+    pub fn unused_mods(&self) -> Vec<(String, Location)> {
+        self.used_mods
+            .iter()
+            .filter(|(ident, _)| !self.referenced_mods.contains(ident))
+            .cloned()
+            .collect()
+    }
+
     /// Starts a new scope.
     pub fn new_scope(&mut self) {
+        let scope_id = ScopeId {
+            mod_id: self.mod_id,
+            index: self.scopes.len() as u32,
+        };
+        let scope = Scope {
+            parent: self.context_scopes.last().copied(),
+            names: HashMap::new(),
+        };
+        self.scopes.insert(scope_id, scope);
         self.contexts.push(HashMap::new());
+        self.context_scopes.push(scope_id);
     }
 
     /// Exit the current scope.
     pub fn exit_scope(&mut self) {
         self.contexts.pop();
+        self.context_scopes.pop();
     }
 
-    /// Declare a name, will fail if the name already exists in the current context or corresponds
-    /// to an import alias.
+    /// Declare a name, will fail if the name already exists in the current scope or corresponds
+    /// to an import alias. Names declared in enclosing scopes are shadowed.
     pub fn declare(&mut self, ident: String, loc: Location) -> Result<(NameId, TypeVar), Location> {
-        if let Some(n) = self.find_in_context(&ident) {
-            return Err(n.loc);
+        let current_scope = self.contexts.last().and_then(|ctx| ctx.get(&ident));
+        if let Some(id) = current_scope {
+            return Err(self.names.get(*id).loc);
         } else if let Some(_) = self.imported_modules.get(&ident) {
             return Err(Location::dummy()); // TODO: get the location of the corresponding `use` statement.
         }
+        let shadows = self.find_in_context(&ident).is_some();
 
         let ident_key = ident.clone();
         let t_var = self.checker.fresh();
         let n_id = self.names.fresh(ident, loc, t_var);
+        let entity = Entity::Local(self.mod_id, n_id);
+        if let Some(scope) = self.current_scope() {
+            scope.names.insert(ident_key.clone(), entity);
+        }
         self.add_in_context(ident_key, n_id);
+        if shadows {
+            self.shadowings.push(n_id);
+        }
         Ok((n_id, t_var))
     }
 
@@ -89,6 +194,50 @@ impl<'a, 'ctx, 'ty> State<'a, 'ctx, 'ty> {
         None
     }
 
+    /// Returns the name in scope closest to `ident`, if any is likely to be a typo of `ident`.
+    pub fn find_similar(&self, ident: &str) -> Option<&str> {
+        let names = self.contexts.iter().flat_map(|ctx| ctx.keys());
+        let values = self.value_namespace.keys();
+        let mods = self.imported_modules.keys();
+        similar_name(ident, names.chain(values).chain(mods).map(String::as_str))
+    }
+
+    /// Records the name as captured by the closures being resolved, if it is declared by a
+    /// function enclosing them.
+    pub fn capture(&mut self, ident: &str, loc: Location) {
+        let depth = match self
+            .contexts
+            .iter()
+            .rposition(|ctx| ctx.contains_key(ident))
+        {
+            Some(depth) => depth,
+            None => return,
+        };
+        let n_id = self.contexts[depth][ident];
+        for closure in self.closures.iter_mut().filter(|c| depth < c.depth) {
+            if !closure.captures.iter().any(|var| var.n_id == n_id) {
+                closure.captures.push(Variable {
+                    ident: ident.to_owned(),
+                    loc,
+                    n_id,
+                });
+            }
+        }
+    }
+
+    /// Returns true if the name is declared outside of the innermost closure being resolved.
+    pub fn is_captured(&self, ident: &str) -> bool {
+        match (
+            self.closures.last(),
+            self.contexts
+                .iter()
+                .rposition(|ctx| ctx.contains_key(ident)),
+        ) {
+            (Some(closure), Some(depth)) => depth < closure.depth,
+            _ => false,
+        }
+    }
+
     /// Adds a new declaration in the current context.
     fn add_in_context(&mut self, name: String, id: usize) {
         match self.contexts.last_mut() {
@@ -103,6 +252,158 @@ impl<'a, 'ctx, 'ty> State<'a, 'ctx, 'ty> {
             .insert(ident, ValueKind::Function(fun_id, t_var));
         self.fun_types.insert(fun_id, t_var);
     }
+
+    /// Removes a declaration imported with `use` from the namespaces.
+    fn remove_import(&mut self, ident: &str) {
+        if let Some(item) = self.imported_items.remove(ident) {
+            if item.is_value {
+                self.value_namespace.remove(ident);
+            }
+            if item.is_type {
+                self.type_namespace.remove(ident);
+            }
+        }
+    }
+
+    /// Records a use of a declaration.
+    pub fn add_reference(
+        &mut self,
+        ident: String,
+        loc: Location,
+        decl: Declaration,
+        t_var: TypeVar,
+    ) {
+        self.references.push(Reference {
+            ident,
+            loc,
+            decl,
+            t_var,
+        });
+    }
+
+    /// Records the entity declared or referred to by an AST node, the node is assumed to be the
+    /// name itself and to be looked up in the current scope.
+    fn add_node(
+        &mut self,
+        id: ast::NodeId,
+        loc: Location,
+        entity: Entity,
+        is_declaration: bool,
+    ) -> &mut Node {
+        let node = Node {
+            loc,
+            name_loc: loc,
+            entity,
+            is_declaration,
+            scope: self.context_scopes.last().copied(),
+        };
+        match self.nodes.entry(id) {
+            Entry::Occupied(mut entry) => {
+                entry.insert(node);
+                entry.into_mut()
+            }
+            Entry::Vacant(entry) => entry.insert(node),
+        }
+    }
+
+    /// Records a reference to a struct by an AST node, if `t_var` is known to be a struct.
+    fn add_struct_node(
+        &mut self,
+        id: ast::NodeId,
+        loc: Location,
+        t_var: TypeVar,
+    ) -> Option<&mut Node> {
+        match self.checker.get_t(t_var) {
+            Some(hir::Type::Struct(s_id)) => {
+                Some(self.add_node(id, loc, Entity::Struct(s_id), false))
+            }
+            _ => None,
+        }
+    }
+
+    /// Records the access to a field, mapped to the field once the program is type checked.
+    fn add_field_access(
+        &mut self,
+        id: ast::NodeId,
+        loc: Location,
+        field: String,
+        struct_t_var: TypeVar,
+    ) {
+        self.field_accesses.push(FieldAccess {
+            id,
+            loc,
+            field,
+            struct_t_var,
+        });
+    }
+
+    fn current_scope(&mut self) -> Option<&mut Scope> {
+        let scope_id = self.context_scopes.last()?;
+        self.scopes.get_mut(scope_id)
+    }
+
+    /// Records the top level declarations of the module in its top level scope, including the
+    /// declarations and the modules imported with `use`.
+    fn record_top_level_scope(&mut self) {
+        let mut names = HashMap::new();
+        for (ident, value) in &self.value_namespace {
+            let entity = match value {
+                ValueKind::Function(fun_id, _) => Some(Entity::Function(*fun_id)),
+                ValueKind::Module(mod_id) => Some(Entity::Module(*mod_id)),
+                ValueKind::Imported(mod_id) => self.get_declared_entity(ident, *mod_id),
+            };
+            if let Some(entity) = entity {
+                names.insert(ident.clone(), entity);
+            }
+        }
+        for (ident, t_var) in &self.type_namespace {
+            if let Some(hir::Type::Struct(s_id)) = self.checker.get_t(*t_var) {
+                names.entry(ident.clone()).or_insert(Entity::Struct(s_id));
+            }
+        }
+        for (ident, mod_id) in &self.imported_modules {
+            names
+                .entry(ident.clone())
+                .or_insert(Entity::Module(*mod_id));
+        }
+        let top_level = ScopeId {
+            mod_id: self.mod_id,
+            index: 0,
+        };
+        if let Some(scope) = self.scopes.get_mut(&top_level) {
+            scope.names.extend(names);
+        }
+    }
+
+    /// Returns the entity named `ident` in the declarations of a module, values come first.
+    fn get_declared_entity(&self, ident: &str, mod_id: ModId) -> Option<Entity> {
+        let decls = self.ctx.get_mod_from_id(mod_id)?;
+        match decls.val_decls.get(ident) {
+            Some(ValueDeclaration::Function(fun_id)) => Some(Entity::Function(*fun_id)),
+            Some(ValueDeclaration::Module(mod_id)) => Some(Entity::Module(*mod_id)),
+            None => match decls.type_decls.get(ident) {
+                Some(hir::Type::Struct(s_id)) => Some(Entity::Struct(*s_id)),
+                _ => None,
+            },
+        }
+    }
+}
+
+/// A declaration imported from another module with `use`, it can be a value, a type or both.
+struct ImportedItem {
+    mod_id: ModId,
+    /// The location of the name for explicit imports, of the `use` statement for glob imports.
+    loc: Location,
+    is_glob: bool,
+    is_value: bool,
+    is_type: bool,
+}
+
+/// A closure being resolved, the names declared in the contexts below `depth` belong to the
+/// enclosing functions.
+struct ClosureScope {
+    depth: usize,
+    captures: Vec<Variable>,
 }
 
 pub struct NameResolver<'err, E: ErrorHandler> {
@@ -138,9 +439,12 @@ impl<'err, 'a, 'ctx, 'ty, E: ErrorHandler> NameResolver<'err, E> {
             ast_program.module.kind,
             &mut state,
         );
+        self.register_used_mods(ast_program.used, false, &mut state);
         let structs = self.register_and_resolve_structs(ast_program.structs, &mut state);
-        self.register_used_mods(ast_program.used, &mut state);
         let declared_funs = self.register_functions(funs, &mut state);
+        let declared_inits = self.register_inits(ast_program.inits, &mut state);
+        let memory = self.resolve_memory(ast_program.memories, ast_program.module.kind);
+        let exports = self.resolve_module_attributes(ast_program.attributes);
 
         // Resolve exposed funs
         let exposed_funs = self.resolve_exports(ast_program.exposed, &mut state);
@@ -151,18 +455,86 @@ impl<'err, 'a, 'ctx, 'ty, E: ErrorHandler> NameResolver<'err, E> {
                 named_funs.push(named_fun);
             }
         }
+        let mut inits = Vec::with_capacity(declared_inits.len());
+        for init in declared_inits.into_iter() {
+            if let Some(named_init) = self.resolve_function(init, &exposed_funs, &mut state) {
+                inits.push(named_init.fun_id);
+                named_funs.push(named_init);
+            }
+        }
+        named_funs.append(&mut state.lambdas);
 
+        let unused_mods = state.unused_mods();
+        state.record_top_level_scope();
         ResolvedProgram {
             funs: named_funs,
+            inits,
+            memory,
+            exports,
             structs,
             imports,
             data: state.data,
             names: state.names,
+            references: state.references,
+            nodes: state.nodes,
+            scopes: state.scopes,
+            field_accesses: state.field_accesses,
             fun_types: state.fun_types,
+            unused_mods,
+            read_names: state.read_names,
+            shadowings: state.shadowings,
             module: ast_program.module,
         }
     }
 
+    /// Registers the declarations of a module without resolving the function bodies.
+    ///
+    /// IDs are allocated in the same order as in `resolve`, the declarations can therefore be
+    /// published before the module is resolved. This lets the modules of an import cycle refer
+    /// to each others.
+    pub fn declare(
+        &mut self,
+        ast_program: &ast::Program,
+        imported_modules: HashMap<String, ModId>,
+        ctx: &'ctx Ctx,
+        checker: &'a mut TypeChecker<'ctx, 'ty>,
+        known_values: &'a KnownValues,
+    ) -> DeclaredProgram {
+        let mut state = State::new(
+            ast_program.module.id,
+            imported_modules,
+            checker,
+            ctx,
+            known_values,
+        );
+        let imports = self.register_and_resolve_imports(
+            ast_program.imports.clone(),
+            ast_program.module.kind,
+            &mut state,
+        );
+        // The functions of the other modules of the cycle may not be declared yet
+        self.register_used_mods(ast_program.used.clone(), true, &mut state);
+        let structs = self.register_and_resolve_structs(ast_program.structs.clone(), &mut state);
+        let mut funs = Vec::with_capacity(ast_program.funs.len());
+        for fun in &ast_program.funs {
+            let (fun_id, _, _) = self.register_function(fun, &mut state);
+            funs.push(FunctionSignature {
+                ident: fun.ident.clone(),
+                fun_id,
+                is_pub: fun.is_pub,
+                loc: fun.loc,
+            });
+        }
+
+        DeclaredProgram {
+            funs,
+            imports,
+            structs,
+            fun_types: state.fun_types,
+            module: ast_program.module.clone(),
+        }
+    }
+
     /// Check that each names used inside the function are correctly defined.
     /// Also responsible for checking if the function is exposed.
     fn resolve_function(
@@ -179,6 +551,8 @@ impl<'err, 'a, 'ctx, 'ty, E: ErrorHandler> NameResolver<'err, E> {
             match state.declare(param.ident.clone(), param.loc) {
                 Ok((n_id, t_var)) => {
                     state.checker.set_equal(t, t_var, self.err, param.loc);
+                    let entity = Entity::Local(state.mod_id, n_id);
+                    state.add_node(param.id, param.loc, entity, true);
                     fun_params.push(Variable {
                         ident: param.ident,
                         loc: param.loc,
@@ -200,17 +574,23 @@ impl<'err, 'a, 'ctx, 'ty, E: ErrorHandler> NameResolver<'err, E> {
 
         match fun.body {
             ast::Body::Zephyr(block) => {
-                let block = self.resolve_block(block, state, &mut locals, fun.fun_id);
+                let block = self.resolve_block(block, state, &mut locals, fun.ret);
                 state.exit_scope();
 
                 Some(Function {
                     ident: fun.ident,
                     params: fun_params,
                     locals,
+                    captures: None,
                     body: Body::Zephyr(block),
                     is_pub: fun.is_pub,
+                    inline: fun.inline,
+                    is_test: fun.is_test,
+                    is_entry: fun.is_entry,
                     exposed,
                     loc: fun.loc,
+                    keyword_loc: fun.keyword_loc,
+                    span: fun.span,
                     fun_id: fun.fun_id,
                 })
             }
@@ -222,10 +602,16 @@ impl<'err, 'a, 'ctx, 'ty, E: ErrorHandler> NameResolver<'err, E> {
                     ident: fun.ident,
                     params: fun_params,
                     locals,
+                    captures: None,
                     body: Body::Asm(stmts),
                     is_pub: fun.is_pub,
+                    inline: fun.inline,
+                    is_test: fun.is_test,
+                    is_entry: fun.is_entry,
                     exposed,
                     loc: fun.loc,
+                    keyword_loc: fun.keyword_loc,
+                    span: fun.span,
                     fun_id: fun.fun_id,
                 })
             }
@@ -237,12 +623,12 @@ impl<'err, 'a, 'ctx, 'ty, E: ErrorHandler> NameResolver<'err, E> {
         block: ast::Block,
         state: &mut State,
         locals: &mut Vec<NameId>,
-        fun_id: FunId,
+        ret: TypeVar,
     ) -> Block {
         state.new_scope();
         let mut stmts = Vec::new();
         for stmt in block.stmts.into_iter() {
-            let named_stmt = match self.resolve_stmt(stmt, state, locals, fun_id) {
+            let named_stmt = match self.resolve_stmt(stmt, state, locals, ret) {
                 Ok(stmt) => stmt,
                 Err(()) => {
                     self.err.silent_report();
@@ -261,11 +647,33 @@ impl<'err, 'a, 'ctx, 'ty, E: ErrorHandler> NameResolver<'err, E> {
         stmt: ast::Statement,
         state: &mut State,
         locals: &mut Vec<NameId>,
-        fun_id: FunId,
+        ret: TypeVar,
     ) -> Result<Statement, ()> {
         let stmt = match stmt {
             ast::Statement::AssignStmt { target, expr } => {
+                if let ast::Expression::Variable(var) = &target {
+                    if var.namespace.is_none() && state.is_captured(&var.ident) {
+                        self.err.report(
+                            var.loc,
+                            format!(
+                                "Variable '{}' is captured by value and can not be assigned",
+                                var.ident
+                            ),
+                        );
+                        return Err(());
+                    }
+                }
+                // Assigning a variable does not count as reading it.
+                let assigned = match &target {
+                    ast::Expression::Variable(var) if var.namespace.is_none() => state
+                        .find_in_context(&var.ident)
+                        .map(|name| (name.n_id, state.read_names.contains(&name.n_id))),
+                    _ => None,
+                };
                 let (target, target_t_var) = self.resolve_expression(target, state)?;
+                if let Some((n_id, false)) = assigned {
+                    state.read_names.remove(&n_id);
+                }
                 let (expr, expr_t_var) = self.resolve_expression(expr, state)?;
                 let loc = target.get_loc().merge(expr.get_loc());
                 state
@@ -277,6 +685,17 @@ impl<'err, 'a, 'ctx, 'ty, E: ErrorHandler> NameResolver<'err, E> {
                 match state.declare(var.ident.clone(), var.loc) {
                     Ok((n_id, var_t_var)) => {
                         locals.push(n_id);
+                        let entity = Entity::Local(state.mod_id, n_id);
+                        state.add_node(var.id, var.loc, entity, true);
+                        if let Some(t) = &var.t {
+                            if let Ok(t_var) = self.get_type(t, state) {
+                                let what = format!("variable '{}'", var.ident);
+                                let loc = var.loc.merge(t.get_loc());
+                                let t_var =
+                                    state.checker.fresh_with_origin(t_var, what, self.err, loc);
+                                state.checker.set_equal(t_var, var_t_var, self.err, loc);
+                            }
+                        }
                         let (expr, expr_t_var) = self.resolve_expression(expr, state)?;
                         let loc = var.loc.merge(expr.get_loc());
                         state
@@ -309,9 +728,9 @@ impl<'err, 'a, 'ctx, 'ty, E: ErrorHandler> NameResolver<'err, E> {
                 state
                     .checker
                     .set_type(expr_t_var, ScalarType::Bool, self.err, expr.get_loc());
-                let block = self.resolve_block(block, state, locals, fun_id);
+                let block = self.resolve_block(block, state, locals, ret);
                 let else_block = if let Some(else_block) = else_block {
-                    let else_block = self.resolve_block(else_block, state, locals, fun_id);
+                    let else_block = self.resolve_block(else_block, state, locals, ret);
                     Some(else_block)
                 } else {
                     None
@@ -327,23 +746,21 @@ impl<'err, 'a, 'ctx, 'ty, E: ErrorHandler> NameResolver<'err, E> {
                 state
                     .checker
                     .set_type(expr_t_id, ScalarType::Bool, self.err, expr.get_loc());
-                let block = self.resolve_block(block, state, locals, fun_id);
+                let block = self.resolve_block(block, state, locals, ret);
                 Statement::WhileStmt { expr, block }
             }
             ast::Statement::ReturnStmt { expr, loc } => {
-                // Fun return type
-                let fun_t_var = self.get_fun_t_var(fun_id, state)?;
-                // Add constraint
+                // Checked right away, so that a mismatch is reported on the return statement
                 if let Some(ret_expr) = expr {
                     let (expr, ret_t_var) = self.resolve_expression(ret_expr, state)?;
-                    state.checker.set_return(fun_t_var, ret_t_var, loc);
+                    state.checker.set_equal(ret, ret_t_var, self.err, loc);
                     Statement::ReturnStmt {
                         expr: Some(expr),
                         loc,
                     }
                 } else {
                     let null_t_var = state.checker.scalar(ScalarType::Null);
-                    state.checker.set_return(fun_t_var, null_t_var, loc);
+                    state.checker.set_equal(ret, null_t_var, self.err, loc);
                     Statement::ReturnStmt { expr: None, loc }
                 }
             }
@@ -351,6 +768,10 @@ impl<'err, 'a, 'ctx, 'ty, E: ErrorHandler> NameResolver<'err, E> {
                 let (expr, _) = self.resolve_expression(expr, state)?;
                 Statement::ExprStmt(expr)
             }
+            ast::Statement::DeferStmt { expr, loc } => {
+                let (expr, _) = self.resolve_expression(expr, state)?;
+                Statement::DeferStmt { expr, loc }
+            }
         };
         Ok(stmt)
     }
@@ -361,7 +782,7 @@ impl<'err, 'a, 'ctx, 'ty, E: ErrorHandler> NameResolver<'err, E> {
         state: &mut State,
     ) -> Result<(Expression, TypeVar), ()> {
         match expr {
-            ast::Expression::Unary { unop, expr } => {
+            ast::Expression::Unary { unop, expr, .. } => {
                 let (expr, op_t_var) = self.resolve_expression(*expr, state)?;
                 match unop {
                     ast::UnaryOperator::Minus => {
@@ -398,12 +819,49 @@ impl<'err, 'a, 'ctx, 'ty, E: ErrorHandler> NameResolver<'err, E> {
                         };
                         Ok((expr, op_t_var))
                     }
+                    ast::UnaryOperator::Reference => {
+                        let loc = expr.get_loc();
+                        match &expr {
+                            Expression::Variable(_)
+                            | Expression::Access { .. }
+                            | Expression::Dereference { .. } => (),
+                            _ => {
+                                self.err.report(
+                                    loc,
+                                    String::from(
+                                        "Only variables, fields and dereferences can be referenced",
+                                    ),
+                                );
+                                return Err(());
+                            }
+                        }
+                        let t_var = state.checker.fresh();
+                        state.checker.set_ref(t_var, op_t_var, self.err, loc);
+                        let expr = Expression::Reference {
+                            expr: Box::new(expr),
+                            loc,
+                            t_var,
+                        };
+                        Ok((expr, t_var))
+                    }
+                    ast::UnaryOperator::Dereference => {
+                        let loc = expr.get_loc();
+                        let t_var = state.checker.fresh();
+                        state.checker.set_ref(op_t_var, t_var, self.err, loc);
+                        let expr = Expression::Dereference {
+                            expr: Box::new(expr),
+                            loc,
+                            t_var,
+                        };
+                        Ok((expr, t_var))
+                    }
                 }
             }
             ast::Expression::Binary {
                 expr_left,
                 binop,
                 expr_right,
+                ..
             } => {
                 let (left_expr, left_t_var) = self.resolve_expression(*expr_left, state)?;
                 let (right_expr, right_t_var) = self.resolve_expression(*expr_right, state)?;
@@ -536,7 +994,7 @@ impl<'err, 'a, 'ctx, 'ty, E: ErrorHandler> NameResolver<'err, E> {
                 }
             }
             ast::Expression::Literal(value) => match value {
-                ast::Value::Integer { val, loc } => {
+                ast::Value::Integer { val, loc, .. } => {
                     let t_var = state.checker.fresh();
                     state.checker.set_one_of(
                         t_var,
@@ -547,7 +1005,7 @@ impl<'err, 'a, 'ctx, 'ty, E: ErrorHandler> NameResolver<'err, E> {
                     let expr = Expression::Literal(Value::Integer { val, loc, t_var });
                     Ok((expr, t_var))
                 }
-                ast::Value::Float { val, loc } => {
+                ast::Value::Float { val, loc, .. } => {
                     let t_var = state.checker.fresh();
                     state.checker.set_one_of(
                         t_var,
@@ -558,43 +1016,45 @@ impl<'err, 'a, 'ctx, 'ty, E: ErrorHandler> NameResolver<'err, E> {
                     let expr = Expression::Literal(Value::Float { val, loc, t_var });
                     Ok((expr, t_var))
                 }
-                ast::Value::Boolean { val, loc } => {
+                ast::Value::Boolean { val, loc, .. } => {
                     let t_var = state.checker.scalar(ScalarType::Bool);
                     let expr = Expression::Literal(Value::Boolean { val, loc, t_var });
                     Ok((expr, t_var))
                 }
-                ast::Value::Str { val, loc } => {
-                    let len = val.len() as u64;
-                    let data_id = state.data.fresh_id();
-                    state
-                        .data
-                        .insert(data_id, Data::Str(data_id, val.into_bytes()));
-                    let str_s_id = state.known_values.structs.str;
-                    let t_var = state.checker.fresh();
-                    state.checker.set_struct(t_var, str_s_id, self.err, loc);
-                    let expr = Expression::Literal(Value::Str {
-                        data_id,
-                        len,
-                        loc,
-                        t_var,
-                    });
-                    Ok((expr, t_var))
-                }
+                ast::Value::Str { val, loc, .. } => self.resolve_str(val, loc, state),
                 ast::Value::Struct {
                     namespace,
                     ident,
                     fields,
+                    id,
                     loc,
                 } => {
                     let t_var_struct = self.get_type_from_str(&ident, namespace, loc, state);
                     let t_var = state.checker.fresh();
                     if let Ok(t_var_struct) = t_var_struct {
                         state.checker.set_equal(t_var, t_var_struct, self.err, loc);
+                        if let Some(node) = state.add_struct_node(id, loc, t_var_struct) {
+                            node.name_loc = Location {
+                                len: ident.len() as u32,
+                                ..loc
+                            };
+                            if namespace.is_some() {
+                                node.scope = None;
+                            }
+                        }
                     }
+                    let s_id = match state.nodes.get(&id).map(|node| &node.entity) {
+                        Some(Entity::Struct(s_id)) => Some(*s_id),
+                        _ => None,
+                    };
                     let n = fields.len();
                     let mut hir_fields = Vec::with_capacity(n);
                     let mut field_types = Vec::with_capacity(n);
                     for field in fields {
+                        if let Some(s_id) = s_id {
+                            let entity = Entity::Field(s_id, field.ident.clone());
+                            state.add_node(field.id, field.loc, entity, false).scope = None;
+                        }
                         let (expr, t_var_field) = self.resolve_expression(field.expr, state)?;
                         field_types.push((t_var_field, field.ident.clone(), field.loc));
                         hir_fields.push(FieldValue {
@@ -615,7 +1075,7 @@ impl<'err, 'a, 'ctx, 'ty, E: ErrorHandler> NameResolver<'err, E> {
                     });
                     Ok((expr, t_var))
                 }
-                ast::Value::Tuple { values, loc } => {
+                ast::Value::Tuple { values, loc, .. } => {
                     let t_var = state.checker.fresh();
                     let mut values_t_vars = Vec::with_capacity(values.len());
                     let mut resolved_values = Vec::with_capacity(values.len());
@@ -636,30 +1096,76 @@ impl<'err, 'a, 'ctx, 'ty, E: ErrorHandler> NameResolver<'err, E> {
             ast::Expression::Variable(var) => {
                 let value = self.get_value(&var.ident, var.namespace, var.loc, state)?;
                 if let Some((expr, t_var)) = value {
+                    match expr {
+                        Expression::Function { fun_id, .. } => {
+                            let entity = Entity::Function(fun_id);
+                            let node = state.add_node(var.id, var.loc, entity, false);
+                            if var.namespace.is_some() {
+                                node.scope = None;
+                            }
+                            let decl = Declaration::Function(fun_id);
+                            state.add_reference(var.ident, var.loc, decl, t_var);
+                        }
+                        Expression::Namespace { mod_id, .. } => {
+                            state.add_node(var.id, var.loc, Entity::Module(mod_id), false);
+                        }
+                        _ => (),
+                    }
                     Ok((expr, t_var))
                 } else if let Some(name) = state.find_in_context(&var.ident) {
+                    let (n_id, t_var) = (name.n_id, name.t_var);
                     let expr = Expression::Variable(Variable {
                         ident: var.ident.clone(),
                         loc: var.loc,
-                        n_id: name.n_id,
+                        n_id,
                     });
-                    Ok((expr, name.t_var))
+                    state.read_names.insert(n_id);
+                    state.capture(&var.ident, var.loc);
+                    let entity = Entity::Local(state.mod_id, n_id);
+                    state.add_node(var.id, var.loc, entity, false);
+                    state.add_reference(var.ident, var.loc, Declaration::Name(n_id), t_var);
+                    Ok((expr, t_var))
                 } else if let Some(mod_id) = state.imported_modules.get(&var.ident) {
+                    let mod_id = *mod_id;
+                    state.referenced_mods.insert(var.ident.clone());
+                    state.add_node(var.id, var.loc, Entity::Module(mod_id), false);
                     let expr = Expression::Namespace {
-                        mod_id: *mod_id,
+                        mod_id,
                         loc: var.loc,
                     };
                     let t_var = state.checker.scalar(ScalarType::Null);
                     Ok((expr, t_var))
+                } else if let Some(alias) = state.aliased_mods.get(&var.ident) {
+                    let suggestion = Suggestion {
+                        loc: var.loc,
+                        replacement: alias.clone(),
+                        message: format!("module '{}' is imported as '{}'", var.ident, alias),
+                    };
+                    let message = format!("Variable {} used but not declared", var.ident);
+                    self.err
+                        .report_with_suggestion(var.loc, message, suggestion);
+                    Err(())
+                } else if self.report_ambiguous(&var.ident, var.loc, state) {
+                    Err(())
                 } else {
-                    self.err.report(
-                        var.loc,
-                        format!("Variable {} used but not declared", var.ident),
-                    );
+                    let message = format!("Variable {} used but not declared", var.ident);
+                    match state.find_similar(&var.ident) {
+                        Some(ident) => {
+                            let suggestion = Suggestion::typo(var.loc, ident);
+                            self.err
+                                .report_with_suggestion(var.loc, message, suggestion);
+                        }
+                        None => self.err.report(var.loc, message),
+                    }
                     return Err(());
                 }
             }
-            ast::Expression::Call { fun, args } => {
+            ast::Expression::Call { fun, args, .. } => {
+                if let ast::Expression::Variable(var) = &*fun {
+                    if let Some(built_in) = self.get_built_in(var, state) {
+                        return self.resolve_built_in(built_in, var.loc, args, state);
+                    }
+                }
                 let n = args.len();
                 let mut resolved_args = Vec::with_capacity(n);
                 let mut args_t_vars = Vec::with_capacity(n);
@@ -689,21 +1195,50 @@ impl<'err, 'a, 'ctx, 'ty, E: ErrorHandler> NameResolver<'err, E> {
                         };
                         Ok((expr, ret_t_var))
                     }
-                    _ => {
+                    Expression::Namespace { .. } => {
+                        self.err
+                            .report(loc, String::from("Only function can be called"));
+                        Err(())
+                    }
+                    fun => {
                         // Indirect call
-                        todo!("Indirect calls are not yet supported");
+                        let ret_t_var = state.checker.fresh();
+                        state.checker.set_call(fun_t_var, args_t_vars, loc);
+                        state.checker.set_return(fun_t_var, ret_t_var, loc);
+                        let expr = Expression::CallIndirect {
+                            fun: Box::new(fun),
+                            loc,
+                            args: resolved_args,
+                            fun_t_var,
+                            ret_t_var,
+                        };
+                        Ok((expr, ret_t_var))
                     }
                 }
             }
-            ast::Expression::Access { namespace, field } => {
+            ast::Expression::Access {
+                namespace, field, ..
+            } => {
                 let (expr, access_obj_t_var) = self.resolve_expression(*namespace, state)?;
                 match expr {
                     Expression::Variable { .. }
                     | Expression::Access { .. }
-                    | Expression::Literal(Value::Struct { .. }) => {
+                    | Expression::CallDirect { .. }
+                    | Expression::CallIndirect { .. }
+                    | Expression::Dereference { .. }
+                    | Expression::Literal(Value::Struct { .. })
+                    | Expression::Literal(Value::Tuple { .. }) => {
                         // Reduce the field
                         let (field, loc_field) = match &*field {
-                            ast::Expression::Variable(var) => (var.ident.clone(), var.loc),
+                            ast::Expression::Variable(var) => {
+                                let ident = var.ident.clone();
+                                state.add_field_access(var.id, var.loc, ident, access_obj_t_var);
+                                (var.ident.clone(), var.loc)
+                            }
+                            ast::Expression::Literal(ast::Value::Integer { val, loc, .. }) => {
+                                // Tuple field, `t.0` is equivalent to `t._0`
+                                (format!("_{}", val), *loc)
+                            }
                             _ => {
                                 let (expr, _) = self.resolve_expression(*field, state)?;
                                 self.err.report(
@@ -739,104 +1274,549 @@ impl<'err, 'a, 'ctx, 'ty, E: ErrorHandler> NameResolver<'err, E> {
                     _ => {
                         self.err.report(
                             expr.get_loc(),
-                            String::from("The left operand of an access must be an identifier, a struct or a module."),
+                            String::from("The left operand of an access must be an identifier, a call, a dereference, a struct, a tuple or a module."),
                         );
                         return Err(());
                     }
                 }
             }
+            ast::Expression::Lambda(lambda) => self.resolve_lambda(lambda, state),
         }
     }
 
-    /// Resolves a namespace expression by re-resolving the 'field' expression inside the new
-    /// namespace.
-    fn resolve_namespace_expr(
+    /// Resolves a string literal, which is stored as a constant `Str` struct.
+    fn resolve_str(
         &mut self,
-        mod_id: ModId,
-        namespace_loc: Location,
-        field: ast::Expression,
+        val: String,
+        loc: Location,
         state: &mut State,
     ) -> Result<(Expression, TypeVar), ()> {
-        match field {
-            ast::Expression::Variable(var) => {
-                let var = ast::Variable {
-                    namespace: Some(mod_id),
-                    ident: var.ident,
-                    t: var.t,
-                    loc: var.loc,
-                };
-                self.resolve_expression(ast::Expression::Variable(var), state)
-            }
-            ast::Expression::Literal(ast::Value::Struct {
-                ident, fields, loc, ..
-            }) => {
-                let value = ast::Value::Struct {
-                    namespace: Some(mod_id),
-                    ident,
-                    fields,
-                    loc,
-                };
-                self.resolve_expression(ast::Expression::Literal(value), state)
-            }
-            _ => {
-                self.err
-                    .report(namespace_loc, String::from("Invalid access"));
-                Err(())
-            }
-        }
+        let str_s_id = self.get_known_structs("String literals", loc, state)?.str;
+        let data_id = state.declare_str(val, str_s_id);
+        let t_var = state.checker.fresh();
+        state.checker.set_struct(t_var, str_s_id, self.err, loc);
+        let expr = Expression::Literal(Value::Str {
+            data_id,
+            loc,
+            t_var,
+        });
+        Ok((expr, t_var))
     }
 
-    fn resolve_asm(
-        &mut self,
-        stmts: Vec<ast::AsmStatement>,
-        state: &mut State,
-    ) -> Vec<AsmStatement> {
-        let mut resolved_stmts = Vec::with_capacity(stmts.len());
-
-        for stmt in stmts {
-            match self.resolve_asm_statement(stmt, state) {
-                Ok(stmt) => resolved_stmts.push(stmt),
-                Err(_) => self.err.silent_report(),
-            }
+    /// Returns the built-in called by a variable, if any. Built-ins are shadowed by any value or
+    /// module declared under the same name.
+    fn get_built_in(&self, var: &ast::Variable, state: &State) -> Option<BuiltIn> {
+        if var.namespace.is_some()
+            || state.value_namespace.contains_key(&var.ident)
+            || state.imported_modules.contains_key(&var.ident)
+            || state.find_in_context(&var.ident).is_some()
+        {
+            return None;
+        }
+        match var.ident.as_str() {
+            "concat" => Some(BuiltIn::Concat),
+            "format" => Some(BuiltIn::Format),
+            "assert" => Some(BuiltIn::Assert),
+            "assert_eq" => Some(BuiltIn::AssertEq),
+            "panic" => Some(BuiltIn::Panic),
+            ident => Intrinsic::from_name(ident).map(BuiltIn::Intrinsic),
         }
-
-        resolved_stmts
     }
 
-    fn resolve_asm_statement(
+    /// Resolves a call to a built-in.
+    fn resolve_built_in(
         &mut self,
-        stmt: ast::AsmStatement,
+        built_in: BuiltIn,
+        loc: Location,
+        args: Vec<ast::Expression>,
         state: &mut State,
-    ) -> Result<AsmStatement, ()> {
-        match stmt {
-            ast::AsmStatement::Control { cntrl, loc } => Ok(AsmStatement::Control { cntrl, loc }),
-            ast::AsmStatement::Memory { mem, loc } => Ok(AsmStatement::Memory { mem, loc }),
-            ast::AsmStatement::Const { val, loc } => Ok(AsmStatement::Const { val, loc }),
-            ast::AsmStatement::Parametric { param, loc } => {
-                Ok(AsmStatement::Parametric { param, loc })
-            }
-            ast::AsmStatement::Local { local, loc } => match local {
-                ast::AsmLocal::Get {
-                    ident,
-                    loc: arg_loc,
-                } => match state.find_in_context(&ident) {
-                    Some(name) => {
-                        let var = Variable {
-                            ident,
-                            loc: arg_loc,
-                            n_id: name.n_id,
-                        };
-                        Ok(AsmStatement::Local {
-                            local: AsmLocal::Get { var },
-                            loc,
-                        })
-                    }
-                    None => {
+    ) -> Result<(Expression, TypeVar), ()> {
+        match built_in {
+            BuiltIn::Concat => {
+                self.get_known_structs("'concat'", loc, state)?;
+                let mut val = String::new();
+                let mut loc = loc;
+                let mut is_valid = true;
+                for arg in args {
+                    if let Some((s, s_loc)) = self.get_const_str(&arg, state) {
+                        val.push_str(&s);
+                        loc = loc.merge(s_loc);
+                    } else {
+                        let (arg, _) = self.resolve_expression(arg, state)?;
                         self.err.report(
-                            arg_loc,
-                            format!("No variable '{}' in current scope.", &ident),
+                            arg.get_loc(),
+                            String::from("The arguments of 'concat' must be string literals"),
                         );
-                        Err(())
+                        is_valid = false;
+                    }
+                }
+                if !is_valid {
+                    return Err(());
+                }
+                self.resolve_str(val, loc, state)
+            }
+            BuiltIn::Format => {
+                let str_s_id = self.get_known_funs("'format'", loc, state)?.1.str;
+                let mut args = args.into_iter();
+                let (fmt, fmt_loc) = match args.next() {
+                    Some(arg) => match self.get_const_str(&arg, state) {
+                        Some(fmt) => fmt,
+                        None => {
+                            let (arg, _) = self.resolve_expression(arg, state)?;
+                            self.err.report(
+                                arg.get_loc(),
+                                String::from(
+                                    "The first argument of 'format' must be a string literal",
+                                ),
+                            );
+                            return Err(());
+                        }
+                    },
+                    None => {
+                        self.err
+                            .report(loc, String::from("'format' expects a format string"));
+                        return Err(());
+                    }
+                };
+                let pieces = self.split_format(&fmt, fmt_loc)?;
+                let mut resolved_args = Vec::new();
+                for arg in args {
+                    resolved_args.push(self.resolve_expression(arg, state)?);
+                }
+                let loc = match resolved_args.last() {
+                    Some((arg, _)) => loc.merge(arg.get_loc()),
+                    None => loc.merge(fmt_loc),
+                };
+                if pieces.len() != resolved_args.len() + 1 {
+                    self.err.report(
+                        loc,
+                        format!(
+                            "The format string expects {} arguments, got {}",
+                            pieces.len() - 1,
+                            resolved_args.len()
+                        ),
+                    );
+                    return Err(());
+                }
+                let mut parts = Vec::with_capacity(pieces.len() + resolved_args.len());
+                let mut resolved_args = resolved_args.into_iter();
+                for piece in pieces {
+                    if !piece.is_empty() {
+                        parts.push(self.resolve_str(piece, fmt_loc, state)?);
+                    }
+                    if let Some(arg) = resolved_args.next() {
+                        parts.push(arg);
+                    }
+                }
+                let t_var = state.checker.fresh();
+                state.checker.set_struct(t_var, str_s_id, self.err, loc);
+                Ok((Expression::Format { parts, loc }, t_var))
+            }
+            BuiltIn::Assert | BuiltIn::AssertEq => {
+                let (ident, n, expected) = match built_in {
+                    BuiltIn::Assert => ("assert", 1, "one argument"),
+                    _ => ("assert_eq", 2, "two arguments"),
+                };
+                let what = format!("'{}'", ident);
+                let fun_id = self.get_known_funs(&what, loc, state)?.0.assert;
+                if args.len() != n {
+                    self.err.report(
+                        loc,
+                        format!("'{}' expects {}, got {}", ident, expected, args.len()),
+                    );
+                    return Err(());
+                }
+                let mut args = args.into_iter();
+                let cond = match built_in {
+                    BuiltIn::Assert => args.next().unwrap(),
+                    _ => {
+                        let (left, right) = (args.next().unwrap(), args.next().unwrap());
+                        // The comparison is not written in the code, it borrows the ID of its
+                        // left operand.
+                        ast::Expression::Binary {
+                            id: left.id(),
+                            loc: left.get_loc().merge(right.get_loc()),
+                            expr_left: Box::new(left),
+                            binop: ast::BinaryOperator::Equal,
+                            expr_right: Box::new(right),
+                        }
+                    }
+                };
+                let (cond, cond_t_var) = self.resolve_expression(cond, state)?;
+                let loc = loc.merge(cond.get_loc());
+                self.resolve_assert(fun_id, cond, cond_t_var, loc, state)
+            }
+            BuiltIn::Panic => {
+                let fun_id = self.get_known_funs("'panic'", loc, state)?.0.panic;
+                if args.len() != 1 {
+                    self.err.report(
+                        loc,
+                        format!("'panic' expects a message, got {} arguments", args.len()),
+                    );
+                    return Err(());
+                }
+                let message = self.resolve_expression(args.into_iter().next().unwrap(), state)?;
+                let loc = loc.merge(message.0.get_loc());
+                self.resolve_known_call(fun_id, vec![message], loc, state)
+            }
+            BuiltIn::Intrinsic(intrinsic) => {
+                if intrinsic.is_simd() {
+                    self.require_feature(ast::Feature::Simd, loc, state)?;
+                } else if intrinsic.is_atomic() {
+                    self.require_feature(ast::Feature::Threads, loc, state)?;
+                }
+                let params = intrinsic.get_params();
+                if args.len() != params.len() {
+                    let expected = match params.len() {
+                        0 => String::from("no arguments"),
+                        1 => String::from("one argument"),
+                        n => format!("{} arguments", n),
+                    };
+                    self.err.report(
+                        loc,
+                        format!("'{}' expects {}, got {}", intrinsic, expected, args.len()),
+                    );
+                    return Err(());
+                }
+                if let Intrinsic::ExtractLane(shape) | Intrinsic::ReplaceLane(shape) = intrinsic {
+                    // The lane index is encoded in the instruction
+                    match &args[1] {
+                        ast::Expression::Literal(ast::Value::Integer { val, .. })
+                            if *val < shape.lanes() as u64 => {}
+                        arg => {
+                            self.err.report(
+                                arg.get_loc(),
+                                format!(
+                                    "'{}' expects a lane index literal between 0 and {}",
+                                    intrinsic,
+                                    shape.lanes() - 1
+                                ),
+                            );
+                            return Err(());
+                        }
+                    }
+                }
+                let mut resolved_args = Vec::with_capacity(args.len());
+                let mut loc = loc;
+                for (arg, t) in args.into_iter().zip(params) {
+                    let (arg, arg_t_var) = self.resolve_expression(arg, state)?;
+                    state
+                        .checker
+                        .set_type(arg_t_var, *t, self.err, arg.get_loc());
+                    loc = loc.merge(arg.get_loc());
+                    resolved_args.push(arg);
+                }
+                let expr = Expression::Intrinsic {
+                    intrinsic,
+                    args: resolved_args,
+                    loc,
+                };
+                Ok((expr, state.checker.scalar(intrinsic.get_t())))
+            }
+        }
+    }
+
+    /// Resolves an assertion to a call to `fun_id`, the assertion function of the runtime, along
+    /// with the file and line of the assertion.
+    fn resolve_assert(
+        &mut self,
+        fun_id: FunId,
+        cond: Expression,
+        cond_t_var: TypeVar,
+        loc: Location,
+        state: &mut State,
+    ) -> Result<(Expression, TypeVar), ()> {
+        let file = state.ctx.get_file_name(loc.f_id).unwrap_or("");
+        let file = self.resolve_str(file.to_owned(), loc, state)?;
+        let line_t_var = state.checker.scalar(ScalarType::I32);
+        let line = Expression::Literal(Value::Integer {
+            val: self.get_line(loc, state),
+            loc,
+            t_var: line_t_var,
+        });
+        let args = vec![(cond, cond_t_var), file, (line, line_t_var)];
+        self.resolve_known_call(fun_id, args, loc, state)
+    }
+
+    /// Resolves a call to a known function of `core`.
+    fn resolve_known_call(
+        &mut self,
+        fun_id: FunId,
+        args: Vec<(Expression, TypeVar)>,
+        loc: Location,
+        state: &mut State,
+    ) -> Result<(Expression, TypeVar), ()> {
+        let fun_t_var = match state.ctx.get_fun_signature(fun_id) {
+            Some((t, _)) => state.checker.lift_t_fun(t),
+            None => {
+                self.err
+                    .report_internal(loc, format!("Known function {} is not in context", fun_id));
+                return Err(());
+            }
+        };
+        let ret_t_var = state.checker.fresh();
+        let (args, args_t_vars) = args.into_iter().unzip();
+        state.checker.set_call(fun_t_var, args_t_vars, loc);
+        state.checker.set_return(fun_t_var, ret_t_var, loc);
+        let expr = Expression::CallDirect {
+            fun_id,
+            args,
+            loc,
+            fun_t_var,
+            ret_t_var,
+        };
+        Ok((expr, ret_t_var))
+    }
+
+    /// Returns the line of a location, starting at 1, or 0 if the file is not known.
+    fn get_line(&self, loc: Location, state: &State) -> u64 {
+        match state.ctx.sources().get(loc.f_id) {
+            Some(file) => file.line_index().line_of(loc.pos) as u64 + 1,
+            None => 0,
+        }
+    }
+
+    /// Returns the value of a string known at compile time, that is a string literal or a
+    /// concatenation of such strings, along with its location.
+    fn get_const_str(&self, expr: &ast::Expression, state: &State) -> Option<(String, Location)> {
+        match expr {
+            ast::Expression::Literal(ast::Value::Str { val, loc, .. }) => Some((val.clone(), *loc)),
+            ast::Expression::Call { fun, args, .. } => match &**fun {
+                ast::Expression::Variable(var) => match self.get_built_in(var, state) {
+                    Some(BuiltIn::Concat) => {
+                        let mut val = String::new();
+                        let mut loc = var.loc;
+                        for arg in args {
+                            let (s, s_loc) = self.get_const_str(arg, state)?;
+                            val.push_str(&s);
+                            loc = loc.merge(s_loc);
+                        }
+                        Some((val, loc))
+                    }
+                    _ => None,
+                },
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Splits a format string around its `{}` placeholders, braces are escaped as `{{` and `}}`.
+    fn split_format(&mut self, fmt: &str, loc: Location) -> Result<Vec<String>, ()> {
+        let mut pieces = vec![String::new()];
+        let mut chars = fmt.chars().peekable();
+        while let Some(c) = chars.next() {
+            match (c, chars.peek()) {
+                ('{', Some('{')) | ('}', Some('}')) => {
+                    chars.next();
+                    pieces.last_mut().unwrap().push(c);
+                }
+                ('{', Some('}')) => {
+                    chars.next();
+                    pieces.push(String::new());
+                }
+                ('{', _) | ('}', _) => {
+                    self.err.report(
+                        loc,
+                        String::from("Unmatched brace in format string, escape it as '{{' or '}}'"),
+                    );
+                    return Err(());
+                }
+                (c, _) => pieces.last_mut().unwrap().push(c),
+            }
+        }
+        Ok(pieces)
+    }
+
+    /// Resolves an anonymous function, which is registered as a function of the module. The
+    /// variables of the enclosing functions referred to by its body are captured by value.
+    fn resolve_lambda(
+        &mut self,
+        lambda: ast::Lambda,
+        state: &mut State,
+    ) -> Result<(Expression, TypeVar), ()> {
+        let mut params = Vec::with_capacity(lambda.params.len());
+        let mut declared_params = Vec::with_capacity(lambda.params.len());
+        for param in lambda.params {
+            let t = match self.get_type(&param.t, state) {
+                Ok(t) => self.param_t_var(&param, t, state),
+                Err(()) => state.checker.scalar(ScalarType::Null),
+            };
+            params.push(t);
+            declared_params.push((param, t));
+        }
+        let ret = match &lambda.result {
+            Some(t) => {
+                let t_var = self.get_type(t, state)?;
+                let what = String::from("return type of the lambda");
+                state
+                    .checker
+                    .fresh_with_origin(t_var, what, self.err, t.get_loc())
+            }
+            None => state.checker.scalar(ScalarType::Null),
+        };
+        let t_var = state.checker.fresh();
+        state
+            .checker
+            .set_fun(t_var, params, ret, self.err, lambda.loc);
+        let ret = match &lambda.result {
+            Some(_) => ret,
+            None => {
+                let what = String::from("signature of the lambda");
+                state
+                    .checker
+                    .fresh_with_origin(ret, what, self.err, lambda.loc)
+            }
+        };
+        let fun_id = state.funs.fresh_id();
+        state.fun_types.insert(fun_id, t_var);
+        state.add_node(lambda.id, lambda.loc, Entity::Function(fun_id), true);
+        let fun = DeclaredFunction {
+            ident: String::from("lambda"),
+            params: declared_params,
+            ret,
+            body: ast::Body::Zephyr(lambda.body),
+            is_pub: false,
+            inline: Inline::Auto,
+            is_test: false,
+            is_entry: false,
+            loc: lambda.loc,
+            keyword_loc: lambda.loc,
+            span: lambda.loc,
+            fun_id,
+        };
+
+        state.closures.push(ClosureScope {
+            depth: state.contexts.len(),
+            captures: Vec::new(),
+        });
+        let fun = self.resolve_function(fun, &HashMap::new(), state);
+        let captures = match state.closures.pop() {
+            Some(closure) => closure.captures,
+            None => Vec::new(),
+        };
+        if let Some(mut fun) = fun {
+            fun.captures = Some(captures.iter().map(|var| var.n_id).collect());
+            state.lambdas.push(fun);
+        }
+        let expr = Expression::Closure {
+            fun_id,
+            captures,
+            loc: lambda.loc,
+            t_var,
+        };
+        Ok((expr, t_var))
+    }
+
+    /// Resolves a namespace expression by re-resolving the 'field' expression inside the new
+    /// namespace.
+    fn resolve_namespace_expr(
+        &mut self,
+        mod_id: ModId,
+        namespace_loc: Location,
+        field: ast::Expression,
+        state: &mut State,
+    ) -> Result<(Expression, TypeVar), ()> {
+        match field {
+            ast::Expression::Variable(var) => {
+                let var = ast::Variable {
+                    namespace: Some(mod_id),
+                    ..var
+                };
+                self.resolve_expression(ast::Expression::Variable(var), state)
+            }
+            ast::Expression::Literal(ast::Value::Struct {
+                ident,
+                fields,
+                id,
+                loc,
+                ..
+            }) => {
+                let value = ast::Value::Struct {
+                    namespace: Some(mod_id),
+                    ident,
+                    fields,
+                    id,
+                    loc,
+                };
+                self.resolve_expression(ast::Expression::Literal(value), state)
+            }
+            _ => {
+                self.err
+                    .report(namespace_loc, String::from("Invalid access"));
+                Err(())
+            }
+        }
+    }
+
+    fn resolve_asm(
+        &mut self,
+        stmts: Vec<ast::AsmStatement>,
+        state: &mut State,
+    ) -> Vec<AsmStatement> {
+        let mut resolved_stmts = Vec::with_capacity(stmts.len());
+
+        for stmt in stmts {
+            match self.resolve_asm_statement(stmt, state) {
+                Ok(stmt) => resolved_stmts.push(stmt),
+                Err(_) => self.err.silent_report(),
+            }
+        }
+
+        resolved_stmts
+    }
+
+    fn resolve_asm_statement(
+        &mut self,
+        stmt: ast::AsmStatement,
+        state: &mut State,
+    ) -> Result<AsmStatement, ()> {
+        match stmt {
+            ast::AsmStatement::Control { cntrl, loc } => Ok(AsmStatement::Control { cntrl, loc }),
+            ast::AsmStatement::Memory { mem, loc } => {
+                if let AsmMemory::V128Load { .. } | AsmMemory::V128Store { .. } = mem {
+                    self.require_feature(ast::Feature::Simd, loc, state)?;
+                }
+                Ok(AsmStatement::Memory { mem, loc })
+            }
+            ast::AsmStatement::Simd { simd, loc } => {
+                self.require_feature(ast::Feature::Simd, loc, state)?;
+                Ok(AsmStatement::Simd { simd, loc })
+            }
+            ast::AsmStatement::Atomic { atomic, loc } => {
+                self.require_feature(ast::Feature::Threads, loc, state)?;
+                Ok(AsmStatement::Atomic { atomic, loc })
+            }
+            ast::AsmStatement::Const { val, loc } => Ok(AsmStatement::Const { val, loc }),
+            ast::AsmStatement::Raw { bytes, loc } => Ok(AsmStatement::Raw { bytes, loc }),
+            ast::AsmStatement::Parametric { param, loc } => {
+                Ok(AsmStatement::Parametric { param, loc })
+            }
+            ast::AsmStatement::Local { local, loc } => match local {
+                ast::AsmLocal::Get {
+                    ident,
+                    loc: arg_loc,
+                } => match state.find_in_context(&ident) {
+                    Some(name) => {
+                        let (n_id, t_var) = (name.n_id, name.t_var);
+                        state.read_names.insert(n_id);
+                        state.add_reference(ident.clone(), arg_loc, Declaration::Name(n_id), t_var);
+                        let var = Variable {
+                            ident,
+                            loc: arg_loc,
+                            n_id,
+                        };
+                        Ok(AsmStatement::Local {
+                            local: AsmLocal::Get { var },
+                            loc,
+                        })
+                    }
+                    None => {
+                        self.err.report(
+                            arg_loc,
+                            format!("No variable '{}' in current scope.", &ident),
+                        );
+                        Err(())
                     }
                 },
                 ast::AsmLocal::Set {
@@ -844,10 +1824,12 @@ impl<'err, 'a, 'ctx, 'ty, E: ErrorHandler> NameResolver<'err, E> {
                     loc: arg_loc,
                 } => match state.find_in_context(&ident) {
                     Some(name) => {
+                        let (n_id, t_var) = (name.n_id, name.t_var);
+                        state.add_reference(ident.clone(), arg_loc, Declaration::Name(n_id), t_var);
                         let var = Variable {
                             ident,
                             loc: arg_loc,
-                            n_id: name.n_id,
+                            n_id,
                         };
                         Ok(AsmStatement::Local {
                             local: AsmLocal::Set { var },
@@ -874,44 +1856,243 @@ impl<'err, 'a, 'ctx, 'ty, E: ErrorHandler> NameResolver<'err, E> {
     ) -> Vec<DeclaredFunction> {
         let mut declared_funs = Vec::with_capacity(funs.len());
         for fun in funs {
-            // Check parameters types
-            let mut params = Vec::new();
-            let mut declared_params = Vec::new();
-            for param in fun.params {
-                let t = if let Ok(t) = self.get_type(&param.t, state) {
-                    t
-                } else {
-                    state.checker.scalar(ScalarType::Null)
-                };
-                params.push(t.clone());
-                declared_params.push((param, t));
+            let (fun_id, params, ret) = self.register_function(&fun, state);
+            let (inline, is_test, is_entry) = self.fun_attributes(&fun.attributes);
+            let takes_arguments = !fun.params.is_empty() || fun.result.is_some();
+            if is_test && takes_arguments {
+                self.err.report(
+                    fun.loc,
+                    String::from("A test function must take no arguments and return nothing"),
+                );
+            }
+            if is_entry && takes_arguments {
+                self.err.report(
+                    fun.loc,
+                    String::from("The entry point must take no arguments and return nothing"),
+                );
+            }
+            declared_funs.push(DeclaredFunction {
+                ident: fun.ident,
+                params: fun.params.into_iter().zip(params).collect(),
+                ret,
+                body: fun.body,
+                is_pub: fun.is_pub,
+                inline,
+                is_test,
+                is_entry,
+                loc: fun.loc,
+                keyword_loc: fun.keyword_loc,
+                span: fun.span,
+                fun_id,
+            })
+        }
+
+        declared_funs
+    }
+
+    /// Register the signature of a top level function into the global state (`state`), returns
+    /// the ID of the function and the types of its parameters.
+    fn register_function(
+        &mut self,
+        fun: &ast::Function,
+        state: &mut State<'a, 'ctx, 'ty>,
+    ) -> (FunId, Vec<TypeVar>, TypeVar) {
+        // Check parameters types
+        let mut params = Vec::with_capacity(fun.params.len());
+        for param in &fun.params {
+            let t = if let Ok(t) = self.get_type(&param.t, state) {
+                self.param_t_var(param, t, state)
+            } else {
+                state.checker.scalar(ScalarType::Null)
+            };
+            params.push(t);
+        }
+
+        // Check result type
+        let mut ret = state.checker.scalar(ScalarType::Null);
+        if let Some(t) = &fun.result {
+            if let Ok(t_var) = self.get_type(t, state) {
+                let what = format!("return type of '{}'", fun.ident);
+                ret = state
+                    .checker
+                    .fresh_with_origin(t_var, what, self.err, t.get_loc());
             }
+        }
 
-            // Check result type
-            let mut ret = state.checker.scalar(ScalarType::Null);
-            if let Some(t) = &fun.result {
-                if let Ok(t) = self.get_type(t, state) {
-                    ret = t;
+        let fun_t_var = state.checker.fresh();
+        state
+            .checker
+            .set_fun(fun_t_var, params.clone(), ret, self.err, fun.loc);
+        let fun_id = state.funs.fresh_id();
+        match state.value_namespace.get(&fun.ident) {
+            Some(ValueKind::Module(_)) => self.report_name_conflict(&fun.ident, fun.loc, state),
+            Some(ValueKind::Imported(_)) => self.shadow_import(&fun.ident, fun.loc, state),
+            _ => (),
+        }
+        state.ambiguous_items.remove(&fun.ident);
+        state.declare_fun(fun.ident.clone(), fun_id, fun_t_var);
+        state.add_node(fun.id, fun.loc, Entity::Function(fun_id), true);
+        // Errors on the returns of functions without a result point to their signature
+        if fun.result.is_none() {
+            let what = format!("signature of '{}'", fun.ident);
+            ret = state
+                .checker
+                .fresh_with_origin(ret, what, self.err, fun.loc);
+        }
+        (fun_id, params, ret)
+    }
+
+    /// Returns the type variable of a parameter of type `t`, type errors involving the parameter
+    /// point back to its declaration.
+    fn param_t_var(&mut self, param: &ast::Parameter, t: TypeVar, state: &mut State) -> TypeVar {
+        let what = format!("parameter '{}'", param.ident);
+        let loc = param.loc.merge(param.t.get_loc());
+        state.checker.fresh_with_origin(t, what, self.err, loc)
+    }
+
+    /// Returns the inlining preference set by the attributes of a function and whether it is
+    /// marked as a test and as the entry point, unknown attributes are ignored with a warning.
+    fn fun_attributes(&mut self, attributes: &[ast::Attribute]) -> (Inline, bool, bool) {
+        let mut inline = Inline::Auto;
+        let mut is_test = false;
+        let mut is_entry = false;
+        for attribute in attributes {
+            let preference = match attribute.ident.as_str() {
+                "inline" => Some(Inline::Always),
+                "no_inline" => Some(Inline::Never),
+                "test" | "entry" => None,
+                _ => {
+                    self.err.warn(
+                        attribute.loc,
+                        format!("Unknown attribute '{}', it is ignored", attribute.ident),
+                    );
+                    continue;
+                }
+            };
+            if !attribute.args.is_empty() || !attribute.named_args.is_empty() {
+                self.err.warn(
+                    attribute.loc,
+                    format!("'{}' does not take arguments", attribute.ident),
+                );
+            }
+            let preference = match preference {
+                Some(preference) => preference,
+                None if attribute.ident == "test" => {
+                    is_test = true;
+                    continue;
                 }
+                None => {
+                    is_entry = true;
+                    continue;
+                }
+            };
+            if inline != Inline::Auto && inline != preference {
+                self.err.report(
+                    attribute.loc,
+                    String::from("A function can not be both 'inline' and 'no_inline'"),
+                );
             }
+            inline = preference;
+        }
+        (inline, is_test, is_entry)
+    }
 
+    /// Register init blocks as anonymous functions taking no arguments and returning nothing.
+    /// Init functions are not declared in the value namespace, they can not be called by the
+    /// user.
+    fn register_inits(
+        &mut self,
+        inits: Vec<ast::Init>,
+        state: &mut State<'a, 'ctx, 'ty>,
+    ) -> Vec<DeclaredFunction> {
+        let mut declared_inits = Vec::with_capacity(inits.len());
+        for init in inits {
+            let ret = state.checker.scalar(ScalarType::Null);
             let fun_t_var = state.checker.fresh();
             state
                 .checker
-                .set_fun(fun_t_var, params, ret, self.err, fun.loc);
+                .set_fun(fun_t_var, Vec::new(), ret, self.err, init.loc);
             let fun_id = state.funs.fresh_id();
-            state.declare_fun(fun.ident.clone(), fun_id, fun_t_var);
-            declared_funs.push(DeclaredFunction {
-                ident: fun.ident,
-                params: declared_params,
-                body: fun.body,
-                is_pub: fun.is_pub,
-                loc: fun.loc,
+            state.fun_types.insert(fun_id, fun_t_var);
+            state.add_node(init.id, init.loc, Entity::Function(fun_id), true);
+            declared_inits.push(DeclaredFunction {
+                ident: String::from("init"),
+                params: Vec::new(),
+                ret,
+                body: ast::Body::Zephyr(init.block),
+                is_pub: false,
+                inline: Inline::Auto,
+                is_test: false,
+                is_entry: false,
+                loc: init.loc,
+                keyword_loc: init.loc,
+                span: init.loc,
                 fun_id,
             })
         }
 
-        declared_funs
+        declared_inits
+    }
+
+    /// Returns the memory declared by the module, if any.
+    /// Will rise an error if memory is declared more than once or in an unappropriate module.
+    fn resolve_memory(
+        &mut self,
+        memories: Vec<ast::Memory>,
+        module_kind: ast::ModuleKind,
+    ) -> Option<ast::Memory> {
+        let mut memories = memories.into_iter();
+        let memory = memories.next()?;
+        if module_kind != ast::ModuleKind::Runtime {
+            self.err.report(
+                memory.loc,
+                String::from("Memory declarations are only permitted in 'runtime' modules."),
+            );
+        }
+        for duplicate in memories {
+            self.err.report(
+                duplicate.loc,
+                String::from("Memory is already declared in this module."),
+            );
+        }
+        Some(memory)
+    }
+
+    /// Returns the export names set by the attributes of the module declaration, unknown
+    /// attributes are ignored with a warning.
+    fn resolve_module_attributes(&mut self, attributes: Vec<ast::Attribute>) -> ModuleExports {
+        let mut exports = ModuleExports::default();
+        for attribute in attributes {
+            let export = match attribute.ident.as_str() {
+                "export_memory" => &mut exports.memory,
+                "export_table" => &mut exports.table,
+                _ => {
+                    self.err.warn(
+                        attribute.loc,
+                        format!("Unknown attribute '{}', it is ignored", attribute.ident),
+                    );
+                    continue;
+                }
+            };
+            let name = match attribute.args.as_slice() {
+                [name] if attribute.named_args.is_empty() => name.clone(),
+                _ => {
+                    self.err.report(
+                        attribute.loc,
+                        format!("'{}' expects a single name", attribute.ident),
+                    );
+                    continue;
+                }
+            };
+            if export.is_some() {
+                self.err.report(
+                    attribute.loc,
+                    format!("'{}' is already set for this module", attribute.ident),
+                );
+            }
+            *export = Some(name);
+        }
+        exports
     }
 
     /// Register top level imports into the global state (`state`) and return resolved
@@ -931,6 +2112,23 @@ impl<'err, 'a, 'ctx, 'ty, E: ErrorHandler> NameResolver<'err, E> {
                 String::from("Function imports are only permitted in 'runtime' modules."),
             );
         }
+        // Each function of the host can be imported only once
+        let mut imported: HashMap<(String, String), Location> = HashMap::new();
+        for import in &imports {
+            for proto in &import.prototypes {
+                let pair = (import.from.clone(), proto.ident.clone());
+                if let Some(previous) = imported.get(&pair) {
+                    self.err.report_with_note(
+                        proto.loc,
+                        format!("'{}' is already imported from '{}'", pair.1, pair.0),
+                        *previous,
+                        String::from("previously imported here"),
+                    );
+                } else {
+                    imported.insert(pair, proto.loc);
+                }
+            }
+        }
         for import in imports {
             resolved_imports.push(Imports {
                 from: import.from,
@@ -984,7 +2182,8 @@ impl<'err, 'a, 'ctx, 'ty, E: ErrorHandler> NameResolver<'err, E> {
                     state
                         .checker
                         .set_fun(t_var, params, ret, self.err, proto.loc);
-                    state.declare_fun(proto.ident.clone(), fun_id, t_var);
+                    state.declare_fun(ident.clone(), fun_id, t_var);
+                    state.add_node(proto.id, proto.loc, Entity::Function(fun_id), true);
                     resolved_protos.push(FunctionPrototype {
                         ident: proto.ident,
                         is_pub: proto.is_pub,
@@ -1004,15 +2203,21 @@ impl<'err, 'a, 'ctx, 'ty, E: ErrorHandler> NameResolver<'err, E> {
     }
 
     /// Register the top level structs in the Type namespace, then resolve the structs fields.
+    ///
+    /// IDs are allocated in declaration order, see `declare_structs`.
     fn register_and_resolve_structs(
         &mut self,
         structs: Vec<ast::Struct>,
         state: &mut State<'a, 'ctx, 'ty>,
     ) -> StructStore {
         let mut resolved_structs = Store::with_capacity(state.mod_id, structs.len());
-        for struc in structs {
+        let mut s_ids = Vec::with_capacity(structs.len());
+        for struc in &structs {
             let s_id = resolved_structs.fresh_id();
-            self.register_struct(&struc, s_id, state);
+            self.register_struct(struc, s_id, state);
+            s_ids.push(s_id);
+        }
+        for (struc, s_id) in structs.into_iter().zip(s_ids) {
             let s = self.resolve_struct(struc, s_id, state);
             resolved_structs.insert(s_id, s);
         }
@@ -1023,6 +2228,14 @@ impl<'err, 'a, 'ctx, 'ty, E: ErrorHandler> NameResolver<'err, E> {
     fn register_struct(&mut self, struc: &ast::Struct, s_id: StructId, state: &mut State) {
         let t_var = state.checker.fresh();
         state.checker.set_struct(t_var, s_id, self.err, struc.loc);
+        state.checker.declare_struct(s_id, struc.ident.clone());
+        state.add_node(struc.id, struc.loc, Entity::Struct(s_id), true);
+        let imported = state.imported_items.get(&struc.ident);
+        if imported.is_some_and(|item| item.is_type) {
+            state.type_namespace.remove(&struc.ident);
+            self.shadow_import(&struc.ident, struc.loc, state);
+        }
+        state.ambiguous_items.remove(&struc.ident);
         let exists = state
             .type_namespace
             .insert(struc.ident.clone(), t_var)
@@ -1047,10 +2260,20 @@ impl<'err, 'a, 'ctx, 'ty, E: ErrorHandler> NameResolver<'err, E> {
         for field in struc.fields {
             let loc = field.loc;
             let is_pub = field.is_pub;
-            let t_var = state.checker.fresh();
-            if let Ok(t) = self.get_type(&field.t, state) {
-                state.checker.set_equal(t_var, t, self.err, loc);
+            let t_var = match self.get_type(&field.t, state) {
+                Ok(t) => {
+                    let what = format!("field '{}'", field.ident);
+                    state.checker.fresh_with_origin(t, what, self.err, loc)
+                }
+                Err(()) => state.checker.fresh(),
             };
+            let entity = Entity::Field(s_id, field.ident.clone());
+            let node = state.add_node(field.id, loc, entity, true);
+            node.name_loc = Location {
+                len: field.ident.len() as u32,
+                ..loc
+            };
+            node.scope = None;
             fields.insert(field.ident, StructField { t_var, loc, is_pub });
         }
 
@@ -1063,21 +2286,36 @@ impl<'err, 'a, 'ctx, 'ty, E: ErrorHandler> NameResolver<'err, E> {
         }
     }
 
-    /// Resolve the exposed functions and return a map of function ID to their name.
+    /// Resolve the exposed functions and return a map of function ID to their name, two functions
+    /// can not be exposed under the same name.
     fn resolve_exports(
         &mut self,
         exposed: Vec<ast::Expose>,
         state: &mut State,
     ) -> HashMap<FunId, String> {
         let mut exposed_funs = HashMap::with_capacity(exposed.len());
+        let mut names: HashMap<String, Location> = HashMap::with_capacity(exposed.len());
         for fun in exposed {
             if let Some(ValueKind::Function(f_id, _)) = state.value_namespace.get(&fun.ident) {
+                let f_id = *f_id;
+                let node = state.add_node(fun.id, fun.loc, Entity::Function(f_id), false);
+                node.name_loc = fun.ident_loc;
                 let exposed_name = if let Some(alias) = fun.alias {
                     alias
                 } else {
                     fun.ident
                 };
-                exposed_funs.insert(*f_id, exposed_name);
+                if let Some(previous) = names.get(&exposed_name) {
+                    self.err.report_with_note(
+                        fun.loc,
+                        format!("A function is already exposed as '{}'", exposed_name),
+                        *previous,
+                        String::from("previously exposed here"),
+                    );
+                    continue;
+                }
+                names.insert(exposed_name.clone(), fun.loc);
+                exposed_funs.insert(f_id, exposed_name);
             } else {
                 self.err.report(
                     fun.loc,
@@ -1088,33 +2326,226 @@ impl<'err, 'a, 'ctx, 'ty, E: ErrorHandler> NameResolver<'err, E> {
         exposed_funs
     }
 
-    /// Add the imported modules to the global namespace.
-    fn register_used_mods(&mut self, used: Vec<ast::Use>, state: &mut State<'a, 'ctx, 'ty>) {
+    /// Add the imported modules and declarations to the global namespace.
+    ///
+    /// Local declarations shadow the declarations imported by glob imports, which are in turn
+    /// shadowed by explicit imports. A name imported by several glob imports is ambiguous and can
+    /// not be used.
+    fn register_used_mods(
+        &mut self,
+        used: Vec<ast::Use>,
+        declaring: bool,
+        state: &mut State<'a, 'ctx, 'ty>,
+    ) {
         for import in used {
-            // Choose an identifier for the module
-            let ident = if let Some(alias) = import.alias {
-                alias
-            } else if let Some(module) = import.path.path.last() {
-                module.clone()
-            } else {
-                import.path.root.clone()
-            };
-            // Insert into the namespace
-            match state.ctx.get_mod_id_from_path(&import.path) {
-                Some(mod_id) => {
-                    state
-                        .value_namespace
-                        .insert(ident, ValueKind::Module(mod_id));
-                }
+            let mod_id = match state.ctx.get_mod_id_from_path(&import.path) {
+                Some(mod_id) => mod_id,
                 None => {
                     let loc = import.loc;
                     self.err.report(
                         loc,
                         format!("Module '{}' doesn't exist or can't be found.", &import.path),
                     );
+                    continue;
+                }
+            };
+            state.add_node(import.id, import.loc, Entity::Module(mod_id), false);
+            match &import.items {
+                None => self.register_used_mod(&import, mod_id, state),
+                Some(ast::UseItems::Selected(items)) => {
+                    for item in items {
+                        let found = self.import_item(&item.ident, mod_id, item.loc, false, state);
+                        if !found && !declaring {
+                            self.report_missing_item(item, mod_id, &import.path, state);
+                        }
+                        if let Some(entity) = state.get_declared_entity(&item.ident, mod_id) {
+                            state.add_node(item.id, item.loc, entity, false);
+                        }
+                    }
                 }
+                Some(ast::UseItems::Glob) => {
+                    let ctx = state.ctx;
+                    if let Some(decls) = ctx.get_mod_from_id(mod_id) {
+                        let mut names: Vec<&String> = decls
+                            .val_decls
+                            .keys()
+                            .chain(decls.type_decls.keys())
+                            .collect();
+                        names.sort();
+                        names.dedup();
+                        for name in names {
+                            self.import_item(name, mod_id, import.loc, true, state);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Adds a module imported with `use` to the global namespace.
+    fn register_used_mod(&mut self, import: &ast::Use, mod_id: ModId, state: &mut State) {
+        // Choose an identifier for the module
+        let ident = match &import.alias {
+            Some(alias) => alias.clone(),
+            None => import.path.alias().to_owned(),
+        };
+        if let Some(ValueKind::Module(other_id)) = state.value_namespace.get(&ident) {
+            if *other_id != mod_id {
+                self.report_name_conflict(&ident, import.loc, state);
+                return;
             }
         }
+        if import.alias.is_some() {
+            let name = import.path.alias().to_owned();
+            state.aliased_mods.insert(name, ident.clone());
+        }
+        state.used_mods.push((ident.clone(), import.loc));
+        state
+            .value_namespace
+            .insert(ident, ValueKind::Module(mod_id));
+    }
+
+    /// Adds the value and type named `ident` of a module to the global namespace, returns false
+    /// if the module declares neither.
+    fn import_item(
+        &mut self,
+        ident: &str,
+        mod_id: ModId,
+        loc: Location,
+        is_glob: bool,
+        state: &mut State,
+    ) -> bool {
+        let ctx = state.ctx;
+        let decls = match ctx.get_mod_from_id(mod_id) {
+            Some(decls) => decls,
+            None => return false,
+        };
+        let is_value = decls.val_decls.contains_key(ident);
+        let t = decls.type_decls.get(ident);
+        if !is_value && t.is_none() {
+            return false;
+        }
+        if let Some(other) = state.imported_items.get(ident) {
+            if other.mod_id == mod_id {
+                return true;
+            }
+            match (other.is_glob, is_glob) {
+                (false, false) => {
+                    let message = format!("The name '{}' is already imported", ident);
+                    let note = format!("'{}' is first imported here", ident);
+                    self.err.report_with_note(loc, message, other.loc, note);
+                    return true;
+                }
+                (false, true) => return true,
+                (true, true) => {
+                    state.remove_import(ident);
+                    state.ambiguous_items.insert(ident.to_owned(), loc);
+                    return true;
+                }
+                (true, false) => state.remove_import(ident),
+            }
+        }
+        if is_glob && state.ambiguous_items.contains_key(ident) {
+            return true;
+        }
+        state.ambiguous_items.remove(ident);
+        if is_value {
+            if let Some(ValueKind::Module(_)) = state.value_namespace.get(ident) {
+                if !is_glob {
+                    self.report_name_conflict(ident, loc, state);
+                }
+                return true;
+            }
+            state
+                .value_namespace
+                .insert(ident.to_owned(), ValueKind::Imported(mod_id));
+        }
+        if let Some(t) = t {
+            let t_var = state.checker.lift_t(t);
+            state.type_namespace.insert(ident.to_owned(), t_var);
+        }
+        let item = ImportedItem {
+            mod_id,
+            loc,
+            is_glob,
+            is_value,
+            is_type: t.is_some(),
+        };
+        state.imported_items.insert(ident.to_owned(), item);
+        true
+    }
+
+    /// Reports an explicitly imported declaration which does not exist or is private.
+    fn report_missing_item(
+        &mut self,
+        item: &ast::UseItem,
+        mod_id: ModId,
+        path: &ast::ModulePath,
+        state: &State,
+    ) {
+        if let Some(fun) = state.ctx.get_private_fun(mod_id, &item.ident) {
+            self.report_private_fun(&item.ident, fun, item.loc);
+            return;
+        }
+        let message = format!("'{}' is not declared in module '{}'", item.ident, path);
+        let decls = state.ctx.get_mod_from_id(mod_id);
+        let candidates = decls
+            .iter()
+            .flat_map(|decls| decls.val_decls.keys().chain(decls.type_decls.keys()))
+            .map(String::as_str);
+        match similar_name(&item.ident, candidates) {
+            Some(ident) => {
+                let suggestion = Suggestion::typo(item.loc, ident);
+                self.err
+                    .report_with_suggestion(item.loc, message, suggestion);
+            }
+            None => self.err.report(item.loc, message),
+        }
+    }
+
+    /// Handles a local declaration named after an imported one: declarations imported by glob
+    /// imports are shadowed, explicitly imported ones conflict with it.
+    fn shadow_import(&mut self, ident: &str, loc: Location, state: &mut State) {
+        if let Some(item) = state.imported_items.remove(ident) {
+            if !item.is_glob {
+                let message = format!(
+                    "The name '{}' is already used by an imported declaration",
+                    ident
+                );
+                let note = format!("'{}' is imported here", ident);
+                self.err.report_with_note(loc, message, item.loc, note);
+            }
+        }
+    }
+
+    /// Reports the use of a name imported by several glob imports, returns false if the name is
+    /// not ambiguous.
+    fn report_ambiguous(&mut self, ident: &str, loc: Location, state: &State) -> bool {
+        match state.ambiguous_items.get(ident) {
+            Some(glob_loc) => {
+                let message = format!("The name '{}' is imported by several glob imports", ident);
+                let note = format!(
+                    "'{}' is also imported here, import it explicitly to choose between them",
+                    ident
+                );
+                self.err.report_with_note(loc, message, *glob_loc, note);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Reports a declaration named after a module imported with `use`, the note points at the
+    /// import.
+    fn report_name_conflict(&mut self, ident: &str, loc: Location, state: &State) {
+        let message = format!("The name '{}' is already used by an imported module", ident);
+        match state.used_mods.iter().find(|(used, _)| used == ident) {
+            Some((_, use_loc)) => {
+                let note = format!("'{}' is imported here, 'as' gives it another name", ident);
+                self.err.report_with_note(loc, message, *use_loc, note);
+            }
+            None => self.err.report(loc, message),
+        }
     }
 
     /// Look for a value in either the given namespace of the local one.
@@ -1134,13 +2565,8 @@ impl<'err, 'a, 'ctx, 'ty, E: ErrorHandler> NameResolver<'err, E> {
                 if let Some(value) = declarations.val_decls.get(val) {
                     match value {
                         ValueDeclaration::Function(fun_id) => {
-                            let expr = Expression::Function {
-                                fun_id: *fun_id,
-                                loc,
-                            };
-                            let fun_t = match state.ctx.get_fun(*fun_id) {
-                                Some(FunKind::Fun(fun)) => &fun.t,
-                                Some(FunKind::Extern(fun)) => &fun.t,
+                            let fun_t = match state.ctx.get_fun_signature(*fun_id) {
+                                Some((t, _)) => t,
                                 None => {
                                     self.err.report_internal(
                                         loc,
@@ -1150,6 +2576,11 @@ impl<'err, 'a, 'ctx, 'ty, E: ErrorHandler> NameResolver<'err, E> {
                                 }
                             };
                             let t_var = state.checker.lift_t_fun(fun_t);
+                            let expr = Expression::Function {
+                                fun_id: *fun_id,
+                                loc,
+                                t_var,
+                            };
                             Ok(Some((expr, t_var)))
                         }
                         ValueDeclaration::Module(mod_id) => {
@@ -1161,11 +2592,19 @@ impl<'err, 'a, 'ctx, 'ty, E: ErrorHandler> NameResolver<'err, E> {
                             Ok(Some((expr, t_var)))
                         }
                     }
+                } else if let Some(fun) = state.ctx.get_private_fun(namespace, val) {
+                    self.report_private_fun(val, fun, loc);
+                    Err(())
                 } else {
-                    self.err.report(
-                        loc,
-                        format!("Value '{}' does not exists", val),
-                    );
+                    let message = format!("Value '{}' does not exists", val);
+                    let candidates = declarations.val_decls.keys().map(String::as_str);
+                    match similar_name(val, candidates) {
+                        Some(ident) => {
+                            let suggestion = Suggestion::typo(loc, ident);
+                            self.err.report_with_suggestion(loc, message, suggestion);
+                        }
+                        None => self.err.report(loc, message),
+                    }
                     Err(())
                 }
             } else {
@@ -1178,12 +2617,17 @@ impl<'err, 'a, 'ctx, 'ty, E: ErrorHandler> NameResolver<'err, E> {
                 match value {
                     ValueKind::Function(fun_id, _) => {
                         let fun_id = *fun_id;
-                        let expr = Expression::Function { fun_id, loc };
                         let t_var = self.get_fun_t_var(fun_id, state)?;
+                        let expr = Expression::Function { fun_id, loc, t_var };
                         Ok(Some((expr, t_var)))
                     }
+                    ValueKind::Imported(mod_id) => {
+                        let mod_id = *mod_id;
+                        self.get_value(val, Some(mod_id), loc, state)
+                    }
                     ValueKind::Module(mod_id) => {
                         let mod_id = *mod_id;
+                        state.referenced_mods.insert(val.to_owned());
                         let expr = Expression::Namespace { mod_id, loc };
                         let t_var = state.checker.scalar(ScalarType::Null);
                         Ok(Some((expr, t_var)))
@@ -1195,6 +2639,82 @@ impl<'err, 'a, 'ctx, 'ty, E: ErrorHandler> NameResolver<'err, E> {
         }
     }
 
+    /// Reports the use of a private function of another module, suggests to make it public.
+    fn report_private_fun(&mut self, ident: &str, fun: &hir::Function, loc: Location) {
+        let suggestion = Suggestion {
+            loc: Location {
+                len: 0,
+                ..fun.keyword_loc
+            },
+            replacement: String::from("pub "),
+            message: format!("declare '{}' with 'pub' to make it public", ident),
+        };
+        let message = format!("Function '{}' is private", ident);
+        self.err.report_with_suggestion(loc, message, suggestion);
+    }
+
+    /// Reports an error if an experimental feature is used without being enabled.
+    fn require_feature(
+        &mut self,
+        feature: ast::Feature,
+        loc: Location,
+        state: &State,
+    ) -> Result<(), ()> {
+        if state.ctx.has_feature(feature) {
+            Ok(())
+        } else {
+            self.err.report(loc, feature.error_message());
+            Err(())
+        }
+    }
+
+    /// Returns the structs of the runtime needed by `what`, reports an error if they are not
+    /// known, that is when compiling without a runtime or the modules of the runtime declaring
+    /// them.
+    fn get_known_structs(
+        &mut self,
+        what: &str,
+        loc: Location,
+        state: &State,
+    ) -> Result<KnownStructs, ()> {
+        match &state.known_values.structs {
+            Some(structs) => Ok(structs.clone()),
+            None => {
+                self.report_unknown_values(what, "'Str' is known", loc, state);
+                Err(())
+            }
+        }
+    }
+
+    /// Returns the functions and structs of the runtime needed by `what`, reports an error if they
+    /// are not known, that is when compiling without a runtime or the runtime itself.
+    fn get_known_funs(
+        &mut self,
+        what: &str,
+        loc: Location,
+        state: &State,
+    ) -> Result<(KnownFunctions, KnownStructs), ()> {
+        match &state.known_values {
+            KnownValues {
+                funs: Some(funs),
+                structs: Some(structs),
+            } => Ok((funs.clone(), structs.clone())),
+            _ => {
+                self.report_unknown_values(what, "it is compiled", loc, state);
+                Err(())
+            }
+        }
+    }
+
+    fn report_unknown_values(&mut self, what: &str, until: &str, loc: Location, state: &State) {
+        let message = if state.ctx.has_runtime() {
+            format!("{} can not be used in the runtime before {}", what, until)
+        } else {
+            format!("{} can not be used without a runtime", what)
+        };
+        self.err.report(loc, message);
+    }
+
     /// Get a type from a (possibly namespaced) string.
     ///
     /// Will raise an error if the type does not exists.
@@ -1206,6 +2726,9 @@ impl<'err, 'a, 'ctx, 'ty, E: ErrorHandler> NameResolver<'err, E> {
         state: &mut State,
     ) -> Result<TypeVar, ()> {
         if let Some(t) = check_built_in_scalar(t) {
+            if t == ScalarType::V128 {
+                self.require_feature(ast::Feature::Simd, loc, state)?;
+            }
             return Ok(state.checker.scalar(t));
         }
         if let Some(mod_id) = namespace {
@@ -1234,6 +2757,8 @@ impl<'err, 'a, 'ctx, 'ty, E: ErrorHandler> NameResolver<'err, E> {
             // Look for type in local namespace
             if let Some(t_var) = state.type_namespace.get(t) {
                 Ok(*t_var)
+            } else if self.report_ambiguous(t, loc, state) {
+                Err(())
             } else {
                 self.err.report(loc, format!("Unknown type: '{}'", t));
                 Err(())
@@ -1251,7 +2776,21 @@ impl<'err, 'a, 'ctx, 'ty, E: ErrorHandler> NameResolver<'err, E> {
         state: &mut State<'a, 'ctx, 'ty>,
     ) -> Result<TypeVar, ()> {
         match ast_t {
-            ast::Type::Simple(path) => self.get_type_from_path(path, state),
+            ast::Type::Simple(path) => {
+                let t_var = self.get_type_from_path(path, state)?;
+                if let Some(node) = state.add_struct_node(path.id, path.loc, t_var) {
+                    if let Some(last) = path.path.last() {
+                        let len = last.len() as u32;
+                        node.name_loc = Location {
+                            pos: path.loc.pos + path.loc.len - len,
+                            len,
+                            f_id: path.loc.f_id,
+                        };
+                        node.scope = None;
+                    }
+                }
+                Ok(t_var)
+            }
             ast::Type::Tuple(tup, loc) => {
                 let mut types = Vec::new();
                 for t in tup {
@@ -1261,6 +2800,29 @@ impl<'err, 'a, 'ctx, 'ty, E: ErrorHandler> NameResolver<'err, E> {
                 state.checker.set_tuple(t_var, types, self.err, *loc);
                 Ok(t_var)
             }
+            ast::Type::Ref(t, loc) => {
+                let pointee = self.get_type(t, state)?;
+                let t_var = state.checker.fresh();
+                state.checker.set_ref(t_var, pointee, self.err, *loc);
+                Ok(t_var)
+            }
+            ast::Type::Fun {
+                params,
+                result,
+                loc,
+            } => {
+                let mut types = Vec::with_capacity(params.len());
+                for t in params {
+                    types.push(self.get_type(t, state)?);
+                }
+                let ret = match result {
+                    Some(t) => self.get_type(t, state)?,
+                    None => state.checker.scalar(ScalarType::Null),
+                };
+                let t_var = state.checker.fresh();
+                state.checker.set_fun(t_var, types, ret, self.err, *loc);
+                Ok(t_var)
+            }
         }
     }
 
@@ -1272,9 +2834,15 @@ impl<'err, 'a, 'ctx, 'ty, E: ErrorHandler> NameResolver<'err, E> {
         // Check for built-in type
         if path.path.is_empty() {
             if let Some(t) = check_built_in_scalar(&path.root) {
+                if t == ScalarType::V128 {
+                    self.require_feature(ast::Feature::Simd, path.loc, state)?;
+                }
                 return Ok(state.checker.scalar(t));
             }
         }
+        if !path.path.is_empty() {
+            state.referenced_mods.insert(path.root.clone());
+        }
         let mut ident = &path.root;
         let mut namespace = NamespaceKind::new(&state.value_namespace, &state.type_namespace);
         for access in &path.path {
@@ -1313,6 +2881,23 @@ impl<'err, 'a, 'ctx, 'ty, E: ErrorHandler> NameResolver<'err, E> {
     }
 }
 
+/// The functions built into the compiler.
+#[derive(Clone, Copy)]
+enum BuiltIn {
+    /// Concatenates string literals at compile time.
+    Concat,
+    /// Formats a string at runtime, `{}` in the format string are replaced by the arguments.
+    Format,
+    /// Aborts the execution if a condition does not hold.
+    Assert,
+    /// Aborts the execution if two values are not equal.
+    AssertEq,
+    /// Aborts the execution with a message, through the panic handler.
+    Panic,
+    /// Lowered directly to an instruction.
+    Intrinsic(Intrinsic),
+}
+
 /// Encapsulate different kinds of namespace: the one being built and others from the Ctx.
 enum NamespaceKind<'state, 'ctx> {
     Resolver(
@@ -1385,6 +2970,7 @@ fn check_built_in_scalar(t: &str) -> Option<ScalarType> {
         "f32" => Some(ScalarType::F32),
         "f64" => Some(ScalarType::F64),
         "bool" => Some(ScalarType::Bool),
+        "v128" => Some(ScalarType::V128),
         _ => None,
     }
 }
@@ -1416,3 +3002,20 @@ fn check_base_type_from_type(t: &ast::Type) -> Option<ScalarType> {
         None
     }
 }
+
+/// Returns the public structs declared by a module, with the IDs they are given once the module
+/// is resolved.
+pub fn declare_structs(ast_program: &ast::Program) -> ModuleDeclarations {
+    let mod_id = ast_program.module.id;
+    let mut decls = ModuleDeclarations::new(mod_id);
+    let mut structs: StructStore = Store::new(mod_id);
+    for struc in &ast_program.structs {
+        let s_id = structs.fresh_id();
+        if struc.is_pub {
+            decls
+                .type_decls
+                .insert(struc.ident.clone(), hir::Type::Struct(s_id));
+        }
+    }
+    decls
+}
@@ -7,8 +7,13 @@
 //! In order to guarantee that identifiers are unique a single Store per Module ID per kind of ID
 //! must be built. Store elements can be transformed using the `transmute` method while conserving
 //! their previous IDs.
+//!
+//! IDs pack the module ID in their upper 32 bits and a counter in the lower ones. Module IDs are
+//! derived from the path of the modules (see `Ctx::stable_mod_id`), the IDs of the items of a
+//! module thus do not change when other modules are added and are stable across builds.
 
-use crate::ctx::ModId;
+use crate::ctx::{ModId, StoreStats};
+use crate::error::ErrorHandler;
 use std::collections::{HashMap, HashSet};
 
 use zephyr_lang_derive::Identifier;
@@ -20,6 +25,7 @@ pub type Id = u64;
 /// A trait implemented by an Identifier type (a type capable of producing an Id)
 pub trait Identifier {
     fn new(id: Id) -> Self;
+    fn id(&self) -> Id;
 }
 
 /// An helper macro to define new IDs
@@ -43,15 +49,30 @@ define_id!(TupleId);
 define_id!(TypeId);
 define_id!(TypeVarId);
 
-/// A list of IDs for known funs and strucs.
+/// IDs of the functions synthesized by the compiler, which live in the reserved module 0. The
+/// values of the runtime are looked up by name instead, see `ctx::KnownValues`.
 pub mod known_ids {
     use super::*;
 
-    // Functions
-    pub const MALLOC_ID: FunId = FunId(1);
+    /// The function calling the init blocks of all modules.
+    pub const INIT_ID: FunId = FunId(2);
+    /// The allocator exported for the canonical ABI of the component model.
+    pub const REALLOC_ID: FunId = FunId(3);
+
+    /// Returns the ID of the `idx`-th adapter generated for the component target.
+    pub fn adapter_id(idx: u32) -> FunId {
+        FunId(0x100 + idx as Id)
+    }
+
+    /// Returns the ID of the function through which `fun_id` is called when used as a value.
+    pub fn reference_id(fun_id: FunId) -> FunId {
+        FunId(fun_id.0 | 1 << 63)
+    }
 
-    // Structs
-    pub const STR_ID: StructId = StructId(1);
+    /// Returns the ID of the function releasing a reference to a struct of type `s_id`.
+    pub fn drop_id(s_id: StructId) -> FunId {
+        FunId(s_id.0 | 1 << 62)
+    }
 }
 
 // ———————————————————————————— Store definition ———————————————————————————— //
@@ -61,6 +82,8 @@ pub struct Store<I, T> {
     counter: u32,
     data: HashMap<I, T>,
     merged_mods: HashSet<ModId>,
+    /// Number of items of the merged stores which replaced an item with the same ID.
+    collisions: usize,
 }
 
 impl<I, T> Store<I, T>
@@ -73,6 +96,7 @@ where
             counter: 0,
             data: HashMap::new(),
             merged_mods: HashSet::new(),
+            collisions: 0,
         }
     }
 
@@ -83,6 +107,7 @@ where
             counter: 0,
             data: HashMap::with_capacity(capacity),
             merged_mods: HashSet::new(),
+            collisions: 0,
         }
     }
 
@@ -98,11 +123,37 @@ where
         self.data.insert(id, item);
     }
 
-    /// Tries to retrieve an item from its ID.
+    /// Returns true if the ID has been generated by a store of the same module as this one.
+    pub fn is_local(&self, id: &I) -> bool {
+        (id.id() >> 32) as u32 == self.mod_id.0
+    }
+
+    /// Retrieves an item which must be in the store, `pass` is the name of the pass requesting
+    /// it.
     ///
-    /// This will never return None if the ID has been generated by this store.
-    pub fn get(&self, id: I) -> Option<&T> {
-        self.data.get(&id)
+    /// A missing item is a bug of the compiler: debug builds panic with the kind of the store,
+    /// the module of the ID and the pass, release builds report an internal error instead.
+    pub fn expect_get(&self, id: I, pass: &str, err: &mut impl ErrorHandler) -> Result<&T, ()>
+    where
+        I: std::fmt::Debug,
+    {
+        if let Some(item) = self.data.get(&id) {
+            return Ok(item);
+        }
+        let kind = std::any::type_name::<T>();
+        let kind = kind.rsplit("::").next().unwrap_or(kind);
+        let message = format!(
+            "{:?} is not in the {} store of module {} (requested by {})",
+            id,
+            kind,
+            id.id() >> 32,
+            pass
+        );
+        if cfg!(debug_assertions) {
+            panic!("[Internal Error] {}", message);
+        }
+        err.report_internal_no_loc(message);
+        Err(())
     }
 
     /// Generates a globally unique ID for this kind of store.
@@ -122,19 +173,42 @@ where
         } else if self.merged_mods.contains(&other.mod_id) {
             panic!("A store with the same module ID has already been merged!");
         }
+        self.collisions += other.collisions;
+        self.collisions += other
+            .data
+            .keys()
+            .filter(|id| self.data.contains_key(id))
+            .count();
         self.data.extend(other.data);
         self.merged_mods.insert(other.mod_id);
     }
 
+    /// Returns statistics about the items of the store, the memory is an estimate which ignores
+    /// the memory held by the items themselves.
+    pub fn stats(&self) -> StoreStats {
+        let capacity = self.data.capacity();
+        StoreStats {
+            items: self.data.len(),
+            capacity,
+            collisions: self.collisions,
+            // Hash maps use an extra control byte per entry
+            bytes: capacity * (std::mem::size_of::<(I, T)>() + 1),
+        }
+    }
+
     /// Transform a `Store<I, T>` into `Store<I, Q>` by applying a function to all its elements.
     ///
     /// If the transformation function returns None, the item is dropped.
     pub fn transmute<Q, F>(self, mut fun: F) -> Store<I, Q>
     where
+        I: Ord,
         F: FnMut(T) -> Option<Q>,
     {
         let mut data = HashMap::with_capacity(self.data.len());
-        for (id, item) in self.data.into_iter() {
+        // Items are transformed in order, the transformation may report errors or allocate IDs
+        let mut items: Vec<(I, T)> = self.data.into_iter().collect();
+        items.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+        for (id, item) in items {
             if let Some(transmuted_item) = fun(item) {
                 data.insert(id, transmuted_item);
             }
@@ -144,13 +218,20 @@ where
             mod_id: self.mod_id,
             counter: self.counter,
             merged_mods: self.merged_mods,
+            collisions: self.collisions,
             data,
         }
     }
 
-    /// Iterates over (id, item) tuples.
-    pub fn iter(&self) -> std::collections::hash_map::Iter<'_, I, T> {
-        self.data.iter()
+    /// Iterates over (id, item) tuples sorted by ID, this must be used when the iteration
+    /// produces code or output.
+    pub fn iter_sorted(&self) -> std::vec::IntoIter<(&I, &T)>
+    where
+        I: Ord,
+    {
+        let mut items: Vec<(&I, &T)> = self.data.iter().collect();
+        items.sort_unstable_by_key(|(id, _)| *id);
+        items.into_iter()
     }
 }
 
@@ -168,8 +249,9 @@ impl<I, T> IntoIterator for Store<I, T> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::error::DummyHandler;
 
-    #[derive(Identifier, Eq, PartialEq, Hash, Debug, Copy, Clone)]
+    #[derive(Identifier, Eq, PartialEq, Hash, Debug, Copy, Clone, Ord, PartialOrd)]
     pub struct TestId(Id);
 
     #[test]
@@ -178,7 +260,68 @@ mod tests {
         let id: TestId = store.add('a');
         let other_id = store.add('b');
         assert_ne!(id, other_id);
-        assert_eq!(store.get(id), Some(&'a'));
-        assert_eq!(store.get(other_id), Some(&'b'));
+        let mut err = DummyHandler::new();
+        assert_eq!(store.expect_get(id, "test", &mut err), Ok(&'a'));
+        assert_eq!(store.expect_get(other_id, "test", &mut err), Ok(&'b'));
+    }
+
+    #[test]
+    fn expect_get() {
+        let mut store = Store::new(ModId(1));
+        let id: TestId = store.add('a');
+        let mut err = DummyHandler::new();
+        assert_eq!(store.expect_get(id, "test", &mut err), Ok(&'a'));
+        assert!(!err.has_error());
+        assert!(store.is_local(&id));
+        assert!(!store.is_local(&TestId::new(2 << 32)));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(
+        expected = "TestId(4294967297) is not in the char store of module 1 (requested by test)"
+    )]
+    fn expect_get_missing() {
+        let mut store = Store::new(ModId(1));
+        store.add('a');
+        let id: TestId = store.fresh_id();
+        let _ = store.expect_get(id, "test", &mut DummyHandler::new());
+    }
+
+    #[test]
+    #[cfg(not(debug_assertions))]
+    fn expect_get_missing() {
+        let mut store = Store::new(ModId(1));
+        store.add('a');
+        let id: TestId = store.fresh_id();
+        let mut err = DummyHandler::new();
+        assert_eq!(store.expect_get(id, "test", &mut err), Err(()));
+        assert!(err.has_error());
+    }
+
+    #[test]
+    fn stats() {
+        let mut store = Store::new(ModId(1));
+        let id: TestId = store.add('a');
+        store.add('b');
+        let mut other = Store::new(ModId(2));
+        other.insert(id, 'c');
+        other.add('d');
+        store.extend(other);
+        let stats = store.stats();
+        assert_eq!(stats.items, 3);
+        assert_eq!(stats.collisions, 1);
+        assert!(stats.capacity >= 3);
+    }
+
+    #[test]
+    fn iter_sorted() {
+        let mut store = Store::new(ModId(1));
+        let ids: Vec<TestId> = (0..64).map(|idx| store.add(idx)).collect();
+        let sorted: Vec<TestId> = store.iter_sorted().map(|(id, _)| *id).collect();
+        assert_eq!(sorted, ids);
+        let store = store.transmute(|idx| Some(idx * 2));
+        let items: Vec<u32> = store.iter_sorted().map(|(_, item)| *item).collect();
+        assert_eq!(items, (0..64).map(|idx| idx * 2).collect::<Vec<_>>());
     }
 }
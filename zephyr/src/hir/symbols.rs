@@ -0,0 +1,159 @@
+//! Symbols
+//!
+//! Collects the names declared and referenced by a module together with their inferred types, so
+//! that editor tooling can answer questions such as "where is this declared?" or "what is the
+//! type of this?" without walking the HIR.
+//!
+//! The node map complements the symbols: it maps the AST nodes to the HIR entities they declare
+//! or refer to, which lets tools go from a position in the source code to what the compiler knows
+//! about it. Nodes also know the scope their name is looked up in, the scopes record the names
+//! they declare so that a rename can tell whether the new name would collide with another one.
+
+use std::collections::{BTreeMap, HashMap};
+
+use super::hir::Type;
+use super::names::{Declaration, FunId, NameId, ResolvedProgram, StructId};
+use super::type_check::{TypeChecker, TypeVar};
+use crate::ast::NodeId;
+use crate::ctx::{Ctx, ModId};
+use crate::error::Location;
+
+/// The AST nodes mapped to HIR entities, ordered by ID.
+pub type NodeMap = BTreeMap<NodeId, Node>;
+
+/// The scopes of the modules, see `Scope`.
+pub type ScopeMap = HashMap<ScopeId, Scope>;
+
+/// A name appearing in the source code.
+pub struct Symbol {
+    pub ident: String,
+    pub loc: Location,
+    /// Location of the declaration, equal to `loc` for the declaration itself.
+    pub decl_loc: Location,
+    /// The inferred type, if it could be determined.
+    pub t: Option<Type>,
+}
+
+/// An AST node declaring or referring to a HIR entity.
+#[derive(Debug, Clone)]
+pub struct Node {
+    pub loc: Location,
+    /// Location of the name of the entity within the node, equal to `loc` unless the node is
+    /// larger than its name, such as `Point { x: 1 }` or `geometry.Point`.
+    pub name_loc: Location,
+    pub entity: Entity,
+    /// Whether the node is the declaration of the entity rather than a use of it.
+    pub is_declaration: bool,
+    /// The innermost scope the name is looked up in, none for the names which are not looked up
+    /// in scopes: fields and names qualified by a module.
+    pub scope: Option<ScopeId>,
+}
+
+/// Something the compiler knows about and nodes can refer to.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Entity {
+    Function(FunId),
+    Struct(StructId),
+    /// A field of a struct, identified by its name.
+    Field(StructId, String),
+    /// A parameter or local variable, names are numbered per module.
+    Local(ModId, NameId),
+    Module(ModId),
+}
+
+/// Identifies a scope, the scope 0 of a module holds its top level declarations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ScopeId {
+    pub mod_id: ModId,
+    pub index: u32,
+}
+
+/// A scope and the names declared in it.
+#[derive(Debug, Clone)]
+pub struct Scope {
+    /// The enclosing scope, none for the top level scope of a module.
+    pub parent: Option<ScopeId>,
+    pub names: HashMap<String, Entity>,
+}
+
+/// The access to a field, whose struct is only known once the program is type checked.
+pub struct FieldAccess {
+    pub id: NodeId,
+    pub loc: Location,
+    pub field: String,
+    pub struct_t_var: TypeVar,
+}
+
+/// Returns the symbols of a resolved program, must be called after type checking.
+pub fn collect_symbols(
+    program: &ResolvedProgram,
+    checker: &mut TypeChecker,
+    ctx: &Ctx,
+) -> Vec<Symbol> {
+    let mut symbols = Vec::new();
+    let mut fun_locs = HashMap::new();
+
+    // Declarations
+    for name in program.names.iter() {
+        symbols.push(Symbol {
+            ident: name.name.clone(),
+            loc: name.loc,
+            decl_loc: name.loc,
+            t: checker.get_t(name.t_var),
+        });
+    }
+    for fun in &program.funs {
+        fun_locs.insert(fun.fun_id, fun.loc);
+        symbols.push(Symbol {
+            ident: fun.ident.clone(),
+            loc: fun.loc,
+            decl_loc: fun.loc,
+            t: program
+                .fun_types
+                .get(&fun.fun_id)
+                .and_then(|t_var| checker.get_t(*t_var)),
+        });
+    }
+    for import in &program.imports {
+        for proto in &import.prototypes {
+            fun_locs.insert(proto.fun_id, proto.loc);
+        }
+    }
+
+    // References
+    for reference in &program.references {
+        let decl_loc = match reference.decl {
+            Declaration::Name(n_id) => Some(program.names.get(n_id).loc),
+            Declaration::Function(fun_id) => match fun_locs.get(&fun_id) {
+                Some(loc) => Some(*loc),
+                None => ctx.get_fun_signature(fun_id).map(|(_, loc)| loc),
+            },
+        };
+        if let Some(decl_loc) = decl_loc {
+            symbols.push(Symbol {
+                ident: reference.ident.clone(),
+                loc: reference.loc,
+                decl_loc,
+                t: checker.get_t(reference.t_var),
+            });
+        }
+    }
+    symbols
+}
+
+/// Maps the field accesses of a resolved program to the fields they refer to, must be called
+/// after type checking.
+pub fn collect_field_nodes(program: &mut ResolvedProgram, checker: &mut TypeChecker) {
+    for access in program.field_accesses.drain(..) {
+        if let Some(Type::Struct(s_id)) = checker.get_t(access.struct_t_var) {
+            let node = Node {
+                loc: access.loc,
+                name_loc: access.loc,
+                entity: Entity::Field(s_id, access.field),
+                is_declaration: false,
+                scope: None,
+            };
+            program.nodes.insert(access.id, node);
+        }
+    }
+}
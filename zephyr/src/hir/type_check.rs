@@ -1,3 +1,13 @@
+//! Type Checker
+//!
+//! Infers the type of each expression by solving the constraints collected during name
+//! resolution.
+//!
+//! Integer semantics: integers are represented in two's complement and arithmetic wraps around on
+//! overflow. When compiling with overflow checks (`--overflow-checks`) the signed overflow of an
+//! addition, subtraction, multiplication or negation panics instead. Comparisons are signed, while
+//! divisions and remainders are unsigned.
+
 use super::hir;
 use super::hir::{ScalarType, TupleId, TupleStore};
 use super::names::{StructId, StructStore};
@@ -37,6 +47,24 @@ enum CompositeKind {
     Tuple,
     Fun,
     Struct(StructId),
+    /// A reference, to a single type.
+    Ref,
+}
+
+/// The reason why two types could not be unified.
+enum Mismatch {
+    /// The types are incompatible.
+    Incompatible,
+    /// The error has already been reported, for instance by a nested unification.
+    Reported,
+}
+
+/// The declaration a type variable originates from, used to explain type errors.
+#[derive(Clone)]
+struct Origin {
+    /// A description of the declaration, such as "parameter `x`".
+    what: String,
+    loc: Location,
 }
 
 enum TypeConstraint {
@@ -63,6 +91,35 @@ enum TypeConstraint {
     },
 }
 
+impl TypeConstraint {
+    /// Returns the location and explanation of a constraint that could not be solved.
+    fn unsolved(&self) -> (Location, String) {
+        match self {
+            TypeConstraint::Access {
+                field_name, loc, ..
+            } => (
+                *loc,
+                format!(
+                    "Could not infer the type of the value whose field '{}' is accessed",
+                    field_name
+                ),
+            ),
+            TypeConstraint::Call { loc, .. } => (
+                *loc,
+                String::from("Could not infer the type of the called value"),
+            ),
+            TypeConstraint::Return { loc, .. } => (
+                *loc,
+                String::from("Could not infer the return type of the called value"),
+            ),
+            TypeConstraint::StructLiteral { loc, .. } => (
+                *loc,
+                String::from("Could not infer the type of the struct literal"),
+            ),
+        }
+    }
+}
+
 pub struct TyStore {
     arena: Arena<Ty>,
 }
@@ -152,6 +209,10 @@ pub struct TypeChecker<'ctx, 'ty> {
     constraints: Vec<TypeConstraint>,
     subs: Substitution<'ty>,
 
+    // Provenance of type variables, used for error messages
+    origins: HashMap<TypeVar, Origin>,
+    struct_idents: HashMap<StructId, String>,
+
     // Keep track of tuple types
     tuple_map: HashMap<Vec<TypeVar>, TupleId>,
     tuples: TupleStore,
@@ -163,6 +224,7 @@ pub struct TypeChecker<'ctx, 'ty> {
     t_f64: TypeVar,
     t_bool: TypeVar,
     t_null: TypeVar,
+    t_v128: TypeVar,
 }
 
 impl<'ctx, 'ty> TypeChecker<'ctx, 'ty> {
@@ -174,12 +236,14 @@ impl<'ctx, 'ty> TypeChecker<'ctx, 'ty> {
         let t_f64 = TypeVar(3);
         let t_bool = TypeVar(4);
         let t_null = TypeVar(5);
+        let t_v128 = TypeVar(6);
         subs.insert(t_i32, Ty::Base(ScalarType::I32));
         subs.insert(t_i64, Ty::Base(ScalarType::I64));
         subs.insert(t_f32, Ty::Base(ScalarType::F32));
         subs.insert(t_f64, Ty::Base(ScalarType::F64));
         subs.insert(t_bool, Ty::Base(ScalarType::Bool));
         subs.insert(t_null, Ty::Base(ScalarType::Null));
+        subs.insert(t_v128, Ty::Base(ScalarType::V128));
         Self {
             ctx,
             subs,
@@ -189,8 +253,11 @@ impl<'ctx, 'ty> TypeChecker<'ctx, 'ty> {
             t_f64,
             t_bool,
             t_null,
-            type_var_counter: 6, // !IMPORTANT: must be (strictly) higher than highest scalar t_var
+            t_v128,
+            type_var_counter: 7, // !IMPORTANT: must be (strictly) higher than highest scalar t_var
             constraints: Vec::new(),
+            origins: HashMap::new(),
+            struct_idents: HashMap::new(),
             tuple_map: HashMap::new(),
             tuples: Store::new(mod_id),
         }
@@ -219,9 +286,31 @@ impl<'ctx, 'ty> TypeChecker<'ctx, 'ty> {
             ScalarType::F64 => self.t_f64,
             ScalarType::Bool => self.t_bool,
             ScalarType::Null => self.t_null,
+            ScalarType::V128 => self.t_v128,
         }
     }
 
+    /// Return a fresh type variable equal to `t`, whose type originates from the declaration
+    /// described by `what` (such as "parameter `x`") at `loc`. Type errors involving the variable
+    /// point back to that declaration.
+    pub fn fresh_with_origin(
+        &mut self,
+        t: TypeVar,
+        what: String,
+        err: &mut impl ErrorHandler,
+        loc: Location,
+    ) -> TypeVar {
+        let t_var = self.fresh();
+        let _ = self.unify_var_var(t_var, t, err, loc);
+        self.origins.insert(t_var, Origin { what, loc });
+        t_var
+    }
+
+    /// Register the name of a struct, used for error messages.
+    pub fn declare_struct(&mut self, s_id: StructId, ident: String) {
+        self.struct_idents.insert(s_id, ident);
+    }
+
     /// Gives a type to a type variable, will raise an error if the type variable already has a
     /// different type.
     pub fn set_type(
@@ -309,7 +398,25 @@ impl<'ctx, 'ty> TypeChecker<'ctx, 'ty> {
         let _ = self.unify_var_ty(&t_var, tuple_ty, err, loc);
     }
 
-    /// Apply an 'equal' type constraint on `t_var_1` and `t_var_2`.
+    /// Set a type variable to the type of a reference to `pointee`.
+    ///
+    /// Contrary to other composite types the type variable may already be bound, as is the case
+    /// when dereferencing a value.
+    pub fn set_ref(
+        &mut self,
+        t_var: TypeVar,
+        pointee: TypeVar,
+        err: &mut impl ErrorHandler,
+        loc: Location,
+    ) {
+        let ref_t_var = self.fresh();
+        self.subs
+            .insert(ref_t_var, Ty::Composite(CompositeKind::Ref, vec![pointee]));
+        let _ = self.unify_var_var(t_var, ref_t_var, err, loc);
+    }
+
+    /// Apply an 'equal' type constraint on `t_var_1` and `t_var_2`, where `t_var_1` is the expected
+    /// type.
     pub fn set_equal(
         &mut self,
         t_var_1: TypeVar,
@@ -390,13 +497,26 @@ impl<'ctx, 'ty> TypeChecker<'ctx, 'ty> {
                 break;
             }
         }
-        if error || self.constraints.len() > 0 {
+        if error {
+            Err(())
+        } else if self.constraints.len() > 0 {
+            // No further progress can be made: the remaining constraints are under-determined
+            for constr in &self.constraints {
+                let (loc, message) = constr.unsolved();
+                err.report(loc, message);
+            }
             Err(())
         } else {
             Ok(())
         }
     }
 
+    /// Returns true if the type of `t_var` is ambiguous and falls back to the default type, that is
+    /// the first candidate. This should be called after `type_check`.
+    pub fn is_defaulted(&mut self, t_var: TypeVar) -> bool {
+        matches!(self.subs.substitute(t_var), Ty::OneOf(_, ts) if ts.len() > 1)
+    }
+
     /// Try to convert a type variable into an HIR type. This should be called after `type_check`
     /// to ensure that all constraints have been taken into account.
     /// Will return a none in case the type can't be determined.
@@ -424,6 +544,10 @@ impl<'ctx, 'ty> TypeChecker<'ctx, 'ty> {
                     }
                     Some(hir::Type::Fun(hir::FunctionType { params, ret }))
                 }
+                CompositeKind::Ref => {
+                    let pointee = self.get_t(*ts.first()?)?;
+                    Some(hir::Type::Ref(Box::new(pointee)))
+                }
                 CompositeKind::Tuple => {
                     if let Some(tup_id) = self.tuple_map.get(ts) {
                         // This tuple type has already been created
@@ -447,6 +571,8 @@ impl<'ctx, 'ty> TypeChecker<'ctx, 'ty> {
 
     // ———————————————————————————————— Solver —————————————————————————————————— //
 
+    /// Unify two type variables. `t_var_1` is the expected type and `t_var_2` the type found, the
+    /// origin of the expected type is used to explain the error if the types are incompatible.
     fn unify_var_var(
         &mut self,
         t_var_1: TypeVar,
@@ -459,27 +585,45 @@ impl<'ctx, 'ty> TypeChecker<'ctx, 'ty> {
         if ty_1 == ty_2 {
             return Ok(Progress::None);
         }
-        match (ty_1, ty_2) {
-            (Ty::Var(t_1), ty) => self.unify_var_ty(t_1, ty, err, loc),
-            (Ty::Base(t_1), Ty::Base(t_2)) => self.unify_base_base(t_1, t_2, err, loc),
-            (Ty::Base(t), Ty::OneOf(t_var, ts)) => {
-                self.unify_base_oneof(ty_1, t, t_var, ts, err, loc)
+        match self.unify_ty_ty(ty_1, ty_2, err, loc) {
+            Ok(progress) => {
+                // Variables that were unbound inherit the origin of the type they are bound to
+                if let Ty::Var(_) = ty_1 {
+                    self.inherit_origin(t_var_1, t_var_2);
+                }
+                if let Ty::Var(_) = ty_2 {
+                    self.inherit_origin(t_var_2, t_var_1);
+                }
+                Ok(progress)
             }
-            (Ty::Base(t), Ty::Composite(_, _)) => {
-                err.report(loc, format!("Incompatible type: {} and advanced type", t));
+            Err(Mismatch::Incompatible) => {
+                self.report_mismatch(t_var_1, t_var_2, err, loc);
                 Err(())
             }
+            Err(Mismatch::Reported) => Err(()),
+        }
+    }
+
+    fn unify_ty_ty(
+        &mut self,
+        ty_1: &'ty Ty,
+        ty_2: &'ty Ty,
+        err: &mut impl ErrorHandler,
+        loc: Location,
+    ) -> Result<Progress, Mismatch> {
+        match (ty_1, ty_2) {
+            (Ty::Var(t_1), ty) => self.unify_var_ty(t_1, ty, err, loc),
+            (Ty::Base(t_1), Ty::Base(t_2)) => self.unify_base_base(t_1, t_2),
+            (Ty::Base(t), Ty::OneOf(t_var, ts)) => self.unify_base_oneof(ty_1, t, t_var, ts),
+            (Ty::Base(_), Ty::Composite(_, _)) => Err(Mismatch::Incompatible),
             (Ty::OneOf(t_var_1, ts_1), Ty::OneOf(t_var_2, ts_2)) => {
-                self.unify_oneof_oneof(*t_var_1, ts_1, *t_var_2, ts_2, err, loc)
-            }
-            (Ty::OneOf(_, _), Ty::Composite(_, _)) => {
-                err.report(loc, String::from("Incompatible types"));
-                Err(())
+                self.unify_oneof_oneof(*t_var_1, ts_1, *t_var_2, ts_2)
             }
+            (Ty::OneOf(_, _), Ty::Composite(_, _)) => Err(Mismatch::Incompatible),
             (Ty::Composite(kind_1, tys_1), Ty::Composite(kind_2, tys_2)) => {
                 self.unify_composite_composite(kind_1, tys_1, kind_2, tys_2, err, loc)
             }
-            _ => return self.unify_var_var(t_var_2, t_var_1, err, loc),
+            _ => self.unify_ty_ty(ty_2, ty_1, err, loc),
         }
     }
 
@@ -512,7 +656,7 @@ impl<'ctx, 'ty> TypeChecker<'ctx, 'ty> {
                 match kind {
                     CompositeKind::Struct(s_id) => {
                         let t_var_field = self.get_field(*s_id, &field_name, structs, err, loc)?;
-                        self.unify_var_var(t_var, t_var_field, err, loc)
+                        self.unify_var_var(t_var_field, t_var, err, loc)
                         // Note: once generics are implemented they should be checked here
                     }
                     CompositeKind::Tuple => {
@@ -533,12 +677,22 @@ impl<'ctx, 'ty> TypeChecker<'ctx, 'ty> {
                             );
                             return Err(());
                         }
-                        self.unify_var_var(t_var, ts[idx], err, loc)
+                        self.unify_var_var(ts[idx], t_var, err, loc)
                     }
                     CompositeKind::Fun => {
                         err.report(loc, String::from("Can't access field of a function"));
                         Err(())
                     }
+                    CompositeKind::Ref => {
+                        err.report(
+                            loc,
+                            format!(
+                                "No field '{}' on references, dereference them first with `*`",
+                                &field_name
+                            ),
+                        );
+                        Err(())
+                    }
                 }
             }
         }
@@ -579,7 +733,7 @@ impl<'ctx, 'ty> TypeChecker<'ctx, 'ty> {
                     let mut progress = Progress::None;
                     for (arg_t_var, param_t_var) in t_var_args.iter().zip(types.iter()) {
                         if let Ok(Progress::Some) =
-                            self.unify_var_var(*arg_t_var, *param_t_var, err, loc)
+                            self.unify_var_var(*param_t_var, *arg_t_var, err, loc)
                         {
                             progress = Progress::Some;
                         }
@@ -614,7 +768,7 @@ impl<'ctx, 'ty> TypeChecker<'ctx, 'ty> {
             }
             Ty::Composite(CompositeKind::Fun, types) => {
                 if let Some(fun_ret_t_var) = types.last() {
-                    self.unify_var_var(t_var_ret, *fun_ret_t_var, err, loc)
+                    self.unify_var_var(*fun_ret_t_var, t_var_ret, err, loc)
                 } else {
                     err.report_internal(
                         loc,
@@ -697,7 +851,7 @@ impl<'ctx, 'ty> TypeChecker<'ctx, 'ty> {
         ty: &'ty Ty,
         err: &mut impl ErrorHandler,
         loc: Location,
-    ) -> Result<Progress, ()> {
+    ) -> Result<Progress, Mismatch> {
         match ty {
             Ty::Var(_) | Ty::OneOf(_, _) | Ty::Base(_) => self.subs.insert_ref(*t_var, ty),
             Ty::Composite(_kind, t_vars) => {
@@ -713,12 +867,9 @@ impl<'ctx, 'ty> TypeChecker<'ctx, 'ty> {
         &mut self,
         t_1: &ScalarType,
         t_2: &ScalarType,
-        err: &mut impl ErrorHandler,
-        loc: Location,
-    ) -> Result<Progress, ()> {
+    ) -> Result<Progress, Mismatch> {
         if t_1 != t_2 {
-            err.report(loc, format!("Expected type {}, got {}", t_1, t_2));
-            Err(())
+            Err(Mismatch::Incompatible)
         } else {
             Ok(Progress::None)
         }
@@ -730,25 +881,12 @@ impl<'ctx, 'ty> TypeChecker<'ctx, 'ty> {
         t: &ScalarType,
         t_var: &TypeVar,
         ts: &Vec<ScalarType>,
-        err: &mut impl ErrorHandler,
-        loc: Location,
-    ) -> Result<Progress, ()> {
+    ) -> Result<Progress, Mismatch> {
         if ts.contains(t) {
             self.subs.insert_ref(*t_var, ty_ref);
             Ok(Progress::Some)
         } else {
-            err.report(
-                loc,
-                format!(
-                    "Incompatible types: can be one of {} but got {}",
-                    ts.iter()
-                        .map(|t| format!("{}", t))
-                        .collect::<Vec<String>>()
-                        .join(", "),
-                    t
-                ),
-            );
-            Err(())
+            Err(Mismatch::Incompatible)
         }
     }
 
@@ -758,9 +896,7 @@ impl<'ctx, 'ty> TypeChecker<'ctx, 'ty> {
         ts_1: &Vec<ScalarType>,
         t_var_2: TypeVar,
         ts_2: &Vec<ScalarType>,
-        err: &mut impl ErrorHandler,
-        loc: Location,
-    ) -> Result<Progress, ()> {
+    ) -> Result<Progress, Mismatch> {
         // TODO: improve algorithm complexity (n_max=7, we can deal with that for now)
         let mut intersection = Vec::new();
         for t in ts_1 {
@@ -777,8 +913,7 @@ impl<'ctx, 'ty> TypeChecker<'ctx, 'ty> {
             self.subs.insert(t_var_2, Ty::Var(t_var_1));
             Ok(Progress::Some)
         } else {
-            err.report(loc, String::from("Incompatible types"));
-            Err(())
+            Err(Mismatch::Incompatible)
         }
     }
 
@@ -790,23 +925,140 @@ impl<'ctx, 'ty> TypeChecker<'ctx, 'ty> {
         tys_2: &Vec<TypeVar>,
         err: &mut impl ErrorHandler,
         loc: Location,
-    ) -> Result<Progress, ()> {
+    ) -> Result<Progress, Mismatch> {
         if kind_1 == kind_2 && tys_1.len() == tys_2.len() {
             let mut progress = Progress::None;
+            let mut error = false;
             for (t_var_1, t_var_2) in tys_1.iter().zip(tys_2.iter()) {
-                if let Ok(Progress::Some) = self.unify_var_var(*t_var_1, *t_var_2, err, loc) {
-                    progress = Progress::Some;
+                match self.unify_var_var(*t_var_1, *t_var_2, err, loc) {
+                    Ok(Progress::Some) => progress = Progress::Some,
+                    Ok(Progress::None) => {}
+                    Err(()) => error = true,
                 }
             }
-            Ok(progress)
+            if error {
+                Err(Mismatch::Reported)
+            } else {
+                Ok(progress)
+            }
         } else {
-            err.report(loc, String::from("Incompatible types"));
-            Err(())
+            Err(Mismatch::Incompatible)
         }
     }
 
     // ————————————————————————————————— Helpers ———————————————————————————————— //
 
+    /// Return the origin of a type variable, if any.
+    fn get_origin(&mut self, t_var: TypeVar) -> Option<&Origin> {
+        if self.origins.contains_key(&t_var) {
+            return self.origins.get(&t_var);
+        }
+        match self.subs.substitute(t_var) {
+            Ty::Var(t_var) | Ty::OneOf(t_var, _) => self.origins.get(t_var),
+            _ => None,
+        }
+    }
+
+    /// Give the origin of `from` to `t_var`, unless it already has one.
+    fn inherit_origin(&mut self, t_var: TypeVar, from: TypeVar) {
+        if self.origins.contains_key(&t_var) {
+            return;
+        }
+        if let Some(origin) = self.get_origin(from).cloned() {
+            self.origins.insert(t_var, origin);
+        }
+    }
+
+    /// Report that the types of `t_var_1` (expected) and `t_var_2` (found) are incompatible.
+    ///
+    /// If one of the types originates from a declaration, it is considered as the expected one
+    /// and the error explains where it comes from.
+    fn report_mismatch(
+        &mut self,
+        t_var_1: TypeVar,
+        t_var_2: TypeVar,
+        err: &mut impl ErrorHandler,
+        loc: Location,
+    ) {
+        let (expected, found, origin) = if let Some(origin) = self.get_origin(t_var_1).cloned() {
+            (t_var_1, t_var_2, origin)
+        } else if let Some(origin) = self.get_origin(t_var_2).cloned() {
+            (t_var_2, t_var_1, origin)
+        } else {
+            let message = format!(
+                "Expected {}, found {}",
+                self.display_t(t_var_1),
+                self.display_t(t_var_2)
+            );
+            err.report(loc, message);
+            return;
+        };
+        let message = format!(
+            "Expected {} because of {} declared here, found {}",
+            self.display_t(expected),
+            origin.what,
+            self.display_t(found)
+        );
+        err.report_with_note(
+            loc,
+            message,
+            origin.loc,
+            format!("{} declared here", origin.what),
+        );
+    }
+
+    /// Return a human readable representation of the type of a type variable.
+    pub fn display_t(&mut self, t_var: TypeVar) -> String {
+        match self.subs.substitute(t_var) {
+            Ty::Var(_) => String::from("_"),
+            Ty::Base(t) => format!("{}", t),
+            Ty::OneOf(_, ts) => format!(
+                "one of {}",
+                ts.iter()
+                    .map(|t| format!("{}", t))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+            Ty::Composite(kind, ts) => match kind {
+                CompositeKind::Struct(s_id) => {
+                    if let Some(ident) = self.struct_idents.get(s_id) {
+                        format!("struct {}", ident)
+                    } else if let Some(struc) = self.ctx.get_struct(*s_id) {
+                        format!("struct {}", struc.ident)
+                    } else {
+                        String::from("struct")
+                    }
+                }
+                CompositeKind::Tuple => {
+                    let types = ts
+                        .iter()
+                        .map(|t| self.display_t(*t))
+                        .collect::<Vec<String>>();
+                    if types.len() == 1 {
+                        format!("({},)", types[0])
+                    } else {
+                        format!("({})", types.join(", "))
+                    }
+                }
+                CompositeKind::Ref => match ts.first() {
+                    Some(t) => format!("&{}", self.display_t(*t)),
+                    None => String::from("&_"),
+                },
+                CompositeKind::Fun => match ts.split_last() {
+                    Some((ret, params)) => {
+                        let params = params
+                            .iter()
+                            .map(|t| self.display_t(*t))
+                            .collect::<Vec<String>>()
+                            .join(", ");
+                        format!("fun({}): {}", params, self.display_t(*ret))
+                    }
+                    None => String::from("fun"),
+                },
+            },
+        }
+    }
+
     /// Lift an HIR type (typically obtained through the Ctx and from another module) into a type
     /// variable.
     ///
@@ -831,13 +1083,24 @@ impl<'ctx, 'ty> TypeChecker<'ctx, 'ty> {
                 t_var
             }
             hir::Type::Tuple(tup_id) => {
-                let tup = self.ctx.get_tuple(*tup_id).unwrap();
+                let tup = match self.ctx.get_tuple(*tup_id) {
+                    Some(tup) => tup,
+                    // Left unconstrained, the type variable is reported when solving types
+                    None => return self.fresh(),
+                };
                 let types = tup.types.iter().map(|t| self.lift_t(t)).collect();
                 let t_var = self.fresh();
                 self.subs
                     .insert(t_var, Ty::Composite(CompositeKind::Tuple, types));
                 t_var
             }
+            hir::Type::Ref(t) => {
+                let pointee = self.lift_t(t);
+                let t_var = self.fresh();
+                self.subs
+                    .insert(t_var, Ty::Composite(CompositeKind::Ref, vec![pointee]));
+                t_var
+            }
             hir::Type::Scalar(x) => self.scalar(*x),
         }
     }
@@ -893,7 +1156,8 @@ impl<'ctx, 'ty> TypeChecker<'ctx, 'ty> {
         err: &mut impl ErrorHandler,
         loc: Location,
     ) -> Result<TypeVar, ()> {
-        if let Some(struc) = structs.get(s_id) {
+        if structs.is_local(&s_id) {
+            let struc = structs.expect_get(s_id, "type checking", err)?;
             if let Some(field) = struc.fields.get(field) {
                 Ok(field.t_var)
             } else {
@@ -927,7 +1191,8 @@ impl<'ctx, 'ty> TypeChecker<'ctx, 'ty> {
         err: &mut impl ErrorHandler,
         loc: Location,
     ) -> Result<usize, ()> {
-        if let Some(struc) = structs.get(s_id) {
+        if structs.is_local(&s_id) {
+            let struc = structs.expect_get(s_id, "type checking", err)?;
             Ok(struc.fields.len())
         } else if let Some(struc) = self.ctx.get_struct(s_id) {
             Ok(struc.fields.len())
@@ -946,7 +1211,8 @@ impl<'ctx, 'ty> TypeChecker<'ctx, 'ty> {
         loc: Location,
     ) -> Result<HashSet<String>, ()> {
         let mut set = HashSet::new();
-        if let Some(struc) = structs.get(s_id) {
+        if structs.is_local(&s_id) {
+            let struc = structs.expect_get(s_id, "type checking", err)?;
             for (field, _) in &struc.fields {
                 set.insert(field.clone());
             }
@@ -998,7 +1264,7 @@ mod tests {
         let ctx = Ctx::new();
         let loc = Location::dummy();
         let structs = StructStore::new(ModId(1));
-        let mut err = DummyHandler::new_no_file();
+        let mut err = DummyHandler::new();
         let mut checker = TypeChecker::new(&ctx, &store, ModId(42));
 
         let t_var_1 = checker.fresh();
@@ -1115,6 +1381,7 @@ impl fmt::Display for CompositeKind {
             CompositeKind::Tuple => write!(f, "Tuple"),
             CompositeKind::Fun => write!(f, "Fun"),
             CompositeKind::Struct(s_id) => write!(f, "Struct({})", s_id),
+            CompositeKind::Ref => write!(f, "Ref"),
         }
     }
 }
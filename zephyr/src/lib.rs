@@ -5,8 +5,17 @@ mod ast;
 mod ctx;
 mod hir;
 mod mir;
+#[cfg(feature = "cranelift")]
+mod native;
+mod ssa;
 mod wasm;
 
 pub mod error;
 pub mod resolver;
-pub use ctx::Ctx;
+pub use ast::{format_code, highlight, Feature, Span, SpanKind};
+pub use ctx::{
+    Arch, Artifacts, BuildMetadata, Ctx, Event, FunctionMetrics, GraphKind, ModuleMetrics,
+    ModuleStats, Phase, PostProcess, Snapshot, StoreStats, System, Target, Timings, TEST_PREFIX,
+};
+pub use mir::{GcStrategy, RuntimeValue};
+pub use wasm::custom_sections;
@@ -0,0 +1,33 @@
+//! Backends
+//!
+//! A backend consumes a MIR program and produces the final output of the compiler. The program is
+//! handed over in two steps: the module-level items first (imports, data, memory and start
+//! function), then each function in order. The backend is then asked to produce its output.
+
+use super::mir::{Function, Program};
+
+pub trait Backend {
+    /// The output of the backend, for instance the bytes of a binary module.
+    type Output;
+
+    /// Emits the module-level items of the program.
+    ///
+    /// This is called once, before any function is emitted, the functions of the program are
+    /// visible so that the backend can assign them an index.
+    fn emit_module(&mut self, program: &Program);
+
+    /// Emits a function of the program, functions are emitted in the order of the program.
+    fn emit_function(&mut self, fun: Function);
+
+    /// Produces the output, errors reported while emitting the program result in an `Err`.
+    fn finish(self) -> Result<Self::Output, ()>;
+}
+
+/// Compiles a MIR program with the given backend.
+pub fn emit<B: Backend>(program: Program, mut backend: B) -> Result<B::Output, ()> {
+    backend.emit_module(&program);
+    for fun in program.funs {
+        backend.emit_function(fun);
+    }
+    backend.finish()
+}
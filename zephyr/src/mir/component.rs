@@ -0,0 +1,391 @@
+//! Component Adapters
+//!
+//! The component model passes values following the canonical ABI: strings are passed as a
+//! pointer and a length, records are flattened into their fields and results which do not fit in
+//! a single value are written to memory, a pointer to them being returned instead. Zephyr passes
+//! strings and structs as pointers to their location in memory, thus exposed functions taking or
+//! returning such values are wrapped into adapters converting between both representations.
+//!
+//! The host allocates the memory of the values it passes with the exposed `cabi_realloc`. The
+//! structs built by the adapters are allocated like any other struct of the module, that is with
+//! a reference count when reference counting is enabled.
+
+use super::mir::*;
+use crate::hir::known_ids::{adapter_id, REALLOC_ID};
+
+/// Name under which the allocator used by the host is exposed.
+pub const REALLOC_EXPORT: &str = "cabi_realloc";
+
+/// A value crossing the boundary of a component.
+pub enum CanonType {
+    /// A scalar, along with its layout in canonical memory.
+    Scalar(Type, MemoryLayout),
+    Str,
+    /// A struct, its fields are sorted by name and come with their offset, type and layout in the
+    /// struct.
+    Record {
+        size: u32,
+        fields: Vec<(Offset, Type, MemoryLayout, CanonType)>,
+    },
+}
+
+/// The signature of an exposed function, as seen by the host.
+pub struct CanonSignature {
+    pub params: Vec<CanonType>,
+    pub ret: Option<CanonType>,
+}
+
+/// The layout of a `Str` in memory.
+pub struct StrLayout {
+    pub size: u32,
+    pub len: Offset,
+    pub start: Offset,
+}
+
+impl CanonType {
+    /// Appends the types of the values this type is flattened into.
+    fn flatten(&self, types: &mut Vec<Type>) {
+        match self {
+            CanonType::Scalar(t, _) => types.push(*t),
+            CanonType::Str => types.extend(&[Type::I32, Type::I32]),
+            CanonType::Record { fields, .. } => {
+                for (_, _, _, t) in fields {
+                    t.flatten(types);
+                }
+            }
+        }
+    }
+
+    /// Alignment of the type in canonical memory.
+    fn alignment(&self) -> u32 {
+        match self {
+            CanonType::Scalar(_, layout) => layout.offset(),
+            CanonType::Str => 4,
+            CanonType::Record { fields, .. } => fields
+                .iter()
+                .map(|(_, _, _, t)| t.alignment())
+                .max()
+                .unwrap_or(1),
+        }
+    }
+
+    /// Size of the type in canonical memory.
+    fn size(&self) -> u32 {
+        match self {
+            CanonType::Scalar(_, layout) => layout.offset(),
+            CanonType::Str => 8,
+            CanonType::Record { fields, .. } => {
+                let mut size = 0;
+                for (_, _, _, t) in fields {
+                    size = align(size, t.alignment()) + t.size();
+                }
+                align(size, self.alignment())
+            }
+        }
+    }
+}
+
+impl CanonSignature {
+    /// Functions passing only scalars follow the canonical ABI already.
+    fn needs_adapter(&self) -> bool {
+        self.params
+            .iter()
+            .chain(self.ret.iter())
+            .any(|t| !matches!(t, CanonType::Scalar(..)))
+    }
+}
+
+/// Exposes adapters in place of the exposed functions of `signatures` which do not follow the
+/// canonical ABI, along with `cabi_realloc`. Structs are allocated with `struct_alloc`, which
+/// takes a size and returns an address just as `malloc`.
+pub fn expose_adapters(
+    funs: &mut Vec<Function>,
+    signatures: &[(FunId, CanonSignature)],
+    malloc: FunId,
+    struct_alloc: FunId,
+    str_layout: &StrLayout,
+) -> Result<(), String> {
+    let mut adapters = Vec::new();
+    for (idx, (fun_id, signature)) in signatures.iter().enumerate() {
+        if !signature.needs_adapter() {
+            continue;
+        }
+        let fun = funs
+            .iter_mut()
+            .find(|fun| fun.fun_id == *fun_id)
+            .ok_or_else(|| format!("Can't adapt fun: no fun with id '{}'", fun_id))?;
+        let mut builder = Builder {
+            params: Vec::new(),
+            param_t: Vec::new(),
+            locals: Vec::new(),
+            stmts: Vec::new(),
+            next_local: 0,
+            malloc,
+            struct_alloc,
+            str_layout,
+        };
+        let args = signature
+            .params
+            .iter()
+            .map(|t| builder.lift(t))
+            .collect::<Vec<_>>();
+        for arg in args {
+            builder.stmts.push(Statement::Local(Local::Get(arg)));
+        }
+        builder
+            .stmts
+            .push(Statement::Call(Call::Direct(fun.fun_id)));
+        let mut ret_t = Vec::new();
+        if let Some(t) = &signature.ret {
+            let value = builder.fresh(fun.ret_t[0]);
+            builder.stmts.push(Statement::Local(Local::Set(value)));
+            t.flatten(&mut ret_t);
+            if ret_t.len() > 1 {
+                let area = builder.malloc(t.size());
+                builder.write(value, t, area, 0);
+                builder.stmts.push(Statement::Local(Local::Get(area)));
+                ret_t = vec![Type::I32];
+            } else {
+                builder.push_flat(value, t);
+            }
+        }
+        adapters.push(Function {
+            ident: format!("{}_adapter", fun.ident),
+            params: builder.params,
+            param_t: builder.param_t,
+            ret_t,
+            locals: builder.locals,
+            body: Block::Block {
+                id: 0,
+                stmts: builder.stmts,
+                t: None,
+            },
+            is_pub: false,
+            inline: Inline::Auto,
+            exposed: fun.exposed.take(),
+            fun_id: adapter_id(idx as u32),
+        });
+    }
+    funs.extend(adapters);
+    funs.push(realloc(malloc));
+    Ok(())
+}
+
+/// Returns `cabi_realloc`, only fresh allocations are requested when passing strings.
+fn realloc(malloc: FunId) -> Function {
+    Function {
+        ident: String::from(REALLOC_EXPORT),
+        params: vec![0, 1, 2, 3],
+        param_t: vec![Type::I32; 4],
+        ret_t: vec![Type::I32],
+        locals: Vec::new(),
+        body: Block::Block {
+            id: 0,
+            stmts: vec![
+                Statement::Local(Local::Get(3)),
+                Statement::Call(Call::Direct(malloc)),
+            ],
+            t: None,
+        },
+        is_pub: false,
+        inline: Inline::Auto,
+        exposed: Some(String::from(REALLOC_EXPORT)),
+        fun_id: REALLOC_ID,
+    }
+}
+
+/// Builds the body of an adapter.
+struct Builder<'a> {
+    params: Vec<LocalId>,
+    param_t: Vec<Type>,
+    locals: Vec<LocalVariable>,
+    stmts: Vec<Statement>,
+    next_local: LocalId,
+    malloc: FunId,
+    struct_alloc: FunId,
+    str_layout: &'a StrLayout,
+}
+
+impl<'a> Builder<'a> {
+    fn fresh(&mut self, t: Type) -> LocalId {
+        let id = self.next_local;
+        self.next_local += 1;
+        self.locals.push(LocalVariable { id, t });
+        id
+    }
+
+    fn param(&mut self, t: Type) -> LocalId {
+        let id = self.next_local;
+        self.next_local += 1;
+        self.params.push(id);
+        self.param_t.push(t);
+        id
+    }
+
+    /// Allocates `size` bytes, returns a local holding their address.
+    fn malloc(&mut self, size: u32) -> LocalId {
+        self.alloc(self.malloc, size)
+    }
+
+    /// Allocates a struct of `size` bytes, returns a local holding its address.
+    fn alloc_struct(&mut self, size: u32) -> LocalId {
+        self.alloc(self.struct_alloc, size)
+    }
+
+    fn alloc(&mut self, alloc: FunId, size: u32) -> LocalId {
+        let ptr = self.fresh(Type::I32);
+        self.stmts.extend(vec![
+            Statement::Const(Value::I32(size as i32)),
+            Statement::Call(Call::Direct(alloc)),
+            Statement::Local(Local::Set(ptr)),
+        ]);
+        ptr
+    }
+
+    fn load(&mut self, ptr: LocalId, t: Type, layout: MemoryLayout, offset: Offset) -> LocalId {
+        let value = self.fresh(t);
+        let load = match (t, layout) {
+            (Type::I32, MemoryLayout::U8) => Memory::I32Load8u { align: 0, offset },
+            (Type::I32, _) => Memory::I32Load { align: 2, offset },
+            (Type::I64, MemoryLayout::U8) => Memory::I64Load8u { align: 0, offset },
+            (Type::I64, _) => Memory::I64Load { align: 3, offset },
+            (Type::F32, _) => Memory::F32Load { align: 2, offset },
+            (Type::F64, _) => Memory::F64Load { align: 3, offset },
+            (Type::V128, _) => Memory::V128Load { align: 4, offset },
+        };
+        self.stmts.extend(vec![
+            Statement::Local(Local::Get(ptr)),
+            Statement::Memory(load),
+            Statement::Local(Local::Set(value)),
+        ]);
+        value
+    }
+
+    fn store(
+        &mut self,
+        ptr: LocalId,
+        value: LocalId,
+        t: Type,
+        layout: MemoryLayout,
+        offset: Offset,
+    ) {
+        let store = match (t, layout) {
+            (Type::I32, MemoryLayout::U8) => Memory::I32Store8 { align: 0, offset },
+            (Type::I32, _) => Memory::I32Store { align: 2, offset },
+            (Type::I64, MemoryLayout::U8) => Memory::I64Store8 { align: 0, offset },
+            (Type::I64, _) => Memory::I64Store { align: 3, offset },
+            (Type::F32, _) => Memory::F32Store { align: 2, offset },
+            (Type::F64, _) => Memory::F64Store { align: 3, offset },
+            (Type::V128, _) => Memory::V128Store { align: 4, offset },
+        };
+        self.stmts.extend(vec![
+            Statement::Local(Local::Get(ptr)),
+            Statement::Local(Local::Get(value)),
+            Statement::Memory(store),
+        ]);
+    }
+
+    /// Builds a Zephyr value out of flattened parameters, returns a local holding it.
+    fn lift(&mut self, t: &CanonType) -> LocalId {
+        match t {
+            CanonType::Scalar(t, _) => self.param(*t),
+            CanonType::Str => {
+                let start = self.param(Type::I32);
+                let len = self.param(Type::I32);
+                let s = self.alloc_struct(self.str_layout.size);
+                let layout = MemoryLayout::I32;
+                self.store(s, start, Type::I32, layout, self.str_layout.start);
+                self.store(s, len, Type::I32, layout, self.str_layout.len);
+                s
+            }
+            CanonType::Record { size, fields } => {
+                let mut values = Vec::with_capacity(fields.len());
+                for (_, _, _, field_t) in fields {
+                    values.push(self.lift(field_t));
+                }
+                let record = self.alloc_struct(*size);
+                for ((offset, t, layout, _), value) in fields.iter().zip(values) {
+                    self.store(record, value, *t, *layout, *offset);
+                }
+                record
+            }
+        }
+    }
+
+    /// Pushes the flattened values of a Zephyr value onto the stack.
+    fn push_flat(&mut self, value: LocalId, t: &CanonType) {
+        match t {
+            CanonType::Scalar(..) => self.stmts.push(Statement::Local(Local::Get(value))),
+            CanonType::Str => {
+                let layout = MemoryLayout::I32;
+                let start = self.load(value, Type::I32, layout, self.str_layout.start);
+                let len = self.load(value, Type::I32, layout, self.str_layout.len);
+                self.stmts.push(Statement::Local(Local::Get(start)));
+                self.stmts.push(Statement::Local(Local::Get(len)));
+            }
+            CanonType::Record { fields, .. } => {
+                for (offset, t, layout, field_t) in fields {
+                    let field = self.load(value, *t, *layout, *offset);
+                    self.push_flat(field, field_t);
+                }
+            }
+        }
+    }
+
+    /// Writes a Zephyr value in canonical memory, at `offset` from `ptr`.
+    fn write(&mut self, value: LocalId, t: &CanonType, ptr: LocalId, offset: Offset) {
+        match t {
+            CanonType::Scalar(t, layout) => self.store(ptr, value, *t, *layout, offset),
+            CanonType::Str => {
+                let layout = MemoryLayout::I32;
+                let start = self.load(value, Type::I32, layout, self.str_layout.start);
+                let len = self.load(value, Type::I32, layout, self.str_layout.len);
+                self.store(ptr, start, Type::I32, layout, offset);
+                self.store(ptr, len, Type::I32, layout, offset + 4);
+            }
+            CanonType::Record { fields, .. } => {
+                let mut field_offset = 0;
+                for (zephyr_offset, zephyr_t, zephyr_layout, field_t) in fields {
+                    field_offset = align(field_offset, field_t.alignment());
+                    let field = self.load(value, *zephyr_t, *zephyr_layout, *zephyr_offset);
+                    self.write(field, field_t, ptr, offset + field_offset);
+                    field_offset += field_t.size();
+                }
+            }
+        }
+    }
+}
+
+/// Rounds `offset` up to the next multiple of `alignment`.
+fn align(offset: u32, alignment: u32) -> u32 {
+    offset.div_ceil(alignment) * alignment
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn canonical_layout() {
+        let scalar = |t: Type| CanonType::Scalar(t, t.layout());
+        let record = CanonType::Record {
+            size: 24,
+            fields: vec![
+                (
+                    16,
+                    Type::I32,
+                    MemoryLayout::U8,
+                    CanonType::Scalar(Type::I32, MemoryLayout::U8),
+                ),
+                (0, Type::F64, MemoryLayout::F64, scalar(Type::F64)),
+                (8, Type::I32, MemoryLayout::I32, CanonType::Str),
+            ],
+        };
+        assert_eq!(record.alignment(), 8);
+        // bool at 0, f64 at 8, string at 16
+        assert_eq!(record.size(), 24);
+        let mut types = Vec::new();
+        record.flatten(&mut types);
+        assert_eq!(types, vec![Type::I32, Type::F64, Type::I32, Type::I32]);
+    }
+}
@@ -5,34 +5,41 @@
 //! and so on...
 use std::collections::{HashMap, HashSet};
 
+use super::component::{expose_adapters, CanonSignature, CanonType, StrLayout};
 use super::mir::*;
+use super::{GcStrategy, LoweringOptions, RC_HEADER_SIZE};
 
 use crate::arena::Arena;
-use crate::ctx::{Ctx, KnownFunctions};
-use crate::error::ErrorHandler;
+use crate::ctx::{Ctx, KnownFunctions, KnownStructs};
+use crate::error::{ErrorHandler, Location};
+use crate::hir::known_ids::{drop_id, reference_id, INIT_ID};
 use crate::hir::{
-    AccessKind, Binop as HirBinop, Block as HirBlock, Body as HirBody, Data as HirData,
-    Expression as Expr, FunKind, Function as HirFun, FunctionPrototype as HirFunProto,
-    Import as HirImport, IntegerType as HirIntergerType, LocalId as HirLocalId,
+    AccessKind, Binop as HirBinop, Block as HirBlock, Body as HirBody, Constant as HirConstant,
+    Data as HirData, Expression as Expr, FieldValue as HirFieldValue, FunKind, Function as HirFun,
+    FunctionPrototype as HirFunProto, FunctionType as HirFunType, Import as HirImport,
+    IntegerType as HirIntergerType, Intrinsic, LocalId as HirLocalId,
     LocalVariable as HirLocalVariable, NonNullScalarType as HirNonNullScalarType,
     NumericType as HirNumericType, PlaceExpression as PlaceExpr, ScalarType as HirScalarType,
     Statement as S, Struct as HirStruct, Tuple as HirTuple, TupleId, Type as HirType,
-    Unop as HirUnop, Value as V,
+    Unop as HirUnop, Value as V, Variable as HirVariable,
 };
 use crate::hir::{AsmControl, AsmLocal, AsmMemory, AsmParametric, AsmStatement};
 
+/// Memory layout of a value: its MIR types, along with how and at which offset each is stored.
+type ValueLayout = Vec<(Type, MemoryLayout, Offset)>;
+
 enum FromBinop {
     Binop(Binop),
     Relop(Relop),
     Logical(Logical),
 }
 
-pub enum Place<'a> {
-    Local(&'a [LocalId], Vec<Type>),
+pub enum Place {
+    Local(Vec<LocalId>, Vec<Type>),
     Address {
         address_l_id: LocalId,
         offset: u32,
-        t: &'a Vec<(Type, MemoryLayout, Offset)>,
+        t: ValueLayout,
     },
 }
 
@@ -40,14 +47,17 @@ struct MIR {
     funs: Vec<Function>,
     imports: Vec<Imports>,
     data: HashMap<DataId, Data>,
+    table: Vec<FunId>,
 }
 
 struct HIR<'a> {
     funs: &'a HashMap<FunId, FunKind>,
+    inits: &'a Vec<FunId>,
     tuples: &'a HashMap<TupleId, HirTuple>,
     structs: &'a HashMap<StructId, HirStruct>,
     imports: &'a Vec<HirImport>,
     data: &'a HashMap<DataId, HirData>,
+    known_structs: Option<&'a KnownStructs>,
 }
 
 pub struct MirProducer<'a, 'arena, E: ErrorHandler> {
@@ -61,8 +71,27 @@ pub struct MirProducer<'a, 'arena, E: ErrorHandler> {
     // A mapping from HIR local variable ID to MIR local variable ID
     locals: HashMap<HirLocalId, Vec<LocalId>>,
 
-    // Functions used by the runtime, such as `malloc`
-    known_funs: &'a KnownFunctions,
+    // Local variables of the current function whose address is taken, they are stored in its
+    // frame on the shadow stack, along with their layout and offset in the frame
+    in_memory: HashMap<HirLocalId, (ValueLayout, Offset)>,
+
+    // The local holding the address of the frame of the current function and the size of the
+    // frame, if the function has one
+    frame: Option<(LocalId, u32)>,
+
+    // Variables of the current function bound to structs that never escape it, those are
+    // allocated in its frame at the given offset
+    stack_structs: HashMap<HirLocalId, Offset>,
+
+    // Variables of the current function holding references to structs, which are released when
+    // the function returns (only with reference counting)
+    owned: Vec<(HirLocalId, HirType)>,
+
+    // Functions used by the runtime, such as `malloc`, unknown when compiling without a runtime
+    known_funs: Option<&'a KnownFunctions>,
+
+    // Overflow checks and trap handler
+    options: LoweringOptions,
 
     // MIR & HIR items
     mir: MIR,
@@ -91,6 +120,7 @@ impl MIR {
             funs: Vec::new(),
             imports: Vec::new(),
             data: HashMap::new(),
+            table: Vec::new(),
         }
     }
 }
@@ -98,34 +128,57 @@ impl MIR {
 impl<'a> HIR<'a> {
     pub fn new(ctx: &'a Ctx) -> Self {
         let funs = ctx.hir_funs();
+        let inits = ctx.hir_inits();
         let tuples = ctx.hir_tuples();
         let structs = ctx.hir_structs();
         let imports = ctx.hir_imports();
         let data = ctx.hir_data();
+        let known_structs = ctx.hir_known_structs();
 
         Self {
             funs,
+            inits,
             tuples,
             structs,
             imports,
             data,
+            known_structs,
         }
     }
+
+    /// Returns the functions sorted by ID, so that they are lowered in the same order from one
+    /// compilation to another.
+    fn sorted_funs(&self) -> Vec<(&'a FunId, &'a FunKind)> {
+        let mut funs: Vec<_> = self.funs.iter().collect();
+        funs.sort_unstable_by_key(|(fun_id, _)| **fun_id);
+        funs
+    }
+
+    /// Returns true if `s_id` is the `Str` struct of the runtime.
+    fn is_str(&self, s_id: StructId) -> bool {
+        self.known_structs
+            .map_or(false, |known_structs| known_structs.str == s_id)
+    }
 }
 
 impl<'a, 'arena, E: ErrorHandler> MirProducer<'a, 'arena, E> {
     fn new(
         ctx: &'a Ctx,
-        known_funs: &'a KnownFunctions,
         struct_arena: &'arena Arena<Struct>,
         tuple_arena: &'arena Arena<Tuple>,
+        options: LoweringOptions,
         err: &'a mut E,
     ) -> Self {
         Self {
             bb_id: 0,
             local_id: 0,
             locals: HashMap::new(),
-            known_funs,
+            in_memory: HashMap::new(),
+            frame: None,
+            stack_structs: HashMap::new(),
+            owned: Vec::new(),
+            known_funs: ctx.hir_known_funs(),
+            options,
             err,
             mir: MIR::new(),
             hir: HIR::new(ctx),
@@ -140,17 +193,27 @@ impl<'a, 'arena, E: ErrorHandler> MirProducer<'a, 'arena, E> {
         }
     }
 
-    pub fn lower(ctx: &'a Ctx, known_funs: &'a KnownFunctions, err: &'a mut E) -> Program {
+    /// Lowers the exposed functions, the functions in `roots` and all the functions they use.
+    pub fn lower(
+        ctx: &'a Ctx,
+        roots: &[FunId],
+        options: LoweringOptions,
+        err: &'a mut E,
+    ) -> Program {
         let struct_arena = Arena::new();
         let tuple_arena = Arena::new();
-        let reducer = MirProducer::new(ctx, known_funs, &struct_arena, &tuple_arena, err);
-        let mir = reducer.do_lower();
+        let reducer = MirProducer::new(ctx, &struct_arena, &tuple_arena, options, err);
+        let mir = reducer.do_lower(roots);
         mir
     }
 
-    fn do_lower(mut self) -> Program {
+    fn do_lower(mut self, roots: &[FunId]) -> Program {
+        let entry = self.options.entry;
+        for fun_id in roots.iter().chain(&entry) {
+            self.use_fun(*fun_id);
+        }
         // Register exposed functions
-        for (fun_id, fun_kind) in self.hir.funs {
+        for (fun_id, fun_kind) in self.hir.sorted_funs() {
             match fun_kind {
                 FunKind::Fun(fun) => {
                     if fun.exposed.is_some() {
@@ -160,6 +223,16 @@ impl<'a, 'arena, E: ErrorHandler> MirProducer<'a, 'arena, E> {
                 _ => (),
             }
         }
+        // Register init functions
+        for fun_id in self.hir.inits {
+            self.use_fun(*fun_id);
+        }
+        // Component adapters allocate structs once all functions are lowered
+        if self.options.component && self.is_rc() {
+            if let Some(known_funs) = self.known_funs {
+                self.use_fun(known_funs.rc_alloc);
+            }
+        }
 
         while let Some(fun_id) = self.todo_funs.pop() {
             // Retrieve HIR fun
@@ -180,8 +253,8 @@ impl<'a, 'arena, E: ErrorHandler> MirProducer<'a, 'arena, E> {
             };
             // Lower fun
             match self.lower_fun(fun) {
-                Ok(fun) => self.mir.funs.push(fun),
-                Err(err) => self.err.report_internal_no_loc(err),
+                Ok(mir_fun) => self.mir.funs.push(mir_fun),
+                Err(err) => self.err.report_internal(fun.loc, err),
             }
         }
 
@@ -198,9 +271,12 @@ impl<'a, 'arena, E: ErrorHandler> MirProducer<'a, 'arena, E> {
                 }
             };
             // Lower data
-            self.mir
-                .data
-                .insert(data_id, MirProducer::<E>::lower_data(data));
+            match self.lower_data(data) {
+                Ok(data) => {
+                    self.mir.data.insert(data_id, data);
+                }
+                Err(err) => self.err.report_internal_no_loc(err),
+            }
         }
 
         for import in self.hir.imports {
@@ -246,10 +322,145 @@ impl<'a, 'arena, E: ErrorHandler> MirProducer<'a, 'arena, E> {
             }
         }
 
+        let start = self.lower_inits();
+        if self.options.component {
+            self.lower_component_adapters();
+        }
+        // Functions are lowered in no particular order, sort them for reproducible builds
+        self.mir.funs.sort_by_key(|fun| fun.fun_id);
+        self.mir.table.sort();
         Program {
             funs: self.mir.funs,
             data: self.mir.data,
             imports: self.mir.imports,
+            start,
+            memory: self.options.memory,
+            exports: self.options.exports.clone(),
+            table: self.mir.table,
+            allocator: self.options.runtime,
+        }
+    }
+
+    /// Synthesizes a function calling the init functions of all modules, if any, and returns
+    /// the ID of the function to use as wasm start function.
+    ///
+    /// When a function is exposed as the entry point of the target, such as `_start` for WASI, the
+    /// initializer is called at the top of its body instead, as the host may not be ready to serve
+    /// imports while the start function runs. On targets without such a convention the function
+    /// marked `#[entry]`, if any, becomes the start function and calls the initializer itself.
+    fn lower_inits(&mut self) -> Option<FunId> {
+        let entry_name = self.options.target.entry_point();
+        if let (Some(entry), Some(name)) = (self.options.entry, entry_name) {
+            if let Some(fun) = self.mir.funs.iter_mut().find(|fun| fun.fun_id == entry) {
+                fun.exposed = Some(String::from(name));
+            }
+        }
+        let start = match entry_name {
+            Some(_) => None,
+            None => self.options.entry,
+        };
+        if self.hir.inits.is_empty() {
+            return start;
+        }
+        let stmts = self
+            .hir
+            .inits
+            .iter()
+            .map(|fun_id| Statement::Call(Call::Direct(*fun_id)))
+            .collect();
+        let id = self.fresh_bb_id();
+        self.mir.funs.push(Function {
+            ident: String::from("init"),
+            params: Vec::new(),
+            param_t: Vec::new(),
+            ret_t: Vec::new(),
+            locals: Vec::new(),
+            body: Block::Block { id, stmts, t: None },
+            is_pub: false,
+            inline: Inline::Auto,
+            exposed: None,
+            fun_id: INIT_ID,
+        });
+
+        let entry_point = self.mir.funs.iter_mut().find(|fun| match entry_name {
+            Some(name) => fun.exposed.as_deref() == Some(name),
+            None => Some(fun.fun_id) == start,
+        });
+        match entry_point {
+            Some(Function {
+                body: Block::Block { stmts, .. },
+                ..
+            }) => {
+                stmts.insert(0, Statement::Call(Call::Direct(INIT_ID)));
+                start
+            }
+            _ => Some(INIT_ID),
+        }
+    }
+
+    /// Exposes adapters following the canonical ABI of the component model in place of the
+    /// exposed functions passing strings or structs.
+    fn lower_component_adapters(&mut self) {
+        // Adapter IDs are attributed in order
+        let mut signatures = Vec::new();
+        for (fun_id, fun_kind) in self.hir.sorted_funs() {
+            let fun = match fun_kind {
+                FunKind::Fun(fun) if fun.exposed.is_some() => fun,
+                _ => continue,
+            };
+            let params = fun
+                .t
+                .params
+                .iter()
+                .map(|t| self.lower_canon_type(t))
+                .collect::<Result<Vec<_>, _>>();
+            let ret = match *fun.t.ret {
+                HirType::Scalar(HirScalarType::Null) => Ok(None),
+                ref t => self.lower_canon_type(t).map(Some),
+            };
+            match (params, ret) {
+                (Ok(params), Ok(ret)) => signatures.push((*fun_id, CanonSignature { params, ret })),
+                (Err(err), _) | (_, Err(err)) => self.err.report(fun.loc, err),
+            }
+        }
+        let (known_funs, known_structs) = match (self.known_funs, self.hir.known_structs) {
+            (Some(known_funs), Some(known_structs)) => (known_funs, known_structs),
+            _ => {
+                self.err.report_internal_no_loc(String::from(
+                    "Components can not be lowered without a runtime",
+                ));
+                return;
+            }
+        };
+        let str_layout = match self.get_struct(&known_structs.str) {
+            Ok(s) => match (s.fields.get("len"), s.fields.get("start")) {
+                (Some(len), Some(start)) => StrLayout {
+                    size: s.size,
+                    len: len.offset,
+                    start: start.offset,
+                },
+                _ => {
+                    self.err
+                        .report_internal_no_loc(String::from("Unexpected layout for 'Str'"));
+                    return;
+                }
+            },
+            Err(err) => {
+                self.err.report_internal_no_loc(err);
+                return;
+            }
+        };
+        let malloc = known_funs.malloc;
+        let struct_alloc = match self.struct_alloc() {
+            Ok(struct_alloc) => struct_alloc,
+            Err(err) => {
+                self.err.report_internal_no_loc(err);
+                return;
+            }
+        };
+        let funs = &mut self.mir.funs;
+        if let Err(err) = expose_adapters(funs, &signatures, malloc, struct_alloc, &str_layout) {
+            self.err.report_internal_no_loc(err);
         }
     }
 
@@ -273,8 +484,10 @@ impl<'a, 'arena, E: ErrorHandler> MirProducer<'a, 'arena, E> {
     ///
     /// ! Locals are assumed to be registered first, this function will panic if this assumption
     /// fail to be satisfied.
-    fn get_local_ids(&self, id: HirLocalId) -> &Vec<LocalId> {
-        self.locals.get(&id).unwrap()
+    fn get_local_ids(&self, id: HirLocalId) -> Result<&Vec<LocalId>, String> {
+        self.locals
+            .get(&id)
+            .ok_or_else(|| format!("No MIR local registered for HIR local {}", id))
     }
 
     /// Creates a new mapping Hir Local -> Vec<Mir Local>
@@ -357,8 +570,11 @@ impl<'a, 'arena, E: ErrorHandler> MirProducer<'a, 'arena, E> {
         };
 
         let mut fields = HashMap::with_capacity(s.fields.len());
+        // Fields are sorted by name so that the layout does not change between builds
+        let mut hir_fields = s.fields.iter().collect::<Vec<_>>();
+        hir_fields.sort_by_key(|(field_name, _)| *field_name);
         // Collect alignments and sizes
-        for (field_name, field) in &s.fields {
+        for (field_name, field) in hir_fields {
             // Compute memory layout of the field
             let t = match self.try_into_mir_layout(&field.t) {
                 Ok(t) => t,
@@ -402,11 +618,9 @@ impl<'a, 'arena, E: ErrorHandler> MirProducer<'a, 'arena, E> {
     ///
     /// The memory blocks returned by malloc are guaranteed to have an alignment of 8, this
     /// function should arrange all the fields so that all of them have an alignment suitable for
-    /// their types while minimizing unused space.
+    /// their types while minimizing unused space. Fields are returned in the order of the tuple
+    /// and are represented as locals in that same order, only their memory layout is reordered.
     fn lower_tuple(&mut self, tup_id: TupleId) -> Result<Tuple, String> {
-        let mut align_1 = Vec::new();
-        let mut align_4 = Vec::new();
-        let mut align_8 = Vec::new();
         let tup = match self.hir.tuples.get(&tup_id) {
             Some(tup) => tup,
             None => {
@@ -417,52 +631,36 @@ impl<'a, 'arena, E: ErrorHandler> MirProducer<'a, 'arena, E> {
             }
         };
 
-        let mut fields = Vec::with_capacity(tup.types.len());
+        // Collect alignments and sizes
+        let mut layouts = Vec::with_capacity(tup.types.len());
         for t in &tup.types {
             // Compute memory layout of the field
             let ts = match self.try_into_mir_layout(t) {
                 Ok(t) => t,
                 Err(e) => {
                     self.err.report_internal_no_loc(e);
-                    continue;
+                    Vec::new()
                 }
             };
             let (alignment, size) = self.get_alignment(&t)?;
-            match alignment {
-                Alignment::A1 => align_1.push((size, ts)),
-                Alignment::A4 => align_4.push((size, ts)),
-                Alignment::A8 => align_8.push((size, ts)),
-            }
+            layouts.push((alignment, size, ts));
         }
 
         // Decide of the layout, this can be optimized in the future
+        let mut offsets = vec![0; layouts.len()];
         let mut offset = 0;
-        let mut local_offset = 0;
-        for (size, t) in align_8.drain(..) {
-            offset = align_offset(offset, Alignment::A8);
-            let nb_locals = t.len();
-            fields.push(TupleField {
-                offset,
-                t,
-                local_offset,
-                nb_locals,
-            });
-            offset += size;
-            local_offset += nb_locals;
-        }
-        for (size, t) in align_4.drain(..) {
-            offset = align_offset(offset, Alignment::A4);
-            let nb_locals = t.len();
-            fields.push(TupleField {
-                offset,
-                t,
-                local_offset,
-                nb_locals,
-            });
-            offset += size;
-            local_offset += nb_locals;
+        for alignment in [Alignment::A8, Alignment::A4, Alignment::A1] {
+            for (idx, (field_alignment, size, _)) in layouts.iter().enumerate() {
+                if *field_alignment == alignment {
+                    offset = align_offset(offset, alignment);
+                    offsets[idx] = offset;
+                    offset += size;
+                }
+            }
         }
-        for (size, t) in align_1.drain(..) {
+        let mut fields = Vec::with_capacity(layouts.len());
+        let mut local_offset = 0;
+        for ((_, _, t), offset) in layouts.into_iter().zip(offsets) {
             let nb_locals = t.len();
             fields.push(TupleField {
                 offset,
@@ -470,7 +668,6 @@ impl<'a, 'arena, E: ErrorHandler> MirProducer<'a, 'arena, E> {
                 local_offset,
                 nb_locals,
             });
-            offset += size;
             local_offset += nb_locals;
         }
         Ok(Tuple {
@@ -480,11 +677,42 @@ impl<'a, 'arena, E: ErrorHandler> MirProducer<'a, 'arena, E> {
         })
     }
 
-    fn lower_data(data: &HirData) -> Data {
-        let data = match data {
-            HirData::Str(_, data) => data.to_owned(),
-        };
-        data
+    /// Lowers a data, the data it points to are registered for lowering.
+    fn lower_data(&mut self, data: &HirData) -> Result<Data, String> {
+        match data {
+            HirData::Bytes(bytes) => Ok(Data {
+                bytes: bytes.clone(),
+                relocations: Vec::new(),
+            }),
+            HirData::Struct { struct_id, fields } => {
+                let struc = self.get_struct(struct_id)?;
+                // With reference counting the struct is preceded by a count of 0, marking it as
+                // static
+                let header = self.struct_header();
+                let mut bytes = vec![0; (header + struc.size) as usize];
+                let mut relocations = Vec::new();
+                for (ident, constant) in fields {
+                    let field = struc.fields.get(ident).ok_or_else(|| {
+                        format!("Field does not exist in MIR struct: '{}'", ident)
+                    })?;
+                    match field.t.as_slice() {
+                        [(Type::I32, MemoryLayout::I32, 0)] => (),
+                        _ => return Err(format!("Field '{}' can not hold an i32 constant", ident)),
+                    }
+                    let offset = (header + field.offset) as usize;
+                    match constant {
+                        HirConstant::I32(val) => {
+                            bytes[offset..(offset + 4)].copy_from_slice(&val.to_le_bytes())
+                        }
+                        HirConstant::DataPointer(data_id) => {
+                            self.use_data(*data_id);
+                            relocations.push((header + field.offset, *data_id));
+                        }
+                    }
+                }
+                Ok(Data { bytes, relocations })
+            }
+        }
     }
 
     fn lower_fun(&mut self, fun: &HirFun) -> Result<Function, String> {
@@ -498,6 +726,22 @@ impl<'a, 'arena, E: ErrorHandler> MirProducer<'a, 'arena, E> {
             param_t.extend(self.try_into_mir_t(&t)?);
         }
         let ret_t = self.try_into_mir_t(&t.ret)?;
+        // Variables whose address is taken are moved to the frame of the function, params and
+        // captured variables are copied there by the prologue
+        let referenced = fun.referenced_locals();
+        let stack_structs = self.lower_stack_structs(fun)?;
+        let mut prologue = Vec::new();
+        self.in_memory.clear();
+        self.stack_structs.clear();
+        self.frame = None;
+        if !referenced.is_empty() || !stack_structs.is_empty() {
+            let frame_l_id = self.fresh_local_id();
+            locals.push(LocalVariable {
+                id: frame_l_id,
+                t: Type::I32,
+            });
+            self.frame = Some((frame_l_id, 0));
+        }
         // Register params and local variables
         assert!(fun.params.len() == fun.t.params.len());
         for (param_local_id, param_t) in fun.params.iter().zip(fun.t.params.iter()) {
@@ -507,15 +751,98 @@ impl<'a, 'arena, E: ErrorHandler> MirProducer<'a, 'arena, E> {
                 local_ids.push(self.fresh_local_id());
             }
             params.extend(local_ids.clone());
-            self.register_locals(*param_local_id, local_ids);
+            if referenced.contains(param_local_id) {
+                for l_id in &local_ids {
+                    prologue.push(Statement::Local(Local::Get(*l_id)));
+                }
+                self.lower_local_in_memory(*param_local_id, param_t)?;
+                self.lower_variable_store(*param_local_id, &mut prologue, &mut locals)?;
+            } else {
+                self.register_locals(*param_local_id, local_ids);
+            }
         }
         for l in &fun.locals {
+            if referenced.contains(&l.id) {
+                self.lower_local_in_memory(l.id, &l.t)?;
+                self.lower_variable_clear(l.id, &l.t, &mut prologue)?;
+                continue;
+            }
+            if let Some(size) = stack_structs.get(&l.id) {
+                self.lower_struct_in_memory(l.id, *size)?;
+            }
             let mir_locals = self.lower_local_variable(l)?;
             self.register_locals(l.id, mir_locals.iter().map(|l| l.id).collect());
             locals.extend(mir_locals);
         }
+        // Closures take their environment as last parameter and start by loading the captured
+        // variables
+        if let Some(captures) = &fun.captures {
+            let env_l_id = self.fresh_local_id();
+            params.push(env_l_id);
+            param_t.push(Type::I32);
+            let (layout, _) = self.lower_env(captures.iter().map(|l| &l.t).collect())?;
+            for (l, (offset, t)) in captures.iter().zip(layout) {
+                let mir_locals = self.lower_local_variable(l)?;
+                for (local, (t, t_layout, t_offset)) in mir_locals.iter().zip(t) {
+                    prologue.push(Statement::Local(Local::Get(env_l_id)));
+                    prologue.push(Statement::Memory(get_load_instr(
+                        t,
+                        t_layout,
+                        offset + t_offset,
+                    )?));
+                    prologue.push(Statement::Local(Local::Set(local.id)));
+                }
+                // The closure keeps its references, the variables hold their own
+                for (local, managed) in mir_locals.iter().zip(self.managed_values(&l.t)?) {
+                    if managed.is_some() {
+                        prologue.push(Statement::Local(Local::Get(local.id)));
+                        self.lower_retain_call(&mut prologue)?;
+                    }
+                }
+                if referenced.contains(&l.id) {
+                    for local in &mir_locals {
+                        prologue.push(Statement::Local(Local::Get(local.id)));
+                    }
+                    locals.extend(mir_locals);
+                    self.lower_local_in_memory(l.id, &l.t)?;
+                    self.lower_variable_store(l.id, &mut prologue, &mut locals)?;
+                    continue;
+                }
+                self.register_locals(l.id, mir_locals.iter().map(|l| l.id).collect());
+                locals.extend(mir_locals);
+            }
+        }
+        // Variables own the references they hold, including params and captured variables
+        self.owned.clear();
+        if self.is_rc() && matches!(fun.body, HirBody::Zephyr(_)) {
+            let params = fun.params.iter().copied().zip(fun.t.params.iter());
+            let captures = fun.captures.iter().flatten();
+            let variables = params
+                .chain(fun.locals.iter().chain(captures).map(|l| (l.id, &l.t)))
+                .collect::<Vec<_>>();
+            for (id, t) in variables {
+                if self.managed_values(t)?.iter().any(Option::is_some) {
+                    self.owned.push((id, t.clone()));
+                }
+            }
+        }
+        // The frame is reserved before any variable is stored in it
+        if let Some((frame_l_id, size)) = &mut self.frame {
+            // Keep the stack pointer aligned to 8
+            *size = (*size + 7) & !7;
+            prologue.splice(
+                0..0,
+                vec![
+                    Statement::Global(Global::Get(STACK_POINTER)),
+                    Statement::Const(Value::I32(*size as i32)),
+                    Statement::Binop(Binop::I32Sub),
+                    Statement::Local(Local::Tee(*frame_l_id)),
+                    Statement::Global(Global::Set(STACK_POINTER)),
+                ],
+            );
+        }
         // Reduce function body
-        let (block, block_locals) = match &fun.body {
+        let (mut block, block_locals) = match &fun.body {
             HirBody::Zephyr(block) => self.lower_block(block)?,
             HirBody::Asm(stmts) => (
                 Block::Block {
@@ -527,6 +854,18 @@ impl<'a, 'arena, E: ErrorHandler> MirProducer<'a, 'arena, E> {
             ),
         };
         locals.extend(block_locals);
+        if let Block::Block { stmts, .. } = &mut block {
+            stmts.splice(0..0, prologue);
+            self.lower_owned_release(stmts)?;
+            self.lower_frame_release(stmts);
+            // Every path returns before reaching the end of a function returning values, see
+            // `HirProducer::check_returns`, but the end must still type check.
+            if let HirBody::Zephyr(body) = &fun.body {
+                if !ret_t.is_empty() && !matches!(body.stmts.last(), Some(S::ReturnStmt { .. })) {
+                    stmts.push(Statement::Control(Control::Unreachable));
+                }
+            }
+        }
 
         Ok(Function {
             ident: fun.ident.clone(),
@@ -536,11 +875,57 @@ impl<'a, 'arena, E: ErrorHandler> MirProducer<'a, 'arena, E> {
             locals,
             body: block,
             is_pub: fun.is_pub,
+            inline: fun.inline,
             exposed: fun.exposed.clone(),
             fun_id: fun.fun_id,
         })
     }
 
+    /// Generates the function through which `fun_id` is called when used as a value, it follows
+    /// the calling convention of closures and ignores its environment.
+    fn lower_fun_reference(&mut self, fun_id: FunId, t: &HirFunType) -> Result<(), String> {
+        let ident = match self.hir.funs.get(&fun_id) {
+            Some(FunKind::Fun(fun)) => &fun.ident,
+            Some(FunKind::Extern(proto)) => &proto.ident,
+            None => return Err(format!("No function with id '{}'", fun_id)),
+        };
+        let ident = format!("{}_ref", ident);
+        self.use_fun(fun_id);
+        let mut params = Vec::new();
+        let mut param_t = Vec::new();
+        let mut stmts = Vec::new();
+        for param in &t.params {
+            for t in self.try_into_mir_t(param)? {
+                let l_id = self.fresh_local_id();
+                params.push(l_id);
+                param_t.push(t);
+                stmts.push(Statement::Local(Local::Get(l_id)));
+            }
+        }
+        params.push(self.fresh_local_id());
+        param_t.push(Type::I32);
+        stmts.push(Statement::Call(Call::Direct(fun_id)));
+        let ret_t = self.try_into_mir_t(&t.ret)?;
+        let body = Block::Block {
+            id: self.fresh_bb_id(),
+            stmts,
+            t: None,
+        };
+        self.mir.funs.push(Function {
+            ident,
+            params,
+            param_t,
+            ret_t,
+            locals: Vec::new(),
+            body,
+            is_pub: false,
+            inline: Inline::Auto,
+            exposed: None,
+            fun_id: reference_id(fun_id),
+        });
+        Ok(())
+    }
+
     fn lower_local_variable(
         &mut self,
         local: &HirLocalVariable,
@@ -556,6 +941,181 @@ impl<'a, 'arena, E: ErrorHandler> MirProducer<'a, 'arena, E> {
         Ok(locals)
     }
 
+    /// Registers a local variable stored in the frame of the current function.
+    fn lower_local_in_memory(&mut self, id: HirLocalId, t: &HirType) -> Result<(), String> {
+        let layout = self.try_into_mir_layout(t)?;
+        let (alignment, size) = self.get_alignment(t)?;
+        let (frame_l_id, frame_size) = self
+            .frame
+            .as_mut()
+            .ok_or_else(|| String::from("Local variable stored in memory without a frame"))?;
+        let align = alignment.bytes();
+        let offset = frame_size.div_ceil(align) * align;
+        *frame_size = offset + size;
+        let frame_l_id = *frame_l_id;
+        self.register_locals(id, vec![frame_l_id]);
+        self.in_memory.insert(id, (layout, offset));
+        Ok(())
+    }
+
+    /// Returns the local variables of a function bound to structs that can be allocated in its
+    /// frame, along with the size of those structs, header included.
+    fn lower_stack_structs(&mut self, fun: &HirFun) -> Result<HashMap<HirLocalId, u32>, String> {
+        let non_escaping = fun.non_escaping_structs();
+        let mut structs = HashMap::new();
+        for l in &fun.locals {
+            let s_id = match &l.t {
+                HirType::Struct(s_id) if non_escaping.contains(&l.id) => s_id,
+                _ => continue,
+            };
+            // The fields of a struct in the frame are never released, so with reference counting
+            // only structs without references to other structs are allocated there
+            if self.is_rc() {
+                let hir_struct = self
+                    .hir
+                    .structs
+                    .get(s_id)
+                    .ok_or_else(|| format!("No struct with id '{}'", s_id))?;
+                let mut managed = false;
+                for field in hir_struct.fields.values() {
+                    managed |= self.managed_values(&field.t)?.iter().any(Option::is_some);
+                }
+                if managed {
+                    continue;
+                }
+            }
+            let size = self.struct_header() + self.get_struct(s_id)?.size;
+            structs.insert(l.id, size);
+        }
+        Ok(structs)
+    }
+
+    /// Reserves room for a struct in the frame of the current function, bound to the local
+    /// variable `id`.
+    fn lower_struct_in_memory(&mut self, id: HirLocalId, size: u32) -> Result<(), String> {
+        let (_, frame_size) = self
+            .frame
+            .as_mut()
+            .ok_or_else(|| String::from("Struct stored in memory without a frame"))?;
+        // Structs may hold 64 bits values
+        let offset = frame_size.div_ceil(8) * 8;
+        *frame_size = offset + size;
+        self.stack_structs.insert(id, offset);
+        Ok(())
+    }
+
+    /// Releases the frame of the current function, if any, the values on the stack are left
+    /// untouched.
+    fn lower_frame_release(&self, stmts: &mut Vec<Statement>) {
+        if let Some((frame_l_id, size)) = self.frame {
+            stmts.push(Statement::Local(Local::Get(frame_l_id)));
+            stmts.push(Statement::Const(Value::I32(size as i32)));
+            stmts.push(Statement::Binop(Binop::I32Add));
+            stmts.push(Statement::Global(Global::Set(STACK_POINTER)));
+        }
+    }
+
+    /// Returns the place of a local variable.
+    fn lower_variable_place(&mut self, id: HirLocalId, t: &HirType) -> Result<Place, String> {
+        let l_ids = self.get_local_ids(id)?.clone();
+        if let Some((layout, offset)) = self.in_memory.get(&id) {
+            return Ok(Place::Address {
+                address_l_id: l_ids[0],
+                offset: *offset,
+                t: layout.clone(),
+            });
+        }
+        let types = self.try_into_mir_t(t)?;
+        Ok(Place::Local(l_ids, types))
+    }
+
+    /// Pushes the value of a local variable on the stack, without adding references to the
+    /// structs it holds.
+    fn lower_variable_load(
+        &mut self,
+        var: &HirVariable,
+        stmts: &mut Vec<Statement>,
+    ) -> Result<Vec<Type>, String> {
+        if let Some((layout, offset)) = self.in_memory.get(&var.n_id) {
+            let (layout, offset) = (layout.clone(), *offset);
+            let frame_l_id = self.get_local_ids(var.n_id)?[0];
+            return self.lower_load(frame_l_id, offset, &layout, stmts);
+        }
+        for l_id in self.get_local_ids(var.n_id)? {
+            stmts.push(Statement::Local(Local::Get(*l_id)));
+        }
+        self.try_into_mir_t(&var.t)
+    }
+
+    /// Pops the value of a local variable from the stack and stores it in the variable.
+    fn lower_variable_store(
+        &mut self,
+        id: HirLocalId,
+        stmts: &mut Vec<Statement>,
+        locals: &mut Vec<LocalVariable>,
+    ) -> Result<(), String> {
+        let l_ids = self.get_local_ids(id)?.clone();
+        match self.in_memory.get(&id) {
+            Some((layout, offset)) => {
+                let (layout, offset) = (layout.clone(), *offset);
+                self.lower_store(l_ids[0], offset, &layout, stmts, locals)
+            }
+            None => {
+                for l_id in l_ids.iter().rev() {
+                    stmts.push(Statement::Local(Local::Set(*l_id)));
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Pops values from the stack and stores them at the address held by `address_l_id`.
+    fn lower_store(
+        &mut self,
+        address_l_id: LocalId,
+        offset: u32,
+        layout: &ValueLayout,
+        stmts: &mut Vec<Statement>,
+        locals: &mut Vec<LocalVariable>,
+    ) -> Result<(), String> {
+        // Iterate on types in reverse order (stack => last in, first out)
+        for (t, t_layout, t_offset) in layout.iter().rev() {
+            // Create a local to store temporary result
+            let l_id = self.fresh_local_id();
+            locals.push(LocalVariable { t: *t, id: l_id });
+            stmts.push(Statement::Local(Local::Set(l_id)));
+            // Push the address on the stack
+            stmts.push(Statement::Local(Local::Get(address_l_id)));
+            // Push the value on the stack
+            stmts.push(Statement::Local(Local::Get(l_id)));
+            // Store the value
+            let store_instr = get_store_instr(*t, *t_layout, offset + t_offset)?;
+            stmts.push(Statement::Memory(store_instr));
+        }
+        Ok(())
+    }
+
+    /// Pushes the values stored at the address held by `address_l_id` on the stack.
+    fn lower_load(
+        &mut self,
+        address_l_id: LocalId,
+        offset: u32,
+        layout: &ValueLayout,
+        stmts: &mut Vec<Statement>,
+    ) -> Result<Vec<Type>, String> {
+        let mut types = Vec::with_capacity(layout.len());
+        for (t, t_layout, t_offset) in layout {
+            stmts.push(Statement::Local(Local::Get(address_l_id)));
+            stmts.push(Statement::Memory(get_load_instr(
+                *t,
+                *t_layout,
+                offset + t_offset,
+            )?));
+            types.push(*t);
+        }
+        Ok(types)
+    }
+
     /// Lowers a block of statements, local variables may be created for the need of computations
     /// and are returned along the reduced block.
     fn lower_block(&mut self, block: &HirBlock) -> Result<(Block, Vec<LocalVariable>), String> {
@@ -579,22 +1139,45 @@ impl<'a, 'arena, E: ErrorHandler> MirProducer<'a, 'arena, E> {
                     self.lower_assign_stmt(target, expr, stmts, locals)?;
                 }
                 S::LetStmt { var, expr } => {
-                    self.lower_expr(&expr, stmts, locals)?;
-                    for l_id in self.get_local_ids(var.n_id).iter().rev() {
-                        stmts.push(Statement::Local(Local::Set(*l_id)));
+                    match expr {
+                        // Structs that never escape the function are allocated in its frame
+                        Expr::Literal(V::Struct {
+                            struct_id,
+                            fields,
+                            loc,
+                        }) => {
+                            let offset = self.stack_structs.get(&var.n_id).copied();
+                            self.lower_struct_literal(
+                                struct_id, fields, *loc, offset, stmts, locals,
+                            )?;
+                        }
+                        _ => {
+                            self.lower_expr(&expr, stmts, locals)?;
+                        }
                     }
+                    // The variable may hold a value from a previous iteration of a loop
+                    let place = self.lower_variable_place(var.n_id, &var.t)?;
+                    self.lower_place_release(&place, &var.t, stmts)?;
+                    self.lower_variable_store(var.n_id, stmts, locals)?;
                 }
                 S::ExprStmt(expr) => {
                     let values = self.lower_expr(&expr, stmts, locals)?;
                     // drop unused values
-                    for _ in values {
-                        stmts.push(Statement::Parametric(Parametric::Drop));
+                    if self.is_rc() {
+                        let t = self.get_expr_t(expr);
+                        self.lower_release(&t, stmts)?;
+                    } else {
+                        for _ in values {
+                            stmts.push(Statement::Parametric(Parametric::Drop));
+                        }
                     }
                 }
                 S::ReturnStmt { expr, .. } => {
                     if let Some(e) = expr {
                         self.lower_expr(&e, stmts, locals)?;
                     }
+                    self.lower_owned_release(stmts)?;
+                    self.lower_frame_release(stmts);
                     stmts.push(Statement::Control(Control::Return))
                 }
                 S::WhileStmt { expr, block } => {
@@ -649,6 +1232,235 @@ impl<'a, 'arena, E: ErrorHandler> MirProducer<'a, 'arena, E> {
         Ok(())
     }
 
+    /// Lowers a binary operation whose operands are on top of the stack.
+    ///
+    /// If overflow checks are enabled, signed integer additions, subtractions and multiplications
+    /// are followed by a check calling the `overflow` function of `core`, which panics, if the
+    /// result overflowed. If a trap handler is set, integer divisions and remainders call it with
+    /// `TRAP_DIVISION_BY_ZERO` before trapping when the divisor is zero.
+    fn lower_binop(
+        &mut self,
+        binop: Binop,
+        stmts: &mut Vec<Statement>,
+        locals: &mut Vec<LocalVariable>,
+    ) {
+        let (t, zero, xor, and, div, ne, lt) = match binop {
+            Binop::I32Add | Binop::I32Sub | Binop::I32Mul if self.options.overflow_checks => (
+                Type::I32,
+                Value::I32(0),
+                Binop::I32Xor,
+                Binop::I32And,
+                Binop::I32DivS,
+                Relop::I32Ne,
+                Relop::I32Lt,
+            ),
+            Binop::I64Add | Binop::I64Sub | Binop::I64Mul if self.options.overflow_checks => (
+                Type::I64,
+                Value::I64(0),
+                Binop::I64Xor,
+                Binop::I64And,
+                Binop::I64DivS,
+                Relop::I64Ne,
+                Relop::I64Lt,
+            ),
+            Binop::I32Div | Binop::I32Rem | Binop::I64Div | Binop::I64Rem => {
+                if let Some(handler) = self.options.trap_handler {
+                    self.lower_guarded_division(binop, handler, stmts, locals);
+                } else {
+                    stmts.push(Statement::Binop(binop));
+                }
+                return;
+            }
+            _ => {
+                stmts.push(Statement::Binop(binop));
+                return;
+            }
+        };
+        let mut fresh_local = || {
+            let id = self.fresh_local_id();
+            locals.push(LocalVariable { id, t });
+            id
+        };
+        let (x, y, result) = (fresh_local(), fresh_local(), fresh_local());
+        let get = |l_id| Statement::Local(Local::Get(l_id));
+        let is_add = matches!(binop, Binop::I32Add | Binop::I64Add);
+        let is_sub = matches!(binop, Binop::I32Sub | Binop::I64Sub);
+
+        stmts.push(Statement::Local(Local::Set(y)));
+        stmts.push(Statement::Local(Local::Set(x)));
+        stmts.push(get(x));
+        stmts.push(get(y));
+        stmts.push(Statement::Binop(binop));
+        stmts.push(Statement::Local(Local::Set(result)));
+        if is_add || is_sub {
+            // The result overflowed if its sign differs from the sign of both operands (addition)
+            // or from the sign of x while the operands have different signs (subtraction).
+            let (lhs, rhs) = if is_add {
+                ((x, result), (y, result))
+            } else {
+                ((x, y), (x, result))
+            };
+            stmts.extend(vec![
+                get(lhs.0),
+                get(lhs.1),
+                Statement::Binop(xor.clone()),
+                get(rhs.0),
+                get(rhs.1),
+                Statement::Binop(xor),
+                Statement::Binop(and),
+                Statement::Const(zero.clone()),
+                Statement::Relop(lt),
+            ]);
+        } else {
+            // The result overflowed if dividing it by x does not give back y. Note that `MIN / -1`
+            // itself traps.
+            let is_overflow = Block::If {
+                id: self.fresh_bb_id(),
+                then_stmts: vec![
+                    get(result),
+                    get(x),
+                    Statement::Binop(div),
+                    get(y),
+                    Statement::Relop(ne.clone()),
+                ],
+                else_stmts: vec![Statement::Const(Value::I32(0))],
+                t: Some(Type::I32),
+            };
+            stmts.extend(vec![
+                get(x),
+                Statement::Const(zero),
+                Statement::Relop(ne),
+                Statement::Block(Box::new(is_overflow)),
+            ]);
+        }
+        let overflow = match self.known_funs {
+            Some(known_funs) => known_funs.overflow,
+            None => {
+                let message = String::from("Overflow checks without a runtime");
+                self.err.report_internal_no_loc(message);
+                return;
+            }
+        };
+        self.use_fun(overflow);
+        let trap = Block::If {
+            id: self.fresh_bb_id(),
+            then_stmts: vec![
+                Statement::Call(Call::Direct(overflow)),
+                Statement::Control(Control::Unreachable),
+            ],
+            else_stmts: Vec::new(),
+            t: None,
+        };
+        stmts.push(Statement::Block(Box::new(trap)));
+        stmts.push(get(result));
+    }
+
+    /// Lowers an integer division or remainder whose operands are on top of the stack, calling
+    /// the trap handler with `TRAP_DIVISION_BY_ZERO` before trapping if the divisor is zero.
+    fn lower_guarded_division(
+        &mut self,
+        binop: Binop,
+        handler: FunId,
+        stmts: &mut Vec<Statement>,
+        locals: &mut Vec<LocalVariable>,
+    ) {
+        let (t, zero, eq) = match binop {
+            Binop::I64Div | Binop::I64Rem => (Type::I64, Value::I64(0), Relop::I64Eq),
+            _ => (Type::I32, Value::I32(0), Relop::I32Eq),
+        };
+        let x = self.fresh_local_id();
+        let y = self.fresh_local_id();
+        locals.push(LocalVariable { id: x, t });
+        locals.push(LocalVariable { id: y, t });
+        self.use_fun(handler);
+        let trap = Block::If {
+            id: self.fresh_bb_id(),
+            then_stmts: vec![
+                Statement::Const(Value::I32(TRAP_DIVISION_BY_ZERO)),
+                Statement::Call(Call::Direct(handler)),
+                Statement::Control(Control::Unreachable),
+            ],
+            else_stmts: Vec::new(),
+            t: None,
+        };
+        stmts.extend(vec![
+            Statement::Local(Local::Set(y)),
+            Statement::Local(Local::Set(x)),
+            Statement::Local(Local::Get(y)),
+            Statement::Const(zero),
+            Statement::Relop(eq),
+            Statement::Block(Box::new(trap)),
+            Statement::Local(Local::Get(x)),
+            Statement::Local(Local::Get(y)),
+            Statement::Binop(binop),
+        ]);
+    }
+
+    /// Lowers the integer `abs`, `min` and `max` intrinsics whose operands are on top of the stack,
+    /// they have no instruction of their own and select one of their operands instead.
+    fn lower_integer_intrinsic(
+        &mut self,
+        intrinsic: Intrinsic,
+        stmts: &mut Vec<Statement>,
+        locals: &mut Vec<LocalVariable>,
+    ) {
+        let (t, zero, sub, lt, gt) = match intrinsic {
+            Intrinsic::I64Abs | Intrinsic::I64Min | Intrinsic::I64Max => (
+                Type::I64,
+                Value::I64(0),
+                Binop::I64Sub,
+                Relop::I64Lt,
+                Relop::I64Gt,
+            ),
+            _ => (
+                Type::I32,
+                Value::I32(0),
+                Binop::I32Sub,
+                Relop::I32Lt,
+                Relop::I32Gt,
+            ),
+        };
+        let mut fresh_local = || {
+            let id = self.fresh_local_id();
+            locals.push(LocalVariable { id, t });
+            id
+        };
+        let get = |l_id| Statement::Local(Local::Get(l_id));
+        let (cond, then_stmts, else_stmts) = match intrinsic {
+            Intrinsic::I32Abs | Intrinsic::I64Abs => {
+                // The negation wraps, the absolute value of the smallest integer is itself.
+                let x = fresh_local();
+                stmts.push(Statement::Local(Local::Set(x)));
+                (
+                    vec![get(x), Statement::Const(zero.clone()), Statement::Relop(lt)],
+                    vec![Statement::Const(zero), get(x), Statement::Binop(sub)],
+                    vec![get(x)],
+                )
+            }
+            _ => {
+                let (x, y) = (fresh_local(), fresh_local());
+                stmts.push(Statement::Local(Local::Set(y)));
+                stmts.push(Statement::Local(Local::Set(x)));
+                let relop = match intrinsic {
+                    Intrinsic::I32Min | Intrinsic::I64Min => lt,
+                    _ => gt,
+                };
+                (
+                    vec![get(x), get(y), Statement::Relop(relop)],
+                    vec![get(x)],
+                    vec![get(y)],
+                )
+            }
+        };
+        stmts.extend(cond);
+        stmts.push(Statement::Block(Box::new(Block::If {
+            id: self.fresh_bb_id(),
+            then_stmts,
+            else_stmts,
+            t: Some(t),
+        })));
+    }
+
     /// Push new statements that execute the given expression and return the types of values added
     /// on top of the stack.
     fn lower_expr(
@@ -682,6 +1494,14 @@ impl<'a, 'arena, E: ErrorHandler> MirProducer<'a, 'arena, E> {
                 V::DataPointer(data_id, _) => {
                     self.use_data(*data_id);
                     stmts.push(Statement::Const(Value::DataPointer(*data_id)));
+                    // Structs are preceded by their header, if any
+                    let header = self.struct_header();
+                    if header != 0
+                        && matches!(self.hir.data.get(data_id), Some(HirData::Struct { .. }))
+                    {
+                        stmts.push(Statement::Const(Value::I32(header as i32)));
+                        stmts.push(Statement::Binop(Binop::I32Add));
+                    }
                     vec![Type::I32]
                 }
                 V::Tuple { values, .. } => {
@@ -692,62 +1512,47 @@ impl<'a, 'arena, E: ErrorHandler> MirProducer<'a, 'arena, E> {
                     types
                 }
                 V::Struct {
-                    struct_id, fields, ..
-                } => {
-                    let struc = self.get_struct(struct_id)?;
-                    // Allocate memory
-                    stmts.push(Statement::Const(Value::I32(struc.size as i32)));
-                    stmts.push(Statement::Call(Call::Direct(self.known_funs.malloc)));
-                    self.use_fun(self.known_funs.malloc); // Don't forget to register malloc!
-                                                          // Save the pointer in a local variable
-                    let pointer_l_id = self.fresh_local_id();
-                    locals.push(LocalVariable {
-                        t: Type::I32,
-                        id: pointer_l_id,
-                    });
-                    stmts.push(Statement::Local(Local::Set(pointer_l_id)));
-                    // Now fill the fields with their values
-                    for field in fields {
-                        let (layout, offset) = if let Some(f) = struc.fields.get(&field.ident) {
-                            (&f.t, f.offset)
-                        } else {
-                            self.err.report_internal_no_loc(format!(
-                                "Field does not exist in MIR struct: '{}'",
-                                &field.ident
-                            ));
-                            continue;
-                        };
-                        // Put memory location and value on top of stack
-                        stmts.push(Statement::Local(Local::Get(pointer_l_id)));
-                        let values_types = self.lower_expr(&*field.expr, stmts, locals)?;
-                        if values_types.len() != layout.len() {
-                            self.err.report_internal_no_loc(format!(
-                                "Number of value miss match in field: expected {}, got {}",
-                                values_types.len(),
-                                layout.len()
-                            ));
-                            continue;
-                        }
-                        // Store values one by one
-                        for (t, (t_2, t_layout, t_offset)) in values_types.iter().zip(layout) {
-                            assert_eq!(t, t_2);
-                            stmts.push(Statement::Memory(get_store_instr(
-                                *t,
-                                *t_layout,
-                                offset + t_offset,
-                            )?));
-                        }
-                    }
-                    // Put the struct pointer on top of the stack
-                    stmts.push(Statement::Local(Local::Get(pointer_l_id)));
-                    vec![Type::I32]
-                }
+                    struct_id,
+                    fields,
+                    loc,
+                } => self.lower_struct_literal(struct_id, fields, *loc, None, stmts, locals)?,
             },
             Expr::Variable(var) => {
-                for l_id in self.get_local_ids(var.n_id) {
-                    stmts.push(Statement::Local(Local::Get(*l_id)));
+                let types = self.lower_variable_load(var, stmts)?;
+                self.lower_retain(&var.t, stmts, locals)?;
+                types
+            }
+            Expr::Reference { place, .. } => {
+                match self.lower_place_expression(place, stmts, locals)? {
+                    Place::Address {
+                        address_l_id,
+                        offset,
+                        ..
+                    } => {
+                        stmts.push(Statement::Local(Local::Get(address_l_id)));
+                        if offset != 0 {
+                            stmts.push(Statement::Const(Value::I32(offset as i32)));
+                            stmts.push(Statement::Binop(Binop::I32Add));
+                        }
+                        vec![Type::I32]
+                    }
+                    Place::Local(..) => {
+                        return Err(format!("Reference to '{}' which is not in memory", place))
+                    }
                 }
-                self.try_into_mir_t(&var.t)?
+            }
+            Expr::Dereference { expr, t, .. } => {
+                self.lower_expr(expr, stmts, locals)?;
+                let layout = self.try_into_mir_layout(t)?;
+                let address_l_id = self.fresh_local_id();
+                locals.push(LocalVariable {
+                    id: address_l_id,
+                    t: Type::I32,
+                });
+                stmts.push(Statement::Local(Local::Set(address_l_id)));
+                let types = self.lower_load(address_l_id, 0, &layout, stmts)?;
+                self.lower_retain(t, stmts, locals)?;
+                types
             }
             Expr::Binary {
                 expr_left,
@@ -761,7 +1566,7 @@ impl<'a, 'arena, E: ErrorHandler> MirProducer<'a, 'arena, E> {
                         let t = binop.get_t();
                         self.lower_expr(expr_left, stmts, locals)?;
                         self.lower_expr(expr_right, stmts, locals)?;
-                        stmts.push(Statement::Binop(binop));
+                        self.lower_binop(binop, stmts, locals);
                         vec![t]
                     }
                     FromBinop::Relop(relop) => {
@@ -810,13 +1615,13 @@ impl<'a, 'arena, E: ErrorHandler> MirProducer<'a, 'arena, E> {
                     HirNumericType::I32 => {
                         stmts.push(Statement::Const(Value::I32(0)));
                         self.lower_expr(expr, stmts, locals)?;
-                        stmts.push(Statement::Binop(Binop::I32Sub));
+                        self.lower_binop(Binop::I32Sub, stmts, locals);
                         vec![Type::I32]
                     }
                     HirNumericType::I64 => {
                         stmts.push(Statement::Const(Value::I64(0)));
                         self.lower_expr(expr, stmts, locals)?;
-                        stmts.push(Statement::Binop(Binop::I64Sub));
+                        self.lower_binop(Binop::I64Sub, stmts, locals);
                         vec![Type::I64]
                     }
                     HirNumericType::F32 => {
@@ -826,7 +1631,7 @@ impl<'a, 'arena, E: ErrorHandler> MirProducer<'a, 'arena, E> {
                     }
                     HirNumericType::F64 => {
                         self.lower_expr(expr, stmts, locals)?;
-                        stmts.push(Statement::Unop(Unop::F32Neg));
+                        stmts.push(Statement::Unop(Unop::F64Neg));
                         vec![Type::F64]
                     }
                 },
@@ -840,27 +1645,149 @@ impl<'a, 'arena, E: ErrorHandler> MirProducer<'a, 'arena, E> {
             Expr::CallDirect {
                 fun_id, args, t, ..
             } => {
-                self.use_fun(*fun_id);
+                // Panics are redirected to the panic handler, if any, and never return. The default
+                // handler relies on WASI, without it panics trap right away.
+                let is_panic = self
+                    .known_funs
+                    .map_or(false, |known_funs| *fun_id == known_funs.panic);
+                let fun_id = match self.options.panic_handler {
+                    Some(handler) if is_panic => handler,
+                    None if is_panic && !self.options.target.has_wasi() => {
+                        for arg in args {
+                            for _ in self.lower_expr(arg, stmts, locals)? {
+                                stmts.push(Statement::Parametric(Parametric::Drop));
+                            }
+                        }
+                        stmts.push(Statement::Control(Control::Unreachable));
+                        return self.try_into_mir_t(&t.ret);
+                    }
+                    _ => *fun_id,
+                };
+                self.use_fun(fun_id);
                 for arg in args {
                     self.lower_expr(arg, stmts, locals)?;
                 }
-                stmts.push(Statement::Call(Call::Direct(*fun_id)));
+                stmts.push(Statement::Call(Call::Direct(fun_id)));
+                if is_panic {
+                    stmts.push(Statement::Control(Control::Unreachable));
+                }
                 self.try_into_mir_t(&t.ret)?
             }
-            Expr::CallIndirect { loc, .. } => {
-                self.err
-                    .report(*loc, String::from("Indirect call are not yet supported"));
-                todo!()
+            Expr::Intrinsic {
+                intrinsic, args, ..
+            } => {
+                // The lane index of the lane intrinsics is an immediate, it is not evaluated
+                let lane = match intrinsic {
+                    Intrinsic::ExtractLane(_) | Intrinsic::ReplaceLane(_) => match &args[1] {
+                        Expr::Literal(V::I32(lane, _)) => Some(*lane as u8),
+                        _ => return Err(String::from("Expected a literal lane index")),
+                    },
+                    _ => None,
+                };
+                for (idx, arg) in args.iter().enumerate() {
+                    if lane.is_none() || idx != 1 {
+                        self.lower_expr(arg, stmts, locals)?;
+                    }
+                }
+                match (intrinsic, lane) {
+                    (Intrinsic::ExtractLane(shape), Some(lane)) => {
+                        stmts.push(Statement::Simd(Simd::ExtractLane(*shape, lane)))
+                    }
+                    (Intrinsic::ReplaceLane(shape), Some(lane)) => {
+                        stmts.push(Statement::Simd(Simd::ReplaceLane(*shape, lane)))
+                    }
+                    _ => match get_intrinsic(*intrinsic) {
+                        Some(stmt) => stmts.push(stmt),
+                        None => self.lower_integer_intrinsic(*intrinsic, stmts, locals),
+                    },
+                }
+                self.try_into_mir_t(&HirType::Scalar(intrinsic.get_t()))?
+            }
+            Expr::CallIndirect { fun, args, t, .. } => {
+                // The closure is saved while the arguments are evaluated, it is then passed as the
+                // environment of the function stored at its first field.
+                self.lower_expr(fun, stmts, locals)?;
+                let closure_l_id = self.fresh_local_id();
+                locals.push(LocalVariable {
+                    id: closure_l_id,
+                    t: Type::I32,
+                });
+                stmts.push(Statement::Local(Local::Set(closure_l_id)));
+                for arg in args {
+                    self.lower_expr(arg, stmts, locals)?;
+                }
+                stmts.push(Statement::Local(Local::Get(closure_l_id)));
+                stmts.push(Statement::Local(Local::Get(closure_l_id)));
+                stmts.push(Statement::Memory(Memory::I32Load {
+                    align: 2,
+                    offset: 0,
+                }));
+                let mut params = Vec::with_capacity(t.params.len() + 1);
+                for param in &t.params {
+                    params.extend(self.try_into_mir_t(param)?);
+                }
+                params.push(Type::I32);
+                let ret_t = self.try_into_mir_t(&t.ret)?;
+                stmts.push(Statement::Call(Call::Indirect(Signature {
+                    params,
+                    ret_t: ret_t.clone(),
+                })));
+                ret_t
+            }
+            Expr::Function { fun_id, t, loc } => {
+                if !self.check_heap_allocation("Function values", *loc) {
+                    return Ok(vec![Type::I32]);
+                }
+                let ref_id = reference_id(*fun_id);
+                if !self.mir.table.contains(&ref_id) {
+                    self.lower_fun_reference(*fun_id, t)?;
+                    self.mir.table.push(ref_id);
+                }
+                self.lower_closure(ref_id, &[], stmts, locals)?;
+                vec![Type::I32]
             }
-            Expr::Access {
-                expr, kind, t: _t, ..
-            } => match kind {
+            Expr::Closure {
+                fun_id,
+                captures,
+                loc,
+                ..
+            } => {
+                if !self.check_heap_allocation("Closures", *loc) {
+                    return Ok(vec![Type::I32]);
+                }
+                self.use_fun(*fun_id);
+                if !self.mir.table.contains(fun_id) {
+                    self.mir.table.push(*fun_id);
+                }
+                self.lower_closure(*fun_id, captures, stmts, locals)?;
+                vec![Type::I32]
+            }
+            Expr::Access { expr, kind, t, .. } => match kind {
                 AccessKind::Struct { field, s_id } => {
                     let struc = self.get_struct(s_id)?;
-                    let field = struc.fields.get(field).unwrap();
+                    let field = struc
+                        .fields
+                        .get(field)
+                        .ok_or_else(|| format!("Field '{}' does not exist in MIR struct", field))?;
                     let mut types = Vec::with_capacity(field.t.len());
-                    self.lower_expr(expr, stmts, locals)?;
+                    let owned = self.lower_operand(expr, stmts, locals)?;
+                    // Each load consumes the pointer, save it if multiple values are loaded or if
+                    // it must be released afterward
+                    let pointer_l_id = if field.t.len() > 1 || owned {
+                        let pointer_l_id = self.fresh_local_id();
+                        locals.push(LocalVariable {
+                            id: pointer_l_id,
+                            t: Type::I32,
+                        });
+                        stmts.push(Statement::Local(Local::Set(pointer_l_id)));
+                        Some(pointer_l_id)
+                    } else {
+                        None
+                    };
                     for (t, layout, offset) in &field.t {
+                        if let Some(pointer_l_id) = pointer_l_id {
+                            stmts.push(Statement::Local(Local::Get(pointer_l_id)));
+                        }
                         stmts.push(Statement::Memory(get_load_instr(
                             *t,
                             *layout,
@@ -868,26 +1795,36 @@ impl<'a, 'arena, E: ErrorHandler> MirProducer<'a, 'arena, E> {
                         )?));
                         types.push(*t);
                     }
+                    // The field is copied out of the struct
+                    self.lower_retain(t, stmts, locals)?;
+                    if let (true, Some(pointer_l_id)) = (owned, pointer_l_id) {
+                        stmts.push(Statement::Local(Local::Get(pointer_l_id)));
+                        self.lower_release_value(Some(*s_id), stmts)?;
+                    }
                     types
                 }
                 AccessKind::Tuple { tup_id, index } => {
                     // For now let's evaluate the whole expression, this can be optimized when
                     // dealing with a place expression (i.e. fetching only the required part)
-                    self.lower_expr(expr, stmts, locals)?;
+                    let owned = self.lower_operand(expr, stmts, locals)?;
                     let tup = self.get_tuple(tup_id)?;
                     let field = &tup.fields[*index as usize];
+                    // The values of the other fields are released if the tuple is owned
+                    let managed = if owned {
+                        self.managed_values(&HirType::Tuple(*tup_id))?
+                    } else {
+                        vec![None; tup.nb_locals]
+                    };
                     let mut types = Vec::with_capacity(field.t.len());
                     let mut tmp_var = Vec::with_capacity(field.t.len());
                     let nb_locals = field.nb_locals;
                     let nb_before = field.local_offset;
-                    let nb_after = tup.nb_locals - nb_before - nb_locals;
                     // Drop values after the values of interests
-                    for _ in 0..nb_after {
-                        stmts.push(Statement::Parametric(Parametric::Drop));
+                    for managed in managed[(nb_before + nb_locals)..].iter().rev() {
+                        self.lower_release_value(*managed, stmts)?;
                     }
                     // Store values in temporary variables (could be optimized if nb_before == 0)
                     for (t, _, _) in field.t.iter().rev() {
-                        types.push(*t);
                         let tmp_var_id = self.fresh_local_id();
                         locals.push(LocalVariable {
                             id: tmp_var_id,
@@ -897,13 +1834,19 @@ impl<'a, 'arena, E: ErrorHandler> MirProducer<'a, 'arena, E> {
                         tmp_var.push(tmp_var_id);
                     }
                     // Drop values before those of interest
-                    for _ in 0..nb_before {
-                        stmts.push(Statement::Parametric(Parametric::Drop));
+                    for managed in managed[..nb_before].iter().rev() {
+                        self.lower_release_value(*managed, stmts)?;
                     }
                     // Restore values
                     for tmp_var_id in tmp_var.iter().rev() {
                         stmts.push(Statement::Local(Local::Get(*tmp_var_id)));
                     }
+                    for (t, _, _) in &field.t {
+                        types.push(*t);
+                    }
+                    if !owned {
+                        self.lower_retain(t, stmts, locals)?;
+                    }
                     types
                 }
             },
@@ -922,8 +1865,14 @@ impl<'a, 'arena, E: ErrorHandler> MirProducer<'a, 'arena, E> {
     ) -> Result<(), String> {
         // Push values on the stack
         self.lower_expr(&expr, stmts, locals)?;
+        let t = match place {
+            PlaceExpr::Variable(var) => &var.t,
+            PlaceExpr::Access { t, .. } | PlaceExpr::Dereference { t, .. } => t,
+        };
         // Compute memory location (no effect on the stack)
-        let place = self.lower_place_expression(place)?;
+        let place = self.lower_place_expression(place, stmts, locals)?;
+        // The references held by the overwritten value are released
+        self.lower_place_release(&place, t, stmts)?;
         match place {
             Place::Local(locals_ids, _) => {
                 for l_id in locals_ids.iter().rev() {
@@ -934,66 +1883,69 @@ impl<'a, 'arena, E: ErrorHandler> MirProducer<'a, 'arena, E> {
                 address_l_id,
                 offset,
                 t,
-            } => {
-                // Release mut ref
-                let t = t.clone();
-                // Iterate on types in reverse order (stack => last in, first out)
-                for (t, t_layout, t_offset) in t.iter().rev() {
-                    // Create a local to store temporary result
-                    let l_id = self.fresh_local_id();
-                    locals.push(LocalVariable { t: *t, id: l_id });
-                    stmts.push(Statement::Local(Local::Set(l_id)));
-                    // Push the address on the stack
-                    stmts.push(Statement::Local(Local::Get(address_l_id)));
-                    // Push the value on the stack
-                    stmts.push(Statement::Local(Local::Get(l_id)));
-                    // Store the value
-                    let store_instr = get_store_instr(*t, *t_layout, offset + t_offset)?;
-                    stmts.push(Statement::Memory(store_instr));
-                }
-            }
+            } => self.lower_store(address_l_id, offset, &t, stmts, locals)?,
         }
         Ok(())
     }
 
-    fn lower_place_expression(&mut self, place: &PlaceExpr) -> Result<Place, String> {
+    /// Lowers a place expression, the statements computing the address of the place (if any) are
+    /// pushed to `stmts` and have no effect on the stack.
+    fn lower_place_expression(
+        &mut self,
+        place: &PlaceExpr,
+        stmts: &mut Vec<Statement>,
+        locals: &mut Vec<LocalVariable>,
+    ) -> Result<Place, String> {
         match place {
-            PlaceExpr::Variable(var) => {
-                let types = self.try_into_mir_t(&var.t)?;
-                let locals = self.get_local_ids(var.n_id);
-                Ok(Place::Local(locals, types))
-            }
+            PlaceExpr::Variable(var) => self.lower_variable_place(var.n_id, &var.t),
             PlaceExpr::Access { expr, kind, .. } => match kind {
                 AccessKind::Struct { field, s_id } => {
-                    let place = self.lower_place_expression(expr)?;
-                    let (address_l_id, total_offset) = match place {
+                    // Structs are represented by a pointer to their fields
+                    let place = self.lower_place_expression(expr, stmts, locals)?;
+                    let address_l_id = match place {
                         Place::Address {
                             address_l_id,
                             offset,
                             ..
-                        } => (address_l_id, offset),
+                        } => {
+                            let pointer_l_id = self.fresh_local_id();
+                            locals.push(LocalVariable {
+                                id: pointer_l_id,
+                                t: Type::I32,
+                            });
+                            stmts.push(Statement::Local(Local::Get(address_l_id)));
+                            stmts.push(Statement::Memory(get_load_instr(
+                                Type::I32,
+                                MemoryLayout::I32,
+                                offset,
+                            )?));
+                            stmts.push(Statement::Local(Local::Set(pointer_l_id)));
+                            pointer_l_id
+                        }
                         Place::Local(locals_ids, _) => {
                             if locals_ids.len() != 1 {
                                 return Err(String::from(
                                     "Struct must be represented by their pointers",
                                 ));
                             }
-                            (locals_ids[0], 0)
+                            locals_ids[0]
                         }
                     };
                     let struc = self.get_struct(s_id)?;
-                    let field = struc.fields.get(field).unwrap();
-                    let offset = field.offset;
+                    let field = struc
+                        .fields
+                        .get(field)
+                        .ok_or_else(|| format!("Field '{}' does not exist in MIR struct", field))?;
                     Ok(Place::Address {
                         address_l_id,
-                        offset: offset + total_offset,
-                        t: &field.t,
+                        offset: field.offset,
+                        t: field.t.clone(),
                     })
                 }
                 AccessKind::Tuple { index, tup_id } => {
                     let tup = self.get_tuple(tup_id)?;
                     let field = &tup.fields[*index as usize];
-                    let place = self.lower_place_expression(expr)?;
+                    let place = self.lower_place_expression(expr, stmts, locals)?;
                     match place {
                         Place::Address {
                             address_l_id,
@@ -1002,7 +1954,7 @@ impl<'a, 'arena, E: ErrorHandler> MirProducer<'a, 'arena, E> {
                         } => Ok(Place::Address {
                             address_l_id,
                             offset: offset + field.offset,
-                            t: &field.t,
+                            t: field.t.clone(),
                         }),
                         Place::Local(locals, _) => {
                             let locals_start = field.local_offset;
@@ -1011,11 +1963,28 @@ impl<'a, 'arena, E: ErrorHandler> MirProducer<'a, 'arena, E> {
                             for (t, _, _) in &field.t {
                                 types.push(*t);
                             }
-                            Ok(Place::Local(&locals[locals_start..locals_end], types))
+                            Ok(Place::Local(
+                                locals[locals_start..locals_end].to_vec(),
+                                types,
+                            ))
                         }
                     }
                 }
             },
+            PlaceExpr::Dereference { expr, t, .. } => {
+                self.lower_expr(expr, stmts, locals)?;
+                let address_l_id = self.fresh_local_id();
+                locals.push(LocalVariable {
+                    id: address_l_id,
+                    t: Type::I32,
+                });
+                stmts.push(Statement::Local(Local::Set(address_l_id)));
+                Ok(Place::Address {
+                    address_l_id,
+                    offset: 0,
+                    t: self.try_into_mir_layout(t)?,
+                })
+            }
         }
     }
 
@@ -1027,7 +1996,7 @@ impl<'a, 'arena, E: ErrorHandler> MirProducer<'a, 'arena, E> {
         for stmt in stmts {
             match self.lower_asm_statement(stmt) {
                 Ok(stmt) => reduced_stmts.push(stmt),
-                Err(err) => self.err.report_no_loc(err), //TODO: track location
+                Err(err) => self.err.report(stmt.get_loc(), err),
             }
         }
         Ok(reduced_stmts)
@@ -1038,12 +2007,12 @@ impl<'a, 'arena, E: ErrorHandler> MirProducer<'a, 'arena, E> {
             AsmStatement::Const { ref val, .. } => Ok(Statement::Const(val.clone())),
             AsmStatement::Local { local, .. } => match local {
                 AsmLocal::Get { var, .. } => {
-                    let locals = self.get_local_ids(var.n_id);
+                    let locals = self.get_local_ids(var.n_id)?;
                     assert!(locals.len() == 1);
                     Ok(Statement::Local(Local::Get(locals[0])))
                 }
                 AsmLocal::Set { var } => {
-                    let locals = self.get_local_ids(var.n_id);
+                    let locals = self.get_local_ids(var.n_id)?;
                     assert!(locals.len() == 1);
                     Ok(Statement::Local(Local::Set(locals[0])))
                 }
@@ -1104,7 +2073,20 @@ impl<'a, 'arena, E: ErrorHandler> MirProducer<'a, 'arena, E> {
                         offset: *offset,
                     }))
                 }
+                AsmMemory::V128Load { align, offset } => Ok(Statement::Memory(Memory::V128Load {
+                    align: *align,
+                    offset: *offset,
+                })),
+                AsmMemory::V128Store { align, offset } => {
+                    Ok(Statement::Memory(Memory::V128Store {
+                        align: *align,
+                        offset: *offset,
+                    }))
+                }
             },
+            AsmStatement::Simd { simd, .. } => Ok(Statement::Simd(simd.clone())),
+            AsmStatement::Atomic { atomic, .. } => Ok(Statement::Atomic(atomic.clone())),
+            AsmStatement::Raw { bytes, .. } => Ok(Statement::Raw(bytes.clone())),
         }
     }
 
@@ -1141,7 +2123,9 @@ impl<'a, 'arena, E: ErrorHandler> MirProducer<'a, 'arena, E> {
                 Some(t) => vec![t],
                 None => vec![],
             }),
-            HirType::Fun(_) => Err(String::from("Function as value are not yet supported.")),
+            // Closures are represented by a pointer to their index in the table followed by their
+            // environment
+            HirType::Fun(_) => Ok(vec![Type::I32]),
             HirType::Tuple(tup_id) => {
                 let tup = self.get_tuple(tup_id)?;
                 let mut types = Vec::with_capacity(tup.fields.len());
@@ -1154,6 +2138,8 @@ impl<'a, 'arena, E: ErrorHandler> MirProducer<'a, 'arena, E> {
             }
             // For now structs are always boxed and represented by a pointer to their location
             HirType::Struct(_) => Ok(vec![Type::I32]),
+            // References are represented by an address
+            HirType::Ref(_) => Ok(vec![Type::I32]),
         }
     }
 
@@ -1167,7 +2153,7 @@ impl<'a, 'arena, E: ErrorHandler> MirProducer<'a, 'arena, E> {
                 Some(t) => vec![(t, t.layout(), 0)],
                 None => vec![],
             }),
-            HirType::Fun(_) => Err(String::from("Functions as value are not yet supported.")),
+            HirType::Fun(_) => Ok(vec![(Type::I32, MemoryLayout::I32, 0)]),
             HirType::Tuple(tup_id) => {
                 let tup = self.get_tuple(tup_id)?;
                 let mut types = Vec::with_capacity(tup.fields.len());
@@ -1180,7 +2166,49 @@ impl<'a, 'arena, E: ErrorHandler> MirProducer<'a, 'arena, E> {
                 Ok(types)
             }
             // For now structs are always boxed and represented by a pointer to their location
-            HirType::Struct(_) => Ok(vec![(Type::I32, MemoryLayout::I32, 0)]),
+            HirType::Struct(_) | HirType::Ref(_) => Ok(vec![(Type::I32, MemoryLayout::I32, 0)]),
+        }
+    }
+
+    /// Converts a type to its representation at the boundary of a component.
+    fn lower_canon_type(&mut self, t: &HirType) -> Result<CanonType, String> {
+        match t {
+            HirType::Scalar(HirScalarType::Bool) => {
+                Ok(CanonType::Scalar(Type::I32, MemoryLayout::U8))
+            }
+            HirType::Scalar(x) => match get_mir_t(x) {
+                Some(t) => Ok(CanonType::Scalar(t, t.layout())),
+                None => Err(String::from("Null values can not be passed to components")),
+            },
+            HirType::Struct(s_id) if self.hir.is_str(*s_id) => Ok(CanonType::Str),
+            HirType::Struct(s_id) => {
+                let s = self.get_struct(s_id)?;
+                let hir_struct = self
+                    .hir
+                    .structs
+                    .get(s_id)
+                    .ok_or_else(|| format!("No struct with id '{}'", s_id))?;
+                let mut hir_fields = hir_struct.fields.iter().collect::<Vec<_>>();
+                hir_fields.sort_by_key(|(field_name, _)| *field_name);
+                let mut fields = Vec::with_capacity(hir_fields.len());
+                for (field_name, field) in hir_fields {
+                    let mir_field = s.fields.get(field_name).ok_or_else(|| {
+                        format!("Field does not exist in MIR struct: '{}'", field_name)
+                    })?;
+                    let (t, layout, offset) = match mir_field.t.as_slice() {
+                        [(t, layout, offset)] => (*t, *layout, mir_field.offset + offset),
+                        _ => return Err(format!("Unexpected layout for field '{}'", field_name)),
+                    };
+                    fields.push((offset, t, layout, self.lower_canon_type(&field.t)?));
+                }
+                Ok(CanonType::Record {
+                    size: s.size,
+                    fields,
+                })
+            }
+            HirType::Tuple(_) | HirType::Fun(_) | HirType::Ref(_) => Err(String::from(
+                "Tuples, functions and references can not be passed to components",
+            )),
         }
     }
 
@@ -1193,6 +2221,7 @@ impl<'a, 'arena, E: ErrorHandler> MirProducer<'a, 'arena, E> {
                 HirScalarType::F32 => (Alignment::A4, 4),
                 HirScalarType::F64 => (Alignment::A8, 8),
                 HirScalarType::Bool => (Alignment::A1, 1),
+                HirScalarType::V128 => (Alignment::A8, 16),
                 HirScalarType::Null => (Alignment::A1, 0),
             }),
             HirType::Struct(_) => Ok((Alignment::A4, 4)), // Represented as a i32 pointer for now
@@ -1200,8 +2229,546 @@ impl<'a, 'arena, E: ErrorHandler> MirProducer<'a, 'arena, E> {
                 let tup = self.get_tuple(tup_id)?;
                 Ok((Alignment::A8, tup.size)) // We can optimize alignment in some cases
             }
-            _ => todo!("Only scalar and struct are supported inside structures at the time"), //
+            HirType::Fun(_) => Ok((Alignment::A4, 4)), // Represented as a i32 pointer
+            HirType::Ref(_) => Ok((Alignment::A4, 4)), // Represented as a i32 address
+        }
+    }
+
+    /// Lowers a struct literal and pushes a pointer to it on the stack, the struct is allocated
+    /// on the heap or at `frame_offset` in the frame of the current function.
+    fn lower_struct_literal(
+        &mut self,
+        struct_id: &StructId,
+        fields: &[HirFieldValue],
+        loc: Location,
+        frame_offset: Option<Offset>,
+        stmts: &mut Vec<Statement>,
+        locals: &mut Vec<LocalVariable>,
+    ) -> Result<Vec<Type>, String> {
+        let struc = self.get_struct(struct_id)?;
+        // Allocate memory
+        match frame_offset {
+            Some(offset) => {
+                let header = self.struct_header();
+                let frame_l_id = self
+                    .frame
+                    .map(|(frame_l_id, _)| frame_l_id)
+                    .ok_or_else(|| String::from("Struct stored in memory without a frame"))?;
+                if header != 0 {
+                    // A count of zero marks the struct as static, its references are not counted
+                    stmts.push(Statement::Local(Local::Get(frame_l_id)));
+                    stmts.push(Statement::Const(Value::I32(0)));
+                    stmts.push(Statement::Memory(Memory::I32Store { align: 2, offset }));
+                }
+                stmts.push(Statement::Local(Local::Get(frame_l_id)));
+                if offset + header != 0 {
+                    stmts.push(Statement::Const(Value::I32((offset + header) as i32)));
+                    stmts.push(Statement::Binop(Binop::I32Add));
+                }
+            }
+            None => {
+                if !self.check_heap_allocation("Structs escaping their function", loc) {
+                    return Ok(vec![Type::I32]);
+                }
+                let alloc = self.struct_alloc()?;
+                stmts.push(Statement::Const(Value::I32(struc.size as i32)));
+                stmts.push(Statement::Call(Call::Direct(alloc)));
+                self.use_fun(alloc); // Don't forget to register malloc!
+            }
+        }
+        // Save the pointer in a local variable
+        let pointer_l_id = self.fresh_local_id();
+        locals.push(LocalVariable {
+            t: Type::I32,
+            id: pointer_l_id,
+        });
+        stmts.push(Statement::Local(Local::Set(pointer_l_id)));
+        // Now fill the fields with their values
+        for field in fields {
+            let (layout, offset) = if let Some(f) = struc.fields.get(&field.ident) {
+                (&f.t, f.offset)
+            } else {
+                self.err.report_internal_no_loc(format!(
+                    "Field does not exist in MIR struct: '{}'",
+                    &field.ident
+                ));
+                continue;
+            };
+            if layout.len() == 1 {
+                // Put memory location and value on top of stack
+                stmts.push(Statement::Local(Local::Get(pointer_l_id)));
+            }
+            let values_types = self.lower_expr(&*field.expr, stmts, locals)?;
+            if values_types.len() != layout.len() {
+                self.err.report_internal_no_loc(format!(
+                    "Number of value miss match in field: expected {}, got {}",
+                    values_types.len(),
+                    layout.len()
+                ));
+                continue;
+            }
+            if layout.len() == 1 {
+                let (t, t_layout, t_offset) = layout[0];
+                stmts.push(Statement::Memory(get_store_instr(
+                    t,
+                    t_layout,
+                    offset + t_offset,
+                )?));
+                continue;
+            }
+            // Multiple values (e.g. a tuple): save them in temporary variables, then
+            // store them one by one
+            let mut tmp_vars = Vec::with_capacity(layout.len());
+            for t in values_types.iter().rev() {
+                let tmp_var_id = self.fresh_local_id();
+                locals.push(LocalVariable {
+                    id: tmp_var_id,
+                    t: *t,
+                });
+                stmts.push(Statement::Local(Local::Set(tmp_var_id)));
+                tmp_vars.push(tmp_var_id);
+            }
+            for (tmp_var_id, (t, t_layout, t_offset)) in tmp_vars.iter().rev().zip(layout) {
+                stmts.push(Statement::Local(Local::Get(pointer_l_id)));
+                stmts.push(Statement::Local(Local::Get(*tmp_var_id)));
+                stmts.push(Statement::Memory(get_store_instr(
+                    *t,
+                    *t_layout,
+                    offset + t_offset,
+                )?));
+            }
+        }
+        // Put the struct pointer on top of the stack
+        stmts.push(Statement::Local(Local::Get(pointer_l_id)));
+        Ok(vec![Type::I32])
+    }
+
+    /// Allocates a closure calling the function at `fun_id` in the table with the captured
+    /// variables as environment, and pushes a pointer to it on the stack.
+    fn lower_closure(
+        &mut self,
+        fun_id: FunId,
+        captures: &[HirVariable],
+        stmts: &mut Vec<Statement>,
+        locals: &mut Vec<LocalVariable>,
+    ) -> Result<(), String> {
+        let (layout, size) = self.lower_env(captures.iter().map(|var| &var.t).collect())?;
+        stmts.push(Statement::Const(Value::I32(size as i32)));
+        let malloc = self.known_funs()?.malloc;
+        stmts.push(Statement::Call(Call::Direct(malloc)));
+        self.use_fun(malloc);
+        let closure_l_id = self.fresh_local_id();
+        locals.push(LocalVariable {
+            id: closure_l_id,
+            t: Type::I32,
+        });
+        stmts.push(Statement::Local(Local::Set(closure_l_id)));
+        stmts.push(Statement::Local(Local::Get(closure_l_id)));
+        stmts.push(Statement::Const(Value::TableIndex(fun_id)));
+        stmts.push(Statement::Memory(Memory::I32Store {
+            align: 2,
+            offset: 0,
+        }));
+        for (var, (offset, t)) in captures.iter().zip(layout) {
+            let l_ids = self.get_local_ids(var.n_id)?.clone();
+            // Variables stored in memory are copied from there
+            let in_memory = self
+                .in_memory
+                .get(&var.n_id)
+                .map(|(layout, var_offset)| (layout.clone(), *var_offset));
+            let managed = self.managed_values(&var.t)?;
+            for (idx, ((t, t_layout, t_offset), managed)) in t.into_iter().zip(managed).enumerate()
+            {
+                stmts.push(Statement::Local(Local::Get(closure_l_id)));
+                match &in_memory {
+                    Some((layout, var_offset)) => {
+                        let (t, t_layout, t_offset) = layout[idx];
+                        stmts.push(Statement::Local(Local::Get(l_ids[0])));
+                        stmts.push(Statement::Memory(get_load_instr(
+                            t,
+                            t_layout,
+                            var_offset + t_offset,
+                        )?));
+                    }
+                    None => stmts.push(Statement::Local(Local::Get(l_ids[idx]))),
+                }
+                stmts.push(Statement::Memory(get_store_instr(
+                    t,
+                    t_layout,
+                    offset + t_offset,
+                )?));
+                // The closure holds its own references, which are never released
+                if managed.is_some() {
+                    stmts.push(Statement::Local(Local::Get(closure_l_id)));
+                    stmts.push(Statement::Memory(get_load_instr(
+                        t,
+                        t_layout,
+                        offset + t_offset,
+                    )?));
+                    self.lower_retain_call(stmts)?;
+                }
+            }
+        }
+        stmts.push(Statement::Local(Local::Get(closure_l_id)));
+        Ok(())
+    }
+
+    /// Decides of the memory layout of a closure capturing values of the given types, returns
+    /// the offset and layout of each value along with the size of the closure. The index of the
+    /// function in the table comes first.
+    fn lower_env(
+        &mut self,
+        types: Vec<&HirType>,
+    ) -> Result<(Vec<(Offset, ValueLayout)>, u32), String> {
+        let mut layout = Vec::with_capacity(types.len());
+        let mut offset = 4;
+        for t in types {
+            let (alignment, size) = self.get_alignment(t)?;
+            offset = align_offset(offset, alignment);
+            layout.push((offset, self.try_into_mir_layout(t)?));
+            offset += size;
+        }
+        Ok((layout, offset))
+    }
+
+    // ——————————————————————————— Reference counting ——————————————————————————— //
+    //
+    // With reference counting, structs are preceded by a count of their references and values
+    // own the references they hold: expressions produce owned values, which are either stored in
+    // a variable or a field, passed to the callee of a call or released. Reading a variable or a
+    // field copies the value and thus adds a reference to the structs it holds, the references
+    // held by a variable are released when it is overwritten and when the function returns.
+    // Structs are freed along with their last reference, after releasing their fields.
+
+    fn is_rc(&self) -> bool {
+        self.options.gc == GcStrategy::ReferenceCounting
+    }
+
+    /// Returns true if heap allocations are possible, otherwise reports an error as there is no
+    /// allocator without a runtime.
+    fn check_heap_allocation(&mut self, what: &str, loc: Location) -> bool {
+        if !self.options.runtime {
+            let message = format!(
+                "{} are allocated on the heap, which requires a runtime",
+                what
+            );
+            self.err.report(loc, message);
+        }
+        self.options.runtime
+    }
+
+    /// Returns the functions of the runtime, which are unknown when compiling without one.
+    fn known_funs(&self) -> Result<&'a KnownFunctions, String> {
+        self.known_funs
+            .ok_or_else(|| String::from("The functions of the runtime are not known"))
+    }
+
+    /// Returns the function used to allocate structs.
+    fn struct_alloc(&self) -> Result<FunId, String> {
+        let known_funs = self.known_funs()?;
+        if self.is_rc() {
+            Ok(known_funs.rc_alloc)
+        } else {
+            Ok(known_funs.malloc)
+        }
+    }
+
+    /// Returns the size of the header preceding structs.
+    fn struct_header(&self) -> u32 {
+        if self.is_rc() {
+            RC_HEADER_SIZE
+        } else {
+            0
+        }
+    }
+
+    /// Returns, for each of the MIR values a value of type `t` is represented by, the ID of the
+    /// struct it points to if its references are counted.
+    fn managed_values(&mut self, t: &HirType) -> Result<Vec<Option<StructId>>, String> {
+        match t {
+            HirType::Struct(s_id) if self.is_rc() => Ok(vec![Some(*s_id)]),
+            HirType::Tuple(tup_id) if self.is_rc() => {
+                let tup = self
+                    .hir
+                    .tuples
+                    .get(tup_id)
+                    .ok_or_else(|| format!("No tuple with id '{}'", tup_id))?;
+                let mut values = Vec::new();
+                for t in &tup.types {
+                    values.extend(self.managed_values(t)?);
+                }
+                Ok(values)
+            }
+            _ => Ok(vec![None; self.try_into_mir_t(t)?.len()]),
+        }
+    }
+
+    /// Returns the type of the value produced by an expression.
+    fn get_expr_t(&self, expr: &Expr) -> HirType {
+        match expr {
+            Expr::Variable(var) => var.t.clone(),
+            Expr::Literal(value) => match value {
+                V::I32(..) => HirType::Scalar(HirScalarType::I32),
+                V::I64(..) => HirType::Scalar(HirScalarType::I64),
+                V::F32(..) => HirType::Scalar(HirScalarType::F32),
+                V::F64(..) => HirType::Scalar(HirScalarType::F64),
+                V::Bool(..) => HirType::Scalar(HirScalarType::Bool),
+                V::Struct { struct_id, .. } => HirType::Struct(*struct_id),
+                V::Tuple { tup_id, .. } => HirType::Tuple(*tup_id),
+                V::DataPointer(data_id, _) => match self.hir.data.get(data_id) {
+                    Some(HirData::Struct { struct_id, .. }) => HirType::Struct(*struct_id),
+                    _ => HirType::Scalar(HirScalarType::I32),
+                },
+            },
+            Expr::Binary { binop, .. } => match binop {
+                HirBinop::Eq(_)
+                | HirBinop::Ne(_)
+                | HirBinop::Lt(_)
+                | HirBinop::Le(_)
+                | HirBinop::Gt(_)
+                | HirBinop::Ge(_) => HirType::Scalar(HirScalarType::Bool),
+                _ => HirType::Scalar(binop.get_t()),
+            },
+            Expr::Unary { unop, .. } => HirType::Scalar(unop.get_t()),
+            Expr::Intrinsic { intrinsic, .. } => HirType::Scalar(intrinsic.get_t()),
+            Expr::CallDirect { t, .. } | Expr::CallIndirect { t, .. } => (*t.ret).clone(),
+            Expr::Access { t, .. } | Expr::Dereference { t, .. } => t.clone(),
+            Expr::Reference { t, .. } => HirType::Ref(Box::new(t.clone())),
+            Expr::Function { t, .. } | Expr::Closure { t, .. } => HirType::Fun(t.clone()),
+            Expr::Nop { .. } => HirType::Scalar(HirScalarType::Null),
+        }
+    }
+
+    /// Lowers an expression whose value is only read, returns true if the references held by the
+    /// value must be released by the caller.
+    ///
+    /// Variables are read in place, that is without adding references.
+    fn lower_operand(
+        &mut self,
+        expr: &Expr,
+        stmts: &mut Vec<Statement>,
+        locals: &mut Vec<LocalVariable>,
+    ) -> Result<bool, String> {
+        match expr {
+            Expr::Variable(var) if self.is_rc() => {
+                self.lower_variable_load(var, stmts)?;
+                Ok(false)
+            }
+            _ => {
+                self.lower_expr(expr, stmts, locals)?;
+                Ok(self.is_rc())
+            }
+        }
+    }
+
+    /// Adds a reference to the structs held by the value of type `t` on top of the stack, the
+    /// stack is left unchanged.
+    fn lower_retain(
+        &mut self,
+        t: &HirType,
+        stmts: &mut Vec<Statement>,
+        locals: &mut Vec<LocalVariable>,
+    ) -> Result<(), String> {
+        let managed = self.managed_values(t)?;
+        if managed.iter().all(Option::is_none) {
+            return Ok(());
+        }
+        let types = self.try_into_mir_t(t)?;
+        let mut tmp_vars = Vec::with_capacity(types.len());
+        for t in types.iter().rev() {
+            let tmp_var_id = self.fresh_local_id();
+            locals.push(LocalVariable {
+                id: tmp_var_id,
+                t: *t,
+            });
+            stmts.push(Statement::Local(Local::Set(tmp_var_id)));
+            tmp_vars.push(tmp_var_id);
+        }
+        for (tmp_var_id, managed) in tmp_vars.iter().rev().zip(managed) {
+            if managed.is_some() {
+                stmts.push(Statement::Local(Local::Get(*tmp_var_id)));
+                self.lower_retain_call(stmts)?;
+            }
+            stmts.push(Statement::Local(Local::Get(*tmp_var_id)));
+        }
+        Ok(())
+    }
+
+    /// Pops the address of a struct from the stack and adds a reference to it.
+    fn lower_retain_call(&mut self, stmts: &mut Vec<Statement>) -> Result<(), String> {
+        let rc_retain = self.known_funs()?.rc_retain;
+        self.use_fun(rc_retain);
+        stmts.push(Statement::Call(Call::Direct(rc_retain)));
+        Ok(())
+    }
+
+    /// Pops a value of type `t` from the stack, releasing the references it holds.
+    fn lower_release(&mut self, t: &HirType, stmts: &mut Vec<Statement>) -> Result<(), String> {
+        for managed in self.managed_values(t)?.into_iter().rev() {
+            self.lower_release_value(managed, stmts)?;
+        }
+        Ok(())
+    }
+
+    /// Pops a single MIR value from the stack, releasing it if it is a reference to a struct.
+    fn lower_release_value(
+        &mut self,
+        managed: Option<StructId>,
+        stmts: &mut Vec<Statement>,
+    ) -> Result<(), String> {
+        match managed {
+            Some(s_id) => {
+                let drop_fun_id = self.lower_drop(s_id)?;
+                stmts.push(Statement::Call(Call::Direct(drop_fun_id)));
+            }
+            None => stmts.push(Statement::Parametric(Parametric::Drop)),
+        }
+        Ok(())
+    }
+
+    /// Releases the references held by the value of type `t` stored at a place, the stack is left
+    /// unchanged.
+    fn lower_place_release(
+        &mut self,
+        place: &Place,
+        t: &HirType,
+        stmts: &mut Vec<Statement>,
+    ) -> Result<(), String> {
+        let managed = self.managed_values(t)?;
+        match place {
+            Place::Local(l_ids, _) => {
+                for (l_id, managed) in l_ids.iter().zip(managed) {
+                    if managed.is_some() {
+                        stmts.push(Statement::Local(Local::Get(*l_id)));
+                        self.lower_release_value(managed, stmts)?;
+                    }
+                }
+            }
+            Place::Address {
+                address_l_id,
+                offset,
+                t: layout,
+            } => {
+                for ((t, t_layout, t_offset), managed) in layout.iter().zip(managed) {
+                    if managed.is_some() {
+                        stmts.push(Statement::Local(Local::Get(*address_l_id)));
+                        let load = get_load_instr(*t, *t_layout, offset + t_offset)?;
+                        stmts.push(Statement::Memory(load));
+                        self.lower_release_value(managed, stmts)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Clears the references held by a variable stored in the frame, whose memory may hold
+    /// values from a previous call.
+    fn lower_variable_clear(
+        &mut self,
+        id: HirLocalId,
+        t: &HirType,
+        stmts: &mut Vec<Statement>,
+    ) -> Result<(), String> {
+        let managed = self.managed_values(t)?;
+        if let Place::Address {
+            address_l_id,
+            offset,
+            t: layout,
+        } = self.lower_variable_place(id, t)?
+        {
+            for ((t, t_layout, t_offset), managed) in layout.into_iter().zip(managed) {
+                if managed.is_some() {
+                    stmts.push(Statement::Local(Local::Get(address_l_id)));
+                    stmts.push(Statement::Const(Value::I32(0)));
+                    let store = get_store_instr(t, t_layout, offset + t_offset)?;
+                    stmts.push(Statement::Memory(store));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Releases the references held by the variables of the current function, before it
+    /// returns.
+    fn lower_owned_release(&mut self, stmts: &mut Vec<Statement>) -> Result<(), String> {
+        for (id, t) in self.owned.clone() {
+            let place = self.lower_variable_place(id, &t)?;
+            self.lower_place_release(&place, &t, stmts)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the ID of the function releasing a reference to a struct of type `s_id`, which
+    /// releases the fields of the struct and frees it if that was its last reference. The
+    /// function is generated on first use.
+    fn lower_drop(&mut self, s_id: StructId) -> Result<FunId, String> {
+        let fun_id = drop_id(s_id);
+        if !self.lowered_funs.insert(fun_id) {
+            return Ok(fun_id);
+        }
+        let hir_struct = self
+            .hir
+            .structs
+            .get(&s_id)
+            .ok_or_else(|| format!("No struct with id '{}'", s_id))?;
+        let struc = self.get_struct(&s_id)?;
+        let pointer_l_id = self.fresh_local_id();
+        // Fields are released in the order of their names, for reproducible builds
+        let mut hir_fields = hir_struct.fields.iter().collect::<Vec<_>>();
+        hir_fields.sort_by_key(|(field_name, _)| *field_name);
+        let mut then_stmts = Vec::new();
+        for (field_name, field) in hir_fields {
+            let mir_field = struc
+                .fields
+                .get(field_name)
+                .ok_or_else(|| format!("Field does not exist in MIR struct: '{}'", field_name))?;
+            let managed = self.managed_values(&field.t)?;
+            for ((t, t_layout, t_offset), managed) in mir_field.t.iter().zip(managed) {
+                if managed.is_some() {
+                    then_stmts.push(Statement::Local(Local::Get(pointer_l_id)));
+                    let load = get_load_instr(*t, *t_layout, mir_field.offset + t_offset)?;
+                    then_stmts.push(Statement::Memory(load));
+                    self.lower_release_value(managed, &mut then_stmts)?;
+                }
+            }
         }
+        // The block returned by malloc starts with the header
+        then_stmts.push(Statement::Local(Local::Get(pointer_l_id)));
+        then_stmts.push(Statement::Const(Value::I32(RC_HEADER_SIZE as i32)));
+        then_stmts.push(Statement::Binop(Binop::I32Sub));
+        let known_funs = self.known_funs()?;
+        then_stmts.push(Statement::Call(Call::Direct(known_funs.free)));
+        self.use_fun(known_funs.free);
+        self.use_fun(known_funs.rc_release);
+        let stmts = vec![
+            Statement::Local(Local::Get(pointer_l_id)),
+            Statement::Call(Call::Direct(known_funs.rc_release)),
+            Statement::Block(Box::new(Block::If {
+                id: self.fresh_bb_id(),
+                then_stmts,
+                else_stmts: Vec::new(),
+                t: None,
+            })),
+        ];
+        let body = Block::Block {
+            id: self.fresh_bb_id(),
+            stmts,
+            t: None,
+        };
+        self.mir.funs.push(Function {
+            ident: format!("drop_{}", hir_struct.ident),
+            params: vec![pointer_l_id],
+            param_t: vec![Type::I32],
+            ret_t: Vec::new(),
+            locals: Vec::new(),
+            body,
+            is_pub: false,
+            inline: Inline::Auto,
+            exposed: None,
+            fun_id,
+        });
+        Ok(fun_id)
     }
 }
 
@@ -1292,6 +2859,109 @@ fn get_binop(binop: &HirBinop) -> FromBinop {
     }
 }
 
+/// Returns the instruction an intrinsic is lowered to, it expects the arguments of the intrinsic
+/// on the stack. Returns `None` for the intrinsics without a matching instruction, see
+/// `lower_integer_intrinsic`.
+fn get_intrinsic(intrinsic: Intrinsic) -> Option<Statement> {
+    let stmt = match intrinsic {
+        Intrinsic::I32Clz => Statement::Unop(Unop::I32Clz),
+        Intrinsic::I32Ctz => Statement::Unop(Unop::I32Ctz),
+        Intrinsic::I32Popcnt => Statement::Unop(Unop::I32Popcnt),
+        Intrinsic::I64Clz => Statement::Unop(Unop::I64Clz),
+        Intrinsic::I64Ctz => Statement::Unop(Unop::I64Ctz),
+        Intrinsic::I64Popcnt => Statement::Unop(Unop::I64Popcnt),
+        Intrinsic::F32Abs => Statement::Unop(Unop::F32Abs),
+        Intrinsic::F32Sqrt => Statement::Unop(Unop::F32Sqrt),
+        Intrinsic::F32Ceil => Statement::Unop(Unop::F32Ceil),
+        Intrinsic::F32Floor => Statement::Unop(Unop::F32Floor),
+        Intrinsic::F32Trunc => Statement::Unop(Unop::F32Trunc),
+        Intrinsic::F32Nearest => Statement::Unop(Unop::F32Nearest),
+        Intrinsic::F32Min => Statement::Binop(Binop::F32Min),
+        Intrinsic::F32Max => Statement::Binop(Binop::F32Max),
+        Intrinsic::F32Copysign => Statement::Binop(Binop::F32Copysign),
+        Intrinsic::F64Abs => Statement::Unop(Unop::F64Abs),
+        Intrinsic::F64Sqrt => Statement::Unop(Unop::F64Sqrt),
+        Intrinsic::F64Ceil => Statement::Unop(Unop::F64Ceil),
+        Intrinsic::F64Floor => Statement::Unop(Unop::F64Floor),
+        Intrinsic::F64Trunc => Statement::Unop(Unop::F64Trunc),
+        Intrinsic::F64Nearest => Statement::Unop(Unop::F64Nearest),
+        Intrinsic::F64Min => Statement::Binop(Binop::F64Min),
+        Intrinsic::F64Max => Statement::Binop(Binop::F64Max),
+        Intrinsic::F64Copysign => Statement::Binop(Binop::F64Copysign),
+        Intrinsic::I32TruncSatF32S => Statement::Unop(Unop::I32TruncSatF32S),
+        Intrinsic::I32TruncSatF32U => Statement::Unop(Unop::I32TruncSatF32U),
+        Intrinsic::I32TruncSatF64S => Statement::Unop(Unop::I32TruncSatF64S),
+        Intrinsic::I32TruncSatF64U => Statement::Unop(Unop::I32TruncSatF64U),
+        Intrinsic::I64TruncSatF32S => Statement::Unop(Unop::I64TruncSatF32S),
+        Intrinsic::I64TruncSatF32U => Statement::Unop(Unop::I64TruncSatF32U),
+        Intrinsic::I64TruncSatF64S => Statement::Unop(Unop::I64TruncSatF64S),
+        Intrinsic::I64TruncSatF64U => Statement::Unop(Unop::I64TruncSatF64U),
+        Intrinsic::I32ReinterpretF32 => Statement::Unop(Unop::I32ReinterpretF32),
+        Intrinsic::I64ReinterpretF64 => Statement::Unop(Unop::I64ReinterpretF64),
+        Intrinsic::F32ReinterpretI32 => Statement::Unop(Unop::F32ReinterpretI32),
+        Intrinsic::F64ReinterpretI64 => Statement::Unop(Unop::F64ReinterpretI64),
+        Intrinsic::MemorySize => Statement::Memory(Memory::Size),
+        Intrinsic::MemoryGrow => Statement::Memory(Memory::Grow),
+        Intrinsic::Unreachable => Statement::Control(Control::Unreachable),
+        Intrinsic::V128Load => Statement::Memory(Memory::V128Load {
+            align: 4,
+            offset: 0,
+        }),
+        Intrinsic::V128Store => Statement::Memory(Memory::V128Store {
+            align: 4,
+            offset: 0,
+        }),
+        Intrinsic::V128Not => Statement::Simd(Simd::Not),
+        Intrinsic::V128And => Statement::Simd(Simd::And),
+        Intrinsic::V128Or => Statement::Simd(Simd::Or),
+        Intrinsic::V128Xor => Statement::Simd(Simd::Xor),
+        Intrinsic::Splat(shape) => Statement::Simd(Simd::Splat(shape)),
+        Intrinsic::SimdAdd(shape) => Statement::Simd(Simd::Add(shape)),
+        Intrinsic::SimdSub(shape) => Statement::Simd(Simd::Sub(shape)),
+        Intrinsic::SimdMul(shape) => Statement::Simd(Simd::Mul(shape)),
+        Intrinsic::SimdDiv(shape) => Statement::Simd(Simd::Div(shape)),
+        Intrinsic::AtomicLoad(t) => Statement::Atomic(Atomic::Load {
+            t: get_integer_t(t),
+            offset: 0,
+        }),
+        Intrinsic::AtomicStore(t) => Statement::Atomic(Atomic::Store {
+            t: get_integer_t(t),
+            offset: 0,
+        }),
+        Intrinsic::AtomicRmw(op, t) => Statement::Atomic(Atomic::Rmw {
+            op,
+            t: get_integer_t(t),
+            offset: 0,
+        }),
+        Intrinsic::AtomicCmpxchg(t) => Statement::Atomic(Atomic::Cmpxchg {
+            t: get_integer_t(t),
+            offset: 0,
+        }),
+        Intrinsic::AtomicNotify => Statement::Atomic(Atomic::Notify { offset: 0 }),
+        Intrinsic::AtomicWait(t) => Statement::Atomic(Atomic::Wait {
+            t: get_integer_t(t),
+            offset: 0,
+        }),
+        Intrinsic::AtomicFence => Statement::Atomic(Atomic::Fence),
+        Intrinsic::I32Abs
+        | Intrinsic::I32Min
+        | Intrinsic::I32Max
+        | Intrinsic::I64Abs
+        | Intrinsic::I64Min
+        | Intrinsic::I64Max
+        | Intrinsic::ExtractLane(_)
+        | Intrinsic::ReplaceLane(_) => return None,
+    };
+    Some(stmt)
+}
+
+fn get_integer_t(t: HirIntergerType) -> Type {
+    match t {
+        HirIntergerType::I32 => Type::I32,
+        HirIntergerType::I64 => Type::I64,
+    }
+}
+
 /// Convert a scalar value into its MIR representation.
 fn get_mir_t(t: &HirScalarType) -> Option<Type> {
     match t {
@@ -1300,6 +2970,7 @@ fn get_mir_t(t: &HirScalarType) -> Option<Type> {
         HirScalarType::F32 => Some(Type::F32),
         HirScalarType::F64 => Some(Type::F64),
         HirScalarType::Bool => Some(Type::I32),
+        HirScalarType::V128 => Some(Type::V128),
         HirScalarType::Null => None,
     }
 }
@@ -1325,6 +2996,10 @@ fn get_load_instr(t: Type, l: MemoryLayout, offset: u32) -> Result<Memory, Strin
             MemoryLayout::F64 => Ok(Memory::F64Load { offset, align: 3 }),
             _ => Err(format!("Unexpected memory layout for f64")),
         },
+        Type::V128 => match l {
+            MemoryLayout::V128 => Ok(Memory::V128Load { offset, align: 4 }),
+            _ => Err(String::from("Unexpected memory layout for v128")),
+        },
     }
 }
 
@@ -1349,6 +3024,10 @@ fn get_store_instr(t: Type, l: MemoryLayout, offset: u32) -> Result<Memory, Stri
             MemoryLayout::F64 => Ok(Memory::F64Store { offset, align: 3 }),
             _ => Err(format!("Unexpected memory layout for f64")),
         },
+        Type::V128 => match l {
+            MemoryLayout::V128 => Ok(Memory::V128Store { offset, align: 4 }),
+            _ => Err(String::from("Unexpected memory layout for v128")),
+        },
     }
 }
 
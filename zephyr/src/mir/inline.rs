@@ -0,0 +1,565 @@
+//! Inlining
+//!
+//! Calls to small functions are replaced by the body of the callee, wrapped in a block whose
+//! result is the result of the callee. The arguments are moved from the stack to fresh locals of
+//! the caller before entering the block, and returns become branches to the end of the block.
+//! Local and block IDs of the callee are renamed so that they do not collide with the ones of the
+//! caller.
+//!
+//! Functions marked `#[inline]` are inlined regardless of their size and functions marked
+//! `#[no_inline]` are never inlined. Recursive functions and functions returning more than one
+//! value are never inlined.
+
+use std::collections::{HashMap, HashSet};
+
+use super::mir::*;
+
+/// Inlines the functions with at most `threshold` statements (`0` inlines only the functions
+/// marked `#[inline]`), then removes the functions which are not called anymore and are neither
+/// exposed, the start function, in the table, nor part of `roots`.
+pub fn inline(program: &mut Program, threshold: usize, roots: &[FunId]) {
+    let calls = program
+        .funs
+        .iter()
+        .map(|fun| (fun.fun_id, called_functions(&fun.body)))
+        .collect::<HashMap<_, _>>();
+    let callees = program
+        .funs
+        .iter()
+        .filter(|fun| match fun.inline {
+            Inline::Always => true,
+            Inline::Never => false,
+            Inline::Auto => threshold > 0 && size(&fun.body) <= threshold,
+        })
+        .filter(|fun| fun.ret_t.len() <= 1)
+        .filter(|fun| !is_recursive(fun.fun_id, &calls))
+        .filter_map(Callee::new)
+        .collect::<HashMap<_, _>>();
+    if callees.is_empty() {
+        return;
+    }
+
+    for fun in &mut program.funs {
+        let mut inliner = Inliner {
+            callees: &callees,
+            locals: &mut fun.locals,
+            next_local: 0,
+            next_block: max_block_id(&fun.body) + 1,
+        };
+        inliner.next_local = fun
+            .params
+            .iter()
+            .chain(inliner.locals.iter().map(|local| &local.id))
+            .max()
+            .map_or(0, |id| id + 1);
+        inliner.block(&mut fun.body);
+    }
+
+    // Remove the functions which are not used anymore
+    let calls = program
+        .funs
+        .iter()
+        .map(|fun| (fun.fun_id, called_functions(&fun.body)))
+        .collect::<HashMap<_, _>>();
+    let mut used = HashSet::new();
+    let mut to_visit = program
+        .funs
+        .iter()
+        .filter(|fun| fun.exposed.is_some() || program.start == Some(fun.fun_id))
+        .map(|fun| fun.fun_id)
+        .chain(program.table.iter().copied())
+        .chain(roots.iter().copied())
+        .collect::<Vec<_>>();
+    while let Some(fun_id) = to_visit.pop() {
+        if used.insert(fun_id) {
+            if let Some(callees) = calls.get(&fun_id) {
+                to_visit.extend(callees.iter().copied());
+            }
+        }
+    }
+    program.funs.retain(|fun| used.contains(&fun.fun_id));
+}
+
+/// A function that can be inlined.
+struct Callee {
+    params: Vec<(LocalId, Type)>,
+    locals: Vec<LocalVariable>,
+    body_id: BasicBlockId,
+    stmts: Vec<Statement>,
+    ret_t: Option<Type>,
+}
+
+impl Callee {
+    fn new(fun: &Function) -> Option<(FunId, Self)> {
+        let (body_id, stmts) = match &fun.body {
+            Block::Block { id, stmts, .. } => (*id, stmts.clone()),
+            _ => return None,
+        };
+        // The locals and labels referred to by raw instructions can not be renamed
+        let mut has_raw = false;
+        fun.body.visit_statements(&mut |stmt| {
+            if let Statement::Raw(_) = stmt {
+                has_raw = true;
+            }
+        });
+        if has_raw {
+            return None;
+        }
+        let callee = Self {
+            params: fun
+                .params
+                .iter()
+                .copied()
+                .zip(fun.param_t.iter().copied())
+                .collect(),
+            locals: fun
+                .locals
+                .iter()
+                .map(|local| LocalVariable {
+                    id: local.id,
+                    t: local.t,
+                })
+                .collect(),
+            body_id,
+            stmts,
+            ret_t: fun.ret_t.first().copied(),
+        };
+        Some((fun.fun_id, callee))
+    }
+}
+
+struct Inliner<'a> {
+    callees: &'a HashMap<FunId, Callee>,
+    /// The locals of the caller.
+    locals: &'a mut Vec<LocalVariable>,
+    next_local: LocalId,
+    next_block: BasicBlockId,
+}
+
+impl<'a> Inliner<'a> {
+    fn block(&mut self, block: &mut Block) {
+        match block {
+            Block::Block { stmts, .. } | Block::Loop { stmts, .. } => self.statements(stmts),
+            Block::If {
+                then_stmts,
+                else_stmts,
+                ..
+            } => {
+                self.statements(then_stmts);
+                self.statements(else_stmts);
+            }
+        }
+    }
+
+    fn statements(&mut self, stmts: &mut Vec<Statement>) {
+        let mut inlined = Vec::with_capacity(stmts.len());
+        for mut stmt in stmts.drain(..) {
+            match &mut stmt {
+                Statement::Block(block) => self.block(block),
+                Statement::Call(Call::Direct(fun_id)) => {
+                    if let Some(callee) = self.callees.get(fun_id) {
+                        let mut block = self.inline(callee, &mut inlined);
+                        // The callee may call other functions that can be inlined
+                        self.block(&mut block);
+                        stmt = Statement::Block(Box::new(block));
+                    }
+                }
+                _ => (),
+            }
+            inlined.push(stmt);
+        }
+        *stmts = inlined;
+    }
+
+    /// Returns a block executing the body of the callee, the statements moving the arguments to
+    /// the locals of the callee are pushed to `stmts` as blocks can not consume values from the
+    /// stack.
+    fn inline(&mut self, callee: &Callee, stmts: &mut Vec<Statement>) -> Block {
+        let mut locals = HashMap::new();
+        let mut params = Vec::with_capacity(callee.params.len());
+        for (param, t) in &callee.params {
+            let id = self.fresh_local(*t);
+            locals.insert(*param, id);
+            params.push(id);
+        }
+        // The arguments are on the stack, the last one on top
+        for param in params.iter().rev() {
+            stmts.push(Statement::Local(Local::Set(*param)));
+        }
+        // Locals start at zero for each call
+        let mut body = Vec::new();
+        for local in &callee.locals {
+            let id = self.fresh_local(local.t);
+            locals.insert(local.id, id);
+            body.push(Statement::Const(zero(local.t)));
+            body.push(Statement::Local(Local::Set(id)));
+        }
+
+        let mut renamer = Renamer {
+            locals,
+            blocks: HashMap::new(),
+            next_block: &mut self.next_block,
+            exit: 0,
+        };
+        let exit = renamer.fresh_block(callee.body_id);
+        renamer.exit = exit;
+        body.extend(
+            callee
+                .stmts
+                .iter()
+                .map(|stmt| renamer.statement(stmt.clone())),
+        );
+
+        Block::Block {
+            id: exit,
+            stmts: body,
+            t: callee.ret_t,
+        }
+    }
+
+    fn fresh_local(&mut self, t: Type) -> LocalId {
+        let id = self.next_local;
+        self.next_local += 1;
+        self.locals.push(LocalVariable { id, t });
+        id
+    }
+}
+
+/// Renames the locals and blocks of an inlined function.
+struct Renamer<'a> {
+    locals: HashMap<LocalId, LocalId>,
+    blocks: HashMap<BasicBlockId, BasicBlockId>,
+    next_block: &'a mut BasicBlockId,
+    /// The block wrapping the inlined function, returns branch to it.
+    exit: BasicBlockId,
+}
+
+impl<'a> Renamer<'a> {
+    fn fresh_block(&mut self, id: BasicBlockId) -> BasicBlockId {
+        let new_id = *self.next_block;
+        *self.next_block += 1;
+        self.blocks.insert(id, new_id);
+        new_id
+    }
+
+    fn statement(&mut self, stmt: Statement) -> Statement {
+        match stmt {
+            Statement::Local(Local::Get(id)) => Statement::Local(Local::Get(self.locals[&id])),
+            Statement::Local(Local::Set(id)) => Statement::Local(Local::Set(self.locals[&id])),
+            Statement::Local(Local::Tee(id)) => Statement::Local(Local::Tee(self.locals[&id])),
+            Statement::Control(Control::Return) => Statement::Control(Control::Br(self.exit)),
+            Statement::Control(Control::Br(id)) => {
+                Statement::Control(Control::Br(self.blocks[&id]))
+            }
+            Statement::Control(Control::BrIf(id)) => {
+                Statement::Control(Control::BrIf(self.blocks[&id]))
+            }
+            Statement::Block(block) => Statement::Block(Box::new(self.block(*block))),
+            stmt => stmt,
+        }
+    }
+
+    fn block(&mut self, block: Block) -> Block {
+        match block {
+            Block::Block { id, stmts, t } => Block::Block {
+                id: self.fresh_block(id),
+                stmts: self.statements(stmts),
+                t,
+            },
+            Block::Loop { id, stmts, t } => Block::Loop {
+                id: self.fresh_block(id),
+                stmts: self.statements(stmts),
+                t,
+            },
+            Block::If {
+                id,
+                then_stmts,
+                else_stmts,
+                t,
+            } => Block::If {
+                id: self.fresh_block(id),
+                then_stmts: self.statements(then_stmts),
+                else_stmts: self.statements(else_stmts),
+                t,
+            },
+        }
+    }
+
+    fn statements(&mut self, stmts: Vec<Statement>) -> Vec<Statement> {
+        stmts.into_iter().map(|stmt| self.statement(stmt)).collect()
+    }
+}
+
+/// Returns the number of statements of a block, including the ones of nested blocks.
+fn size(block: &Block) -> usize {
+    let stmts_size = |stmts: &Vec<Statement>| {
+        stmts
+            .iter()
+            .map(|stmt| match stmt {
+                Statement::Block(block) => 1 + size(block),
+                _ => 1,
+            })
+            .sum::<usize>()
+    };
+    match block {
+        Block::Block { stmts, .. } | Block::Loop { stmts, .. } => stmts_size(stmts),
+        Block::If {
+            then_stmts,
+            else_stmts,
+            ..
+        } => stmts_size(then_stmts) + stmts_size(else_stmts),
+    }
+}
+
+/// Returns the functions called directly from a block.
+fn called_functions(block: &Block) -> HashSet<FunId> {
+    let mut callees = HashSet::new();
+    block.visit_statements(&mut |stmt| {
+        if let Statement::Call(Call::Direct(fun_id)) = stmt {
+            callees.insert(*fun_id);
+        }
+    });
+    callees
+}
+
+fn max_block_id(block: &Block) -> BasicBlockId {
+    let mut max_id = block_id(block);
+    block.visit_statements(&mut |stmt| {
+        if let Statement::Block(block) = stmt {
+            max_id = max_id.max(block_id(block));
+        }
+    });
+    max_id
+}
+
+fn block_id(block: &Block) -> BasicBlockId {
+    match block {
+        Block::Block { id, .. } | Block::Loop { id, .. } | Block::If { id, .. } => *id,
+    }
+}
+
+/// Whether a function can call itself, directly or not.
+fn is_recursive(fun_id: FunId, calls: &HashMap<FunId, HashSet<FunId>>) -> bool {
+    let mut visited = HashSet::new();
+    let mut to_visit = calls
+        .get(&fun_id)
+        .map_or(Vec::new(), |callees| callees.iter().copied().collect());
+    while let Some(callee) = to_visit.pop() {
+        if callee == fun_id {
+            return true;
+        }
+        if visited.insert(callee) {
+            if let Some(callees) = calls.get(&callee) {
+                to_visit.extend(callees.iter().copied());
+            }
+        }
+    }
+    false
+}
+
+fn zero(t: Type) -> Value {
+    match t {
+        Type::I32 => Value::I32(0),
+        Type::I64 => Value::I64(0),
+        Type::F32 => Value::F32(0.0),
+        Type::F64 => Value::F64(0.0),
+        Type::V128 => Value::V128(0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::DummyHandler;
+    use crate::hir::Identifier;
+    use crate::mir::RuntimeValue;
+
+    fn function(fun_id: FunId, params: Vec<LocalId>, stmts: Vec<Statement>) -> Function {
+        Function {
+            ident: String::from("test"),
+            param_t: params.iter().map(|_| Type::I32).collect(),
+            params,
+            ret_t: vec![Type::I32],
+            locals: Vec::new(),
+            body: Block::Block {
+                id: 0,
+                stmts,
+                t: None,
+            },
+            is_pub: false,
+            inline: Inline::Auto,
+            exposed: None,
+            fun_id,
+        }
+    }
+
+    #[test]
+    fn inline_call() {
+        // Returns the first argument if it is greater than the second one, the second minus the
+        // first otherwise.
+        let callee_id = FunId::new(1);
+        let mut callee = function(
+            callee_id,
+            vec![0, 1],
+            vec![
+                Statement::Local(Local::Get(0)),
+                Statement::Local(Local::Get(1)),
+                Statement::Relop(Relop::I32Gt),
+                Statement::Block(Box::new(Block::If {
+                    id: 1,
+                    t: None,
+                    then_stmts: vec![
+                        Statement::Local(Local::Get(0)),
+                        Statement::Control(Control::Return),
+                    ],
+                    else_stmts: Vec::new(),
+                })),
+                Statement::Local(Local::Get(1)),
+                Statement::Local(Local::Get(0)),
+                Statement::Binop(Binop::I32Sub),
+                Statement::Local(Local::Set(2)),
+                Statement::Local(Local::Get(2)),
+            ],
+        );
+        callee.locals.push(LocalVariable {
+            id: 2,
+            t: Type::I32,
+        });
+        let caller_id = FunId::new(0);
+        let mut caller = function(
+            caller_id,
+            vec![0],
+            vec![
+                Statement::Local(Local::Get(0)),
+                Statement::Const(Value::I32(10)),
+                Statement::Call(Call::Direct(callee_id)),
+                Statement::Const(Value::I32(20)),
+                Statement::Local(Local::Get(0)),
+                Statement::Call(Call::Direct(callee_id)),
+                Statement::Binop(Binop::I32Add),
+            ],
+        );
+        caller.exposed = Some(String::from("test"));
+        let mut program = Program {
+            funs: vec![caller, callee],
+            imports: Vec::new(),
+            data: HashMap::new(),
+            start: None,
+            memory: MemoryLimits::default(),
+            exports: ModuleExports::default(),
+            table: Vec::new(),
+            allocator: true,
+        };
+
+        let run = |program: &Program, arg| {
+            let mut err = DummyHandler::new();
+            let args = vec![RuntimeValue::I32(arg)];
+            super::super::interpret(program, caller_id, args, None, &mut err)
+        };
+        let expected = [3, 15, 30]
+            .iter()
+            .map(|arg| run(&program, *arg))
+            .collect::<Vec<_>>();
+        inline(&mut program, 100, &[]);
+        assert_eq!(program.funs.len(), 1);
+        assert!(called_functions(&program.funs[0].body).is_empty());
+        for (arg, expected) in [3, 15, 30].iter().zip(expected) {
+            assert_eq!(run(&program, *arg), expected);
+        }
+    }
+
+    #[test]
+    fn recursive_functions() {
+        let fun_id = FunId::new(0);
+        let mut fun = function(
+            fun_id,
+            vec![0],
+            vec![
+                Statement::Local(Local::Get(0)),
+                Statement::Call(Call::Direct(fun_id)),
+            ],
+        );
+        fun.exposed = Some(String::from("test"));
+        let mut program = Program {
+            funs: vec![fun],
+            imports: Vec::new(),
+            data: HashMap::new(),
+            start: None,
+            memory: MemoryLimits::default(),
+            exports: ModuleExports::default(),
+            table: Vec::new(),
+            allocator: true,
+        };
+        inline(&mut program, 100, &[]);
+        assert_eq!(program.funs.len(), 1);
+        assert!(called_functions(&program.funs[0].body).contains(&fun_id));
+    }
+
+    #[test]
+    fn inline_attributes() {
+        let always_id = FunId::new(1);
+        let mut always = function(always_id, vec![], vec![Statement::Const(Value::I32(1))]);
+        always.inline = Inline::Always;
+        let never_id = FunId::new(2);
+        let mut never = function(never_id, vec![], vec![Statement::Const(Value::I32(2))]);
+        never.inline = Inline::Never;
+        let caller_id = FunId::new(0);
+        let mut caller = function(
+            caller_id,
+            vec![],
+            vec![
+                Statement::Call(Call::Direct(always_id)),
+                Statement::Call(Call::Direct(never_id)),
+                Statement::Binop(Binop::I32Add),
+            ],
+        );
+        caller.exposed = Some(String::from("test"));
+        let mut program = Program {
+            funs: vec![caller, always, never],
+            imports: Vec::new(),
+            data: HashMap::new(),
+            start: None,
+            memory: MemoryLimits::default(),
+            exports: ModuleExports::default(),
+            table: Vec::new(),
+            allocator: true,
+        };
+
+        // Functions marked `#[inline]` are inlined even when inlining is disabled
+        inline(&mut program, 0, &[]);
+        let calls = called_functions(&program.funs[0].body);
+        assert!(!calls.contains(&always_id));
+        assert!(calls.contains(&never_id));
+
+        inline(&mut program, 100, &[]);
+        assert_eq!(program.funs.len(), 2);
+        assert!(called_functions(&program.funs[0].body).contains(&never_id));
+    }
+
+    #[test]
+    fn raw_not_inlined() {
+        let raw_id = FunId::new(1);
+        let mut raw = function(raw_id, vec![], vec![Statement::Raw(vec![0x41, 0x2a])]);
+        raw.inline = Inline::Always;
+        let caller_id = FunId::new(0);
+        let mut caller = function(
+            caller_id,
+            vec![],
+            vec![Statement::Call(Call::Direct(raw_id))],
+        );
+        caller.exposed = Some(String::from("test"));
+        let mut program = Program {
+            funs: vec![caller, raw],
+            imports: Vec::new(),
+            data: HashMap::new(),
+            start: None,
+            memory: MemoryLimits::default(),
+            exports: ModuleExports::default(),
+            table: Vec::new(),
+            allocator: true,
+        };
+        inline(&mut program, 100, &[]);
+        assert_eq!(program.funs.len(), 2);
+        assert!(called_functions(&program.funs[0].body).contains(&raw_id));
+    }
+}
@@ -0,0 +1,1326 @@
+//! MIR Interpreter
+//!
+//! Executes MIR functions directly, without going through WebAssembly. The interpreter follows
+//! the semantic of the wasm code produced by the compiler: it works on a stack of values, a set of
+//! locals per function and a linear memory initialized with the data segments and the allocator
+//! metadata (see `wasm::sections::SectionData`).
+//!
+//! Imported functions are provided by the host and can not be interpreted.
+
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::fmt;
+
+use super::mir::*;
+use crate::error::ErrorHandler;
+
+/// Size of a page of linear memory, in bytes.
+const PAGE_SIZE: usize = 0x10000;
+/// Maximum number of pages of the linear memory.
+const MAX_PAGES: usize = 0x10000;
+/// Maximum depth of nested function calls.
+const MAX_CALL_DEPTH: usize = 1000;
+
+/// A value produced by the interpreter.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RuntimeValue {
+    I32(i32),
+    I64(i64),
+    F32(f32),
+    F64(f64),
+    V128(u128),
+}
+
+/// How the execution continues after a statement.
+enum Flow {
+    Next,
+    Branch(BasicBlockId),
+    Return,
+}
+
+/// The state of a function being executed.
+struct Frame {
+    locals: HashMap<LocalId, RuntimeValue>,
+    stack: Vec<RuntimeValue>,
+}
+
+pub struct Interpreter<'mir, 'err, E: ErrorHandler> {
+    funs: HashMap<FunId, &'mir Function>,
+    imports: HashMap<FunId, &'mir FunctionPrototype>,
+    table: &'mir [FunId],
+    offsets: HashMap<DataId, u32>,
+    memory: Vec<u8>,
+    globals: HashMap<GlobalId, RuntimeValue>,
+    depth: usize,
+    /// Number of statements that can still be executed, if limited.
+    fuel: Option<u64>,
+    err: &'err mut E,
+}
+
+impl<'mir, 'err, E: ErrorHandler> Interpreter<'mir, 'err, E> {
+    pub fn new(program: &'mir Program, error_handler: &'err mut E) -> Self {
+        let funs = program.funs.iter().map(|fun| (fun.fun_id, fun)).collect();
+        let imports = program
+            .imports
+            .iter()
+            .flat_map(|import| import.prototypes.iter())
+            .map(|proto| (proto.fun_id, proto))
+            .collect();
+        let mut interpreter = Self {
+            funs,
+            imports,
+            offsets: HashMap::new(),
+            table: &program.table,
+            memory: vec![0; PAGE_SIZE],
+            globals: HashMap::new(),
+            depth: 0,
+            fuel: None,
+            err: error_handler,
+        };
+        interpreter.initialize_memory(&program.data, program.stack_size());
+        interpreter
+    }
+
+    /// Limits the number of statements executed, default to `None` (no limit).
+    ///
+    /// The budget is shared by all subsequent calls, the execution traps once it is exhausted.
+    /// This guarantees that programs terminate, for instance programs with infinite loops.
+    pub fn set_fuel(&mut self, fuel: Option<u64>) {
+        self.fuel = fuel;
+    }
+
+    /// Lays out data segments, the shadow stack and the allocator metadata the same way the wasm
+    /// backend does.
+    fn initialize_memory(&mut self, data: &HashMap<DataId, Data>, stack_size: u32) {
+        // The first bytes are reserved by the allocator.
+        let mut offset = 8;
+        for (data_id, data) in data {
+            self.offsets.insert(*data_id, offset as u32);
+            // Maintain an offset such that an aligment of 8 is always guaranteed.
+            offset += data.bytes.len().div_ceil(8) * 8;
+        }
+        if stack_size > 0 {
+            // The shadow stack follows the data and grows downward
+            offset += stack_size.div_ceil(8) as usize * 8;
+            self.globals
+                .insert(STACK_POINTER, RuntimeValue::I32(offset as i32));
+        }
+        self.ensure_memory(offset + 8);
+        for (data_id, data) in data {
+            let start = self.offsets[data_id] as usize;
+            match data.relocate(&self.offsets) {
+                Ok(bytes) => self.memory[start..(start + bytes.len())].copy_from_slice(&bytes),
+                Err(err) => self.err.report_internal_no_loc(err),
+            }
+        }
+
+        // mem[0..4] - address of the first block
+        let first_block_offset = offset + 4;
+        self.write(0, &(first_block_offset as u32).to_le_bytes());
+        // mem[offset..(offset + 4)] - mocked block footer with allocated bit set
+        self.write(offset, &0xffffffff_u32.to_le_bytes());
+        // mem[(offset + 4)..(offset + 8)] - first block header (its size)
+        let block_size = self.memory.len() - (first_block_offset + 4);
+        self.write(first_block_offset, &(block_size as u32).to_le_bytes());
+    }
+
+    /// Grows the memory, by a whole number of pages, until it contains at least `size` bytes.
+    fn ensure_memory(&mut self, size: usize) {
+        if size > self.memory.len() {
+            let nb_pages = size.div_ceil(PAGE_SIZE);
+            self.memory.resize(nb_pages * PAGE_SIZE, 0);
+        }
+    }
+
+    fn write(&mut self, addr: usize, bytes: &[u8]) {
+        self.memory[addr..(addr + bytes.len())].copy_from_slice(bytes);
+    }
+
+    /// Calls a function with the given arguments and returns its results.
+    pub fn call(
+        &mut self,
+        fun_id: FunId,
+        args: Vec<RuntimeValue>,
+    ) -> Result<Vec<RuntimeValue>, ()> {
+        let fun = match self.funs.get(&fun_id) {
+            Some(fun) => *fun,
+            None => {
+                return match self.imports.get(&fun_id) {
+                    Some(proto) => self.trap(format!(
+                        "imported function '{}' can not be interpreted",
+                        proto.ident
+                    )),
+                    None => self.internal(format!("function {} does not exist", fun_id)),
+                }
+            }
+        };
+        if self.depth >= MAX_CALL_DEPTH {
+            return self.trap(String::from("call stack exhausted"));
+        }
+
+        let mut frame = Frame {
+            locals: HashMap::new(),
+            stack: Vec::new(),
+        };
+        for (param, arg) in fun.params.iter().zip(args) {
+            frame.locals.insert(*param, arg);
+        }
+        for local in &fun.locals {
+            frame.locals.insert(local.id, zero(local.t));
+        }
+
+        self.depth += 1;
+        let flow = self.block(&fun.body, &mut frame);
+        self.depth -= 1;
+        flow?;
+
+        // The results are on top of the stack, whether the function returned or reached its end.
+        let nb_results = fun.ret_t.len();
+        if frame.stack.len() < nb_results {
+            return self.internal(format!("missing results for function '{}'", fun.ident));
+        }
+        Ok(frame.stack.split_off(frame.stack.len() - nb_results))
+    }
+
+    fn block(&mut self, block: &Block, frame: &mut Frame) -> Result<Flow, ()> {
+        match block {
+            Block::Block { id, stmts, t } => {
+                let height = frame.stack.len();
+                let flow = self.statements(stmts, frame)?;
+                Ok(exit_block(*id, flow, height, t, frame))
+            }
+            Block::Loop { id, stmts, .. } => {
+                let height = frame.stack.len();
+                loop {
+                    match self.statements(stmts, frame)? {
+                        Flow::Branch(target) if target == *id => frame.stack.truncate(height),
+                        flow => return Ok(flow),
+                    }
+                }
+            }
+            Block::If {
+                id,
+                then_stmts,
+                else_stmts,
+                t,
+            } => {
+                let condition = self.pop_i32(frame)?;
+                let height = frame.stack.len();
+                let stmts = if condition != 0 {
+                    then_stmts
+                } else {
+                    else_stmts
+                };
+                let flow = self.statements(stmts, frame)?;
+                Ok(exit_block(*id, flow, height, t, frame))
+            }
+        }
+    }
+
+    fn statements(&mut self, stmts: &[Statement], frame: &mut Frame) -> Result<Flow, ()> {
+        for stmt in stmts {
+            match self.statement(stmt, frame)? {
+                Flow::Next => (),
+                flow => return Ok(flow),
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    fn statement(&mut self, stmt: &Statement, frame: &mut Frame) -> Result<Flow, ()> {
+        if let Some(fuel) = &mut self.fuel {
+            if *fuel == 0 {
+                return self.trap(String::from("fuel exhausted"));
+            }
+            *fuel -= 1;
+        }
+        match stmt {
+            Statement::Local(Local::Get(l_id)) => match frame.locals.get(l_id) {
+                Some(value) => frame.stack.push(*value),
+                None => return self.internal(format!("local {} does not exist", l_id)),
+            },
+            Statement::Local(Local::Set(l_id)) => {
+                let value = self.pop(frame)?;
+                frame.locals.insert(*l_id, value);
+            }
+            Statement::Local(Local::Tee(l_id)) => {
+                let value = self.pop(frame)?;
+                frame.locals.insert(*l_id, value);
+                frame.stack.push(value);
+            }
+            Statement::Const(value) => {
+                let value = match value {
+                    Value::I32(x) => RuntimeValue::I32(*x),
+                    Value::I64(x) => RuntimeValue::I64(*x),
+                    Value::F32(x) => RuntimeValue::F32(*x),
+                    Value::F64(x) => RuntimeValue::F64(*x),
+                    Value::V128(x) => RuntimeValue::V128(*x),
+                    Value::DataPointer(data_id) => match self.offsets.get(data_id) {
+                        Some(offset) => RuntimeValue::I32(*offset as i32),
+                        None => return self.internal(format!("data {} does not exist", data_id)),
+                    },
+                    Value::TableIndex(fun_id) => {
+                        let index = self.table.iter().position(|f| f == fun_id);
+                        match index {
+                            Some(index) => RuntimeValue::I32(index as i32),
+                            None => {
+                                return self.internal(format!("function {} not in table", fun_id))
+                            }
+                        }
+                    }
+                };
+                frame.stack.push(value);
+            }
+            Statement::Block(block) => return self.block(block, frame),
+            Statement::Unop(unop) => self.unop(unop, frame)?,
+            Statement::Binop(binop) => self.binop(binop, frame)?,
+            Statement::Relop(relop) => self.relop(relop, frame)?,
+            Statement::Control(control) => match control {
+                Control::Return => return Ok(Flow::Return),
+                Control::Unreachable => return self.trap(String::from("unreachable")),
+                Control::Br(target) => return Ok(Flow::Branch(*target)),
+                Control::BrIf(target) => {
+                    if self.pop_i32(frame)? != 0 {
+                        return Ok(Flow::Branch(*target));
+                    }
+                }
+            },
+            Statement::Call(Call::Direct(fun_id)) => {
+                let nb_params = match (self.funs.get(fun_id), self.imports.get(fun_id)) {
+                    (Some(fun), _) => fun.param_t.len(),
+                    (None, Some(proto)) => proto.param_t.len(),
+                    (None, None) => {
+                        return self.internal(format!("function {} does not exist", fun_id))
+                    }
+                };
+                if frame.stack.len() < nb_params {
+                    return self.internal(String::from("missing arguments on the stack"));
+                }
+                let args = frame.stack.split_off(frame.stack.len() - nb_params);
+                let results = self.call(*fun_id, args)?;
+                frame.stack.extend(results);
+            }
+            Statement::Call(Call::Indirect(signature)) => {
+                let index = self.pop_i32(frame)?;
+                let fun_id = match self.table.get(index as usize) {
+                    Some(fun_id) => *fun_id,
+                    None => return self.trap(format!("undefined table element {}", index)),
+                };
+                let nb_params = signature.params.len();
+                if frame.stack.len() < nb_params {
+                    return self.internal(String::from("missing arguments on the stack"));
+                }
+                let args = frame.stack.split_off(frame.stack.len() - nb_params);
+                let results = self.call(fun_id, args)?;
+                frame.stack.extend(results);
+            }
+            Statement::Parametric(Parametric::Drop) => {
+                self.pop(frame)?;
+            }
+            Statement::Memory(memory) => self.memory(memory, frame)?,
+            Statement::Global(Global::Get(g_id)) => match self.globals.get(g_id) {
+                Some(value) => frame.stack.push(*value),
+                None => return self.internal(format!("global {} does not exist", g_id)),
+            },
+            Statement::Global(Global::Set(g_id)) => {
+                let value = self.pop(frame)?;
+                self.globals.insert(*g_id, value);
+            }
+            Statement::Simd(simd) => self.simd(simd, frame)?,
+            Statement::Atomic(atomic) => self.atomic(atomic, frame)?,
+            Statement::Raw(_) => {
+                return self.trap(String::from("raw instructions can not be interpreted"))
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    fn unop(&mut self, unop: &Unop, frame: &mut Frame) -> Result<(), ()> {
+        let value = match unop {
+            Unop::I32Clz => RuntimeValue::I32(self.pop_i32(frame)?.leading_zeros() as i32),
+            Unop::I32Ctz => RuntimeValue::I32(self.pop_i32(frame)?.trailing_zeros() as i32),
+            Unop::I32Popcnt => RuntimeValue::I32(self.pop_i32(frame)?.count_ones() as i32),
+            Unop::I64Clz => RuntimeValue::I64(self.pop_i64(frame)?.leading_zeros() as i64),
+            Unop::I64Ctz => RuntimeValue::I64(self.pop_i64(frame)?.trailing_zeros() as i64),
+            Unop::I64Popcnt => RuntimeValue::I64(self.pop_i64(frame)?.count_ones() as i64),
+            Unop::F32Neg => RuntimeValue::F32(-self.pop_f32(frame)?),
+            Unop::F32Abs => RuntimeValue::F32(self.pop_f32(frame)?.abs()),
+            Unop::F32Sqrt => RuntimeValue::F32(self.pop_f32(frame)?.sqrt()),
+            Unop::F32Ceil => RuntimeValue::F32(self.pop_f32(frame)?.ceil()),
+            Unop::F32Floor => RuntimeValue::F32(self.pop_f32(frame)?.floor()),
+            Unop::F32Trunc => RuntimeValue::F32(self.pop_f32(frame)?.trunc()),
+            Unop::F32Nearest => RuntimeValue::F32(self.pop_f32(frame)?.round_ties_even()),
+            Unop::F64Neg => RuntimeValue::F64(-self.pop_f64(frame)?),
+            Unop::F64Abs => RuntimeValue::F64(self.pop_f64(frame)?.abs()),
+            Unop::F64Sqrt => RuntimeValue::F64(self.pop_f64(frame)?.sqrt()),
+            Unop::F64Ceil => RuntimeValue::F64(self.pop_f64(frame)?.ceil()),
+            Unop::F64Floor => RuntimeValue::F64(self.pop_f64(frame)?.floor()),
+            Unop::F64Trunc => RuntimeValue::F64(self.pop_f64(frame)?.trunc()),
+            Unop::F64Nearest => RuntimeValue::F64(self.pop_f64(frame)?.round_ties_even()),
+            // Casts from floats to integers saturate and convert NaN to 0, as `trunc_sat` does
+            Unop::I32TruncSatF32S => RuntimeValue::I32(self.pop_f32(frame)? as i32),
+            Unop::I32TruncSatF32U => RuntimeValue::I32(self.pop_f32(frame)? as u32 as i32),
+            Unop::I32TruncSatF64S => RuntimeValue::I32(self.pop_f64(frame)? as i32),
+            Unop::I32TruncSatF64U => RuntimeValue::I32(self.pop_f64(frame)? as u32 as i32),
+            Unop::I64TruncSatF32S => RuntimeValue::I64(self.pop_f32(frame)? as i64),
+            Unop::I64TruncSatF32U => RuntimeValue::I64(self.pop_f32(frame)? as u64 as i64),
+            Unop::I64TruncSatF64S => RuntimeValue::I64(self.pop_f64(frame)? as i64),
+            Unop::I64TruncSatF64U => RuntimeValue::I64(self.pop_f64(frame)? as u64 as i64),
+            Unop::I32ReinterpretF32 => RuntimeValue::I32(self.pop_f32(frame)?.to_bits() as i32),
+            Unop::I64ReinterpretF64 => RuntimeValue::I64(self.pop_f64(frame)?.to_bits() as i64),
+            Unop::F32ReinterpretI32 => {
+                RuntimeValue::F32(f32::from_bits(self.pop_i32(frame)? as u32))
+            }
+            Unop::F64ReinterpretI64 => {
+                RuntimeValue::F64(f64::from_bits(self.pop_i64(frame)? as u64))
+            }
+        };
+        frame.stack.push(value);
+        Ok(())
+    }
+
+    fn binop(&mut self, binop: &Binop, frame: &mut Frame) -> Result<(), ()> {
+        let value = match binop.get_t() {
+            Type::I32 => {
+                let y = self.pop_i32(frame)?;
+                let x = self.pop_i32(frame)?;
+                RuntimeValue::I32(match binop {
+                    Binop::I32Xor => x ^ y,
+                    Binop::I32Or => x | y,
+                    Binop::I32And => x & y,
+                    Binop::I32Add => x.wrapping_add(y),
+                    Binop::I32Sub => x.wrapping_sub(y),
+                    Binop::I32Mul => x.wrapping_mul(y),
+                    // Divisions are unsigned, as in the wasm backend.
+                    Binop::I32Div => match (x as u32).checked_div(y as u32) {
+                        Some(z) => z as i32,
+                        None => return self.trap(String::from("integer divide by zero")),
+                    },
+                    Binop::I32Rem => match (x as u32).checked_rem(y as u32) {
+                        Some(z) => z as i32,
+                        None => return self.trap(String::from("integer divide by zero")),
+                    },
+                    Binop::I32DivS => match x.checked_div(y) {
+                        Some(z) => z,
+                        None if y == 0 => return self.trap(String::from("integer divide by zero")),
+                        None => return self.trap(String::from("integer overflow")),
+                    },
+                    _ => unreachable!(),
+                })
+            }
+            Type::I64 => {
+                let y = self.pop_i64(frame)?;
+                let x = self.pop_i64(frame)?;
+                RuntimeValue::I64(match binop {
+                    Binop::I64Xor => x ^ y,
+                    Binop::I64Or => x | y,
+                    Binop::I64And => x & y,
+                    Binop::I64Add => x.wrapping_add(y),
+                    Binop::I64Sub => x.wrapping_sub(y),
+                    Binop::I64Mul => x.wrapping_mul(y),
+                    Binop::I64Div => match (x as u64).checked_div(y as u64) {
+                        Some(z) => z as i64,
+                        None => return self.trap(String::from("integer divide by zero")),
+                    },
+                    Binop::I64Rem => match (x as u64).checked_rem(y as u64) {
+                        Some(z) => z as i64,
+                        None => return self.trap(String::from("integer divide by zero")),
+                    },
+                    Binop::I64DivS => match x.checked_div(y) {
+                        Some(z) => z,
+                        None if y == 0 => return self.trap(String::from("integer divide by zero")),
+                        None => return self.trap(String::from("integer overflow")),
+                    },
+                    _ => unreachable!(),
+                })
+            }
+            Type::F32 => {
+                let y = self.pop_f32(frame)?;
+                let x = self.pop_f32(frame)?;
+                RuntimeValue::F32(match binop {
+                    Binop::F32Add => x + y,
+                    Binop::F32Sub => x - y,
+                    Binop::F32Mul => x * y,
+                    Binop::F32Div => x / y,
+                    // Both operands are exactly represented as f64, and so is the result
+                    Binop::F32Min => float_min(x as f64, y as f64) as f32,
+                    Binop::F32Max => float_max(x as f64, y as f64) as f32,
+                    Binop::F32Copysign => x.copysign(y),
+                    _ => unreachable!(),
+                })
+            }
+            Type::F64 => {
+                let y = self.pop_f64(frame)?;
+                let x = self.pop_f64(frame)?;
+                RuntimeValue::F64(match binop {
+                    Binop::F64Add => x + y,
+                    Binop::F64Sub => x - y,
+                    Binop::F64Mul => x * y,
+                    Binop::F64Div => x / y,
+                    Binop::F64Min => float_min(x, y),
+                    Binop::F64Max => float_max(x, y),
+                    Binop::F64Copysign => x.copysign(y),
+                    _ => unreachable!(),
+                })
+            }
+            Type::V128 => return self.internal(String::from("binop on a v128")),
+        };
+        frame.stack.push(value);
+        Ok(())
+    }
+
+    fn relop(&mut self, relop: &Relop, frame: &mut Frame) -> Result<(), ()> {
+        let result = match relop.get_t() {
+            Type::I32 => {
+                let y = self.pop_i32(frame)?;
+                let x = self.pop_i32(frame)?;
+                compare(relop, x, y)
+            }
+            Type::I64 => {
+                let y = self.pop_i64(frame)?;
+                let x = self.pop_i64(frame)?;
+                compare(relop, x, y)
+            }
+            Type::F32 => {
+                let y = self.pop_f32(frame)?;
+                let x = self.pop_f32(frame)?;
+                compare(relop, x, y)
+            }
+            Type::F64 => {
+                let y = self.pop_f64(frame)?;
+                let x = self.pop_f64(frame)?;
+                compare(relop, x, y)
+            }
+            Type::V128 => return self.internal(String::from("relop on a v128")),
+        };
+        frame.stack.push(RuntimeValue::I32(result as i32));
+        Ok(())
+    }
+
+    fn simd(&mut self, simd: &Simd, frame: &mut Frame) -> Result<(), ()> {
+        let value = match simd {
+            Simd::Splat(shape) => {
+                let x = self.pop_lane(*shape, frame)?;
+                let v = (0..shape.lanes()).fold(0, |v, lane| set_lane(v, *shape, lane, x));
+                RuntimeValue::V128(v)
+            }
+            Simd::ExtractLane(shape, lane) => {
+                let v = self.pop_v128(frame)?;
+                let x = get_lane(v, *shape, *lane);
+                match shape {
+                    Shape::I8x16 | Shape::I16x8 | Shape::I32x4 => RuntimeValue::I32(x as i32),
+                    Shape::I64x2 => RuntimeValue::I64(x as i64),
+                    Shape::F32x4 => RuntimeValue::F32(f32::from_bits(x as u32)),
+                    Shape::F64x2 => RuntimeValue::F64(f64::from_bits(x)),
+                }
+            }
+            Simd::ReplaceLane(shape, lane) => {
+                let x = self.pop_lane(*shape, frame)?;
+                let v = self.pop_v128(frame)?;
+                RuntimeValue::V128(set_lane(v, *shape, *lane, x))
+            }
+            Simd::Not => RuntimeValue::V128(!self.pop_v128(frame)?),
+            Simd::And | Simd::Or | Simd::Xor => {
+                let y = self.pop_v128(frame)?;
+                let x = self.pop_v128(frame)?;
+                RuntimeValue::V128(match simd {
+                    Simd::And => x & y,
+                    Simd::Or => x | y,
+                    _ => x ^ y,
+                })
+            }
+            Simd::Add(shape) | Simd::Sub(shape) | Simd::Mul(shape) | Simd::Div(shape) => {
+                let y = self.pop_v128(frame)?;
+                let x = self.pop_v128(frame)?;
+                let mut v = 0;
+                for lane in 0..shape.lanes() {
+                    let z = lane_op(
+                        simd,
+                        *shape,
+                        get_lane(x, *shape, lane),
+                        get_lane(y, *shape, lane),
+                    );
+                    v = set_lane(v, *shape, lane, z);
+                }
+                RuntimeValue::V128(v)
+            }
+        };
+        frame.stack.push(value);
+        Ok(())
+    }
+
+    /// Executes an atomic operation, the interpreter runs a single thread: the accesses can not
+    /// interleave and there is no other thread to notify a waiting one.
+    fn atomic(&mut self, atomic: &Atomic, frame: &mut Frame) -> Result<(), ()> {
+        let len = 1 << atomic.align();
+        match atomic {
+            Atomic::Load { t, offset } => {
+                let addr = self.atomic_address(*offset, len, frame)?;
+                let value = self.read_integer(addr, len);
+                frame.stack.push(integer_value(*t, value));
+            }
+            Atomic::Store { t, offset } => {
+                let value = self.pop_integer(*t, frame)?;
+                let addr = self.atomic_address(*offset, len, frame)?;
+                self.write(addr, &value.to_le_bytes()[..len]);
+            }
+            Atomic::Rmw { op, t, offset } => {
+                let value = self.pop_integer(*t, frame)?;
+                let addr = self.atomic_address(*offset, len, frame)?;
+                let old = self.read_integer(addr, len);
+                let new = match op {
+                    RmwOp::Add => old.wrapping_add(value),
+                    RmwOp::Sub => old.wrapping_sub(value),
+                    RmwOp::And => old & value,
+                    RmwOp::Or => old | value,
+                    RmwOp::Xor => old ^ value,
+                    RmwOp::Xchg => value,
+                };
+                self.write(addr, &new.to_le_bytes()[..len]);
+                frame.stack.push(integer_value(*t, old));
+            }
+            Atomic::Cmpxchg { t, offset } => {
+                let replacement = self.pop_integer(*t, frame)?;
+                let expected = self.pop_integer(*t, frame)?;
+                let addr = self.atomic_address(*offset, len, frame)?;
+                let old = self.read_integer(addr, len);
+                if old == expected {
+                    self.write(addr, &replacement.to_le_bytes()[..len]);
+                }
+                frame.stack.push(integer_value(*t, old));
+            }
+            Atomic::Notify { offset } => {
+                self.pop_i32(frame)?;
+                self.atomic_address(*offset, len, frame)?;
+                frame.stack.push(RuntimeValue::I32(0));
+            }
+            Atomic::Wait { t, offset } => {
+                let timeout = self.pop_i64(frame)?;
+                let expected = self.pop_integer(*t, frame)?;
+                let addr = self.atomic_address(*offset, len, frame)?;
+                // Returns 1 if the value differs and 2 once the timeout expired
+                if self.read_integer(addr, len) != expected {
+                    frame.stack.push(RuntimeValue::I32(1));
+                } else if timeout < 0 {
+                    return self.trap(String::from("waiting forever without other threads"));
+                } else {
+                    frame.stack.push(RuntimeValue::I32(2));
+                }
+            }
+            Atomic::Fence => (),
+        }
+        Ok(())
+    }
+
+    fn memory(&mut self, memory: &Memory, frame: &mut Frame) -> Result<(), ()> {
+        match memory {
+            Memory::Size => {
+                let nb_pages = self.memory.len() / PAGE_SIZE;
+                frame.stack.push(RuntimeValue::I32(nb_pages as i32));
+            }
+            Memory::Grow => {
+                let delta = self.pop_i32(frame)?;
+                let nb_pages = self.memory.len() / PAGE_SIZE;
+                if delta < 0 || nb_pages + delta as usize > MAX_PAGES {
+                    frame.stack.push(RuntimeValue::I32(-1));
+                } else {
+                    self.memory
+                        .resize((nb_pages + delta as usize) * PAGE_SIZE, 0);
+                    frame.stack.push(RuntimeValue::I32(nb_pages as i32));
+                }
+            }
+            Memory::I32Load { offset, .. } => {
+                let bytes = self.load(*offset, 4, frame)?;
+                let value = i32::from_le_bytes(bytes.try_into().unwrap());
+                frame.stack.push(RuntimeValue::I32(value));
+            }
+            Memory::I64Load { offset, .. } => {
+                let bytes = self.load(*offset, 8, frame)?;
+                let value = i64::from_le_bytes(bytes.try_into().unwrap());
+                frame.stack.push(RuntimeValue::I64(value));
+            }
+            Memory::F32Load { offset, .. } => {
+                let bytes = self.load(*offset, 4, frame)?;
+                let value = f32::from_le_bytes(bytes.try_into().unwrap());
+                frame.stack.push(RuntimeValue::F32(value));
+            }
+            Memory::F64Load { offset, .. } => {
+                let bytes = self.load(*offset, 8, frame)?;
+                let value = f64::from_le_bytes(bytes.try_into().unwrap());
+                frame.stack.push(RuntimeValue::F64(value));
+            }
+            Memory::V128Load { offset, .. } => {
+                let bytes = self.load(*offset, 16, frame)?;
+                let value = u128::from_le_bytes(bytes.try_into().unwrap());
+                frame.stack.push(RuntimeValue::V128(value));
+            }
+            Memory::I32Load8u { offset, .. } => {
+                let byte = self.load(*offset, 1, frame)?[0];
+                frame.stack.push(RuntimeValue::I32(byte as i32));
+            }
+            Memory::I64Load8u { offset, .. } => {
+                let byte = self.load(*offset, 1, frame)?[0];
+                frame.stack.push(RuntimeValue::I64(byte as i64));
+            }
+            Memory::I32Store { offset, .. } => {
+                let value = self.pop_i32(frame)?;
+                self.store(*offset, &value.to_le_bytes(), frame)?;
+            }
+            Memory::I64Store { offset, .. } => {
+                let value = self.pop_i64(frame)?;
+                self.store(*offset, &value.to_le_bytes(), frame)?;
+            }
+            Memory::F32Store { offset, .. } => {
+                let value = self.pop_f32(frame)?;
+                self.store(*offset, &value.to_le_bytes(), frame)?;
+            }
+            Memory::F64Store { offset, .. } => {
+                let value = self.pop_f64(frame)?;
+                self.store(*offset, &value.to_le_bytes(), frame)?;
+            }
+            Memory::V128Store { offset, .. } => {
+                let value = self.pop_v128(frame)?;
+                self.store(*offset, &value.to_le_bytes(), frame)?;
+            }
+            Memory::I32Store8 { offset, .. } => {
+                let value = self.pop_i32(frame)?;
+                self.store(*offset, &[value as u8], frame)?;
+            }
+            Memory::I64Store8 { offset, .. } => {
+                let value = self.pop_i64(frame)?;
+                self.store(*offset, &[value as u8], frame)?;
+            }
+            Memory::Nop => (),
+        }
+        Ok(())
+    }
+
+    /// Pops an address and reads `len` bytes of memory at that address plus `offset`.
+    fn load(&mut self, offset: u32, len: usize, frame: &mut Frame) -> Result<&[u8], ()> {
+        let addr = self.effective_address(offset, len, frame)?;
+        Ok(&self.memory[addr..(addr + len)])
+    }
+
+    /// Pops an address and writes `bytes` in memory at that address plus `offset`.
+    fn store(&mut self, offset: u32, bytes: &[u8], frame: &mut Frame) -> Result<(), ()> {
+        let addr = self.effective_address(offset, bytes.len(), frame)?;
+        self.write(addr, bytes);
+        Ok(())
+    }
+
+    fn effective_address(
+        &mut self,
+        offset: u32,
+        len: usize,
+        frame: &mut Frame,
+    ) -> Result<usize, ()> {
+        let addr = self.pop_i32(frame)? as u32 as usize + offset as usize;
+        if addr + len > self.memory.len() {
+            return self.trap(String::from("out of bounds memory access"));
+        }
+        Ok(addr)
+    }
+
+    /// Pops an address and checks that the atomic access of `len` bytes at that address plus
+    /// `offset` is aligned.
+    fn atomic_address(&mut self, offset: u32, len: usize, frame: &mut Frame) -> Result<usize, ()> {
+        let addr = self.effective_address(offset, len, frame)?;
+        if addr % len != 0 {
+            return self.trap(String::from("unaligned atomic memory access"));
+        }
+        Ok(addr)
+    }
+
+    /// Reads an integer of `len` bytes, at most 8, zero-extended to 64 bits.
+    fn read_integer(&self, addr: usize, len: usize) -> u64 {
+        let mut bytes = [0; 8];
+        bytes[..len].copy_from_slice(&self.memory[addr..(addr + len)]);
+        u64::from_le_bytes(bytes)
+    }
+
+    fn pop(&mut self, frame: &mut Frame) -> Result<RuntimeValue, ()> {
+        match frame.stack.pop() {
+            Some(value) => Ok(value),
+            None => self.internal(String::from("the stack is empty")),
+        }
+    }
+
+    fn pop_i32(&mut self, frame: &mut Frame) -> Result<i32, ()> {
+        match self.pop(frame)? {
+            RuntimeValue::I32(x) => Ok(x),
+            value => self.internal(format!("expected an i32, got {:?}", value)),
+        }
+    }
+
+    fn pop_i64(&mut self, frame: &mut Frame) -> Result<i64, ()> {
+        match self.pop(frame)? {
+            RuntimeValue::I64(x) => Ok(x),
+            value => self.internal(format!("expected an i64, got {:?}", value)),
+        }
+    }
+
+    fn pop_f32(&mut self, frame: &mut Frame) -> Result<f32, ()> {
+        match self.pop(frame)? {
+            RuntimeValue::F32(x) => Ok(x),
+            value => self.internal(format!("expected an f32, got {:?}", value)),
+        }
+    }
+
+    fn pop_f64(&mut self, frame: &mut Frame) -> Result<f64, ()> {
+        match self.pop(frame)? {
+            RuntimeValue::F64(x) => Ok(x),
+            value => self.internal(format!("expected an f64, got {:?}", value)),
+        }
+    }
+
+    fn pop_v128(&mut self, frame: &mut Frame) -> Result<u128, ()> {
+        match self.pop(frame)? {
+            RuntimeValue::V128(x) => Ok(x),
+            value => self.internal(format!("expected a v128, got {:?}", value)),
+        }
+    }
+
+    /// Pops an `i32` or `i64` and returns its bits, zero-extended to 64 bits.
+    fn pop_integer(&mut self, t: Type, frame: &mut Frame) -> Result<u64, ()> {
+        match t {
+            Type::I32 => Ok(self.pop_i32(frame)? as u32 as u64),
+            Type::I64 => Ok(self.pop_i64(frame)? as u64),
+            _ => self.internal(format!("expected an integer type, got {}", t)),
+        }
+    }
+
+    /// Pops a value that fits in a lane of `shape`, and returns its bits.
+    fn pop_lane(&mut self, shape: Shape, frame: &mut Frame) -> Result<u64, ()> {
+        match shape.lane_t() {
+            Type::I32 => Ok(self.pop_i32(frame)? as u32 as u64),
+            Type::I64 => Ok(self.pop_i64(frame)? as u64),
+            Type::F32 => Ok(self.pop_f32(frame)?.to_bits() as u64),
+            Type::F64 => Ok(self.pop_f64(frame)?.to_bits()),
+            Type::V128 => self.internal(String::from("a lane can not hold a v128")),
+        }
+    }
+
+    /// Reports an error raised by the program being executed.
+    fn trap<T>(&mut self, message: String) -> Result<T, ()> {
+        self.err
+            .report_no_loc(format!("Runtime error: {}", message));
+        Err(())
+    }
+
+    /// Reports an error caused by an invalid MIR program.
+    fn internal<T>(&mut self, message: String) -> Result<T, ()> {
+        self.err
+            .report_internal_no_loc(format!("Interpreter: {}", message));
+        Err(())
+    }
+}
+
+/// Leaves a block: a branch targeting the block exits it keeping only its result, if any, on top
+/// of the stack.
+fn exit_block(
+    id: BasicBlockId,
+    flow: Flow,
+    height: usize,
+    t: &Option<Type>,
+    frame: &mut Frame,
+) -> Flow {
+    match flow {
+        Flow::Branch(target) if target == id => {
+            let nb_results = if t.is_some() { 1 } else { 0 };
+            let results = frame.stack.split_off(frame.stack.len() - nb_results);
+            frame.stack.truncate(height);
+            frame.stack.extend(results);
+            Flow::Next
+        }
+        flow => flow,
+    }
+}
+
+fn compare<T: PartialOrd>(relop: &Relop, x: T, y: T) -> bool {
+    match relop {
+        Relop::I32Eq | Relop::I64Eq | Relop::F32Eq | Relop::F64Eq => x == y,
+        Relop::I32Ne | Relop::I64Ne | Relop::F32Ne | Relop::F64Ne => x != y,
+        Relop::I32Lt | Relop::I64Lt | Relop::F32Lt | Relop::F64Lt => x < y,
+        Relop::I32Gt | Relop::I64Gt | Relop::F32Gt | Relop::F64Gt => x > y,
+        Relop::I32Le | Relop::I64Le | Relop::F32Le | Relop::F64Le => x <= y,
+        Relop::I32Ge | Relop::I64Ge | Relop::F32Ge | Relop::F64Ge => x >= y,
+    }
+}
+
+/// The minimum following WebAssembly: NaN if either operand is NaN, and -0 is smaller than +0.
+fn float_min(x: f64, y: f64) -> f64 {
+    if x.is_nan() || y.is_nan() {
+        f64::NAN
+    } else if x == y {
+        if x.is_sign_negative() {
+            x
+        } else {
+            y
+        }
+    } else {
+        x.min(y)
+    }
+}
+
+/// The maximum following WebAssembly, see `float_min`.
+fn float_max(x: f64, y: f64) -> f64 {
+    if x.is_nan() || y.is_nan() {
+        f64::NAN
+    } else if x == y {
+        if x.is_sign_positive() {
+            x
+        } else {
+            y
+        }
+    } else {
+        x.max(y)
+    }
+}
+
+/// Returns the bits of a lane of `v`, zero-extended.
+fn get_lane(v: u128, shape: Shape, lane: u8) -> u64 {
+    let width = 128 / shape.lanes() as u32;
+    let mask = u128::MAX >> (128 - width);
+    ((v >> (lane as u32 * width)) & mask) as u64
+}
+
+/// Returns `v` with a lane replaced by the low bits of `x`.
+fn set_lane(v: u128, shape: Shape, lane: u8, x: u64) -> u128 {
+    let width = 128 / shape.lanes() as u32;
+    let shift = lane as u32 * width;
+    let mask = (u128::MAX >> (128 - width)) << shift;
+    (v & !mask) | (((x as u128) << shift) & mask)
+}
+
+/// Applies a lane-wise arithmetic operation to the bits of two lanes, integer operations wrap.
+fn lane_op(simd: &Simd, shape: Shape, x: u64, y: u64) -> u64 {
+    match shape {
+        Shape::F32x4 => {
+            let (x, y) = (f32::from_bits(x as u32), f32::from_bits(y as u32));
+            let z = match simd {
+                Simd::Add(_) => x + y,
+                Simd::Sub(_) => x - y,
+                Simd::Mul(_) => x * y,
+                _ => x / y,
+            };
+            z.to_bits() as u64
+        }
+        Shape::F64x2 => {
+            let (x, y) = (f64::from_bits(x), f64::from_bits(y));
+            let z = match simd {
+                Simd::Add(_) => x + y,
+                Simd::Sub(_) => x - y,
+                Simd::Mul(_) => x * y,
+                _ => x / y,
+            };
+            z.to_bits()
+        }
+        _ => match simd {
+            Simd::Add(_) => x.wrapping_add(y),
+            Simd::Sub(_) => x.wrapping_sub(y),
+            _ => x.wrapping_mul(y),
+        },
+    }
+}
+
+/// Converts the bits of an integer of type `t` into a value.
+fn integer_value(t: Type, bits: u64) -> RuntimeValue {
+    match t {
+        Type::I64 => RuntimeValue::I64(bits as i64),
+        _ => RuntimeValue::I32(bits as i32),
+    }
+}
+
+fn zero(t: Type) -> RuntimeValue {
+    match t {
+        Type::I32 => RuntimeValue::I32(0),
+        Type::I64 => RuntimeValue::I64(0),
+        Type::F32 => RuntimeValue::F32(0.0),
+        Type::F64 => RuntimeValue::F64(0.0),
+        Type::V128 => RuntimeValue::V128(0),
+    }
+}
+
+impl fmt::Display for RuntimeValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuntimeValue::I32(x) => write!(f, "{}", x),
+            RuntimeValue::I64(x) => write!(f, "{}", x),
+            RuntimeValue::F32(x) => write!(f, "{}", x),
+            RuntimeValue::F64(x) => write!(f, "{}", x),
+            RuntimeValue::V128(x) => write!(f, "{:#034x}", x),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::DummyHandler;
+    use crate::hir::Identifier;
+
+    fn program(fun: Function) -> Program {
+        Program {
+            funs: vec![fun],
+            imports: Vec::new(),
+            data: HashMap::new(),
+            start: None,
+            memory: MemoryLimits::default(),
+            exports: ModuleExports::default(),
+            table: Vec::new(),
+            allocator: true,
+        }
+    }
+
+    fn function(fun_id: FunId, locals: Vec<LocalVariable>, stmts: Vec<Statement>) -> Function {
+        Function {
+            ident: String::from("test"),
+            params: Vec::new(),
+            param_t: Vec::new(),
+            ret_t: vec![Type::I32],
+            locals,
+            body: Block::Block {
+                id: 0,
+                stmts,
+                t: None,
+            },
+            is_pub: true,
+            inline: Inline::Auto,
+            exposed: None,
+            fun_id,
+        }
+    }
+
+    #[test]
+    fn loops() {
+        // Sums integers from 1 to 10.
+        let stmts = vec![
+            Statement::Block(Box::new(Block::Loop {
+                id: 1,
+                t: None,
+                stmts: vec![
+                    Statement::Local(Local::Get(0)),
+                    Statement::Const(Value::I32(1)),
+                    Statement::Binop(Binop::I32Add),
+                    Statement::Local(Local::Set(0)),
+                    Statement::Local(Local::Get(1)),
+                    Statement::Local(Local::Get(0)),
+                    Statement::Binop(Binop::I32Add),
+                    Statement::Local(Local::Set(1)),
+                    Statement::Local(Local::Get(0)),
+                    Statement::Const(Value::I32(10)),
+                    Statement::Relop(Relop::I32Lt),
+                    Statement::Control(Control::BrIf(1)),
+                ],
+            })),
+            Statement::Local(Local::Get(1)),
+        ];
+        let locals = vec![
+            LocalVariable {
+                id: 0,
+                t: Type::I32,
+            },
+            LocalVariable {
+                id: 1,
+                t: Type::I32,
+            },
+        ];
+        let fun_id = FunId::new(0);
+        let program = program(function(fun_id, locals, stmts));
+        let mut err = DummyHandler::new();
+        let results = Interpreter::new(&program, &mut err).call(fun_id, Vec::new());
+        assert_eq!(results, Ok(vec![RuntimeValue::I32(55)]));
+    }
+
+    #[test]
+    fn fuel() {
+        let stmts = vec![Statement::Block(Box::new(Block::Loop {
+            id: 1,
+            t: None,
+            stmts: vec![Statement::Control(Control::Br(1))],
+        }))];
+        let fun_id = FunId::new(0);
+        let program = program(function(fun_id, Vec::new(), stmts));
+        let mut err = DummyHandler::new();
+        let mut interpreter = Interpreter::new(&program, &mut err);
+        interpreter.set_fuel(Some(1000));
+        let results = interpreter.call(fun_id, Vec::new());
+        assert!(results.is_err());
+        assert!(err.has_error());
+    }
+
+    #[test]
+    fn traps() {
+        let stmts = vec![
+            Statement::Const(Value::I32(1)),
+            Statement::Const(Value::I32(0)),
+            Statement::Binop(Binop::I32Div),
+        ];
+        let fun_id = FunId::new(0);
+        let program = program(function(fun_id, Vec::new(), stmts));
+        let mut err = DummyHandler::new();
+        let results = Interpreter::new(&program, &mut err).call(fun_id, Vec::new());
+        assert!(results.is_err());
+        assert!(err.has_error());
+    }
+
+    #[test]
+    fn start_function() {
+        // The start function stores a value read by the called function.
+        let start_id = FunId::new(1);
+        let mut start = function(
+            start_id,
+            Vec::new(),
+            vec![
+                Statement::Const(Value::I32(8)),
+                Statement::Const(Value::I32(42)),
+                Statement::Memory(Memory::I32Store {
+                    align: 2,
+                    offset: 0,
+                }),
+            ],
+        );
+        start.ret_t = Vec::new();
+        let fun_id = FunId::new(0);
+        let fun = function(
+            fun_id,
+            Vec::new(),
+            vec![
+                Statement::Const(Value::I32(8)),
+                Statement::Memory(Memory::I32Load {
+                    align: 2,
+                    offset: 0,
+                }),
+            ],
+        );
+        let mut program = program(fun);
+        program.funs.push(start);
+        program.start = Some(start_id);
+        let mut err = DummyHandler::new();
+        let results = super::super::interpret(&program, fun_id, Vec::new(), None, &mut err);
+        assert_eq!(results, Ok(vec![RuntimeValue::I32(42)]));
+    }
+
+    #[test]
+    fn data_relocations() {
+        // A struct pointing to some bytes, reads the first byte through the struct.
+        let bytes_id = DataId::new(1);
+        let struct_id = DataId::new(2);
+        let stmts = vec![
+            Statement::Const(Value::DataPointer(struct_id)),
+            Statement::Memory(Memory::I32Load {
+                align: 2,
+                offset: 4,
+            }),
+            Statement::Memory(Memory::I32Load8u {
+                align: 0,
+                offset: 0,
+            }),
+        ];
+        let fun_id = FunId::new(0);
+        let mut program = program(function(fun_id, Vec::new(), stmts));
+        program.data.insert(
+            bytes_id,
+            Data {
+                bytes: b"zephyr".to_vec(),
+                relocations: Vec::new(),
+            },
+        );
+        program.data.insert(
+            struct_id,
+            Data {
+                bytes: vec![6, 0, 0, 0, 0, 0, 0, 0],
+                relocations: vec![(4, bytes_id)],
+            },
+        );
+        let mut err = DummyHandler::new();
+        let results = Interpreter::new(&program, &mut err).call(fun_id, Vec::new());
+        assert_eq!(results, Ok(vec![RuntimeValue::I32(b'z' as i32)]));
+    }
+
+    #[test]
+    fn indirect_calls() {
+        // Calls a function doubling its argument through the table.
+        let callee_id = FunId::new(1);
+        let mut callee = function(
+            callee_id,
+            Vec::new(),
+            vec![
+                Statement::Local(Local::Get(0)),
+                Statement::Local(Local::Get(0)),
+                Statement::Binop(Binop::I32Add),
+            ],
+        );
+        callee.params = vec![0];
+        callee.param_t = vec![Type::I32];
+        let fun_id = FunId::new(0);
+        let fun = function(
+            fun_id,
+            Vec::new(),
+            vec![
+                Statement::Const(Value::I32(21)),
+                Statement::Const(Value::TableIndex(callee_id)),
+                Statement::Call(Call::Indirect(Signature {
+                    params: vec![Type::I32],
+                    ret_t: vec![Type::I32],
+                })),
+            ],
+        );
+        let mut program = program(fun);
+        program.funs.push(callee);
+        program.table.push(callee_id);
+        let mut err = DummyHandler::new();
+        let results = Interpreter::new(&program, &mut err).call(fun_id, Vec::new());
+        assert_eq!(results, Ok(vec![RuntimeValue::I32(42)]));
+    }
+
+    #[test]
+    fn shadow_stack() {
+        // Stores a value in a frame of the shadow stack, then returns it plus the size of the
+        // frame once released.
+        let stmts = vec![
+            Statement::Global(Global::Get(STACK_POINTER)),
+            Statement::Const(Value::I32(8)),
+            Statement::Binop(Binop::I32Sub),
+            Statement::Local(Local::Tee(0)),
+            Statement::Global(Global::Set(STACK_POINTER)),
+            Statement::Local(Local::Get(0)),
+            Statement::Const(Value::I32(34)),
+            Statement::Memory(Memory::I32Store {
+                align: 2,
+                offset: 4,
+            }),
+            Statement::Local(Local::Get(0)),
+            Statement::Const(Value::I32(8)),
+            Statement::Binop(Binop::I32Add),
+            Statement::Global(Global::Set(STACK_POINTER)),
+            Statement::Local(Local::Get(0)),
+            Statement::Memory(Memory::I32Load {
+                align: 2,
+                offset: 4,
+            }),
+            Statement::Global(Global::Get(STACK_POINTER)),
+            Statement::Local(Local::Get(0)),
+            Statement::Binop(Binop::I32Sub),
+            Statement::Binop(Binop::I32Add),
+        ];
+        let locals = vec![LocalVariable {
+            id: 0,
+            t: Type::I32,
+        }];
+        let fun_id = FunId::new(0);
+        let program = program(function(fun_id, locals, stmts));
+        assert_eq!(program.stack_size(), STACK_SIZE);
+        let mut err = DummyHandler::new();
+        let results = Interpreter::new(&program, &mut err).call(fun_id, Vec::new());
+        assert_eq!(results, Ok(vec![RuntimeValue::I32(42)]));
+    }
+
+    #[test]
+    fn simd() {
+        // Splats 20 to four lanes, replaces the third one with 2 and sums two of the lanes
+        // once stored and loaded back.
+        let stmts = vec![
+            Statement::Const(Value::I32(16)),
+            Statement::Const(Value::I32(20)),
+            Statement::Simd(Simd::Splat(Shape::I32x4)),
+            Statement::Const(Value::I32(2)),
+            Statement::Simd(Simd::ReplaceLane(Shape::I32x4, 2)),
+            Statement::Const(Value::V128(1)),
+            Statement::Simd(Simd::Add(Shape::I32x4)),
+            Statement::Memory(Memory::V128Store {
+                align: 4,
+                offset: 0,
+            }),
+            Statement::Const(Value::I32(16)),
+            Statement::Memory(Memory::V128Load {
+                align: 4,
+                offset: 0,
+            }),
+            Statement::Local(Local::Tee(0)),
+            Statement::Simd(Simd::ExtractLane(Shape::I32x4, 0)),
+            Statement::Local(Local::Get(0)),
+            Statement::Simd(Simd::ExtractLane(Shape::I8x16, 8)),
+            Statement::Binop(Binop::I32Add),
+        ];
+        let locals = vec![LocalVariable {
+            id: 0,
+            t: Type::V128,
+        }];
+        let fun_id = FunId::new(0);
+        let program = program(function(fun_id, locals, stmts));
+        let mut err = DummyHandler::new();
+        let results = Interpreter::new(&program, &mut err).call(fun_id, Vec::new());
+        assert_eq!(results, Ok(vec![RuntimeValue::I32(23)]));
+    }
+
+    #[test]
+    fn atomics() {
+        // Adds 40 to 2 stored in memory, then replaces it if it holds 42 and returns the value
+        // seen by the exchange plus the result of a wait that does not block.
+        let stmts = vec![
+            Statement::Const(Value::I32(8)),
+            Statement::Const(Value::I32(2)),
+            Statement::Atomic(Atomic::Store {
+                t: Type::I32,
+                offset: 0,
+            }),
+            Statement::Const(Value::I32(8)),
+            Statement::Const(Value::I32(40)),
+            Statement::Atomic(Atomic::Rmw {
+                op: RmwOp::Add,
+                t: Type::I32,
+                offset: 0,
+            }),
+            Statement::Parametric(Parametric::Drop),
+            Statement::Const(Value::I32(4)),
+            Statement::Const(Value::I32(42)),
+            Statement::Const(Value::I32(7)),
+            Statement::Atomic(Atomic::Cmpxchg {
+                t: Type::I32,
+                offset: 4,
+            }),
+            Statement::Const(Value::I32(8)),
+            Statement::Const(Value::I32(42)),
+            Statement::Const(Value::I64(-1)),
+            Statement::Atomic(Atomic::Wait {
+                t: Type::I32,
+                offset: 0,
+            }),
+            Statement::Binop(Binop::I32Add),
+        ];
+        let fun_id = FunId::new(0);
+        let program = program(function(fun_id, Vec::new(), stmts));
+        let mut err = DummyHandler::new();
+        let results = Interpreter::new(&program, &mut err).call(fun_id, Vec::new());
+        assert_eq!(results, Ok(vec![RuntimeValue::I32(43)]));
+    }
+
+    #[test]
+    fn saturating_conversions() {
+        // Out of range and NaN floats saturate, the bits of 1.0 are 0x3f800000.
+        let stmts = vec![
+            Statement::Const(Value::F64(1e20)),
+            Statement::Unop(Unop::I32TruncSatF64S),
+            Statement::Const(Value::F32(-5.0)),
+            Statement::Unop(Unop::I64TruncSatF32U),
+            Statement::Const(Value::F64(f64::NAN)),
+            Statement::Unop(Unop::I32TruncSatF64U),
+            Statement::Const(Value::F32(1.0)),
+            Statement::Unop(Unop::I32ReinterpretF32),
+        ];
+        let fun_id = FunId::new(0);
+        let mut fun = function(fun_id, Vec::new(), stmts);
+        fun.ret_t = vec![Type::I32, Type::I64, Type::I32, Type::I32];
+        let program = program(fun);
+        let mut err = DummyHandler::new();
+        let results = Interpreter::new(&program, &mut err).call(fun_id, Vec::new());
+        let expected = vec![
+            RuntimeValue::I32(i32::MAX),
+            RuntimeValue::I64(0),
+            RuntimeValue::I32(0),
+            RuntimeValue::I32(0x3f800000),
+        ];
+        assert_eq!(results, Ok(expected));
+    }
+}
@@ -0,0 +1,485 @@
+//! Loop optimizations
+//!
+//! Two transformations are applied to each loop, starting from the innermost ones:
+//!
+//! - Loop-invariant code motion: pure expressions depending only on constants and locals that
+//!   are not assigned within the loop are computed once before the loop and stored in a fresh
+//!   local.
+//! - Strength reduction: multiplications of an induction variable (a local incremented by a
+//!   constant exactly once per iteration) by a constant are replaced by a fresh local, which is
+//!   incremented alongside the induction variable.
+//!
+//! Only the top level statements of a loop are considered for code motion, as they are executed
+//! at least once whenever the loop is entered.
+
+use std::collections::HashMap;
+
+use super::mir::*;
+
+/// Applies loop optimizations to a function.
+pub fn optimize_loops(fun: &mut Function) {
+    let next_local = fun
+        .params
+        .iter()
+        .chain(fun.locals.iter().map(|local| &local.id))
+        .max()
+        .map_or(0, |id| id + 1);
+    let mut optimizer = LoopOptimizer {
+        locals: &mut fun.locals,
+        next_local,
+    };
+    optimizer.block(&mut fun.body);
+}
+
+/// A value on the stack, produced by the statements in `start..end`.
+#[derive(Clone, Copy)]
+struct Operand {
+    start: usize,
+    end: usize,
+}
+
+/// A local incremented by a constant step exactly once per iteration.
+struct InductionVariable {
+    step: Value,
+    /// Either an addition or a subtraction.
+    binop: Binop,
+}
+
+struct LoopOptimizer<'a> {
+    /// The locals of the function.
+    locals: &'a mut Vec<LocalVariable>,
+    next_local: LocalId,
+}
+
+impl<'a> LoopOptimizer<'a> {
+    fn block(&mut self, block: &mut Block) {
+        match block {
+            Block::Block { stmts, .. } | Block::Loop { stmts, .. } => self.statements(stmts),
+            Block::If {
+                then_stmts,
+                else_stmts,
+                ..
+            } => {
+                self.statements(then_stmts);
+                self.statements(else_stmts);
+            }
+        }
+    }
+
+    /// Optimizes the loops within a sequence of statements, the statements moved out of a loop
+    /// are inserted right before it.
+    fn statements(&mut self, stmts: &mut Vec<Statement>) {
+        let mut optimized = Vec::with_capacity(stmts.len());
+        for mut stmt in stmts.drain(..) {
+            if let Statement::Block(block) = &mut stmt {
+                self.block(block);
+                if let Block::Loop { stmts: body, .. } = block.as_mut() {
+                    let assignments = assignments(body);
+                    optimized.extend(self.hoist_invariants(body, &assignments));
+                    optimized.extend(self.reduce_strength(body, &assignments));
+                }
+            }
+            optimized.push(stmt);
+        }
+        *stmts = optimized;
+    }
+
+    /// Replaces the loop-invariant expressions of a loop body by fresh locals, returns the
+    /// statements initializing those locals.
+    fn hoist_invariants(
+        &mut self,
+        body: &mut Vec<Statement>,
+        assignments: &HashMap<LocalId, usize>,
+    ) -> Vec<Statement> {
+        let invariants = invariant_expressions(body, assignments);
+        if invariants.is_empty() {
+            return Vec::new();
+        }
+
+        let mut preheader = Vec::new();
+        let mut new_body = Vec::with_capacity(body.len());
+        let mut invariants = invariants.into_iter().peekable();
+        for (idx, stmt) in body.drain(..).enumerate() {
+            match invariants.peek() {
+                Some(expr) if idx >= expr.start => {
+                    preheader.push(stmt);
+                    if idx + 1 == expr.end {
+                        let t = match &preheader[preheader.len() - 1] {
+                            Statement::Binop(binop) => binop.get_t(),
+                            Statement::Relop(relop) => relop.get_t(),
+                            Statement::Unop(unop) => unop.get_t(),
+                            _ => unreachable!("invariant expressions end with an operator"),
+                        };
+                        let local = self.fresh_local(t);
+                        preheader.push(Statement::Local(Local::Set(local)));
+                        new_body.push(Statement::Local(Local::Get(local)));
+                        invariants.next();
+                    }
+                }
+                _ => new_body.push(stmt),
+            }
+        }
+        *body = new_body;
+        preheader
+    }
+
+    /// Replaces the multiplications of induction variables by constants by fresh locals, returns
+    /// the statements initializing those locals.
+    fn reduce_strength(
+        &mut self,
+        body: &mut Vec<Statement>,
+        assignments: &HashMap<LocalId, usize>,
+    ) -> Vec<Statement> {
+        let mut induction_vars = HashMap::new();
+        for window in body.windows(4) {
+            if let [Statement::Local(Local::Get(x)), Statement::Const(step), Statement::Binop(binop), Statement::Local(Local::Set(y))] =
+                window
+            {
+                let is_induction = x == y
+                    && assignments.get(x) == Some(&1)
+                    && matches!(
+                        (step, binop),
+                        (Value::I32(_), Binop::I32Add)
+                            | (Value::I32(_), Binop::I32Sub)
+                            | (Value::I64(_), Binop::I64Add)
+                            | (Value::I64(_), Binop::I64Sub)
+                    );
+                if is_induction {
+                    let induction_var = InductionVariable {
+                        step: step.clone(),
+                        binop: binop.clone(),
+                    };
+                    induction_vars.insert(*x, induction_var);
+                }
+            }
+        }
+        if induction_vars.is_empty() {
+            return Vec::new();
+        }
+
+        // Replace the products, creating one local per (variable, factor) pair
+        let mut products = HashMap::new();
+        let mut preheader = Vec::new();
+        replace_products(body, &mut |x, factor| {
+            let induction_var = induction_vars.get(&x)?;
+            let key = (x, constant_bits(&factor));
+            if let Some((local, _)) = products.get(&key) {
+                return Some(*local);
+            }
+            let (t, mul) = match factor {
+                Value::I32(_) => (Type::I32, Binop::I32Mul),
+                _ => (Type::I64, Binop::I64Mul),
+            };
+            let local = self.fresh_local(t);
+            preheader.extend(vec![
+                Statement::Local(Local::Get(x)),
+                Statement::Const(factor.clone()),
+                Statement::Binop(mul),
+                Statement::Local(Local::Set(local)),
+            ]);
+            let step = match (&induction_var.step, factor) {
+                (Value::I32(step), Value::I32(factor)) => Value::I32(step.wrapping_mul(factor)),
+                (Value::I64(step), Value::I64(factor)) => Value::I64(step.wrapping_mul(factor)),
+                _ => unreachable!("factors have the type of the induction variable"),
+            };
+            products.insert(key, (local, step));
+            Some(local)
+        });
+
+        // Increment the products alongside their induction variables, from the last increment
+        // so that indices remain valid
+        let set_idx = |x| {
+            body.iter()
+                .position(|stmt| matches!(stmt, Statement::Local(Local::Set(y)) if *y == x))
+                .expect("induction variables are assigned in the loop body")
+        };
+        let mut increments = products
+            .into_iter()
+            .map(|((x, _), (local, step))| (set_idx(x), x, local, step))
+            .collect::<Vec<_>>();
+        increments.sort_by_key(|(set_idx, _, local, _)| (*set_idx, *local));
+        for (set_idx, x, local, step) in increments.into_iter().rev() {
+            let binop = induction_vars[&x].binop.clone();
+            let increment = vec![
+                Statement::Local(Local::Get(local)),
+                Statement::Const(step),
+                Statement::Binop(binop),
+                Statement::Local(Local::Set(local)),
+            ];
+            body.splice(set_idx + 1..set_idx + 1, increment);
+        }
+        preheader
+    }
+
+    fn fresh_local(&mut self, t: Type) -> LocalId {
+        let id = self.next_local;
+        self.next_local += 1;
+        self.locals.push(LocalVariable { id, t });
+        id
+    }
+}
+
+/// Returns the maximal loop-invariant expressions of a loop body, in order. Expressions made of
+/// a single statement are not returned, as there is nothing to gain by moving them.
+fn invariant_expressions(
+    body: &[Statement],
+    assignments: &HashMap<LocalId, usize>,
+) -> Vec<Operand> {
+    // The operands produced since the last statement with unknown effects on the stack, `None`
+    // for values which are not loop-invariant
+    let mut stack: Vec<Option<Operand>> = Vec::new();
+    let mut invariants = Vec::new();
+    let mut flush = |stack: &mut Vec<Option<Operand>>| {
+        invariants.extend(
+            stack
+                .drain(..)
+                .flatten()
+                .filter(|operand| operand.end - operand.start > 1),
+        );
+    };
+    for (idx, stmt) in body.iter().enumerate() {
+        let leaf = Some(Operand {
+            start: idx,
+            end: idx + 1,
+        });
+        match stmt {
+            Statement::Const(_) => stack.push(leaf),
+            Statement::Local(Local::Get(x)) if !assignments.contains_key(x) => stack.push(leaf),
+            Statement::Local(Local::Get(_)) => stack.push(None),
+            Statement::Unop(_) => match stack.pop().flatten() {
+                Some(operand) => stack.push(Some(Operand {
+                    start: operand.start,
+                    end: idx + 1,
+                })),
+                None => stack.push(None),
+            },
+            Statement::Binop(binop) if !may_trap(binop) => binary(&mut stack, idx, &mut flush),
+            Statement::Relop(_) => binary(&mut stack, idx, &mut flush),
+            _ => flush(&mut stack),
+        }
+    }
+    flush(&mut stack);
+    invariants.sort_by_key(|operand| operand.start);
+    invariants
+}
+
+/// Combines the two operands on top of the stack.
+fn binary(
+    stack: &mut Vec<Option<Operand>>,
+    idx: usize,
+    flush: &mut impl FnMut(&mut Vec<Option<Operand>>),
+) {
+    let right = stack.pop().flatten();
+    let left = stack.pop().flatten();
+    match (left, right) {
+        (Some(left), Some(_)) => stack.push(Some(Operand {
+            start: left.start,
+            end: idx + 1,
+        })),
+        (left, right) => {
+            let mut operands = vec![left, right];
+            flush(&mut operands);
+            stack.push(None);
+        }
+    }
+}
+
+/// Calls `replace` on each multiplication of a local by a constant, the multiplication is
+/// replaced by a `Local::Get` of the returned local, if any.
+fn replace_products(
+    stmts: &mut Vec<Statement>,
+    replace: &mut impl FnMut(LocalId, Value) -> Option<LocalId>,
+) {
+    let mut idx = 0;
+    while idx < stmts.len() {
+        let product = match &stmts[idx..] {
+            [Statement::Local(Local::Get(x)), Statement::Const(factor), Statement::Binop(binop), ..]
+            | [Statement::Const(factor), Statement::Local(Local::Get(x)), Statement::Binop(binop), ..] => {
+                match (factor, binop) {
+                    (Value::I32(_), Binop::I32Mul) | (Value::I64(_), Binop::I64Mul) => {
+                        Some((*x, factor.clone()))
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+        if let Some(local) = product.and_then(|(x, factor)| replace(x, factor)) {
+            stmts.splice(idx..idx + 3, vec![Statement::Local(Local::Get(local))]);
+        } else if let Statement::Block(block) = &mut stmts[idx] {
+            match block.as_mut() {
+                Block::Block { stmts, .. } | Block::Loop { stmts, .. } => {
+                    replace_products(stmts, replace)
+                }
+                Block::If {
+                    then_stmts,
+                    else_stmts,
+                    ..
+                } => {
+                    replace_products(then_stmts, replace);
+                    replace_products(else_stmts, replace);
+                }
+            }
+        }
+        idx += 1;
+    }
+}
+
+/// Returns the number of assignments of each local within some statements, including nested
+/// blocks.
+fn assignments(stmts: &[Statement]) -> HashMap<LocalId, usize> {
+    fn visit(stmts: &[Statement], assignments: &mut HashMap<LocalId, usize>) {
+        for stmt in stmts {
+            match stmt {
+                Statement::Local(Local::Set(x)) | Statement::Local(Local::Tee(x)) => {
+                    *assignments.entry(*x).or_insert(0) += 1
+                }
+                Statement::Block(block) => match block.as_ref() {
+                    Block::Block { stmts, .. } | Block::Loop { stmts, .. } => {
+                        visit(stmts, assignments)
+                    }
+                    Block::If {
+                        then_stmts,
+                        else_stmts,
+                        ..
+                    } => {
+                        visit(then_stmts, assignments);
+                        visit(else_stmts, assignments);
+                    }
+                },
+                _ => (),
+            }
+        }
+    }
+
+    let mut assignments = HashMap::new();
+    visit(stmts, &mut assignments);
+    assignments
+}
+
+/// Integer divisions and remainders trap when the divisor is zero.
+fn may_trap(binop: &Binop) -> bool {
+    matches!(
+        binop,
+        Binop::I32Div
+            | Binop::I32DivS
+            | Binop::I32Rem
+            | Binop::I64Div
+            | Binop::I64DivS
+            | Binop::I64Rem
+    )
+}
+
+fn constant_bits(value: &Value) -> i64 {
+    match value {
+        Value::I32(x) => *x as i64,
+        Value::I64(x) => *x,
+        _ => unreachable!("only integer constants are used as factors"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::DummyHandler;
+    use crate::hir::Identifier;
+    use crate::mir::RuntimeValue;
+
+    #[test]
+    fn optimize_loop() {
+        // Sums `4 * i + a + b` for i from 1 to 10.
+        let get = |x| Statement::Local(Local::Get(x));
+        let set = |x| Statement::Local(Local::Set(x));
+        let stmts = vec![
+            Statement::Block(Box::new(Block::Loop {
+                id: 1,
+                t: None,
+                stmts: vec![
+                    get(2),
+                    Statement::Const(Value::I32(1)),
+                    Statement::Binop(Binop::I32Add),
+                    set(2),
+                    get(3),
+                    get(2),
+                    Statement::Const(Value::I32(4)),
+                    Statement::Binop(Binop::I32Mul),
+                    Statement::Binop(Binop::I32Add),
+                    get(0),
+                    get(1),
+                    Statement::Binop(Binop::I32Add),
+                    Statement::Binop(Binop::I32Add),
+                    set(3),
+                    get(2),
+                    Statement::Const(Value::I32(10)),
+                    Statement::Relop(Relop::I32Lt),
+                    Statement::Control(Control::BrIf(1)),
+                ],
+            })),
+            get(3),
+        ];
+        let fun_id = FunId::new(0);
+        let fun = Function {
+            ident: String::from("test"),
+            params: vec![0, 1],
+            param_t: vec![Type::I32, Type::I32],
+            ret_t: vec![Type::I32],
+            locals: vec![
+                LocalVariable {
+                    id: 2,
+                    t: Type::I32,
+                },
+                LocalVariable {
+                    id: 3,
+                    t: Type::I32,
+                },
+            ],
+            body: Block::Block {
+                id: 0,
+                stmts,
+                t: None,
+            },
+            is_pub: true,
+            inline: Inline::Auto,
+            exposed: None,
+            fun_id,
+        };
+        let mut program = Program {
+            funs: vec![fun],
+            imports: Vec::new(),
+            data: HashMap::new(),
+            start: None,
+            memory: MemoryLimits::default(),
+            exports: ModuleExports::default(),
+            table: Vec::new(),
+            allocator: true,
+        };
+
+        optimize_loops(&mut program.funs[0]);
+        let stmts = match &program.funs[0].body {
+            Block::Block { stmts, .. } => stmts,
+            _ => panic!("Expected a block"),
+        };
+        let body = match stmts.iter().find_map(|stmt| match stmt {
+            Statement::Block(block) => Some(block.as_ref()),
+            _ => None,
+        }) {
+            Some(Block::Loop { stmts, .. }) => stmts,
+            _ => panic!("Expected a loop"),
+        };
+        assert_eq!(program.funs[0].locals.len(), 4);
+        assert!(!body
+            .iter()
+            .any(|stmt| matches!(stmt, Statement::Binop(Binop::I32Mul))));
+        assert_eq!(
+            body.iter()
+                .filter(|stmt| matches!(stmt, Statement::Binop(Binop::I32Add)))
+                .count(),
+            4
+        );
+
+        let mut err = DummyHandler::new();
+        let args = vec![RuntimeValue::I32(3), RuntimeValue::I32(4)];
+        let results = super::super::interpret(&program, fun_id, args, None, &mut err);
+        assert_eq!(results, Ok(vec![RuntimeValue::I32(290)]));
+    }
+}
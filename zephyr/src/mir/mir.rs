@@ -1,18 +1,104 @@
-#![allow(dead_code)] // Call::Indirect
+#![allow(dead_code)] // Not every instruction is emitted yet
 use std::collections::HashMap;
 use std::fmt;
 
 pub use crate::ast::ModuleKind;
 pub use crate::ctx::ModuleDeclarations;
-pub use crate::hir::{DataId, FunId, StructId};
+pub use crate::hir::{DataId, FunId, Inline, ModuleExports, StructId};
 
-pub type Data = Vec<u8>;
 pub type Offset = u32;
 
+/// Error code passed to the trap handler on integer divisions by zero.
+pub const TRAP_DIVISION_BY_ZERO: i32 = 1;
+
+/// Maximum number of memory pages, that is 4GiB.
+pub const MAX_MEMORY_PAGES: u32 = 0x10000;
+
+/// The global holding the address of the top of the shadow stack.
+///
+/// The shadow stack lives in the linear memory, between the static data and the memory managed
+/// by the allocator, and grows downward. Functions whose local variables need an address reserve
+/// a frame on it in their prologue and release it before returning.
+pub const STACK_POINTER: GlobalId = 0;
+
+/// Size of the shadow stack, in bytes.
+pub const STACK_SIZE: u32 = 0x2000;
+
 pub struct Program {
     pub funs: Vec<Function>,
     pub imports: Vec<Imports>,
     pub data: HashMap<DataId, Data>,
+    /// A function to run once the module is instantiated.
+    pub start: Option<FunId>,
+    pub memory: MemoryLimits,
+    pub exports: ModuleExports,
+    /// The functions which can be called indirectly, by their index in this table.
+    pub table: Vec<FunId>,
+    /// Whether the memory is initialized for the allocator of the runtime.
+    pub allocator: bool,
+}
+
+/// Static data, placed in a data segment.
+pub struct Data {
+    pub bytes: Vec<u8>,
+    /// Offsets in `bytes` at which the 32 bits address of another data must be written.
+    pub relocations: Vec<(Offset, DataId)>,
+}
+
+impl Data {
+    /// Returns the bytes of the data, with the addresses of the data it points to written given
+    /// the `offsets` of all data.
+    pub fn relocate(&self, offsets: &HashMap<DataId, Offset>) -> Result<Vec<u8>, String> {
+        let mut bytes = self.bytes.clone();
+        for (offset, data_id) in &self.relocations {
+            let address = offsets
+                .get(data_id)
+                .ok_or_else(|| format!("Can't relocate data: no data with id '{}'", data_id))?;
+            let offset = *offset as usize;
+            bytes[offset..(offset + 4)].copy_from_slice(&address.to_le_bytes());
+        }
+        Ok(bytes)
+    }
+}
+
+/// Limits of the linear memory, in pages.
+#[derive(Clone, Copy)]
+pub struct MemoryLimits {
+    pub initial: u32,
+    pub max: Option<u32>,
+    /// Whether the memory can be shared between threads, requires a maximum.
+    pub shared: bool,
+}
+
+impl Default for MemoryLimits {
+    fn default() -> Self {
+        Self {
+            initial: 1,
+            max: None,
+            shared: false,
+        }
+    }
+}
+
+impl MemoryLimits {
+    /// Checks that the limits describe a valid memory, the first page is needed by the memory
+    /// allocator.
+    pub fn check(&self) -> Result<(), String> {
+        if self.initial == 0 {
+            return Err(String::from("Memory must have at least one initial page"));
+        }
+        if self.initial > MAX_MEMORY_PAGES || self.max.unwrap_or(0) > MAX_MEMORY_PAGES {
+            return Err(format!("Memory can not exceed {} pages", MAX_MEMORY_PAGES));
+        }
+        match self.max {
+            Some(max) if max < self.initial => Err(format!(
+                "Maximum memory ({} pages) is smaller than the initial memory ({} pages)",
+                max, self.initial
+            )),
+            None if self.shared => Err(String::from("Shared memory requires a maximum size")),
+            _ => Ok(()),
+        }
+    }
 }
 
 pub struct Imports {
@@ -28,6 +114,7 @@ pub struct Function {
     pub locals: Vec<LocalVariable>,
     pub body: Block,
     pub is_pub: bool,
+    pub inline: Inline,
     pub exposed: Option<String>,
     pub fun_id: FunId,
 }
@@ -82,7 +169,9 @@ pub struct LocalVariable {
 
 pub type BasicBlockId = usize;
 pub type LocalId = usize;
+pub type GlobalId = u32;
 
+#[derive(Clone, PartialEq)]
 pub enum Block {
     Block {
         id: BasicBlockId,
@@ -102,6 +191,7 @@ pub enum Block {
     },
 }
 
+#[derive(Clone, PartialEq)]
 pub enum Statement {
     Local(Local),
     Const(Value),
@@ -113,18 +203,42 @@ pub enum Statement {
     Call(Call),
     Parametric(Parametric),
     Memory(Memory),
+    Global(Global),
+    Simd(Simd),
+    Atomic(Atomic),
+    /// Bytes emitted as is into the body of the function, their effect on the stack is unknown.
+    Raw(Vec<u8>),
 }
 
+#[derive(Clone, PartialEq)]
 pub enum Local {
     Get(LocalId),
     Set(LocalId),
+    /// Sets a local, leaving the value on the stack.
+    Tee(LocalId),
 }
 
+#[derive(Clone, PartialEq)]
+pub enum Global {
+    Get(GlobalId),
+    Set(GlobalId),
+}
+
+#[derive(Clone, PartialEq)]
 pub enum Call {
     Direct(FunId),
-    Indirect(),
+    /// Calls the function whose table index is on top of the stack, below its arguments.
+    Indirect(Signature),
+}
+
+/// The type of a function called indirectly.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Signature {
+    pub params: Vec<Type>,
+    pub ret_t: Vec<Type>,
 }
 
+#[derive(Clone, PartialEq)]
 pub enum Control {
     Return,
     Unreachable,
@@ -132,20 +246,68 @@ pub enum Control {
     BrIf(BasicBlockId),
 }
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq)]
 pub enum Value {
     I32(i32),
     I64(i64),
     F32(f32),
     F64(f64),
+    /// A 128 bits vector, lanes are stored in little endian order.
+    V128(u128),
     DataPointer(DataId),
+    /// The index of a function in the table, known once the table is laid out.
+    TableIndex(FunId),
 }
 
+#[derive(Clone, PartialEq)]
 pub enum Unop {
+    /// Count of leading zero bits.
+    I32Clz,
+    /// Count of trailing zero bits.
+    I32Ctz,
+    /// Count of bits set to one.
+    I32Popcnt,
+
+    I64Clz,
+    I64Ctz,
+    I64Popcnt,
+
     F32Neg,
+    F32Abs,
+    F32Sqrt,
+    F32Ceil,
+    F32Floor,
+    F32Trunc,
+    /// Rounds to the nearest integer, ties to even.
+    F32Nearest,
+
     F64Neg,
+    F64Abs,
+    F64Sqrt,
+    F64Ceil,
+    F64Floor,
+    F64Trunc,
+    F64Nearest,
+
+    /// Truncates a float towards zero, saturating to the bounds of the integer type instead of
+    /// trapping. NaN is converted to 0.
+    I32TruncSatF32S,
+    I32TruncSatF32U,
+    I32TruncSatF64S,
+    I32TruncSatF64U,
+    I64TruncSatF32S,
+    I64TruncSatF32U,
+    I64TruncSatF64S,
+    I64TruncSatF64U,
+
+    /// Reinterprets the bits of the operand as a value of another type of the same width.
+    I32ReinterpretF32,
+    I64ReinterpretF64,
+    F32ReinterpretI32,
+    F64ReinterpretI64,
 }
 
+#[derive(Clone, PartialEq)]
 pub enum Binop {
     I32Xor,
     I32Or,
@@ -154,6 +316,8 @@ pub enum Binop {
     I32Sub,
     I32Mul,
     I32Div,
+    /// Signed division, `I32Div` is unsigned.
+    I32DivS,
     I32Rem,
 
     I64Xor,
@@ -163,19 +327,29 @@ pub enum Binop {
     I64Sub,
     I64Mul,
     I64Div,
+    /// Signed division, `I64Div` is unsigned.
+    I64DivS,
     I64Rem,
 
     F32Add,
     F32Sub,
     F32Mul,
     F32Div,
+    F32Min,
+    F32Max,
+    /// The first operand with the sign of the second.
+    F32Copysign,
 
     F64Add,
     F64Sub,
     F64Mul,
     F64Div,
+    F64Min,
+    F64Max,
+    F64Copysign,
 }
 
+#[derive(Clone, PartialEq)]
 pub enum Relop {
     I32Eq,
     I32Ne,
@@ -211,10 +385,12 @@ pub enum Logical {
     Or,
 }
 
+#[derive(Clone, PartialEq)]
 pub enum Parametric {
     Drop,
 }
 
+#[derive(Clone, PartialEq)]
 pub enum Memory {
     Size,
     Grow,
@@ -230,16 +406,152 @@ pub enum Memory {
     I64Store { align: u32, offset: u32 },
     F32Store { align: u32, offset: u32 },
     F64Store { align: u32, offset: u32 },
+    V128Load { align: u32, offset: u32 },
+    V128Store { align: u32, offset: u32 },
     Nop,
 }
 
-/// Wasm types as they appear on the stack.
+/// The interpretation of a 128 bits vector as a sequence of lanes.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum Shape {
+    I8x16,
+    I16x8,
+    I32x4,
+    I64x2,
+    F32x4,
+    F64x2,
+}
+
+/// Operations on 128 bits vectors, the lane indexes are immediates.
+#[derive(Clone, PartialEq)]
+pub enum Simd {
+    Splat(Shape),
+    /// Extracts a lane, the lanes of `I8x16` and `I16x8` are zero-extended.
+    ExtractLane(Shape, u8),
+    ReplaceLane(Shape, u8),
+    Add(Shape),
+    Sub(Shape),
+    Mul(Shape),
+    Div(Shape),
+    Not,
+    And,
+    Or,
+    Xor,
+}
+
+/// Atomic accesses to the linear memory, `t` is either `I32` or `I64` and the accesses always use
+/// the natural alignment of `t`.
+#[derive(Clone, PartialEq)]
+pub enum Atomic {
+    Load {
+        t: Type,
+        offset: u32,
+    },
+    Store {
+        t: Type,
+        offset: u32,
+    },
+    /// Applies the operation to the value in memory and returns the previous value.
+    Rmw {
+        op: RmwOp,
+        t: Type,
+        offset: u32,
+    },
+    /// Replaces the value in memory if it equals the expected one, returns the previous value.
+    Cmpxchg {
+        t: Type,
+        offset: u32,
+    },
+    /// Wakes up to a given number of threads waiting on the address, returns how many woke up.
+    Notify {
+        offset: u32,
+    },
+    /// Suspends the thread until notified if the memory holds the expected value, or until the
+    /// timeout (in nanoseconds, negative for none) expires.
+    Wait {
+        t: Type,
+        offset: u32,
+    },
+    Fence,
+}
+
+/// The read-modify-write atomic operations.
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum RmwOp {
+    Add,
+    Sub,
+    And,
+    Or,
+    Xor,
+    Xchg,
+}
+
+impl Program {
+    /// Returns the size of the shadow stack, which is only reserved if a function uses it.
+    pub fn stack_size(&self) -> u32 {
+        let mut used = false;
+        for fun in &self.funs {
+            fun.body.visit_statements(&mut |stmt| {
+                if let Statement::Global(_) = stmt {
+                    used = true;
+                }
+            });
+        }
+        if used {
+            STACK_SIZE
+        } else {
+            0
+        }
+    }
+}
+
+impl Block {
+    /// Calls `f` on each statement of the block and of its nested blocks.
+    pub fn visit_statements(&self, f: &mut impl FnMut(&Statement)) {
+        let (stmts, else_stmts) = match self {
+            Block::Block { stmts, .. } | Block::Loop { stmts, .. } => (stmts, None),
+            Block::If {
+                then_stmts,
+                else_stmts,
+                ..
+            } => (then_stmts, Some(else_stmts)),
+        };
+        for stmt in stmts.iter().chain(else_stmts.into_iter().flatten()) {
+            f(stmt);
+            if let Statement::Block(block) = stmt {
+                block.visit_statements(f);
+            }
+        }
+    }
+
+    /// Calls `f` on each statement of the block and of its nested blocks, a statement holding a
+    /// block is visited before the statements of that block.
+    pub fn visit_statements_mut(&mut self, f: &mut impl FnMut(&mut Statement)) {
+        let (stmts, else_stmts) = match self {
+            Block::Block { stmts, .. } | Block::Loop { stmts, .. } => (stmts, None),
+            Block::If {
+                then_stmts,
+                else_stmts,
+                ..
+            } => (then_stmts, Some(else_stmts)),
+        };
+        for stmt in stmts.iter_mut().chain(else_stmts.into_iter().flatten()) {
+            f(stmt);
+            if let Statement::Block(block) = stmt {
+                block.visit_statements_mut(f);
+            }
+        }
+    }
+}
+
+/// Wasm types as they appear on the stack.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
 pub enum Type {
     I32,
     I64,
     F32,
     F64,
+    V128,
 }
 
 impl Type {
@@ -249,6 +561,7 @@ impl Type {
             Type::I64 => MemoryLayout::I64,
             Type::F32 => MemoryLayout::F32,
             Type::F64 => MemoryLayout::F64,
+            Type::V128 => MemoryLayout::V128,
         }
     }
 }
@@ -265,6 +578,7 @@ pub enum MemoryLayout {
     I64,
     F32,
     F64,
+    V128,
 }
 
 impl MemoryLayout {
@@ -277,6 +591,48 @@ impl MemoryLayout {
             MemoryLayout::I64 => 8,
             MemoryLayout::F32 => 4,
             MemoryLayout::F64 => 8,
+            MemoryLayout::V128 => 16,
+        }
+    }
+}
+
+impl Unop {
+    /// Return the type produced as the result of the execution of this unop, which is also the
+    /// type of its operand unless the unop is a conversion.
+    pub fn get_t(&self) -> Type {
+        match self {
+            Unop::I32Clz
+            | Unop::I32Ctz
+            | Unop::I32Popcnt
+            | Unop::I32TruncSatF32S
+            | Unop::I32TruncSatF32U
+            | Unop::I32TruncSatF64S
+            | Unop::I32TruncSatF64U
+            | Unop::I32ReinterpretF32 => Type::I32,
+            Unop::I64Clz
+            | Unop::I64Ctz
+            | Unop::I64Popcnt
+            | Unop::I64TruncSatF32S
+            | Unop::I64TruncSatF32U
+            | Unop::I64TruncSatF64S
+            | Unop::I64TruncSatF64U
+            | Unop::I64ReinterpretF64 => Type::I64,
+            Unop::F32Neg
+            | Unop::F32Abs
+            | Unop::F32Sqrt
+            | Unop::F32Ceil
+            | Unop::F32Floor
+            | Unop::F32Trunc
+            | Unop::F32Nearest
+            | Unop::F32ReinterpretI32 => Type::F32,
+            Unop::F64Neg
+            | Unop::F64Abs
+            | Unop::F64Sqrt
+            | Unop::F64Ceil
+            | Unop::F64Floor
+            | Unop::F64Trunc
+            | Unop::F64Nearest
+            | Unop::F64ReinterpretI64 => Type::F64,
         }
     }
 }
@@ -292,6 +648,7 @@ impl Binop {
             Binop::I32Sub => Type::I32,
             Binop::I32Mul => Type::I32,
             Binop::I32Div => Type::I32,
+            Binop::I32DivS => Type::I32,
             Binop::I32Rem => Type::I32,
 
             Binop::I64Xor => Type::I64,
@@ -301,17 +658,24 @@ impl Binop {
             Binop::I64Sub => Type::I64,
             Binop::I64Mul => Type::I64,
             Binop::I64Div => Type::I64,
+            Binop::I64DivS => Type::I64,
             Binop::I64Rem => Type::I64,
 
             Binop::F32Add => Type::F32,
             Binop::F32Sub => Type::F32,
             Binop::F32Mul => Type::F32,
             Binop::F32Div => Type::F32,
+            Binop::F32Min => Type::F32,
+            Binop::F32Max => Type::F32,
+            Binop::F32Copysign => Type::F32,
 
             Binop::F64Add => Type::F64,
             Binop::F64Sub => Type::F64,
             Binop::F64Mul => Type::F64,
             Binop::F64Div => Type::F64,
+            Binop::F64Min => Type::F64,
+            Binop::F64Max => Type::F64,
+            Binop::F64Copysign => Type::F64,
         }
     }
 }
@@ -351,8 +715,159 @@ impl Relop {
     }
 }
 
+impl Shape {
+    pub const ALL: [Shape; 6] = [
+        Shape::I8x16,
+        Shape::I16x8,
+        Shape::I32x4,
+        Shape::I64x2,
+        Shape::F32x4,
+        Shape::F64x2,
+    ];
+
+    /// The name of the shape, as used in instruction names.
+    pub fn name(self) -> &'static str {
+        match self {
+            Shape::I8x16 => "i8x16",
+            Shape::I16x8 => "i16x8",
+            Shape::I32x4 => "i32x4",
+            Shape::I64x2 => "i64x2",
+            Shape::F32x4 => "f32x4",
+            Shape::F64x2 => "f64x2",
+        }
+    }
+
+    /// Returns the shape with the given name, if any.
+    pub fn from_name(name: &str) -> Option<Shape> {
+        Shape::ALL
+            .iter()
+            .copied()
+            .find(|shape| shape.name() == name)
+    }
+
+    /// The number of lanes of the shape.
+    pub fn lanes(self) -> u8 {
+        match self {
+            Shape::I8x16 => 16,
+            Shape::I16x8 => 8,
+            Shape::I32x4 | Shape::F32x4 => 4,
+            Shape::I64x2 | Shape::F64x2 => 2,
+        }
+    }
+
+    /// The type of a lane once on the stack.
+    pub fn lane_t(self) -> Type {
+        match self {
+            Shape::I8x16 | Shape::I16x8 | Shape::I32x4 => Type::I32,
+            Shape::I64x2 => Type::I64,
+            Shape::F32x4 => Type::F32,
+            Shape::F64x2 => Type::F64,
+        }
+    }
+
+    pub fn is_float(self) -> bool {
+        matches!(self, Shape::F32x4 | Shape::F64x2)
+    }
+}
+
+impl Simd {
+    /// Return the types of the operands of the operation.
+    pub fn get_params(&self) -> Vec<Type> {
+        match self {
+            Simd::Splat(shape) => vec![shape.lane_t()],
+            Simd::ExtractLane(_, _) | Simd::Not => vec![Type::V128],
+            Simd::ReplaceLane(shape, _) => vec![Type::V128, shape.lane_t()],
+            Simd::Add(_)
+            | Simd::Sub(_)
+            | Simd::Mul(_)
+            | Simd::Div(_)
+            | Simd::And
+            | Simd::Or
+            | Simd::Xor => vec![Type::V128, Type::V128],
+        }
+    }
+
+    /// Return the type produced as the result of the execution of the operation.
+    pub fn get_t(&self) -> Type {
+        match self {
+            Simd::ExtractLane(shape, _) => shape.lane_t(),
+            _ => Type::V128,
+        }
+    }
+}
+
+impl Atomic {
+    /// Return the types of the operands of the operation.
+    pub fn get_params(&self) -> Vec<Type> {
+        match self {
+            Atomic::Load { .. } => vec![Type::I32],
+            Atomic::Store { t, .. } | Atomic::Rmw { t, .. } => vec![Type::I32, *t],
+            Atomic::Cmpxchg { t, .. } => vec![Type::I32, *t, *t],
+            Atomic::Notify { .. } => vec![Type::I32, Type::I32],
+            Atomic::Wait { t, .. } => vec![Type::I32, *t, Type::I64],
+            Atomic::Fence => Vec::new(),
+        }
+    }
+
+    /// Return the types produced as the result of the execution of the operation.
+    pub fn get_t(&self) -> Vec<Type> {
+        match self {
+            Atomic::Load { t, .. } | Atomic::Rmw { t, .. } | Atomic::Cmpxchg { t, .. } => vec![*t],
+            Atomic::Notify { .. } | Atomic::Wait { .. } => vec![Type::I32],
+            Atomic::Store { .. } | Atomic::Fence => Vec::new(),
+        }
+    }
+
+    /// The alignment of the access as a power of two, which is the size of the value.
+    pub fn align(&self) -> u32 {
+        match self {
+            Atomic::Load { t: Type::I64, .. }
+            | Atomic::Store { t: Type::I64, .. }
+            | Atomic::Rmw { t: Type::I64, .. }
+            | Atomic::Cmpxchg { t: Type::I64, .. }
+            | Atomic::Wait { t: Type::I64, .. } => 3,
+            _ => 2,
+        }
+    }
+
+    /// The offset of the access, added to the address operand.
+    pub fn offset(&self) -> u32 {
+        match self {
+            Atomic::Load { offset, .. }
+            | Atomic::Store { offset, .. }
+            | Atomic::Rmw { offset, .. }
+            | Atomic::Cmpxchg { offset, .. }
+            | Atomic::Notify { offset }
+            | Atomic::Wait { offset, .. } => *offset,
+            Atomic::Fence => 0,
+        }
+    }
+}
+
+impl RmwOp {
+    pub const ALL: [RmwOp; 6] = [
+        RmwOp::Add,
+        RmwOp::Sub,
+        RmwOp::And,
+        RmwOp::Or,
+        RmwOp::Xor,
+        RmwOp::Xchg,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            RmwOp::Add => "add",
+            RmwOp::Sub => "sub",
+            RmwOp::And => "and",
+            RmwOp::Or => "or",
+            RmwOp::Xor => "xor",
+            RmwOp::Xchg => "xchg",
+        }
+    }
+}
+
 /// Possible aligments, in bytes (A8 -> aligment of 8)
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq, Eq)]
 pub enum Alignment {
     A8,
     A4,
@@ -475,6 +990,13 @@ impl fmt::Display for Statement {
             Statement::Call(call) => write!(f, "{}", call),
             Statement::Const(val) => write!(f, "{}", val),
             Statement::Memory(mem) => write!(f, "{}", mem),
+            Statement::Global(global) => write!(f, "{}", global),
+            Statement::Simd(simd) => write!(f, "{}", simd),
+            Statement::Atomic(atomic) => write!(f, "{}", atomic),
+            Statement::Raw(bytes) => {
+                let bytes: Vec<String> = bytes.iter().map(|b| format!("{:#04x}", b)).collect();
+                write!(f, "raw {}", bytes.join(" "))
+            }
         }
     }
 }
@@ -484,6 +1006,16 @@ impl fmt::Display for Local {
         match self {
             Local::Get(l_id) => write!(f, "local.get {}", l_id),
             Local::Set(l_id) => write!(f, "local.set {}", l_id),
+            Local::Tee(l_id) => write!(f, "local.tee {}", l_id),
+        }
+    }
+}
+
+impl fmt::Display for Global {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Global::Get(g_id) => write!(f, "global.get {}", g_id),
+            Global::Set(g_id) => write!(f, "global.set {}", g_id),
         }
     }
 }
@@ -495,7 +1027,9 @@ impl fmt::Display for Value {
             Value::I64(x) => write!(f, "i64.const {}", x),
             Value::F32(x) => write!(f, "f32.const {}", x),
             Value::F64(x) => write!(f, "f64.const {}", x),
+            Value::V128(x) => write!(f, "v128.const {:#034x}", x),
             Value::DataPointer(x) => write!(f, "i32.const ptr:{}", x),
+            Value::TableIndex(x) => write!(f, "i32.const fun:{}", x),
         }
     }
 }
@@ -503,8 +1037,38 @@ impl fmt::Display for Value {
 impl fmt::Display for Unop {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
+            Unop::I32Clz => write!(f, "i32.clz"),
+            Unop::I32Ctz => write!(f, "i32.ctz"),
+            Unop::I32Popcnt => write!(f, "i32.popcnt"),
+            Unop::I64Clz => write!(f, "i64.clz"),
+            Unop::I64Ctz => write!(f, "i64.ctz"),
+            Unop::I64Popcnt => write!(f, "i64.popcnt"),
             Unop::F32Neg => write!(f, "f32.ne"),
+            Unop::F32Abs => write!(f, "f32.abs"),
+            Unop::F32Sqrt => write!(f, "f32.sqrt"),
+            Unop::F32Ceil => write!(f, "f32.ceil"),
+            Unop::F32Floor => write!(f, "f32.floor"),
+            Unop::F32Trunc => write!(f, "f32.trunc"),
+            Unop::F32Nearest => write!(f, "f32.nearest"),
             Unop::F64Neg => write!(f, "f64.ne"),
+            Unop::F64Abs => write!(f, "f64.abs"),
+            Unop::F64Sqrt => write!(f, "f64.sqrt"),
+            Unop::F64Ceil => write!(f, "f64.ceil"),
+            Unop::F64Floor => write!(f, "f64.floor"),
+            Unop::F64Trunc => write!(f, "f64.trunc"),
+            Unop::F64Nearest => write!(f, "f64.nearest"),
+            Unop::I32TruncSatF32S => write!(f, "i32.trunc_sat_f32_s"),
+            Unop::I32TruncSatF32U => write!(f, "i32.trunc_sat_f32_u"),
+            Unop::I32TruncSatF64S => write!(f, "i32.trunc_sat_f64_s"),
+            Unop::I32TruncSatF64U => write!(f, "i32.trunc_sat_f64_u"),
+            Unop::I64TruncSatF32S => write!(f, "i64.trunc_sat_f32_s"),
+            Unop::I64TruncSatF32U => write!(f, "i64.trunc_sat_f32_u"),
+            Unop::I64TruncSatF64S => write!(f, "i64.trunc_sat_f64_s"),
+            Unop::I64TruncSatF64U => write!(f, "i64.trunc_sat_f64_u"),
+            Unop::I32ReinterpretF32 => write!(f, "i32.reinterpret_f32"),
+            Unop::I64ReinterpretF64 => write!(f, "i64.reinterpret_f64"),
+            Unop::F32ReinterpretI32 => write!(f, "f32.reinterpret_i32"),
+            Unop::F64ReinterpretI64 => write!(f, "f64.reinterpret_i64"),
         }
     }
 }
@@ -519,6 +1083,7 @@ impl fmt::Display for Binop {
             Binop::I32Sub => write!(f, "i32.sub"),
             Binop::I32Mul => write!(f, "i32.mul"),
             Binop::I32Div => write!(f, "i32.div"),
+            Binop::I32DivS => write!(f, "i32.div_s"),
             Binop::I32Rem => write!(f, "i32.rem"),
 
             Binop::I64Xor => write!(f, "i64.xor"),
@@ -528,17 +1093,24 @@ impl fmt::Display for Binop {
             Binop::I64Sub => write!(f, "i64.sub"),
             Binop::I64Mul => write!(f, "i64.mul"),
             Binop::I64Div => write!(f, "i64.div"),
+            Binop::I64DivS => write!(f, "i64.div_s"),
             Binop::I64Rem => write!(f, "i64.rem"),
 
             Binop::F32Add => write!(f, "f32.add"),
             Binop::F32Sub => write!(f, "f32.sub"),
             Binop::F32Mul => write!(f, "f32.mul"),
             Binop::F32Div => write!(f, "f32.div"),
+            Binop::F32Min => write!(f, "f32.min"),
+            Binop::F32Max => write!(f, "f32.max"),
+            Binop::F32Copysign => write!(f, "f32.copysign"),
 
             Binop::F64Add => write!(f, "f64.add"),
             Binop::F64Sub => write!(f, "f64.sub"),
             Binop::F64Mul => write!(f, "f64.mul"),
             Binop::F64Div => write!(f, "f64.div"),
+            Binop::F64Min => write!(f, "f64.min"),
+            Binop::F64Max => write!(f, "f64.max"),
+            Binop::F64Copysign => write!(f, "f64.copysign"),
         }
     }
 }
@@ -600,11 +1172,24 @@ impl fmt::Display for Call {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Call::Direct(id) => write!(f, "call {}", id),
-            Call::Indirect() => write!(f, "call_indirect"),
+            Call::Indirect(signature) => write!(f, "call_indirect {}", signature),
         }
     }
 }
 
+impl fmt::Display for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let types = |types: &Vec<Type>| {
+            types
+                .iter()
+                .map(|t| format!("{}", t))
+                .collect::<Vec<String>>()
+                .join(", ")
+        };
+        write!(f, "({}) -> ({})", types(&self.params), types(&self.ret_t))
+    }
+}
+
 impl fmt::Display for LocalVariable {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "    _{}\n", self.id)
@@ -618,6 +1203,7 @@ impl fmt::Display for Type {
             Type::I64 => write!(f, "i64"),
             Type::F32 => write!(f, "f32"),
             Type::F64 => write!(f, "f64"),
+            Type::V128 => write!(f, "v128"),
         }
     }
 }
@@ -639,7 +1225,55 @@ impl fmt::Display for Memory {
             Memory::I64Store { align, offset } => write!(f, "i64.store {}, {}", align, offset),
             Memory::F32Store { align, offset } => write!(f, "f32.store {}, {}", align, offset),
             Memory::F64Store { align, offset } => write!(f, "f64.store {}, {}", align, offset),
+            Memory::V128Load { align, offset } => write!(f, "v128.load {}, {}", align, offset),
+            Memory::V128Store { align, offset } => write!(f, "v128.store {}, {}", align, offset),
             Memory::Nop => write!(f, "nop"),
         }
     }
 }
+
+impl fmt::Display for Atomic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Atomic::Load { t, offset } => write!(f, "{}.atomic.load {}", t, offset),
+            Atomic::Store { t, offset } => write!(f, "{}.atomic.store {}", t, offset),
+            Atomic::Rmw { op, t, offset } => {
+                write!(f, "{}.atomic.rmw.{} {}", t, op.name(), offset)
+            }
+            Atomic::Cmpxchg { t, offset } => write!(f, "{}.atomic.rmw.cmpxchg {}", t, offset),
+            Atomic::Notify { offset } => write!(f, "memory.atomic.notify {}", offset),
+            Atomic::Wait { t, offset } => match t {
+                Type::I64 => write!(f, "memory.atomic.wait64 {}", offset),
+                _ => write!(f, "memory.atomic.wait32 {}", offset),
+            },
+            Atomic::Fence => write!(f, "atomic.fence"),
+        }
+    }
+}
+
+impl fmt::Display for Shape {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl fmt::Display for Simd {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Simd::Splat(shape) => write!(f, "{}.splat", shape),
+            Simd::ExtractLane(shape, lane) => match shape {
+                Shape::I8x16 | Shape::I16x8 => write!(f, "{}.extract_lane_u {}", shape, lane),
+                _ => write!(f, "{}.extract_lane {}", shape, lane),
+            },
+            Simd::ReplaceLane(shape, lane) => write!(f, "{}.replace_lane {}", shape, lane),
+            Simd::Add(shape) => write!(f, "{}.add", shape),
+            Simd::Sub(shape) => write!(f, "{}.sub", shape),
+            Simd::Mul(shape) => write!(f, "{}.mul", shape),
+            Simd::Div(shape) => write!(f, "{}.div", shape),
+            Simd::Not => write!(f, "v128.not"),
+            Simd::And => write!(f, "v128.and"),
+            Simd::Or => write!(f, "v128.or"),
+            Simd::Xor => write!(f, "v128.xor"),
+        }
+    }
+}
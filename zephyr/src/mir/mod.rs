@@ -1,29 +1,139 @@
-use crate::ctx::{Ctx, KnownFunctions};
+use log::debug;
+use std::time::Instant;
+
+use crate::ctx::{Ctx, Target};
 use crate::error::ErrorHandler;
+use crate::ssa;
 
 pub use mir::*;
 
+mod backend;
+mod component;
 mod hir_to_mir;
+mod inline;
+mod interpreter;
+mod loops;
 mod mir;
+mod peephole;
+mod size;
 
+pub use backend::{emit, Backend};
+pub use component::REALLOC_EXPORT;
+pub use interpreter::RuntimeValue;
 pub use mir::Program;
 
+/// Options controlling the lowering of HIR to MIR.
+#[derive(Default, Clone)]
+pub struct LoweringOptions {
+    /// Trap on signed integer overflows instead of wrapping around.
+    pub overflow_checks: bool,
+    /// A function called with an error code before trapping on integer divisions by zero, it must
+    /// take a single `i32` and return nothing.
+    pub trap_handler: Option<FunId>,
+    /// A function called in place of the default panic handler of `core`, it must take a single
+    /// `Str` and return nothing.
+    pub panic_handler: Option<FunId>,
+    /// Limits of the linear memory.
+    pub memory: MemoryLimits,
+    /// Export names of the memory and the function table.
+    pub exports: ModuleExports,
+    /// Functions with at most that many statements are inlined into their callers, `0` disables
+    /// inlining except for the functions marked `#[inline]`.
+    pub inline_threshold: usize,
+    /// Run optimization passes over the MIR.
+    pub optimize: bool,
+    /// Reduce the size of the module: merge identical functions and remove the functions,
+    /// imports and data left unused by the other optimizations.
+    pub opt_size: bool,
+    /// Expose functions following the canonical ABI of the component model, the memory allocator
+    /// must be part of the roots.
+    pub component: bool,
+    /// How the memory of heap objects is reclaimed.
+    pub gc: GcStrategy,
+    /// The conventions of the host, such as the name of the entry point.
+    pub target: Target,
+    /// The function marked `#[entry]`, if any.
+    pub entry: Option<FunId>,
+    /// Whether the runtime is available, heap allocations are reported as errors otherwise.
+    pub runtime: bool,
+}
+
+/// Strategies to reclaim the memory of heap objects.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum GcStrategy {
+    /// Heap objects are never freed.
+    #[default]
+    None,
+    /// The compiler maintains a count of the references to each struct, which is freed along with
+    /// its last reference. Cycles and raw buffers allocated with `malloc` are never freed.
+    ReferenceCounting,
+}
+
+/// Size of the header holding the reference count in front of structs, see `core.mem` for the
+/// layout.
+pub const RC_HEADER_SIZE: u32 = 8;
+
+/// Lowers the HIR of the context to MIR, starting from the exposed functions and the functions in
+/// `roots`.
 pub fn to_mir(
     ctx: &Ctx,
-    known_funs: &KnownFunctions,
+    roots: &[FunId],
+    options: LoweringOptions,
     error_handler: &mut impl ErrorHandler,
-    verbose: bool,
-) -> mir::Program {
-    if verbose {
-        println!("\n/// MIR Production ///\n");
+) -> Result<mir::Program, ()> {
+    debug!("\n/// MIR Production ///\n");
+
+    let start = Instant::now();
+    let mut mir = hir_to_mir::MirProducer::lower(ctx, roots, options.clone(), error_handler);
+    ctx.record_pass("lowering", None, start);
+    if !error_handler.has_error() {
+        let start = Instant::now();
+        inline::inline(&mut mir, options.inline_threshold, roots);
+        ctx.record_pass("inline", None, start);
+    }
+    if options.optimize && !error_handler.has_error() {
+        debug!("\n/// SSA Optimizations ///\n");
+        let signatures = ssa::signatures(&mir);
+        for fun in &mut mir.funs {
+            let start = Instant::now();
+            loops::optimize_loops(fun);
+            ctx.record_pass("loops", Some(&fun.ident), start);
+            let start = Instant::now();
+            ssa::optimize(fun, &signatures);
+            ctx.record_pass("ssa", Some(&fun.ident), start);
+            let start = Instant::now();
+            peephole::optimize_peepholes(fun);
+            ctx.record_pass("peephole", Some(&fun.ident), start);
+        }
+    }
+    if options.opt_size && !error_handler.has_error() {
+        let start = Instant::now();
+        size::optimize_size(&mut mir, roots);
+        ctx.record_pass("size", None, start);
     }
 
-    let mir = hir_to_mir::MirProducer::lower(ctx, known_funs, error_handler);
+    debug!("{}", mir);
 
-    if verbose {
-        println!("{}", mir);
+    if error_handler.has_error() {
+        return Err(());
     }
+    Ok(mir)
+}
 
-    error_handler.flush_and_exit_if_err();
-    mir
+/// Calls a function of a MIR program with the interpreter and returns its results.
+///
+/// If some `fuel` is given, the execution traps after executing that many statements.
+pub fn interpret(
+    mir_program: &mir::Program,
+    fun_id: FunId,
+    args: Vec<RuntimeValue>,
+    fuel: Option<u64>,
+    error_handler: &mut impl ErrorHandler,
+) -> Result<Vec<RuntimeValue>, ()> {
+    let mut interpreter = interpreter::Interpreter::new(mir_program, error_handler);
+    interpreter.set_fuel(fuel);
+    if let Some(start) = mir_program.start {
+        interpreter.call(start, Vec::new())?;
+    }
+    interpreter.call(fun_id, args)
 }
@@ -0,0 +1,169 @@
+//! Peephole optimizations
+//!
+//! Short sequences of statements are rewritten into cheaper equivalents:
+//!
+//! - `const 0` followed by an addition, subtraction, `or` or `xor` is removed.
+//! - `local.set x` followed by `local.get x` becomes `local.tee x`, and `local.tee x` followed by
+//!   `drop` becomes `local.set x`.
+//! - A constant or a `local.get` immediately dropped is removed.
+//! - A comparison followed by `const 1` and `xor` becomes the inverse comparison.
+//!
+//! Rewrites are applied at the end of the already optimized sequence, so that a rewrite can
+//! expose a new pattern with the statements that precede it.
+
+use super::mir::*;
+
+/// Applies peephole optimizations to a function.
+pub fn optimize_peepholes(fun: &mut Function) {
+    block(&mut fun.body);
+}
+
+fn block(block: &mut Block) {
+    match block {
+        Block::Block { stmts, .. } | Block::Loop { stmts, .. } => statements(stmts),
+        Block::If {
+            then_stmts,
+            else_stmts,
+            ..
+        } => {
+            statements(then_stmts);
+            statements(else_stmts);
+        }
+    }
+}
+
+fn statements(stmts: &mut Vec<Statement>) {
+    let mut optimized = Vec::with_capacity(stmts.len());
+    for mut stmt in stmts.drain(..) {
+        if let Statement::Block(b) = &mut stmt {
+            block(b);
+        }
+        optimized.push(stmt);
+        while rewrite(&mut optimized) {}
+    }
+    *stmts = optimized;
+}
+
+/// Rewrites the last statements of `stmts`, returns true if a rewrite happened.
+fn rewrite(stmts: &mut Vec<Statement>) -> bool {
+    let len = stmts.len();
+    match stmts.as_slice() {
+        [.., Statement::Const(Value::I32(0)), Statement::Binop(Binop::I32Add | Binop::I32Sub | Binop::I32Or | Binop::I32Xor)]
+        | [.., Statement::Const(Value::I64(0)), Statement::Binop(Binop::I64Add | Binop::I64Sub | Binop::I64Or | Binop::I64Xor)]
+        | [.., Statement::Const(_) | Statement::Local(Local::Get(_)), Statement::Parametric(Parametric::Drop)] =>
+        {
+            stmts.truncate(len - 2);
+        }
+        [.., Statement::Local(Local::Set(x)), Statement::Local(Local::Get(y))] if x == y => {
+            let x = *x;
+            stmts.truncate(len - 2);
+            stmts.push(Statement::Local(Local::Tee(x)));
+        }
+        [.., Statement::Local(Local::Tee(x)), Statement::Parametric(Parametric::Drop)] => {
+            let x = *x;
+            stmts.truncate(len - 2);
+            stmts.push(Statement::Local(Local::Set(x)));
+        }
+        [.., Statement::Relop(relop), Statement::Const(Value::I32(1)), Statement::Binop(Binop::I32Xor)] =>
+        {
+            let relop = match invert(relop) {
+                Some(relop) => relop,
+                None => return false,
+            };
+            stmts.truncate(len - 3);
+            stmts.push(Statement::Relop(relop));
+        }
+        _ => return false,
+    }
+    true
+}
+
+/// Returns the comparison producing the opposite result, if any.
+///
+/// Ordered float comparisons are false when an operand is NaN, so only equality can be inverted.
+fn invert(relop: &Relop) -> Option<Relop> {
+    let inverse = match relop {
+        Relop::I32Eq => Relop::I32Ne,
+        Relop::I32Ne => Relop::I32Eq,
+        Relop::I32Lt => Relop::I32Ge,
+        Relop::I32Gt => Relop::I32Le,
+        Relop::I32Le => Relop::I32Gt,
+        Relop::I32Ge => Relop::I32Lt,
+        Relop::I64Eq => Relop::I64Ne,
+        Relop::I64Ne => Relop::I64Eq,
+        Relop::I64Lt => Relop::I64Ge,
+        Relop::I64Gt => Relop::I64Le,
+        Relop::I64Le => Relop::I64Gt,
+        Relop::I64Ge => Relop::I64Lt,
+        Relop::F32Eq => Relop::F32Ne,
+        Relop::F32Ne => Relop::F32Eq,
+        Relop::F64Eq => Relop::F64Ne,
+        Relop::F64Ne => Relop::F64Eq,
+        _ => return None,
+    };
+    Some(inverse)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn optimize(stmts: Vec<Statement>) -> String {
+        let mut block = Block::Block {
+            id: 0,
+            stmts,
+            t: None,
+        };
+        super::block(&mut block);
+        format!("{}", block)
+    }
+
+    #[test]
+    fn peepholes() {
+        let get = |x| Statement::Local(Local::Get(x));
+        let set = |x| Statement::Local(Local::Set(x));
+        let drop = || Statement::Parametric(Parametric::Drop);
+        let stmts = vec![
+            get(0),
+            Statement::Const(Value::I32(0)),
+            Statement::Binop(Binop::I32Add),
+            set(1),
+            get(1),
+            get(0),
+            Statement::Relop(Relop::I32Lt),
+            Statement::Const(Value::I32(1)),
+            Statement::Binop(Binop::I32Xor),
+            set(2),
+            get(0),
+            get(1),
+            drop(),
+            drop(),
+            set(0),
+            get(0),
+            drop(),
+        ];
+        let expected = optimize(vec![
+            get(0),
+            Statement::Local(Local::Tee(1)),
+            get(0),
+            Statement::Relop(Relop::I32Ge),
+            set(2),
+            set(0),
+        ]);
+        assert_eq!(optimize(stmts), expected);
+    }
+
+    #[test]
+    fn float_comparisons() {
+        let stmts = vec![
+            Statement::Local(Local::Get(0)),
+            Statement::Local(Local::Get(1)),
+            Statement::Relop(Relop::F64Lt),
+            Statement::Const(Value::I32(1)),
+            Statement::Binop(Binop::I32Xor),
+        ];
+        let optimized = optimize(stmts);
+        assert!(optimized.contains("f64.lt"));
+        assert!(optimized.contains("i32.xor"));
+    }
+}
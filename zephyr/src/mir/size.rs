@@ -0,0 +1,343 @@
+//! Size Optimizations
+//!
+//! Passes run when optimizing for size, once the other optimizations are done:
+//!
+//! - Functions with identical bodies are merged, calls and table entries are redirected to the
+//!   first function with that body. Local and block IDs are unique across the program, so bodies
+//!   are compared once their IDs are renumbered in order of appearance.
+//! - Functions, imports and data which can not be reached from the exposed functions, the start
+//!   function or the roots are removed, as are the table entries which are never referenced.
+//! - Locals are sorted by type, so that the encoder declares them in as few groups as possible.
+
+use std::collections::{HashMap, HashSet};
+
+use super::mir::*;
+
+/// Optimizes the program for size, the functions in `roots` are kept as if they were exposed.
+pub fn optimize_size(program: &mut Program, roots: &[FunId]) {
+    merge_duplicates(program, roots);
+    remove_dead_code(program, roots);
+    for fun in &mut program.funs {
+        fun.locals.sort_by_key(|local| type_rank(local.t));
+    }
+}
+
+/// Merges the functions with identical bodies. Merging functions can make their callers
+/// identical, so this is repeated until no duplicate remains.
+fn merge_duplicates(program: &mut Program, roots: &[FunId]) {
+    loop {
+        let mut shapes: HashMap<_, Vec<(FunId, Shape)>> = HashMap::new();
+        let mut merged = HashMap::new();
+        for fun in &program.funs {
+            let shape = Shape::new(fun);
+            let key = (fun.param_t.clone(), fun.ret_t.clone(), shape.locals.len());
+            let candidates = shapes.entry(key).or_default();
+            let is_removable = fun.exposed.is_none() && !roots.contains(&fun.fun_id);
+            match candidates.iter().find(|(_, other)| *other == shape) {
+                Some((original, _)) if is_removable => {
+                    merged.insert(fun.fun_id, *original);
+                }
+                _ => candidates.push((fun.fun_id, shape)),
+            }
+        }
+        if merged.is_empty() {
+            return;
+        }
+
+        let redirect = |fun_id: &mut FunId| {
+            if let Some(original) = merged.get(fun_id) {
+                *fun_id = *original;
+            }
+        };
+        program.funs.retain(|fun| !merged.contains_key(&fun.fun_id));
+        for fun in &mut program.funs {
+            fun.body.visit_statements_mut(&mut |stmt| match stmt {
+                Statement::Call(Call::Direct(fun_id))
+                | Statement::Const(Value::TableIndex(fun_id)) => redirect(fun_id),
+                _ => (),
+            });
+        }
+        program.table.iter_mut().for_each(&redirect);
+        program.table.sort();
+        program.table.dedup();
+        if let Some(start) = &mut program.start {
+            redirect(start);
+        }
+    }
+}
+
+/// A function body with its local and block IDs renumbered in order of appearance, two functions
+/// with the same shape and signature are interchangeable.
+#[derive(PartialEq)]
+struct Shape {
+    locals: Vec<Type>,
+    body: Block,
+}
+
+impl Shape {
+    fn new(fun: &Function) -> Self {
+        let mut locals = HashMap::new();
+        let ids = fun
+            .params
+            .iter()
+            .chain(fun.locals.iter().map(|local| &local.id));
+        for (idx, id) in ids.enumerate() {
+            locals.insert(*id, idx);
+        }
+        let mut blocks = HashMap::new();
+        let mut body = fun.body.clone();
+        renumber(block_id(&mut body), &mut blocks);
+        body.visit_statements_mut(&mut |stmt| match stmt {
+            Statement::Block(block) => renumber(block_id(block), &mut blocks),
+            Statement::Control(Control::Br(id)) | Statement::Control(Control::BrIf(id)) => {
+                renumber(id, &mut blocks)
+            }
+            Statement::Local(Local::Get(id))
+            | Statement::Local(Local::Set(id))
+            | Statement::Local(Local::Tee(id)) => renumber(id, &mut locals),
+            _ => (),
+        });
+        Self {
+            locals: fun.locals.iter().map(|local| local.t).collect(),
+            body,
+        }
+    }
+}
+
+/// Replaces an ID by its number, IDs seen for the first time get the next number.
+fn renumber(id: &mut usize, numbers: &mut HashMap<usize, usize>) {
+    let next = numbers.len();
+    *id = *numbers.entry(*id).or_insert(next);
+}
+
+fn block_id(block: &mut Block) -> &mut BasicBlockId {
+    match block {
+        Block::Block { id, .. } | Block::Loop { id, .. } | Block::If { id, .. } => id,
+    }
+}
+
+/// The functions, table entries and data used by a function.
+#[derive(Default)]
+struct Uses {
+    funs: Vec<FunId>,
+    table: Vec<FunId>,
+    data: Vec<DataId>,
+}
+
+impl Uses {
+    fn new(fun: &Function) -> Self {
+        let mut uses = Self::default();
+        fun.body.visit_statements(&mut |stmt| match stmt {
+            Statement::Call(Call::Direct(fun_id)) => uses.funs.push(*fun_id),
+            Statement::Const(Value::TableIndex(fun_id)) => uses.table.push(*fun_id),
+            Statement::Const(Value::DataPointer(data_id)) => uses.data.push(*data_id),
+            _ => (),
+        });
+        uses
+    }
+}
+
+/// Removes the functions, imports, table entries and data not reachable from the exposed
+/// functions, the start function or the roots. The whole table is kept if it is exported.
+fn remove_dead_code(program: &mut Program, roots: &[FunId]) {
+    let uses = program
+        .funs
+        .iter()
+        .map(|fun| (fun.fun_id, Uses::new(fun)))
+        .collect::<HashMap<_, _>>();
+    let mut to_visit = program
+        .funs
+        .iter()
+        .filter(|fun| fun.exposed.is_some())
+        .map(|fun| fun.fun_id)
+        .chain(program.start)
+        .chain(roots.iter().copied())
+        .collect::<Vec<_>>();
+    if program.exports.table.is_some() {
+        to_visit.extend(program.table.iter().copied());
+    }
+    let mut reachable = HashSet::new();
+    let mut table = HashSet::new();
+    let mut data = Vec::new();
+    while let Some(fun_id) = to_visit.pop() {
+        if !reachable.insert(fun_id) {
+            continue;
+        }
+        if let Some(uses) = uses.get(&fun_id) {
+            to_visit.extend(uses.funs.iter().chain(uses.table.iter()).copied());
+            table.extend(uses.table.iter().copied());
+            data.extend(uses.data.iter().copied());
+        }
+    }
+
+    program.funs.retain(|fun| reachable.contains(&fun.fun_id));
+    if program.exports.table.is_none() {
+        program.table.retain(|fun_id| table.contains(fun_id));
+    }
+    for imports in &mut program.imports {
+        imports
+            .prototypes
+            .retain(|proto| reachable.contains(&proto.fun_id));
+    }
+    program
+        .imports
+        .retain(|imports| !imports.prototypes.is_empty());
+
+    // Data can hold the address of other data
+    let mut used_data = HashSet::new();
+    while let Some(data_id) = data.pop() {
+        if used_data.insert(data_id) {
+            if let Some(content) = program.data.get(&data_id) {
+                data.extend(content.relocations.iter().map(|(_, data_id)| *data_id));
+            }
+        }
+    }
+    program
+        .data
+        .retain(|data_id, _| used_data.contains(data_id));
+}
+
+fn type_rank(t: Type) -> u8 {
+    match t {
+        Type::I32 => 0,
+        Type::I64 => 1,
+        Type::F32 => 2,
+        Type::F64 => 3,
+        Type::V128 => 4,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hir::Identifier;
+
+    /// A function taking a single local `param`, the ID of its body is `param` as well.
+    fn function(fun_id: FunId, param: LocalId, stmts: Vec<Statement>) -> Function {
+        Function {
+            ident: String::from("test"),
+            params: vec![param],
+            param_t: vec![Type::I32],
+            ret_t: vec![Type::I32],
+            locals: Vec::new(),
+            body: Block::Block {
+                id: param,
+                stmts,
+                t: None,
+            },
+            is_pub: false,
+            inline: Inline::Auto,
+            exposed: None,
+            fun_id,
+        }
+    }
+
+    fn program(funs: Vec<Function>) -> Program {
+        Program {
+            funs,
+            imports: Vec::new(),
+            data: HashMap::new(),
+            start: None,
+            memory: MemoryLimits::default(),
+            exports: ModuleExports::default(),
+            table: Vec::new(),
+            allocator: true,
+        }
+    }
+
+    #[test]
+    fn merge_identical_functions() {
+        // `double_1` and `double_2` differ only by their local IDs, `call_1` and `call_2` become
+        // identical once they call the same function.
+        let ids: Vec<FunId> = (0..5).map(FunId::new).collect();
+        let double = |fun_id: FunId, param: LocalId| {
+            let stmts = vec![
+                Statement::Local(Local::Get(param)),
+                Statement::Local(Local::Get(param)),
+                Statement::Binop(Binop::I32Add),
+            ];
+            function(fun_id, param, stmts)
+        };
+        let call = |fun_id: FunId, param: LocalId, callee: FunId| {
+            let stmts = vec![
+                Statement::Local(Local::Get(param)),
+                Statement::Call(Call::Direct(callee)),
+            ];
+            function(fun_id, param, stmts)
+        };
+        let mut main = function(
+            ids[0],
+            0,
+            vec![
+                Statement::Local(Local::Get(0)),
+                Statement::Call(Call::Direct(ids[3])),
+                Statement::Call(Call::Direct(ids[4])),
+            ],
+        );
+        main.exposed = Some(String::from("main"));
+        let mut program = program(vec![
+            main,
+            double(ids[1], 1),
+            double(ids[2], 2),
+            call(ids[3], 3, ids[1]),
+            call(ids[4], 4, ids[2]),
+        ]);
+
+        optimize_size(&mut program, &[]);
+        let funs: Vec<FunId> = program.funs.iter().map(|fun| fun.fun_id).collect();
+        assert_eq!(funs, vec![ids[0], ids[1], ids[3]]);
+        assert_eq!(Uses::new(&program.funs[0]).funs, vec![ids[3], ids[3]]);
+    }
+
+    #[test]
+    fn remove_unused_imports_and_data() {
+        let ids: Vec<FunId> = (0..4).map(FunId::new).collect();
+        let data_ids: Vec<DataId> = (0..3).map(DataId::new).collect();
+        let mut main = function(
+            ids[0],
+            0,
+            vec![
+                Statement::Const(Value::DataPointer(data_ids[0])),
+                Statement::Call(Call::Direct(ids[1])),
+            ],
+        );
+        main.exposed = Some(String::from("main"));
+        let unused = function(
+            ids[2],
+            1,
+            vec![
+                Statement::Const(Value::DataPointer(data_ids[2])),
+                Statement::Call(Call::Direct(ids[3])),
+            ],
+        );
+        let mut program = program(vec![main, unused]);
+        for (from, fun_id) in [("env", ids[1]), ("unused", ids[3])] {
+            program.imports.push(Imports {
+                from: String::from(from),
+                prototypes: vec![FunctionPrototype {
+                    ident: String::from("log"),
+                    param_t: vec![Type::I32],
+                    ret_t: vec![Type::I32],
+                    alias: None,
+                    is_pub: false,
+                    fun_id,
+                }],
+            });
+        }
+        for (data_id, relocations) in [
+            (data_ids[0], vec![(0, data_ids[1])]),
+            (data_ids[1], Vec::new()),
+            (data_ids[2], Vec::new()),
+        ] {
+            let bytes = vec![0; 4];
+            program.data.insert(data_id, Data { bytes, relocations });
+        }
+
+        optimize_size(&mut program, &[]);
+        assert_eq!(program.funs.len(), 1);
+        assert_eq!(program.imports.len(), 1);
+        assert_eq!(program.imports[0].from, "env");
+        assert!(program.data.contains_key(&data_ids[1]));
+        assert!(!program.data.contains_key(&data_ids[2]));
+    }
+}
@@ -0,0 +1,1131 @@
+//! MIR to native code
+//!
+//! Functions are lowered to Cranelift IR and compiled for the host into an object file. The
+//! conventions of the wasm backend are preserved: pointers are 32 bits offsets into a linear
+//! memory, which is exported as the `zephyr_memory` symbol and initialized with the data segments
+//! and the allocator metadata. The size of the memory is fixed to its initial number of pages,
+//! growing it always fails. The stack pointer of the shadow stack, a wasm global, is kept in a
+//! symbol local to the object.
+//!
+//! Exposed functions and imports keep their name and use the default calling convention of the
+//! host, other functions are local to the object. The start function, if any, is exported as
+//! `zephyr_init` and must be called by the host before any other function.
+
+use std::collections::HashMap;
+
+use cranelift_codegen::entity::EntityRef;
+use cranelift_codegen::ir::condcodes::{FloatCC, IntCC};
+use cranelift_codegen::ir::{
+    types, AbiParam, AtomicRmwOp, Block, Endianness, FuncRef, GlobalValue, InstBuilder, MemFlags,
+    Signature, TrapCode, UserFuncName, Value,
+};
+use cranelift_codegen::{settings, settings::Configurable, Context};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Variable};
+use cranelift_module::{default_libcall_names, DataDescription, FuncId, Linkage, Module};
+use cranelift_object::{ObjectBuilder, ObjectModule};
+
+use crate::error::ErrorHandler;
+use crate::hir;
+use crate::mir;
+
+/// Size of a page of linear memory, in bytes.
+const PAGE_SIZE: u32 = 0x10000;
+/// Name of the symbol holding the linear memory.
+const MEMORY_SYMBOL: &str = "zephyr_memory";
+/// Name of the start function.
+const INIT_SYMBOL: &str = "zephyr_init";
+/// Name of the symbol holding the stack pointer.
+const STACK_POINTER_SYMBOL: &str = "zephyr_stack_pointer";
+/// Trap code for `unreachable`.
+const TRAP_UNREACHABLE: u8 = 1;
+/// Trap code for misaligned atomic memory accesses.
+const TRAP_MISALIGNED: u8 = 2;
+
+type OffsetMap = HashMap<hir::DataId, mir::Offset>;
+
+/// Returns a module producing object files for the host.
+pub fn object_module() -> Result<ObjectModule, String> {
+    let mut flags = settings::builder();
+    // Objects are likely to be linked into position independent executables.
+    flags
+        .set("is_pic", "true")
+        .and_then(|_| flags.set("opt_level", "speed"))
+        .map_err(|e| e.to_string())?;
+    let isa = cranelift_native::builder()
+        .map_err(|e| format!("Unsupported host: {}", e))?
+        .finish(settings::Flags::new(flags))
+        .map_err(|e| format!("Unsupported host: {}", e))?;
+    let builder = ObjectBuilder::new(isa, "zephyr", default_libcall_names())
+        .map_err(|e| format!("Unsupported host: {}", e))?;
+    Ok(ObjectModule::new(builder))
+}
+
+/// Convert MIR to a native object file.
+pub struct Compiler<'err, E: ErrorHandler> {
+    err: &'err mut E,
+    module: ObjectModule,
+    ctx: Context,
+    builder_ctx: FunctionBuilderContext,
+    funs: HashMap<hir::FunId, FuncId>,
+    offsets: OffsetMap,
+    /// Available once the module-level items have been emitted.
+    memory: Option<cranelift_module::DataId>,
+    memory_size: u64,
+    /// The data holding the stack pointer, if the shadow stack is used.
+    stack_pointer: Option<cranelift_module::DataId>,
+}
+
+impl<'err, E: ErrorHandler> Compiler<'err, E> {
+    pub fn new(module: ObjectModule, error_handler: &'err mut E) -> Self {
+        Compiler {
+            err: error_handler,
+            ctx: module.make_context(),
+            module,
+            builder_ctx: FunctionBuilderContext::new(),
+            funs: HashMap::new(),
+            offsets: HashMap::new(),
+            memory: None,
+            memory_size: 0,
+            stack_pointer: None,
+        }
+    }
+
+    /// Lays out the data and the shadow stack in memory the same way the wasm backend does, and
+    /// returns the initial content of the memory along with the initial stack pointer.
+    fn initialize_memory(
+        &mut self,
+        mir_data: &HashMap<mir::DataId, mir::Data>,
+        pages: u32,
+        stack_size: u32,
+    ) -> (Vec<u8>, mir::Offset) {
+        // The first bytes are reserved by the allocator.
+        let mut offset: mir::Offset = 8;
+        // Data are laid out by ID for reproducible builds
+        let mut mir_data = mir_data.iter().collect::<Vec<_>>();
+        mir_data.sort_by_key(|(data_id, _)| *data_id);
+        for (data_id, data) in &mir_data {
+            self.offsets.insert(**data_id, offset);
+            // Maintain an offset such that an aligment of 8 is always guaranteed.
+            offset += (data.bytes.len() as mir::Offset).div_ceil(8) * 8;
+        }
+        // The shadow stack follows the data and grows downward
+        offset += stack_size.div_ceil(8) * 8;
+        let stack_pointer = offset;
+        let size = pages as u64 * PAGE_SIZE as u64;
+        if offset as u64 + 8 > size {
+            self.err.report_no_loc(format!(
+                "Static data ({} bytes) does not fit in the initial memory ({} pages)",
+                offset + 8,
+                pages
+            ));
+            return (Vec::new(), stack_pointer);
+        }
+
+        let mut memory = vec![0; size as usize];
+        for (data_id, data) in &mir_data {
+            let start = self.offsets[data_id] as usize;
+            match data.relocate(&self.offsets) {
+                Ok(bytes) => memory[start..(start + bytes.len())].copy_from_slice(&bytes),
+                Err(err) => self.err.report_internal_no_loc(err),
+            }
+        }
+
+        let offset = offset as usize;
+        let first_block_offset = offset + 4;
+        let block_size = memory.len() - (first_block_offset + 4);
+        // mem[0..4] - address of the first block
+        memory[0..4].copy_from_slice(&(first_block_offset as u32).to_le_bytes());
+        // mem[offset..(offset + 4)] - mocked block footer with allocated bit set
+        memory[offset..(offset + 4)].copy_from_slice(&0xffffffff_u32.to_le_bytes());
+        // mem[(offset + 4)..(offset + 8)] - first block header (its size)
+        memory[first_block_offset..(first_block_offset + 4)]
+            .copy_from_slice(&(block_size as u32).to_le_bytes());
+        (memory, stack_pointer)
+    }
+
+    fn define_memory(
+        &mut self,
+        description: &DataDescription,
+    ) -> Result<cranelift_module::DataId, String> {
+        let memory_id = self
+            .module
+            .declare_data(MEMORY_SYMBOL, Linkage::Export, true, false)
+            .map_err(|e| e.to_string())?;
+        self.module
+            .define_data(memory_id, description)
+            .map_err(|e| e.to_string())?;
+        Ok(memory_id)
+    }
+
+    fn define_stack_pointer(&mut self, value: u32) -> Result<cranelift_module::DataId, String> {
+        let stack_pointer_id = self
+            .module
+            .declare_data(STACK_POINTER_SYMBOL, Linkage::Local, true, false)
+            .map_err(|e| e.to_string())?;
+        let mut description = DataDescription::new();
+        description.define(Box::new(value.to_le_bytes()));
+        description.set_align(4);
+        self.module
+            .define_data(stack_pointer_id, &description)
+            .map_err(|e| e.to_string())?;
+        Ok(stack_pointer_id)
+    }
+
+    fn signature(&self, param_t: &[mir::Type], ret_t: &[mir::Type]) -> Signature {
+        let mut signature = self.module.make_signature();
+        for t in param_t {
+            signature.params.push(AbiParam::new(native_t(*t)));
+        }
+        for t in ret_t {
+            signature.returns.push(AbiParam::new(native_t(*t)));
+        }
+        signature
+    }
+
+    fn declare_function(&mut self, name: &str, linkage: Linkage, signature: &Signature) -> FuncId {
+        match self.module.declare_function(name, linkage, signature) {
+            Ok(func_id) => func_id,
+            Err(e) => {
+                self.err
+                    .report_internal_no_loc(format!("Could not declare '{}': {}", name, e));
+                // The error is reported, any ID will do
+                FuncId::new(0)
+            }
+        }
+    }
+}
+
+impl<'err, E: ErrorHandler> mir::Backend for Compiler<'err, E> {
+    type Output = Vec<u8>;
+
+    fn emit_module(&mut self, program: &mir::Program) {
+        let stack_size = program.stack_size();
+        let (memory, stack_pointer) =
+            self.initialize_memory(&program.data, program.memory.initial, stack_size);
+        self.memory_size = memory.len() as u64;
+        let mut description = DataDescription::new();
+        description.define(memory.into_boxed_slice());
+        description.set_align(8);
+        match self.define_memory(&description) {
+            Ok(memory_id) => self.memory = Some(memory_id),
+            Err(e) => self
+                .err
+                .report_internal_no_loc(format!("Could not define the memory: {}", e)),
+        }
+        if stack_size > 0 {
+            match self.define_stack_pointer(stack_pointer) {
+                Ok(stack_pointer_id) => self.stack_pointer = Some(stack_pointer_id),
+                Err(e) => self
+                    .err
+                    .report_internal_no_loc(format!("Could not define the stack pointer: {}", e)),
+            }
+        }
+
+        for proto in program.imports.iter().flat_map(|import| &import.prototypes) {
+            let signature = self.signature(&proto.param_t, &proto.ret_t);
+            let func_id = self.declare_function(&proto.ident, Linkage::Import, &signature);
+            self.funs.insert(proto.fun_id, func_id);
+        }
+        for fun in &program.funs {
+            let signature = self.signature(&fun.param_t, &fun.ret_t);
+            let (name, linkage) = match &fun.exposed {
+                Some(name) => (name.clone(), Linkage::Export),
+                None if program.start == Some(fun.fun_id) => {
+                    (String::from(INIT_SYMBOL), Linkage::Export)
+                }
+                None => (format!("{}.{}", fun.ident, fun.fun_id), Linkage::Local),
+            };
+            let func_id = self.declare_function(&name, linkage, &signature);
+            self.funs.insert(fun.fun_id, func_id);
+        }
+    }
+
+    fn emit_function(&mut self, fun: mir::Function) {
+        let (func_id, memory_id) = match (self.funs.get(&fun.fun_id), self.memory) {
+            (Some(func_id), Some(memory_id)) => (*func_id, memory_id),
+            _ => {
+                self.err.report_internal_no_loc(String::from(
+                    "Functions must be emitted after the module",
+                ));
+                return;
+            }
+        };
+        let mut synchronizes = false;
+        fun.body.visit_statements(&mut |stmt| {
+            if let mir::Statement::Atomic(mir::Atomic::Notify { .. } | mir::Atomic::Wait { .. }) =
+                stmt
+            {
+                synchronizes = true;
+            }
+        });
+        if synchronizes {
+            self.err.report_no_loc(format!(
+                "Function '{}': atomic wait and notify are not supported by the native target",
+                fun.ident
+            ));
+            return;
+        }
+        self.ctx.func.signature = self.signature(&fun.param_t, &fun.ret_t);
+        self.ctx.func.name = UserFuncName::user(0, func_id.as_u32());
+        let memory = self
+            .module
+            .declare_data_in_func(memory_id, &mut self.ctx.func);
+        let stack_pointer = self
+            .stack_pointer
+            .map(|id| self.module.declare_data_in_func(id, &mut self.ctx.func));
+        let translator = FunctionTranslator {
+            builder: FunctionBuilder::new(&mut self.ctx.func, &mut self.builder_ctx),
+            module: &mut self.module,
+            funs: &self.funs,
+            offsets: &self.offsets,
+            fun_refs: HashMap::new(),
+            variables: HashMap::new(),
+            memory,
+            memory_size: self.memory_size,
+            stack_pointer,
+            stack: Vec::new(),
+            frames: Vec::new(),
+        };
+        if let Err(e) = translator.function(&fun) {
+            self.err
+                .report_internal_no_loc(format!("Function '{}': {}", fun.ident, e));
+        } else if let Err(e) = self.module.define_function(func_id, &mut self.ctx) {
+            self.err.report_internal_no_loc(format!(
+                "Could not compile function '{}': {:?}",
+                fun.ident, e
+            ));
+        }
+        self.module.clear_context(&mut self.ctx);
+    }
+
+    fn finish(self) -> Result<Vec<u8>, ()> {
+        if self.err.has_error() {
+            return Err(());
+        }
+        match self.module.finish().emit() {
+            Ok(object) => Ok(object),
+            Err(e) => {
+                self.err
+                    .report_internal_no_loc(format!("Could not emit object file: {}", e));
+                Err(())
+            }
+        }
+    }
+}
+
+/// A block that can be targeted by branches.
+struct Frame {
+    id: mir::BasicBlockId,
+    /// The Cranelift block branches jump to.
+    target: Block,
+    /// Number of values passed to the target.
+    nb_args: usize,
+    /// Whether a branch targets the frame.
+    reached: bool,
+}
+
+/// Translates the structured stack machine of the MIR to Cranelift IR, values on the MIR stack
+/// are tracked as SSA values.
+///
+/// Translation methods return whether the code following the translated item is reachable,
+/// unreachable code is not translated.
+struct FunctionTranslator<'a, 'b> {
+    builder: FunctionBuilder<'a>,
+    module: &'b mut ObjectModule,
+    funs: &'b HashMap<hir::FunId, FuncId>,
+    offsets: &'b OffsetMap,
+    fun_refs: HashMap<hir::FunId, FuncRef>,
+    variables: HashMap<mir::LocalId, Variable>,
+    memory: GlobalValue,
+    memory_size: u64,
+    stack_pointer: Option<GlobalValue>,
+    stack: Vec<Value>,
+    frames: Vec<Frame>,
+}
+
+impl<'a, 'b> FunctionTranslator<'a, 'b> {
+    fn function(mut self, fun: &mir::Function) -> Result<(), String> {
+        let entry = self.builder.create_block();
+        self.builder.append_block_params_for_function_params(entry);
+        self.builder.switch_to_block(entry);
+        self.builder.seal_block(entry);
+        let params = self.builder.block_params(entry).to_vec();
+        for ((param, t), value) in fun.params.iter().zip(&fun.param_t).zip(params) {
+            let var = self.declare_var(*param, *t);
+            self.builder.def_var(var, value);
+        }
+        for local in &fun.locals {
+            let var = self.declare_var(local.id, local.t);
+            let zero = self.zero(local.t);
+            self.builder.def_var(var, zero);
+        }
+
+        // Branching to the body of the function returns from it.
+        let exit = self.builder.create_block();
+        for t in &fun.ret_t {
+            self.builder.append_block_param(exit, native_t(*t));
+        }
+        let (id, stmts) = match &fun.body {
+            mir::Block::Block { id, stmts, .. } => (*id, stmts),
+            _ => return Err(String::from("the body must be a block")),
+        };
+        self.frames.push(Frame {
+            id,
+            target: exit,
+            nb_args: fun.ret_t.len(),
+            reached: false,
+        });
+        if self.statements(stmts)? {
+            let results = self.pop_n(fun.ret_t.len())?;
+            self.builder.ins().jump(exit, &results);
+        }
+        self.builder.switch_to_block(exit);
+        self.builder.seal_block(exit);
+        let results = self.builder.block_params(exit).to_vec();
+        self.builder.ins().return_(&results);
+        self.builder.finalize();
+        Ok(())
+    }
+
+    fn declare_var(&mut self, l_id: mir::LocalId, t: mir::Type) -> Variable {
+        let var = Variable::new(self.variables.len());
+        self.builder.declare_var(var, native_t(t));
+        self.variables.insert(l_id, var);
+        var
+    }
+
+    fn statements(&mut self, stmts: &[mir::Statement]) -> Result<bool, String> {
+        for stmt in stmts {
+            if !self.statement(stmt)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn block(&mut self, block: &mir::Block) -> Result<bool, String> {
+        let height = self.stack.len();
+        match block {
+            mir::Block::Block { id, stmts, t } => {
+                let next = self.block_with_result(t);
+                self.push_frame(*id, next, t);
+                let reachable = self.statements(stmts)?;
+                self.exit_frame(reachable, false, height, next)
+            }
+            mir::Block::Loop { id, stmts, t } => {
+                let header = self.builder.create_block();
+                let next = self.block_with_result(t);
+                self.builder.ins().jump(header, &[]);
+                self.builder.switch_to_block(header);
+                self.push_frame(*id, header, &None);
+                let reachable = self.statements(stmts)?;
+                if reachable {
+                    let results = self.pop_n(t.iter().count())?;
+                    self.builder.ins().jump(next, &results);
+                }
+                self.frames.pop();
+                // All the branches to the header are known by now
+                self.builder.seal_block(header);
+                self.stack.truncate(height);
+                self.switch_to(next, reachable)
+            }
+            mir::Block::If {
+                id,
+                then_stmts,
+                else_stmts,
+                t,
+            } => {
+                let condition = self.pop()?;
+                let then_block = self.builder.create_block();
+                let else_block = self.builder.create_block();
+                let next = self.block_with_result(t);
+                self.builder
+                    .ins()
+                    .brif(condition, then_block, &[], else_block, &[]);
+                self.builder.seal_block(then_block);
+                self.builder.seal_block(else_block);
+                let height = self.stack.len();
+                self.push_frame(*id, next, t);
+
+                self.builder.switch_to_block(then_block);
+                let then_reachable = self.statements(then_stmts)?;
+                if then_reachable {
+                    let results = self.pop_n(t.iter().count())?;
+                    self.builder.ins().jump(next, &results);
+                }
+                self.stack.truncate(height);
+                self.builder.switch_to_block(else_block);
+                let else_reachable = self.statements(else_stmts)?;
+                self.exit_frame(else_reachable, then_reachable, height, next)
+            }
+        }
+    }
+
+    fn block_with_result(&mut self, t: &Option<mir::Type>) -> Block {
+        let block = self.builder.create_block();
+        if let Some(t) = t {
+            self.builder.append_block_param(block, native_t(*t));
+        }
+        block
+    }
+
+    fn push_frame(&mut self, id: mir::BasicBlockId, target: Block, t: &Option<mir::Type>) {
+        self.frames.push(Frame {
+            id,
+            target,
+            nb_args: t.iter().count(),
+            reached: false,
+        });
+    }
+
+    /// Leaves the innermost frame, whose target is `next`, falling through to it if `reachable`.
+    ///
+    /// `reached` tells whether `next` is reached from elsewhere, such as the other branch of an
+    /// `if`.
+    fn exit_frame(
+        &mut self,
+        reachable: bool,
+        reached: bool,
+        height: usize,
+        next: Block,
+    ) -> Result<bool, String> {
+        let frame = self.frames.pop().ok_or("no frame to exit")?;
+        if reachable {
+            let results = self.pop_n(frame.nb_args)?;
+            self.builder.ins().jump(next, &results);
+        }
+        self.stack.truncate(height);
+        self.switch_to(next, reachable || reached || frame.reached)
+    }
+
+    /// Continues the translation in `block` if it is reachable, its parameters are pushed on
+    /// the stack.
+    fn switch_to(&mut self, block: Block, reachable: bool) -> Result<bool, String> {
+        if reachable {
+            self.builder.switch_to_block(block);
+            self.builder.seal_block(block);
+            self.stack.extend(self.builder.block_params(block).to_vec());
+        }
+        Ok(reachable)
+    }
+
+    /// Branches to a frame, the arguments of the branch are left on the stack.
+    fn branch(
+        &mut self,
+        target: mir::BasicBlockId,
+        condition: Option<Value>,
+    ) -> Result<bool, String> {
+        let frame = self
+            .frames
+            .iter_mut()
+            .rev()
+            .find(|frame| frame.id == target)
+            .ok_or_else(|| format!("no block with ID {}", target))?;
+        frame.reached = true;
+        let (target, nb_args) = (frame.target, frame.nb_args);
+        if self.stack.len() < nb_args {
+            return Err(String::from("stack underflow"));
+        }
+        let args = self.stack[(self.stack.len() - nb_args)..].to_vec();
+        match condition {
+            Some(condition) => {
+                let next = self.builder.create_block();
+                self.builder.ins().brif(condition, target, &args, next, &[]);
+                self.builder.switch_to_block(next);
+                self.builder.seal_block(next);
+                Ok(true)
+            }
+            None => {
+                self.builder.ins().jump(target, &args);
+                Ok(false)
+            }
+        }
+    }
+
+    fn statement(&mut self, stmt: &mir::Statement) -> Result<bool, String> {
+        match stmt {
+            mir::Statement::Local(mir::Local::Get(l_id)) => {
+                let var = self.variable(*l_id)?;
+                let value = self.builder.use_var(var);
+                self.stack.push(value);
+            }
+            mir::Statement::Local(mir::Local::Set(l_id)) => {
+                let var = self.variable(*l_id)?;
+                let value = self.pop()?;
+                self.builder.def_var(var, value);
+            }
+            mir::Statement::Local(mir::Local::Tee(l_id)) => {
+                let var = self.variable(*l_id)?;
+                let value = self.pop()?;
+                self.builder.def_var(var, value);
+                self.stack.push(value);
+            }
+            mir::Statement::Const(value) => {
+                let value = match value {
+                    mir::Value::I32(x) => self.builder.ins().iconst(types::I32, *x as u32 as i64),
+                    mir::Value::I64(x) => self.builder.ins().iconst(types::I64, *x),
+                    mir::Value::F32(x) => self.builder.ins().f32const(*x),
+                    mir::Value::F64(x) => self.builder.ins().f64const(*x),
+                    mir::Value::V128(x) => self.v128_const(*x),
+                    mir::Value::DataPointer(data_id) => match self.offsets.get(data_id) {
+                        Some(offset) => self.builder.ins().iconst(types::I32, *offset as i64),
+                        None => return Err(format!("data {} does not exist", data_id)),
+                    },
+                    mir::Value::TableIndex(_) => {
+                        return Err(String::from("function tables are not supported"))
+                    }
+                };
+                self.stack.push(value);
+            }
+            mir::Statement::Block(block) => return self.block(block),
+            mir::Statement::Unop(unop) => {
+                let x = self.pop()?;
+                let value = match unop {
+                    mir::Unop::I32Clz | mir::Unop::I64Clz => self.builder.ins().clz(x),
+                    mir::Unop::I32Ctz | mir::Unop::I64Ctz => self.builder.ins().ctz(x),
+                    mir::Unop::I32Popcnt | mir::Unop::I64Popcnt => self.builder.ins().popcnt(x),
+                    mir::Unop::F32Neg | mir::Unop::F64Neg => self.builder.ins().fneg(x),
+                    mir::Unop::F32Abs | mir::Unop::F64Abs => self.builder.ins().fabs(x),
+                    mir::Unop::F32Sqrt | mir::Unop::F64Sqrt => self.builder.ins().sqrt(x),
+                    mir::Unop::F32Ceil | mir::Unop::F64Ceil => self.builder.ins().ceil(x),
+                    mir::Unop::F32Floor | mir::Unop::F64Floor => self.builder.ins().floor(x),
+                    mir::Unop::F32Trunc | mir::Unop::F64Trunc => self.builder.ins().trunc(x),
+                    mir::Unop::F32Nearest | mir::Unop::F64Nearest => self.builder.ins().nearest(x),
+                    mir::Unop::I32TruncSatF32S | mir::Unop::I32TruncSatF64S => {
+                        self.builder.ins().fcvt_to_sint_sat(types::I32, x)
+                    }
+                    mir::Unop::I32TruncSatF32U | mir::Unop::I32TruncSatF64U => {
+                        self.builder.ins().fcvt_to_uint_sat(types::I32, x)
+                    }
+                    mir::Unop::I64TruncSatF32S | mir::Unop::I64TruncSatF64S => {
+                        self.builder.ins().fcvt_to_sint_sat(types::I64, x)
+                    }
+                    mir::Unop::I64TruncSatF32U | mir::Unop::I64TruncSatF64U => {
+                        self.builder.ins().fcvt_to_uint_sat(types::I64, x)
+                    }
+                    mir::Unop::I32ReinterpretF32
+                    | mir::Unop::I64ReinterpretF64
+                    | mir::Unop::F32ReinterpretI32
+                    | mir::Unop::F64ReinterpretI64 => {
+                        let t = native_t(unop.get_t());
+                        self.builder.ins().bitcast(t, MemFlags::new(), x)
+                    }
+                };
+                self.stack.push(value);
+            }
+            mir::Statement::Binop(binop) => {
+                let y = self.pop()?;
+                let x = self.pop()?;
+                let ins = self.builder.ins();
+                let value = match binop {
+                    mir::Binop::I32Add | mir::Binop::I64Add => ins.iadd(x, y),
+                    mir::Binop::I32Sub | mir::Binop::I64Sub => ins.isub(x, y),
+                    mir::Binop::I32Mul | mir::Binop::I64Mul => ins.imul(x, y),
+                    mir::Binop::I32Div | mir::Binop::I64Div => ins.udiv(x, y),
+                    mir::Binop::I32DivS | mir::Binop::I64DivS => ins.sdiv(x, y),
+                    mir::Binop::I32Rem | mir::Binop::I64Rem => ins.urem(x, y),
+                    mir::Binop::I32Xor | mir::Binop::I64Xor => ins.bxor(x, y),
+                    mir::Binop::I32Or | mir::Binop::I64Or => ins.bor(x, y),
+                    mir::Binop::I32And | mir::Binop::I64And => ins.band(x, y),
+                    mir::Binop::F32Add | mir::Binop::F64Add => ins.fadd(x, y),
+                    mir::Binop::F32Sub | mir::Binop::F64Sub => ins.fsub(x, y),
+                    mir::Binop::F32Mul | mir::Binop::F64Mul => ins.fmul(x, y),
+                    mir::Binop::F32Div | mir::Binop::F64Div => ins.fdiv(x, y),
+                    mir::Binop::F32Min | mir::Binop::F64Min => ins.fmin(x, y),
+                    mir::Binop::F32Max | mir::Binop::F64Max => ins.fmax(x, y),
+                    mir::Binop::F32Copysign | mir::Binop::F64Copysign => ins.fcopysign(x, y),
+                };
+                self.stack.push(value);
+            }
+            mir::Statement::Relop(relop) => {
+                let y = self.pop()?;
+                let x = self.pop()?;
+                let ins = self.builder.ins();
+                let value = match relop {
+                    mir::Relop::I32Eq | mir::Relop::I64Eq => ins.icmp(IntCC::Equal, x, y),
+                    mir::Relop::I32Ne | mir::Relop::I64Ne => ins.icmp(IntCC::NotEqual, x, y),
+                    mir::Relop::I32Lt | mir::Relop::I64Lt => ins.icmp(IntCC::SignedLessThan, x, y),
+                    mir::Relop::I32Gt | mir::Relop::I64Gt => {
+                        ins.icmp(IntCC::SignedGreaterThan, x, y)
+                    }
+                    mir::Relop::I32Le | mir::Relop::I64Le => {
+                        ins.icmp(IntCC::SignedLessThanOrEqual, x, y)
+                    }
+                    mir::Relop::I32Ge | mir::Relop::I64Ge => {
+                        ins.icmp(IntCC::SignedGreaterThanOrEqual, x, y)
+                    }
+                    mir::Relop::F32Eq | mir::Relop::F64Eq => ins.fcmp(FloatCC::Equal, x, y),
+                    mir::Relop::F32Ne | mir::Relop::F64Ne => ins.fcmp(FloatCC::NotEqual, x, y),
+                    mir::Relop::F32Lt | mir::Relop::F64Lt => ins.fcmp(FloatCC::LessThan, x, y),
+                    mir::Relop::F32Gt | mir::Relop::F64Gt => ins.fcmp(FloatCC::GreaterThan, x, y),
+                    mir::Relop::F32Le | mir::Relop::F64Le => {
+                        ins.fcmp(FloatCC::LessThanOrEqual, x, y)
+                    }
+                    mir::Relop::F32Ge | mir::Relop::F64Ge => {
+                        ins.fcmp(FloatCC::GreaterThanOrEqual, x, y)
+                    }
+                };
+                // Comparisons produce booleans as i32, as in wasm
+                let value = self.builder.ins().uextend(types::I32, value);
+                self.stack.push(value);
+            }
+            mir::Statement::Control(control) => match control {
+                mir::Control::Return => {
+                    let id = self.frames.first().ok_or("no function frame")?.id;
+                    return self.branch(id, None);
+                }
+                mir::Control::Unreachable => {
+                    self.builder
+                        .ins()
+                        .trap(TrapCode::unwrap_user(TRAP_UNREACHABLE));
+                    return Ok(false);
+                }
+                mir::Control::Br(target) => return self.branch(*target, None),
+                mir::Control::BrIf(target) => {
+                    let condition = self.pop()?;
+                    return self.branch(*target, Some(condition));
+                }
+            },
+            mir::Statement::Call(call) => match call {
+                mir::Call::Direct(fun_id) => self.call(*fun_id)?,
+                mir::Call::Indirect(_) => {
+                    return Err(String::from("indirect calls are not supported"))
+                }
+            },
+            mir::Statement::Parametric(mir::Parametric::Drop) => {
+                self.pop()?;
+            }
+            mir::Statement::Memory(memory) => self.memory(memory)?,
+            mir::Statement::Global(global) => self.global(global)?,
+            mir::Statement::Simd(simd) => self.simd(simd)?,
+            mir::Statement::Atomic(atomic) => self.atomic(atomic)?,
+            mir::Statement::Raw(_) => {
+                return Err(String::from("raw instructions are not supported"))
+            }
+        }
+        Ok(true)
+    }
+
+    /// Waiting and notifying need a runtime managing the threads, they are not supported.
+    fn atomic(&mut self, atomic: &mir::Atomic) -> Result<(), String> {
+        let size = 1 << atomic.align();
+        let value = match atomic {
+            mir::Atomic::Load { t, offset } => {
+                let address = self.atomic_address(*offset, size)?;
+                self.builder
+                    .ins()
+                    .atomic_load(native_t(*t), mem_flags(), address)
+            }
+            mir::Atomic::Store { offset, .. } => {
+                let value = self.pop()?;
+                let address = self.atomic_address(*offset, size)?;
+                self.builder.ins().atomic_store(mem_flags(), value, address);
+                return Ok(());
+            }
+            mir::Atomic::Rmw { op, t, offset } => {
+                let value = self.pop()?;
+                let address = self.atomic_address(*offset, size)?;
+                let op = match op {
+                    mir::RmwOp::Add => AtomicRmwOp::Add,
+                    mir::RmwOp::Sub => AtomicRmwOp::Sub,
+                    mir::RmwOp::And => AtomicRmwOp::And,
+                    mir::RmwOp::Or => AtomicRmwOp::Or,
+                    mir::RmwOp::Xor => AtomicRmwOp::Xor,
+                    mir::RmwOp::Xchg => AtomicRmwOp::Xchg,
+                };
+                self.builder
+                    .ins()
+                    .atomic_rmw(native_t(*t), mem_flags(), op, address, value)
+            }
+            mir::Atomic::Cmpxchg { offset, .. } => {
+                let replacement = self.pop()?;
+                let expected = self.pop()?;
+                let address = self.atomic_address(*offset, size)?;
+                self.builder
+                    .ins()
+                    .atomic_cas(mem_flags(), address, expected, replacement)
+            }
+            mir::Atomic::Notify { .. } | mir::Atomic::Wait { .. } => {
+                return Err(String::from("atomic wait and notify are not supported"))
+            }
+            mir::Atomic::Fence => {
+                self.builder.ins().fence();
+                return Ok(());
+            }
+        };
+        self.stack.push(value);
+        Ok(())
+    }
+
+    /// Vectors are kept as `I8X16` and reinterpreted with the lanes of each operation.
+    fn simd(&mut self, simd: &mir::Simd) -> Result<(), String> {
+        let value = match simd {
+            mir::Simd::Splat(shape) => {
+                let x = self.pop()?;
+                let x = self.narrow_lane(*shape, x);
+                let v = self.builder.ins().splat(vector_t(*shape), x);
+                self.to_v128(v)
+            }
+            mir::Simd::ExtractLane(shape, lane) => {
+                let v = self.pop_vector(*shape)?;
+                let x = self.builder.ins().extractlane(v, *lane);
+                match shape {
+                    mir::Shape::I8x16 | mir::Shape::I16x8 => {
+                        self.builder.ins().uextend(types::I32, x)
+                    }
+                    _ => x,
+                }
+            }
+            mir::Simd::ReplaceLane(shape, lane) => {
+                let x = self.pop()?;
+                let x = self.narrow_lane(*shape, x);
+                let v = self.pop_vector(*shape)?;
+                let v = self.builder.ins().insertlane(v, x, *lane);
+                self.to_v128(v)
+            }
+            mir::Simd::Not => {
+                let v = self.pop()?;
+                self.builder.ins().bnot(v)
+            }
+            mir::Simd::And | mir::Simd::Or | mir::Simd::Xor => {
+                let y = self.pop()?;
+                let x = self.pop()?;
+                let ins = self.builder.ins();
+                match simd {
+                    mir::Simd::And => ins.band(x, y),
+                    mir::Simd::Or => ins.bor(x, y),
+                    _ => ins.bxor(x, y),
+                }
+            }
+            mir::Simd::Add(shape)
+            | mir::Simd::Sub(shape)
+            | mir::Simd::Mul(shape)
+            | mir::Simd::Div(shape) => {
+                let y = self.pop_vector(*shape)?;
+                let x = self.pop_vector(*shape)?;
+                let ins = self.builder.ins();
+                let v = match (simd, shape.is_float()) {
+                    (mir::Simd::Add(_), false) => ins.iadd(x, y),
+                    (mir::Simd::Sub(_), false) => ins.isub(x, y),
+                    (mir::Simd::Mul(_), false) => ins.imul(x, y),
+                    (mir::Simd::Add(_), true) => ins.fadd(x, y),
+                    (mir::Simd::Sub(_), true) => ins.fsub(x, y),
+                    (mir::Simd::Mul(_), true) => ins.fmul(x, y),
+                    (_, true) => ins.fdiv(x, y),
+                    (_, false) => return Err(String::from("integer vector division")),
+                };
+                self.to_v128(v)
+            }
+        };
+        self.stack.push(value);
+        Ok(())
+    }
+
+    /// Pops a vector, with the lanes of `shape`.
+    fn pop_vector(&mut self, shape: mir::Shape) -> Result<Value, String> {
+        let v = self.pop()?;
+        let t = vector_t(shape);
+        if t == types::I8X16 {
+            Ok(v)
+        } else {
+            Ok(self.builder.ins().bitcast(t, mem_flags(), v))
+        }
+    }
+
+    fn to_v128(&mut self, v: Value) -> Value {
+        if self.builder.func.dfg.value_type(v) == types::I8X16 {
+            v
+        } else {
+            self.builder.ins().bitcast(types::I8X16, mem_flags(), v)
+        }
+    }
+
+    /// Lanes of `I8x16` and `I16x8` are given as i32, and truncated.
+    fn narrow_lane(&mut self, shape: mir::Shape, x: Value) -> Value {
+        match shape {
+            mir::Shape::I8x16 => self.builder.ins().ireduce(types::I8, x),
+            mir::Shape::I16x8 => self.builder.ins().ireduce(types::I16, x),
+            _ => x,
+        }
+    }
+
+    fn v128_const(&mut self, x: u128) -> Value {
+        let constant = self
+            .builder
+            .func
+            .dfg
+            .constants
+            .insert(x.to_le_bytes().to_vec().into());
+        self.builder.ins().vconst(types::I8X16, constant)
+    }
+
+    fn call(&mut self, fun_id: hir::FunId) -> Result<(), String> {
+        let fun_ref = match self.fun_refs.get(&fun_id) {
+            Some(fun_ref) => *fun_ref,
+            None => {
+                let func_id = self
+                    .funs
+                    .get(&fun_id)
+                    .ok_or_else(|| format!("function {} does not exist", fun_id))?;
+                let fun_ref = self
+                    .module
+                    .declare_func_in_func(*func_id, self.builder.func);
+                self.fun_refs.insert(fun_id, fun_ref);
+                fun_ref
+            }
+        };
+        let signature = self.builder.func.dfg.ext_funcs[fun_ref].signature;
+        let nb_params = self.builder.func.dfg.signatures[signature].params.len();
+        let args = self.pop_n(nb_params)?;
+        let call = self.builder.ins().call(fun_ref, &args);
+        self.stack
+            .extend(self.builder.inst_results(call).iter().copied());
+        Ok(())
+    }
+
+    fn memory(&mut self, memory: &mir::Memory) -> Result<(), String> {
+        let pages = (self.memory_size / PAGE_SIZE as u64) as i64;
+        let value = match memory {
+            mir::Memory::Size => self.builder.ins().iconst(types::I32, pages),
+            mir::Memory::Grow => {
+                // The memory has a fixed size, growing it fails.
+                self.pop()?;
+                self.builder.ins().iconst(types::I32, u32::MAX as i64)
+            }
+            mir::Memory::I32Load8u { offset, .. } => {
+                let address = self.address(*offset, 1)?;
+                self.builder
+                    .ins()
+                    .uload8(types::I32, mem_flags(), address, 0)
+            }
+            mir::Memory::I64Load8u { offset, .. } => {
+                let address = self.address(*offset, 1)?;
+                self.builder
+                    .ins()
+                    .uload8(types::I64, mem_flags(), address, 0)
+            }
+            mir::Memory::I32Load { offset, .. } => self.load(types::I32, *offset)?,
+            mir::Memory::I64Load { offset, .. } => self.load(types::I64, *offset)?,
+            mir::Memory::F32Load { offset, .. } => self.load(types::F32, *offset)?,
+            mir::Memory::F64Load { offset, .. } => self.load(types::F64, *offset)?,
+            mir::Memory::V128Load { offset, .. } => self.load(types::I8X16, *offset)?,
+            mir::Memory::I32Store8 { offset, .. } | mir::Memory::I64Store8 { offset, .. } => {
+                let value = self.pop()?;
+                let address = self.address(*offset, 1)?;
+                self.builder.ins().istore8(mem_flags(), value, address, 0);
+                return Ok(());
+            }
+            mir::Memory::I32Store { offset, .. }
+            | mir::Memory::I64Store { offset, .. }
+            | mir::Memory::F32Store { offset, .. }
+            | mir::Memory::F64Store { offset, .. }
+            | mir::Memory::V128Store { offset, .. } => {
+                let value = self.pop()?;
+                let t = self.builder.func.dfg.value_type(value);
+                let address = self.address(*offset, t.bytes())?;
+                self.builder.ins().store(mem_flags(), value, address, 0);
+                return Ok(());
+            }
+            mir::Memory::Nop => return Ok(()),
+        };
+        self.stack.push(value);
+        Ok(())
+    }
+
+    fn global(&mut self, global: &mir::Global) -> Result<(), String> {
+        // The stack pointer is the only global
+        let symbol = match (global, self.stack_pointer) {
+            (
+                mir::Global::Get(mir::STACK_POINTER) | mir::Global::Set(mir::STACK_POINTER),
+                Some(symbol),
+            ) => symbol,
+            _ => return Err(String::from("unknown global")),
+        };
+        let pointer_t = self.module.target_config().pointer_type();
+        let address = self.builder.ins().symbol_value(pointer_t, symbol);
+        match global {
+            mir::Global::Get(_) => {
+                let value = self.builder.ins().load(types::I32, mem_flags(), address, 0);
+                self.stack.push(value);
+            }
+            mir::Global::Set(_) => {
+                let value = self.pop()?;
+                self.builder.ins().store(mem_flags(), value, address, 0);
+            }
+        }
+        Ok(())
+    }
+
+    fn load(&mut self, t: types::Type, offset: u32) -> Result<Value, String> {
+        let address = self.address(offset, t.bytes())?;
+        Ok(self.builder.ins().load(t, mem_flags(), address, 0))
+    }
+
+    /// Pops a memory address and returns the corresponding native pointer, out of bounds
+    /// accesses trap.
+    fn address(&mut self, offset: u32, size: u32) -> Result<Value, String> {
+        let address = self.pop()?;
+        let pointer_t = self.module.target_config().pointer_type();
+        let address = self.builder.ins().uextend(types::I64, address);
+        let address = self.builder.ins().iadd_imm(address, offset as i64);
+        let out_of_bounds = self.builder.ins().icmp_imm(
+            IntCC::UnsignedGreaterThan,
+            address,
+            (self.memory_size - size as u64) as i64,
+        );
+        self.builder
+            .ins()
+            .trapnz(out_of_bounds, TrapCode::HEAP_OUT_OF_BOUNDS);
+        let address = if pointer_t == types::I64 {
+            address
+        } else {
+            self.builder.ins().ireduce(pointer_t, address)
+        };
+        let base = self.builder.ins().symbol_value(pointer_t, self.memory);
+        Ok(self.builder.ins().iadd(base, address))
+    }
+
+    /// Pops a memory address for an atomic access of `size` bytes, misaligned accesses trap.
+    fn atomic_address(&mut self, offset: u32, size: u32) -> Result<Value, String> {
+        // The memory is aligned on 8 bytes, native addresses have the alignment of wasm ones
+        let address = self.address(offset, size)?;
+        let misaligned = self.builder.ins().band_imm(address, size as i64 - 1);
+        self.builder
+            .ins()
+            .trapnz(misaligned, TrapCode::unwrap_user(TRAP_MISALIGNED));
+        Ok(address)
+    }
+
+    fn variable(&self, l_id: mir::LocalId) -> Result<Variable, String> {
+        self.variables
+            .get(&l_id)
+            .copied()
+            .ok_or_else(|| format!("local {} does not exist", l_id))
+    }
+
+    fn zero(&mut self, t: mir::Type) -> Value {
+        match t {
+            mir::Type::I32 => self.builder.ins().iconst(types::I32, 0),
+            mir::Type::I64 => self.builder.ins().iconst(types::I64, 0),
+            mir::Type::F32 => self.builder.ins().f32const(0.0),
+            mir::Type::F64 => self.builder.ins().f64const(0.0),
+            mir::Type::V128 => self.v128_const(0),
+        }
+    }
+
+    fn pop(&mut self) -> Result<Value, String> {
+        self.stack
+            .pop()
+            .ok_or_else(|| String::from("stack underflow"))
+    }
+
+    /// Pops `n` values, in the order they were pushed.
+    fn pop_n(&mut self, n: usize) -> Result<Vec<Value>, String> {
+        if self.stack.len() < n {
+            return Err(String::from("stack underflow"));
+        }
+        Ok(self.stack.split_off(self.stack.len() - n))
+    }
+}
+
+/// Memory accesses follow the little endian convention of wasm.
+fn mem_flags() -> MemFlags {
+    let mut flags = MemFlags::new();
+    flags.set_endianness(Endianness::Little);
+    flags
+}
+
+fn native_t(t: mir::Type) -> types::Type {
+    match t {
+        mir::Type::I32 => types::I32,
+        mir::Type::I64 => types::I64,
+        mir::Type::F32 => types::F32,
+        mir::Type::F64 => types::F64,
+        mir::Type::V128 => types::I8X16,
+    }
+}
+
+fn vector_t(shape: mir::Shape) -> types::Type {
+    match shape {
+        mir::Shape::I8x16 => types::I8X16,
+        mir::Shape::I16x8 => types::I16X8,
+        mir::Shape::I32x4 => types::I32X4,
+        mir::Shape::I64x2 => types::I64X2,
+        mir::Shape::F32x4 => types::F32X4,
+        mir::Shape::F64x2 => types::F64X2,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::to_native;
+    use crate::error::{DummyHandler, ErrorHandler};
+    use crate::hir::Identifier;
+    use crate::mir::*;
+
+    #[test]
+    fn object_file() {
+        // Sums integers from 1 to 10, in an exposed function.
+        let stmts = vec![
+            Statement::Block(Box::new(Block::Loop {
+                id: 1,
+                t: None,
+                stmts: vec![
+                    Statement::Local(Local::Get(0)),
+                    Statement::Const(Value::I32(1)),
+                    Statement::Binop(Binop::I32Add),
+                    Statement::Local(Local::Set(0)),
+                    Statement::Local(Local::Get(1)),
+                    Statement::Local(Local::Get(0)),
+                    Statement::Binop(Binop::I32Add),
+                    Statement::Local(Local::Set(1)),
+                    Statement::Local(Local::Get(0)),
+                    Statement::Const(Value::I32(10)),
+                    Statement::Relop(Relop::I32Lt),
+                    Statement::Control(Control::BrIf(1)),
+                ],
+            })),
+            Statement::Local(Local::Get(1)),
+        ];
+        let fun = Function {
+            ident: String::from("test"),
+            params: Vec::new(),
+            param_t: Vec::new(),
+            ret_t: vec![Type::I32],
+            locals: vec![
+                LocalVariable {
+                    id: 0,
+                    t: Type::I32,
+                },
+                LocalVariable {
+                    id: 1,
+                    t: Type::I32,
+                },
+            ],
+            body: Block::Block {
+                id: 0,
+                stmts,
+                t: None,
+            },
+            is_pub: true,
+            inline: Inline::Auto,
+            exposed: Some(String::from("sum_to_ten")),
+            fun_id: FunId::new(0),
+        };
+        let program = Program {
+            funs: vec![fun],
+            imports: Vec::new(),
+            data: std::collections::HashMap::new(),
+            start: None,
+            memory: MemoryLimits::default(),
+            exports: ModuleExports::default(),
+            table: Vec::new(),
+            allocator: true,
+        };
+        let mut err = DummyHandler::new();
+        let object = to_native(program, &mut err).unwrap();
+        let contains = |name: &[u8]| object.windows(name.len()).any(|bytes| bytes == name);
+        assert!(contains(b"sum_to_ten"));
+        assert!(contains(b"zephyr_memory"));
+    }
+}
@@ -0,0 +1,24 @@
+use log::debug;
+
+use crate::error::ErrorHandler;
+use crate::mir;
+
+mod mir_to_native;
+
+/// Compiles MIR down to a native object file for the host.
+pub fn to_native(
+    mir_program: mir::Program,
+    error_handler: &mut impl ErrorHandler,
+) -> Result<Vec<u8>, ()> {
+    debug!("\n/// Compiling to native code ///\n");
+
+    let module = match mir_to_native::object_module() {
+        Ok(module) => module,
+        Err(e) => {
+            error_handler.report_no_loc(e);
+            return Err(());
+        }
+    };
+    let compiler = mir_to_native::Compiler::new(module, error_handler);
+    mir::emit(mir_program, compiler)
+}
@@ -0,0 +1,200 @@
+use std::fmt;
+
+use crate::mir::{Atomic, Binop, FunId, Global, Memory, Relop, Simd, Type, Unop, Value};
+
+pub type ValueId = usize;
+pub type BlockId = usize;
+
+/// A function in SSA form, as a control flow graph of basic blocks.
+///
+/// The entry block is the first block, values are defined exactly once, either by an
+/// instruction or by a phi node.
+pub struct Function {
+    pub ret_t: Vec<Type>,
+    pub blocks: Vec<BasicBlock>,
+    /// The type of each value, indexed by value ID.
+    pub values: Vec<Type>,
+}
+
+pub struct BasicBlock {
+    pub preds: Vec<BlockId>,
+    pub phis: Vec<Phi>,
+    pub insts: Vec<Inst>,
+    pub terminator: Terminator,
+}
+
+pub struct Phi {
+    pub result: ValueId,
+    /// One argument per predecessor, in the same order.
+    pub args: Vec<ValueId>,
+}
+
+pub struct Inst {
+    pub results: Vec<ValueId>,
+    pub op: Op,
+    pub args: Vec<ValueId>,
+}
+
+pub enum Op {
+    /// The nth parameter of the function.
+    Param(usize),
+    Const(Value),
+    Unop(Unop),
+    Binop(Binop),
+    Relop(Relop),
+    Call(FunId),
+    Memory(Memory),
+    Global(Global),
+    Simd(Simd),
+    Atomic(Atomic),
+}
+
+pub enum Terminator {
+    Jump(BlockId),
+    Branch {
+        cond: ValueId,
+        then_block: BlockId,
+        else_block: BlockId,
+    },
+    Return(Vec<ValueId>),
+    Unreachable,
+}
+
+impl Function {
+    /// Returns a new value of type `t`.
+    pub fn new_value(&mut self, t: Type) -> ValueId {
+        self.values.push(t);
+        self.values.len() - 1
+    }
+
+    /// Returns a new empty block.
+    pub fn new_block(&mut self) -> BlockId {
+        self.blocks.push(BasicBlock {
+            preds: Vec::new(),
+            phis: Vec::new(),
+            insts: Vec::new(),
+            terminator: Terminator::Unreachable,
+        });
+        self.blocks.len() - 1
+    }
+}
+
+impl Op {
+    /// Pure operations have no side effects and can not trap, they can be moved or removed
+    /// freely.
+    pub fn is_pure(&self) -> bool {
+        match self {
+            Op::Param(_) | Op::Const(_) | Op::Unop(_) | Op::Relop(_) | Op::Simd(_) => true,
+            Op::Binop(binop) => !matches!(
+                binop,
+                Binop::I32Div
+                    | Binop::I32DivS
+                    | Binop::I32Rem
+                    | Binop::I64Div
+                    | Binop::I64DivS
+                    | Binop::I64Rem
+            ),
+            Op::Call(_) | Op::Memory(_) | Op::Global(_) | Op::Atomic(_) => false,
+        }
+    }
+}
+
+impl Terminator {
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            Terminator::Jump(target) => vec![*target],
+            Terminator::Branch {
+                then_block,
+                else_block,
+                ..
+            } => vec![*then_block, *else_block],
+            Terminator::Return(_) | Terminator::Unreachable => Vec::new(),
+        }
+    }
+
+    pub fn args(&self) -> Vec<ValueId> {
+        match self {
+            Terminator::Branch { cond, .. } => vec![*cond],
+            Terminator::Return(values) => values.clone(),
+            Terminator::Jump(_) | Terminator::Unreachable => Vec::new(),
+        }
+    }
+
+    pub fn args_mut(&mut self) -> Vec<&mut ValueId> {
+        match self {
+            Terminator::Branch { cond, .. } => vec![cond],
+            Terminator::Return(values) => values.iter_mut().collect(),
+            Terminator::Jump(_) | Terminator::Unreachable => Vec::new(),
+        }
+    }
+}
+
+// ———————————————————————————————— Display ————————————————————————————————— //
+
+fn values_to_string(values: &[ValueId]) -> String {
+    values
+        .iter()
+        .map(|value| format!("%{}", value))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (id, block) in self.blocks.iter().enumerate() {
+            if id != 0 && block.preds.is_empty() {
+                continue;
+            }
+            let preds = block
+                .preds
+                .iter()
+                .map(|pred| format!("bb{}", pred))
+                .collect::<Vec<_>>()
+                .join(", ");
+            writeln!(f, "  bb{}: ({})", id, preds)?;
+            for phi in &block.phis {
+                writeln!(
+                    f,
+                    "    %{} = phi [{}]",
+                    phi.result,
+                    values_to_string(&phi.args)
+                )?;
+            }
+            for inst in &block.insts {
+                write!(f, "    ")?;
+                if !inst.results.is_empty() {
+                    write!(f, "{} = ", values_to_string(&inst.results))?;
+                }
+                match &inst.op {
+                    Op::Param(idx) => write!(f, "param {}", idx)?,
+                    Op::Const(value) => write!(f, "{}", value)?,
+                    Op::Unop(unop) => write!(f, "{}", unop)?,
+                    Op::Binop(binop) => write!(f, "{}", binop)?,
+                    Op::Relop(relop) => write!(f, "{}", relop)?,
+                    Op::Call(fun_id) => write!(f, "call {}", fun_id)?,
+                    Op::Memory(memory) => write!(f, "{}", memory)?,
+                    Op::Global(global) => write!(f, "{}", global)?,
+                    Op::Simd(simd) => write!(f, "{}", simd)?,
+                    Op::Atomic(atomic) => write!(f, "{}", atomic)?,
+                }
+                if !inst.args.is_empty() {
+                    write!(f, " {}", values_to_string(&inst.args))?;
+                }
+                writeln!(f)?;
+            }
+            match &block.terminator {
+                Terminator::Jump(target) => writeln!(f, "    jump bb{}", target)?,
+                Terminator::Branch {
+                    cond,
+                    then_block,
+                    else_block,
+                } => writeln!(f, "    branch %{} bb{} bb{}", cond, then_block, else_block)?,
+                Terminator::Return(values) => {
+                    writeln!(f, "    return {}", values_to_string(values))?
+                }
+                Terminator::Unreachable => writeln!(f, "    unreachable")?,
+            }
+        }
+        Ok(())
+    }
+}
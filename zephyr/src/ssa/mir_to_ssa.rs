@@ -0,0 +1,520 @@
+//! MIR to SSA
+//!
+//! The stack of the MIR is evaluated symbolically, so that each statement producing a value
+//! becomes an instruction defining a fresh SSA value. Locals are converted into SSA values using
+//! the algorithm from "Simple and Efficient Construction of Static Single Assignment Form" (Braun
+//! et al.), which inserts phi nodes on demand while walking the structured control flow.
+
+use std::collections::{HashMap, HashSet};
+
+use super::ir::*;
+use crate::mir;
+use crate::mir::{BasicBlockId, Call, Control, FunId, LocalId, Statement, Type, Value};
+
+/// The number of parameters and the return types of a function.
+pub type Signature = (usize, Vec<Type>);
+
+/// Converts a MIR function to SSA form.
+///
+/// An error is returned if the function uses a construct which is not supported by the SSA
+/// form, such as indirect calls.
+pub fn to_ssa(
+    fun: &mir::Function,
+    signatures: &HashMap<FunId, Signature>,
+) -> Result<Function, String> {
+    let mut builder = Builder {
+        fun: Function {
+            ret_t: fun.ret_t.clone(),
+            blocks: Vec::new(),
+            values: Vec::new(),
+        },
+        signatures,
+        local_t: HashMap::new(),
+        current_def: HashMap::new(),
+        sealed: HashSet::new(),
+        incomplete_phis: HashMap::new(),
+        labels: Vec::new(),
+        stack: Vec::new(),
+        current: None,
+    };
+    builder.function(fun)?;
+    Ok(builder.fun)
+}
+
+/// Where a branch to a MIR block leads.
+enum Target {
+    /// The end of a block or if, with the phis receiving the results.
+    Join(BlockId, Vec<ValueId>),
+    /// The beginning of a loop.
+    Header(BlockId),
+    /// The end of the function.
+    Return,
+}
+
+struct Label {
+    id: BasicBlockId,
+    target: Target,
+}
+
+struct Builder<'a> {
+    fun: Function,
+    signatures: &'a HashMap<FunId, Signature>,
+    local_t: HashMap<LocalId, Type>,
+    current_def: HashMap<(LocalId, BlockId), ValueId>,
+    sealed: HashSet<BlockId>,
+    incomplete_phis: HashMap<BlockId, Vec<(LocalId, ValueId)>>,
+    labels: Vec<Label>,
+    /// The values on the stack.
+    stack: Vec<ValueId>,
+    /// The block being built, `None` in unreachable code.
+    current: Option<BlockId>,
+}
+
+impl<'a> Builder<'a> {
+    fn function(&mut self, fun: &mir::Function) -> Result<(), String> {
+        let entry = self.fun.new_block();
+        self.sealed.insert(entry);
+        self.current = Some(entry);
+
+        // Parameters are passed as arguments, other locals start at zero
+        for (idx, (param, t)) in fun.params.iter().zip(&fun.param_t).enumerate() {
+            let value = self.inst(Op::Param(idx), Vec::new(), vec![*t])?[0];
+            self.local_t.insert(*param, *t);
+            self.write_variable(*param, entry, value);
+        }
+        for local in &fun.locals {
+            let zero = match local.t {
+                Type::I32 => Value::I32(0),
+                Type::I64 => Value::I64(0),
+                Type::F32 => Value::F32(0.0),
+                Type::F64 => Value::F64(0.0),
+                Type::V128 => Value::V128(0),
+            };
+            let value = self.inst(Op::Const(zero), Vec::new(), vec![local.t])?[0];
+            self.local_t.insert(local.id, local.t);
+            self.write_variable(local.id, entry, value);
+        }
+
+        let (id, stmts) = match &fun.body {
+            mir::Block::Block { id, stmts, .. } => (*id, stmts),
+            _ => return Err(String::from("the body of a function must be a block")),
+        };
+        self.labels.push(Label {
+            id,
+            target: Target::Return,
+        });
+        self.statements(stmts)?;
+        if self.current.is_some() {
+            let values = self.pop_n(self.fun.ret_t.len())?;
+            self.terminate(Terminator::Return(values));
+        }
+        Ok(())
+    }
+
+    fn statements(&mut self, stmts: &[Statement]) -> Result<(), String> {
+        for stmt in stmts {
+            // The rest of the block is dead code
+            if self.current.is_none() {
+                return Ok(());
+            }
+            self.statement(stmt)?;
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self, stmt: &Statement) -> Result<(), String> {
+        let current = self.current.unwrap();
+        match stmt {
+            Statement::Local(mir::Local::Get(local)) => {
+                let value = self.read_variable(*local, current)?;
+                self.stack.push(value);
+            }
+            Statement::Local(mir::Local::Set(local)) => {
+                let value = self.pop()?;
+                self.write_variable(*local, current, value);
+            }
+            Statement::Local(mir::Local::Tee(local)) => {
+                let value = self.peek_n(1)?[0];
+                self.write_variable(*local, current, value);
+            }
+            Statement::Const(value) => {
+                let t = match value {
+                    Value::I32(_) | Value::DataPointer(_) | Value::TableIndex(_) => Type::I32,
+                    Value::I64(_) => Type::I64,
+                    Value::F32(_) => Type::F32,
+                    Value::F64(_) => Type::F64,
+                    Value::V128(_) => Type::V128,
+                };
+                self.push_inst(Op::Const(value.clone()), 0, vec![t])?;
+            }
+            Statement::Unop(unop) => {
+                self.push_inst(Op::Unop(unop.clone()), 1, vec![unop.get_t()])?
+            }
+            Statement::Binop(binop) => {
+                self.push_inst(Op::Binop(binop.clone()), 2, vec![binop.get_t()])?
+            }
+            Statement::Relop(relop) => {
+                self.push_inst(Op::Relop(relop.clone()), 2, vec![relop.get_t()])?
+            }
+            Statement::Parametric(mir::Parametric::Drop) => {
+                self.pop()?;
+            }
+            Statement::Memory(memory) => {
+                let (nb_args, results) = match memory {
+                    mir::Memory::Size => (0, vec![Type::I32]),
+                    mir::Memory::Grow => (1, vec![Type::I32]),
+                    mir::Memory::I32Load8u { .. } | mir::Memory::I32Load { .. } => {
+                        (1, vec![Type::I32])
+                    }
+                    mir::Memory::I64Load8u { .. } | mir::Memory::I64Load { .. } => {
+                        (1, vec![Type::I64])
+                    }
+                    mir::Memory::F32Load { .. } => (1, vec![Type::F32]),
+                    mir::Memory::F64Load { .. } => (1, vec![Type::F64]),
+                    mir::Memory::V128Load { .. } => (1, vec![Type::V128]),
+                    mir::Memory::I32Store8 { .. }
+                    | mir::Memory::I32Store { .. }
+                    | mir::Memory::I64Store8 { .. }
+                    | mir::Memory::I64Store { .. }
+                    | mir::Memory::F32Store { .. }
+                    | mir::Memory::F64Store { .. }
+                    | mir::Memory::V128Store { .. } => (2, Vec::new()),
+                    mir::Memory::Nop => return Ok(()),
+                };
+                self.push_inst(Op::Memory(memory.clone()), nb_args, results)?;
+            }
+            // The only global is the stack pointer, an i32
+            Statement::Global(global @ mir::Global::Get(_)) => {
+                self.push_inst(Op::Global(global.clone()), 0, vec![Type::I32])?
+            }
+            Statement::Global(global @ mir::Global::Set(_)) => {
+                self.push_inst(Op::Global(global.clone()), 1, Vec::new())?
+            }
+            Statement::Simd(simd) => {
+                let nb_args = simd.get_params().len();
+                self.push_inst(Op::Simd(simd.clone()), nb_args, vec![simd.get_t()])?
+            }
+            Statement::Atomic(atomic) => {
+                let nb_args = atomic.get_params().len();
+                self.push_inst(Op::Atomic(atomic.clone()), nb_args, atomic.get_t())?
+            }
+            Statement::Call(Call::Direct(fun_id)) => {
+                let (nb_params, ret_t) = self
+                    .signatures
+                    .get(fun_id)
+                    .ok_or_else(|| format!("unknown function {}", fun_id))?;
+                self.push_inst(Op::Call(*fun_id), *nb_params, ret_t.clone())?;
+            }
+            Statement::Call(Call::Indirect(_)) => {
+                return Err(String::from("indirect calls are not supported"))
+            }
+            Statement::Raw(_) => return Err(String::from("raw instructions are not supported")),
+            Statement::Control(Control::Return) => {
+                let values = self.pop_n(self.fun.ret_t.len())?;
+                self.terminate(Terminator::Return(values));
+            }
+            Statement::Control(Control::Unreachable) => self.terminate(Terminator::Unreachable),
+            Statement::Control(Control::Br(id)) => {
+                let target = self.branch_target(*id, current)?;
+                self.terminate(Terminator::Jump(target));
+            }
+            Statement::Control(Control::BrIf(id)) => {
+                let cond = self.pop()?;
+                let target = self.branch_target(*id, current)?;
+                let next = self.fun.new_block();
+                self.add_edge(current, next, &[]);
+                self.seal(next)?;
+                self.terminate(Terminator::Branch {
+                    cond,
+                    then_block: target,
+                    else_block: next,
+                });
+                self.current = Some(next);
+            }
+            Statement::Block(block) => self.block(block)?,
+        }
+        Ok(())
+    }
+
+    fn block(&mut self, block: &mir::Block) -> Result<(), String> {
+        let current = self.current.unwrap();
+        match block {
+            mir::Block::Block { id, stmts, t } => {
+                let height = self.stack.len();
+                let (join, results) = self.join_block(*t);
+                self.labels.push(Label {
+                    id: *id,
+                    target: Target::Join(join, results.clone()),
+                });
+                self.statements(stmts)?;
+                self.jump_to_join(join, &results)?;
+                self.labels.pop();
+                self.enter_join(join, results, height)?;
+            }
+            mir::Block::Loop { id, stmts, .. } => {
+                let header = self.fun.new_block();
+                self.add_edge(current, header, &[]);
+                self.terminate(Terminator::Jump(header));
+                self.current = Some(header);
+                self.labels.push(Label {
+                    id: *id,
+                    target: Target::Header(header),
+                });
+                // The results of the loop, if any, are left on the stack
+                self.statements(stmts)?;
+                self.labels.pop();
+                self.seal(header)?;
+            }
+            mir::Block::If {
+                id,
+                then_stmts,
+                else_stmts,
+                t,
+            } => {
+                let cond = self.pop()?;
+                let height = self.stack.len();
+                let then_block = self.fun.new_block();
+                let else_block = self.fun.new_block();
+                self.add_edge(current, then_block, &[]);
+                self.add_edge(current, else_block, &[]);
+                self.seal(then_block)?;
+                self.seal(else_block)?;
+                self.terminate(Terminator::Branch {
+                    cond,
+                    then_block,
+                    else_block,
+                });
+
+                let (join, results) = self.join_block(*t);
+                self.labels.push(Label {
+                    id: *id,
+                    target: Target::Join(join, results.clone()),
+                });
+                self.current = Some(then_block);
+                self.statements(then_stmts)?;
+                self.jump_to_join(join, &results)?;
+                self.stack.truncate(height);
+                self.current = Some(else_block);
+                self.statements(else_stmts)?;
+                self.jump_to_join(join, &results)?;
+                self.labels.pop();
+                self.enter_join(join, results, height)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Creates the block following a MIR block, with a phi for its result if any.
+    fn join_block(&mut self, t: Option<Type>) -> (BlockId, Vec<ValueId>) {
+        let join = self.fun.new_block();
+        let results = t
+            .into_iter()
+            .map(|t| self.new_phi(join, t))
+            .collect::<Vec<_>>();
+        (join, results)
+    }
+
+    /// Jumps from the end of a MIR block to the block following it, if reachable.
+    fn jump_to_join(&mut self, join: BlockId, results: &[ValueId]) -> Result<(), String> {
+        if let Some(current) = self.current {
+            let args = self.peek_n(results.len())?;
+            self.add_edge(current, join, &args);
+            self.terminate(Terminator::Jump(join));
+        }
+        Ok(())
+    }
+
+    /// Continues after a MIR block, the join block is unreachable if no branch leads to it.
+    fn enter_join(
+        &mut self,
+        join: BlockId,
+        results: Vec<ValueId>,
+        height: usize,
+    ) -> Result<(), String> {
+        self.seal(join)?;
+        self.stack.truncate(height);
+        if self.fun.blocks[join].preds.is_empty() {
+            self.current = None;
+        } else {
+            self.current = Some(join);
+            self.stack.extend(results);
+        }
+        Ok(())
+    }
+
+    /// Returns the block a branch to the MIR block `id` leads to, adding the corresponding edge.
+    fn branch_target(&mut self, id: BasicBlockId, current: BlockId) -> Result<BlockId, String> {
+        let label = self
+            .labels
+            .iter()
+            .rev()
+            .find(|label| label.id == id)
+            .ok_or_else(|| format!("unknown block {}", id))?;
+        match &label.target {
+            Target::Join(join, results) => {
+                let join = *join;
+                let args = self.peek_n(results.len())?;
+                self.add_edge(current, join, &args);
+                Ok(join)
+            }
+            Target::Header(header) => {
+                let header = *header;
+                self.add_edge(current, header, &[]);
+                Ok(header)
+            }
+            Target::Return => {
+                // Returning is not an edge of the graph, go through a dedicated block
+                let values = self.peek_n(self.fun.ret_t.len())?;
+                let exit = self.fun.new_block();
+                self.add_edge(current, exit, &[]);
+                self.seal(exit)?;
+                self.fun.blocks[exit].terminator = Terminator::Return(values);
+                Ok(exit)
+            }
+        }
+    }
+
+    // ————————————————————————————— SSA construction —————————————————————————————— //
+
+    fn write_variable(&mut self, local: LocalId, block: BlockId, value: ValueId) {
+        self.current_def.insert((local, block), value);
+    }
+
+    fn read_variable(&mut self, local: LocalId, block: BlockId) -> Result<ValueId, String> {
+        if let Some(value) = self.current_def.get(&(local, block)) {
+            return Ok(*value);
+        }
+        let t = *self
+            .local_t
+            .get(&local)
+            .ok_or_else(|| format!("unknown local {}", local))?;
+        let preds = &self.fun.blocks[block].preds;
+        let (nb_preds, first_pred) = (preds.len(), preds.first().copied());
+        let value = if !self.sealed.contains(&block) {
+            // Not all predecessors are known yet
+            let phi = self.new_phi(block, t);
+            self.incomplete_phis
+                .entry(block)
+                .or_default()
+                .push((local, phi));
+            phi
+        } else if let (1, Some(pred)) = (nb_preds, first_pred) {
+            self.read_variable(local, pred)?
+        } else if nb_preds == 0 {
+            return Err(format!("local {} is read in an unreachable block", local));
+        } else {
+            // Break potential cycles with an operand-less phi
+            let phi = self.new_phi(block, t);
+            self.write_variable(local, block, phi);
+            self.add_phi_operands(local, block, phi)?;
+            phi
+        };
+        self.write_variable(local, block, value);
+        Ok(value)
+    }
+
+    fn add_phi_operands(
+        &mut self,
+        local: LocalId,
+        block: BlockId,
+        phi: ValueId,
+    ) -> Result<(), String> {
+        let preds = self.fun.blocks[block].preds.clone();
+        for pred in preds {
+            let arg = self.read_variable(local, pred)?;
+            self.phi_mut(block, phi).args.push(arg);
+        }
+        Ok(())
+    }
+
+    /// Marks a block as sealed: all its predecessors are known.
+    fn seal(&mut self, block: BlockId) -> Result<(), String> {
+        if let Some(phis) = self.incomplete_phis.remove(&block) {
+            for (local, phi) in phis {
+                self.add_phi_operands(local, block, phi)?;
+            }
+        }
+        self.sealed.insert(block);
+        Ok(())
+    }
+
+    fn new_phi(&mut self, block: BlockId, t: Type) -> ValueId {
+        let result = self.fun.new_value(t);
+        self.fun.blocks[block].phis.push(Phi {
+            result,
+            args: Vec::new(),
+        });
+        result
+    }
+
+    fn phi_mut(&mut self, block: BlockId, phi: ValueId) -> &mut Phi {
+        self.fun.blocks[block]
+            .phis
+            .iter_mut()
+            .find(|p| p.result == phi)
+            .expect("the phi belongs to the block")
+    }
+
+    /// Adds an edge, `args` are the arguments of the phis receiving the results of a MIR block.
+    fn add_edge(&mut self, from: BlockId, to: BlockId, args: &[ValueId]) {
+        self.fun.blocks[to].preds.push(from);
+        // The first phis of a join block receive the results
+        for (phi, arg) in self.fun.blocks[to].phis.iter_mut().zip(args) {
+            phi.args.push(*arg);
+        }
+    }
+
+    // ——————————————————————————————————— Helpers ———————————————————————————————————— //
+
+    /// Appends an instruction to the current block, returns its results.
+    fn inst(
+        &mut self,
+        op: Op,
+        args: Vec<ValueId>,
+        results_t: Vec<Type>,
+    ) -> Result<Vec<ValueId>, String> {
+        let current = self.current.ok_or("instruction in unreachable code")?;
+        let results = results_t
+            .into_iter()
+            .map(|t| self.fun.new_value(t))
+            .collect::<Vec<_>>();
+        self.fun.blocks[current].insts.push(Inst {
+            results: results.clone(),
+            op,
+            args,
+        });
+        Ok(results)
+    }
+
+    /// Appends an instruction taking its arguments from the stack and pushing its results.
+    fn push_inst(&mut self, op: Op, nb_args: usize, results_t: Vec<Type>) -> Result<(), String> {
+        let args = self.pop_n(nb_args)?;
+        let results = self.inst(op, args, results_t)?;
+        self.stack.extend(results);
+        Ok(())
+    }
+
+    fn terminate(&mut self, terminator: Terminator) {
+        if let Some(current) = self.current.take() {
+            self.fun.blocks[current].terminator = terminator;
+        }
+    }
+
+    fn pop(&mut self) -> Result<ValueId, String> {
+        self.stack.pop().ok_or_else(|| String::from("empty stack"))
+    }
+
+    /// Pops `n` values, the first one being the deepest in the stack.
+    fn pop_n(&mut self, n: usize) -> Result<Vec<ValueId>, String> {
+        let values = self.peek_n(n)?;
+        self.stack.truncate(self.stack.len() - n);
+        Ok(values)
+    }
+
+    fn peek_n(&self, n: usize) -> Result<Vec<ValueId>, String> {
+        if self.stack.len() < n {
+            return Err(String::from("not enough values on the stack"));
+        }
+        Ok(self.stack[self.stack.len() - n..].to_vec())
+    }
+}
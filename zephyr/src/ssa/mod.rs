@@ -0,0 +1,183 @@
+use log::debug;
+use std::collections::HashMap;
+
+use crate::mir;
+use crate::mir::FunId;
+
+mod ir;
+mod mir_to_ssa;
+mod opt;
+mod ssa_to_mir;
+
+/// The number of parameters and the return types of the functions of a program.
+pub type Signatures = HashMap<FunId, mir_to_ssa::Signature>;
+
+/// Returns the signatures of the functions and imports of a MIR program, needed to convert its
+/// functions to SSA.
+pub fn signatures(mir_program: &mir::Program) -> Signatures {
+    mir_program
+        .funs
+        .iter()
+        .map(|fun| (fun.fun_id, (fun.param_t.len(), fun.ret_t.clone())))
+        .chain(mir_program.imports.iter().flat_map(|import| {
+            import
+                .prototypes
+                .iter()
+                .map(|proto| (proto.fun_id, (proto.param_t.len(), proto.ret_t.clone())))
+        }))
+        .collect()
+}
+
+/// Optimizes a MIR function by converting it to SSA form, running the SSA optimizations and
+/// converting it back to MIR.
+///
+/// Functions that can not be converted to SSA are left untouched.
+pub fn optimize(fun: &mut mir::Function, signatures: &Signatures) {
+    let mut ssa_fun = match mir_to_ssa::to_ssa(fun, signatures) {
+        Ok(ssa_fun) => ssa_fun,
+        Err(err) => {
+            debug!("{}: not converted to SSA, {}\n", fun.ident, err);
+            return;
+        }
+    };
+    opt::optimize(&mut ssa_fun);
+    debug!("{}:\n{}", fun.ident, ssa_fun);
+    let (locals, body) = ssa_to_mir::to_mir(&ssa_fun, &fun.params);
+    fun.locals = locals;
+    fun.body = body;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::DummyHandler;
+    use crate::hir::Identifier;
+    use crate::mir::*;
+
+    fn program(params: Vec<LocalId>, locals: Vec<LocalId>, stmts: Vec<Statement>) -> Program {
+        let fun = Function {
+            ident: String::from("test"),
+            param_t: params.iter().map(|_| Type::I32).collect(),
+            params,
+            ret_t: vec![Type::I32],
+            locals: locals
+                .into_iter()
+                .map(|id| LocalVariable { id, t: Type::I32 })
+                .collect(),
+            body: Block::Block {
+                id: 0,
+                stmts,
+                t: Some(Type::I32),
+            },
+            is_pub: true,
+            inline: Inline::Auto,
+            exposed: None,
+            fun_id: FunId::new(0),
+        };
+        Program {
+            funs: vec![fun],
+            imports: Vec::new(),
+            data: HashMap::new(),
+            start: None,
+            memory: MemoryLimits::default(),
+            exports: ModuleExports::default(),
+            table: Vec::new(),
+            allocator: true,
+        }
+    }
+
+    fn run(program: &Program, arg: i32) -> Result<Vec<RuntimeValue>, ()> {
+        let mut err = DummyHandler::new();
+        let args = vec![RuntimeValue::I32(arg)];
+        interpret(program, FunId::new(0), args, None, &mut err)
+    }
+
+    #[test]
+    fn round_trip() {
+        // Returns early for negative numbers, otherwise sums the odd numbers below x.
+        let get = |x| Statement::Local(Local::Get(x));
+        let set = |x| Statement::Local(Local::Set(x));
+        let stmts = vec![
+            Statement::Const(Value::I32(-1)),
+            get(0),
+            Statement::Const(Value::I32(0)),
+            Statement::Relop(Relop::I32Lt),
+            Statement::Control(Control::BrIf(0)),
+            Statement::Parametric(Parametric::Drop),
+            Statement::Block(Box::new(Block::Block {
+                id: 1,
+                t: None,
+                stmts: vec![Statement::Block(Box::new(Block::Loop {
+                    id: 2,
+                    t: None,
+                    stmts: vec![
+                        get(1),
+                        get(0),
+                        Statement::Relop(Relop::I32Ge),
+                        Statement::Control(Control::BrIf(1)),
+                        get(2),
+                        get(1),
+                        Statement::Const(Value::I32(2)),
+                        Statement::Binop(Binop::I32Rem),
+                        Statement::Block(Box::new(Block::If {
+                            id: 3,
+                            t: Some(Type::I32),
+                            then_stmts: vec![get(1)],
+                            else_stmts: vec![Statement::Const(Value::I32(0))],
+                        })),
+                        Statement::Binop(Binop::I32Add),
+                        set(2),
+                        get(1),
+                        Statement::Const(Value::I32(1)),
+                        Statement::Binop(Binop::I32Add),
+                        set(1),
+                        Statement::Control(Control::Br(2)),
+                    ],
+                }))],
+            })),
+            get(2),
+        ];
+        let mut program = program(vec![0], vec![1, 2], stmts);
+        let args = [-5, 0, 1, 10, 17];
+        let expected = args
+            .iter()
+            .map(|arg| run(&program, *arg))
+            .collect::<Vec<_>>();
+        let signatures = signatures(&program);
+        optimize(&mut program.funs[0], &signatures);
+        for (arg, expected) in args.iter().zip(expected) {
+            assert_eq!(run(&program, *arg), expected);
+        }
+    }
+
+    #[test]
+    fn fold_branches() {
+        let stmts = vec![
+            Statement::Const(Value::I32(2)),
+            Statement::Const(Value::I32(3)),
+            Statement::Binop(Binop::I32Add),
+            Statement::Const(Value::I32(5)),
+            Statement::Relop(Relop::I32Eq),
+            Statement::Block(Box::new(Block::If {
+                id: 1,
+                t: Some(Type::I32),
+                then_stmts: vec![Statement::Local(Local::Get(0))],
+                else_stmts: vec![Statement::Const(Value::I32(0))],
+            })),
+        ];
+        let mut program = program(vec![0], Vec::new(), stmts);
+        let signatures = signatures(&program);
+        optimize(&mut program.funs[0], &signatures);
+        match &program.funs[0].body {
+            Block::Block { stmts, .. } => assert!(matches!(
+                stmts.as_slice(),
+                [
+                    Statement::Local(Local::Get(0)),
+                    Statement::Control(Control::Return),
+                ]
+            )),
+            _ => panic!("Expected a block"),
+        }
+        assert_eq!(run(&program, 42), Ok(vec![RuntimeValue::I32(42)]));
+    }
+}
@@ -0,0 +1,309 @@
+//! SSA optimizations
+//!
+//! Constants are propagated and folded, branches on constants are replaced by jumps and the
+//! blocks that become unreachable are removed. Phis whose arguments are all the same value are
+//! replaced by that value, and finally the pure instructions whose results are unused are
+//! removed.
+
+use std::collections::HashMap;
+
+use super::ir::*;
+use crate::mir::{Binop, Relop, Value};
+
+/// Optimizes a function in place.
+pub fn optimize(fun: &mut Function) {
+    loop {
+        let mut changed = fold_constants(fun);
+        changed |= fold_branches(fun);
+        changed |= remove_unreachable_blocks(fun);
+        changed |= remove_trivial_phis(fun);
+        if !changed {
+            break;
+        }
+    }
+    eliminate_dead_code(fun);
+}
+
+/// Replaces the operations on constant integers by their result.
+fn fold_constants(fun: &mut Function) -> bool {
+    let mut constants = HashMap::new();
+    let mut changed = false;
+    loop {
+        let mut folded = false;
+        for block in &mut fun.blocks {
+            for inst in &mut block.insts {
+                let value = match (&inst.op, inst.args.as_slice()) {
+                    (Op::Const(value), _) => {
+                        constants.insert(inst.results[0], value.clone());
+                        continue;
+                    }
+                    (Op::Binop(binop), [x, y]) => match (constants.get(x), constants.get(y)) {
+                        (Some(x), Some(y)) => fold_binop(binop, x, y),
+                        _ => None,
+                    },
+                    (Op::Relop(relop), [x, y]) => match (constants.get(x), constants.get(y)) {
+                        (Some(x), Some(y)) => fold_relop(relop, x, y),
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                if let Some(value) = value {
+                    constants.insert(inst.results[0], value.clone());
+                    inst.op = Op::Const(value);
+                    inst.args.clear();
+                    folded = true;
+                }
+            }
+        }
+        if !folded {
+            return changed;
+        }
+        changed = true;
+    }
+}
+
+fn fold_binop(binop: &Binop, x: &Value, y: &Value) -> Option<Value> {
+    match (x, y) {
+        (Value::I32(x), Value::I32(y)) => match binop {
+            Binop::I32Add => Some(x.wrapping_add(*y)),
+            Binop::I32Sub => Some(x.wrapping_sub(*y)),
+            Binop::I32Mul => Some(x.wrapping_mul(*y)),
+            Binop::I32And => Some(x & y),
+            Binop::I32Or => Some(x | y),
+            Binop::I32Xor => Some(x ^ y),
+            _ => None,
+        }
+        .map(Value::I32),
+        (Value::I64(x), Value::I64(y)) => match binop {
+            Binop::I64Add => Some(x.wrapping_add(*y)),
+            Binop::I64Sub => Some(x.wrapping_sub(*y)),
+            Binop::I64Mul => Some(x.wrapping_mul(*y)),
+            Binop::I64And => Some(x & y),
+            Binop::I64Or => Some(x | y),
+            Binop::I64Xor => Some(x ^ y),
+            _ => None,
+        }
+        .map(Value::I64),
+        _ => None,
+    }
+}
+
+fn fold_relop(relop: &Relop, x: &Value, y: &Value) -> Option<Value> {
+    let result = match (x, y) {
+        (Value::I32(x), Value::I32(y)) => match relop {
+            Relop::I32Eq => x == y,
+            Relop::I32Ne => x != y,
+            Relop::I32Lt => x < y,
+            Relop::I32Gt => x > y,
+            Relop::I32Le => x <= y,
+            Relop::I32Ge => x >= y,
+            _ => return None,
+        },
+        (Value::I64(x), Value::I64(y)) => match relop {
+            Relop::I64Eq => x == y,
+            Relop::I64Ne => x != y,
+            Relop::I64Lt => x < y,
+            Relop::I64Gt => x > y,
+            Relop::I64Le => x <= y,
+            Relop::I64Ge => x >= y,
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some(Value::I32(result as i32))
+}
+
+/// Replaces the branches on constant conditions by jumps.
+fn fold_branches(fun: &mut Function) -> bool {
+    let constants = fun
+        .blocks
+        .iter()
+        .flat_map(|block| &block.insts)
+        .filter_map(|inst| match &inst.op {
+            Op::Const(Value::I32(x)) => Some((inst.results[0], *x)),
+            _ => None,
+        })
+        .collect::<HashMap<_, _>>();
+    let mut changed = false;
+    for block_id in 0..fun.blocks.len() {
+        let (taken, not_taken) = match &fun.blocks[block_id].terminator {
+            Terminator::Branch {
+                cond,
+                then_block,
+                else_block,
+            } => match constants.get(cond) {
+                Some(0) => (*else_block, *then_block),
+                Some(_) => (*then_block, *else_block),
+                None => continue,
+            },
+            _ => continue,
+        };
+        fun.blocks[block_id].terminator = Terminator::Jump(taken);
+        remove_pred(fun, not_taken, block_id);
+        changed = true;
+    }
+    changed
+}
+
+/// Removes the blocks which can not be reached from the entry block.
+fn remove_unreachable_blocks(fun: &mut Function) -> bool {
+    let mut reachable = vec![false; fun.blocks.len()];
+    let mut to_visit = vec![0];
+    while let Some(block) = to_visit.pop() {
+        if !reachable[block] {
+            reachable[block] = true;
+            to_visit.extend(fun.blocks[block].terminator.successors());
+        }
+    }
+
+    let mut changed = false;
+    for (block_id, block) in fun.blocks.iter_mut().enumerate() {
+        if !reachable[block_id] {
+            if !block.preds.is_empty() || !block.insts.is_empty() {
+                changed = true;
+            }
+            block.preds.clear();
+            block.phis.clear();
+            block.insts.clear();
+            block.terminator = Terminator::Unreachable;
+            continue;
+        }
+        let mut idx = 0;
+        while idx < block.preds.len() {
+            if reachable[block.preds[idx]] {
+                idx += 1;
+            } else {
+                block.preds.remove(idx);
+                for phi in &mut block.phis {
+                    phi.args.remove(idx);
+                }
+                changed = true;
+            }
+        }
+    }
+    changed
+}
+
+/// Replaces the phis whose arguments are all the same value (or the phi itself) by that value.
+fn remove_trivial_phis(fun: &mut Function) -> bool {
+    let mut aliases = HashMap::new();
+    loop {
+        let mut removed = false;
+        for block in &mut fun.blocks {
+            let mut idx = 0;
+            while idx < block.phis.len() {
+                let phi = &block.phis[idx];
+                match trivial_phi_value(phi, &aliases) {
+                    Some(value) => {
+                        aliases.insert(phi.result, value);
+                        block.phis.remove(idx);
+                        removed = true;
+                    }
+                    None => idx += 1,
+                }
+            }
+        }
+        if !removed {
+            break;
+        }
+    }
+    if aliases.is_empty() {
+        return false;
+    }
+
+    for block in &mut fun.blocks {
+        for phi in &mut block.phis {
+            for arg in &mut phi.args {
+                *arg = resolve(&aliases, *arg);
+            }
+        }
+        for inst in &mut block.insts {
+            for arg in &mut inst.args {
+                *arg = resolve(&aliases, *arg);
+            }
+        }
+        for arg in block.terminator.args_mut() {
+            *arg = resolve(&aliases, *arg);
+        }
+    }
+    true
+}
+
+/// Returns the only value a phi can take, if any.
+fn trivial_phi_value(phi: &Phi, aliases: &HashMap<ValueId, ValueId>) -> Option<ValueId> {
+    let mut same = None;
+    for arg in &phi.args {
+        let arg = resolve(aliases, *arg);
+        if arg == phi.result || Some(arg) == same {
+            continue;
+        }
+        if same.is_some() {
+            return None;
+        }
+        same = Some(arg);
+    }
+    same
+}
+
+/// Removes the pure instructions and phis whose results are not used.
+fn eliminate_dead_code(fun: &mut Function) {
+    let mut uses = vec![0; fun.values.len()];
+    for block in &fun.blocks {
+        let args = block
+            .phis
+            .iter()
+            .flat_map(|phi| &phi.args)
+            .chain(block.insts.iter().flat_map(|inst| &inst.args))
+            .copied()
+            .chain(block.terminator.args());
+        for arg in args {
+            uses[arg] += 1;
+        }
+    }
+
+    loop {
+        let mut removed = false;
+        for block in &mut fun.blocks {
+            block.phis.retain(|phi| {
+                if uses[phi.result] > 0 {
+                    return true;
+                }
+                for arg in &phi.args {
+                    uses[*arg] -= 1;
+                }
+                removed = true;
+                false
+            });
+            block.insts.retain(|inst| {
+                if !inst.op.is_pure() || inst.results.iter().any(|result| uses[*result] > 0) {
+                    return true;
+                }
+                for arg in &inst.args {
+                    uses[*arg] -= 1;
+                }
+                removed = true;
+                false
+            });
+        }
+        if !removed {
+            break;
+        }
+    }
+}
+
+fn remove_pred(fun: &mut Function, block: BlockId, pred: BlockId) {
+    let block = &mut fun.blocks[block];
+    if let Some(idx) = block.preds.iter().position(|p| *p == pred) {
+        block.preds.remove(idx);
+        for phi in &mut block.phis {
+            phi.args.remove(idx);
+        }
+    }
+}
+
+fn resolve(aliases: &HashMap<ValueId, ValueId>, mut value: ValueId) -> ValueId {
+    while let Some(alias) = aliases.get(&value) {
+        value = *alias;
+    }
+    value
+}
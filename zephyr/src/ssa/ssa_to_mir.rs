@@ -0,0 +1,455 @@
+//! SSA to MIR
+//!
+//! The control flow graph is turned back into structured control flow following "Beyond
+//! Relooper: Recursive Translation of Unstructured Control Flow to Structured Control Flow"
+//! (Ramsey): the code is generated by walking the dominator tree, a `Loop` is opened at each loop
+//! header and a `Block` ends right before each block with several forward predecessors.
+//!
+//! Pure values used once, in the block defining them, are computed right where they are used so
+//! that they stay on the stack, other values are stored in locals. Phis are stored in locals too,
+//! set on each incoming edge.
+
+use std::collections::HashMap;
+
+use super::ir::*;
+use crate::mir;
+use crate::mir::{BasicBlockId, Call, Control, Local, LocalId, LocalVariable, Statement};
+
+/// Converts a function in SSA form back into structured MIR, returns the locals of the function
+/// and its body.
+///
+/// The graph must be reducible, which is always the case for graphs built from MIR.
+pub fn to_mir(fun: &Function, params: &[LocalId]) -> (Vec<LocalVariable>, mir::Block) {
+    let mut stackifier = Stackifier::new(fun, params);
+    let mut stmts = Vec::new();
+    let body_id = stackifier.fresh_block_id();
+    stackifier.do_tree(0, &mut Vec::new(), &mut stmts);
+    if !fun.ret_t.is_empty()
+        && !matches!(
+            stmts.last(),
+            Some(Statement::Control(Control::Return))
+                | Some(Statement::Control(Control::Unreachable))
+        )
+    {
+        // All paths return, but the validator does not know it
+        stmts.push(Statement::Control(Control::Unreachable));
+    }
+    let body = mir::Block::Block {
+        id: body_id,
+        stmts,
+        t: None,
+    };
+    (stackifier.locals, body)
+}
+
+/// An enclosing construct that can be targeted by branches.
+enum Context {
+    /// A `Block` ending right before a basic block.
+    BlockFollowedBy(BlockId),
+    /// A `Loop` starting at a basic block.
+    LoopHeadedBy(BlockId),
+}
+
+struct Stackifier<'a> {
+    fun: &'a Function,
+    params: &'a [LocalId],
+    /// Reverse postorder number of the reachable blocks.
+    rpo_number: Vec<Option<usize>>,
+    /// Children of each block in the dominator tree, in reverse postorder.
+    dominated: Vec<Vec<BlockId>>,
+    is_loop_header: Vec<bool>,
+    is_merge_node: Vec<bool>,
+    /// Location of the instruction defining each value, `None` for phis.
+    defs: Vec<Option<(BlockId, usize)>>,
+    /// Values computed where they are used instead of being stored in a local.
+    is_inlined: Vec<bool>,
+    /// The local storing each value, if any.
+    value_locals: HashMap<ValueId, LocalId>,
+    locals: Vec<LocalVariable>,
+    next_local: LocalId,
+    next_block: BasicBlockId,
+}
+
+impl<'a> Stackifier<'a> {
+    fn new(fun: &'a Function, params: &'a [LocalId]) -> Self {
+        let nb_blocks = fun.blocks.len();
+        let mut stackifier = Self {
+            fun,
+            params,
+            rpo_number: vec![None; nb_blocks],
+            dominated: vec![Vec::new(); nb_blocks],
+            is_loop_header: vec![false; nb_blocks],
+            is_merge_node: vec![false; nb_blocks],
+            defs: vec![None; fun.values.len()],
+            is_inlined: vec![false; fun.values.len()],
+            value_locals: HashMap::new(),
+            locals: Vec::new(),
+            next_local: params.iter().max().map_or(0, |id| id + 1),
+            next_block: 0,
+        };
+        stackifier.analyze_control_flow();
+        stackifier.analyze_values();
+        stackifier
+    }
+
+    /// Computes the reverse postorder, the dominator tree, loop headers and merge nodes.
+    fn analyze_control_flow(&mut self) {
+        let blocks = &self.fun.blocks;
+
+        // Depth-first search from the entry block
+        let mut visited = vec![false; blocks.len()];
+        let mut postorder = Vec::new();
+        let mut to_visit = vec![(0, 0)];
+        visited[0] = true;
+        while let Some((block, succ_idx)) = to_visit.pop() {
+            let succs = blocks[block].terminator.successors();
+            if succ_idx < succs.len() {
+                to_visit.push((block, succ_idx + 1));
+                let succ = succs[succ_idx];
+                if !visited[succ] {
+                    visited[succ] = true;
+                    to_visit.push((succ, 0));
+                }
+            } else {
+                postorder.push(block);
+            }
+        }
+        let rpo = postorder.into_iter().rev().collect::<Vec<_>>();
+        for (number, block) in rpo.iter().enumerate() {
+            self.rpo_number[*block] = Some(number);
+        }
+        let rpo_number = &self.rpo_number;
+        let reachable_preds = |block: BlockId| {
+            blocks[block]
+                .preds
+                .iter()
+                .copied()
+                .filter(move |pred| rpo_number[*pred].is_some())
+        };
+
+        // Dominators, from "A Simple, Fast Dominance Algorithm" (Cooper et al.)
+        let mut idom: Vec<Option<BlockId>> = vec![None; blocks.len()];
+        idom[0] = Some(0);
+        let intersect = |idom: &Vec<Option<BlockId>>, mut a: BlockId, mut b: BlockId| {
+            while a != b {
+                while rpo_number[a] > rpo_number[b] {
+                    a = idom[a].unwrap();
+                }
+                while rpo_number[b] > rpo_number[a] {
+                    b = idom[b].unwrap();
+                }
+            }
+            a
+        };
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for block in rpo.iter().skip(1).copied() {
+                let mut new_idom = None;
+                for pred in reachable_preds(block) {
+                    if idom[pred].is_none() {
+                        continue;
+                    }
+                    new_idom = match new_idom {
+                        None => Some(pred),
+                        Some(other) => Some(intersect(&idom, pred, other)),
+                    };
+                }
+                if new_idom != idom[block] {
+                    idom[block] = new_idom;
+                    changed = true;
+                }
+            }
+        }
+
+        for block in rpo.iter().copied() {
+            if block != 0 {
+                self.dominated[idom[block].unwrap()].push(block);
+            }
+            let number = rpo_number[block];
+            let mut nb_forward_preds = 0;
+            for pred in reachable_preds(block) {
+                if rpo_number[pred] >= number {
+                    self.is_loop_header[block] = true;
+                } else {
+                    nb_forward_preds += 1;
+                }
+            }
+            self.is_merge_node[block] = nb_forward_preds > 1;
+        }
+    }
+
+    /// Decides which values are stored in locals.
+    fn analyze_values(&mut self) {
+        let fun = self.fun;
+        let mut uses = vec![0; fun.values.len()];
+        let mut use_block = vec![None; fun.values.len()];
+        for (block_id, block) in fun.blocks.iter().enumerate() {
+            if self.rpo_number[block_id].is_none() {
+                continue;
+            }
+            let mut add_use = |value: ValueId, block_id: BlockId| {
+                uses[value] += 1;
+                use_block[value] = Some(block_id);
+            };
+            for phi in &block.phis {
+                // Arguments of phis are used at the end of the predecessors
+                for (arg, pred) in phi.args.iter().zip(&block.preds) {
+                    add_use(*arg, *pred);
+                }
+            }
+            for (idx, inst) in block.insts.iter().enumerate() {
+                for result in &inst.results {
+                    self.defs[*result] = Some((block_id, idx));
+                }
+                for arg in &inst.args {
+                    add_use(*arg, block_id);
+                }
+            }
+            for arg in block.terminator.args() {
+                add_use(arg, block_id);
+            }
+        }
+
+        for (block_id, block) in fun.blocks.iter().enumerate() {
+            if self.rpo_number[block_id].is_none() {
+                continue;
+            }
+            for phi in &block.phis {
+                self.new_value_local(phi.result);
+            }
+            for inst in &block.insts {
+                match inst.op {
+                    // Parameters and constants are cheap to get again
+                    Op::Param(_) | Op::Const(_) => self.is_inlined[inst.results[0]] = true,
+                    _ if inst.op.is_pure()
+                        && inst.results.len() == 1
+                        && uses[inst.results[0]] == 1
+                        && use_block[inst.results[0]] == Some(block_id) =>
+                    {
+                        self.is_inlined[inst.results[0]] = true
+                    }
+                    _ => {
+                        for result in &inst.results {
+                            if uses[*result] > 0 {
+                                self.new_value_local(*result);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // ——————————————————————————————— Control flow ——————————————————————————————— //
+
+    fn do_tree(
+        &mut self,
+        block: BlockId,
+        context: &mut Vec<(Context, BasicBlockId)>,
+        out: &mut Vec<Statement>,
+    ) {
+        // The blocks following the ones dominated by `block`, the one appearing last comes
+        // first as it is the outermost
+        let mut merge_nodes = self.dominated[block]
+            .iter()
+            .copied()
+            .filter(|child| self.is_merge_node[*child])
+            .collect::<Vec<_>>();
+        merge_nodes.sort_by_key(|child| std::cmp::Reverse(self.rpo_number[*child]));
+
+        if self.is_loop_header[block] {
+            let id = self.fresh_block_id();
+            let mut stmts = Vec::new();
+            context.push((Context::LoopHeadedBy(block), id));
+            self.node_within(block, &merge_nodes, context, &mut stmts);
+            context.pop();
+            out.push(Statement::Block(Box::new(mir::Block::Loop {
+                id,
+                stmts,
+                t: None,
+            })));
+        } else {
+            self.node_within(block, &merge_nodes, context, out);
+        }
+    }
+
+    fn node_within(
+        &mut self,
+        block: BlockId,
+        merge_nodes: &[BlockId],
+        context: &mut Vec<(Context, BasicBlockId)>,
+        out: &mut Vec<Statement>,
+    ) {
+        if let Some((follower, merge_nodes)) = merge_nodes.split_first() {
+            let id = self.fresh_block_id();
+            let mut stmts = Vec::new();
+            context.push((Context::BlockFollowedBy(*follower), id));
+            self.node_within(block, merge_nodes, context, &mut stmts);
+            context.pop();
+            out.push(Statement::Block(Box::new(mir::Block::Block {
+                id,
+                stmts,
+                t: None,
+            })));
+            self.do_tree(*follower, context, out);
+            return;
+        }
+
+        let fun = self.fun;
+        for idx in 0..fun.blocks[block].insts.len() {
+            self.inst(block, idx, out);
+        }
+        match &fun.blocks[block].terminator {
+            Terminator::Jump(target) => self.do_branch(block, *target, context, out),
+            Terminator::Branch {
+                cond,
+                then_block,
+                else_block,
+            } => {
+                let (then_block, else_block) = (*then_block, *else_block);
+                self.value(*cond, out);
+                let id = self.fresh_block_id();
+                let mut then_stmts = Vec::new();
+                let mut else_stmts = Vec::new();
+                self.do_branch(block, then_block, context, &mut then_stmts);
+                self.do_branch(block, else_block, context, &mut else_stmts);
+                out.push(Statement::Block(Box::new(mir::Block::If {
+                    id,
+                    then_stmts,
+                    else_stmts,
+                    t: None,
+                })));
+            }
+            Terminator::Return(values) => {
+                for value in values {
+                    self.value(*value, out);
+                }
+                out.push(Statement::Control(Control::Return));
+            }
+            Terminator::Unreachable => out.push(Statement::Control(Control::Unreachable)),
+        }
+    }
+
+    fn do_branch(
+        &mut self,
+        from: BlockId,
+        to: BlockId,
+        context: &mut Vec<(Context, BasicBlockId)>,
+        out: &mut Vec<Statement>,
+    ) {
+        self.set_phis(from, to, out);
+        let target = if self.rpo_number[to] <= self.rpo_number[from] {
+            context.iter().rev().find_map(|(ctx, id)| match ctx {
+                Context::LoopHeadedBy(header) if *header == to => Some(*id),
+                _ => None,
+            })
+        } else if self.is_merge_node[to] {
+            context.iter().rev().find_map(|(ctx, id)| match ctx {
+                Context::BlockFollowedBy(follower) if *follower == to => Some(*id),
+                _ => None,
+            })
+        } else {
+            self.do_tree(to, context, out);
+            return;
+        };
+        let target = target.expect("the control flow graph is reducible");
+        out.push(Statement::Control(Control::Br(target)));
+    }
+
+    /// Sets the locals of the phis of `to` to their values coming from `from`. The new values
+    /// are all pushed on the stack before any phi is set.
+    fn set_phis(&mut self, from: BlockId, to: BlockId, out: &mut Vec<Statement>) {
+        let fun = self.fun;
+        let to = &fun.blocks[to];
+        let pred_idx = match to.preds.iter().position(|pred| *pred == from) {
+            Some(idx) => idx,
+            None => return,
+        };
+        let copies = to
+            .phis
+            .iter()
+            .map(|phi| (phi.result, phi.args[pred_idx]))
+            .filter(|(phi, arg)| phi != arg)
+            .collect::<Vec<_>>();
+        for (_, arg) in &copies {
+            self.value(*arg, out);
+        }
+        for (phi, _) in copies.iter().rev() {
+            let local = self.value_locals[phi];
+            out.push(Statement::Local(Local::Set(local)));
+        }
+    }
+
+    // ——————————————————————————————————— Values ———————————————————————————————————— //
+
+    /// Emits an instruction, unless it is computed where its result is used.
+    fn inst(&mut self, block: BlockId, idx: usize, out: &mut Vec<Statement>) {
+        let fun = self.fun;
+        let inst = &fun.blocks[block].insts[idx];
+        if inst.results.iter().any(|result| self.is_inlined[*result]) {
+            return;
+        }
+        for arg in &inst.args {
+            self.value(*arg, out);
+        }
+        out.push(op_to_statement(&inst.op));
+        for result in inst.results.iter().rev() {
+            match self.value_locals.get(result) {
+                Some(local) => out.push(Statement::Local(Local::Set(*local))),
+                None => out.push(Statement::Parametric(mir::Parametric::Drop)),
+            }
+        }
+    }
+
+    /// Pushes a value on the stack.
+    fn value(&mut self, value: ValueId, out: &mut Vec<Statement>) {
+        if let Some(local) = self.value_locals.get(&value) {
+            out.push(Statement::Local(Local::Get(*local)));
+            return;
+        }
+        let fun = self.fun;
+        let (block, idx) = self.defs[value].expect("values are defined by a phi or an instruction");
+        let inst = &fun.blocks[block].insts[idx];
+        match &inst.op {
+            Op::Param(param) => out.push(Statement::Local(Local::Get(self.params[*param]))),
+            op => {
+                for arg in &inst.args {
+                    self.value(*arg, out);
+                }
+                out.push(op_to_statement(op));
+            }
+        }
+    }
+
+    fn new_value_local(&mut self, value: ValueId) {
+        let id = self.next_local;
+        self.next_local += 1;
+        self.locals.push(LocalVariable {
+            id,
+            t: self.fun.values[value],
+        });
+        self.value_locals.insert(value, id);
+    }
+
+    fn fresh_block_id(&mut self) -> BasicBlockId {
+        let id = self.next_block;
+        self.next_block += 1;
+        id
+    }
+}
+
+fn op_to_statement(op: &Op) -> Statement {
+    match op {
+        Op::Param(_) => unreachable!("parameters are read from their local"),
+        Op::Const(value) => Statement::Const(value.clone()),
+        Op::Unop(unop) => Statement::Unop(unop.clone()),
+        Op::Binop(binop) => Statement::Binop(binop.clone()),
+        Op::Relop(relop) => Statement::Relop(relop.clone()),
+        Op::Call(fun_id) => Statement::Call(Call::Direct(*fun_id)),
+        Op::Memory(memory) => Statement::Memory(memory.clone()),
+        Op::Global(global) => Statement::Global(global.clone()),
+        Op::Simd(simd) => Statement::Simd(simd.clone()),
+        Op::Atomic(atomic) => Statement::Atomic(atomic.clone()),
+    }
+}
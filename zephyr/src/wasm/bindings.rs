@@ -0,0 +1,238 @@
+//! JavaScript Bindings
+//!
+//! Generates a small ES module which instantiates a Zephyr module and wraps its exported
+//! functions, converting JavaScript values to their Zephyr representation and back. Strings are
+//! copied into a buffer allocated with the allocator of the module, which must be exported.
+//! When the module counts references to structs, the `Str` passed to the module is preceded by a
+//! header holding a single reference.
+//!
+//! TypeScript declarations describing the wrapped functions can be generated alongside.
+
+/// Name under which the memory allocator is exported when bindings are requested, unless it is
+/// already exposed.
+pub const MALLOC_EXPORT: &str = "__zephyr_malloc";
+
+/// Offsets of the fields of a `Str` in memory, fields are laid out by name.
+const STR_LEN_OFFSET: u32 = 0;
+const STR_START_OFFSET: u32 = 4;
+const STR_SIZE: u32 = 8;
+
+/// The JavaScript representation of a value crossing the module boundary.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum JsType {
+    /// Integers, floats and pointers to structs.
+    Number,
+    /// 64 bits integers.
+    BigInt,
+    Boolean,
+    /// A `Str`, converted to and from a JavaScript string.
+    Str,
+    /// No value, only valid as a return type.
+    Void,
+}
+
+/// An exported function, its arguments and result are converted if a signature is known and
+/// passed as is otherwise.
+pub struct JsFunction {
+    pub name: String,
+    pub signature: Option<(Vec<JsType>, JsType)>,
+}
+
+/// Returns the source of an ES module wrapping the given functions, `header` is the size of the
+/// reference count preceding structs, if any.
+pub fn js_bindings(
+    funs: &[JsFunction],
+    memory_export: &str,
+    malloc_export: &str,
+    header: u32,
+) -> String {
+    let mut wrappers = String::new();
+    for fun in funs {
+        wrappers.push_str(&format!("        {:?}: {},\n", fun.name, wrapper(fun)));
+    }
+    let alloc_str = if header == 0 {
+        format!(
+            "const str = exports[{:?}]({});\n        const view = new DataView(memory.buffer);",
+            malloc_export, STR_SIZE
+        )
+    } else {
+        format!(
+            "const str = exports[{:?}]({}) + {};\n        \
+             const view = new DataView(memory.buffer);\n        \
+             view.setInt32(str - {}, 1, true);",
+            malloc_export,
+            STR_SIZE + header,
+            header,
+            header
+        )
+    };
+
+    format!(
+        r#"// Generated by the Zephyr compiler.
+
+// Instantiates the module, `source` is either the bytes of the module or a `WebAssembly.Module`.
+export async function instantiate(source, imports = {{}}) {{
+    const result = await WebAssembly.instantiate(source, imports);
+    const instance = result instanceof WebAssembly.Instance ? result : result.instance;
+    const exports = instance.exports;
+    const memory = exports[{memory:?}];
+    const encoder = new TextEncoder();
+    const decoder = new TextDecoder();
+
+    function encodeStr(string) {{
+        const bytes = encoder.encode(string);
+        const start = exports[{malloc:?}](bytes.length);
+        new Uint8Array(memory.buffer, start, bytes.length).set(bytes);
+        {alloc_str}
+        view.setInt32(str + {len}, bytes.length, true);
+        view.setInt32(str + {start}, start, true);
+        return str;
+    }}
+
+    function decodeStr(str) {{
+        const view = new DataView(memory.buffer);
+        const len = view.getInt32(str + {len}, true);
+        const start = view.getInt32(str + {start}, true);
+        return decoder.decode(new Uint8Array(memory.buffer, start, len));
+    }}
+
+    return {{
+        instance,
+{wrappers}    }};
+}}
+"#,
+        memory = memory_export,
+        malloc = malloc_export,
+        alloc_str = alloc_str,
+        len = STR_LEN_OFFSET,
+        start = STR_START_OFFSET,
+        wrappers = wrappers,
+    )
+}
+
+/// Returns TypeScript declarations for the module generated by `js_bindings`.
+pub fn ts_declarations(funs: &[JsFunction]) -> String {
+    let mut members = String::new();
+    for fun in funs {
+        members.push_str(&format!("    {:?}{};\n", fun.name, ts_signature(fun)));
+    }
+
+    format!(
+        r#"// Generated by the Zephyr compiler.
+
+export interface Exports {{
+    instance: WebAssembly.Instance;
+{members}}}
+
+export function instantiate(
+    source: BufferSource | WebAssembly.Module,
+    imports?: WebAssembly.Imports,
+): Promise<Exports>;
+"#,
+        members = members,
+    )
+}
+
+/// Returns the TypeScript signature of a wrapped function, as used in an interface.
+fn ts_signature(fun: &JsFunction) -> String {
+    let (params, ret) = match &fun.signature {
+        Some(signature) => signature,
+        None => return String::from("(...args: any[]): any"),
+    };
+    let params = params
+        .iter()
+        .enumerate()
+        .map(|(idx, t)| match t {
+            // Arguments are converted with `BigInt`, which also accepts numbers
+            JsType::BigInt => format!("arg{}: bigint | number", idx),
+            _ => format!("arg{}: {}", idx, ts_type(*t)),
+        })
+        .collect::<Vec<_>>();
+    format!("({}): {}", params.join(", "), ts_type(*ret))
+}
+
+fn ts_type(t: JsType) -> &'static str {
+    match t {
+        JsType::Number => "number",
+        JsType::BigInt => "bigint",
+        JsType::Boolean => "boolean",
+        JsType::Str => "string",
+        JsType::Void => "void",
+    }
+}
+
+/// Returns a JavaScript arrow function calling the export and converting values.
+fn wrapper(fun: &JsFunction) -> String {
+    let (params, ret) = match &fun.signature {
+        Some(signature) => signature,
+        None => return format!("(...args) => exports[{:?}](...args)", fun.name),
+    };
+    let names = (0..params.len())
+        .map(|idx| format!("arg{}", idx))
+        .collect::<Vec<_>>();
+    let args = names
+        .iter()
+        .zip(params)
+        .map(|(name, t)| match t {
+            JsType::BigInt => format!("BigInt({})", name),
+            JsType::Boolean => format!("{} ? 1 : 0", name),
+            JsType::Str => format!("encodeStr({})", name),
+            _ => name.clone(),
+        })
+        .collect::<Vec<_>>();
+    let call = format!("exports[{:?}]({})", fun.name, args.join(", "));
+    let body = match ret {
+        JsType::Boolean => format!("{} !== 0", call),
+        JsType::Str => format!("decodeStr({})", call),
+        JsType::Void => format!("{{ {}; }}", call),
+        _ => call,
+    };
+    format!("({}) => {}", names.join(", "), body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wrappers() {
+        let fun = |name: &str, signature| JsFunction {
+            name: String::from(name),
+            signature,
+        };
+        assert_eq!(
+            wrapper(&fun(
+                "greet",
+                Some((vec![JsType::Str, JsType::Boolean], JsType::Str))
+            )),
+            r#"(arg0, arg1) => decodeStr(exports["greet"](encodeStr(arg0), arg1 ? 1 : 0))"#
+        );
+        assert_eq!(
+            wrapper(&fun("log", Some((vec![JsType::BigInt], JsType::Void)))),
+            r#"(arg0) => { exports["log"](BigInt(arg0)); }"#
+        );
+        assert_eq!(
+            wrapper(&fun("pair", None)),
+            r#"(...args) => exports["pair"](...args)"#
+        );
+    }
+
+    #[test]
+    fn declarations() {
+        let funs = vec![
+            JsFunction {
+                name: String::from("greet"),
+                signature: Some((vec![JsType::Str, JsType::BigInt], JsType::Boolean)),
+            },
+            JsFunction {
+                name: String::from("pair"),
+                signature: None,
+            },
+        ];
+        let declarations = ts_declarations(&funs);
+        assert!(
+            declarations.contains(r#"    "greet"(arg0: string, arg1: bigint | number): boolean;"#)
+        );
+        assert!(declarations.contains(r#"    "pair"(...args: any[]): any;"#));
+    }
+}
@@ -0,0 +1,401 @@
+//! Component Encoding
+//!
+//! Wraps a core module into a component of the WebAssembly component model, described by a WIT
+//! world. Imported functions are lowered into core functions passed to the core module, and the
+//! exposed functions of the core module are lifted into component functions. Exposed functions
+//! are expected to follow the canonical ABI already (see the component adapters of the MIR).
+
+use std::collections::HashMap;
+
+use super::opcode::{to_leb, to_sleb, MAGIC_NUMBER};
+use super::wasm::{encode_name, WasmVec};
+use super::wit::{WitFunction, WitRecord, WitType, WitWorld};
+use crate::mir::REALLOC_EXPORT;
+
+/// Version and layer of the component binary format.
+const COMPONENT_VERSION: [u8; 4] = [0x0d, 0x00, 0x01, 0x00];
+
+/// Maximum number of flattened parameters, see the canonical ABI.
+const MAX_FLAT_PARAMS: usize = 16;
+
+// Sections
+const SEC_CORE_MODULE: u8 = 0x01;
+const SEC_CORE_INSTANCE: u8 = 0x02;
+const SEC_ALIAS: u8 = 0x06;
+const SEC_TYPE: u8 = 0x07;
+const SEC_CANON: u8 = 0x08;
+const SEC_IMPORT: u8 = 0x0a;
+const SEC_EXPORT: u8 = 0x0b;
+
+// Sorts
+const SORT_CORE: u8 = 0x00;
+const SORT_FUNC: u8 = 0x01;
+const SORT_TYPE: u8 = 0x03;
+const SORT_INSTANCE: u8 = 0x05;
+const CORE_SORT_FUNC: u8 = 0x00;
+const CORE_SORT_MEMORY: u8 = 0x02;
+const CORE_SORT_INSTANCE: u8 = 0x12;
+
+// Types
+const TYPE_BOOL: u8 = 0x7f;
+const TYPE_S32: u8 = 0x7a;
+const TYPE_S64: u8 = 0x78;
+const TYPE_F32: u8 = 0x76;
+const TYPE_F64: u8 = 0x75;
+const TYPE_STRING: u8 = 0x73;
+const TYPE_RECORD: u8 = 0x72;
+const TYPE_FUNC: u8 = 0x40;
+const TYPE_INSTANCE: u8 = 0x42;
+
+// Canonical options
+const OPT_UTF8: u8 = 0x00;
+const OPT_MEMORY: u8 = 0x03;
+const OPT_REALLOC: u8 = 0x04;
+
+/// Wraps a core module into a component implementing `world`, `memory` is the name under which
+/// the core module exports its memory.
+pub fn to_component(module: &[u8], world: &WitWorld, memory: &str) -> Result<Vec<u8>, String> {
+    let mut encoder = Encoder::new();
+
+    // Lower imports into core instances
+    let mut instantiate_args = WasmVec::new();
+    for import in &world.imports {
+        let mut decls = WasmVec::new();
+        for (idx, fun) in import.funs.iter().enumerate() {
+            if !fun.is_scalar() {
+                return Err(format!(
+                    "Function '{}' imported from '{}' can only pass scalars to be imported by a component",
+                    fun.core_name, import.core_module
+                ));
+            }
+            let mut decl = vec![0x01];
+            decl.extend(encoder.func_type(fun)?);
+            decls.extend_item(decl);
+            let mut decl = vec![0x04];
+            decl.extend(export_name(&fun.name));
+            decl.extend(&[SORT_FUNC]);
+            decl.extend(to_leb(idx as u64));
+            decls.extend_item(decl);
+        }
+        let mut instance_type = vec![TYPE_INSTANCE];
+        instance_type.extend(decls);
+        let type_idx = encoder.add_type(instance_type);
+
+        let mut import_desc = export_name(&import.module);
+        import_desc.push(SORT_INSTANCE);
+        import_desc.extend(to_leb(type_idx as u64));
+        encoder.section(SEC_IMPORT, vec_of(vec![import_desc]));
+        let instance_idx = encoder.next(SORT_INSTANCE);
+
+        let mut core_exports = WasmVec::new();
+        for fun in &import.funs {
+            let mut alias = vec![SORT_FUNC, 0x00];
+            alias.extend(to_leb(instance_idx as u64));
+            alias.extend(encode_name(&fun.name));
+            encoder.section(SEC_ALIAS, vec_of(vec![alias]));
+            let func_idx = encoder.next(SORT_FUNC);
+
+            let mut lower = vec![0x01, 0x00];
+            lower.extend(to_leb(func_idx as u64));
+            lower.extend(vec_of(Vec::new())); // No options
+            encoder.section(SEC_CANON, vec_of(vec![lower]));
+            let core_func_idx = encoder.next(CORE_SORT_FUNC);
+
+            let mut export = encode_name(&fun.core_name);
+            export.push(CORE_SORT_FUNC);
+            export.extend(to_leb(core_func_idx as u64));
+            core_exports.extend_item(export);
+        }
+        let mut instance = vec![0x01];
+        instance.extend(core_exports);
+        encoder.section(SEC_CORE_INSTANCE, vec_of(vec![instance]));
+        let core_instance_idx = encoder.next(CORE_SORT_INSTANCE);
+
+        let mut arg = encode_name(&import.core_module);
+        arg.push(CORE_SORT_INSTANCE);
+        arg.extend(to_leb(core_instance_idx as u64));
+        instantiate_args.extend_item(arg);
+    }
+
+    // Instantiate the core module
+    encoder.section(SEC_CORE_MODULE, module.to_vec());
+    let mut instance = vec![0x00];
+    instance.extend(to_leb(0)); // Module index
+    instance.extend(instantiate_args);
+    encoder.section(SEC_CORE_INSTANCE, vec_of(vec![instance]));
+    let core_instance = encoder.next(CORE_SORT_INSTANCE);
+    let memory_idx = encoder.alias_core_export(core_instance, CORE_SORT_MEMORY, memory);
+    let realloc_idx = encoder.alias_core_export(core_instance, CORE_SORT_FUNC, REALLOC_EXPORT);
+
+    // Records must be exported before being used by exported functions
+    for record in &world.records {
+        encoder.export_record(record, &world.records)?;
+    }
+
+    // Lift exposed functions
+    for fun in &world.exports {
+        let mut flat_params = 0;
+        for t in &fun.params {
+            flat_params += t.flat_len(&world.records);
+        }
+        if flat_params > MAX_FLAT_PARAMS {
+            return Err(format!(
+                "Function '{}' takes more than {} values once flattened, which is not supported by components",
+                fun.core_name, MAX_FLAT_PARAMS
+            ));
+        }
+        let core_func_idx =
+            encoder.alias_core_export(core_instance, CORE_SORT_FUNC, &fun.core_name);
+        let func_type = encoder.func_type(fun)?;
+        let type_idx = encoder.add_type(func_type);
+
+        let mut options = WasmVec::new();
+        let records = &world.records;
+        let has_string = fun
+            .params
+            .iter()
+            .chain(&fun.ret)
+            .any(|t| t.has_string(records));
+        let ret_in_memory = fun.ret.as_ref().map(|t| t.flat_len(records) > 1);
+        if has_string {
+            options.push_item(OPT_UTF8);
+        }
+        if has_string || ret_in_memory == Some(true) {
+            let mut option = vec![OPT_MEMORY];
+            option.extend(to_leb(memory_idx as u64));
+            options.extend_item(option);
+        }
+        if fun.params.iter().any(|t| t.has_string(records)) {
+            let mut option = vec![OPT_REALLOC];
+            option.extend(to_leb(realloc_idx as u64));
+            options.extend_item(option);
+        }
+        let mut lift = vec![0x00, 0x00];
+        lift.extend(to_leb(core_func_idx as u64));
+        lift.extend(options);
+        lift.extend(to_leb(type_idx as u64));
+        encoder.section(SEC_CANON, vec_of(vec![lift]));
+        let func_idx = encoder.next(SORT_FUNC);
+
+        let mut export = export_name(&fun.name);
+        export.push(SORT_FUNC);
+        export.extend(to_leb(func_idx as u64));
+        export.push(0x00); // No type ascription
+        encoder.section(SEC_EXPORT, vec_of(vec![export]));
+        encoder.next(SORT_FUNC);
+    }
+
+    Ok(encoder.finish())
+}
+
+/// Keeps track of the sections and of the index spaces of a component.
+struct Encoder {
+    bytes: Vec<u8>,
+    indices: HashMap<u8, u32>,
+    records: HashMap<String, u32>,
+}
+
+impl Encoder {
+    fn new() -> Self {
+        let mut bytes = MAGIC_NUMBER.to_le_bytes().to_vec();
+        bytes.extend(&COMPONENT_VERSION);
+        Self {
+            bytes,
+            indices: HashMap::new(),
+            records: HashMap::new(),
+        }
+    }
+
+    fn finish(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    fn section(&mut self, id: u8, content: Vec<u8>) {
+        self.bytes.push(id);
+        self.bytes.extend(to_leb(content.len() as u64));
+        self.bytes.extend(content);
+    }
+
+    /// Returns the index of the last item added to an index space. Core sorts are distinct from
+    /// the component sorts they share a byte with.
+    fn next(&mut self, sort: u8) -> u32 {
+        let counter = self.indices.entry(sort).or_insert(0);
+        *counter += 1;
+        *counter - 1
+    }
+
+    fn add_type(&mut self, t: Vec<u8>) -> u32 {
+        self.section(SEC_TYPE, vec_of(vec![t]));
+        self.next(SORT_TYPE)
+    }
+
+    fn alias_core_export(&mut self, instance: u32, sort: u8, name: &str) -> u32 {
+        let mut alias = vec![SORT_CORE, sort, 0x01];
+        alias.extend(to_leb(instance as u64));
+        alias.extend(encode_name(name));
+        self.section(SEC_ALIAS, vec_of(vec![alias]));
+        self.next(sort)
+    }
+
+    /// Defines and exports a record, along with the records it depends on.
+    fn export_record(&mut self, record: &WitRecord, records: &[WitRecord]) -> Result<u32, String> {
+        if let Some(idx) = self.records.get(&record.name) {
+            return Ok(*idx);
+        }
+        for (_, t) in &record.fields {
+            if let WitType::Record(name) = t {
+                match records.iter().find(|r| &r.name == name) {
+                    Some(dependency) => self.export_record(dependency, records)?,
+                    None => return Err(format!("Unknown record '{}'", name)),
+                };
+            }
+        }
+        let mut fields = WasmVec::new();
+        for (name, t) in &record.fields {
+            let mut field = encode_name(name);
+            field.extend(self.val_type(t)?);
+            fields.extend_item(field);
+        }
+        let mut record_type = vec![TYPE_RECORD];
+        record_type.extend(fields);
+        let type_idx = self.add_type(record_type);
+
+        let mut export = export_name(&record.name);
+        export.push(SORT_TYPE);
+        export.extend(to_leb(type_idx as u64));
+        export.push(0x00); // No type ascription
+        self.section(SEC_EXPORT, vec_of(vec![export]));
+        let idx = self.next(SORT_TYPE);
+        self.records.insert(record.name.clone(), idx);
+        Ok(idx)
+    }
+
+    fn func_type(&self, fun: &WitFunction) -> Result<Vec<u8>, String> {
+        let mut params = WasmVec::new();
+        for (idx, t) in fun.params.iter().enumerate() {
+            let mut param = encode_name(&format!("arg{}", idx));
+            param.extend(self.val_type(t)?);
+            params.extend_item(param);
+        }
+        let mut func_type = vec![TYPE_FUNC];
+        func_type.extend(params);
+        match &fun.ret {
+            Some(t) => {
+                func_type.push(0x00);
+                func_type.extend(self.val_type(t)?);
+            }
+            None => func_type.extend(&[0x01, 0x00]),
+        }
+        Ok(func_type)
+    }
+
+    fn val_type(&self, t: &WitType) -> Result<Vec<u8>, String> {
+        let t = match t {
+            WitType::S32 => TYPE_S32,
+            WitType::S64 => TYPE_S64,
+            WitType::F32 => TYPE_F32,
+            WitType::F64 => TYPE_F64,
+            WitType::Bool => TYPE_BOOL,
+            WitType::String => TYPE_STRING,
+            WitType::Record(name) => match self.records.get(name) {
+                Some(idx) => return Ok(to_sleb(*idx as i64)),
+                None => return Err(format!("Unknown record '{}'", name)),
+            },
+            WitType::Tuple(_) => {
+                return Err(String::from("Tuples are not supported by components"));
+            }
+        };
+        Ok(vec![t])
+    }
+}
+
+/// Encodes a plain import or export name.
+fn export_name(name: &str) -> Vec<u8> {
+    let mut encoded = vec![0x00];
+    encoded.extend(encode_name(name));
+    encoded
+}
+
+fn vec_of(items: Vec<Vec<u8>>) -> Vec<u8> {
+    let mut vec = WasmVec::new();
+    for item in items {
+        vec.extend_item(item);
+    }
+    vec.into_iter().collect()
+}
+
+impl WitType {
+    /// Number of core values the type is flattened into.
+    fn flat_len(&self, records: &[WitRecord]) -> usize {
+        match self {
+            WitType::String => 2,
+            WitType::Tuple(types) => types.iter().map(|t| t.flat_len(records)).sum(),
+            WitType::Record(name) => match records.iter().find(|r| &r.name == name) {
+                Some(record) => record.fields.iter().map(|(_, t)| t.flat_len(records)).sum(),
+                None => 1,
+            },
+            _ => 1,
+        }
+    }
+
+    fn has_string(&self, records: &[WitRecord]) -> bool {
+        match self {
+            WitType::String => true,
+            WitType::Tuple(types) => types.iter().any(|t| t.has_string(records)),
+            WitType::Record(name) => match records.iter().find(|r| &r.name == name) {
+                Some(record) => record.fields.iter().any(|(_, t)| t.has_string(records)),
+                None => false,
+            },
+            _ => false,
+        }
+    }
+}
+
+impl WitFunction {
+    fn is_scalar(&self) -> bool {
+        let is_scalar = |t: &WitType| {
+            matches!(
+                t,
+                WitType::S32 | WitType::S64 | WitType::F32 | WitType::F64 | WitType::Bool
+            )
+        };
+        self.params.iter().chain(&self.ret).all(is_scalar)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wasm::WitImport;
+
+    fn fun(name: &str, params: Vec<WitType>, ret: Option<WitType>) -> WitFunction {
+        WitFunction {
+            name: String::from(name),
+            core_name: String::from(name),
+            params,
+            ret,
+        }
+    }
+
+    #[test]
+    fn component() {
+        let mut world = WitWorld {
+            name: String::from("demo"),
+            records: Vec::new(),
+            imports: Vec::new(),
+            exports: vec![fun("len", vec![WitType::String], Some(WitType::S32))],
+        };
+        let component = to_component(&[], &world, "memory").unwrap();
+        assert_eq!(
+            component[..8],
+            [0x00, 0x61, 0x73, 0x6d, 0x0d, 0x00, 0x01, 0x00]
+        );
+
+        world.imports.push(WitImport {
+            module: String::from("env"),
+            core_module: String::from("env"),
+            funs: vec![fun("log", vec![WitType::String], None)],
+        });
+        assert!(to_component(&[], &world, "memory").is_err());
+    }
+}
@@ -0,0 +1,91 @@
+//! Custom Sections
+//!
+//! Custom sections are ignored by runtimes, the compiler uses them to embed build metadata into
+//! the modules it produces. This module also reads them back from existing modules.
+
+use super::opcode::*;
+use super::sections::SectionCustom;
+use super::validate::Reader;
+
+/// Name of the section holding the version of the compiler.
+pub const COMPILER_SECTION: &str = "zephyr.compiler";
+/// Name of the section holding the name of the package.
+pub const PACKAGE_SECTION: &str = "zephyr.package";
+/// Name of the section holding the hash of the preceding bytes of the module.
+pub const HASH_SECTION: &str = "zephyr.hash";
+
+/// Encodes a custom section, it can be appended to an encoded module.
+pub fn encode_custom_section(name: &str, content: &[u8]) -> Vec<u8> {
+    SectionCustom::new(String::from(name), content.to_vec()).encode()
+}
+
+/// Hashes the content of a module using 64 bits FNV-1a.
+///
+/// The hash is only used to identify builds, it is not suitable for cryptographic usage.
+pub fn content_hash(bytes: &[u8]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const PRIME: u64 = 0x100000001b3;
+
+    bytes.iter().fold(OFFSET_BASIS, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(PRIME)
+    })
+}
+
+/// Returns the name and content of the custom sections of a binary module, in order.
+pub fn custom_sections(module: &[u8]) -> Result<Vec<(String, Vec<u8>)>, String> {
+    let mut reader = Reader::new(module);
+    if reader.bytes(4).ok() != Some(&MAGIC_NUMBER.to_le_bytes()[..]) {
+        return Err(String::from("Not a wasm module: invalid magic number"));
+    }
+    if reader.bytes(4)? != VERSION.to_le_bytes() {
+        return Err(String::from("Unsupported wasm version"));
+    }
+
+    let mut sections = Vec::new();
+    while !reader.is_empty() {
+        let id = reader.byte()?;
+        let size = reader.u32()? as usize;
+        let mut section = Reader::new(reader.bytes(size)?);
+        if id == SEC_CUSTOM {
+            let name = section.name()?;
+            let mut content = Vec::new();
+            while !section.is_empty() {
+                content.push(section.byte()?);
+            }
+            sections.push((name, content));
+        }
+    }
+    Ok(sections)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::sections::{Module, SectionData};
+    use super::*;
+
+    #[test]
+    fn read_custom_sections() {
+        let mut module = Module::new(&[], Vec::new(), Vec::new(), SectionData::new());
+        module.add_custom_section(String::from(COMPILER_SECTION), b"0.1.0".to_vec());
+        let mut bytecode = module.encode();
+        let hash = format!("{:016x}", content_hash(&bytecode));
+        bytecode.extend(encode_custom_section(HASH_SECTION, hash.as_bytes()));
+
+        let sections = custom_sections(&bytecode).unwrap();
+        assert_eq!(
+            sections,
+            vec![
+                (String::from(COMPILER_SECTION), b"0.1.0".to_vec()),
+                (String::from(HASH_SECTION), hash.into_bytes()),
+            ]
+        );
+        assert!(custom_sections(b"\0elf\x01\0\0\0").is_err());
+        assert!(custom_sections(&bytecode[..bytecode.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn fnv_hash() {
+        assert_eq!(content_hash(b""), 0xcbf29ce484222325);
+        assert_eq!(content_hash(b"a"), 0xaf63dc4c8601ec8c);
+    }
+}
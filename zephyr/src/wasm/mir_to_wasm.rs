@@ -1,5 +1,6 @@
 use super::opcode::*;
 use super::sections;
+use super::validate;
 use super::wasm;
 use crate::error::ErrorHandler;
 use crate::hir;
@@ -12,17 +13,23 @@ type LocalsMap = HashMap<mir::LocalId, usize>;
 type BlocksMap = HashMap<mir::BasicBlockId, usize>;
 type FunctionsMap = HashMap<hir::FunId, usize>;
 type OffsetMap = HashMap<hir::DataId, wasm::Offset>;
+type SignaturesMap = HashMap<mir::Signature, usize>;
 
 /// State globally availlable, which contains functions and global variables.
 struct GlobalState {
     funs: FunctionsMap,
     offsets: OffsetMap,
+    /// Index of the functions in the table.
+    table: FunctionsMap,
+    /// Type index of the signatures used by indirect calls.
+    signatures: SignaturesMap,
 }
 
 impl GlobalState {
     pub fn new(
         funs: &Vec<mir::Function>,
         imports: &Vec<mir::Imports>,
+        table: &[hir::FunId],
         offsets: OffsetMap,
     ) -> GlobalState {
         let mut fun_map = HashMap::new();
@@ -36,9 +43,25 @@ impl GlobalState {
         for (idx, fun) in funs.iter().enumerate() {
             fun_map.insert(fun.fun_id, idx + fun_idx);
         }
+        let table = table
+            .iter()
+            .enumerate()
+            .map(|(idx, fun_id)| (*fun_id, idx))
+            .collect();
+        let mut signatures = HashMap::new();
+        for fun in funs {
+            fun.body.visit_statements(&mut |stmt| {
+                if let mir::Statement::Call(mir::Call::Indirect(signature)) = stmt {
+                    let idx = signatures.len();
+                    signatures.entry(signature.clone()).or_insert(idx);
+                }
+            });
+        }
         GlobalState {
             funs: fun_map,
             offsets,
+            table,
+            signatures,
         }
     }
 }
@@ -81,52 +104,91 @@ impl<'a> LocalState<'a> {
 /// Convert MIR to the final wasm output.
 pub struct Compiler<'err, E: ErrorHandler> {
     err: &'err mut E,
+    validate: bool,
+    /// Available once the module-level items have been emitted.
+    global_state: Option<GlobalState>,
+    funs: Vec<wasm::Function>,
+    imports: Vec<wasm::Import>,
+    data: sections::SectionData,
+    memory: mir::MemoryLimits,
+    exports: mir::ModuleExports,
+    start: Option<u64>,
+    /// Initial value of the stack pointer, if the shadow stack is used.
+    stack_pointer: Option<u32>,
+    /// Function indices of the table elements.
+    table: Vec<u64>,
+    /// Types used by indirect calls, they come first in the type section.
+    signatures: Vec<wasm::Signature>,
+    /// Function names, by index, to report validation errors.
+    names: Vec<String>,
 }
 
 impl<'err, E: ErrorHandler> Compiler<'err, E> {
-    pub fn new(error_handler: &'err mut E) -> Self {
-        Compiler { err: error_handler }
+    pub fn new(error_handler: &'err mut E, validate: bool) -> Self {
+        Compiler {
+            err: error_handler,
+            validate,
+            global_state: None,
+            funs: Vec::new(),
+            imports: Vec::new(),
+            data: sections::SectionData::new(),
+            memory: mir::MemoryLimits::default(),
+            exports: mir::ModuleExports::default(),
+            start: None,
+            stack_pointer: None,
+            table: Vec::new(),
+            signatures: Vec::new(),
+            names: Vec::new(),
+        }
     }
 
-    pub fn compile(&mut self, mir: mir::Program) -> Vec<Instr> {
-        let (data_section, offsets) = self.initialize_data(mir.data);
-        let global_state = GlobalState::new(&mir.funs, &mir.imports, offsets);
-        let mut funs = Vec::new();
-        let mut imports = Vec::new();
-        for fun in mir.funs {
-            funs.push(self.function(fun, &global_state));
+    /// Validates the generated module, errors are reported as internal errors.
+    fn validate(&mut self, bytecode: &[u8], names: &[String]) {
+        if let Err(e) = validate::validate(bytecode) {
+            let fun = e.fun_idx.and_then(|idx| names.get(idx as usize));
+            self.err.report_internal_no_loc(match fun {
+                Some(fun) => format!(
+                    "Generated invalid wasm for function '{}': {}",
+                    fun, e.message
+                ),
+                None => format!("Generated invalid wasm module: {}", e.message),
+            });
         }
-        for module_imports in mir.imports {
-            imports.extend(self.module_imports(module_imports));
-        }
-
-        let module = sections::Module::new(funs, imports, data_section);
-        module.encode()
     }
 
+    /// Lays out the data in memory, then writes the addresses of the data they point to.
     fn initialize_data(
-        &self,
-        mir_data: HashMap<mir::DataId, mir::Data>,
+        &mut self,
+        mir_data: &HashMap<mir::DataId, mir::Data>,
     ) -> (sections::SectionData, OffsetMap) {
         let mut data_section = sections::SectionData::new();
         let mut offsets = HashMap::with_capacity(mir_data.len());
-        for (data_id, data) in mir_data {
-            let offset = data_section.add_data_segment(data);
-            offsets.insert(data_id, offset);
+        // Data are laid out by ID for reproducible builds
+        let mut mir_data = mir_data.iter().collect::<Vec<_>>();
+        mir_data.sort_by_key(|(data_id, _)| *data_id);
+        for (data_id, data) in &mir_data {
+            let offset = data_section.reserve(data.bytes.len() as mir::Offset);
+            offsets.insert(**data_id, offset);
+        }
+        for (data_id, data) in &mir_data {
+            match data.relocate(&offsets) {
+                Ok(bytes) => data_section.add_data_segment(offsets[data_id], bytes),
+                Err(err) => self.err.report_internal_no_loc(err),
+            }
         }
         (data_section, offsets)
     }
 
     /// Compiles a set of MIR module imports to a list of wasm imports.
-    fn module_imports(&mut self, module_imports: mir::Imports) -> Vec<wasm::Import> {
+    fn module_imports(&mut self, module_imports: &mir::Imports) -> Vec<wasm::Import> {
         let mut imports = Vec::with_capacity(module_imports.prototypes.len());
-        for import in module_imports.prototypes {
+        for import in &module_imports.prototypes {
             imports.push(self.import(module_imports.from.clone(), import));
         }
         imports
     }
 
-    fn import(&mut self, module: String, proto: mir::FunctionPrototype) -> wasm::Import {
+    fn import(&mut self, module: String, proto: &mir::FunctionPrototype) -> wasm::Import {
         let mut params = Vec::new();
         let mut results = Vec::new();
 
@@ -135,17 +197,18 @@ impl<'err, E: ErrorHandler> Compiler<'err, E> {
             params.push(t);
         }
 
-        for t in proto.ret_t {
-            results.push(mir_t_to_wasm(t));
+        for t in &proto.ret_t {
+            results.push(mir_t_to_wasm(*t));
         }
 
         wasm::Import {
             module,
-            name: proto.ident,
-            param_types: params,
-            ret_types: results,
-            kind: KIND_FUNC,
-            type_idx: std::usize::MAX,
+            name: proto.ident.clone(),
+            desc: wasm::ImportDesc::Func {
+                param_types: params,
+                ret_types: results,
+                type_idx: std::usize::MAX,
+            },
         }
     }
 
@@ -187,15 +250,21 @@ impl<'err, E: ErrorHandler> Compiler<'err, E> {
             locals_map.insert(*param, idx);
             idx += 1;
         }
+        // Consecutive locals of the same type are declared together
         for local in &fun.locals {
-            let t = mir_t_to_wasm(local.t);
-            local_decl.push(0x1); // TODO: compress locals of same types
-            local_decl.push(type_to_bytes(t));
+            let t = type_to_bytes(mir_t_to_wasm(local.t));
+            match local_decl.last_mut() {
+                Some((count, last_t)) if *last_t == t => *count += 1,
+                _ => local_decl.push((1, t)),
+            }
             locals_map.insert(local.id, idx);
             idx += 1;
         }
-        code.extend(to_leb(fun.locals.len() as u64));
-        code.extend(local_decl);
+        code.extend(to_leb(local_decl.len() as u64));
+        for (count, t) in local_decl {
+            code.extend(to_leb(count));
+            code.push(t);
+        }
     }
 
     fn body(&mut self, block: mir::Block, s: &mut LocalState, code: &mut Vec<Instr>) {
@@ -280,6 +349,11 @@ impl<'err, E: ErrorHandler> Compiler<'err, E> {
                         code.push(INSTR_LOCAL_GET);
                         code.extend(to_leb(local_idx as u64));
                     }
+                    mir::Local::Tee(l_id) => {
+                        let local_idx = s.locals[&l_id];
+                        code.push(INSTR_LOCAL_TEE);
+                        code.extend(to_leb(local_idx as u64));
+                    }
                 },
                 mir::Statement::Const(val) => match val {
                     mir::Value::I32(x) => {
@@ -298,11 +372,21 @@ impl<'err, E: ErrorHandler> Compiler<'err, E> {
                         code.push(INSTR_F64_CST);
                         code.extend(x.to_le_bytes().iter());
                     }
+                    mir::Value::V128(x) => {
+                        code.push(INSTR_SIMD_PREFIX);
+                        code.extend(to_leb(SIMD_V128_CONST as u64));
+                        code.extend(x.to_le_bytes().iter());
+                    }
                     mir::Value::DataPointer(data_id) => {
                         let offset = *s.global_state.offsets.get(&data_id).unwrap();
                         code.push(INSTR_I32_CST);
                         code.extend(to_sleb(offset as i64));
                     }
+                    mir::Value::TableIndex(fun_id) => {
+                        let idx = s.global_state.table[&fun_id];
+                        code.push(INSTR_I32_CST);
+                        code.extend(to_sleb(idx as i64));
+                    }
                 },
                 mir::Statement::Control(cntrl) => match cntrl {
                     mir::Control::Return => code.push(INSTR_RETURN),
@@ -318,20 +402,38 @@ impl<'err, E: ErrorHandler> Compiler<'err, E> {
                 },
                 mir::Statement::Block(block) => self.block(*block, s, code),
                 mir::Statement::Binop(binop) => code.push(get_binop(binop)),
-                mir::Statement::Unop(unop) => code.push(get_unop(unop)),
+                mir::Statement::Unop(unop) => match get_trunc_sat(&unop) {
+                    Some(instr) => {
+                        code.push(INSTR_MISC_PREFIX);
+                        code.extend(to_leb(instr as u64));
+                    }
+                    None => code.push(get_unop(unop)),
+                },
                 mir::Statement::Relop(relop) => code.push(get_relop(relop)),
                 mir::Statement::Call(call) => match call {
                     mir::Call::Direct(fun_id) => {
                         code.push(INSTR_CALL);
                         code.extend(to_leb(s.get_fun(fun_id) as u64));
                     }
-                    mir::Call::Indirect() => self
-                        .err
-                        .report_internal_no_loc(String::from("Indirect call not yet implemented")),
+                    mir::Call::Indirect(signature) => {
+                        code.push(INSTR_CALL_INDIRECT);
+                        code.extend(to_leb(s.global_state.signatures[&signature] as u64));
+                        code.push(0x00); // Table index
+                    }
                 },
                 mir::Statement::Parametric(param) => match param {
                     mir::Parametric::Drop => code.push(INSTR_DROP),
                 },
+                mir::Statement::Global(global) => match global {
+                    mir::Global::Get(g_id) => {
+                        code.push(INSTR_GLOBAL_GET);
+                        code.extend(to_leb(g_id as u64));
+                    }
+                    mir::Global::Set(g_id) => {
+                        code.push(INSTR_GLOBAL_SET);
+                        code.extend(to_leb(g_id as u64));
+                    }
+                },
                 mir::Statement::Memory(mem) => match mem {
                     mir::Memory::Size => {
                         code.push(INSTR_MEMORY_SIZE);
@@ -401,19 +503,192 @@ impl<'err, E: ErrorHandler> Compiler<'err, E> {
                         code.extend(to_leb(align as u64));
                         code.extend(to_leb(offset as u64));
                     }
+                    mir::Memory::V128Load { align, offset } => {
+                        code.push(INSTR_SIMD_PREFIX);
+                        code.extend(to_leb(SIMD_V128_LOAD as u64));
+                        code.extend(to_leb(align as u64));
+                        code.extend(to_leb(offset as u64));
+                    }
+                    mir::Memory::V128Store { align, offset } => {
+                        code.push(INSTR_SIMD_PREFIX);
+                        code.extend(to_leb(SIMD_V128_STORE as u64));
+                        code.extend(to_leb(align as u64));
+                        code.extend(to_leb(offset as u64));
+                    }
                     mir::Memory::Nop => (),
                 },
+                mir::Statement::Simd(simd) => {
+                    code.push(INSTR_SIMD_PREFIX);
+                    code.extend(to_leb(get_simd(&simd) as u64));
+                    match simd {
+                        mir::Simd::ExtractLane(_, lane) | mir::Simd::ReplaceLane(_, lane) => {
+                            code.push(lane)
+                        }
+                        _ => (),
+                    }
+                }
+                mir::Statement::Atomic(atomic) => {
+                    code.push(INSTR_ATOMIC_PREFIX);
+                    code.extend(to_leb(get_atomic(&atomic) as u64));
+                    if atomic == mir::Atomic::Fence {
+                        // Reserved byte
+                        code.push(0x00);
+                    } else {
+                        code.extend(to_leb(atomic.align() as u64));
+                        code.extend(to_leb(atomic.offset() as u64));
+                    }
+                }
+                mir::Statement::Raw(bytes) => code.extend(bytes),
             }
         }
     }
 }
 
+impl<'err, E: ErrorHandler> Compiler<'err, E> {
+    /// Returns the size in bytes of the code of each emitted function, in order of emission.
+    pub fn code_sizes(self) -> Result<Vec<usize>, ()> {
+        if self.err.has_error() {
+            return Err(());
+        }
+        Ok(self
+            .funs
+            .iter()
+            .map(|fun| to_leb(fun.body.len() as u64).len() + fun.body.len())
+            .collect())
+    }
+}
+
+impl<'err, E: ErrorHandler> mir::Backend for Compiler<'err, E> {
+    type Output = Vec<Instr>;
+
+    fn emit_module(&mut self, program: &mir::Program) {
+        let (mut data_section, offsets) = self.initialize_data(&program.data);
+        data_section.set_allocator(program.allocator);
+        let stack_size = program.stack_size();
+        if stack_size > 0 {
+            // The shadow stack follows the data and grows downward
+            self.stack_pointer = Some(data_section.reserve(stack_size) + stack_size);
+        }
+        let memory = program.memory;
+        if data_section.size() as u64 > memory.initial as u64 * wasm::PAGE_SIZE as u64 {
+            self.err.report_no_loc(format!(
+                "Static data ({} bytes) does not fit in the initial memory ({} pages)",
+                data_section.size(),
+                memory.initial
+            ));
+        }
+        let global_state =
+            GlobalState::new(&program.funs, &program.imports, &program.table, offsets);
+        self.names = program
+            .imports
+            .iter()
+            .flat_map(|import| import.prototypes.iter().map(|proto| proto.ident.clone()))
+            .chain(program.funs.iter().map(|fun| fun.ident.clone()))
+            .collect();
+        for module_imports in &program.imports {
+            let imports = self.module_imports(module_imports);
+            self.imports.extend(imports);
+        }
+        self.start = program.start.map(|start| global_state.funs[&start] as u64);
+        self.table = program
+            .table
+            .iter()
+            .map(|fun_id| global_state.funs[fun_id] as u64)
+            .collect();
+        let mut signatures = global_state.signatures.iter().collect::<Vec<_>>();
+        signatures.sort_by_key(|(_, idx)| **idx);
+        self.signatures = signatures
+            .into_iter()
+            .map(|(signature, _)| {
+                (
+                    signature.params.iter().map(|t| mir_t_to_wasm(*t)).collect(),
+                    signature.ret_t.iter().map(|t| mir_t_to_wasm(*t)).collect(),
+                )
+            })
+            .collect();
+        self.data = data_section;
+        self.memory = memory;
+        self.exports = program.exports.clone();
+        self.global_state = Some(global_state);
+    }
+
+    fn emit_function(&mut self, fun: mir::Function) {
+        let global_state = match self.global_state.take() {
+            Some(global_state) => global_state,
+            None => {
+                self.err.report_internal_no_loc(String::from(
+                    "Functions must be emitted after the module",
+                ));
+                return;
+            }
+        };
+        let fun = self.function(fun, &global_state);
+        self.funs.push(fun);
+        self.global_state = Some(global_state);
+    }
+
+    fn finish(mut self) -> Result<Vec<Instr>, ()> {
+        if self.err.has_error() {
+            return Err(());
+        }
+        let data = std::mem::replace(&mut self.data, sections::SectionData::new());
+        let funs = std::mem::take(&mut self.funs);
+        let imports = std::mem::take(&mut self.imports);
+        let mut module = sections::Module::new(&self.signatures, funs, imports, data);
+        module.set_memory(match self.memory {
+            mir::MemoryLimits {
+                initial,
+                max: Some(max),
+                shared: true,
+            } => wasm::Limit::Shared(initial, max),
+            mir::MemoryLimits {
+                initial,
+                max: Some(max),
+                ..
+            } => wasm::Limit::MinMax(initial, max),
+            mir::MemoryLimits { initial, .. } => wasm::Limit::Min(initial),
+        });
+        module.set_memory_export(self.exports.memory.take());
+        if !self.table.is_empty() || self.exports.table.is_some() {
+            let size = self.table.len() as u32;
+            module.add_table(wasm::Limit::Min(size));
+            if !self.table.is_empty() {
+                let funs = std::mem::take(&mut self.table);
+                module.add_element_segment(wasm::ElementSegment::new(0, 0, funs));
+            }
+        }
+        if let Some(name) = &self.exports.table {
+            module.add_export(name, KIND_TABLE, 0);
+        }
+        if let Some(start) = self.start {
+            module.set_start(start);
+        }
+        if let Some(stack_pointer) = self.stack_pointer {
+            module.add_global(wasm::Global {
+                t: wasm::Type::I32,
+                mutable: true,
+                init: wasm::ConstExpr::I32(stack_pointer as i32),
+            });
+        }
+        let bytecode = module.encode();
+        if self.validate {
+            let names = std::mem::take(&mut self.names);
+            self.validate(&bytecode, &names);
+        }
+        if self.err.has_error() {
+            return Err(());
+        }
+        Ok(bytecode)
+    }
+}
+
 fn get_binop(binop: mir::Binop) -> Instr {
     match binop {
         mir::Binop::I32Add => INSTR_I32_ADD,
         mir::Binop::I32Sub => INSTR_I32_SUB,
         mir::Binop::I32Mul => INSTR_I32_MUL,
         mir::Binop::I32Div => INSTR_I32_DIV_U,
+        mir::Binop::I32DivS => INSTR_I32_DIV_S,
         mir::Binop::I32Rem => INSTR_I32_REM_U,
         mir::Binop::I32Xor => INSTR_I32_XOR,
         mir::Binop::I32And => INSTR_I32_AND,
@@ -423,6 +698,7 @@ fn get_binop(binop: mir::Binop) -> Instr {
         mir::Binop::I64Sub => INSTR_I64_SUB,
         mir::Binop::I64Mul => INSTR_I64_MUL,
         mir::Binop::I64Div => INSTR_I64_DIV_U,
+        mir::Binop::I64DivS => INSTR_I64_DIV_S,
         mir::Binop::I64Rem => INSTR_I64_REM_U,
         mir::Binop::I64Xor => INSTR_I64_XOR,
         mir::Binop::I64And => INSTR_I64_AND,
@@ -432,11 +708,17 @@ fn get_binop(binop: mir::Binop) -> Instr {
         mir::Binop::F32Sub => INSTR_F32_SUB,
         mir::Binop::F32Mul => INSTR_F32_MUL,
         mir::Binop::F32Div => INSTR_F32_DIV,
+        mir::Binop::F32Min => INSTR_F32_MIN,
+        mir::Binop::F32Max => INSTR_F32_MAX,
+        mir::Binop::F32Copysign => INSTR_F32_COPYSIGN,
 
         mir::Binop::F64Add => INSTR_F64_ADD,
         mir::Binop::F64Sub => INSTR_F64_SUB,
         mir::Binop::F64Mul => INSTR_F64_MUL,
         mir::Binop::F64Div => INSTR_F64_DIV,
+        mir::Binop::F64Min => INSTR_F64_MIN,
+        mir::Binop::F64Max => INSTR_F64_MAX,
+        mir::Binop::F64Copysign => INSTR_F64_COPYSIGN,
     }
 }
 
@@ -450,11 +732,57 @@ fn get_unop(unop: mir::Unop) -> Instr {
         //   > in two’s complement representation.
         //
         // https://www.w3.org/TR/wasm-core-1/#concepts%E2%91%A0
+        mir::Unop::I32Clz => INSTR_I32_CLZ,
+        mir::Unop::I32Ctz => INSTR_I32_CTZ,
+        mir::Unop::I32Popcnt => INSTR_I32_POPCNT,
+        mir::Unop::I64Clz => INSTR_I64_CLZ,
+        mir::Unop::I64Ctz => INSTR_I64_CTZ,
+        mir::Unop::I64Popcnt => INSTR_I64_POPCNT,
         mir::Unop::F32Neg => INSTR_F32_NEG,
+        mir::Unop::F32Abs => INSTR_F32_ABS,
+        mir::Unop::F32Sqrt => INSTR_F32_SQRT,
+        mir::Unop::F32Ceil => INSTR_F32_CEIL,
+        mir::Unop::F32Floor => INSTR_F32_FLOOR,
+        mir::Unop::F32Trunc => INSTR_F32_TRUNC,
+        mir::Unop::F32Nearest => INSTR_F32_NEAREST,
         mir::Unop::F64Neg => INSTR_F64_NEG,
+        mir::Unop::F64Abs => INSTR_F64_ABS,
+        mir::Unop::F64Sqrt => INSTR_F64_SQRT,
+        mir::Unop::F64Ceil => INSTR_F64_CEIL,
+        mir::Unop::F64Floor => INSTR_F64_FLOOR,
+        mir::Unop::F64Trunc => INSTR_F64_TRUNC,
+        mir::Unop::F64Nearest => INSTR_F64_NEAREST,
+        mir::Unop::I32ReinterpretF32 => INSTR_I32_REINTERPRET_F32,
+        mir::Unop::I64ReinterpretF64 => INSTR_I64_REINTERPRET_F64,
+        mir::Unop::F32ReinterpretI32 => INSTR_F32_REINTERPRET_I32,
+        mir::Unop::F64ReinterpretI64 => INSTR_F64_REINTERPRET_I64,
+        mir::Unop::I32TruncSatF32S
+        | mir::Unop::I32TruncSatF32U
+        | mir::Unop::I32TruncSatF64S
+        | mir::Unop::I32TruncSatF64U
+        | mir::Unop::I64TruncSatF32S
+        | mir::Unop::I64TruncSatF32U
+        | mir::Unop::I64TruncSatF64S
+        | mir::Unop::I64TruncSatF64U => unreachable!("saturating truncations are prefixed"),
     }
 }
 
+/// Returns the opcode following `INSTR_MISC_PREFIX` for the saturating truncations.
+fn get_trunc_sat(unop: &mir::Unop) -> Option<MiscInstr> {
+    let instr = match unop {
+        mir::Unop::I32TruncSatF32S => MISC_I32_TRUNC_SAT_F32_S,
+        mir::Unop::I32TruncSatF32U => MISC_I32_TRUNC_SAT_F32_U,
+        mir::Unop::I32TruncSatF64S => MISC_I32_TRUNC_SAT_F64_S,
+        mir::Unop::I32TruncSatF64U => MISC_I32_TRUNC_SAT_F64_U,
+        mir::Unop::I64TruncSatF32S => MISC_I64_TRUNC_SAT_F32_S,
+        mir::Unop::I64TruncSatF32U => MISC_I64_TRUNC_SAT_F32_U,
+        mir::Unop::I64TruncSatF64S => MISC_I64_TRUNC_SAT_F64_S,
+        mir::Unop::I64TruncSatF64U => MISC_I64_TRUNC_SAT_F64_U,
+        _ => return None,
+    };
+    Some(instr)
+}
+
 fn get_relop(relop: mir::Relop) -> Instr {
     match relop {
         mir::Relop::I32Eq => INSTR_I32_EQ,
@@ -487,11 +815,108 @@ fn get_relop(relop: mir::Relop) -> Instr {
     }
 }
 
+fn get_atomic(atomic: &mir::Atomic) -> AtomicInstr {
+    use mir::{Atomic, RmwOp};
+    let wide = atomic.align() == 3;
+    match atomic {
+        Atomic::Load { .. } if wide => ATOMIC_I64_LOAD,
+        Atomic::Load { .. } => ATOMIC_I32_LOAD,
+        Atomic::Store { .. } if wide => ATOMIC_I64_STORE,
+        Atomic::Store { .. } => ATOMIC_I32_STORE,
+        Atomic::Rmw { op, .. } => match (op, wide) {
+            (RmwOp::Add, false) => ATOMIC_I32_RMW_ADD,
+            (RmwOp::Add, true) => ATOMIC_I64_RMW_ADD,
+            (RmwOp::Sub, false) => ATOMIC_I32_RMW_SUB,
+            (RmwOp::Sub, true) => ATOMIC_I64_RMW_SUB,
+            (RmwOp::And, false) => ATOMIC_I32_RMW_AND,
+            (RmwOp::And, true) => ATOMIC_I64_RMW_AND,
+            (RmwOp::Or, false) => ATOMIC_I32_RMW_OR,
+            (RmwOp::Or, true) => ATOMIC_I64_RMW_OR,
+            (RmwOp::Xor, false) => ATOMIC_I32_RMW_XOR,
+            (RmwOp::Xor, true) => ATOMIC_I64_RMW_XOR,
+            (RmwOp::Xchg, false) => ATOMIC_I32_RMW_XCHG,
+            (RmwOp::Xchg, true) => ATOMIC_I64_RMW_XCHG,
+        },
+        Atomic::Cmpxchg { .. } if wide => ATOMIC_I64_RMW_CMPXCHG,
+        Atomic::Cmpxchg { .. } => ATOMIC_I32_RMW_CMPXCHG,
+        Atomic::Notify { .. } => ATOMIC_NOTIFY,
+        Atomic::Wait { .. } if wide => ATOMIC_WAIT64,
+        Atomic::Wait { .. } => ATOMIC_WAIT32,
+        Atomic::Fence => ATOMIC_FENCE,
+    }
+}
+
+fn get_simd(simd: &mir::Simd) -> SimdInstr {
+    use mir::Shape;
+    match simd {
+        mir::Simd::Splat(shape) => match shape {
+            Shape::I8x16 => SIMD_I8X16_SPLAT,
+            Shape::I16x8 => SIMD_I16X8_SPLAT,
+            Shape::I32x4 => SIMD_I32X4_SPLAT,
+            Shape::I64x2 => SIMD_I64X2_SPLAT,
+            Shape::F32x4 => SIMD_F32X4_SPLAT,
+            Shape::F64x2 => SIMD_F64X2_SPLAT,
+        },
+        mir::Simd::ExtractLane(shape, _) => match shape {
+            Shape::I8x16 => SIMD_I8X16_EXTRACT_LANE_U,
+            Shape::I16x8 => SIMD_I16X8_EXTRACT_LANE_U,
+            Shape::I32x4 => SIMD_I32X4_EXTRACT_LANE,
+            Shape::I64x2 => SIMD_I64X2_EXTRACT_LANE,
+            Shape::F32x4 => SIMD_F32X4_EXTRACT_LANE,
+            Shape::F64x2 => SIMD_F64X2_EXTRACT_LANE,
+        },
+        mir::Simd::ReplaceLane(shape, _) => match shape {
+            Shape::I8x16 => SIMD_I8X16_REPLACE_LANE,
+            Shape::I16x8 => SIMD_I16X8_REPLACE_LANE,
+            Shape::I32x4 => SIMD_I32X4_REPLACE_LANE,
+            Shape::I64x2 => SIMD_I64X2_REPLACE_LANE,
+            Shape::F32x4 => SIMD_F32X4_REPLACE_LANE,
+            Shape::F64x2 => SIMD_F64X2_REPLACE_LANE,
+        },
+        mir::Simd::Add(shape) => match shape {
+            Shape::I8x16 => SIMD_I8X16_ADD,
+            Shape::I16x8 => SIMD_I16X8_ADD,
+            Shape::I32x4 => SIMD_I32X4_ADD,
+            Shape::I64x2 => SIMD_I64X2_ADD,
+            Shape::F32x4 => SIMD_F32X4_ADD,
+            Shape::F64x2 => SIMD_F64X2_ADD,
+        },
+        mir::Simd::Sub(shape) => match shape {
+            Shape::I8x16 => SIMD_I8X16_SUB,
+            Shape::I16x8 => SIMD_I16X8_SUB,
+            Shape::I32x4 => SIMD_I32X4_SUB,
+            Shape::I64x2 => SIMD_I64X2_SUB,
+            Shape::F32x4 => SIMD_F32X4_SUB,
+            Shape::F64x2 => SIMD_F64X2_SUB,
+        },
+        mir::Simd::Mul(shape) => match shape {
+            // There is no i8x16.mul, it is rejected by the resolver
+            Shape::I8x16 => panic!("i8x16.mul does not exist"),
+            Shape::I16x8 => SIMD_I16X8_MUL,
+            Shape::I32x4 => SIMD_I32X4_MUL,
+            Shape::I64x2 => SIMD_I64X2_MUL,
+            Shape::F32x4 => SIMD_F32X4_MUL,
+            Shape::F64x2 => SIMD_F64X2_MUL,
+        },
+        mir::Simd::Div(shape) => match shape {
+            Shape::F32x4 => SIMD_F32X4_DIV,
+            Shape::F64x2 => SIMD_F64X2_DIV,
+            // Integer vectors can not be divided, rejected by the resolver
+            _ => panic!("{}.div does not exist", shape),
+        },
+        mir::Simd::Not => SIMD_V128_NOT,
+        mir::Simd::And => SIMD_V128_AND,
+        mir::Simd::Or => SIMD_V128_OR,
+        mir::Simd::Xor => SIMD_V128_XOR,
+    }
+}
+
 fn mir_t_to_wasm(t: mir::Type) -> wasm::Type {
     match t {
         mir::Type::I32 => wasm::Type::I32,
         mir::Type::I64 => wasm::Type::I64,
         mir::Type::F32 => wasm::Type::F32,
         mir::Type::F64 => wasm::Type::F64,
+        mir::Type::V128 => wasm::Type::V128,
     }
 }
@@ -1,24 +1,58 @@
+use log::debug;
+
 use crate::error::ErrorHandler;
+use crate::hir;
 use crate::mir;
+use crate::mir::Backend;
 
+mod bindings;
+mod component;
+mod custom;
 mod mir_to_wasm;
 mod opcode;
 mod sections;
+mod validate;
 mod wasm;
+mod wat;
+mod wit;
+
+pub use bindings::{js_bindings, ts_declarations, JsFunction, JsType, MALLOC_EXPORT};
+pub use component::to_component;
+pub use custom::{
+    content_hash, custom_sections, encode_custom_section, COMPILER_SECTION, HASH_SECTION,
+    PACKAGE_SECTION,
+};
+pub use validate::validate;
+pub use wat::to_wat;
+pub use wit::{wit_name, WitFunction, WitImport, WitRecord, WitType, WitWorld};
 
+/// Compiles MIR down to a wasm module.
+///
+/// If `validate` is set the module is validated before being returned, an invalid module is
+/// reported as an internal error.
 pub fn to_wasm<'err>(
     mir_program: mir::Program,
     error_handler: &'err mut impl ErrorHandler,
-    verbose: bool,
-) -> Vec<u8> {
-    if verbose {
-        println!("\n/// Compiling ///\n");
-    }
-
-    let mut compiler = mir_to_wasm::Compiler::new(error_handler);
-    let program = compiler.compile(mir_program);
+    validate: bool,
+) -> Result<Vec<u8>, ()> {
+    debug!("\n/// Compiling ///\n");
 
-    error_handler.flush_and_exit_if_err();
+    let compiler = mir_to_wasm::Compiler::new(error_handler, validate);
+    mir::emit(mir_program, compiler)
+}
 
-    program
+/// Compiles the functions of a MIR program and returns the size in bytes of their code, an
+/// estimate of their share of the final module.
+pub fn code_sizes(
+    mir_program: mir::Program,
+    error_handler: &mut impl ErrorHandler,
+) -> Result<Vec<(hir::FunId, usize)>, ()> {
+    let mut compiler = mir_to_wasm::Compiler::new(error_handler, false);
+    compiler.emit_module(&mir_program);
+    let fun_ids: Vec<hir::FunId> = mir_program.funs.iter().map(|fun| fun.fun_id).collect();
+    for fun in mir_program.funs {
+        compiler.emit_function(fun);
+    }
+    let sizes = compiler.code_sizes()?;
+    Ok(fun_ids.into_iter().zip(sizes).collect())
 }
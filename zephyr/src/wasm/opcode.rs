@@ -36,6 +36,12 @@ pub const I32: Type = 0x7f;
 pub const I64: Type = 0x7e;
 pub const F32: Type = 0x7d;
 pub const F64: Type = 0x7c;
+pub const V128: Type = 0x7b;
+
+// Global mutability
+pub type Mut = u8;
+pub const MUT_CONST: Mut = 0x00;
+pub const MUT_VAR: Mut = 0x01;
 
 // Instructions
 pub type Instr = u8;
@@ -49,14 +55,19 @@ pub const INSTR_ELSE: Instr = 0x05;
 pub const INSTR_END: Instr = 0x0b;
 pub const INSTR_BR: Instr = 0x0c;
 pub const INSTR_BR_IF: Instr = 0x0d;
+pub const INSTR_BR_TABLE: Instr = 0x0e;
 pub const INSTR_RETURN: Instr = 0x0f;
 pub const INSTR_CALL: Instr = 0x10;
 pub const INSTR_CALL_INDIRECT: Instr = 0x11;
 // Parametric
 pub const INSTR_DROP: Instr = 0x1a;
+pub const INSTR_SELECT: Instr = 0x1b;
 // Variables
 pub const INSTR_LOCAL_GET: Instr = 0x20;
 pub const INSTR_LOCAL_SET: Instr = 0x21;
+pub const INSTR_LOCAL_TEE: Instr = 0x22;
+pub const INSTR_GLOBAL_GET: Instr = 0x23;
+pub const INSTR_GLOBAL_SET: Instr = 0x24;
 // Memory
 pub const INSTR_I32_LOAD: Instr = 0x28;
 pub const INSTR_I64_LOAD: Instr = 0x29;
@@ -118,6 +129,9 @@ pub const INSTR_F64_GT: Instr = 0x64;
 pub const INSTR_F64_LE: Instr = 0x65;
 pub const INSTR_F64_GE: Instr = 0x66;
 // I32 operations
+pub const INSTR_I32_CLZ: Instr = 0x67;
+pub const INSTR_I32_CTZ: Instr = 0x68;
+pub const INSTR_I32_POPCNT: Instr = 0x69;
 pub const INSTR_I32_ADD: Instr = 0x6a;
 pub const INSTR_I32_SUB: Instr = 0x6b;
 pub const INSTR_I32_MUL: Instr = 0x6c;
@@ -129,6 +143,9 @@ pub const INSTR_I32_AND: Instr = 0x71;
 pub const INSTR_I32_OR: Instr = 0x72;
 pub const INSTR_I32_XOR: Instr = 0x73;
 // I64 operations
+pub const INSTR_I64_CLZ: Instr = 0x79;
+pub const INSTR_I64_CTZ: Instr = 0x7a;
+pub const INSTR_I64_POPCNT: Instr = 0x7b;
 pub const INSTR_I64_ADD: Instr = 0x7c;
 pub const INSTR_I64_SUB: Instr = 0x7d;
 pub const INSTR_I64_MUL: Instr = 0x7e;
@@ -142,16 +159,121 @@ pub const INSTR_I64_XOR: Instr = 0x85;
 // F32 operations
 pub const INSTR_F32_ABS: Instr = 0x8b;
 pub const INSTR_F32_NEG: Instr = 0x8c;
+pub const INSTR_F32_CEIL: Instr = 0x8d;
+pub const INSTR_F32_FLOOR: Instr = 0x8e;
+pub const INSTR_F32_TRUNC: Instr = 0x8f;
+pub const INSTR_F32_NEAREST: Instr = 0x90;
+pub const INSTR_F32_SQRT: Instr = 0x91;
 pub const INSTR_F32_ADD: Instr = 0x92;
 pub const INSTR_F32_SUB: Instr = 0x93;
 pub const INSTR_F32_MUL: Instr = 0x94;
 pub const INSTR_F32_DIV: Instr = 0x95;
+pub const INSTR_F32_MIN: Instr = 0x96;
+pub const INSTR_F32_MAX: Instr = 0x97;
+pub const INSTR_F32_COPYSIGN: Instr = 0x98;
 // F64 operations
+pub const INSTR_F64_ABS: Instr = 0x99;
 pub const INSTR_F64_NEG: Instr = 0x9a;
+pub const INSTR_F64_CEIL: Instr = 0x9b;
+pub const INSTR_F64_FLOOR: Instr = 0x9c;
+pub const INSTR_F64_TRUNC: Instr = 0x9d;
+pub const INSTR_F64_NEAREST: Instr = 0x9e;
+pub const INSTR_F64_SQRT: Instr = 0x9f;
 pub const INSTR_F64_ADD: Instr = 0xa0;
 pub const INSTR_F64_SUB: Instr = 0xa1;
 pub const INSTR_F64_MUL: Instr = 0xa2;
 pub const INSTR_F64_DIV: Instr = 0xa3;
+pub const INSTR_F64_MIN: Instr = 0xa4;
+pub const INSTR_F64_MAX: Instr = 0xa5;
+pub const INSTR_F64_COPYSIGN: Instr = 0xa6;
+// Conversions
+pub const INSTR_I32_REINTERPRET_F32: Instr = 0xbc;
+pub const INSTR_I64_REINTERPRET_F64: Instr = 0xbd;
+pub const INSTR_F32_REINTERPRET_I32: Instr = 0xbe;
+pub const INSTR_F64_REINTERPRET_I64: Instr = 0xbf;
+// Saturating truncations, the prefix is followed by the opcode as an unsigned LEB128
+pub const INSTR_MISC_PREFIX: Instr = 0xfc;
+pub type MiscInstr = u32;
+pub const MISC_I32_TRUNC_SAT_F32_S: MiscInstr = 0x00;
+pub const MISC_I32_TRUNC_SAT_F32_U: MiscInstr = 0x01;
+pub const MISC_I32_TRUNC_SAT_F64_S: MiscInstr = 0x02;
+pub const MISC_I32_TRUNC_SAT_F64_U: MiscInstr = 0x03;
+pub const MISC_I64_TRUNC_SAT_F32_S: MiscInstr = 0x04;
+pub const MISC_I64_TRUNC_SAT_F32_U: MiscInstr = 0x05;
+pub const MISC_I64_TRUNC_SAT_F64_S: MiscInstr = 0x06;
+pub const MISC_I64_TRUNC_SAT_F64_U: MiscInstr = 0x07;
+// SIMD, the prefix is followed by the opcode as an unsigned LEB128
+pub const INSTR_SIMD_PREFIX: Instr = 0xfd;
+pub type SimdInstr = u32;
+pub const SIMD_V128_LOAD: SimdInstr = 0x00;
+pub const SIMD_V128_STORE: SimdInstr = 0x0b;
+pub const SIMD_V128_CONST: SimdInstr = 0x0c;
+pub const SIMD_I8X16_SPLAT: SimdInstr = 0x0f;
+pub const SIMD_I16X8_SPLAT: SimdInstr = 0x10;
+pub const SIMD_I32X4_SPLAT: SimdInstr = 0x11;
+pub const SIMD_I64X2_SPLAT: SimdInstr = 0x12;
+pub const SIMD_F32X4_SPLAT: SimdInstr = 0x13;
+pub const SIMD_F64X2_SPLAT: SimdInstr = 0x14;
+pub const SIMD_I8X16_EXTRACT_LANE_U: SimdInstr = 0x16;
+pub const SIMD_I8X16_REPLACE_LANE: SimdInstr = 0x17;
+pub const SIMD_I16X8_EXTRACT_LANE_U: SimdInstr = 0x19;
+pub const SIMD_I16X8_REPLACE_LANE: SimdInstr = 0x1a;
+pub const SIMD_I32X4_EXTRACT_LANE: SimdInstr = 0x1b;
+pub const SIMD_I32X4_REPLACE_LANE: SimdInstr = 0x1c;
+pub const SIMD_I64X2_EXTRACT_LANE: SimdInstr = 0x1d;
+pub const SIMD_I64X2_REPLACE_LANE: SimdInstr = 0x1e;
+pub const SIMD_F32X4_EXTRACT_LANE: SimdInstr = 0x1f;
+pub const SIMD_F32X4_REPLACE_LANE: SimdInstr = 0x20;
+pub const SIMD_F64X2_EXTRACT_LANE: SimdInstr = 0x21;
+pub const SIMD_F64X2_REPLACE_LANE: SimdInstr = 0x22;
+pub const SIMD_V128_NOT: SimdInstr = 0x4d;
+pub const SIMD_V128_AND: SimdInstr = 0x4e;
+pub const SIMD_V128_OR: SimdInstr = 0x50;
+pub const SIMD_V128_XOR: SimdInstr = 0x51;
+pub const SIMD_I8X16_ADD: SimdInstr = 0x6e;
+pub const SIMD_I8X16_SUB: SimdInstr = 0x71;
+pub const SIMD_I16X8_ADD: SimdInstr = 0x8e;
+pub const SIMD_I16X8_SUB: SimdInstr = 0x91;
+pub const SIMD_I16X8_MUL: SimdInstr = 0x95;
+pub const SIMD_I32X4_ADD: SimdInstr = 0xae;
+pub const SIMD_I32X4_SUB: SimdInstr = 0xb1;
+pub const SIMD_I32X4_MUL: SimdInstr = 0xb5;
+pub const SIMD_I64X2_ADD: SimdInstr = 0xce;
+pub const SIMD_I64X2_SUB: SimdInstr = 0xd1;
+pub const SIMD_I64X2_MUL: SimdInstr = 0xd5;
+pub const SIMD_F32X4_ADD: SimdInstr = 0xe4;
+pub const SIMD_F32X4_SUB: SimdInstr = 0xe5;
+pub const SIMD_F32X4_MUL: SimdInstr = 0xe6;
+pub const SIMD_F32X4_DIV: SimdInstr = 0xe7;
+pub const SIMD_F64X2_ADD: SimdInstr = 0xf0;
+pub const SIMD_F64X2_SUB: SimdInstr = 0xf1;
+pub const SIMD_F64X2_MUL: SimdInstr = 0xf2;
+pub const SIMD_F64X2_DIV: SimdInstr = 0xf3;
+// Atomics, the prefix is followed by the opcode as an unsigned LEB128
+pub const INSTR_ATOMIC_PREFIX: Instr = 0xfe;
+pub type AtomicInstr = u32;
+pub const ATOMIC_NOTIFY: AtomicInstr = 0x00;
+pub const ATOMIC_WAIT32: AtomicInstr = 0x01;
+pub const ATOMIC_WAIT64: AtomicInstr = 0x02;
+pub const ATOMIC_FENCE: AtomicInstr = 0x03;
+pub const ATOMIC_I32_LOAD: AtomicInstr = 0x10;
+pub const ATOMIC_I64_LOAD: AtomicInstr = 0x11;
+pub const ATOMIC_I32_STORE: AtomicInstr = 0x17;
+pub const ATOMIC_I64_STORE: AtomicInstr = 0x18;
+pub const ATOMIC_I32_RMW_ADD: AtomicInstr = 0x1e;
+pub const ATOMIC_I64_RMW_ADD: AtomicInstr = 0x1f;
+pub const ATOMIC_I32_RMW_SUB: AtomicInstr = 0x25;
+pub const ATOMIC_I64_RMW_SUB: AtomicInstr = 0x26;
+pub const ATOMIC_I32_RMW_AND: AtomicInstr = 0x2c;
+pub const ATOMIC_I64_RMW_AND: AtomicInstr = 0x2d;
+pub const ATOMIC_I32_RMW_OR: AtomicInstr = 0x33;
+pub const ATOMIC_I64_RMW_OR: AtomicInstr = 0x34;
+pub const ATOMIC_I32_RMW_XOR: AtomicInstr = 0x3a;
+pub const ATOMIC_I64_RMW_XOR: AtomicInstr = 0x3b;
+pub const ATOMIC_I32_RMW_XCHG: AtomicInstr = 0x41;
+pub const ATOMIC_I64_RMW_XCHG: AtomicInstr = 0x42;
+pub const ATOMIC_I32_RMW_CMPXCHG: AtomicInstr = 0x48;
+pub const ATOMIC_I64_RMW_CMPXCHG: AtomicInstr = 0x49;
 
 const LEB_MASK: u64 = 0x0000007f;
 const ONE_MASK: u64 = 0xffffffffffffffff;
@@ -180,31 +302,19 @@ pub fn to_leb<'a>(val: u64) -> Vec<u8> {
 ///
 /// https://en.wikipedia.org/wiki/LEB128
 pub fn to_sleb(val: i64) -> Vec<u8> {
-    let negative = val < 0;
+    let mut remainder = val;
     let mut sleb = Vec::new();
-    // Work with unsigned integers to avoid sign extension on shifts
-    let mut remainder = if negative { (-val) as u64 } else { val as u64 };
-
-    // Compute the number of significant bits and rounds to the closest
-    // greater multiple of 7
-    let mut n_bits = 64 - remainder.leading_zeros() + 1;
-    n_bits += (7 - n_bits % 7) % 7;
-
-    // Encode negative numbers using two's complement
-    if negative {
-        remainder = (!remainder) + 1;
-    }
-
     loop {
-        let mut byte = (LEB_MASK & remainder) as u8;
-        remainder = remainder >> 7;
-        n_bits -= 7;
-        if n_bits == 0 {
+        let byte = (LEB_MASK as i64 & remainder) as u8;
+        // Arithmetic shift, the sign is preserved
+        remainder >>= 7;
+        // Stop once the remaining bits are a sign extension of the last byte's sign bit
+        let sign_bit = byte & 0x40;
+        if (remainder == 0 && sign_bit == 0) || (remainder == -1 && sign_bit != 0) {
             sleb.push(byte);
             break;
         } else {
-            byte += 0x80;
-            sleb.push(byte);
+            sleb.push(byte + 0x80);
         }
     }
     sleb
@@ -216,6 +326,7 @@ pub fn type_to_bytes(t: wasm::Type) -> u8 {
         wasm::Type::F64 => F64,
         wasm::Type::I32 => I32,
         wasm::Type::I64 => I64,
+        wasm::Type::V128 => V128,
     }
 }
 
@@ -229,6 +340,12 @@ mod tests {
         assert_eq!(vec![0x5], to_leb(5));
         assert_eq!(vec![0x80, 0x1], to_leb(128));
         assert_eq!(vec![0xff, 0x1], to_leb(255));
+        assert_eq!(vec![0xe5, 0x8e, 0x26], to_leb(624485));
+        assert_eq!(vec![0xff, 0xff, 0xff, 0xff, 0x0f], to_leb(u32::MAX as u64));
+        assert_eq!(
+            vec![0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01],
+            to_leb(u64::MAX)
+        );
     }
 
     #[test]
@@ -237,5 +354,13 @@ mod tests {
         assert_eq!(vec![0x5], to_sleb(5));
         assert_eq!(vec![0x7f], to_sleb(-1));
         assert_eq!(vec![0xc0, 0xbb, 0x78], to_sleb(-123456));
+        assert_eq!(vec![0x40], to_sleb(-64));
+        assert_eq!(vec![0xc0, 0x0], to_sleb(64));
+        assert_eq!(
+            vec![0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x7f],
+            to_sleb(i64::MIN)
+        );
+        assert_eq!(vec![0xff, 0xff, 0xff, 0xff, 0x07], to_sleb(i32::MAX as i64));
+        assert_eq!(vec![0x80, 0x80, 0x80, 0x80, 0x78], to_sleb(i32::MIN as i64));
     }
 }
@@ -3,7 +3,7 @@ use std::collections::HashMap;
 
 use super::opcode::*;
 use super::wasm;
-use super::wasm::{DataSegment, Offset, WasmVec};
+use super::wasm::{encode_name, DataSegment, ElementSegment, Offset, WasmVec};
 
 /// Handles type index attribution and storage.
 struct TypeStore {
@@ -48,15 +48,29 @@ struct SectionType {
 impl SectionType {
     // Function declaration format:
     // [Func] (nb_args) [arg_1] [arg_2] ... (nb_results) [result_1] [result_2] ...
-    fn new(funs: &mut Vec<wasm::Function>, imports: &mut Vec<wasm::Import>) -> Self {
+    fn new(
+        signatures: &[wasm::Signature],
+        funs: &mut Vec<wasm::Function>,
+        imports: &mut Vec<wasm::Import>,
+    ) -> Self {
         let mut type_store = TypeStore::new();
+        for (param_types, ret_types) in signatures {
+            type_store.get_idx(SectionType::build_type(param_types, ret_types));
+        }
         for fun in funs.iter_mut() {
             let fun_type = SectionType::build_type(&fun.param_types, &fun.ret_types);
             fun.type_idx = type_store.get_idx(fun_type);
         }
         for import in imports.iter_mut() {
-            let fun_type = SectionType::build_type(&import.param_types, &import.ret_types);
-            import.type_idx = type_store.get_idx(fun_type);
+            if let wasm::ImportDesc::Func {
+                param_types,
+                ret_types,
+                type_idx,
+            } = &mut import.desc
+            {
+                let fun_type = SectionType::build_type(param_types, ret_types);
+                *type_idx = type_store.get_idx(fun_type);
+            }
         }
 
         Self {
@@ -104,18 +118,28 @@ impl SectionImport {
         let mut wasm_imports = WasmVec::new();
         for import in imports {
             let mut raw_import = Vec::new();
-            let mut module = WasmVec::new();
-            let mut name = WasmVec::new();
-            for byte in import.module.as_bytes() {
-                module.push_item(*byte);
-            }
-            for byte in import.name.as_bytes() {
-                name.push_item(*byte);
+            raw_import.extend(encode_name(&import.module));
+            raw_import.extend(encode_name(&import.name));
+            match import.desc {
+                wasm::ImportDesc::Func { type_idx, .. } => {
+                    raw_import.push(KIND_FUNC);
+                    raw_import.extend(to_leb(type_idx as u64));
+                }
+                wasm::ImportDesc::Table(limit) => {
+                    raw_import.push(KIND_TABLE);
+                    raw_import.push(ANY_FUNC);
+                    raw_import.extend(limit.encode());
+                }
+                wasm::ImportDesc::Memory(limit) => {
+                    raw_import.push(KIND_MEM);
+                    raw_import.extend(limit.encode());
+                }
+                wasm::ImportDesc::Global { t, mutable } => {
+                    raw_import.push(KIND_GLOBAL);
+                    raw_import.push(type_to_bytes(t));
+                    raw_import.push(if mutable { MUT_VAR } else { MUT_CONST });
+                }
             }
-            raw_import.extend(module);
-            raw_import.extend(name);
-            raw_import.push(import.kind);
-            raw_import.extend(to_leb(import.type_idx as u64));
             wasm_imports.extend_item(raw_import);
         }
         Self {
@@ -162,6 +186,35 @@ impl SectionFunction {
     }
 }
 
+struct SectionTable {
+    tables: WasmVec,
+}
+
+impl SectionTable {
+    fn new() -> Self {
+        Self {
+            tables: WasmVec::new(),
+        }
+    }
+
+    /// Adds a table of functions references.
+    fn add_table(&mut self, limit: wasm::Limit) {
+        let mut table = vec![ANY_FUNC];
+        table.extend(limit.encode());
+        self.tables.extend_item(table);
+    }
+
+    fn encode(self) -> Vec<Instr> {
+        let mut bytecode = Vec::new();
+
+        bytecode.push(SEC_TABLE);
+        bytecode.extend(to_leb(self.tables.size()));
+        bytecode.extend(self.tables);
+
+        bytecode
+    }
+}
+
 struct SectionMemory {
     memories: WasmVec,
 }
@@ -171,19 +224,7 @@ impl SectionMemory {
         let mut mems = WasmVec::new();
 
         for memory in memories {
-            let mut mem = Vec::new();
-            match memory {
-                wasm::Limit::Min(min) => {
-                    mem.push(0x00); // No upper limit flag
-                    mem.extend(to_leb(min as u64));
-                }
-                wasm::Limit::MinMax(min, max) => {
-                    mem.push(0x01); // With upper limit flag
-                    mem.extend(to_leb(min as u64));
-                    mem.extend(to_leb(max as u64));
-                }
-            }
-            mems.extend_item(mem);
+            mems.extend_item(memory.encode());
         }
 
         Self { memories: mems }
@@ -201,6 +242,75 @@ impl SectionMemory {
     }
 }
 
+struct SectionGlobal {
+    globals: WasmVec,
+}
+
+impl SectionGlobal {
+    fn new() -> Self {
+        Self {
+            globals: WasmVec::new(),
+        }
+    }
+
+    fn add_global(&mut self, global: wasm::Global) {
+        let mut raw_global = Vec::new();
+        raw_global.push(type_to_bytes(global.t));
+        raw_global.push(if global.mutable { MUT_VAR } else { MUT_CONST });
+        raw_global.extend(global.init.encode());
+        self.globals.extend_item(raw_global);
+    }
+
+    fn encode(self) -> Vec<Instr> {
+        let mut bytecode = Vec::new();
+
+        bytecode.push(SEC_GLOBAL);
+        bytecode.extend(to_leb(self.globals.size()));
+        bytecode.extend(self.globals);
+
+        bytecode
+    }
+}
+
+struct SectionStart {
+    fun_idx: u64,
+}
+
+impl SectionStart {
+    fn encode(self) -> Vec<Instr> {
+        let fun_idx = to_leb(self.fun_idx);
+        let mut bytecode = Vec::new();
+
+        bytecode.push(SEC_START);
+        bytecode.extend(to_leb(fun_idx.len() as u64));
+        bytecode.extend(fun_idx);
+
+        bytecode
+    }
+}
+
+struct SectionElement {
+    elements: WasmVec,
+}
+
+impl SectionElement {
+    fn new() -> Self {
+        Self {
+            elements: WasmVec::new(),
+        }
+    }
+
+    fn encode(self) -> Vec<Instr> {
+        let mut bytecode = Vec::new();
+
+        bytecode.push(SEC_ELEMENT);
+        bytecode.extend(to_leb(self.elements.size()));
+        bytecode.extend(self.elements);
+
+        bytecode
+    }
+}
+
 struct SectionCode {
     bodies: WasmVec,
 }
@@ -253,18 +363,16 @@ impl SectionExport {
             }
         }
 
-        // Export memory
-        let mem_name = "memory".as_bytes();
-        let mut data = Vec::new();
-        data.extend(to_leb(mem_name.len() as u64));
-        data.extend(mem_name);
-        data.push(KIND_MEM);
-        data.push(0);
-        exports.extend_item(data);
-
         Self { exports }
     }
 
+    fn add_export(&mut self, name: &str, kind: Kind, idx: u64) {
+        let mut data = encode_name(name);
+        data.push(kind);
+        data.extend(to_leb(idx));
+        self.exports.extend_item(data);
+    }
+
     fn encode(self) -> Vec<Instr> {
         let mut bytecode = Vec::new();
 
@@ -280,20 +388,23 @@ pub struct SectionData {
     data: WasmVec,
     offset: Offset,
     nb_pages: u32,
+    allocator: bool,
 }
 
 impl SectionData {
     pub fn new() -> Self {
         // Offset is initialized to 8 as the first bytes are reserved by the allocator.
-        Self { data: WasmVec::new(), offset: 8, nb_pages: 1 }
+        Self { data: WasmVec::new(), offset: 8, nb_pages: 1, allocator: true }
+    }
+
+    /// Sets whether the segments needed by the memory allocator are inserted, defaults to `true`.
+    pub fn set_allocator(&mut self, allocator: bool) {
+        self.allocator = allocator;
     }
 
-    /// Insert a new data segment and return its offset.
-    pub fn add_data_segment(&mut self, data: Vec<u8>) -> Offset {
+    /// Reserves `len` bytes for a data segment, or any other use, and return their offset.
+    pub fn reserve(&mut self, len: Offset) -> Offset {
         let offset = self.offset;
-        let len = data.len() as Offset;
-        self.data
-            .extend_item(DataSegment::new(0, offset, data.into()));
 
         // Maintain an offset such that an aligment of 8 is always guaranteed.
         if len % 8 != 0 {
@@ -304,6 +415,17 @@ impl SectionData {
         offset
     }
 
+    /// Insert a data segment at an offset returned by `reserve`.
+    pub fn add_data_segment(&mut self, offset: Offset, data: Vec<u8>) {
+        self.data
+            .extend_item(DataSegment::new(0, offset, data.into()));
+    }
+
+    /// Returns the number of bytes used by data segments, including the ones of the allocator.
+    pub fn size(&self) -> Offset {
+        self.offset + 8
+    }
+
     /// Insert memory segments needed to initialize the memory allocator.
     ///
     /// ! Caution: this function assumes that no other data segment will be added, call it just
@@ -337,7 +459,9 @@ impl SectionData {
     }
 
     fn encode(mut self) -> Vec<Instr> {
-        self.add_allocator_segments();
+        if self.allocator {
+            self.add_allocator_segments();
+        }
         let mut bytecode = Vec::new();
 
         bytecode.push(SEC_DATA);
@@ -348,19 +472,55 @@ impl SectionData {
     }
 }
 
+pub struct SectionCustom {
+    name: String,
+    content: Vec<u8>,
+}
+
+impl SectionCustom {
+    pub fn new(name: String, content: Vec<u8>) -> Self {
+        Self { name, content }
+    }
+
+    pub fn encode(self) -> Vec<Instr> {
+        let mut payload = encode_name(&self.name);
+        payload.extend(self.content);
+        let mut bytecode = Vec::new();
+
+        bytecode.push(SEC_CUSTOM);
+        bytecode.extend(to_leb(payload.len() as u64));
+        bytecode.extend(payload);
+
+        bytecode
+    }
+}
+
 pub struct Module {
     types: SectionType,
     imports: SectionImport,
     functions: SectionFunction,
+    tables: SectionTable,
     memories: SectionMemory,
+    globals: SectionGlobal,
     exports: SectionExport,
+    start: Option<SectionStart>,
+    elements: SectionElement,
     code: SectionCode,
     data: SectionData,
+    customs: Vec<SectionCustom>,
+    memory_export: Option<String>,
 }
 
 impl Module {
-    pub fn new(mut funs: Vec<wasm::Function>, mut imports: Vec<wasm::Import>, data: SectionData) -> Self {
-        let types = SectionType::new(&mut funs, &mut imports); // Must be called first because of side effects
+    /// Creates a module whose first types are the given signatures, in order, so that they can be
+    /// referred to by index before the module is built (e.g. by `call_indirect`).
+    pub fn new(
+        signatures: &[wasm::Signature],
+        mut funs: Vec<wasm::Function>,
+        mut imports: Vec<wasm::Import>,
+        data: SectionData,
+    ) -> Self {
+        let types = SectionType::new(signatures, &mut funs, &mut imports); // Must be called first because of side effects
         let imports = SectionImport::new(imports);
         let functions = SectionFunction::new(&funs);
         let memories = SectionMemory::new(vec![wasm::Limit::Min(1)]);
@@ -370,29 +530,348 @@ impl Module {
             types,
             imports,
             functions,
+            tables: SectionTable::new(),
             memories,
+            globals: SectionGlobal::new(),
             code,
             exports,
+            start: None,
+            elements: SectionElement::new(),
             data,
+            customs: Vec::new(),
+            memory_export: Some(String::from("memory")),
         }
     }
 
-    pub fn encode(self) -> Vec<Instr> {
+    /// Sets the limits of the memory, the memory allocator spans the initial pages.
+    pub fn set_memory(&mut self, limit: wasm::Limit) {
+        let initial = match limit {
+            wasm::Limit::Min(min) => min,
+            wasm::Limit::MinMax(min, _) => min,
+            wasm::Limit::Shared(min, _) => min,
+        };
+        self.memories = SectionMemory::new(vec![limit]);
+        self.data.nb_pages = initial;
+    }
+
+    /// Sets the name under which the memory is exported, defaults to "memory". The memory is not
+    /// exported if the name is `None`.
+    pub fn set_memory_export(&mut self, name: Option<String>) {
+        self.memory_export = name;
+    }
+
+    pub fn encode(mut self) -> Vec<Instr> {
         let mut bytecode = Vec::new();
+        if let Some(name) = &self.memory_export {
+            self.exports.add_export(name, KIND_MEM, 0);
+        }
 
         // Header
         bytecode.extend(MAGIC_NUMBER.to_le_bytes().iter());
         bytecode.extend(VERSION.to_le_bytes().iter());
 
-        // Sections
+        // Sections, the optional ones are omitted when empty
         bytecode.extend(self.types.encode());
         bytecode.extend(self.imports.encode());
         bytecode.extend(self.functions.encode());
+        if !self.tables.tables.is_empty() {
+            bytecode.extend(self.tables.encode());
+        }
         bytecode.extend(self.memories.encode());
+        if !self.globals.globals.is_empty() {
+            bytecode.extend(self.globals.encode());
+        }
         bytecode.extend(self.exports.encode());
+        if let Some(start) = self.start {
+            bytecode.extend(start.encode());
+        }
+        if !self.elements.elements.is_empty() {
+            bytecode.extend(self.elements.encode());
+        }
         bytecode.extend(self.code.encode());
         bytecode.extend(self.data.encode());
+        for custom in self.customs {
+            bytecode.extend(custom.encode());
+        }
 
         bytecode
     }
 }
+
+#[allow(dead_code)] // Not used by the compiler yet.
+impl Module {
+    /// Adds a table of function references, its index follows the imported tables.
+    pub fn add_table(&mut self, limit: wasm::Limit) {
+        self.tables.add_table(limit);
+    }
+
+    /// Adds a global, its index follows the imported globals.
+    pub fn add_global(&mut self, global: wasm::Global) {
+        self.globals.add_global(global);
+    }
+
+    /// Exports an item of the given kind, function are exported with `Function::exposed` instead.
+    pub fn add_export(&mut self, name: &str, kind: Kind, idx: u64) {
+        self.exports.add_export(name, kind, idx);
+    }
+
+    /// Sets the function called when the module is instantiated.
+    pub fn set_start(&mut self, fun_idx: u64) {
+        self.start = Some(SectionStart { fun_idx });
+    }
+
+    pub fn add_element_segment(&mut self, segment: ElementSegment) {
+        self.elements.elements.extend_item(segment);
+    }
+
+    /// Adds a custom section, custom sections are ignored by runtimes but can be used by tools.
+    pub fn add_custom_section(&mut self, name: String, content: Vec<u8>) {
+        self.customs.push(SectionCustom { name, content });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn function(exposed: Option<&str>) -> wasm::Function {
+        wasm::Function {
+            param_types: vec![wasm::Type::I32, wasm::Type::I32],
+            ret_types: vec![wasm::Type::I32],
+            type_idx: std::usize::MAX,
+            exposed: exposed.map(String::from),
+            fun_id: 0,
+            body: vec![0x00, INSTR_LOCAL_GET, 0x00, INSTR_END],
+        }
+    }
+
+    /// Returns the identifiers of the sections of an encoded module.
+    fn section_ids(module: &[u8]) -> Vec<SecTyp> {
+        let mut ids = Vec::new();
+        let mut idx = 8; // Skip the magic number and version
+        while idx < module.len() {
+            ids.push(module[idx]);
+            idx += 1;
+            let (mut size, mut shift) = (0, 0);
+            loop {
+                let byte = module[idx];
+                idx += 1;
+                size |= ((byte & 0x7f) as usize) << shift;
+                shift += 7;
+                if byte & 0x80 == 0 {
+                    break;
+                }
+            }
+            idx += size;
+        }
+        ids
+    }
+
+    #[test]
+    fn type_section() {
+        let mut funs = vec![function(None), function(None)];
+        let types = SectionType::new(&[], &mut funs, &mut Vec::new());
+        assert_eq!(funs[1].type_idx, 0);
+        assert_eq!(
+            types.encode(),
+            vec![SEC_TYPE, 0x07, 0x01, FUNC, 0x02, I32, I32, 0x01, I32]
+        );
+
+        // Signatures are registered first
+        let mut funs = vec![function(None)];
+        let signatures = vec![(vec![wasm::Type::I32], Vec::new())];
+        let types = SectionType::new(&signatures, &mut funs, &mut Vec::new());
+        assert_eq!(funs[0].type_idx, 1);
+        assert_eq!(
+            types.encode(),
+            vec![SEC_TYPE, 0x0b, 0x02, FUNC, 0x01, I32, 0x00, FUNC, 0x02, I32, I32, 0x01, I32]
+        );
+    }
+
+    #[test]
+    fn import_section() {
+        let imports = vec![
+            wasm::Import {
+                module: String::from("env"),
+                name: String::from("f"),
+                desc: wasm::ImportDesc::Func {
+                    param_types: Vec::new(),
+                    ret_types: Vec::new(),
+                    type_idx: 0,
+                },
+            },
+            wasm::Import {
+                module: String::from("env"),
+                name: String::from("g"),
+                desc: wasm::ImportDesc::Global {
+                    t: wasm::Type::I64,
+                    mutable: true,
+                },
+            },
+            wasm::Import {
+                module: String::from("env"),
+                name: String::from("m"),
+                desc: wasm::ImportDesc::Memory(wasm::Limit::MinMax(1, 2)),
+            },
+        ];
+        #[rustfmt::skip]
+        let expected = vec![
+            SEC_IMPORT, 0x1c, 0x03,
+            0x03, b'e', b'n', b'v', 0x01, b'f', KIND_FUNC, 0x00,
+            0x03, b'e', b'n', b'v', 0x01, b'g', KIND_GLOBAL, I64, MUT_VAR,
+            0x03, b'e', b'n', b'v', 0x01, b'm', KIND_MEM, 0x01, 0x01, 0x02,
+        ];
+        assert_eq!(SectionImport::new(imports).encode(), expected);
+    }
+
+    #[test]
+    fn table_and_memory_sections() {
+        let mut tables = SectionTable::new();
+        tables.add_table(wasm::Limit::Min(1));
+        assert_eq!(
+            tables.encode(),
+            vec![SEC_TABLE, 0x04, 0x01, ANY_FUNC, 0x00, 0x01]
+        );
+
+        let memories = SectionMemory::new(vec![wasm::Limit::MinMax(1, 0x10000)]);
+        assert_eq!(
+            memories.encode(),
+            vec![SEC_MEMORY, 0x06, 0x01, 0x01, 0x01, 0x80, 0x80, 0x04]
+        );
+
+        let memories = SectionMemory::new(vec![wasm::Limit::Shared(2, 16)]);
+        assert_eq!(
+            memories.encode(),
+            vec![SEC_MEMORY, 0x04, 0x01, 0x03, 0x02, 0x10]
+        );
+    }
+
+    #[test]
+    fn global_section() {
+        let mut globals = SectionGlobal::new();
+        globals.add_global(wasm::Global {
+            t: wasm::Type::I32,
+            mutable: true,
+            init: wasm::ConstExpr::I32(-1),
+        });
+        globals.add_global(wasm::Global {
+            t: wasm::Type::F32,
+            mutable: false,
+            init: wasm::ConstExpr::F32(1.0),
+        });
+        #[rustfmt::skip]
+        let expected = vec![
+            SEC_GLOBAL, 0x0e, 0x02,
+            I32, MUT_VAR, INSTR_I32_CST, 0x7f, INSTR_END,
+            F32, MUT_CONST, INSTR_F32_CST, 0x00, 0x00, 0x80, 0x3f, INSTR_END,
+        ];
+        assert_eq!(globals.encode(), expected);
+    }
+
+    #[test]
+    fn export_section() {
+        let mut exports = SectionExport::new(&vec![function(Some("f"))]);
+        exports.add_export("mem", KIND_MEM, 0);
+        exports.add_export("g", KIND_GLOBAL, 1);
+        #[rustfmt::skip]
+        let expected = vec![
+            SEC_EXPORT, 0x0f, 0x03,
+            0x01, b'f', KIND_FUNC, 0x00,
+            0x03, b'm', b'e', b'm', KIND_MEM, 0x00,
+            0x01, b'g', KIND_GLOBAL, 0x01,
+        ];
+        assert_eq!(exports.encode(), expected);
+    }
+
+    #[test]
+    fn start_and_element_sections() {
+        let start = SectionStart { fun_idx: 200 };
+        assert_eq!(start.encode(), vec![SEC_START, 0x02, 0xc8, 0x01]);
+
+        let mut elements = SectionElement::new();
+        elements
+            .elements
+            .extend_item(ElementSegment::new(0, 0, vec![0, 1]));
+        #[rustfmt::skip]
+        let expected = vec![
+            SEC_ELEMENT, 0x08, 0x01,
+            0x00, INSTR_I32_CST, 0x00, INSTR_END, 0x02, 0x00, 0x01,
+        ];
+        assert_eq!(elements.encode(), expected);
+    }
+
+    #[test]
+    fn code_section() {
+        let code = SectionCode::new(&vec![function(None)]);
+        #[rustfmt::skip]
+        let expected = vec![
+            SEC_CODE, 0x06, 0x01,
+            0x04, 0x00, INSTR_LOCAL_GET, 0x00, INSTR_END,
+        ];
+        assert_eq!(code.encode(), expected);
+    }
+
+    #[test]
+    fn data_segment() {
+        // Offsets are signed, 64 must be encoded on two bytes
+        let segment = DataSegment::new(0, 64, vec![0x2a].into());
+        assert_eq!(
+            segment.encode(),
+            vec![0x00, INSTR_I32_CST, 0xc0, 0x00, INSTR_END, 0x01, 0x2a]
+        );
+    }
+
+    #[test]
+    fn custom_section() {
+        let custom = SectionCustom {
+            name: String::from("name"),
+            content: vec![0x01, 0x02],
+        };
+        assert_eq!(
+            custom.encode(),
+            vec![SEC_CUSTOM, 0x07, 0x04, b'n', b'a', b'm', b'e', 0x01, 0x02]
+        );
+    }
+
+    #[test]
+    fn section_order() {
+        let mut module = Module::new(
+            &[],
+            vec![function(Some("f"))],
+            Vec::new(),
+            SectionData::new(),
+        );
+        #[rustfmt::skip]
+        let expected = vec![
+            SEC_TYPE, SEC_IMPORT, SEC_FUNCTION, SEC_MEMORY, SEC_EXPORT, SEC_CODE, SEC_DATA,
+        ];
+        assert_eq!(section_ids(&module.encode()), expected);
+
+        module = Module::new(
+            &[],
+            vec![function(Some("f"))],
+            Vec::new(),
+            SectionData::new(),
+        );
+        module.add_custom_section(String::from("producers"), Vec::new());
+        module.add_element_segment(ElementSegment::new(0, 0, vec![0]));
+        module.set_start(0);
+        module.add_global(wasm::Global {
+            t: wasm::Type::I64,
+            mutable: false,
+            init: wasm::ConstExpr::I64(0),
+        });
+        module.add_table(wasm::Limit::Min(1));
+        let encoded = module.encode();
+        assert_eq!(
+            &encoded[..8],
+            &[0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00]
+        );
+        #[rustfmt::skip]
+        let expected = vec![
+            SEC_TYPE, SEC_IMPORT, SEC_FUNCTION, SEC_TABLE, SEC_MEMORY, SEC_GLOBAL, SEC_EXPORT,
+            SEC_START, SEC_ELEMENT, SEC_CODE, SEC_DATA, SEC_CUSTOM,
+        ];
+        assert_eq!(section_ids(&encoded), expected);
+    }
+}
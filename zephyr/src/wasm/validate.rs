@@ -0,0 +1,1204 @@
+//! Wasm Validation
+//!
+//! A lightweight validator for the modules produced by the compiler, it follows the validation
+//! algorithm described in the appendix of the WebAssembly specification (version 1.0), extended
+//! with multiple function results. The validator is used as a self-check: an invalid module is
+//! the sign of a bug in the compiler.
+
+use std::collections::HashSet;
+
+use super::opcode::*;
+
+/// An error found while validating a module.
+#[derive(Debug)]
+pub struct ValidationError {
+    /// The index of the function whose body is invalid, if any.
+    pub fun_idx: Option<u32>,
+    pub message: String,
+}
+
+/// The maximum number of pages of a memory, that is 4GiB.
+const MAX_PAGES: u32 = 0x10000;
+
+/// Validates a binary module.
+pub fn validate(module: &[u8]) -> Result<(), ValidationError> {
+    let mut validator = Validator::new();
+    validator.module(module)
+}
+
+struct FunctionType {
+    params: Vec<Type>,
+    results: Vec<Type>,
+}
+
+/// The items declared (or imported) by the module.
+struct Validator {
+    types: Vec<FunctionType>,
+    /// The type index of each function, imported functions first.
+    funs: Vec<u32>,
+    nb_imported_funs: u32,
+    /// The type and mutability of each global, imported globals first.
+    globals: Vec<(Type, bool)>,
+    nb_imported_globals: u32,
+    nb_tables: u32,
+    nb_memories: u32,
+}
+
+impl Validator {
+    fn new() -> Self {
+        Self {
+            types: Vec::new(),
+            funs: Vec::new(),
+            nb_imported_funs: 0,
+            globals: Vec::new(),
+            nb_imported_globals: 0,
+            nb_tables: 0,
+            nb_memories: 0,
+        }
+    }
+
+    fn module(&mut self, module: &[u8]) -> Result<(), ValidationError> {
+        let module_error = |message| ValidationError {
+            fun_idx: None,
+            message,
+        };
+        let mut reader = Reader::new(module);
+        if reader.bytes(4).map_err(module_error)? != MAGIC_NUMBER.to_le_bytes() {
+            return Err(module_error(String::from("invalid magic number")));
+        }
+        if reader.bytes(4).map_err(module_error)? != VERSION.to_le_bytes() {
+            return Err(module_error(String::from("unsupported version")));
+        }
+
+        let mut last_id = SEC_CUSTOM;
+        let mut has_code = false;
+        while !reader.is_empty() {
+            let id = reader.byte().map_err(module_error)?;
+            let size = reader.u32().map_err(module_error)? as usize;
+            let mut section = Reader::new(reader.bytes(size).map_err(module_error)?);
+            if id != SEC_CUSTOM {
+                if id <= last_id {
+                    return Err(module_error(format!(
+                        "section {} is duplicated or out of order",
+                        id
+                    )));
+                }
+                last_id = id;
+            }
+            if id == SEC_CODE {
+                has_code = true;
+                self.code_section(&mut section)?;
+            } else {
+                self.section(id, &mut section).map_err(module_error)?;
+            }
+            if !section.is_empty() {
+                return Err(module_error(format!("size mismatch in section {}", id)));
+            }
+        }
+
+        if !has_code && self.funs.len() as u32 > self.nb_imported_funs {
+            return Err(module_error(String::from(
+                "functions are declared without a code section",
+            )));
+        }
+        Ok(())
+    }
+
+    /// Validates a section, except the code section.
+    fn section(&mut self, id: SecTyp, section: &mut Reader) -> Result<(), String> {
+        match id {
+            SEC_CUSTOM => {
+                section.name()?;
+                section.skip();
+            }
+            SEC_TYPE => {
+                for _ in 0..section.u32()? {
+                    if section.byte()? != FUNC {
+                        return Err(String::from("expected a function type"));
+                    }
+                    let params = section.val_types()?;
+                    // Functions may return multiple values (e.g. tuples), as in the multi-value
+                    // extension.
+                    let results = section.val_types()?;
+                    self.types.push(FunctionType { params, results });
+                }
+            }
+            SEC_IMPORT => {
+                for _ in 0..section.u32()? {
+                    section.name()?;
+                    section.name()?;
+                    match section.byte()? {
+                        KIND_FUNC => {
+                            let type_idx = self.type_idx(section)?;
+                            self.funs.push(type_idx);
+                            self.nb_imported_funs += 1;
+                        }
+                        KIND_TABLE => self.table(section)?,
+                        KIND_MEM => self.memory(section)?,
+                        KIND_GLOBAL => {
+                            let t = section.val_type()?;
+                            let mutable = section.mutability()?;
+                            self.globals.push((t, mutable));
+                            self.nb_imported_globals += 1;
+                        }
+                        kind => return Err(format!("unknown import kind {}", kind)),
+                    }
+                }
+            }
+            SEC_FUNCTION => {
+                for _ in 0..section.u32()? {
+                    let type_idx = self.type_idx(section)?;
+                    self.funs.push(type_idx);
+                }
+            }
+            SEC_TABLE => {
+                for _ in 0..section.u32()? {
+                    self.table(section)?;
+                }
+            }
+            SEC_MEMORY => {
+                for _ in 0..section.u32()? {
+                    self.memory(section)?;
+                }
+            }
+            SEC_GLOBAL => {
+                for _ in 0..section.u32()? {
+                    let t = section.val_type()?;
+                    let mutable = section.mutability()?;
+                    self.const_expr(section, t)?;
+                    self.globals.push((t, mutable));
+                }
+            }
+            SEC_EXPORT => {
+                let mut names = HashSet::new();
+                for _ in 0..section.u32()? {
+                    let name = section.name()?;
+                    let kind = section.byte()?;
+                    let idx = section.u32()?;
+                    let nb_items = match kind {
+                        KIND_FUNC => self.funs.len() as u32,
+                        KIND_TABLE => self.nb_tables,
+                        KIND_MEM => self.nb_memories,
+                        KIND_GLOBAL => self.globals.len() as u32,
+                        _ => return Err(format!("unknown export kind {}", kind)),
+                    };
+                    if idx >= nb_items {
+                        return Err(format!("export '{}' refers to an unknown item", name));
+                    }
+                    if !names.insert(name.clone()) {
+                        return Err(format!("duplicate export '{}'", name));
+                    }
+                }
+            }
+            SEC_START => {
+                let fun_idx = section.u32()?;
+                let t = self.fun_type(fun_idx)?;
+                if !t.params.is_empty() || !t.results.is_empty() {
+                    return Err(String::from(
+                        "the start function must not take nor return any value",
+                    ));
+                }
+            }
+            SEC_ELEMENT => {
+                for _ in 0..section.u32()? {
+                    if section.u32()? >= self.nb_tables {
+                        return Err(String::from("element segment refers to an unknown table"));
+                    }
+                    self.const_expr(section, I32)?;
+                    for _ in 0..section.u32()? {
+                        self.fun_type(section.u32()?)?;
+                    }
+                }
+            }
+            SEC_DATA => {
+                for _ in 0..section.u32()? {
+                    if section.u32()? >= self.nb_memories {
+                        return Err(String::from("data segment refers to an unknown memory"));
+                    }
+                    self.const_expr(section, I32)?;
+                    let len = section.u32()? as usize;
+                    section.bytes(len)?;
+                }
+            }
+            _ => return Err(format!("unknown section {}", id)),
+        }
+        Ok(())
+    }
+
+    fn code_section(&self, section: &mut Reader) -> Result<(), ValidationError> {
+        let nb_bodies = section.u32().map_err(|message| ValidationError {
+            fun_idx: None,
+            message,
+        })?;
+        if nb_bodies as usize + self.nb_imported_funs as usize != self.funs.len() {
+            return Err(ValidationError {
+                fun_idx: None,
+                message: String::from("the number of functions and bodies differ"),
+            });
+        }
+        for fun_idx in self.nb_imported_funs..(self.funs.len() as u32) {
+            let type_idx = self.funs[fun_idx as usize];
+            section
+                .u32()
+                .and_then(|size| section.bytes(size as usize))
+                .and_then(|body| self.function(type_idx, &mut Reader::new(body)))
+                .map_err(|message| ValidationError {
+                    fun_idx: Some(fun_idx),
+                    message,
+                })?;
+        }
+        Ok(())
+    }
+
+    fn function(&self, type_idx: u32, body: &mut Reader) -> Result<(), String> {
+        let t = &self.types[type_idx as usize];
+        let mut locals = t.params.clone();
+        for _ in 0..body.u32()? {
+            let nb_locals = body.u32()? as usize;
+            let local_t = body.val_type()?;
+            if locals.len() + nb_locals > u32::MAX as usize {
+                return Err(String::from("too many locals"));
+            }
+            locals.resize(locals.len() + nb_locals, local_t);
+        }
+
+        let mut validator = FunctionValidator {
+            module: self,
+            locals,
+            results: &t.results,
+            operands: Vec::new(),
+            controls: Vec::new(),
+        };
+        validator.push_ctrl(FrameKind::Block, t.results.clone());
+        while !validator.controls.is_empty() {
+            let instr = body.byte()?;
+            validator
+                .instr(instr, body)
+                .map_err(|e| format!("{} (instruction 0x{:02x} at byte {})", e, instr, body.pos))?;
+        }
+        if !body.is_empty() {
+            return Err(String::from(
+                "unexpected bytes after the end of the function",
+            ));
+        }
+        Ok(())
+    }
+
+    fn type_idx(&self, reader: &mut Reader) -> Result<u32, String> {
+        let type_idx = reader.u32()?;
+        if type_idx as usize >= self.types.len() {
+            return Err(format!("unknown type {}", type_idx));
+        }
+        Ok(type_idx)
+    }
+
+    fn fun_type(&self, fun_idx: u32) -> Result<&FunctionType, String> {
+        match self.funs.get(fun_idx as usize) {
+            Some(type_idx) => Ok(&self.types[*type_idx as usize]),
+            None => Err(format!("unknown function {}", fun_idx)),
+        }
+    }
+
+    fn table(&mut self, reader: &mut Reader) -> Result<(), String> {
+        if reader.byte()? != ANY_FUNC {
+            return Err(String::from("tables must contain function references"));
+        }
+        reader.limits(u32::MAX, false)?;
+        self.nb_tables += 1;
+        if self.nb_tables > 1 {
+            return Err(String::from("multiple tables are not supported"));
+        }
+        Ok(())
+    }
+
+    fn memory(&mut self, reader: &mut Reader) -> Result<(), String> {
+        reader.limits(MAX_PAGES, true)?;
+        self.nb_memories += 1;
+        if self.nb_memories > 1 {
+            return Err(String::from("multiple memories are not supported"));
+        }
+        Ok(())
+    }
+
+    /// Validates a constant expression producing a value of type `t`.
+    fn const_expr(&self, reader: &mut Reader, t: Type) -> Result<(), String> {
+        let expr_t = match reader.byte()? {
+            INSTR_I32_CST => {
+                reader.sleb(32)?;
+                I32
+            }
+            INSTR_I64_CST => {
+                reader.sleb(64)?;
+                I64
+            }
+            INSTR_F32_CST => {
+                reader.bytes(4)?;
+                F32
+            }
+            INSTR_F64_CST => {
+                reader.bytes(8)?;
+                F64
+            }
+            INSTR_GLOBAL_GET => {
+                let global_idx = reader.u32()?;
+                if global_idx >= self.nb_imported_globals {
+                    return Err(String::from(
+                        "constant expressions can only refer to imported globals",
+                    ));
+                }
+                let (global_t, mutable) = self.globals[global_idx as usize];
+                if mutable {
+                    return Err(String::from(
+                        "constant expressions can not refer to mutable globals",
+                    ));
+                }
+                global_t
+            }
+            instr => {
+                return Err(format!(
+                    "instruction 0x{:02x} is not allowed in constant expressions",
+                    instr
+                ))
+            }
+        };
+        if reader.byte()? != INSTR_END {
+            return Err(String::from(
+                "constant expressions must contain a single instruction",
+            ));
+        }
+        if expr_t != t {
+            return Err(format!(
+                "constant expression of type {} where {} is expected",
+                type_name(expr_t),
+                type_name(t)
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[derive(PartialEq, Eq)]
+enum FrameKind {
+    Block,
+    Loop,
+    If,
+    Else,
+}
+
+/// A control frame, that is a block, loop or if.
+struct Frame {
+    kind: FrameKind,
+    results: Vec<Type>,
+    /// The height of the operand stack when entering the frame.
+    height: usize,
+    /// Set after an unconditional branch, the stack is then polymorphic.
+    unreachable: bool,
+}
+
+impl Frame {
+    /// The types expected by a branch targeting the frame.
+    fn label_types(&self) -> &[Type] {
+        match self.kind {
+            FrameKind::Loop => &[],
+            _ => &self.results,
+        }
+    }
+}
+
+/// Type checks the body of a function.
+struct FunctionValidator<'a> {
+    module: &'a Validator,
+    locals: Vec<Type>,
+    results: &'a [Type],
+    /// The operand stack, `None` stands for an unknown type in unreachable code.
+    operands: Vec<Option<Type>>,
+    controls: Vec<Frame>,
+}
+
+impl<'a> FunctionValidator<'a> {
+    fn push(&mut self, t: Type) {
+        self.operands.push(Some(t));
+    }
+
+    fn push_all(&mut self, types: &[Type]) {
+        for t in types {
+            self.push(*t);
+        }
+    }
+
+    fn pop_any(&mut self) -> Result<Option<Type>, String> {
+        let frame = self.controls.last().expect("No control frame");
+        if self.operands.len() == frame.height {
+            if frame.unreachable {
+                return Ok(None);
+            }
+            return Err(String::from("type mismatch: the stack is empty"));
+        }
+        Ok(self.operands.pop().unwrap())
+    }
+
+    fn pop(&mut self, expected: Type) -> Result<(), String> {
+        match self.pop_any()? {
+            Some(t) if t != expected => Err(format!(
+                "type mismatch: expected {} but found {}",
+                type_name(expected),
+                type_name(t)
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    fn pop_all(&mut self, types: &[Type]) -> Result<(), String> {
+        for t in types.iter().rev() {
+            self.pop(*t)?;
+        }
+        Ok(())
+    }
+
+    fn push_ctrl(&mut self, kind: FrameKind, results: Vec<Type>) {
+        self.controls.push(Frame {
+            kind,
+            results,
+            height: self.operands.len(),
+            unreachable: false,
+        });
+    }
+
+    fn pop_ctrl(&mut self) -> Result<Frame, String> {
+        let frame = self.controls.last().expect("No control frame");
+        let results = frame.results.clone();
+        let height = frame.height;
+        self.pop_all(&results)?;
+        if self.operands.len() != height {
+            return Err(String::from(
+                "type mismatch: values remaining on the stack at the end of a block",
+            ));
+        }
+        Ok(self.controls.pop().unwrap())
+    }
+
+    /// Marks the rest of the current frame as unreachable.
+    fn unreachable(&mut self) {
+        let frame = self.controls.last_mut().expect("No control frame");
+        self.operands.truncate(frame.height);
+        frame.unreachable = true;
+    }
+
+    fn label(&self, depth: u32) -> Result<Vec<Type>, String> {
+        let depth = depth as usize;
+        if depth >= self.controls.len() {
+            return Err(format!("unknown label {}", depth));
+        }
+        Ok(self.controls[self.controls.len() - depth - 1]
+            .label_types()
+            .to_vec())
+    }
+
+    fn block_type(&self, reader: &mut Reader) -> Result<Vec<Type>, String> {
+        match reader.byte()? {
+            BLOCK_TYPE => Ok(Vec::new()),
+            t if is_val_type(t) => Ok(vec![t]),
+            t => Err(format!("invalid block type 0x{:02x}", t)),
+        }
+    }
+
+    fn local(&self, reader: &mut Reader) -> Result<Type, String> {
+        let local_idx = reader.u32()?;
+        match self.locals.get(local_idx as usize) {
+            Some(t) => Ok(*t),
+            None => Err(format!("unknown local {}", local_idx)),
+        }
+    }
+
+    fn global(&self, reader: &mut Reader) -> Result<(Type, bool), String> {
+        let global_idx = reader.u32()?;
+        match self.module.globals.get(global_idx as usize) {
+            Some(global) => Ok(*global),
+            None => Err(format!("unknown global {}", global_idx)),
+        }
+    }
+
+    /// Validates the immediate of a memory instruction accessing `width` bytes.
+    fn mem_arg(&self, reader: &mut Reader, width: u32) -> Result<(), String> {
+        if self.module.nb_memories == 0 {
+            return Err(String::from("memory instruction without memory"));
+        }
+        let align = reader.u32()?;
+        reader.u32()?; // offset
+        if align >= 32 || 1 << align > width {
+            return Err(format!(
+                "alignment 2^{} is larger than natural alignment",
+                align
+            ));
+        }
+        Ok(())
+    }
+
+    /// Validates the immediate of an atomic instruction accessing `width` bytes, which must use
+    /// the natural alignment.
+    fn atomic_mem_arg(&self, reader: &mut Reader, width: u32) -> Result<(), String> {
+        if self.module.nb_memories == 0 {
+            return Err(String::from("memory instruction without memory"));
+        }
+        let align = reader.u32()?;
+        reader.u32()?; // offset
+        if align >= 32 || 1 << align != width {
+            return Err(format!(
+                "alignment 2^{} of an atomic access is not the natural alignment",
+                align
+            ));
+        }
+        Ok(())
+    }
+
+    fn instr(&mut self, instr: Instr, reader: &mut Reader) -> Result<(), String> {
+        match instr {
+            // Control
+            INSTR_UNREACHABLE => self.unreachable(),
+            INSTR_NOP => (),
+            INSTR_BLOCK | INSTR_LOOP => {
+                let results = self.block_type(reader)?;
+                let kind = if instr == INSTR_BLOCK {
+                    FrameKind::Block
+                } else {
+                    FrameKind::Loop
+                };
+                self.push_ctrl(kind, results);
+            }
+            INSTR_IF => {
+                let results = self.block_type(reader)?;
+                self.pop(I32)?;
+                self.push_ctrl(FrameKind::If, results);
+            }
+            INSTR_ELSE => {
+                let frame = self.pop_ctrl()?;
+                if frame.kind != FrameKind::If {
+                    return Err(String::from("else without a matching if"));
+                }
+                self.push_ctrl(FrameKind::Else, frame.results);
+            }
+            INSTR_END => {
+                let frame = self.pop_ctrl()?;
+                if frame.kind == FrameKind::If && !frame.results.is_empty() {
+                    return Err(String::from("an if returning a value must have an else"));
+                }
+                self.push_all(&frame.results);
+            }
+            INSTR_BR => {
+                let types = self.label(reader.u32()?)?;
+                self.pop_all(&types)?;
+                self.unreachable();
+            }
+            INSTR_BR_IF => {
+                let types = self.label(reader.u32()?)?;
+                self.pop(I32)?;
+                self.pop_all(&types)?;
+                self.push_all(&types);
+            }
+            INSTR_BR_TABLE => {
+                let mut labels = Vec::new();
+                for _ in 0..reader.u32()? {
+                    labels.push(reader.u32()?);
+                }
+                let types = self.label(reader.u32()?)?;
+                for label in labels {
+                    if self.label(label)?.len() != types.len() {
+                        return Err(String::from("br_table targets labels of different arity"));
+                    }
+                }
+                self.pop(I32)?;
+                self.pop_all(&types)?;
+                self.unreachable();
+            }
+            INSTR_RETURN => {
+                let results = self.results;
+                self.pop_all(results)?;
+                self.unreachable();
+            }
+            INSTR_CALL => {
+                let module = self.module;
+                let t = module.fun_type(reader.u32()?)?;
+                self.pop_all(&t.params)?;
+                self.push_all(&t.results);
+            }
+            INSTR_CALL_INDIRECT => {
+                let module = self.module;
+                let type_idx = module.type_idx(reader)?;
+                if reader.byte()? != 0x00 || module.nb_tables == 0 {
+                    return Err(String::from("call_indirect requires a table"));
+                }
+                let t = &module.types[type_idx as usize];
+                self.pop(I32)?;
+                self.pop_all(&t.params)?;
+                self.push_all(&t.results);
+            }
+
+            // Parametric
+            INSTR_DROP => {
+                self.pop_any()?;
+            }
+            INSTR_SELECT => {
+                self.pop(I32)?;
+                let t = self.pop_any()?;
+                let other_t = self.pop_any()?;
+                match (t, other_t) {
+                    (Some(t), Some(other_t)) if t != other_t => {
+                        return Err(format!(
+                            "type mismatch: select between {} and {}",
+                            type_name(other_t),
+                            type_name(t)
+                        ))
+                    }
+                    _ => self.operands.push(t.or(other_t)),
+                }
+            }
+
+            // Variables
+            INSTR_LOCAL_GET => {
+                let t = self.local(reader)?;
+                self.push(t);
+            }
+            INSTR_LOCAL_SET => {
+                let t = self.local(reader)?;
+                self.pop(t)?;
+            }
+            INSTR_LOCAL_TEE => {
+                let t = self.local(reader)?;
+                self.pop(t)?;
+                self.push(t);
+            }
+            INSTR_GLOBAL_GET => {
+                let (t, _) = self.global(reader)?;
+                self.push(t);
+            }
+            INSTR_GLOBAL_SET => {
+                let (t, mutable) = self.global(reader)?;
+                if !mutable {
+                    return Err(String::from("global is immutable"));
+                }
+                self.pop(t)?;
+            }
+
+            // Memory
+            0x28..=0x35 => {
+                let (t, width) = match instr {
+                    INSTR_I32_LOAD => (I32, 4),
+                    INSTR_I64_LOAD => (I64, 8),
+                    INSTR_F32_LOAD => (F32, 4),
+                    INSTR_F64_LOAD => (F64, 8),
+                    0x2c | 0x2d => (I32, 1),
+                    0x2e | 0x2f => (I32, 2),
+                    0x30 | 0x31 => (I64, 1),
+                    0x32 | 0x33 => (I64, 2),
+                    _ => (I64, 4),
+                };
+                self.mem_arg(reader, width)?;
+                self.pop(I32)?;
+                self.push(t);
+            }
+            0x36..=0x3e => {
+                let (t, width) = match instr {
+                    INSTR_I32_STORE => (I32, 4),
+                    INSTR_I64_STORE => (I64, 8),
+                    INSTR_F32_STORE => (F32, 4),
+                    INSTR_F64_STORE => (F64, 8),
+                    0x3a => (I32, 1),
+                    0x3b => (I32, 2),
+                    0x3c => (I64, 1),
+                    0x3d => (I64, 2),
+                    _ => (I64, 4),
+                };
+                self.mem_arg(reader, width)?;
+                self.pop(t)?;
+                self.pop(I32)?;
+            }
+            INSTR_MEMORY_SIZE | INSTR_MEMORY_GROW => {
+                if reader.byte()? != 0x00 || self.module.nb_memories == 0 {
+                    return Err(String::from("memory instruction without memory"));
+                }
+                if instr == INSTR_MEMORY_GROW {
+                    self.pop(I32)?;
+                }
+                self.push(I32);
+            }
+
+            // Constants
+            INSTR_I32_CST => {
+                reader.sleb(32)?;
+                self.push(I32);
+            }
+            INSTR_I64_CST => {
+                reader.sleb(64)?;
+                self.push(I64);
+            }
+            INSTR_F32_CST => {
+                reader.bytes(4)?;
+                self.push(F32);
+            }
+            INSTR_F64_CST => {
+                reader.bytes(8)?;
+                self.push(F64);
+            }
+
+            // Numeric instructions
+            0x45..=0xbf => {
+                let (params, result) = numeric_type(instr);
+                self.pop_all(params)?;
+                self.push(result);
+            }
+
+            // Saturating truncations
+            INSTR_MISC_PREFIX => {
+                let instr = reader.u32()?;
+                let (param, result) = match instr {
+                    MISC_I32_TRUNC_SAT_F32_S | MISC_I32_TRUNC_SAT_F32_U => (F32, I32),
+                    MISC_I32_TRUNC_SAT_F64_S | MISC_I32_TRUNC_SAT_F64_U => (F64, I32),
+                    MISC_I64_TRUNC_SAT_F32_S | MISC_I64_TRUNC_SAT_F32_U => (F32, I64),
+                    MISC_I64_TRUNC_SAT_F64_S | MISC_I64_TRUNC_SAT_F64_U => (F64, I64),
+                    _ => return Err(String::from("unknown instruction")),
+                };
+                self.pop(param)?;
+                self.push(result);
+            }
+
+            // SIMD
+            INSTR_SIMD_PREFIX => {
+                let instr = reader.u32()?;
+                match instr {
+                    SIMD_V128_LOAD => {
+                        self.mem_arg(reader, 16)?;
+                        self.pop(I32)?;
+                        self.push(V128);
+                    }
+                    SIMD_V128_STORE => {
+                        self.mem_arg(reader, 16)?;
+                        self.pop(V128)?;
+                        self.pop(I32)?;
+                    }
+                    SIMD_V128_CONST => {
+                        reader.bytes(16)?;
+                        self.push(V128);
+                    }
+                    _ => {
+                        let (lanes, params, result) =
+                            simd_type(instr).ok_or("unknown instruction")?;
+                        if let Some(lanes) = lanes {
+                            if reader.byte()? >= lanes {
+                                return Err(String::from("invalid lane index"));
+                            }
+                        }
+                        self.pop_all(params)?;
+                        self.push(result);
+                    }
+                }
+            }
+            INSTR_ATOMIC_PREFIX => {
+                let instr = reader.u32()?;
+                if instr == ATOMIC_FENCE {
+                    if reader.byte()? != 0x00 {
+                        return Err(String::from("expected a zero byte after atomic.fence"));
+                    }
+                    return Ok(());
+                }
+                let (width, params, result) = atomic_type(instr).ok_or("unknown instruction")?;
+                self.atomic_mem_arg(reader, width)?;
+                self.pop_all(params)?;
+                if let Some(result) = result {
+                    self.push(result);
+                }
+            }
+
+            _ => return Err(String::from("unknown instruction")),
+        }
+        Ok(())
+    }
+}
+
+/// Returns the number of bytes accessed and the type of the atomic instructions emitted by the
+/// compiler, except `atomic.fence`.
+fn atomic_type(instr: AtomicInstr) -> Option<(u32, &'static [Type], Option<Type>)> {
+    let t: (u32, &'static [Type], Option<Type>) = match instr {
+        ATOMIC_NOTIFY => (4, &[I32, I32], Some(I32)),
+        ATOMIC_WAIT32 => (4, &[I32, I32, I64], Some(I32)),
+        ATOMIC_WAIT64 => (8, &[I32, I64, I64], Some(I32)),
+        ATOMIC_I32_LOAD => (4, &[I32], Some(I32)),
+        ATOMIC_I64_LOAD => (8, &[I32], Some(I64)),
+        ATOMIC_I32_STORE => (4, &[I32, I32], None),
+        ATOMIC_I64_STORE => (8, &[I32, I64], None),
+        ATOMIC_I32_RMW_ADD | ATOMIC_I32_RMW_SUB | ATOMIC_I32_RMW_AND | ATOMIC_I32_RMW_OR
+        | ATOMIC_I32_RMW_XOR | ATOMIC_I32_RMW_XCHG => (4, &[I32, I32], Some(I32)),
+        ATOMIC_I64_RMW_ADD | ATOMIC_I64_RMW_SUB | ATOMIC_I64_RMW_AND | ATOMIC_I64_RMW_OR
+        | ATOMIC_I64_RMW_XOR | ATOMIC_I64_RMW_XCHG => (8, &[I32, I64], Some(I64)),
+        ATOMIC_I32_RMW_CMPXCHG => (4, &[I32, I32, I32], Some(I32)),
+        ATOMIC_I64_RMW_CMPXCHG => (8, &[I32, I64, I64], Some(I64)),
+        _ => return None,
+    };
+    Some(t)
+}
+
+/// Returns the number of lanes of the lane index immediate, if any, and the type of the SIMD
+/// instructions emitted by the compiler.
+fn simd_type(instr: SimdInstr) -> Option<(Option<u8>, &'static [Type], Type)> {
+    let t: (Option<u8>, &'static [Type], Type) = match instr {
+        SIMD_I8X16_SPLAT | SIMD_I16X8_SPLAT | SIMD_I32X4_SPLAT => (None, &[I32], V128),
+        SIMD_I64X2_SPLAT => (None, &[I64], V128),
+        SIMD_F32X4_SPLAT => (None, &[F32], V128),
+        SIMD_F64X2_SPLAT => (None, &[F64], V128),
+        SIMD_I8X16_EXTRACT_LANE_U => (Some(16), &[V128], I32),
+        SIMD_I8X16_REPLACE_LANE => (Some(16), &[V128, I32], V128),
+        SIMD_I16X8_EXTRACT_LANE_U => (Some(8), &[V128], I32),
+        SIMD_I16X8_REPLACE_LANE => (Some(8), &[V128, I32], V128),
+        SIMD_I32X4_EXTRACT_LANE => (Some(4), &[V128], I32),
+        SIMD_I32X4_REPLACE_LANE => (Some(4), &[V128, I32], V128),
+        SIMD_I64X2_EXTRACT_LANE => (Some(2), &[V128], I64),
+        SIMD_I64X2_REPLACE_LANE => (Some(2), &[V128, I64], V128),
+        SIMD_F32X4_EXTRACT_LANE => (Some(4), &[V128], F32),
+        SIMD_F32X4_REPLACE_LANE => (Some(4), &[V128, F32], V128),
+        SIMD_F64X2_EXTRACT_LANE => (Some(2), &[V128], F64),
+        SIMD_F64X2_REPLACE_LANE => (Some(2), &[V128, F64], V128),
+        SIMD_V128_NOT => (None, &[V128], V128),
+        SIMD_V128_AND | SIMD_V128_OR | SIMD_V128_XOR => (None, &[V128, V128], V128),
+        // Lane-wise arithmetic
+        SIMD_I8X16_ADD | SIMD_I8X16_SUB | SIMD_I16X8_ADD | SIMD_I16X8_SUB | SIMD_I16X8_MUL
+        | SIMD_I32X4_ADD | SIMD_I32X4_SUB | SIMD_I32X4_MUL | SIMD_I64X2_ADD | SIMD_I64X2_SUB
+        | SIMD_I64X2_MUL | SIMD_F32X4_ADD | SIMD_F32X4_SUB | SIMD_F32X4_MUL | SIMD_F32X4_DIV
+        | SIMD_F64X2_ADD | SIMD_F64X2_SUB | SIMD_F64X2_MUL | SIMD_F64X2_DIV => {
+            (None, &[V128, V128], V128)
+        }
+        _ => return None,
+    };
+    Some(t)
+}
+
+/// Returns the type of a numeric instruction, that is an opcode between `0x45` and `0xbf`.
+fn numeric_type(instr: Instr) -> (&'static [Type], Type) {
+    match instr {
+        0x45 => (&[I32], I32),             // i32.eqz
+        0x46..=0x4f => (&[I32, I32], I32), // i32 comparisons
+        0x50 => (&[I64], I32),             // i64.eqz
+        0x51..=0x5a => (&[I64, I64], I32), // i64 comparisons
+        0x5b..=0x60 => (&[F32, F32], I32), // f32 comparisons
+        0x61..=0x66 => (&[F64, F64], I32), // f64 comparisons
+        0x67..=0x69 => (&[I32], I32),      // i32 unary operations
+        0x6a..=0x78 => (&[I32, I32], I32), // i32 binary operations
+        0x79..=0x7b => (&[I64], I64),      // i64 unary operations
+        0x7c..=0x8a => (&[I64, I64], I64), // i64 binary operations
+        0x8b..=0x91 => (&[F32], F32),      // f32 unary operations
+        0x92..=0x98 => (&[F32, F32], F32), // f32 binary operations
+        0x99..=0x9f => (&[F64], F64),      // f64 unary operations
+        0xa0..=0xa6 => (&[F64, F64], F64), // f64 binary operations
+        0xa7 => (&[I64], I32),             // i32.wrap_i64
+        0xa8 | 0xa9 => (&[F32], I32),      // i32.trunc_f32
+        0xaa | 0xab => (&[F64], I32),      // i32.trunc_f64
+        0xac | 0xad => (&[I32], I64),      // i64.extend_i32
+        0xae | 0xaf => (&[F32], I64),      // i64.trunc_f32
+        0xb0 | 0xb1 => (&[F64], I64),      // i64.trunc_f64
+        0xb2 | 0xb3 => (&[I32], F32),      // f32.convert_i32
+        0xb4 | 0xb5 => (&[I64], F32),      // f32.convert_i64
+        0xb6 => (&[F64], F32),             // f32.demote_f64
+        0xb7 | 0xb8 => (&[I32], F64),      // f64.convert_i32
+        0xb9 | 0xba => (&[I64], F64),      // f64.convert_i64
+        0xbb => (&[F32], F64),             // f64.promote_f32
+        0xbc => (&[F32], I32),             // i32.reinterpret_f32
+        0xbd => (&[F64], I64),             // i64.reinterpret_f64
+        0xbe => (&[I32], F32),             // f32.reinterpret_i32
+        _ => (&[I64], F64),                // f64.reinterpret_i64
+    }
+}
+
+fn is_val_type(t: Type) -> bool {
+    matches!(t, I32 | I64 | F32 | F64 | V128)
+}
+
+pub(super) fn type_name(t: Type) -> &'static str {
+    match t {
+        I32 => "i32",
+        I64 => "i64",
+        F32 => "f32",
+        V128 => "v128",
+        _ => "f64",
+    }
+}
+
+/// A cursor over a slice of bytes.
+pub(super) struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub(super) fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, pos: 0 }
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.pos >= self.bytes.len()
+    }
+
+    fn skip(&mut self) {
+        self.pos = self.bytes.len();
+    }
+
+    pub(super) fn byte(&mut self) -> Result<u8, String> {
+        let byte = *self
+            .bytes
+            .get(self.pos)
+            .ok_or_else(|| String::from("unexpected end of section"))?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    pub(super) fn bytes(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if self.bytes.len() - self.pos < len {
+            return Err(String::from("unexpected end of section"));
+        }
+        let bytes = &self.bytes[self.pos..(self.pos + len)];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    /// Reads an unsigned LEB128 integer of at most 32 bits.
+    pub(super) fn u32(&mut self) -> Result<u32, String> {
+        let mut val: u64 = 0;
+        for shift in (0..35).step_by(7) {
+            let byte = self.byte()?;
+            val |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                if val > u32::MAX as u64 {
+                    return Err(String::from("integer too large"));
+                }
+                return Ok(val as u32);
+            }
+        }
+        Err(String::from("integer representation too long"))
+    }
+
+    /// Skips a signed LEB128 integer of at most `bits` bits.
+    fn sleb(&mut self, bits: u32) -> Result<(), String> {
+        self.signed(bits).map(|_| ())
+    }
+
+    /// Reads a signed LEB128 integer of at most `bits` bits.
+    pub(super) fn signed(&mut self, bits: u32) -> Result<i64, String> {
+        let mut val: i64 = 0;
+        let mut shift = 0;
+        for _ in 0..bits.div_ceil(7) {
+            let byte = self.byte()?;
+            if shift < 64 {
+                val |= ((byte & 0x7f) as i64) << shift;
+            }
+            shift += 7;
+            if byte & 0x80 == 0 {
+                if shift < 64 && byte & 0x40 != 0 {
+                    val |= -1 << shift;
+                }
+                return Ok(val);
+            }
+        }
+        Err(String::from("integer representation too long"))
+    }
+
+    pub(super) fn name(&mut self) -> Result<String, String> {
+        let len = self.u32()? as usize;
+        let bytes = self.bytes(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| String::from("invalid UTF-8 name"))
+    }
+
+    pub(super) fn val_type(&mut self) -> Result<Type, String> {
+        match self.byte()? {
+            t if is_val_type(t) => Ok(t),
+            t => Err(format!("invalid value type 0x{:02x}", t)),
+        }
+    }
+
+    pub(super) fn val_types(&mut self) -> Result<Vec<Type>, String> {
+        let mut types = Vec::new();
+        for _ in 0..self.u32()? {
+            types.push(self.val_type()?);
+        }
+        Ok(types)
+    }
+
+    fn mutability(&mut self) -> Result<bool, String> {
+        match self.byte()? {
+            MUT_CONST => Ok(false),
+            MUT_VAR => Ok(true),
+            m => Err(format!("invalid mutability {}", m)),
+        }
+    }
+
+    /// Reads limits whose bounds must not exceed `max`, shared limits are accepted only if
+    /// `shared` is set.
+    fn limits(&mut self, max: u32, shared: bool) -> Result<(), String> {
+        let (min, upper) = match self.byte()? {
+            0x00 => (self.u32()?, None),
+            0x01 => (self.u32()?, Some(self.u32()?)),
+            0x03 if shared => (self.u32()?, Some(self.u32()?)),
+            flag => return Err(format!("invalid limits flag {}", flag)),
+        };
+        if min > max {
+            return Err(format!("limits exceed {}", max));
+        }
+        if let Some(upper) = upper {
+            if upper > max {
+                return Err(format!("limits exceed {}", max));
+            }
+            if upper < min {
+                return Err(String::from("the maximum is smaller than the minimum"));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::sections::{Module, SectionData};
+    use super::super::wasm;
+    use super::*;
+
+    /// Builds a module with a single function `(i32) -> ret_types`.
+    fn module(ret_types: Vec<wasm::Type>, instrs: &[Instr]) -> Vec<u8> {
+        let mut body = vec![0x00]; // No locals
+        body.extend(instrs);
+        body.push(INSTR_END);
+        let fun = wasm::Function {
+            param_types: vec![wasm::Type::I32],
+            ret_types,
+            type_idx: std::usize::MAX,
+            exposed: None,
+            fun_id: 0,
+            body,
+        };
+        Module::new(&[], vec![fun], Vec::new(), SectionData::new()).encode()
+    }
+
+    fn error(module: &[u8]) -> String {
+        let err = validate(module).unwrap_err();
+        assert_eq!(err.fun_idx, Some(0));
+        err.message
+    }
+
+    #[test]
+    fn valid_functions() {
+        let i32_t = vec![wasm::Type::I32];
+        #[rustfmt::skip]
+        let bodies: Vec<(Vec<wasm::Type>, Vec<Instr>)> = vec![
+            (i32_t.clone(), vec![INSTR_LOCAL_GET, 0x00, INSTR_I32_CST, 0x01, INSTR_I32_ADD]),
+            (i32_t.clone(), vec![INSTR_UNREACHABLE]),
+            (i32_t.clone(), vec![INSTR_I32_CST, 0x00, INSTR_RETURN]),
+            (i32_t.clone(), vec![
+                INSTR_LOCAL_GET, 0x00,
+                INSTR_IF, I32, INSTR_I32_CST, 0x01, INSTR_ELSE, INSTR_I32_CST, 0x02, INSTR_END,
+            ]),
+            (Vec::new(), vec![
+                INSTR_BLOCK, BLOCK_TYPE,
+                INSTR_LOOP, BLOCK_TYPE,
+                INSTR_LOCAL_GET, 0x00, INSTR_BR_IF, 0x01, INSTR_BR, 0x00,
+                INSTR_END,
+                INSTR_END,
+            ]),
+            (Vec::new(), vec![
+                INSTR_LOCAL_GET, 0x00, INSTR_I64_CST, 0x2a, INSTR_I64_STORE, 0x03, 0x00,
+            ]),
+        ];
+        for (ret_types, instrs) in bodies {
+            assert!(validate(&module(ret_types, &instrs)).is_ok());
+        }
+    }
+
+    #[test]
+    fn type_mismatch() {
+        let message = error(&module(vec![wasm::Type::I64], &[INSTR_LOCAL_GET, 0x00]));
+        assert!(message.starts_with("type mismatch: expected i64 but found i32"));
+
+        let message = error(&module(Vec::new(), &[INSTR_I32_CST, 0x00]));
+        assert!(message.starts_with("type mismatch: values remaining on the stack"));
+
+        // Function ending with an if whose branches both return
+        let message = error(&module(
+            vec![wasm::Type::I32],
+            &[
+                INSTR_LOCAL_GET,
+                0x00,
+                INSTR_IF,
+                BLOCK_TYPE,
+                INSTR_I32_CST,
+                0x00,
+                INSTR_RETURN,
+                INSTR_ELSE,
+                INSTR_I32_CST,
+                0x01,
+                INSTR_RETURN,
+                INSTR_END,
+            ],
+        ));
+        assert!(message.starts_with("type mismatch: the stack is empty"));
+    }
+
+    #[test]
+    fn invalid_instructions() {
+        let message = error(&module(Vec::new(), &[INSTR_BR, 0x01]));
+        assert!(message.starts_with("unknown label 1"));
+
+        let message = error(&module(Vec::new(), &[INSTR_LOCAL_GET, 0x01, INSTR_DROP]));
+        assert!(message.starts_with("unknown local 1"));
+
+        let message = error(&module(Vec::new(), &[INSTR_CALL, 0x01]));
+        assert!(message.starts_with("unknown function 1"));
+
+        // i32.load with an alignment of 8 bytes
+        let message = error(&module(
+            vec![wasm::Type::I32],
+            &[INSTR_LOCAL_GET, 0x00, INSTR_I32_LOAD, 0x03, 0x00],
+        ));
+        assert!(message.starts_with("alignment 2^3 is larger than natural alignment"));
+
+        // i32.atomic.load with an alignment of 1 byte
+        let message = error(&module(
+            vec![wasm::Type::I32],
+            &[INSTR_LOCAL_GET, 0x00, INSTR_ATOMIC_PREFIX, 0x10, 0x00, 0x00],
+        ));
+        assert!(message.starts_with("alignment 2^0 of an atomic access is not"));
+
+        let message = error(&module(vec![wasm::Type::I32], &[0xff]));
+        assert!(message.starts_with("unknown instruction"));
+    }
+
+    #[test]
+    fn invalid_module() {
+        let mut bytes = module(Vec::new(), &[]);
+        bytes[0] = 0x01;
+        assert_eq!(
+            validate(&bytes).unwrap_err().message,
+            "invalid magic number"
+        );
+
+        // A memory section followed by a type section
+        let mut bytes = module(Vec::new(), &[]);
+        bytes.truncate(8);
+        bytes.extend(&[SEC_MEMORY, 0x03, 0x01, 0x00, 0x01, SEC_TYPE, 0x01, 0x00]);
+        let err = validate(&bytes).unwrap_err();
+        assert_eq!(err.fun_idx, None);
+        assert_eq!(err.message, "section 1 is duplicated or out of order");
+
+        // Memory larger than 4GiB
+        let mut bytes = module(Vec::new(), &[]);
+        bytes.truncate(8);
+        bytes.extend(&[SEC_MEMORY, 0x05, 0x01, 0x00, 0x81, 0x80, 0x04]);
+        assert_eq!(validate(&bytes).unwrap_err().message, "limits exceed 65536");
+
+        // Shared limits are only valid for memories
+        let mut bytes = module(Vec::new(), &[]);
+        bytes.truncate(8);
+        bytes.extend(&[SEC_MEMORY, 0x04, 0x01, 0x03, 0x01, 0x02]);
+        assert!(validate(&bytes).is_ok());
+        let mut bytes = module(Vec::new(), &[]);
+        bytes.truncate(8);
+        bytes.extend(&[SEC_TABLE, 0x05, 0x01, ANY_FUNC, 0x03, 0x01, 0x02]);
+        assert_eq!(
+            validate(&bytes).unwrap_err().message,
+            "invalid limits flag 3"
+        );
+    }
+}
@@ -1,9 +1,12 @@
 use super::opcode;
-use super::opcode::to_leb;
+use super::opcode::{to_leb, to_sleb};
 
 pub type Offset = u32;
 
-pub const PAGE_SIZE: u32 = 0xffff;
+pub const PAGE_SIZE: u32 = 0x10000;
+
+/// Parameter and result types of a function type.
+pub type Signature = (Vec<Type>, Vec<Type>);
 
 pub struct Function {
     pub param_types: Vec<Type>,
@@ -17,10 +20,70 @@ pub struct Function {
 pub struct Import {
     pub module: String,
     pub name: String,
-    pub param_types: Vec<Type>,
-    pub ret_types: Vec<Type>,
-    pub kind: opcode::Kind,
-    pub type_idx: usize, // Used by encode
+    pub desc: ImportDesc,
+}
+
+/// The kind of an imported item and its type.
+#[allow(dead_code)] // Only functions are imported for now.
+pub enum ImportDesc {
+    Func {
+        param_types: Vec<Type>,
+        ret_types: Vec<Type>,
+        type_idx: usize, // Used by encode
+    },
+    Table(Limit),
+    Memory(Limit),
+    Global {
+        t: Type,
+        mutable: bool,
+    },
+}
+
+pub struct Global {
+    pub t: Type,
+    pub mutable: bool,
+    pub init: ConstExpr,
+}
+
+/// A constant expression, used to initialize globals and as segment offsets.
+#[allow(dead_code)] // Only I32 is used for now.
+#[derive(Copy, Clone)]
+pub enum ConstExpr {
+    I32(i32),
+    I64(i64),
+    F32(f32),
+    F64(f64),
+    GlobalGet(u32),
+}
+
+impl ConstExpr {
+    pub fn encode(self) -> Vec<u8> {
+        let mut expr = Vec::new();
+        match self {
+            ConstExpr::I32(val) => {
+                expr.push(opcode::INSTR_I32_CST);
+                expr.extend(to_sleb(val as i64));
+            }
+            ConstExpr::I64(val) => {
+                expr.push(opcode::INSTR_I64_CST);
+                expr.extend(to_sleb(val));
+            }
+            ConstExpr::F32(val) => {
+                expr.push(opcode::INSTR_F32_CST);
+                expr.extend(&val.to_le_bytes());
+            }
+            ConstExpr::F64(val) => {
+                expr.push(opcode::INSTR_F64_CST);
+                expr.extend(&val.to_le_bytes());
+            }
+            ConstExpr::GlobalGet(idx) => {
+                expr.push(opcode::INSTR_GLOBAL_GET);
+                expr.extend(to_leb(idx as u64));
+            }
+        }
+        expr.push(opcode::INSTR_END);
+        expr
+    }
 }
 
 #[derive(Copy, Clone)]
@@ -29,14 +92,49 @@ pub enum Type {
     I64,
     F32,
     F64,
+    V128,
 }
 
 /// Describe a range.
-/// Used to specify the initial/maximal size of a memory in pages (64Ki).
+/// Used to specify the initial/maximal size of a memory in pages (64Ki), or of a table in
+/// elements.
 #[allow(dead_code)] // MinMax never used for now.
+#[derive(Copy, Clone)]
 pub enum Limit {
     Min(u32),
     MinMax(u32, u32),
+    /// Limits of a memory shared between threads, the maximum is mandatory.
+    Shared(u32, u32),
+}
+
+impl Limit {
+    pub fn encode(self) -> Vec<u8> {
+        let mut limit = Vec::new();
+        match self {
+            Limit::Min(min) => {
+                limit.push(0x00); // No upper limit flag
+                limit.extend(to_leb(min as u64));
+            }
+            Limit::MinMax(min, max) => {
+                limit.push(0x01); // With upper limit flag
+                limit.extend(to_leb(min as u64));
+                limit.extend(to_leb(max as u64));
+            }
+            Limit::Shared(min, max) => {
+                limit.push(0x03); // Shared with upper limit flag
+                limit.extend(to_leb(min as u64));
+                limit.extend(to_leb(max as u64));
+            }
+        }
+        limit
+    }
+}
+
+/// Encodes a name as a vector of UTF-8 bytes.
+pub fn encode_name(name: &str) -> Vec<u8> {
+    let mut encoded = to_leb(name.len() as u64);
+    encoded.extend(name.as_bytes());
+    encoded
 }
 
 /// A struct representing a Wasm vector (as specified by the binary format).
@@ -68,6 +166,11 @@ impl WasmVec {
         self.size += 1;
     }
 
+    /// Returns true if the vector contains no item.
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
     /// Return the size (in bytes) of this vector
     pub fn size(&self) -> u64 {
         let header_size = to_leb(self.size).len(); // TODO: don't need to build the vector to get its len.
@@ -116,9 +219,7 @@ impl DataSegment {
         // mem_idx
         data_segment.extend(to_leb(self.mem_idx as u64));
         // offset
-        data_segment.push(opcode::INSTR_I32_CST);
-        data_segment.extend(to_leb(self.offset as u64));
-        data_segment.push(opcode::INSTR_END);
+        data_segment.extend(ConstExpr::I32(self.offset as i32).encode());
         // data
         data_segment.extend(self.data);
 
@@ -134,3 +235,45 @@ impl std::iter::IntoIterator for DataSegment {
         self.encode().into_iter()
     }
 }
+
+/// Initializes a range of a table with function indices.
+pub struct ElementSegment {
+    table_idx: u32,
+    offset: u32,
+    funs: Vec<u64>,
+}
+
+impl ElementSegment {
+    pub fn new(table_idx: u32, offset: u32, funs: Vec<u64>) -> Self {
+        Self {
+            table_idx,
+            offset,
+            funs,
+        }
+    }
+
+    pub fn encode(self) -> Vec<u8> {
+        let mut element_segment = Vec::new();
+        // table_idx
+        element_segment.extend(to_leb(self.table_idx as u64));
+        // offset
+        element_segment.extend(ConstExpr::I32(self.offset as i32).encode());
+        // function indices
+        let mut funs = WasmVec::new();
+        for fun in self.funs {
+            funs.extend_item(to_leb(fun));
+        }
+        element_segment.extend(funs);
+
+        element_segment
+    }
+}
+
+impl std::iter::IntoIterator for ElementSegment {
+    type Item = u8;
+    type IntoIter = std::vec::IntoIter<u8>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.encode().into_iter()
+    }
+}
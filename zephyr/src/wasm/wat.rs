@@ -0,0 +1,645 @@
+//! Wasm Text Format
+//!
+//! Prints the modules produced by the compiler in the WebAssembly text format, items are
+//! referred to by index and annotated with a comment. Only the instructions emitted by the
+//! compiler are supported, this is meant for inspecting and snapshotting the output rather than
+//! for producing modules to be assembled again.
+
+use super::opcode::*;
+use super::validate::{type_name, Reader};
+
+/// Names of the numeric instructions, from `i32.eqz` (0x45) to `f64.reinterpret_i64` (0xbf).
+#[rustfmt::skip]
+const NUMERIC_INSTRS: [&str; 123] = [
+    "i32.eqz", "i32.eq", "i32.ne", "i32.lt_s", "i32.lt_u", "i32.gt_s", "i32.gt_u", "i32.le_s",
+    "i32.le_u", "i32.ge_s", "i32.ge_u",
+    "i64.eqz", "i64.eq", "i64.ne", "i64.lt_s", "i64.lt_u", "i64.gt_s", "i64.gt_u", "i64.le_s",
+    "i64.le_u", "i64.ge_s", "i64.ge_u",
+    "f32.eq", "f32.ne", "f32.lt", "f32.gt", "f32.le", "f32.ge",
+    "f64.eq", "f64.ne", "f64.lt", "f64.gt", "f64.le", "f64.ge",
+    "i32.clz", "i32.ctz", "i32.popcnt", "i32.add", "i32.sub", "i32.mul", "i32.div_s",
+    "i32.div_u", "i32.rem_s", "i32.rem_u", "i32.and", "i32.or", "i32.xor", "i32.shl",
+    "i32.shr_s", "i32.shr_u", "i32.rotl", "i32.rotr",
+    "i64.clz", "i64.ctz", "i64.popcnt", "i64.add", "i64.sub", "i64.mul", "i64.div_s",
+    "i64.div_u", "i64.rem_s", "i64.rem_u", "i64.and", "i64.or", "i64.xor", "i64.shl",
+    "i64.shr_s", "i64.shr_u", "i64.rotl", "i64.rotr",
+    "f32.abs", "f32.neg", "f32.ceil", "f32.floor", "f32.trunc", "f32.nearest", "f32.sqrt",
+    "f32.add", "f32.sub", "f32.mul", "f32.div", "f32.min", "f32.max", "f32.copysign",
+    "f64.abs", "f64.neg", "f64.ceil", "f64.floor", "f64.trunc", "f64.nearest", "f64.sqrt",
+    "f64.add", "f64.sub", "f64.mul", "f64.div", "f64.min", "f64.max", "f64.copysign",
+    "i32.wrap_i64", "i32.trunc_f32_s", "i32.trunc_f32_u", "i32.trunc_f64_s", "i32.trunc_f64_u",
+    "i64.extend_i32_s", "i64.extend_i32_u", "i64.trunc_f32_s", "i64.trunc_f32_u",
+    "i64.trunc_f64_s", "i64.trunc_f64_u",
+    "f32.convert_i32_s", "f32.convert_i32_u", "f32.convert_i64_s", "f32.convert_i64_u",
+    "f32.demote_f64",
+    "f64.convert_i32_s", "f64.convert_i32_u", "f64.convert_i64_s", "f64.convert_i64_u",
+    "f64.promote_f32",
+    "i32.reinterpret_f32", "i64.reinterpret_f64", "f32.reinterpret_i32", "f64.reinterpret_i64",
+];
+
+/// Names of the saturating truncations, from `i32.trunc_sat_f32_s` (0xfc 0x00) to
+/// `i64.trunc_sat_f64_u` (0xfc 0x07).
+#[rustfmt::skip]
+const TRUNC_SAT_INSTRS: [&str; 8] = [
+    "i32.trunc_sat_f32_s", "i32.trunc_sat_f32_u", "i32.trunc_sat_f64_s", "i32.trunc_sat_f64_u",
+    "i64.trunc_sat_f32_s", "i64.trunc_sat_f32_u", "i64.trunc_sat_f64_s", "i64.trunc_sat_f64_u",
+];
+
+/// Names of the memory instructions, from `i32.load` (0x28) to `i64.store32` (0x3e).
+#[rustfmt::skip]
+const MEMORY_INSTRS: [&str; 23] = [
+    "i32.load", "i64.load", "f32.load", "f64.load", "i32.load8_s", "i32.load8_u",
+    "i32.load16_s", "i32.load16_u", "i64.load8_s", "i64.load8_u", "i64.load16_s",
+    "i64.load16_u", "i64.load32_s", "i64.load32_u",
+    "i32.store", "i64.store", "f32.store", "f64.store", "i32.store8", "i32.store16",
+    "i64.store8", "i64.store16", "i64.store32",
+];
+
+/// Returns the text format of a binary module.
+pub fn to_wat(module: &[u8]) -> Result<String, String> {
+    let mut printer = Printer {
+        out: String::from("(module\n"),
+        types: Vec::new(),
+        funs: Vec::new(),
+        nb_imported_funs: 0,
+    };
+    let mut reader = Reader::new(module);
+    if reader.bytes(4)? != MAGIC_NUMBER.to_le_bytes() {
+        return Err(String::from("invalid magic number"));
+    }
+    if reader.bytes(4)? != VERSION.to_le_bytes() {
+        return Err(String::from("unsupported version"));
+    }
+    while !reader.is_empty() {
+        let id = reader.byte()?;
+        let size = reader.u32()? as usize;
+        let mut section = Reader::new(reader.bytes(size)?);
+        printer.section(id, &mut section)?;
+    }
+    printer.out.push_str(")\n");
+    Ok(printer.out)
+}
+
+struct Printer {
+    out: String,
+    /// Parameters and results of each type.
+    types: Vec<(Vec<Type>, Vec<Type>)>,
+    /// The type index of each function, imported functions first.
+    funs: Vec<u32>,
+    nb_imported_funs: u32,
+}
+
+impl Printer {
+    fn line(&mut self, indent: usize, line: &str) {
+        for _ in 0..indent {
+            self.out.push_str("  ");
+        }
+        self.out.push_str(line);
+        self.out.push('\n');
+    }
+
+    fn section(&mut self, id: SecTyp, section: &mut Reader) -> Result<(), String> {
+        match id {
+            SEC_CUSTOM => {
+                let name = section.name()?;
+                self.line(1, &format!(";; custom section {:?}", name));
+            }
+            SEC_TYPE => {
+                for idx in 0..section.u32()? {
+                    if section.byte()? != FUNC {
+                        return Err(String::from("expected a function type"));
+                    }
+                    let params = section.val_types()?;
+                    let results = section.val_types()?;
+                    let signature = signature(&params, &results);
+                    self.line(1, &format!("(type (;{};) (func{}))", idx, signature));
+                    self.types.push((params, results));
+                }
+            }
+            SEC_IMPORT => {
+                for _ in 0..section.u32()? {
+                    let module = section.name()?;
+                    let name = section.name()?;
+                    let desc = match section.byte()? {
+                        KIND_FUNC => {
+                            let type_idx = section.u32()?;
+                            self.funs.push(type_idx);
+                            self.nb_imported_funs += 1;
+                            format!("(func (;{};) (type {}))", self.funs.len() - 1, type_idx)
+                        }
+                        KIND_TABLE => format!("(table {})", table(section)?),
+                        KIND_MEM => format!("(memory {})", limits(section)?),
+                        KIND_GLOBAL => format!("(global {})", global_type(section)?),
+                        kind => return Err(format!("unknown import kind {}", kind)),
+                    };
+                    self.line(1, &format!("(import {:?} {:?} {})", module, name, desc));
+                }
+            }
+            SEC_FUNCTION => {
+                for _ in 0..section.u32()? {
+                    self.funs.push(section.u32()?);
+                }
+            }
+            SEC_TABLE => {
+                for idx in 0..section.u32()? {
+                    let table = table(section)?;
+                    self.line(1, &format!("(table (;{};) {})", idx, table));
+                }
+            }
+            SEC_MEMORY => {
+                for idx in 0..section.u32()? {
+                    let limits = limits(section)?;
+                    self.line(1, &format!("(memory (;{};) {})", idx, limits));
+                }
+            }
+            SEC_GLOBAL => {
+                for idx in 0..section.u32()? {
+                    let t = global_type(section)?;
+                    let init = const_expr(section)?;
+                    self.line(1, &format!("(global (;{};) {} {})", idx, t, init));
+                }
+            }
+            SEC_EXPORT => {
+                for _ in 0..section.u32()? {
+                    let name = section.name()?;
+                    let kind = match section.byte()? {
+                        KIND_FUNC => "func",
+                        KIND_TABLE => "table",
+                        KIND_MEM => "memory",
+                        KIND_GLOBAL => "global",
+                        kind => return Err(format!("unknown export kind {}", kind)),
+                    };
+                    let idx = section.u32()?;
+                    self.line(1, &format!("(export {:?} ({} {}))", name, kind, idx));
+                }
+            }
+            SEC_START => {
+                let fun_idx = section.u32()?;
+                self.line(1, &format!("(start {})", fun_idx));
+            }
+            SEC_ELEMENT => {
+                for idx in 0..section.u32()? {
+                    section.u32()?; // Table index
+                    let offset = const_expr(section)?;
+                    let mut funs = Vec::new();
+                    for _ in 0..section.u32()? {
+                        funs.push(section.u32()?.to_string());
+                    }
+                    let funs = funs.join(" ");
+                    self.line(1, &format!("(elem (;{};) {} func {})", idx, offset, funs));
+                }
+            }
+            SEC_CODE => {
+                for idx in 0..section.u32()? {
+                    let size = section.u32()? as usize;
+                    let mut body = Reader::new(section.bytes(size)?);
+                    self.function(self.nb_imported_funs + idx, &mut body)?;
+                }
+            }
+            SEC_DATA => {
+                for idx in 0..section.u32()? {
+                    section.u32()?; // Memory index
+                    let offset = const_expr(section)?;
+                    let len = section.u32()? as usize;
+                    let bytes = escape(section.bytes(len)?);
+                    self.line(1, &format!("(data (;{};) {} \"{}\")", idx, offset, bytes));
+                }
+            }
+            _ => return Err(format!("unknown section {}", id)),
+        }
+        Ok(())
+    }
+
+    fn function(&mut self, fun_idx: u32, body: &mut Reader) -> Result<(), String> {
+        let type_idx = *self
+            .funs
+            .get(fun_idx as usize)
+            .ok_or_else(|| format!("unknown function {}", fun_idx))?;
+        let (params, results) = self
+            .types
+            .get(type_idx as usize)
+            .ok_or_else(|| format!("unknown type {}", type_idx))?;
+        let signature = signature(params, results);
+        self.line(
+            1,
+            &format!("(func (;{};) (type {}){}", fun_idx, type_idx, signature),
+        );
+        let mut locals = Vec::new();
+        for _ in 0..body.u32()? {
+            let nb_locals = body.u32()?;
+            let t = type_name(body.val_type()?);
+            for _ in 0..nb_locals {
+                locals.push(t);
+            }
+        }
+        if !locals.is_empty() {
+            self.line(2, &format!("(local {})", locals.join(" ")));
+        }
+        // The last `end` closes the function
+        let mut depth = 0;
+        while !body.is_empty() {
+            let instr = body.byte()?;
+            match instr {
+                INSTR_END if depth == 0 => break,
+                INSTR_END => depth -= 1,
+                INSTR_ELSE => {
+                    self.line(depth + 1, "else");
+                    continue;
+                }
+                _ => (),
+            }
+            let text = self.instr(instr, body)?;
+            self.line(depth + 2, &text);
+            if let INSTR_BLOCK | INSTR_LOOP | INSTR_IF = instr {
+                depth += 1;
+            }
+        }
+        self.line(1, ")");
+        Ok(())
+    }
+
+    /// Returns the text of an instruction, along with its immediates.
+    fn instr(&self, instr: Instr, reader: &mut Reader) -> Result<String, String> {
+        let text = match instr {
+            INSTR_UNREACHABLE => String::from("unreachable"),
+            INSTR_NOP => String::from("nop"),
+            INSTR_BLOCK | INSTR_LOOP | INSTR_IF => {
+                let name = match instr {
+                    INSTR_BLOCK => "block",
+                    INSTR_LOOP => "loop",
+                    _ => "if",
+                };
+                match reader.byte()? {
+                    BLOCK_TYPE => String::from(name),
+                    t => format!("{} (result {})", name, type_name(t)),
+                }
+            }
+            INSTR_END => String::from("end"),
+            INSTR_BR => format!("br {}", reader.u32()?),
+            INSTR_BR_IF => format!("br_if {}", reader.u32()?),
+            INSTR_BR_TABLE => {
+                let mut labels = Vec::new();
+                // The default label follows the table
+                for _ in 0..=reader.u32()? {
+                    labels.push(reader.u32()?.to_string());
+                }
+                format!("br_table {}", labels.join(" "))
+            }
+            INSTR_RETURN => String::from("return"),
+            INSTR_CALL => format!("call {}", reader.u32()?),
+            INSTR_CALL_INDIRECT => {
+                let type_idx = reader.u32()?;
+                reader.byte()?; // Table index
+                format!("call_indirect (type {})", type_idx)
+            }
+            INSTR_DROP => String::from("drop"),
+            INSTR_SELECT => String::from("select"),
+            INSTR_LOCAL_GET => format!("local.get {}", reader.u32()?),
+            INSTR_LOCAL_SET => format!("local.set {}", reader.u32()?),
+            INSTR_LOCAL_TEE => format!("local.tee {}", reader.u32()?),
+            INSTR_GLOBAL_GET => format!("global.get {}", reader.u32()?),
+            INSTR_GLOBAL_SET => format!("global.set {}", reader.u32()?),
+            0x28..=0x3e => {
+                let name = MEMORY_INSTRS[(instr - 0x28) as usize];
+                let align = reader.u32()?;
+                let offset = reader.u32()?;
+                let mut text = String::from(name);
+                if offset != 0 {
+                    text.push_str(&format!(" offset={}", offset));
+                }
+                if 1 << align != width(instr) {
+                    text.push_str(&format!(" align={}", 1u32 << align));
+                }
+                text
+            }
+            INSTR_MEMORY_SIZE | INSTR_MEMORY_GROW => {
+                reader.byte()?; // Memory index
+                if instr == INSTR_MEMORY_SIZE {
+                    String::from("memory.size")
+                } else {
+                    String::from("memory.grow")
+                }
+            }
+            INSTR_I32_CST => format!("i32.const {}", reader.signed(32)?),
+            INSTR_I64_CST => format!("i64.const {}", reader.signed(64)?),
+            INSTR_F32_CST => format!("f32.const {}", float(f32_const(reader)? as f64)),
+            INSTR_F64_CST => format!("f64.const {}", float(f64_const(reader)?)),
+            0x45..=0xbf => String::from(NUMERIC_INSTRS[(instr - 0x45) as usize]),
+            INSTR_MISC_PREFIX => {
+                let instr = reader.u32()?;
+                let name = TRUNC_SAT_INSTRS
+                    .get(instr as usize)
+                    .ok_or_else(|| format!("unknown instruction 0xfc 0x{:02x}", instr))?;
+                String::from(*name)
+            }
+            INSTR_SIMD_PREFIX => simd_instr(reader)?,
+            INSTR_ATOMIC_PREFIX => atomic_instr(reader)?,
+            _ => return Err(format!("unknown instruction 0x{:02x}", instr)),
+        };
+        Ok(text)
+    }
+}
+
+/// Returns the text of an instruction prefixed by `INSTR_SIMD_PREFIX`, only the instructions
+/// emitted by the compiler are supported.
+fn simd_instr(reader: &mut Reader) -> Result<String, String> {
+    let instr = reader.u32()?;
+    let text = match instr {
+        SIMD_V128_LOAD | SIMD_V128_STORE => {
+            let align = reader.u32()?;
+            let offset = reader.u32()?;
+            let mut text = String::from(if instr == SIMD_V128_LOAD {
+                "v128.load"
+            } else {
+                "v128.store"
+            });
+            if offset != 0 {
+                text.push_str(&format!(" offset={}", offset));
+            }
+            if align != 4 {
+                text.push_str(&format!(" align={}", 1u32 << align));
+            }
+            text
+        }
+        SIMD_V128_CONST => {
+            let lanes = reader
+                .bytes(16)?
+                .chunks(4)
+                .map(|lane| u32::from_le_bytes([lane[0], lane[1], lane[2], lane[3]]))
+                .map(|lane| format!("0x{:08x}", lane))
+                .collect::<Vec<_>>();
+            format!("v128.const i32x4 {}", lanes.join(" "))
+        }
+        0x16..=0x22 => {
+            let name = match instr {
+                SIMD_I8X16_EXTRACT_LANE_U => "i8x16.extract_lane_u",
+                SIMD_I8X16_REPLACE_LANE => "i8x16.replace_lane",
+                SIMD_I16X8_EXTRACT_LANE_U => "i16x8.extract_lane_u",
+                SIMD_I16X8_REPLACE_LANE => "i16x8.replace_lane",
+                SIMD_I32X4_EXTRACT_LANE => "i32x4.extract_lane",
+                SIMD_I32X4_REPLACE_LANE => "i32x4.replace_lane",
+                SIMD_I64X2_EXTRACT_LANE => "i64x2.extract_lane",
+                SIMD_I64X2_REPLACE_LANE => "i64x2.replace_lane",
+                SIMD_F32X4_EXTRACT_LANE => "f32x4.extract_lane",
+                SIMD_F32X4_REPLACE_LANE => "f32x4.replace_lane",
+                SIMD_F64X2_EXTRACT_LANE => "f64x2.extract_lane",
+                SIMD_F64X2_REPLACE_LANE => "f64x2.replace_lane",
+                _ => return Err(format!("unknown instruction 0xfd 0x{:02x}", instr)),
+            };
+            format!("{} {}", name, reader.byte()?)
+        }
+        _ => {
+            let name = match instr {
+                SIMD_I8X16_SPLAT => "i8x16.splat",
+                SIMD_I16X8_SPLAT => "i16x8.splat",
+                SIMD_I32X4_SPLAT => "i32x4.splat",
+                SIMD_I64X2_SPLAT => "i64x2.splat",
+                SIMD_F32X4_SPLAT => "f32x4.splat",
+                SIMD_F64X2_SPLAT => "f64x2.splat",
+                SIMD_V128_NOT => "v128.not",
+                SIMD_V128_AND => "v128.and",
+                SIMD_V128_OR => "v128.or",
+                SIMD_V128_XOR => "v128.xor",
+                SIMD_I8X16_ADD => "i8x16.add",
+                SIMD_I8X16_SUB => "i8x16.sub",
+                SIMD_I16X8_ADD => "i16x8.add",
+                SIMD_I16X8_SUB => "i16x8.sub",
+                SIMD_I16X8_MUL => "i16x8.mul",
+                SIMD_I32X4_ADD => "i32x4.add",
+                SIMD_I32X4_SUB => "i32x4.sub",
+                SIMD_I32X4_MUL => "i32x4.mul",
+                SIMD_I64X2_ADD => "i64x2.add",
+                SIMD_I64X2_SUB => "i64x2.sub",
+                SIMD_I64X2_MUL => "i64x2.mul",
+                SIMD_F32X4_ADD => "f32x4.add",
+                SIMD_F32X4_SUB => "f32x4.sub",
+                SIMD_F32X4_MUL => "f32x4.mul",
+                SIMD_F32X4_DIV => "f32x4.div",
+                SIMD_F64X2_ADD => "f64x2.add",
+                SIMD_F64X2_SUB => "f64x2.sub",
+                SIMD_F64X2_MUL => "f64x2.mul",
+                SIMD_F64X2_DIV => "f64x2.div",
+                _ => return Err(format!("unknown instruction 0xfd 0x{:02x}", instr)),
+            };
+            String::from(name)
+        }
+    };
+    Ok(text)
+}
+
+/// Returns the text of an instruction prefixed by `INSTR_ATOMIC_PREFIX`, only the instructions
+/// emitted by the compiler are supported.
+fn atomic_instr(reader: &mut Reader) -> Result<String, String> {
+    let instr = reader.u32()?;
+    let (name, width) = match instr {
+        ATOMIC_FENCE => {
+            reader.byte()?; // Reserved
+            return Ok(String::from("atomic.fence"));
+        }
+        ATOMIC_NOTIFY => ("memory.atomic.notify", 4),
+        ATOMIC_WAIT32 => ("memory.atomic.wait32", 4),
+        ATOMIC_WAIT64 => ("memory.atomic.wait64", 8),
+        ATOMIC_I32_LOAD => ("i32.atomic.load", 4),
+        ATOMIC_I64_LOAD => ("i64.atomic.load", 8),
+        ATOMIC_I32_STORE => ("i32.atomic.store", 4),
+        ATOMIC_I64_STORE => ("i64.atomic.store", 8),
+        ATOMIC_I32_RMW_ADD => ("i32.atomic.rmw.add", 4),
+        ATOMIC_I64_RMW_ADD => ("i64.atomic.rmw.add", 8),
+        ATOMIC_I32_RMW_SUB => ("i32.atomic.rmw.sub", 4),
+        ATOMIC_I64_RMW_SUB => ("i64.atomic.rmw.sub", 8),
+        ATOMIC_I32_RMW_AND => ("i32.atomic.rmw.and", 4),
+        ATOMIC_I64_RMW_AND => ("i64.atomic.rmw.and", 8),
+        ATOMIC_I32_RMW_OR => ("i32.atomic.rmw.or", 4),
+        ATOMIC_I64_RMW_OR => ("i64.atomic.rmw.or", 8),
+        ATOMIC_I32_RMW_XOR => ("i32.atomic.rmw.xor", 4),
+        ATOMIC_I64_RMW_XOR => ("i64.atomic.rmw.xor", 8),
+        ATOMIC_I32_RMW_XCHG => ("i32.atomic.rmw.xchg", 4),
+        ATOMIC_I64_RMW_XCHG => ("i64.atomic.rmw.xchg", 8),
+        ATOMIC_I32_RMW_CMPXCHG => ("i32.atomic.rmw.cmpxchg", 4),
+        ATOMIC_I64_RMW_CMPXCHG => ("i64.atomic.rmw.cmpxchg", 8),
+        _ => return Err(format!("unknown instruction 0xfe 0x{:02x}", instr)),
+    };
+    let align = reader.u32()?;
+    let offset = reader.u32()?;
+    let mut text = String::from(name);
+    if offset != 0 {
+        text.push_str(&format!(" offset={}", offset));
+    }
+    if 1 << align != width {
+        text.push_str(&format!(" align={}", 1u32 << align));
+    }
+    Ok(text)
+}
+
+/// Returns the parameters and results of a function type, with a leading space.
+fn signature(params: &[Type], results: &[Type]) -> String {
+    let mut signature = String::new();
+    if !params.is_empty() {
+        let params = params.iter().map(|t| type_name(*t)).collect::<Vec<_>>();
+        signature.push_str(&format!(" (param {})", params.join(" ")));
+    }
+    if !results.is_empty() {
+        let results = results.iter().map(|t| type_name(*t)).collect::<Vec<_>>();
+        signature.push_str(&format!(" (result {})", results.join(" ")));
+    }
+    signature
+}
+
+fn limits(reader: &mut Reader) -> Result<String, String> {
+    let limits = match reader.byte()? {
+        0x00 => format!("{}", reader.u32()?),
+        0x01 => format!("{} {}", reader.u32()?, reader.u32()?),
+        0x03 => format!("{} {} shared", reader.u32()?, reader.u32()?),
+        flag => return Err(format!("invalid limits flag {}", flag)),
+    };
+    Ok(limits)
+}
+
+fn table(reader: &mut Reader) -> Result<String, String> {
+    if reader.byte()? != ANY_FUNC {
+        return Err(String::from("tables must contain function references"));
+    }
+    Ok(format!("{} funcref", limits(reader)?))
+}
+
+fn global_type(reader: &mut Reader) -> Result<String, String> {
+    let t = type_name(reader.val_type()?);
+    match reader.byte()? {
+        MUT_CONST => Ok(String::from(t)),
+        MUT_VAR => Ok(format!("(mut {})", t)),
+        m => Err(format!("invalid mutability {}", m)),
+    }
+}
+
+fn const_expr(reader: &mut Reader) -> Result<String, String> {
+    let expr = match reader.byte()? {
+        INSTR_I32_CST => format!("(i32.const {})", reader.signed(32)?),
+        INSTR_I64_CST => format!("(i64.const {})", reader.signed(64)?),
+        INSTR_F32_CST => format!("(f32.const {})", float(f32_const(reader)? as f64)),
+        INSTR_F64_CST => format!("(f64.const {})", float(f64_const(reader)?)),
+        INSTR_GLOBAL_GET => format!("(global.get {})", reader.u32()?),
+        instr => return Err(format!("unexpected instruction 0x{:02x}", instr)),
+    };
+    if reader.byte()? != INSTR_END {
+        return Err(String::from(
+            "constant expressions must contain a single instruction",
+        ));
+    }
+    Ok(expr)
+}
+
+fn f32_const(reader: &mut Reader) -> Result<f32, String> {
+    let mut bytes = [0; 4];
+    bytes.copy_from_slice(reader.bytes(4)?);
+    Ok(f32::from_le_bytes(bytes))
+}
+
+fn f64_const(reader: &mut Reader) -> Result<f64, String> {
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(reader.bytes(8)?);
+    Ok(f64::from_le_bytes(bytes))
+}
+
+fn float(val: f64) -> String {
+    if val.is_nan() {
+        String::from("nan")
+    } else if val.is_infinite() {
+        String::from(if val > 0.0 { "inf" } else { "-inf" })
+    } else {
+        format!("{:?}", val)
+    }
+}
+
+/// Returns the number of bytes accessed by a memory instruction.
+fn width(instr: Instr) -> u32 {
+    match instr {
+        INSTR_I64_LOAD | INSTR_F64_LOAD | INSTR_I64_STORE | INSTR_F64_STORE => 8,
+        0x2c | 0x2d | 0x30 | 0x31 | 0x3a | 0x3c => 1,
+        0x2e | 0x2f | 0x32 | 0x33 | 0x3b | 0x3d => 2,
+        _ => 4,
+    }
+}
+
+/// Escapes the bytes of a data segment as a string literal.
+fn escape(bytes: &[u8]) -> String {
+    let mut escaped = String::new();
+    for byte in bytes {
+        match byte {
+            b'"' | b'\\' => escaped.push_str(&format!("\\{}", *byte as char)),
+            0x20..=0x7e => escaped.push(*byte as char),
+            _ => escaped.push_str(&format!("\\{:02x}", byte)),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::sections::{Module, SectionData};
+    use super::super::wasm;
+    use super::*;
+
+    #[test]
+    fn function() {
+        let mut body = vec![0x01, 0x01, I64]; // One i64 local
+        body.extend(&[INSTR_LOCAL_GET, 0x00, INSTR_IF, BLOCK_TYPE]);
+        body.extend(&[INSTR_I64_CST, 0x7f, INSTR_LOCAL_SET, 0x01]);
+        body.extend(&[INSTR_ELSE, INSTR_I32_CST, 0x08, INSTR_I64_LOAD, 0x03, 0x04]);
+        body.extend(&[INSTR_LOCAL_SET, 0x01, INSTR_END, INSTR_LOCAL_GET, 0x01]);
+        body.push(INSTR_END);
+        let fun = wasm::Function {
+            param_types: vec![wasm::Type::I32],
+            ret_types: vec![wasm::Type::I64],
+            type_idx: std::usize::MAX,
+            exposed: Some(String::from("f")),
+            fun_id: 0,
+            body,
+        };
+        let module = Module::new(&[], vec![fun], Vec::new(), SectionData::new()).encode();
+        let wat = to_wat(&module).unwrap();
+        let expected = r#"  (func (;0;) (type 0) (param i32) (result i64)
+    (local i64)
+    local.get 0
+    if
+      i64.const -1
+      local.set 1
+    else
+      i32.const 8
+      i64.load offset=4
+      local.set 1
+    end
+    local.get 1
+  )
+"#;
+        assert!(wat.contains(r#"(export "f" (func 0))"#));
+        assert!(wat.contains(expected), "{}", wat);
+    }
+
+    #[test]
+    fn simd() {
+        let mut body = vec![0x00, INSTR_LOCAL_GET, 0x00];
+        body.extend(&[INSTR_SIMD_PREFIX, SIMD_I32X4_SPLAT as u8]);
+        body.extend(&[INSTR_SIMD_PREFIX, SIMD_V128_CONST as u8]);
+        body.extend(&[1, 0, 0, 0, 2, 0, 0, 0, 3, 0, 0, 0, 4, 0, 0, 0]);
+        body.extend(&[INSTR_SIMD_PREFIX, SIMD_I32X4_ADD as u8, 0x01]);
+        body.extend(&[INSTR_SIMD_PREFIX, SIMD_I32X4_EXTRACT_LANE as u8, 0x02]);
+        body.push(INSTR_END);
+        let fun = wasm::Function {
+            param_types: vec![wasm::Type::I32],
+            ret_types: vec![wasm::Type::I32],
+            type_idx: std::usize::MAX,
+            exposed: None,
+            fun_id: 0,
+            body,
+        };
+        let module = Module::new(&[], vec![fun], Vec::new(), SectionData::new()).encode();
+        let wat = to_wat(&module).unwrap();
+        let expected = r#"    local.get 0
+    i32x4.splat
+    v128.const i32x4 0x00000001 0x00000002 0x00000003 0x00000004
+    i32x4.add
+    i32x4.extract_lane 2
+"#;
+        assert!(wat.contains(expected), "{}", wat);
+    }
+}
@@ -0,0 +1,243 @@
+//! WIT Interface Description
+//!
+//! Describes the imports and exports of a module in the WIT format of the WebAssembly component
+//! model. WIT names are kebab-case, identifiers are converted accordingly (`fd_write` becomes
+//! `fd-write`) and escaped with a `%` when they collide with a keyword.
+use std::fmt;
+
+/// Keywords of the WIT format, which must be escaped when used as names.
+#[rustfmt::skip]
+const KEYWORDS: &[&str] = &[
+    "as", "bool", "borrow", "char", "constructor", "enum", "export", "f32", "f64", "flags",
+    "func", "future", "import", "include", "interface", "list", "option", "own", "package",
+    "record", "resource", "result", "s16", "s32", "s64", "s8", "static", "stream", "string",
+    "tuple", "type", "u16", "u32", "u64", "u8", "use", "variant", "with", "world",
+];
+
+/// Name of the interface holding the records of a world.
+const TYPES_INTERFACE: &str = "types";
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum WitType {
+    S32,
+    S64,
+    F32,
+    F64,
+    Bool,
+    String,
+    /// A record, by name.
+    Record(String),
+    Tuple(Vec<WitType>),
+}
+
+pub struct WitRecord {
+    pub name: String,
+    pub fields: Vec<(String, WitType)>,
+}
+
+pub struct WitFunction {
+    pub name: String,
+    /// Name of the function in the core module.
+    pub core_name: String,
+    pub params: Vec<WitType>,
+    pub ret: Option<WitType>,
+}
+
+/// Functions imported from a single module.
+pub struct WitImport {
+    pub module: String,
+    /// Name of the module in the core module.
+    pub core_module: String,
+    pub funs: Vec<WitFunction>,
+}
+
+/// A world, describing a whole module. All names are expected to be valid kebab-case names.
+pub struct WitWorld {
+    pub name: String,
+    pub records: Vec<WitRecord>,
+    pub imports: Vec<WitImport>,
+    pub exports: Vec<WitFunction>,
+}
+
+/// Converts an identifier to a WIT name, returns `None` if it has no kebab-case equivalent.
+///
+/// Words are separated by underscores or by a lower case letter followed by an upper case one.
+pub fn wit_name(ident: &str) -> Option<String> {
+    let mut words: Vec<String> = Vec::new();
+    let mut previous_is_lower = false;
+    for part in ident.split('_').filter(|part| !part.is_empty()) {
+        let mut word = String::new();
+        for c in part.chars() {
+            if c.is_ascii_uppercase() && previous_is_lower {
+                words.push(word);
+                word = String::new();
+            }
+            previous_is_lower = c.is_ascii_lowercase() || c.is_ascii_digit();
+            word.push(c.to_ascii_lowercase());
+        }
+        words.push(word);
+        previous_is_lower = false;
+    }
+    let is_valid = |word: &String| {
+        let mut chars = word.chars();
+        matches!(chars.next(), Some(c) if c.is_ascii_lowercase())
+            && chars.all(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+    };
+    if words.is_empty() || !words.iter().all(is_valid) {
+        return None;
+    }
+    Some(words.join("-"))
+}
+
+/// Escapes a name if it is a keyword.
+fn escape(name: &str) -> String {
+    if KEYWORDS.contains(&name) {
+        format!("%{}", name)
+    } else {
+        String::from(name)
+    }
+}
+
+impl fmt::Display for WitType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WitType::S32 => write!(f, "s32"),
+            WitType::S64 => write!(f, "s64"),
+            WitType::F32 => write!(f, "f32"),
+            WitType::F64 => write!(f, "f64"),
+            WitType::Bool => write!(f, "bool"),
+            WitType::String => write!(f, "string"),
+            WitType::Record(name) => write!(f, "{}", escape(name)),
+            WitType::Tuple(types) => {
+                let types = types.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+                write!(f, "tuple<{}>", types.join(", "))
+            }
+        }
+    }
+}
+
+impl fmt::Display for WitFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let params = self
+            .params
+            .iter()
+            .enumerate()
+            .map(|(idx, t)| format!("arg{}: {}", idx, t))
+            .collect::<Vec<_>>();
+        write!(f, "{}: func({})", escape(&self.name), params.join(", "))?;
+        if let Some(ret) = &self.ret {
+            write!(f, " -> {}", ret)?;
+        }
+        write!(f, ";")
+    }
+}
+
+impl fmt::Display for WitWorld {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "// Generated by the Zephyr compiler.")?;
+        writeln!(f, "package zephyr:{};", escape(&self.name))?;
+        let uses = if self.records.is_empty() {
+            None
+        } else {
+            let names = self.records.iter().map(|r| escape(&r.name));
+            let names = names.collect::<Vec<_>>().join(", ");
+            Some(format!("use {}.{{{}}};", TYPES_INTERFACE, names))
+        };
+
+        if !self.records.is_empty() {
+            writeln!(f, "\ninterface {} {{", TYPES_INTERFACE)?;
+            for record in &self.records {
+                writeln!(f, "    record {} {{", escape(&record.name))?;
+                for (name, t) in &record.fields {
+                    writeln!(f, "        {}: {},", escape(name), t)?;
+                }
+                writeln!(f, "    }}")?;
+            }
+            writeln!(f, "}}")?;
+        }
+
+        writeln!(f, "\nworld {} {{", escape(&self.name))?;
+        if let Some(uses) = &uses {
+            writeln!(f, "    {}", uses)?;
+        }
+        for import in &self.imports {
+            writeln!(f, "    import {}: interface {{", escape(&import.module))?;
+            if let Some(uses) = &uses {
+                writeln!(f, "        {}", uses)?;
+            }
+            for fun in &import.funs {
+                writeln!(f, "        {}", fun)?;
+            }
+            writeln!(f, "    }}")?;
+        }
+        for fun in &self.exports {
+            writeln!(f, "    export {}", fun)?;
+        }
+        writeln!(f, "}}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names() {
+        assert_eq!(wit_name("fd_write"), Some(String::from("fd-write")));
+        assert_eq!(wit_name("_start"), Some(String::from("start")));
+        assert_eq!(wit_name("readI32"), Some(String::from("read-i32")));
+        assert_eq!(wit_name("Str"), Some(String::from("str")));
+        assert_eq!(wit_name("point_2d"), None);
+        assert_eq!(escape("record"), "%record");
+    }
+
+    #[test]
+    fn world() {
+        let world = WitWorld {
+            name: String::from("demo"),
+            records: vec![WitRecord {
+                name: String::from("point"),
+                fields: vec![
+                    (String::from("x"), WitType::S32),
+                    (String::from("y"), WitType::F64),
+                ],
+            }],
+            imports: vec![WitImport {
+                module: String::from("env"),
+                core_module: String::from("env"),
+                funs: vec![WitFunction {
+                    name: String::from("log"),
+                    core_name: String::from("log"),
+                    params: vec![WitType::String],
+                    ret: None,
+                }],
+            }],
+            exports: vec![WitFunction {
+                name: String::from("pair"),
+                core_name: String::from("pair"),
+                params: vec![WitType::Record(String::from("point")), WitType::Bool],
+                ret: Some(WitType::Tuple(vec![WitType::S64, WitType::S32])),
+            }],
+        };
+        let expected = "// Generated by the Zephyr compiler.
+package zephyr:demo;
+
+interface types {
+    record point {
+        x: s32,
+        y: f64,
+    }
+}
+
+world demo {
+    use types.{point};
+    import env: interface {
+        use types.{point};
+        log: func(arg0: string);
+    }
+    export pair: func(arg0: point, arg1: bool) -> tuple<s64, s32>;
+}
+";
+        assert_eq!(world.to_string(), expected);
+    }
+}
@@ -0,0 +1,47 @@
+//! Artifacts
+//!
+//! When the output of a build is a directory, the WebAssembly module is written to it along with
+//! its text format, JavaScript bindings and a JSON description of the build for build systems.
+//! All files are named after the package, as the module is when no output is given.
+
+use serde_json::json;
+use std::path::{is_separator, Path, PathBuf};
+
+use zephyr::{Artifacts, BuildMetadata};
+
+/// Returns true if the output designates a directory, that is if it ends with a separator or is
+/// an existing directory.
+pub fn is_dir(output: &Path) -> bool {
+    output.to_string_lossy().ends_with(is_separator) || output.is_dir()
+}
+
+/// Returns the files of the artifacts along with their content, the module comes last.
+pub fn files(dir: &Path, module_name: &str, artifacts: Artifacts) -> Vec<(PathBuf, Vec<u8>)> {
+    let module = dir.join(format!("{}.zph.wasm", module_name));
+    let mut files = vec![(module.with_extension("wat"), artifacts.wat.into_bytes())];
+    if let Some((bindings, declarations)) = artifacts.js_bindings {
+        files.push((module.with_extension("js"), bindings.into_bytes()));
+        files.push((module.with_extension("d.ts"), declarations.into_bytes()));
+    }
+    let names = files
+        .iter()
+        .map(|(path, _)| path)
+        .chain(Some(&module))
+        .filter_map(|path| Some(path.file_name()?.to_string_lossy().into_owned()))
+        .collect();
+    let metadata = to_json(&artifacts.metadata, names).to_string();
+    files.push((module.with_extension("json"), metadata.into_bytes()));
+    files.push((module, artifacts.wasm));
+    files
+}
+
+fn to_json(metadata: &BuildMetadata, files: Vec<String>) -> serde_json::Value {
+    json!({
+        "compiler": metadata.compiler,
+        "package": metadata.package,
+        "target": metadata.target.to_string(),
+        "hash": metadata.hash,
+        "exports": metadata.exports,
+        "files": files,
+    })
+}
@@ -0,0 +1,65 @@
+//! Terminal colors
+//!
+//! Styles used to highlight the output of the executables. A style is displayed as an ANSI escape
+//! sequence only if colors are enabled, which by default depends on whether stdout is a terminal
+//! (see `set_choice`).
+
+use std::fmt;
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+pub const AUTO: &str = "auto";
+pub const ALWAYS: &str = "always";
+pub const NEVER: &str = "never";
+
+pub const RED: Style = Style("\x1B[31m");
+pub const GREEN: Style = Style("\x1B[32m");
+pub const YELLOW: Style = Style("\x1B[33m");
+pub const BLUE: Style = Style("\x1B[34m");
+pub const MAGENTA: Style = Style("\x1B[35m");
+pub const BOLD: Style = Style("\x1B[1m");
+pub const END: Style = Style("\x1B[0m");
+
+// The choice is resolved lazily, once the first style is displayed.
+const UNRESOLVED: u8 = 0;
+const ENABLED: u8 = 1;
+const DISABLED: u8 = 2;
+
+static COLORS: AtomicU8 = AtomicU8::new(UNRESOLVED);
+
+/// An ANSI escape sequence, displayed only when colors are enabled.
+#[derive(Clone, Copy, Debug)]
+pub struct Style(&'static str);
+
+impl fmt::Display for Style {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if enabled() {
+            f.write_str(self.0)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Sets when to use colors, either `always`, `never` or `auto` (when stdout is a terminal).
+pub fn set_choice(choice: &str) {
+    let colors = match choice {
+        ALWAYS => ENABLED,
+        NEVER => DISABLED,
+        _ => UNRESOLVED,
+    };
+    COLORS.store(colors, Ordering::Relaxed);
+}
+
+/// Returns true if colors are enabled.
+pub fn enabled() -> bool {
+    match COLORS.load(Ordering::Relaxed) {
+        ENABLED => true,
+        DISABLED => false,
+        _ => {
+            let enabled = std::io::stdout().is_terminal();
+            COLORS.store(if enabled { ENABLED } else { DISABLED }, Ordering::Relaxed);
+            enabled
+        }
+    }
+}
@@ -0,0 +1,233 @@
+//! Daemon mode
+//!
+//! Serves build and check requests over a local TCP socket, sparing editors and scripts the
+//! start of a compiler process on each build. Requests and responses are JSON objects, one per
+//! line:
+//!
+//! ```text
+//! {"id": 1, "method": "build", "args": ["hello.zph", "-O"], "cwd": "/home/me/hello"}
+//! {"id": 1, "success": true, "cached": false, "duration_ms": 3.1, "diagnostics": []}
+//! ```
+//!
+//! The method is either `build` or `check` and takes the arguments of the command of the same
+//! name, relative paths are resolved from `cwd` if set, or from the directory the daemon was
+//! started in. `shutdown` stops the daemon.
+//!
+//! The response to each request is kept in memory along with the modification time of the files
+//! it was produced from: the source files, the directories of the packages and the written
+//! outputs. The same request is answered from memory as long as none of them changed.
+
+use clap::Clap;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::panic;
+use std::path::PathBuf;
+use std::time::{Instant, SystemTime};
+
+use zephyr::error::{ErrorHandler, Level};
+use zephyr::Ctx;
+use zephyrc::error_handler::StandardErrorHandler;
+use zephyrc::resolver::StandardResolver;
+
+use super::{
+    build_package, check_build_config, check_package, exit_with_error, Command, Config,
+    DaemonConfig,
+};
+
+const SHUTDOWN: &str = "shutdown";
+
+/// The modification time of each file a response depends on, `None` if it does not exist.
+type Fingerprint = Vec<(PathBuf, Option<SystemTime>)>;
+
+struct Daemon {
+    /// The directory relative paths are resolved from by default.
+    dir: PathBuf,
+    /// The responses to the requests, along with the state of the files they depend on.
+    cache: HashMap<String, (Fingerprint, Value)>,
+}
+
+/// Listens for requests until asked to shut down, then exits.
+pub fn daemon(config: &DaemonConfig) -> ! {
+    let listener = match TcpListener::bind(("127.0.0.1", config.port)) {
+        Ok(listener) => listener,
+        Err(e) => exit_with_error(format!("Could not listen on port {}: {}", config.port, e)),
+    };
+    let dir = match std::env::current_dir() {
+        Ok(dir) => dir,
+        Err(e) => exit_with_error(format!("Could not read the current directory: {}", e)),
+    };
+    let mut daemon = Daemon {
+        dir,
+        cache: HashMap::new(),
+    };
+    println!("Listening on 127.0.0.1:{}", config.port);
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => daemon.serve(stream),
+            Err(e) => eprintln!("Could not accept a connection: {}", e),
+        }
+    }
+    std::process::exit(0);
+}
+
+impl Daemon {
+    /// Answers the requests of a client until it closes the connection.
+    fn serve(&mut self, stream: TcpStream) {
+        let reader = match stream.try_clone() {
+            Ok(stream) => BufReader::new(stream),
+            Err(_) => return,
+        };
+        let mut writer = stream;
+        for line in reader.lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(_) => return,
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            let request = match serde_json::from_str::<Value>(&line) {
+                Ok(request) => request,
+                Err(e) => {
+                    let _ = writeln!(writer, "{}", failure(Value::Null, e.to_string()));
+                    continue;
+                }
+            };
+            if request["method"] == SHUTDOWN {
+                let _ = writeln!(
+                    writer,
+                    "{}",
+                    json!({ "id": request["id"], "success": true })
+                );
+                std::process::exit(0);
+            }
+            if writeln!(writer, "{}", self.handle(&request)).is_err() {
+                return;
+            }
+        }
+    }
+
+    /// Answers a request, from memory if the files it depends on did not change.
+    fn handle(&mut self, request: &Value) -> Value {
+        let start = Instant::now();
+        let id = request["id"].clone();
+        let method = request["method"].as_str().unwrap_or("");
+        let args = match request["args"].as_array() {
+            Some(args) => args.iter().filter_map(|arg| arg.as_str()).collect(),
+            None => Vec::new(),
+        };
+        let dir = match request["cwd"].as_str() {
+            Some(cwd) => PathBuf::from(cwd),
+            None => self.dir.clone(),
+        };
+        if let Err(e) = std::env::set_current_dir(&dir) {
+            return failure(id, format!("Could not enter '{}': {}", dir.display(), e));
+        }
+
+        let key = json!([method, args, dir]).to_string();
+        if let Some((files, response)) = self.cache.get(&key) {
+            if *files == fingerprint(files.iter().map(|(path, _)| path.clone())) {
+                let mut response = response.clone();
+                response["id"] = id;
+                response["cached"] = json!(true);
+                response["duration_ms"] = json!(start.elapsed().as_secs_f64() * 1000.0);
+                return response;
+            }
+        }
+
+        let result = panic::catch_unwind(|| compile(method, &args));
+        let (mut response, files) = match result {
+            Ok(Ok(result)) => result,
+            Ok(Err(message)) => return failure(id, message),
+            Err(_) => return failure(id, String::from("The compiler panicked")),
+        };
+        let files = fingerprint(files.into_iter().map(|path| dir.join(path)));
+        self.cache.insert(key, (files, response.clone()));
+        response["id"] = id;
+        response["cached"] = json!(false);
+        response["duration_ms"] = json!(start.elapsed().as_secs_f64() * 1000.0);
+        response
+    }
+}
+
+/// Runs a command and returns its response along with the files it depends on.
+fn compile(method: &str, args: &[&str]) -> Result<(Value, Vec<PathBuf>), String> {
+    let mut argv = vec!["zephyr", method];
+    argv.extend(args);
+    let config = Config::try_parse_from(argv).map_err(|e| e.to_string())?;
+    let mut resolver = StandardResolver::new();
+    let mut ctx = Ctx::new();
+    let mut err = StandardErrorHandler::new(ctx.sources().clone());
+    let mut files = Vec::new();
+    let result = match &config.command {
+        Command::Build(config) if config.watch => {
+            return Err(String::from("The daemon does not support watch mode"));
+        }
+        Command::Build(config) => {
+            check_build_config(config)?;
+            files.push(config.package.input.clone());
+            build_package(config, &mut ctx, &mut err, &mut resolver)
+                .map(|outputs| files.extend(outputs))
+        }
+        Command::Check(config) => {
+            files.push(config.package.input.clone());
+            check_package(config, &mut ctx, &mut err, &mut resolver)
+        }
+        _ => return Err(format!("Unsupported method '{}'", method)),
+    };
+
+    // New files are detected through the modification time of their directory
+    let sources = resolver.get_file_paths();
+    let mut dirs: Vec<PathBuf> = sources
+        .iter()
+        .filter_map(|path| path.parent().map(|dir| dir.to_owned()))
+        .collect();
+    dirs.dedup();
+    files.extend(sources);
+    files.extend(dirs);
+
+    let response = json!({
+        "success": result.is_ok() && !err.has_error(),
+        "diagnostics": diagnostics(&err, &ctx, &resolver),
+    });
+    Ok((response, files))
+}
+
+/// Returns the errors and warnings reported while compiling, with their location if any.
+fn diagnostics(err: &StandardErrorHandler, ctx: &Ctx, resolver: &StandardResolver) -> Vec<Value> {
+    let mut diagnostics = Vec::new();
+    for (level, error) in err.errors() {
+        let level = match level {
+            Level::Error => "error",
+            Level::Warning => "warning",
+            Level::Internal => "internal",
+        };
+        let mut diagnostic = json!({ "level": level, "message": error.message });
+        if let Some(loc) = error.loc {
+            let file = ctx.sources().get(loc.f_id);
+            if let (Some(file), Some(path)) = (file, resolver.get_file_path(loc.f_id)) {
+                let (line, column) = file.line_index().line_col(loc.pos);
+                diagnostic["file"] = json!(path.display().to_string());
+                diagnostic["line"] = json!(line + 1);
+                diagnostic["column"] = json!(column + 1);
+            }
+        }
+        diagnostics.push(diagnostic);
+    }
+    diagnostics
+}
+
+fn fingerprint(paths: impl Iterator<Item = PathBuf>) -> Fingerprint {
+    paths
+        .map(|path| {
+            let modified = path.metadata().and_then(|meta| meta.modified()).ok();
+            (path, modified)
+        })
+        .collect()
+}
+
+fn failure(id: Value, message: String) -> Value {
+    json!({ "id": id, "success": false, "error": message })
+}
@@ -0,0 +1,46 @@
+//! Depfiles
+//!
+//! A depfile is a Makefile fragment declaring the source files an output depends on, it is read
+//! by Make, Ninja and Bazel to rebuild the output when one of those files changes.
+
+use std::path::{Path, PathBuf};
+
+/// Returns a rule stating that `target` depends on `sources`, in the Make syntax.
+pub fn depfile(target: &Path, sources: &[PathBuf]) -> String {
+    let mut rule = format!("{}:", escape(target));
+    for source in sources {
+        rule.push_str(" \\\n  ");
+        rule.push_str(&escape(source));
+    }
+    rule.push('\n');
+    rule
+}
+
+/// Escapes the characters of a path which have a meaning in a Makefile.
+fn escape(path: &Path) -> String {
+    let mut escaped = String::new();
+    for c in path.to_string_lossy().chars() {
+        match c {
+            ' ' | '#' => escaped.push('\\'),
+            '$' => escaped.push('$'),
+            _ => (),
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rule() {
+        let sources = vec![PathBuf::from("/pkg/main.zph"), PathBuf::from("/pkg/my lib.zph")];
+        assert_eq!(
+            depfile(Path::new("main.zph.wasm"), &sources),
+            "main.zph.wasm: \\\n  /pkg/main.zph \\\n  /pkg/my\\ lib.zph\n"
+        );
+        assert_eq!(escape(Path::new("a$b#c")), "a$$b\\#c");
+    }
+}
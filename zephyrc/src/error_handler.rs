@@ -1,56 +1,53 @@
+use super::color::{Style, BLUE, BOLD, END, GREEN, MAGENTA, RED, YELLOW};
 use super::errors::Error;
 use std::collections::HashMap;
-use zephyr::error::{ErrorHandler, Level, Location};
+use zephyr::error::{
+    ErrorHandler, Level, LineIndex, Lint, LintLevel, Location, SourceMap, Suggestion,
+};
 use zephyr::resolver::FileId;
 
-const RED: &'static str = "\x1B[31m";
-const YELLOW: &'static str = "\x1B[33m";
-const MAGENTA: &'static str = "\x1B[35m";
-const BOLD: &'static str = "\x1B[1m";
-const END: &'static str = "\x1B[0m";
+const INTERNAL_NOTE: &str =
+    "this is a bug in the compiler, please report it at https://github.com/CharlyCst/zephyr/issues";
 
 /// Store errors encountered during compilation and generate a report on demand.
 ///
-/// Each file should be attributed to a single ErrorHandler. ErrorHandlers can be
-/// merged as needed when proceeding through the pipeline.
+/// The code of the files is looked up in a shared `SourceMap` when printing the errors. Passes can
+/// collect their errors in separate handlers, created with `sink`, which are merged as needed
+/// when proceeding through the pipeline.
+///
+/// Lints are stored regardless of their level, which is only resolved when reporting: this way
+/// the configuration of the handler errors are merged into is the one that applies.
+///
+/// Identical errors reported at the same location are printed only once, and the number of
+/// printed errors can be limited to avoid flooding the output with cascading errors.
 pub struct StandardErrorHandler {
     has_error: bool,
     errors: Vec<Error>,
-    codes: HashMap<FileId, String>,
+    sources: SourceMap,
+    lint_levels: HashMap<Lint, LintLevel>,
+    deny_warnings: bool,
+    error_limit: Option<usize>,
 }
 
 impl ErrorHandler for StandardErrorHandler {
-    fn new(code: String, f_id: FileId) -> Self {
-        let mut codes = HashMap::new();
-        codes.insert(f_id, code);
-        StandardErrorHandler {
-            has_error: false,
-            errors: Vec::new(),
-            codes,
-        }
-    }
-
-    /// Create a fresh `ErrorHandler` without backing it with a file.
-    fn new_no_file() -> StandardErrorHandler {
+    fn sink(&self) -> Self {
         StandardErrorHandler {
             has_error: false,
             errors: Vec::new(),
-            codes: HashMap::new(),
-        }
-    }
-
-    /// Return a file owned by the ErrorHandler.
-    fn get_file(&self, f_id: FileId) -> Option<&str> {
-        if let Some(s) = self.codes.get(&f_id) {
-            Some(&*s)
-        } else {
-            None
+            sources: self.sources.clone(),
+            lint_levels: self.lint_levels.clone(),
+            deny_warnings: self.deny_warnings,
+            error_limit: self.error_limit,
         }
     }
 
     /// Return true if an error was reported.
     fn has_error(&self) -> bool {
         self.has_error
+            || self
+                .errors
+                .iter()
+                .any(|e| self.get_level(e) == Some(Level::Error))
     }
 
     fn log(&mut self, message: String, level: Level, loc: Option<Location>) {
@@ -62,6 +59,68 @@ impl ErrorHandler for StandardErrorHandler {
             loc,
             level,
             message,
+            lint: None,
+            note: None,
+            suggestion: None,
+        })
+    }
+
+    fn lint(&mut self, lint: Lint, loc: Location, message: String) {
+        self.errors.push(Error {
+            loc: Some(loc),
+            level: Level::Warning,
+            message,
+            lint: Some(lint),
+            note: None,
+            suggestion: None,
+        })
+    }
+
+    fn lint_with_note(
+        &mut self,
+        lint: Lint,
+        loc: Location,
+        message: String,
+        note_loc: Location,
+        note: String,
+    ) {
+        self.errors.push(Error {
+            loc: Some(loc),
+            level: Level::Warning,
+            message,
+            lint: Some(lint),
+            note: Some((note_loc, note)),
+            suggestion: None,
+        })
+    }
+
+    fn report_with_note(
+        &mut self,
+        loc: Location,
+        message: String,
+        note_loc: Location,
+        note: String,
+    ) {
+        self.has_error = true;
+        self.errors.push(Error {
+            loc: Some(loc),
+            level: Level::Error,
+            message,
+            lint: None,
+            note: Some((note_loc, note)),
+            suggestion: None,
+        })
+    }
+
+    fn report_with_suggestion(&mut self, loc: Location, message: String, suggestion: Suggestion) {
+        self.has_error = true;
+        self.errors.push(Error {
+            loc: Some(loc),
+            level: Level::Error,
+            message,
+            lint: None,
+            note: None,
+            suggestion: Some(suggestion),
         })
     }
 
@@ -74,17 +133,6 @@ impl ErrorHandler for StandardErrorHandler {
     fn merge(&mut self, other: StandardErrorHandler) {
         self.has_error = self.has_error || other.has_error;
         self.errors.extend(other.errors);
-
-        for (f_id, code) in other.codes.into_iter() {
-            if self.codes.contains_key(&f_id) {
-                self.report_internal_no_loc(format!(
-                    "Merging two ErrorHandlers collecting errors for the same file with id {}.",
-                    f_id
-                ));
-            } else {
-                self.codes.insert(f_id, code);
-            }
-        }
     }
 
     /// Unconditionnaly print all errors that have been reported.
@@ -94,13 +142,82 @@ impl ErrorHandler for StandardErrorHandler {
 }
 
 impl StandardErrorHandler {
-    /// Print all the errors accumulated by this handler.
+    /// Creates a handler printing the errors with the code of the files of `sources`, usually the
+    /// source map of the compilation context.
+    pub fn new(sources: SourceMap) -> Self {
+        StandardErrorHandler {
+            has_error: false,
+            errors: Vec::new(),
+            sources,
+            lint_levels: HashMap::new(),
+            deny_warnings: false,
+            error_limit: None,
+        }
+    }
+
+    /// Sets the level of a lint, overriding its default level.
+    pub fn set_lint_level(&mut self, lint: Lint, level: LintLevel) {
+        self.lint_levels.insert(lint, level);
+    }
+
+    /// Report warnings, including lints, as errors.
+    pub fn set_deny_warnings(&mut self, deny_warnings: bool) {
+        self.deny_warnings = deny_warnings;
+    }
+
+    /// Print at most `limit` errors, warnings are not limited. The remaining errors are only
+    /// counted in the summary.
+    pub fn set_error_limit(&mut self, limit: Option<usize>) {
+        self.error_limit = limit;
+    }
+
+    /// Returns the errors accumulated by this handler along with the level they must be reported
+    /// at. Silenced lints and duplicated errors are omitted.
+    pub fn errors(&self) -> Vec<(Level, &Error)> {
+        let mut errors: Vec<(Level, &Error)> = Vec::new();
+        for e in &self.errors {
+            let level = match self.get_level(e) {
+                Some(level) => level,
+                None => continue,
+            };
+            if !errors.iter().any(|(_, other)| is_duplicate(e, other)) {
+                errors.push((level, e));
+            }
+        }
+        errors
+    }
+
+    /// Returns the level an error must be reported at, or `None` if it is silenced.
+    fn get_level(&self, e: &Error) -> Option<Level> {
+        let level = match e.lint {
+            Some(lint) => match self.lint_levels.get(&lint).copied() {
+                Some(LintLevel::Allow) => return None,
+                Some(LintLevel::Deny) => Level::Error,
+                Some(LintLevel::Warn) => Level::Warning,
+                None if lint.default_level() == LintLevel::Allow => return None,
+                None => Level::Warning,
+            },
+            None => e.level,
+        };
+        if level == Level::Warning && self.deny_warnings {
+            Some(Level::Error)
+        } else {
+            Some(level)
+        }
+    }
+
+    /// Print all the errors accumulated by this handler, followed by a summary if there are
+    /// errors.
     fn print_all(&mut self) {
+        let errors = self.errors();
+        let nb_errors = errors.iter().filter(|(level, _)| is_error(*level)).count();
+        let errors = self.limit(errors);
+
         // Sort errors on file ID.
         let mut errors_no_loc = Vec::new();
-        let mut errors_by_files: HashMap<FileId, Vec<&Error>> = HashMap::new();
-        for err in self.errors.iter() {
-            if let Some(loc) = err.loc {
+        let mut errors_by_files: HashMap<FileId, Vec<(Level, &Error)>> = HashMap::new();
+        for err in errors {
+            if let Some(loc) = err.1.loc {
                 if let Some(errors) = errors_by_files.get_mut(&loc.f_id) {
                     errors.push(err);
                 } else {
@@ -112,16 +229,18 @@ impl StandardErrorHandler {
         }
 
         // Print all errors without location.
-        for err in errors_no_loc {
-            self.print(&err);
+        for (level, err) in errors_no_loc {
+            self.print(level, err);
         }
 
-        // Print all errors with location.
-        for (f_id, errors) in errors_by_files.into_iter() {
-            if let Some(code) = self.codes.get(&f_id) {
-                self.print_errors_with_loc(code, errors);
+        // Print all errors with location, files are printed in a deterministic order.
+        let mut errors_by_files = errors_by_files.into_iter().collect::<Vec<_>>();
+        errors_by_files.sort_by_key(|(f_id, _)| *f_id);
+        for (f_id, errors) in errors_by_files {
+            if let Some(file) = self.sources.get(f_id) {
+                self.print_errors_with_loc(file.code(), &file.line_index(), errors);
             } else {
-                if let Some(err) = errors.first() {
+                if let Some((_, err)) = errors.first() {
                     let err = Error {
                         loc: None,
                         level: Level::Internal,
@@ -129,120 +248,257 @@ impl StandardErrorHandler {
                             "Found errors with unknown file ID '{}': '{}'.",
                             f_id, err.message
                         ),
+                        lint: None,
+                        note: None,
+                        suggestion: None,
                     };
-                    self.print(&err);
+                    self.print(err.level, &err);
                 } else {
                     continue;
                 }
             }
         }
+
+        if nb_errors > 0 {
+            self.print_summary(nb_errors);
+        }
+    }
+
+    /// Drops the errors above the limit, in the order they are printed: errors without location
+    /// first, then by file and location.
+    fn limit<'a>(&self, mut errors: Vec<(Level, &'a Error)>) -> Vec<(Level, &'a Error)> {
+        let limit = match self.error_limit {
+            Some(limit) => limit,
+            None => return errors,
+        };
+        errors.sort_by_key(|(_, e)| e.loc.map(|loc| (loc.f_id, loc)));
+        let mut nb_errors = 0;
+        errors.retain(|(level, _)| {
+            if !is_error(*level) {
+                return true;
+            }
+            nb_errors += 1;
+            nb_errors <= limit
+        });
+        errors
+    }
+
+    /// Prints the number of errors, and how many of them were not printed because of the limit.
+    fn print_summary(&self, nb_errors: usize) {
+        let plural = if nb_errors > 1 { "s" } else { "" };
+        match self.error_limit {
+            Some(limit) if nb_errors > limit => println!(
+                "{}{}Error:{}{} aborting due to {} previous error{} ({} not shown){}",
+                RED,
+                BOLD,
+                END,
+                RED,
+                nb_errors,
+                plural,
+                nb_errors - limit,
+                END
+            ),
+            _ => println!(
+                "{}{}Error:{}{} aborting due to {} previous error{}{}",
+                RED, BOLD, END, RED, nb_errors, plural, END
+            ),
+        }
     }
 
     /// Pretty print errors with code context.
     /// All errors **must** have a location corresponding to `code`.
-    fn print_errors_with_loc(&self, code: &str, mut errors: Vec<&Error>) {
+    fn print_errors_with_loc(
+        &self,
+        code: &str,
+        index: &LineIndex,
+        mut errors: Vec<(Level, &Error)>,
+    ) {
         // Sort errors by locations.
-        errors.sort_unstable();
-
-        let mut error_iterator = errors.iter();
-        let mut err = if let Some(err) = error_iterator.next() {
-            err
-        } else {
-            return;
-        };
+        errors.sort_unstable_by(|(_, e1), (_, e2)| e1.cmp(e2));
 
-        // Maintain position information
-        let mut line = 1;
-        let mut lines_pos = 0;
-        let mut pos = 0;
-        let mut loc = err.loc.unwrap();
-
-        // Iterate the code, keep an extra iterator pointing to the beginning of the current line.
-        let mut iter = code.chars();
-        let mut line_iter = iter.clone();
-        while let Some(c) = iter.next() {
-            if c == '\n' {
-                // Next line
-                line += 1;
-                lines_pos = pos;
-                line_iter = iter.clone();
+        for (level, err) in errors {
+            let loc = match err.loc {
+                Some(loc) => loc,
+                None => continue,
+            };
+            self.print_span(get_color(level), code, index, loc);
+            println!("{}", format_message(level, err));
+            if let Some((note_loc, note)) = &err.note {
+                self.print_note(*note_loc, note);
             }
-            if pos == loc.pos {
-                // Found the location of an error
-                let error_pos = pos - lines_pos;
-                let min_size = error_pos + loc.len;
-                let erroneous_code = self.get_substr(line_iter.clone(), min_size);
-                self.print_line(err, erroneous_code, error_pos, loc.len, line);
-
-                // Continue while at least one error remains.
-                err = if let Some(err) = error_iterator.next() {
-                    err
-                } else {
-                    return;
-                };
-                loc = err.loc.unwrap();
-            }
-            pos += 1;
         }
     }
 
-    /// Pretty print an error with position information.
-    fn print_line(&self, e: &Error, code: String, pos: u32, len: u32, line: usize) {
-        let color = get_color(e);
-        let err_name = get_err_name(e);
+    /// Pretty print the lines of code spanned by a location, the location is underlined.
+    fn print_span(&self, color: Style, code: &str, index: &LineIndex, loc: Location) {
+        let (line, col) = index.line_col(loc.pos);
+        let last_line = index.line_of((loc.pos + loc.len).saturating_sub(1)).max(line);
+        let erroneous_code = (line..=last_line)
+            .map(|line| index.line(line))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let start = std::cmp::min(loc.pos as usize, code.len());
+        let end = std::cmp::min(start + loc.len as usize, code.len());
+        let len = code.get(start..end).map_or(0, |span| span.chars().count());
 
-        println!("{:>5} | {}", line, code);
+        println!("{:>5} | {}", line + 1, erroneous_code);
         println!(
-            "       {:blank$}{}{:^<underline$}{}",
-            " ",
+            "        {:blank$}{}{:^<underline$}{}",
+            "",
             color,
             "^",
             END,
-            blank = pos as usize,
-            underline = len as usize
-        );
-        println!(
-            "{}{}{}:{}{} {}{}\n",
-            color, BOLD, err_name, END, color, e.message, END
+            blank = col,
+            underline = len
         );
     }
 
-    /// Pretty print an error without position information.
-    fn print(&self, e: &Error) {
-        let color = get_color(e);
-        let err_name = get_err_name(e);
+    /// Pretty print the note of an error, the note may point to another file than the error.
+    fn print_note(&self, loc: Location, note: &str) {
+        if let Some(file) = self.sources.get(loc.f_id) {
+            self.print_span(BLUE, file.code(), &file.line_index(), loc);
+        }
+        println!("{}{}note:{} {}\n", BLUE, BOLD, END, note);
+    }
 
-        println!(
-            "{}{}{}:{}{} {}{}\n",
-            color, BOLD, err_name, END, color, e.message, END
-        );
+    /// Pretty print an error without position information.
+    fn print(&self, level: Level, e: &Error) {
+        println!("{}", format_message(level, e));
     }
+}
 
-    /// Returns a copy of the smallest number of full lines starting at `iter`
-    /// and spanning at least `min_size` characters.
-    /// Used to extract lines containing an error.
-    fn get_substr(&self, iter: std::str::Chars<'_>, min_size: u32) -> String {
-        let mut idx = 0;
-        iter.take_while(|c| {
-            idx += 1;
-            idx < min_size as usize || *c != '\n'
-        })
-        .collect()
+fn is_error(level: Level) -> bool {
+    match level {
+        Level::Error | Level::Internal => true,
+        Level::Warning => false,
     }
 }
 
-fn get_color(e: &Error) -> &'static str {
-    match e.level {
+/// Two errors are duplicates if they report the same message at the same location.
+fn is_duplicate(e: &Error, other: &Error) -> bool {
+    e.loc == other.loc
+        && e.level == other.level
+        && e.lint == other.lint
+        && e.message == other.message
+}
+
+fn get_color(level: Level) -> Style {
+    match level {
         Level::Internal => MAGENTA,
         Level::Error => RED,
         Level::Warning => YELLOW,
     }
 }
 
-fn get_err_name(e: &Error) -> &'static str {
-    match e.level {
-        Level::Internal => "Internal",
+/// Formats the message of an error, followed by the suggestion to fix it if any. Internal errors
+/// are followed by a note asking to report them.
+fn format_message(level: Level, e: &Error) -> String {
+    let color = get_color(level);
+    let err_name = get_err_name(level, e);
+    let mut message = format!(
+        "{}{}{}:{}{} {}{}\n",
+        color, BOLD, err_name, END, color, e.message, END
+    );
+    if let Some(suggestion) = &e.suggestion {
+        message.push_str(&format!(
+            "{}{}help:{} {}\n",
+            GREEN, BOLD, END, suggestion.message
+        ));
+    }
+    if level == Level::Internal {
+        message.push_str(&format!("{}{}note:{} {}\n", BLUE, BOLD, END, INTERNAL_NOTE));
+    }
+    message
+}
+
+/// Returns the name of an error, lints are suffixed with their own name.
+fn get_err_name(level: Level, e: &Error) -> String {
+    let name = match level {
+        Level::Internal => "Internal compiler error",
         Level::Error => "Error",
         Level::Warning => "Warning",
+    };
+    match e.lint {
+        Some(lint) => format!("{}[{}]", name, lint),
+        None => String::from(name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn levels(err: &StandardErrorHandler) -> Vec<Level> {
+        err.errors().into_iter().map(|(level, _)| level).collect()
+    }
+
+    #[test]
+    fn lint_levels() {
+        let loc = Location::dummy();
+        let mut err = StandardErrorHandler::new(SourceMap::new());
+        let mut child = err.sink();
+        child.lint(Lint::UnusedImport, loc, String::from("unused"));
+        child.lint(Lint::ImplicitDefault, loc, String::from("default"));
+        err.merge(child);
+        assert_eq!(levels(&err), vec![Level::Warning]);
+        assert!(!err.has_error());
+
+        err.set_lint_level(Lint::ImplicitDefault, LintLevel::Warn);
+        err.set_lint_level(Lint::UnusedImport, LintLevel::Allow);
+        assert_eq!(levels(&err), vec![Level::Warning]);
+        assert_eq!(err.errors()[0].1.lint, Some(Lint::ImplicitDefault));
+
+        err.set_deny_warnings(true);
+        assert_eq!(levels(&err), vec![Level::Error]);
+        assert!(err.has_error());
+
+        let mut err = StandardErrorHandler::new(SourceMap::new());
+        let (message, note) = (String::from("unreachable"), String::from("return"));
+        err.lint_with_note(Lint::UnreachableCode, loc, message, loc, note);
+        assert_eq!(levels(&err), vec![Level::Warning]);
+        assert!(err.errors()[0].1.note.is_some());
+    }
+
+    #[test]
+    fn internal_errors() {
+        let mut err = StandardErrorHandler::new(SourceMap::new());
+        err.report_internal(Location::dummy(), String::from("missing local"));
+        assert_eq!(levels(&err), vec![Level::Internal]);
+        assert!(err.has_error());
+
+        let (level, e) = err.errors()[0];
+        let message = format_message(level, e);
+        assert!(message.contains("Internal compiler error"));
+        assert!(message.contains("missing local"));
+        assert!(message.contains(INTERNAL_NOTE));
+
+        let e = Error {
+            loc: None,
+            level: Level::Error,
+            message: String::from("type mismatch"),
+            lint: None,
+            note: None,
+            suggestion: None,
+        };
+        assert!(!format_message(Level::Error, &e).contains(INTERNAL_NOTE));
+    }
+
+    #[test]
+    fn duplicates_and_limit() {
+        let loc = Location::dummy();
+        let mut err = StandardErrorHandler::new(SourceMap::new());
+        err.report(loc, String::from("type mismatch"));
+        err.report(loc, String::from("type mismatch"));
+        err.report(loc, String::from("undeclared variable"));
+        err.warn(loc, String::from("unused variable"));
+        assert_eq!(err.errors().len(), 3);
+
+        err.set_error_limit(Some(1));
+        let levels = err
+            .limit(err.errors())
+            .into_iter()
+            .map(|(level, _)| level)
+            .collect::<Vec<_>>();
+        assert_eq!(levels, vec![Level::Error, Level::Warning]);
     }
 }
@@ -1,10 +1,16 @@
 use std::cmp::Ordering;
-use zephyr::error::{Level, Location};
+use zephyr::error::{Level, Lint, Location, Suggestion};
 
 pub struct Error {
     pub loc: Option<Location>,
     pub level: Level,
     pub message: String,
+    /// The lint which produced this error, if any.
+    pub lint: Option<Lint>,
+    /// A note pointing at a related location, such as a declaration.
+    pub note: Option<(Location, String)>,
+    /// A fix for the error, which editors can apply.
+    pub suggestion: Option<Suggestion>,
 }
 
 // Error without location are the smallest
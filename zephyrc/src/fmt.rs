@@ -0,0 +1,78 @@
+//! Formatting
+//!
+//! Formats the Zephyr files of a package (or a single file) in place, or checks that they are
+//! already formatted. Assembly files are left untouched.
+
+use std::fs;
+use std::path::PathBuf;
+
+use zephyr::error::{ErrorHandler, SourceMap};
+use zephyr::resolver::FileId;
+use zephyrc::color::{BOLD, END, YELLOW};
+use zephyrc::error_handler::StandardErrorHandler;
+use zephyrc::resolver::{resolve_path, ResolvedPath, ZEPHYR_EXTENSION};
+
+use super::FmtConfig;
+
+/// Formats the files pointed by the configuration, then exits.
+///
+/// In check mode, exits with an error if at least one file is not formatted.
+pub fn fmt(config: &FmtConfig) -> ! {
+    let sources = SourceMap::new();
+    let mut err = StandardErrorHandler::new(sources.clone());
+    let paths = match resolve_path(&config.input) {
+        Ok(ResolvedPath::Dir(paths)) => paths,
+        Ok(ResolvedPath::File(path)) => vec![path],
+        Err(e) => {
+            err.report_no_loc(e);
+            err.flush();
+            std::process::exit(65);
+        }
+    };
+
+    let mut unformatted = Vec::new();
+    for (idx, path) in paths.into_iter().enumerate() {
+        if !path
+            .extension()
+            .map_or(false, |ext| ext.eq(ZEPHYR_EXTENSION))
+        {
+            continue;
+        }
+        let code = match fs::read_to_string(&path) {
+            Ok(code) => code,
+            Err(e) => {
+                err.report_no_loc(format!("Could not read '{}': {}", path.display(), e));
+                continue;
+            }
+        };
+        // IDs only need to be unique among the files of this run.
+        let f_id = FileId(idx as u16 + 1);
+        let file = sources.add(f_id, code);
+        match zephyr::format_code(&file, &mut err) {
+            Ok(formatted) if formatted != file.code() => {
+                if !config.check {
+                    if let Err(e) = fs::write(&path, formatted) {
+                        err.report_no_loc(format!("Could not write '{}': {}", path.display(), e));
+                    }
+                }
+                unformatted.push(path);
+            }
+            _ => (),
+        }
+    }
+
+    err.flush();
+    if config.check {
+        report_unformatted(&unformatted);
+    }
+    if err.has_error() || (config.check && !unformatted.is_empty()) {
+        std::process::exit(65);
+    }
+    std::process::exit(0);
+}
+
+fn report_unformatted(paths: &[PathBuf]) {
+    for path in paths {
+        println!("{}{}Not formatted:{} {}", YELLOW, BOLD, END, path.display());
+    }
+}
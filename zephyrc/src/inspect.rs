@@ -0,0 +1,38 @@
+//! Inspection
+//!
+//! Prints the custom sections of a WebAssembly module, such as the build metadata embedded by
+//! the compiler.
+
+use std::fs;
+
+use zephyr::error::{ErrorHandler, SourceMap};
+use zephyrc::color::{BOLD, END};
+use zephyrc::error_handler::StandardErrorHandler;
+
+use super::InspectConfig;
+
+/// Prints the custom sections of the module pointed by the configuration, then exits.
+///
+/// Textual sections are printed as is, only the size of binary sections is printed.
+pub fn inspect(config: &InspectConfig) -> ! {
+    let mut err = StandardErrorHandler::new(SourceMap::new());
+    let sections = fs::read(&config.input)
+        .map_err(|e| format!("Could not read '{}': {}", config.input.display(), e))
+        .and_then(|module| zephyr::custom_sections(&module));
+    let sections = match sections {
+        Ok(sections) => sections,
+        Err(e) => {
+            err.report_no_loc(e);
+            err.flush();
+            std::process::exit(65);
+        }
+    };
+
+    for (name, content) in sections {
+        match String::from_utf8(content) {
+            Ok(content) => println!("{}{}:{} {}", BOLD, name, END, content),
+            Err(e) => println!("{}{}:{} {} bytes", BOLD, name, END, e.as_bytes().len()),
+        }
+    }
+    std::process::exit(0);
+}
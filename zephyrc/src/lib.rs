@@ -0,0 +1,9 @@
+//! Building blocks shared by the Zephyr executables
+//!
+//! The compiler (`zephyr`) and the language server (`zephyr-lsp`) both rely on the standard
+//! resolver and error handler defined here.
+
+pub mod color;
+pub mod error_handler;
+pub mod errors;
+pub mod resolver;
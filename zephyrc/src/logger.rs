@@ -0,0 +1,51 @@
+//! Logger
+//!
+//! The compiler logs its intermediate representations through the `log` facade, under a target
+//! per phase (such as `zephyr::mir`). This logger prints them on stdout when requested.
+
+use log::{LevelFilter, Log, Metadata, Record};
+
+use super::PackageOptions;
+
+/// The phases whose logs can be selected, named after the modules of the compiler.
+pub const PHASES: &[&str] = &["ast", "hir", "mir", "ssa", "wasm", "native"];
+
+struct Logger {
+    /// The selected targets, all the targets of the compiler if empty.
+    targets: Vec<String>,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        let target = metadata.target();
+        let is_compiler = target == "zephyr" || target.starts_with("zephyr::");
+        is_compiler
+            && (self.targets.is_empty()
+                || self.targets.iter().any(|selected| {
+                    target == selected || target.starts_with(&format!("{}::", selected))
+                }))
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            println!("{}", record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Installs the logger if the options ask for the logs of some phases.
+pub fn init(options: &PackageOptions) {
+    if !options.verbose && options.log.is_empty() {
+        return;
+    }
+    let targets = options
+        .log
+        .iter()
+        .map(|phase| format!("zephyr::{}", phase))
+        .collect();
+    if log::set_boxed_logger(Box::new(Logger { targets })).is_ok() {
+        log::set_max_level(LevelFilter::Debug);
+    }
+}
@@ -0,0 +1,339 @@
+//! Analysis of a document
+//!
+//! Runs the compiler up to the HIR on the module containing a document, then answers the queries
+//! of the editor from the diagnostics and symbols collected along the way.
+
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use zephyr::error::{ErrorHandler, Level, LineIndex, Location, SourceFile};
+use zephyr::resolver::{ModuleKind, ModulePath};
+use zephyr::Ctx;
+use zephyrc::error_handler::StandardErrorHandler;
+use zephyrc::resolver::StandardResolver;
+
+// LSP diagnostic severities
+const SEVERITY_ERROR: u8 = 1;
+const SEVERITY_WARNING: u8 = 2;
+
+/// The result of the analysis of a document.
+pub struct Analysis {
+    path: PathBuf,
+    ctx: Ctx,
+    err: StandardErrorHandler,
+}
+
+impl Analysis {
+    /// Type checks the module containing the document at `path`.
+    pub fn new(path: PathBuf, resolver: &mut StandardResolver) -> Self {
+        let mut ctx = Ctx::new();
+        let mut err = StandardErrorHandler::new(ctx.sources().clone());
+        let _ = check(&path, &mut ctx, &mut err, resolver);
+        Self { path, ctx, err }
+    }
+
+    /// Returns the diagnostics of each file, errors without location are attributed to the
+    /// analyzed document.
+    ///
+    /// The suggestions to fix an error are stored as text edits in the `data` field of its
+    /// diagnostic, from which the code actions are built.
+    pub fn diagnostics(&self, resolver: &StandardResolver) -> HashMap<PathBuf, Vec<Value>> {
+        let mut diagnostics: HashMap<PathBuf, Vec<Value>> = HashMap::new();
+        diagnostics.insert(self.path.clone(), Vec::new());
+        for (level, error) in self.err.errors() {
+            let (path, range) = match error.loc {
+                Some(loc) => match self.get_path_and_range(loc, resolver) {
+                    Some(path_and_range) => path_and_range,
+                    None => continue,
+                },
+                None => (
+                    self.path.clone(),
+                    json!({ "start": origin(), "end": origin() }),
+                ),
+            };
+            let severity = match level {
+                Level::Error | Level::Internal => SEVERITY_ERROR,
+                Level::Warning => SEVERITY_WARNING,
+            };
+            let mut diagnostic = json!({
+                "range": range,
+                "severity": severity,
+                "source": "zephyr",
+                "message": error.message,
+            });
+            if let Some((note_loc, note)) = &error.note {
+                if let Some((note_path, note_range)) = self.get_path_and_range(*note_loc, resolver) {
+                    diagnostic["relatedInformation"] = json!([{
+                        "location": { "uri": path_to_uri(&note_path), "range": note_range },
+                        "message": note,
+                    }]);
+                }
+            }
+            if let Some(suggestion) = &error.suggestion {
+                if let Some((edit_path, edit_range)) =
+                    self.get_path_and_range(suggestion.loc, resolver)
+                {
+                    diagnostic["data"] = json!({
+                        "suggestions": [{
+                            "uri": path_to_uri(&edit_path),
+                            "range": edit_range,
+                            "newText": suggestion.replacement,
+                            "message": suggestion.message,
+                        }],
+                    });
+                }
+            }
+            diagnostics.entry(path).or_default().push(diagnostic);
+        }
+        diagnostics
+    }
+
+    /// Returns the location of the declaration of the symbol at the given position.
+    pub fn definition(
+        &self,
+        path: &Path,
+        line: u64,
+        character: u64,
+        resolver: &StandardResolver,
+    ) -> Option<Value> {
+        let file = self.get_file(path, resolver)?;
+        let index = file.line_index();
+        let node = self
+            .ctx
+            .get_node_at(file.f_id, to_offset(&index, line, character))?;
+        let declaration = self.ctx.get_declaration(&node.entity)?;
+        let (path, range) = self.get_path_and_range(declaration.loc, resolver)?;
+        Some(json!({ "uri": path_to_uri(&path), "range": range }))
+    }
+
+    /// Returns the locations of the declaration and of the uses of the entity at the given
+    /// position, among the modules of the analysis.
+    pub fn references(
+        &self,
+        path: &Path,
+        line: u64,
+        character: u64,
+        include_declaration: bool,
+        resolver: &StandardResolver,
+    ) -> Option<Value> {
+        let file = self.get_file(path, resolver)?;
+        let index = file.line_index();
+        let node = self
+            .ctx
+            .get_node_at(file.f_id, to_offset(&index, line, character))?;
+        let locations = self
+            .ctx
+            .find_references(&node.entity)
+            .into_iter()
+            .filter(|n| include_declaration || !n.is_declaration)
+            .filter_map(|n| self.get_path_and_range(n.name_loc, resolver))
+            .map(|(path, range)| json!({ "uri": path_to_uri(&path), "range": range }))
+            .collect();
+        Some(Value::Array(locations))
+    }
+
+    /// Returns the workspace edit renaming the entity at the given position, or the reason why
+    /// it can not be renamed.
+    pub fn rename(
+        &self,
+        path: &Path,
+        line: u64,
+        character: u64,
+        new_name: &str,
+        resolver: &StandardResolver,
+    ) -> Result<Value, String> {
+        let node = self.get_file(path, resolver).and_then(|file| {
+            let index = file.line_index();
+            self.ctx
+                .get_node_at(file.f_id, to_offset(&index, line, character))
+        });
+        let node = match node {
+            Some(node) => node,
+            None => return Err(String::from("No symbol to rename at this position")),
+        };
+        let mut err = self.err.sink();
+        let edits = match self.ctx.rename(&node.entity, new_name, &mut err) {
+            Ok(edits) => edits,
+            Err(()) => {
+                // A collision is reported for each use of the name, the locations are lost here
+                let mut messages = Vec::new();
+                for (_, error) in err.errors() {
+                    if !messages.contains(&error.message) {
+                        messages.push(error.message.clone());
+                    }
+                }
+                return Err(messages.join("\n"));
+            }
+        };
+        let mut changes: HashMap<String, Vec<Value>> = HashMap::new();
+        for (loc, new_text) in edits {
+            if let Some((path, range)) = self.get_path_and_range(loc, resolver) {
+                let edit = json!({ "range": range, "newText": new_text });
+                changes.entry(path_to_uri(&path)).or_default().push(edit);
+            }
+        }
+        Ok(json!({ "changes": changes }))
+    }
+
+    /// Returns the inferred type of the symbol at the given position.
+    pub fn hover(
+        &self,
+        path: &Path,
+        line: u64,
+        character: u64,
+        resolver: &StandardResolver,
+    ) -> Option<Value> {
+        let file = self.get_file(path, resolver)?;
+        let index = file.line_index();
+        let symbol = self
+            .ctx
+            .get_symbol_at(file.f_id, to_offset(&index, line, character))?;
+        let t = match &symbol.t {
+            Some(t) => self.ctx.display_type(t),
+            None => String::from("unknown"),
+        };
+        Some(json!({
+            "contents": {
+                "kind": "markdown",
+                "value": format!("```zephyr\n{}: {}\n```", symbol.ident, t),
+            },
+            "range": range(&index, symbol.loc),
+        }))
+    }
+
+    /// Returns a file, as seen by this analysis. The file may have been prepared more than once,
+    /// the last version is the one belonging to the analyzed module.
+    fn get_file(&self, path: &Path, resolver: &StandardResolver) -> Option<Arc<SourceFile>> {
+        let sources = self.ctx.sources();
+        resolver
+            .get_file_ids(path)
+            .into_iter()
+            .rev()
+            .find_map(|f_id| sources.get(f_id))
+    }
+
+    fn get_path_and_range(
+        &self,
+        loc: Location,
+        resolver: &StandardResolver,
+    ) -> Option<(PathBuf, Value)> {
+        let path = resolver.get_file_path(loc.f_id)?;
+        let file = self.ctx.sources().get(loc.f_id)?;
+        Some((path, range(&file.line_index(), loc)))
+    }
+}
+
+/// Adds the module containing the document at `path` to the context and checks it.
+fn check(
+    path: &Path,
+    ctx: &mut Ctx,
+    err: &mut StandardErrorHandler,
+    resolver: &mut StandardResolver,
+) -> Result<(), ()> {
+    // A standard module spans the whole directory of the document. The file is parsed with a
+    // separate handler to avoid reporting its errors twice.
+    let mut file_err = err.sink();
+    let (mut files, _) = resolver.prepare_files(path, &mut file_err)?;
+    let kind = match files.pop() {
+        Some(file) => ctx.get_module_kind(file, &mut file_err),
+        None => Err(()),
+    };
+    let root = match kind {
+        Ok(ModuleKind::Standalone) => path.to_owned(),
+        Ok(ModuleKind::Standard) => match path.parent() {
+            Some(dir) => dir.to_owned(),
+            None => path.to_owned(),
+        },
+        Err(()) => {
+            err.merge(file_err);
+            return Err(());
+        }
+    };
+
+    let (module_files, _) = resolver.prepare_files(&root, err)?;
+    let module_name = ctx.get_module_name(module_files, err)?;
+    resolver.add_package(module_name.clone(), root);
+    ctx.add_module(ModulePath::from_root(module_name), err, resolver)?;
+    ctx.check(err, resolver)
+}
+
+fn origin() -> Value {
+    json!({ "line": 0, "character": 0 })
+}
+
+/// Converts a location into an LSP range.
+fn range(index: &LineIndex, loc: Location) -> Value {
+    json!({
+        "start": to_position(index, loc.pos),
+        "end": to_position(index, loc.pos + loc.len),
+    })
+}
+
+/// Converts a byte offset into an LSP position (line and UTF-16 offset).
+fn to_position(index: &LineIndex, pos: u32) -> Value {
+    let (line, character) = index.line_col_utf16(pos);
+    json!({ "line": line, "character": character })
+}
+
+/// Converts an LSP position (line and UTF-16 offset) into a byte offset.
+fn to_offset(index: &LineIndex, line: u64, character: u64) -> u32 {
+    index.offset_utf16(line as usize, character as usize)
+}
+
+/// Converts a `file://` URI into a path.
+pub fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut iter = path.bytes();
+    while let Some(b) = iter.next() {
+        if b == b'%' {
+            let hex = [iter.next()?, iter.next()?];
+            let hex = std::str::from_utf8(&hex).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+        } else {
+            bytes.push(b);
+        }
+    }
+    Some(PathBuf::from(String::from_utf8(bytes).ok()?))
+}
+
+/// Converts a path into a `file://` URI.
+pub fn path_to_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for b in path.to_string_lossy().bytes() {
+        match b {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => {
+                uri.push(b as char)
+            }
+            _ => uri.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    uri
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn positions() {
+        let code = "let a = 1\nlet 😀 = é";
+        let index = LineIndex::new(code);
+        assert_eq!(to_position(&index, 4), json!({ "line": 0, "character": 4 }));
+        assert_eq!(to_position(&index, 14), json!({ "line": 1, "character": 4 }));
+        assert_eq!(to_position(&index, 23), json!({ "line": 1, "character": 10 }));
+        assert_eq!(to_offset(&index, 1, 4), 14);
+        assert_eq!(to_offset(&index, 1, 6), 18);
+        assert_eq!(to_offset(&index, 0, 42), 9); // Clamped to the end of the line.
+    }
+
+    #[test]
+    fn uris() {
+        let path = PathBuf::from("/home/zephyr/my package/é.zph");
+        let uri = path_to_uri(&path);
+        assert_eq!(uri, "file:///home/zephyr/my%20package/%C3%A9.zph");
+        assert_eq!(uri_to_path(&uri), Some(path));
+    }
+}
@@ -0,0 +1,30 @@
+//! The Zephyr language server
+//!
+//! Implements a subset of the Language Server Protocol on top of the compiler library:
+//! diagnostics, go to definition and hover. The server communicates over stdio.
+
+use std::io;
+
+mod analysis;
+mod rpc;
+mod server;
+
+use server::Server;
+
+fn main() {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let mut input = stdin.lock();
+    let mut output = stdout.lock();
+    let mut server = Server::new();
+
+    while let Some(message) = rpc::read_message(&mut input) {
+        for reply in server.handle(message) {
+            rpc::write_message(&mut output, &reply);
+        }
+        if let Some(code) = server.exit_code() {
+            std::process::exit(code);
+        }
+    }
+    std::process::exit(1);
+}
@@ -0,0 +1,64 @@
+//! JSON-RPC transport
+//!
+//! Messages are exchanged over stdio, each message is prefixed by a `Content-Length` header as
+//! defined by the Language Server Protocol.
+
+use serde_json::{json, Value};
+use std::io::{BufRead, Write};
+
+/// JSON-RPC error code for unknown methods.
+pub const METHOD_NOT_FOUND: i64 = -32601;
+/// LSP error code for valid requests which could not be fulfilled.
+pub const REQUEST_FAILED: i64 = -32803;
+
+/// Reads the next message, returns None once the input is closed.
+pub fn read_message(input: &mut impl BufRead) -> Option<Value> {
+    let mut content_length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header).ok()? == 0 {
+            return None;
+        }
+        let header = header.trim();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(length) = header.strip_prefix("Content-Length:") {
+            content_length = length.trim().parse::<usize>().ok();
+        }
+    }
+    let mut content = vec![0; content_length?];
+    input.read_exact(&mut content).ok()?;
+    serde_json::from_slice(&content).ok()
+}
+
+/// Writes a message to the output.
+pub fn write_message(output: &mut impl Write, message: &Value) {
+    let content = message.to_string();
+    let _ = write!(
+        output,
+        "Content-Length: {}\r\n\r\n{}",
+        content.len(),
+        content
+    );
+    let _ = output.flush();
+}
+
+/// Builds the response to a request.
+pub fn response(id: Value, result: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "result": result })
+}
+
+/// Builds an error response to a request.
+pub fn error_response(id: Value, code: i64, message: String) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message },
+    })
+}
+
+/// Builds a notification, that is a message which does not expect any response.
+pub fn notification(method: &str, params: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "method": method, "params": params })
+}
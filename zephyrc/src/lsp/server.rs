@@ -0,0 +1,244 @@
+//! The language server
+//!
+//! Dispatches the messages received from the editor. Documents are analyzed each time they are
+//! opened or modified, the unsaved content of open documents takes precedence over the disk.
+
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::panic;
+use std::path::{Path, PathBuf};
+
+use super::analysis::{path_to_uri, uri_to_path, Analysis};
+use super::rpc;
+use zephyrc::resolver::StandardResolver;
+
+// LSP text document synchronization: the full content is sent on each change.
+const SYNC_FULL: u8 = 1;
+
+pub struct Server {
+    resolver: StandardResolver,
+    analyses: HashMap<PathBuf, Analysis>,
+    /// Files with diagnostics published by the analysis of each document.
+    published: HashMap<PathBuf, HashSet<PathBuf>>,
+    is_shutdown: bool,
+    exit_code: Option<i32>,
+}
+
+impl Server {
+    pub fn new() -> Self {
+        Self {
+            resolver: StandardResolver::new(),
+            analyses: HashMap::new(),
+            published: HashMap::new(),
+            is_shutdown: false,
+            exit_code: None,
+        }
+    }
+
+    /// Returns the exit code once the client asked the server to exit.
+    pub fn exit_code(&self) -> Option<i32> {
+        self.exit_code
+    }
+
+    /// Handles a message and returns the messages to send back to the client.
+    pub fn handle(&mut self, message: Value) -> Vec<Value> {
+        let method = message["method"].as_str().unwrap_or("");
+        let params = &message["params"];
+        let id = message.get("id").cloned();
+        let result = match method {
+            "initialize" => Some(json!({
+                "capabilities": {
+                    "textDocumentSync": SYNC_FULL,
+                    "definitionProvider": true,
+                    "hoverProvider": true,
+                    "referencesProvider": true,
+                    "renameProvider": true,
+                    "codeActionProvider": true,
+                },
+                "serverInfo": { "name": "zephyr-lsp" },
+            })),
+            "shutdown" => {
+                self.is_shutdown = true;
+                Some(Value::Null)
+            }
+            "exit" => {
+                self.exit_code = Some(if self.is_shutdown { 0 } else { 1 });
+                None
+            }
+            "textDocument/didOpen" | "textDocument/didChange" | "textDocument/didSave" => {
+                return self.update(params);
+            }
+            "textDocument/didClose" => return self.close(params),
+            "textDocument/definition" => Some(self.query(params, Analysis::definition)),
+            "textDocument/hover" => Some(self.query(params, Analysis::hover)),
+            "textDocument/references" => Some(self.references(params)),
+            "textDocument/rename" => match (self.rename(params), &id) {
+                (Err(message), Some(id)) => {
+                    let error = rpc::error_response(id.clone(), rpc::REQUEST_FAILED, message);
+                    return vec![error];
+                }
+                (result, _) => result.ok(),
+            },
+            "textDocument/codeAction" => Some(code_actions(params)),
+            _ => {
+                return match id {
+                    Some(id) => vec![rpc::error_response(
+                        id,
+                        rpc::METHOD_NOT_FOUND,
+                        format!("Unsupported method '{}'", method),
+                    )],
+                    None => Vec::new(),
+                };
+            }
+        };
+        match (id, result) {
+            (Some(id), Some(result)) => vec![rpc::response(id, result)],
+            _ => Vec::new(),
+        }
+    }
+
+    /// Updates the content of a document, then analyzes it.
+    fn update(&mut self, params: &Value) -> Vec<Value> {
+        let path = match get_path(params) {
+            Some(path) => path,
+            None => return Vec::new(),
+        };
+        let text = if let Some(text) = params["textDocument"]["text"].as_str() {
+            Some(text)
+        } else if let Some(changes) = params["contentChanges"].as_array() {
+            changes.last().and_then(|change| change["text"].as_str())
+        } else {
+            params["text"].as_str()
+        };
+        if let Some(text) = text {
+            self.resolver.set_overlay(path.clone(), text.to_owned());
+        }
+
+        // The compiler may panic on code it does not support yet, keep the previous analysis then.
+        let resolver = &mut self.resolver;
+        let analysis = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            Analysis::new(path.clone(), resolver)
+        }));
+        match analysis {
+            Ok(analysis) => {
+                let diagnostics = analysis.diagnostics(&self.resolver);
+                self.analyses.insert(path.clone(), analysis);
+                self.publish(path, diagnostics)
+            }
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// Forgets about a document.
+    fn close(&mut self, params: &Value) -> Vec<Value> {
+        let path = match get_path(params) {
+            Some(path) => path,
+            None => return Vec::new(),
+        };
+        self.resolver.remove_overlay(&path);
+        self.analyses.remove(&path);
+        self.publish(path, HashMap::new())
+    }
+
+    /// Publishes diagnostics, and clears those previously published for the same document that
+    /// are no longer relevant.
+    fn publish(
+        &mut self,
+        document: PathBuf,
+        mut diagnostics: HashMap<PathBuf, Vec<Value>>,
+    ) -> Vec<Value> {
+        let files = diagnostics.keys().cloned().collect::<HashSet<PathBuf>>();
+        if let Some(previous_files) = self.published.insert(document, files) {
+            for file in previous_files {
+                diagnostics.entry(file).or_default();
+            }
+        }
+        diagnostics
+            .into_iter()
+            .map(|(path, diagnostics)| {
+                rpc::notification(
+                    "textDocument/publishDiagnostics",
+                    json!({ "uri": path_to_uri(&path), "diagnostics": diagnostics }),
+                )
+            })
+            .collect()
+    }
+
+    /// Answers a query on a position, using the last analysis of the document.
+    fn query<F>(&self, params: &Value, query: F) -> Value
+    where
+        F: Fn(&Analysis, &Path, u64, u64, &StandardResolver) -> Option<Value>,
+    {
+        let path = match get_path(params) {
+            Some(path) => path,
+            None => return Value::Null,
+        };
+        let line = params["position"]["line"].as_u64().unwrap_or(0);
+        let character = params["position"]["character"].as_u64().unwrap_or(0);
+        match self.analyses.get(&path) {
+            Some(analysis) => {
+                query(analysis, &path, line, character, &self.resolver).unwrap_or(Value::Null)
+            }
+            None => Value::Null,
+        }
+    }
+
+    /// Lists the references to the entity at a position, using the last analysis of the document.
+    fn references(&self, params: &Value) -> Value {
+        let include_declaration = params["context"]["includeDeclaration"]
+            .as_bool()
+            .unwrap_or(false);
+        self.query(params, |analysis, path, line, character, resolver| {
+            analysis.references(path, line, character, include_declaration, resolver)
+        })
+    }
+
+    /// Renames the entity at a position, using the last analysis of the document.
+    fn rename(&self, params: &Value) -> Result<Value, String> {
+        let path = match get_path(params) {
+            Some(path) => path,
+            None => return Ok(Value::Null),
+        };
+        let line = params["position"]["line"].as_u64().unwrap_or(0);
+        let character = params["position"]["character"].as_u64().unwrap_or(0);
+        let new_name = params["newName"].as_str().unwrap_or("");
+        match self.analyses.get(&path) {
+            Some(analysis) => analysis.rename(&path, line, character, new_name, &self.resolver),
+            None => Ok(Value::Null),
+        }
+    }
+}
+
+/// Returns a quick fix for each suggestion of the diagnostics the code actions are requested for,
+/// see `Analysis::diagnostics`.
+fn code_actions(params: &Value) -> Value {
+    let mut actions = Vec::new();
+    let diagnostics = params["context"]["diagnostics"].as_array();
+    for diagnostic in diagnostics.into_iter().flatten() {
+        let suggestions = diagnostic["data"]["suggestions"].as_array();
+        for suggestion in suggestions.into_iter().flatten() {
+            let uri = match suggestion["uri"].as_str() {
+                Some(uri) => uri,
+                None => continue,
+            };
+            let mut changes = serde_json::Map::new();
+            changes.insert(
+                uri.to_owned(),
+                json!([{ "range": suggestion["range"], "newText": suggestion["newText"] }]),
+            );
+            actions.push(json!({
+                "title": suggestion["message"],
+                "kind": "quickfix",
+                "diagnostics": [diagnostic],
+                "edit": { "changes": changes },
+            }));
+        }
+    }
+    Value::Array(actions)
+}
+
+/// Returns the path of the document targeted by a request.
+fn get_path(params: &Value) -> Option<PathBuf> {
+    let path = uri_to_path(params["textDocument"]["uri"].as_str()?)?;
+    Some(path.canonicalize().unwrap_or(path))
+}
@@ -8,101 +8,783 @@ use std::fs;
 use std::path;
 use std::path::PathBuf;
 
-use zephyr::error::ErrorHandler;
+use zephyr::error::{ErrorHandler, Lint, LintLevel, SourceMap};
 use zephyr::resolver::ModulePath;
-use zephyr::Ctx;
+use zephyr::{Ctx, Feature, GcStrategy, GraphKind, Target};
 
-mod error_handler;
-mod errors;
-mod resolver;
+mod artifacts;
+mod daemon;
+mod depfile;
+mod fmt;
+mod inspect;
+mod logger;
+mod post_process;
+mod repl;
+#[cfg(feature = "runner")]
+mod run;
+mod stats;
+mod timings;
+mod watch;
 
-use error_handler::StandardErrorHandler;
-use resolver::StandardResolver;
+use zephyrc::color;
+use zephyrc::error_handler::StandardErrorHandler;
+use zephyrc::resolver::{StandardResolver, CORE};
+
+pub const WASM_TARGET: &str = "wasm";
+const WASM32_WASI_TARGET: &str = "wasm32-wasi";
+const WASM32_UNKNOWN_TARGET: &str = "wasm32-unknown";
+const NATIVE_TARGET: &str = "native";
+const COMPONENT_TARGET: &str = "component";
+pub const JS_BINDINGS: &str = "js-bindings";
+const WIT: &str = "wit";
+const DEPFILE: &str = "depfile";
+pub const NO_GC: &str = "none";
+const RC_GC: &str = "rc";
+const DOT: &str = "dot";
+const MODULES_GRAPH: &str = "modules";
+const CALLS_GRAPH: &str = "calls";
 
 /// The Zephyr compiler.
 #[derive(Clap, Debug)]
 #[clap(version = "0.1.0")]
 pub struct Config {
-    /// Use verbose output
-    #[clap(short, long)]
-    pub verbose: bool,
+    /// When to use colors, by default when the output is a terminal
+    #[clap(
+        long,
+        global = true,
+        default_value = color::AUTO,
+        possible_values = &[color::AUTO, color::ALWAYS, color::NEVER]
+    )]
+    pub color: String,
+
+    #[clap(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Clap, Debug)]
+pub enum Command {
+    /// Compile a package
+    Build(BuildConfig),
+    /// Type check a package without generating code
+    Check(CheckConfig),
+    /// Print the module dependency graph or the call graph of a package
+    Graph(GraphConfig),
+    /// Compile a package and run it with WASI
+    #[cfg(feature = "runner")]
+    Run(RunConfig),
+    /// Compile a package and run its test functions, marked with `#[test]`
+    #[cfg(feature = "runner")]
+    Test(TestConfig),
+    /// Format the Zephyr files of a package
+    Fmt(FmtConfig),
+    /// Print the custom sections of a WebAssembly module, such as build metadata
+    Inspect(InspectConfig),
+    /// Print size metrics of the modules and functions of a package
+    Stats(StatsConfig),
+    /// Evaluate statements and expressions interactively
+    Repl,
+    /// Serve build and check requests over a local socket, keeping their results in memory
+    Daemon(DaemonConfig),
+}
 
-    /// Package to build
+/// Options shared by the subcommands compiling a package.
+#[derive(Clap, Debug)]
+pub struct PackageOptions {
+    /// Package to compile
     #[clap(default_value = ".", parse(from_os_str))]
     pub input: PathBuf,
 
-    /// Output location
+    /// Compile the declarations marked with `#[cfg(key = "value")]`, given as `key=value`, can be
+    /// repeated
+    #[clap(long, number_of_values = 1)]
+    pub cfg: Vec<String>,
+
+    /// Enable an experimental feature of the language, either `simd` or `threads`, can be repeated
+    #[clap(long, number_of_values = 1)]
+    pub feature: Vec<String>,
+
+    /// Package used as the runtime in place of `core`, it must provide the allocator, the panic
+    /// handler and the other declarations expected by the compiler
+    #[clap(long, parse(from_os_str))]
+    pub runtime: Option<PathBuf>,
+
+    /// Compile without any runtime: the module has no allocator nor implicit import or export,
+    /// and strings, assertions, panics and heap allocations are rejected
+    #[clap(long, conflicts_with = "runtime")]
+    pub no_runtime: bool,
+
+    /// Print the intermediate representations of all the phases of the compiler
+    #[clap(short, long)]
+    pub verbose: bool,
+
+    /// Print the intermediate representations of a phase of the compiler, can be repeated
+    #[clap(long, number_of_values = 1, possible_values = logger::PHASES)]
+    pub log: Vec<String>,
+
+    /// Report a lint as a warning, can be repeated
+    #[clap(short = "W", long = "warn", number_of_values = 1)]
+    pub warn: Vec<String>,
+
+    /// Silence a lint, can be repeated
+    #[clap(short = "A", long = "allow", number_of_values = 1)]
+    pub allow: Vec<String>,
+
+    /// Report all warnings as errors
+    #[clap(long)]
+    pub deny_warnings: bool,
+
+    /// Maximum number of errors to print, 0 prints all of them
+    #[clap(long, default_value = "20")]
+    pub error_limit: usize,
+
+    /// Print the duration of each compilation phase and statistics about the modules, as a table
+    /// or as JSON
+    #[clap(long, possible_values = &[timings::TEXT, timings::JSON])]
+    pub timings: Option<String>,
+
+    /// Write a trace of the compilation phases and of the passes run on each function to a file,
+    /// in the Chrome trace event format
+    #[clap(long, parse(from_os_str))]
+    pub self_profile: Option<PathBuf>,
+}
+
+/// Options shared by the subcommands generating code.
+#[derive(Clap, Debug)]
+pub struct CodegenOptions {
+    /// Trap on signed integer overflows instead of wrapping around
+    #[clap(long)]
+    pub overflow_checks: bool,
+
+    /// Memory management of heap objects, either never freed or freed by counting the references
+    /// to structs
+    #[clap(long, default_value = NO_GC, possible_values = &[NO_GC, RC_GC])]
+    pub gc: String,
+
+    /// Public function of the package, taking an i32 error code, called before trapping on
+    /// divisions by zero
+    #[clap(long)]
+    pub trap_handler: Option<String>,
+
+    /// Public function of the package, taking a string, called in place of the default panic
+    /// handler
+    #[clap(long)]
+    pub panic_handler: Option<String>,
+
+    /// Validate the generated WebAssembly before writing it
+    #[clap(long)]
+    pub validate: bool,
+
+    /// Initial number of memory pages (64KiB each)
+    #[clap(long)]
+    pub memory_initial: Option<u32>,
+
+    /// Maximum number of memory pages (64KiB each)
+    #[clap(long)]
+    pub memory_max: Option<u32>,
+
+    /// Emit a memory that can be shared between threads, requires a maximum number of pages
+    #[clap(long)]
+    pub shared_memory: bool,
+
+    /// Inline functions of at most that many MIR statements into their callers
+    #[clap(long)]
+    pub inline_threshold: Option<usize>,
+
+    /// Optimize the generated code
+    #[clap(short = "O", long)]
+    pub optimize: bool,
+
+    /// Reduce the size of the generated code, merging identical functions and removing unused
+    /// code
+    #[clap(long)]
+    pub opt_size: bool,
+}
+
+#[derive(Clap, Debug)]
+pub struct BuildConfig {
+    #[clap(flatten)]
+    pub package: PackageOptions,
+
+    #[clap(flatten)]
+    pub codegen: CodegenOptions,
+
+    /// Output location, when it is a directory (an existing one or ending with a separator) the
+    /// module is written to it along with its WebAssembly text, JavaScript bindings and a JSON
+    /// description of the build
     #[clap(short, long, parse(from_os_str))]
     pub output: Option<PathBuf>,
 
-    /// Type check the package
+    /// Target to compile to, either a WebAssembly module (`wasm` stands for `wasm32-wasi`, while
+    /// `wasm32-unknown` does not rely on WASI), a native object file for the host or a WebAssembly
+    /// component
+    #[clap(
+        long,
+        default_value = WASM_TARGET,
+        possible_values = &[
+            WASM_TARGET,
+            WASM32_WASI_TARGET,
+            WASM32_UNKNOWN_TARGET,
+            NATIVE_TARGET,
+            COMPONENT_TARGET
+        ]
+    )]
+    pub target: String,
+
+    /// Emit additional artifacts next to the module, JavaScript bindings, a WIT description or a
+    /// Make-compatible depfile listing the source files of the module, can be repeated
+    #[clap(long, number_of_values = 1, possible_values = &[JS_BINDINGS, WIT, DEPFILE])]
+    pub emit: Vec<String>,
+
+    /// Rebuild the package whenever a source file changes
+    #[clap(long)]
+    pub watch: bool,
+
+    /// Command to run after each successful build in watch mode
+    #[clap(long, requires = "watch")]
+    pub exec: Option<String>,
+
+    /// Embed build metadata (compiler version, package name and module hash) in custom sections
+    #[clap(long)]
+    pub metadata: bool,
+
+    /// Command the module is passed through before being written, such as `wasm-opt -Oz`, it is
+    /// called with the path of the module followed by `-o` and the path to write its result to
+    #[clap(long)]
+    pub post_process: Option<String>,
+}
+
+#[derive(Clap, Debug)]
+pub struct CheckConfig {
+    #[clap(flatten)]
+    pub package: PackageOptions,
+}
+
+#[derive(Clap, Debug)]
+pub struct GraphConfig {
+    #[clap(flatten)]
+    pub package: PackageOptions,
+
+    /// Format of the graph
+    #[clap(long, default_value = DOT, possible_values = &[DOT])]
+    pub format: String,
+
+    /// Graph to print, the modules and their dependencies or the functions and their callees
+    #[clap(long, default_value = MODULES_GRAPH, possible_values = &[MODULES_GRAPH, CALLS_GRAPH])]
+    pub kind: String,
+}
+
+#[cfg(feature = "runner")]
+#[derive(Clap, Debug)]
+pub struct RunConfig {
+    #[clap(flatten)]
+    pub package: PackageOptions,
+
+    #[clap(flatten)]
+    pub codegen: CodegenOptions,
+
+    /// Arguments passed to the program
+    #[clap(last = true)]
+    pub args: Vec<String>,
+}
+
+#[cfg(feature = "runner")]
+#[derive(Clap, Debug)]
+pub struct TestConfig {
+    #[clap(flatten)]
+    pub package: PackageOptions,
+
+    #[clap(flatten)]
+    pub codegen: CodegenOptions,
+
+    /// Only run the tests whose name contains this string
+    #[clap(long)]
+    pub filter: Option<String>,
+}
+
+#[derive(Clap, Debug)]
+pub struct FmtConfig {
+    /// Package or file to format
+    #[clap(default_value = ".", parse(from_os_str))]
+    pub input: PathBuf,
+
+    /// Exit with an error if a file is not formatted, instead of formatting it
     #[clap(long)]
     pub check: bool,
 }
 
+#[derive(Clap, Debug)]
+pub struct InspectConfig {
+    /// WebAssembly module to inspect
+    #[clap(parse(from_os_str))]
+    pub input: PathBuf,
+}
+
+#[derive(Clap, Debug)]
+pub struct StatsConfig {
+    #[clap(flatten)]
+    pub package: PackageOptions,
+
+    #[clap(flatten)]
+    pub codegen: CodegenOptions,
+}
+
+#[derive(Clap, Debug)]
+pub struct DaemonConfig {
+    /// Port to listen on, only connections from the local host are accepted
+    #[clap(long, default_value = "7171")]
+    pub port: u16,
+}
+
 fn main() {
     let config = Config::parse();
+    color::set_choice(&config.color);
+    match &config.command {
+        Command::Build(build_config) => build(build_config),
+        Command::Check(check_config) => check(check_config),
+        Command::Graph(graph_config) => graph(graph_config),
+        #[cfg(feature = "runner")]
+        Command::Run(run_config) => run(run_config),
+        #[cfg(feature = "runner")]
+        Command::Test(test_config) => test(test_config),
+        Command::Fmt(fmt_config) => fmt::fmt(fmt_config),
+        Command::Inspect(inspect_config) => inspect::inspect(inspect_config),
+        Command::Stats(stats_config) => stats::stats(stats_config),
+        Command::Repl => repl::repl(),
+        Command::Daemon(daemon_config) => daemon::daemon(daemon_config),
+    }
+}
+
+/// Compiles the package and writes the module along with the requested artifacts, then exits.
+fn build(config: &BuildConfig) -> ! {
+    if let Err(message) = check_build_config(config) {
+        exit_with_error(message);
+    }
+    if config.watch {
+        let mut paths = StandardResolver::new().get_package_paths();
+        paths.push(resolve_input(&config.package.input));
+        if let Some(runtime) = &config.package.runtime {
+            paths.push(resolve_input(runtime));
+        }
+        watch::watch(config, paths);
+    }
+
+    let mut resolver = StandardResolver::new();
+    let mut ctx = Ctx::new();
+    let mut err = StandardErrorHandler::new(ctx.sources().clone());
+    init(&config.package, &mut ctx);
+    let result = build_package(config, &mut ctx, &mut err, &mut resolver);
+    err.flush();
+    match result {
+        Ok(_) => {
+            timings::report(&config.package, &mut ctx);
+            std::process::exit(0);
+        }
+        Err(()) => std::process::exit(65),
+    }
+}
+
+/// Returns an error if the options of the build are incompatible with each other.
+fn check_build_config(config: &BuildConfig) -> Result<(), String> {
+    let wasm_artifacts = config.emit.iter().any(|emit| emit != DEPFILE);
+    if wasm_target(&config.target).is_none() && wasm_artifacts {
+        return Err(String::from(
+            "Additional artifacts can only be emitted for WebAssembly",
+        ));
+    }
+    let output_dir = config.output.as_deref().map_or(false, artifacts::is_dir);
+    if wasm_target(&config.target).is_none() && output_dir {
+        return Err(String::from(
+            "Only WebAssembly modules can be written to a directory",
+        ));
+    }
+    if wasm_target(&config.target).is_none() && config.post_process.is_some() {
+        return Err(String::from(
+            "Only WebAssembly modules can be post-processed",
+        ));
+    }
+    Ok(())
+}
+
+/// Compiles the package and writes the module along with the requested artifacts, returns the
+/// paths of the written files.
+fn build_package(
+    config: &BuildConfig,
+    ctx: &mut Ctx,
+    err: &mut StandardErrorHandler,
+    resolver: &mut StandardResolver,
+) -> Result<Vec<PathBuf>, ()> {
+    config.codegen.configure(ctx);
+    if let Some(target) = wasm_target(&config.target) {
+        ctx.set_target(target);
+    }
+    let output_dir = config
+        .output
+        .as_deref()
+        .filter(|output| artifacts::is_dir(output));
+    let js_bindings = config.emit.iter().any(|emit| emit == JS_BINDINGS);
+    ctx.set_js_bindings(js_bindings || output_dir.is_some());
+    ctx.set_post_process(config.post_process.clone().map(post_process::command));
+    let module_name = add_package(&config.package, Some(&config.codegen), ctx, err, resolver)?;
+    if config.metadata {
+        ctx.set_metadata(Some(module_name.clone()));
+    }
+    let mut outputs = if let Some(dir) = output_dir {
+        let artifacts = ctx.compile(&module_name, err, resolver)?;
+        if let Err(e) = fs::create_dir_all(dir) {
+            err.report_no_loc(format!("Could not create '{}': {}", dir.display(), e));
+            return Err(());
+        }
+        artifacts::files(dir, &module_name, artifacts)
+    } else {
+        let wasm = compile(config, ctx, &module_name, err, resolver)?;
+        // Chose a name for the output
+        let output = if let Some(output) = &config.output {
+            output.clone()
+        } else {
+            let extension = if config.target == NATIVE_TARGET {
+                "o"
+            } else {
+                "wasm"
+            };
+            path::PathBuf::from(&format!("{}.zph.{}", module_name, extension))
+        };
+        vec![(output, wasm)]
+    };
+
+    // Additional artifacts are written next to the module, which is written last
+    let (output, module) = outputs.pop().unwrap();
+    for (extension, content) in emit(config, ctx, &module_name, err, resolver)? {
+        let path = output.with_extension(extension);
+        if !outputs.iter().any(|(written, _)| *written == path) {
+            outputs.push((path, content.into_bytes()));
+        }
+    }
+    if config.emit.iter().any(|emit| emit == DEPFILE) {
+        let depfile = depfile::depfile(&output, &resolver.get_file_paths());
+        outputs.push((output.with_extension("d"), depfile.into_bytes()));
+    }
+    outputs.push((output, module));
+
+    // Write down compiled code
+    for (path, content) in &outputs {
+        if let Err(e) = fs::write(path, content) {
+            err.report_no_loc(e.to_string());
+            return Err(());
+        }
+    }
+    Ok(outputs.into_iter().map(|(path, _)| path).collect())
+}
+
+/// Type checks the package without generating any code, then exits.
+fn check(config: &CheckConfig) -> ! {
     let mut resolver = StandardResolver::new();
-    let mut err = StandardErrorHandler::new_no_file();
     let mut ctx = Ctx::new();
-    ctx.set_verbose(config.verbose);
+    let mut err = StandardErrorHandler::new(ctx.sources().clone());
+    init(&config.package, &mut ctx);
+    let result = check_package(config, &mut ctx, &mut err, &mut resolver);
+    err.flush();
+    match result {
+        Ok(()) => {
+            timings::report(&config.package, &mut ctx);
+            std::process::exit(0);
+        }
+        Err(()) => std::process::exit(65),
+    }
+}
 
-    // Resolve paths
-    let path = config
-        .input
-        .clone()
-        .canonicalize()
-        .expect("Could not resolve path");
+/// Type checks the package without generating any code.
+fn check_package(
+    config: &CheckConfig,
+    ctx: &mut Ctx,
+    err: &mut StandardErrorHandler,
+    resolver: &mut StandardResolver,
+) -> Result<(), ()> {
+    add_package(&config.package, None, ctx, err, resolver)?;
+    ctx.check(err, resolver)
+}
 
-    // Prepare files & resolver
-    let (module_files, _) = match resolver.prepare_files(&path, &mut err) {
-        Ok(files) => files,
+/// Type checks the package and prints its module or call graph.
+fn graph(config: &GraphConfig) -> ! {
+    let mut resolver = StandardResolver::new();
+    let mut ctx = Ctx::new();
+    let mut err = StandardErrorHandler::new(ctx.sources().clone());
+    let module_name = load_package(&config.package, None, &mut ctx, &mut err, &mut resolver);
+    if let Err(()) = ctx.check(&mut err, &resolver) {
+        err.flush();
+        std::process::exit(65);
+    }
+    err.flush();
+    let kind = match config.kind.as_str() {
+        CALLS_GRAPH => GraphKind::Calls,
+        _ => GraphKind::Modules,
+    };
+    print!("{}", ctx.get_graph(kind, &module_name));
+    timings::report(&config.package, &mut ctx);
+    std::process::exit(0);
+}
+
+/// Compiles the package to WebAssembly and runs it.
+#[cfg(feature = "runner")]
+fn run(config: &RunConfig) -> ! {
+    let mut resolver = StandardResolver::new();
+    let mut ctx = Ctx::new();
+    let mut err = StandardErrorHandler::new(ctx.sources().clone());
+    config.codegen.configure(&mut ctx);
+    let module_name = load_package(
+        &config.package,
+        Some(&config.codegen),
+        &mut ctx,
+        &mut err,
+        &mut resolver,
+    );
+    let wasm = match ctx.get_wasm(&mut err, &resolver) {
+        Ok(wasm) => wasm,
         Err(()) => {
             err.flush();
             std::process::exit(65);
         }
     };
-    let module_name = match ctx.get_module_name(module_files, &mut err) {
-        Ok(module_name) => module_name,
+    err.flush();
+    timings::report(&config.package, &mut ctx);
+    run::run(&wasm, &module_name, &config.args);
+}
+
+/// Compiles the package to WebAssembly along with its test functions and runs them.
+#[cfg(feature = "runner")]
+fn test(config: &TestConfig) -> ! {
+    let mut resolver = StandardResolver::new();
+    let mut ctx = Ctx::new();
+    let mut err = StandardErrorHandler::new(ctx.sources().clone());
+    config.codegen.configure(&mut ctx);
+    let module_name = load_package(
+        &config.package,
+        Some(&config.codegen),
+        &mut ctx,
+        &mut err,
+        &mut resolver,
+    );
+    ctx.set_tests(Some(module_name));
+    let wasm = match ctx.get_wasm(&mut err, &resolver) {
+        Ok(wasm) => wasm,
         Err(()) => {
             err.flush();
             std::process::exit(65);
         }
     };
-    let module = ModulePath::from_root(module_name.clone());
-    resolver.add_package(module_name.clone(), path);
+    err.flush();
+    timings::report(&config.package, &mut ctx);
+    run::test(&wasm, config.filter.as_deref());
+}
 
-    // Compile
-    let _ = ctx.add_module(module, &mut err, &mut resolver);
-    err.flush_and_exit_if_err();
-    if config.check {
-        std::process::exit(0);
+impl CodegenOptions {
+    /// Applies the options to a compilation context.
+    fn configure(&self, ctx: &mut Ctx) {
+        ctx.set_overflow_checks(self.overflow_checks);
+        ctx.set_gc(match self.gc.as_str() {
+            RC_GC => GcStrategy::ReferenceCounting,
+            _ => GcStrategy::None,
+        });
+        ctx.set_validate(self.validate);
+        ctx.set_memory_initial(self.memory_initial);
+        ctx.set_memory_max(self.memory_max);
+        ctx.set_shared_memory(self.shared_memory);
+        ctx.set_inline_threshold(self.inline_threshold.unwrap_or(0));
+        ctx.set_optimize(self.optimize);
+        ctx.set_opt_size(self.opt_size);
     }
-    let wasm = match ctx.get_wasm(&mut err, &resolver) {
-        Ok(wasm) => wasm,
+}
+
+/// Adds the package to the compilation context and returns the name of its root module, exits if
+/// the package contains errors.
+///
+/// The handlers of the code generation options, if any, are resolved within the package.
+fn load_package(
+    options: &PackageOptions,
+    codegen: Option<&CodegenOptions>,
+    ctx: &mut Ctx,
+    err: &mut StandardErrorHandler,
+    resolver: &mut StandardResolver,
+) -> String {
+    init(options, ctx);
+    match add_package(options, codegen, ctx, err, resolver) {
+        Ok(module_name) => module_name,
         Err(()) => {
             err.flush();
             std::process::exit(65);
         }
-    };
+    }
+}
 
-    // Chose a name for the output
-    let output = if let Some(output) = &config.output {
-        output.clone()
-    } else {
-        path::PathBuf::from(&format!("{}.zph.wasm", module_name))
-    };
+/// Sets up the logger and the timings requested by the options.
+fn init(options: &PackageOptions, ctx: &mut Ctx) {
+    logger::init(options);
+    ctx.set_timings(options.timings.is_some() || options.self_profile.is_some());
+}
 
-    // Write down compiled code
-    match fs::write(&output, wasm) {
-        Ok(_) => {
-            err.flush();
-            std::process::exit(0);
+/// Adds the package to the compilation context and returns the name of its root module, fails if
+/// the package contains errors.
+///
+/// The handlers of the code generation options, if any, are resolved within the package.
+fn add_package(
+    options: &PackageOptions,
+    codegen: Option<&CodegenOptions>,
+    ctx: &mut Ctx,
+    err: &mut StandardErrorHandler,
+    resolver: &mut StandardResolver,
+) -> Result<String, ()> {
+    configure_errors(options, err)?;
+    for pair in &options.cfg {
+        match pair.split_once('=') {
+            Some((key, value)) => ctx.add_cfg(key.to_owned(), value.to_owned()),
+            None => err.report_no_loc(format!(
+                "Invalid configuration '{}', expected 'key=value'",
+                pair
+            )),
         }
+    }
+    for name in &options.feature {
+        match Feature::from_name(name) {
+            Some(feature) => ctx.enable_feature(feature),
+            None => err.report_no_loc(format!("Unknown feature '{}'", name)),
+        }
+    }
+    if let Some(runtime) = &options.runtime {
+        match runtime.canonicalize() {
+            Ok(path) => {
+                ctx.set_runtime(Some(runtime.display().to_string()));
+                resolver.add_package(String::from(CORE), path);
+            }
+            Err(e) => err.report_no_loc(resolve_error(runtime, e)),
+        }
+    }
+    ctx.set_no_runtime(options.no_runtime);
+    if err.has_error() {
+        return Err(());
+    }
+    let path = match options.input.canonicalize() {
+        Ok(path) => path,
         Err(e) => {
-            err.report_no_loc(e.to_string());
-            err.flush();
-            std::process::exit(0);
+            err.report_no_loc(resolve_error(&options.input, e));
+            return Err(());
+        }
+    };
+
+    // Prepare files & resolver
+    let (module_files, _) = resolver.prepare_files(&path, err)?;
+    let module_name = ctx.get_module_name(module_files, err)?;
+    let module = ModulePath::from_root(module_name.clone());
+    resolver.add_package(module_name.clone(), path);
+    if let Some(codegen) = codegen {
+        if let Some(trap_handler) = &codegen.trap_handler {
+            ctx.set_trap_handler(module.clone(), trap_handler.clone());
+        }
+        if let Some(panic_handler) = &codegen.panic_handler {
+            ctx.set_panic_handler(module.clone(), panic_handler.clone());
         }
     }
+
+    // Compile
+    ctx.add_module(module, err, resolver)?;
+    if err.has_error() {
+        return Err(());
+    }
+    Ok(module_name)
+}
+
+/// Returns the canonical path of the package to compile, exits if it does not exist.
+fn resolve_input(input: &PathBuf) -> PathBuf {
+    match input.canonicalize() {
+        Ok(path) => path,
+        Err(e) => exit_with_error(resolve_error(input, e)),
+    }
+}
+
+fn resolve_error(input: &PathBuf, e: std::io::Error) -> String {
+    format!("Could not resolve '{}': {}", input.display(), e)
+}
+
+fn exit_with_error(message: String) -> ! {
+    let mut err = StandardErrorHandler::new(SourceMap::new());
+    err.report_no_loc(message);
+    err.flush();
+    std::process::exit(65);
+}
+
+/// Returns the WebAssembly variant selected by a target option, `None` for other targets.
+fn wasm_target(target: &str) -> Option<Target> {
+    match target {
+        WASM_TARGET => Some(Target::WASM32_WASI),
+        _ => Target::from_triple(target).ok(),
+    }
+}
+
+/// Compiles the package for the target selected by the configuration.
+fn compile(
+    config: &BuildConfig,
+    ctx: &mut Ctx,
+    module_name: &str,
+    err: &mut StandardErrorHandler,
+    resolver: &StandardResolver,
+) -> Result<Vec<u8>, ()> {
+    match config.target.as_str() {
+        COMPONENT_TARGET => ctx.get_component(module_name, err, resolver),
+        #[cfg(feature = "cranelift")]
+        NATIVE_TARGET => ctx.get_object(err, resolver),
+        #[cfg(not(feature = "cranelift"))]
+        NATIVE_TARGET => {
+            err.report_no_loc(String::from(
+                "The native target requires a compiler built with the 'cranelift' feature",
+            ));
+            Err(())
+        }
+        _ => ctx.get_wasm(err, resolver),
+    }
+}
+
+/// Generates the additional artifacts requested by the configuration, along with the extension
+/// of the file they are written to.
+fn emit(
+    config: &BuildConfig,
+    ctx: &mut Ctx,
+    module_name: &str,
+    err: &mut StandardErrorHandler,
+    resolver: &StandardResolver,
+) -> Result<Vec<(&'static str, String)>, ()> {
+    let mut artifacts = Vec::new();
+    for emit in &config.emit {
+        match emit.as_str() {
+            JS_BINDINGS => {
+                let (bindings, declarations) = ctx.get_js_bindings(err, resolver)?;
+                artifacts.push(("js", bindings));
+                artifacts.push(("d.ts", declarations));
+            }
+            WIT => artifacts.push(("wit", ctx.get_wit(module_name, err, resolver)?)),
+            _ => (),
+        }
+    }
+    Ok(artifacts)
+}
+
+/// Configures the lint levels of the error handler, fails if a lint does not exist.
+fn configure_errors(options: &PackageOptions, err: &mut StandardErrorHandler) -> Result<(), ()> {
+    let levels = options
+        .warn
+        .iter()
+        .map(|name| (name, LintLevel::Warn))
+        .chain(options.allow.iter().map(|name| (name, LintLevel::Allow)));
+    for (name, level) in levels {
+        match Lint::from_name(name) {
+            Some(lint) => err.set_lint_level(lint, level),
+            None => err.report_no_loc(format!("Unknown lint '{}'", name)),
+        }
+    }
+    err.set_deny_warnings(options.deny_warnings);
+    err.set_error_limit(match options.error_limit {
+        0 => None,
+        limit => Some(limit),
+    });
+    if err.has_error() {
+        return Err(());
+    }
+    Ok(())
 }
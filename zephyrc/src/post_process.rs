@@ -0,0 +1,54 @@
+//! Post-processing
+//!
+//! Passes the generated module through an external command, such as Binaryen's `wasm-opt`. The
+//! module is written to a temporary file whose path is appended to the command, followed by
+//! `-o` and the path the command must write its result to.
+
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use zephyr::PostProcess;
+
+/// Returns a post-processing step running `cmd` through the shell.
+pub fn command(cmd: String) -> PostProcess {
+    Box::new(move |module| {
+        let dir = std::env::temp_dir();
+        let input = dir.join(format!("zephyr-{}.wasm", std::process::id()));
+        let output = dir.join(format!("zephyr-{}.post.wasm", std::process::id()));
+        fs::write(&input, module)
+            .map_err(|e| format!("Could not write '{}': {}", input.display(), e))?;
+        let result = run(&cmd, &input, &output);
+        let _ = fs::remove_file(&input);
+        let _ = fs::remove_file(&output);
+        result
+    })
+}
+
+/// Runs the command and reads its output.
+fn run(cmd: &str, input: &Path, output: &Path) -> Result<Vec<u8>, String> {
+    let status = if cfg!(target_os = "windows") {
+        let cmd = format!(
+            "{} \"{}\" -o \"{}\"",
+            cmd,
+            input.display(),
+            output.display()
+        );
+        Command::new("cmd").args(["/C", &cmd]).status()
+    } else {
+        // The paths are passed as positional arguments to avoid quoting them
+        Command::new("sh")
+            .args(["-c", &format!("{} \"$@\"", cmd), "sh"])
+            .arg(input)
+            .arg("-o")
+            .arg(output)
+            .status()
+    };
+    match status {
+        Ok(status) if status.success() => {
+            fs::read(output).map_err(|e| format!("Could not read the output of '{}': {}", cmd, e))
+        }
+        Ok(status) => Err(format!("'{}' failed: {}", cmd, status)),
+        Err(e) => Err(format!("Could not run '{}': {}", cmd, e)),
+    }
+}
@@ -0,0 +1,270 @@
+//! Read-Eval-Print Loop
+//!
+//! Each input is wrapped, together with the previous declarations and statements, into a synthetic
+//! standalone module. The module is compiled down to MIR and its entry point is evaluated by the
+//! interpreter. Statements are replayed on each evaluation, which preserves top-level bindings
+//! between inputs, while expressions are evaluated only once.
+
+use std::fs;
+use std::io;
+use std::io::{BufRead, Write};
+use std::path::PathBuf;
+
+use zephyr::error::{ErrorHandler, Lint, LintLevel, SourceMap};
+use zephyr::resolver::ModulePath;
+use zephyr::{Ctx, RuntimeValue, SpanKind};
+use zephyrc::error_handler::StandardErrorHandler;
+use zephyrc::resolver::StandardResolver;
+
+const MODULE: &str = "repl";
+const ENTRY_POINT: &str = "__repl";
+const VALUE: &str = "__value";
+
+const PROMPT: &str = "> ";
+const CONTINUATION_PROMPT: &str = "| ";
+
+/// Number of MIR statements an input can execute, so that infinite loops do not hang the REPL.
+const FUEL: u64 = 10_000_000;
+
+/// Keywords starting a top-level declaration.
+const DECLARATION_KEYWORDS: [&str; 7] = ["fun", "pub", "struct", "use", "expose", "from", "init"];
+
+/// Lints which do not make sense for code typed incrementally.
+const ALLOWED_LINTS: [Lint; 3] = [
+    Lint::UnusedVariable,
+    Lint::UnusedFunction,
+    Lint::UnusedImport,
+];
+
+/// Types which can be returned by the entry point and displayed.
+const DISPLAYABLE_TYPES: [&str; 5] = ["i32", "i64", "f32", "f64", "bool"];
+
+/// Runs the REPL until the input is closed, then exits.
+pub fn repl() -> ! {
+    let dir = std::env::temp_dir().join(format!("zephyr-repl-{}", std::process::id()));
+    if let Err(e) = fs::create_dir_all(&dir) {
+        let mut err = StandardErrorHandler::new(SourceMap::new());
+        err.report_no_loc(format!("Could not create '{}': {}", dir.display(), e));
+        err.flush();
+        std::process::exit(65);
+    }
+    let mut session = Session::new(dir.join(format!("{}.zph", MODULE)));
+
+    println!("Zephyr REPL, type ':quit' or press Ctrl-D to exit.");
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    let mut input = String::new();
+    loop {
+        print!(
+            "{}",
+            if input.is_empty() {
+                PROMPT
+            } else {
+                CONTINUATION_PROMPT
+            }
+        );
+        let _ = io::stdout().flush();
+        let line = match lines.next() {
+            Some(Ok(line)) => line,
+            _ => break,
+        };
+        if input.is_empty() {
+            match line.trim() {
+                "" => continue,
+                ":quit" | ":q" => break,
+                ":reset" => {
+                    session.reset();
+                    continue;
+                }
+                _ => (),
+            }
+        }
+        input.push_str(&line);
+        input.push('\n');
+        if is_complete(&input) {
+            session.eval(input.trim_end());
+            input.clear();
+        }
+    }
+
+    let _ = fs::remove_dir_all(&dir);
+    println!();
+    std::process::exit(0);
+}
+
+/// The declarations and statements accepted so far.
+struct Session {
+    path: PathBuf,
+    declarations: Vec<String>,
+    statements: Vec<String>,
+}
+
+impl Session {
+    fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            declarations: Vec::new(),
+            statements: Vec::new(),
+        }
+    }
+
+    fn reset(&mut self) {
+        self.declarations.clear();
+        self.statements.clear();
+    }
+
+    /// Evaluates an input and prints its value, if any.
+    fn eval(&mut self, input: &str) {
+        if is_declaration(input) {
+            let mut declarations = self.declarations.clone();
+            declarations.push(input.to_owned());
+            let code = self.code(&declarations, &self.statements, None);
+            if self.run(&code).is_ok() {
+                self.declarations = declarations;
+            }
+            return;
+        }
+
+        // Try to evaluate the input as an expression first, then fallback to a statement.
+        let binding = format!("let {} = {}", VALUE, input);
+        let code = self.code(&self.declarations, &self.statements, Some(&binding));
+        if let Some(t) = self.get_value_type(&code) {
+            if DISPLAYABLE_TYPES.contains(&t.as_str()) {
+                let ret = format!("{}\n    return {}", binding, VALUE);
+                let code = self.code_returning(&t, &ret);
+                if let Ok(values) = self.run(&code) {
+                    if let Some(value) = values.first() {
+                        println!("{}: {}", display_value(value, &t), t);
+                    }
+                }
+                return;
+            } else if t != "null" {
+                if self.run(&code).is_ok() {
+                    println!("<{}>", t);
+                }
+                return;
+            }
+        }
+        let mut statements = self.statements.clone();
+        statements.push(input.to_owned());
+        let code = self.code(&self.declarations, &statements, None);
+        if self.run(&code).is_ok() {
+            self.statements = statements;
+        }
+    }
+
+    /// Type checks the code and returns the type of the value bound by the last statement, if the
+    /// code is valid. Errors are not reported.
+    fn get_value_type(&self, code: &str) -> Option<String> {
+        let mut resolver = StandardResolver::new();
+        let mut ctx = Ctx::new();
+        let mut err = StandardErrorHandler::new(ctx.sources().clone());
+        let module = self.prepare(code, &mut resolver, &mut err).ok()?;
+        ctx.add_module(module, &mut err, &resolver).ok()?;
+
+        let f_id = *resolver.get_file_ids(&self.path).first()?;
+        let binding = code.rfind(&format!("let {}", VALUE))? + "let ".len();
+        let symbol = ctx.get_symbol_at(f_id, binding as u32)?;
+        symbol.t.as_ref().map(|t| ctx.display_type(t))
+    }
+
+    /// Compiles the code and evaluates its entry point, errors are reported.
+    fn run(&self, code: &str) -> Result<Vec<RuntimeValue>, ()> {
+        let mut resolver = StandardResolver::new();
+        let mut ctx = Ctx::new();
+        let mut err = StandardErrorHandler::new(ctx.sources().clone());
+        for lint in ALLOWED_LINTS.iter() {
+            err.set_lint_level(*lint, LintLevel::Allow);
+        }
+        ctx.set_fuel(Some(FUEL));
+        let result = self
+            .prepare(code, &mut resolver, &mut err)
+            .and_then(|module| {
+                ctx.add_module(module.clone(), &mut err, &resolver)?;
+                ctx.interpret(&module, ENTRY_POINT, &mut err, &resolver)
+            });
+        err.flush();
+        result
+    }
+
+    /// Writes the code of the synthetic module and registers it to the resolver.
+    fn prepare(
+        &self,
+        code: &str,
+        resolver: &mut StandardResolver,
+        err: &mut StandardErrorHandler,
+    ) -> Result<ModulePath, ()> {
+        if let Err(e) = fs::write(&self.path, code) {
+            err.report_no_loc(format!("Could not write '{}': {}", self.path.display(), e));
+            return Err(());
+        }
+        resolver.add_package(String::from(MODULE), self.path.clone());
+        Ok(ModulePath::from_root(String::from(MODULE)))
+    }
+
+    /// Returns the code of the synthetic module, the entry point executes the statements followed
+    /// by `last`, if any.
+    fn code(&self, declarations: &[String], statements: &[String], last: Option<&str>) -> String {
+        let mut body = statements.to_vec();
+        body.extend(last.map(str::to_owned));
+        module_code(declarations, &body, None)
+    }
+
+    /// Returns the code of the synthetic module, the entry point executes the statements followed
+    /// by `last` and returns a value of type `t`.
+    fn code_returning(&self, t: &str, last: &str) -> String {
+        let mut body = self.statements.clone();
+        body.push(last.to_owned());
+        module_code(&self.declarations, &body, Some(t))
+    }
+}
+
+fn module_code(declarations: &[String], body: &[String], ret_t: Option<&str>) -> String {
+    let mut code = format!("standalone module {}\n\n", MODULE);
+    for declaration in declarations {
+        code.push_str(declaration);
+        code.push_str("\n\n");
+    }
+    let ret_t = ret_t.map(|t| format!(": {}", t)).unwrap_or_default();
+    code.push_str(&format!("pub fun {}(){} {{\n", ENTRY_POINT, ret_t));
+    for stmt in body {
+        code.push_str("    ");
+        code.push_str(stmt);
+        code.push('\n');
+    }
+    code.push_str("}\n");
+    code
+}
+
+/// Returns true if the input starts with a top-level declaration.
+fn is_declaration(input: &str) -> bool {
+    let first_word = input
+        .trim_start()
+        .split(|c: char| !c.is_alphanumeric() && c != '_')
+        .next()
+        .unwrap_or("");
+    DECLARATION_KEYWORDS.contains(&first_word)
+}
+
+/// Returns true if all the parenthesis and braces of the input are closed.
+fn is_complete(input: &str) -> bool {
+    let mut depth = 0;
+    for span in zephyr::highlight(input) {
+        if span.kind != SpanKind::Punctuation {
+            continue;
+        }
+        match &input[span.range] {
+            "(" | "{" => depth += 1,
+            ")" | "}" => depth -= 1,
+            _ => (),
+        }
+    }
+    depth <= 0
+}
+
+fn display_value(value: &RuntimeValue, t: &str) -> String {
+    match (value, t) {
+        (RuntimeValue::I32(x), "bool") => format!("{}", *x != 0),
+        (value, _) => format!("{}", value),
+    }
+}
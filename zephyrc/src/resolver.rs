@@ -2,7 +2,7 @@
 //!
 //! This is the implementation used in the official binary of the Zephyr compiler.
 
-use std::cell::Cell;
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 use std::fs;
 use std::path::{Path, PathBuf};
@@ -30,6 +30,8 @@ pub enum ResolvedPath {
 /// The standard implementation of a resolver, used by the zephyr CLI application.
 pub struct StandardResolver {
     package_paths: HashMap<String, PathBuf>,
+    overlays: HashMap<PathBuf, String>,
+    file_paths: RefCell<HashMap<FileId, PathBuf>>,
     file_id: Cell<FileId>,
 }
 
@@ -55,6 +57,8 @@ impl StandardResolver {
         package_paths.insert(String::from(STD), std_path);
         Self {
             package_paths,
+            overlays: HashMap::new(),
+            file_paths: RefCell::new(HashMap::new()),
             file_id: Cell::new(FileId(1)),
         }
     }
@@ -64,6 +68,50 @@ impl StandardResolver {
         self.package_paths.insert(pkg_name, path);
     }
 
+    /// Returns the paths of all the registered packages.
+    pub fn get_package_paths(&self) -> Vec<PathBuf> {
+        self.package_paths.values().cloned().collect()
+    }
+
+    /// Use `code` instead of the content on disk for the file at `path`, this is used by the
+    /// language server to compile unsaved files.
+    pub fn set_overlay(&mut self, path: PathBuf, code: String) {
+        self.overlays.insert(path, code);
+    }
+
+    /// Read the file at `path` from disk again.
+    pub fn remove_overlay(&mut self, path: &Path) {
+        self.overlays.remove(path);
+    }
+
+    /// Returns the path of a file prepared by this resolver.
+    pub fn get_file_path(&self, f_id: FileId) -> Option<PathBuf> {
+        self.file_paths.borrow().get(&f_id).cloned()
+    }
+
+    /// Returns the paths of all the files prepared by this resolver, sorted and without
+    /// duplicates.
+    pub fn get_file_paths(&self) -> Vec<PathBuf> {
+        let mut paths: Vec<PathBuf> = self.file_paths.borrow().values().cloned().collect();
+        paths.sort();
+        paths.dedup();
+        paths
+    }
+
+    /// Returns all the IDs attributed to the file at `path`, a new ID is attributed each time the
+    /// file is prepared. The IDs are sorted in preparation order.
+    pub fn get_file_ids(&self, path: &Path) -> Vec<FileId> {
+        let mut f_ids: Vec<FileId> = self
+            .file_paths
+            .borrow()
+            .iter()
+            .filter(|(_, file_path)| file_path.as_path() == path)
+            .map(|(f_id, _)| *f_id)
+            .collect();
+        f_ids.sort();
+        f_ids
+    }
+
     /// Prepare files at a given path.
     pub fn prepare_files<P: AsRef<Path>>(
         &self,
@@ -99,13 +147,17 @@ impl StandardResolver {
         for path in paths {
             let f_id = self.fresh_f_id();
             let kind = self.get_file_kind(&path, err)?;
-            let code = fs::read_to_string(&path).expect("Internal error: invalid path.");
+            let code = match self.overlays.get(&path) {
+                Some(code) => code.clone(),
+                None => fs::read_to_string(&path).expect("Internal error: invalid path."),
+            };
             let file_name = path
                 .file_stem()
                 .expect("Internal error: path is not a file.")
                 .to_str()
                 .expect("File name at seems to use non standard characters")
                 .to_string();
+            self.file_paths.borrow_mut().insert(f_id, path);
             files.push(PreparedFile {
                 code,
                 f_id,
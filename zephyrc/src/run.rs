@@ -0,0 +1,158 @@
+//! Runner
+//!
+//! Executes a compiled module with an embedded WebAssembly runtime, with the standard input and
+//! outputs of the process exposed through WASI. Both the `wasi_unstable` and
+//! `wasi_snapshot_preview1` interfaces are provided.
+//!
+//! Tests are run the same way, each in a fresh instance of the module so that they do not share
+//! their memory.
+
+use wasmtime::{Engine, Instance, Linker, Module, Store};
+use wasmtime_wasi::preview1::WasiP1Ctx;
+use wasmtime_wasi::{preview0, preview1, I32Exit, WasiCtxBuilder};
+
+use zephyr::error::{ErrorHandler, SourceMap};
+use zephyr::TEST_PREFIX;
+use zephyrc::color::{END, GREEN, RED};
+use zephyrc::error_handler::StandardErrorHandler;
+
+/// Runs the `_start` function of a module, then exits with the exit code of the program. If
+/// `_start` returns an i32 its value is used as the exit code.
+///
+/// `name` is passed to the program as its first argument, followed by `args`.
+pub fn run(module: &[u8], name: &str, args: &[String]) -> ! {
+    match execute(module, name, args) {
+        Ok(code) => std::process::exit(code),
+        Err(e) => exit_with_error(e),
+    }
+}
+
+/// Runs the test functions exposed by a module, whose name contains `filter` if any, then exits
+/// with an error if one of them failed.
+///
+/// A test fails if it traps or exits with a non-zero code, for instance by panicking.
+pub fn test(module: &[u8], filter: Option<&str>) -> ! {
+    let engine = Engine::default();
+    let (module, linker) = match load(&engine, module) {
+        Ok(loaded) => loaded,
+        Err(e) => exit_with_error(e),
+    };
+    let mut tests = module
+        .exports()
+        .filter_map(|export| export.name().strip_prefix(TEST_PREFIX))
+        .filter(|name| filter.map_or(true, |filter| name.contains(filter)))
+        .collect::<Vec<_>>();
+    tests.sort_unstable();
+
+    let plural = if tests.len() == 1 { "" } else { "s" };
+    println!("running {} test{}", tests.len(), plural);
+    let mut failures = Vec::new();
+    for name in &tests {
+        let export = format!("{}{}", TEST_PREFIX, name);
+        let result = instantiate(&engine, &linker, &module, &[String::from(*name)]).and_then(
+            |(mut store, instance)| {
+                let test = instance
+                    .get_typed_func::<(), ()>(&mut store, &export)
+                    .map_err(|e| e.to_string())?;
+                to_exit_code(test.call(&mut store, ())).map_err(|e| e.root_cause().to_string())
+            },
+        );
+        match result {
+            Ok(0) => println!("test {} ... {}ok{}", name, GREEN, END),
+            Ok(code) => {
+                println!(
+                    "test {} ... {}FAILED{} (exit code {})",
+                    name, RED, END, code
+                );
+                failures.push(name);
+            }
+            Err(e) => {
+                println!("test {} ... {}FAILED{}\n{}", name, RED, END, e);
+                failures.push(name);
+            }
+        }
+    }
+
+    let status = if failures.is_empty() {
+        format!("{}ok{}", GREEN, END)
+    } else {
+        format!("{}FAILED{}", RED, END)
+    };
+    println!(
+        "\ntest result: {}. {} passed; {} failed",
+        status,
+        tests.len() - failures.len(),
+        failures.len()
+    );
+    if failures.is_empty() {
+        std::process::exit(0);
+    } else {
+        std::process::exit(101);
+    }
+}
+
+fn execute(module: &[u8], name: &str, args: &[String]) -> Result<i32, String> {
+    let engine = Engine::default();
+    let (module, linker) = load(&engine, module)?;
+    let mut argv = vec![String::from(name)];
+    argv.extend(args.iter().cloned());
+    let (mut store, instance) = instantiate(&engine, &linker, &module, &argv)?;
+    let start = instance
+        .get_func(&mut store, "_start")
+        .ok_or_else(|| String::from("The package must expose a '_start' function"))?;
+    let result = if let Ok(start) = start.typed::<(), ()>(&store) {
+        to_exit_code(start.call(&mut store, ()))
+    } else if let Ok(start) = start.typed::<(), i32>(&store) {
+        start.call(&mut store, ()).or_else(|e| to_exit_code(Err(e)))
+    } else {
+        return Err(format!(
+            "The '_start' function must take no parameters and return nothing or an i32, found {}",
+            start.ty(&store)
+        ));
+    };
+    result.map_err(|e| format!("Runtime error: {:?}", e))
+}
+
+/// Compiles a module and prepares a linker providing the WASI interfaces.
+fn load(engine: &Engine, module: &[u8]) -> Result<(Module, Linker<WasiP1Ctx>), String> {
+    let module =
+        Module::new(engine, module).map_err(|e| format!("Could not load module: {}", e))?;
+    let mut linker: Linker<WasiP1Ctx> = Linker::new(engine);
+    preview0::add_to_linker_sync(&mut linker, |ctx| ctx).map_err(|e| e.to_string())?;
+    preview1::add_to_linker_sync(&mut linker, |ctx| ctx).map_err(|e| e.to_string())?;
+    Ok((module, linker))
+}
+
+/// Creates a new instance of a module, `argv` are the arguments of the program.
+fn instantiate(
+    engine: &Engine,
+    linker: &Linker<WasiP1Ctx>,
+    module: &Module,
+    argv: &[String],
+) -> Result<(Store<WasiP1Ctx>, Instance), String> {
+    let wasi = WasiCtxBuilder::new().inherit_stdio().args(argv).build_p1();
+    let mut store = Store::new(engine, wasi);
+    let instance = linker
+        .instantiate(&mut store, module)
+        .map_err(|e| format!("Could not instantiate module: {}", e))?;
+    Ok((store, instance))
+}
+
+/// Converts the result of a call into the exit code of the program, a call returning normally
+/// exits with code 0. Other errors, such as traps, are returned as is.
+fn to_exit_code(result: wasmtime::Result<()>) -> wasmtime::Result<i32> {
+    match result {
+        Ok(()) => Ok(0),
+        Err(e) => match e.downcast_ref::<I32Exit>() {
+            Some(exit) => Ok(exit.0),
+            None => Err(e),
+        },
+    }
+}
+
+fn exit_with_error(message: String) -> ! {
+    let mut err = StandardErrorHandler::new(SourceMap::new());
+    err.report_no_loc(message);
+    err.flush();
+    std::process::exit(70);
+}
@@ -0,0 +1,91 @@
+//! Stats
+//!
+//! Prints size metrics of the modules and functions of a package, to find the functions which
+//! weigh the most on the final module. Functions which are not part of the module, because they
+//! are never used or always inlined, have no statements nor size.
+
+use zephyr::error::ErrorHandler;
+use zephyr::{Ctx, FunctionMetrics, ModuleMetrics};
+use zephyrc::error_handler::StandardErrorHandler;
+use zephyrc::resolver::StandardResolver;
+
+use super::{load_package, timings, StatsConfig};
+
+/// Compiles the package pointed by the configuration and prints its metrics, then exits.
+pub fn stats(config: &StatsConfig) -> ! {
+    let mut resolver = StandardResolver::new();
+    let mut ctx = Ctx::new();
+    let mut err = StandardErrorHandler::new(ctx.sources().clone());
+    config.codegen.configure(&mut ctx);
+    let module_name = load_package(
+        &config.package,
+        Some(&config.codegen),
+        &mut ctx,
+        &mut err,
+        &mut resolver,
+    );
+    let metrics = match ctx.get_metrics(&module_name, &mut err, &resolver) {
+        Ok(metrics) => metrics,
+        Err(()) => {
+            err.flush();
+            std::process::exit(65);
+        }
+    };
+    err.flush();
+    print!("{}", to_text(&metrics));
+    timings::report(&config.package, &mut ctx);
+    std::process::exit(0);
+}
+
+/// Returns a table with a row per module, holding the totals of its functions, followed by a row
+/// per function.
+fn to_text(modules: &[ModuleMetrics]) -> String {
+    let width = modules
+        .iter()
+        .flat_map(|module| {
+            std::iter::once(module.module.len())
+                .chain(module.functions.iter().map(|fun| fun.name.len() + 2))
+        })
+        .max()
+        .unwrap_or(0)
+        .max("module".len());
+    let mut text = format!(
+        "{:<width$} {:>7} {:>7} {:>11} {:>10}\n",
+        "module",
+        "lines",
+        "locals",
+        "statements",
+        "wasm size",
+        width = width
+    );
+    for module in modules {
+        let total = FunctionMetrics {
+            name: module.module.clone(),
+            lines: module.functions.iter().map(|fun| fun.lines).sum(),
+            locals: module.functions.iter().map(|fun| fun.locals).sum(),
+            statements: Some(module.functions.iter().filter_map(|f| f.statements).sum()),
+            wasm_size: Some(module.functions.iter().filter_map(|f| f.wasm_size).sum()),
+        };
+        text.push_str(&row(&total, "", width));
+        for fun in &module.functions {
+            text.push_str(&row(fun, "  ", width));
+        }
+    }
+    text
+}
+
+fn row(fun: &FunctionMetrics, indent: &str, width: usize) -> String {
+    let display = |value: Option<usize>| match value {
+        Some(value) => value.to_string(),
+        None => String::from("-"),
+    };
+    format!(
+        "{:<width$} {:>7} {:>7} {:>11} {:>10}\n",
+        format!("{}{}", indent, fun.name),
+        fun.lines,
+        fun.locals,
+        display(fun.statements),
+        display(fun.wasm_size),
+        width = width
+    )
+}
@@ -0,0 +1,180 @@
+//! Timings
+//!
+//! Reports the duration of the compilation phases and statistics about the compiled modules and
+//! the HIR stores, either as tables or as JSON for performance tracking. Reports are printed on
+//! stderr to keep stdout for the program when running it.
+//!
+//! The self profile is a trace of the phases and of the passes run on each function, which can
+//! be opened with the Chrome trace viewer (`chrome://tracing`) or Perfetto to diagnose slow
+//! compilations.
+
+use serde_json::json;
+use std::fs;
+use std::time::Duration;
+
+use zephyr::{Ctx, Timings};
+
+use super::{exit_with_error, PackageOptions};
+
+pub const TEXT: &str = "text";
+pub const JSON: &str = "json";
+
+/// Prints the timings recorded by the compilation context and writes the self profile, if
+/// requested by the options.
+pub fn report(options: &PackageOptions, ctx: &mut Ctx) {
+    let timings = match ctx.take_timings() {
+        Some(timings) => timings,
+        None => return,
+    };
+    match options.timings.as_deref() {
+        Some(JSON) => eprintln!("{}", to_json(&timings)),
+        Some(_) => eprint!("{}", to_text(&timings)),
+        None => (),
+    }
+    if let Some(path) = &options.self_profile {
+        if let Err(e) = fs::write(path, to_trace(&timings).to_string()) {
+            exit_with_error(format!("Could not write '{}': {}", path.display(), e));
+        }
+    }
+}
+
+fn to_text(timings: &Timings) -> String {
+    let mut text = format!("{:<16} {:>10}\n", "phase", "time");
+    for (phase, duration) in timings.phases() {
+        text.push_str(&format!("{:<16} {:>10}\n", phase.name(), display(duration)));
+    }
+    text.push_str(&format!(
+        "{:<16} {:>10}\n\n",
+        "total",
+        display(timings.total())
+    ));
+
+    let width = timings
+        .modules
+        .iter()
+        .map(|stats| stats.module.len())
+        .max()
+        .unwrap_or(0)
+        .max("module".len());
+    text.push_str(&format!(
+        "{:<width$} {:>6} {:>7} {:>10}\n",
+        "module",
+        "files",
+        "lines",
+        "functions",
+        width = width
+    ));
+    for stats in &timings.modules {
+        let functions = match stats.functions {
+            Some(functions) => functions.to_string(),
+            None => String::from("-"),
+        };
+        text.push_str(&format!(
+            "{:<width$} {:>6} {:>7} {:>10}\n",
+            stats.module,
+            stats.files,
+            stats.lines,
+            functions,
+            width = width
+        ));
+    }
+
+    if !timings.stores.is_empty() {
+        text.push_str(&format!(
+            "\n{:<8} {:>8} {:>9} {:>11} {:>10}\n",
+            "store", "items", "capacity", "collisions", "memory"
+        ));
+    }
+    for (kind, stats) in &timings.stores {
+        text.push_str(&format!(
+            "{:<8} {:>8} {:>9} {:>11} {:>10}\n",
+            kind,
+            stats.items,
+            stats.capacity,
+            stats.collisions,
+            format!("{}KiB", stats.bytes.div_ceil(1024))
+        ));
+    }
+    text
+}
+
+fn to_json(timings: &Timings) -> serde_json::Value {
+    let phases: serde_json::Map<_, _> = timings
+        .phases()
+        .into_iter()
+        .map(|(phase, duration)| (String::from(phase.name()), json!(millis(duration))))
+        .collect();
+    let modules: Vec<_> = timings
+        .modules
+        .iter()
+        .map(|stats| {
+            json!({
+                "module": stats.module,
+                "files": stats.files,
+                "lines": stats.lines,
+                "functions": stats.functions,
+            })
+        })
+        .collect();
+    let stores: Vec<_> = timings
+        .stores
+        .iter()
+        .map(|(kind, stats)| {
+            json!({
+                "store": kind,
+                "items": stats.items,
+                "capacity": stats.capacity,
+                "collisions": stats.collisions,
+                "bytes": stats.bytes,
+            })
+        })
+        .collect();
+    json!({
+        "phases": phases,
+        "total": millis(timings.total()),
+        "modules": modules,
+        "stores": stores,
+    })
+}
+
+/// Returns the events as complete events of the Chrome trace event format, timestamps are in
+/// microseconds.
+fn to_trace(timings: &Timings) -> serde_json::Value {
+    let events: Vec<_> = timings
+        .events()
+        .into_iter()
+        .map(|event| {
+            let mut trace_event = json!({
+                "name": event.name,
+                "cat": event.category,
+                "ph": "X",
+                "ts": micros(event.start),
+                "dur": micros(event.duration),
+                "pid": 1,
+                "tid": 1,
+            });
+            if let Some(function) = event.function {
+                trace_event["args"] = json!({ "function": function });
+            }
+            trace_event
+        })
+        .collect();
+    json!({
+        "traceEvents": events,
+        "displayTimeUnit": "ms",
+        "otherData": { "modules": to_json(timings)["modules"] },
+    })
+}
+
+fn micros(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1_000_000.0
+}
+
+/// Durations are reported in milliseconds.
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+fn display(duration: Duration) -> String {
+    format!("{:.2}ms", millis(duration))
+}
@@ -0,0 +1,247 @@
+//! Watch mode
+//!
+//! Watches the source files of the package (and of the packages it may depend on) and rebuilds
+//! whenever one of them changes. The compiler exits on the first phase producing errors, hence
+//! each build is run in a child process.
+
+use notify::{watcher, DebouncedEvent, RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::mpsc::channel;
+use std::time::{Duration, Instant};
+
+use super::{BuildConfig, NO_GC, WASM_TARGET};
+use zephyrc::color;
+use zephyrc::color::{BOLD, END, GREEN, RED};
+use zephyrc::resolver::{ASM_EXTENSION, ZEPHYR_EXTENSION};
+
+const CLEAR_SCREEN: &str = "\x1B[2J\x1B[1;1H";
+
+/// Delay used to merge bursts of file system events into a single rebuild.
+const DEBOUNCE_DELAY: Duration = Duration::from_millis(200);
+
+/// Builds the package, then rebuilds it each time a source file under one of `paths` changes.
+///
+/// Never returns, the user is expected to interrupt the process.
+pub fn watch(config: &BuildConfig, paths: Vec<PathBuf>) -> ! {
+    let (tx, rx) = channel();
+    let mut watcher = match watcher(tx, DEBOUNCE_DELAY) {
+        Ok(watcher) => watcher,
+        Err(e) => exit_with_error(format!("Could not start watching files: {}", e)),
+    };
+    for path in paths {
+        // Single files are watched through their directory, as editors often replace files
+        // instead of writing them in place.
+        let (path, mode) = if path.is_file() {
+            match path.parent() {
+                Some(parent) => (parent.to_owned(), RecursiveMode::NonRecursive),
+                None => (path, RecursiveMode::NonRecursive),
+            }
+        } else {
+            (path, RecursiveMode::Recursive)
+        };
+        if let Err(e) = watcher.watch(&path, mode) {
+            exit_with_error(format!(
+                "Could not watch '{}': {}",
+                path.to_str().unwrap_or(""),
+                e
+            ));
+        }
+    }
+
+    build(config);
+    loop {
+        match rx.recv() {
+            Ok(event) => {
+                if is_source_change(&event) {
+                    build(config);
+                }
+            }
+            Err(e) => exit_with_error(format!("Stopped watching files: {}", e)),
+        }
+    }
+}
+
+/// Runs a single build in a child process and prints a condensed report.
+fn build(config: &BuildConfig) {
+    print!("{}", CLEAR_SCREEN);
+    let start = Instant::now();
+    let status = match std::env::current_exe() {
+        Ok(zephyr) => Command::new(zephyr).args(build_args(config)).status(),
+        Err(e) => Err(e),
+    };
+    match status {
+        Ok(status) if status.success() => {
+            println!(
+                "{}{}Build succeeded{} in {:.2}s, watching for changes...",
+                GREEN,
+                BOLD,
+                END,
+                start.elapsed().as_secs_f64()
+            );
+            if let Some(cmd) = &config.exec {
+                run(cmd);
+            }
+        }
+        Ok(_) => println!(
+            "{}{}Build failed{}, watching for changes...",
+            RED, BOLD, END
+        ),
+        Err(e) => println!("{}{}Could not run the compiler:{} {}", RED, BOLD, END, e),
+    }
+}
+
+/// Returns the arguments to pass to the compiler for a single build.
+fn build_args(config: &BuildConfig) -> Vec<String> {
+    let (package, codegen) = (&config.package, &config.codegen);
+    let mut args = vec![String::from("build")];
+    // The output of the build is forwarded to the terminal the watcher is running in
+    let colors = if color::enabled() {
+        color::ALWAYS
+    } else {
+        color::NEVER
+    };
+    args.push(String::from("--color"));
+    args.push(String::from(colors));
+    if package.verbose {
+        args.push(String::from("--verbose"));
+    }
+    for phase in &package.log {
+        args.push(String::from("--log"));
+        args.push(phase.clone());
+    }
+    for pair in &package.cfg {
+        args.push(String::from("--cfg"));
+        args.push(pair.clone());
+    }
+    for feature in &package.feature {
+        args.push(String::from("--feature"));
+        args.push(feature.clone());
+    }
+    if let Some(runtime) = &package.runtime {
+        args.push(String::from("--runtime"));
+        args.push(runtime.to_string_lossy().into_owned());
+    }
+    if package.no_runtime {
+        args.push(String::from("--no-runtime"));
+    }
+    if let Some(timings) = &package.timings {
+        args.push(String::from("--timings"));
+        args.push(timings.clone());
+    }
+    if let Some(self_profile) = &package.self_profile {
+        args.push(String::from("--self-profile"));
+        args.push(self_profile.to_string_lossy().into_owned());
+    }
+    if codegen.overflow_checks {
+        args.push(String::from("--overflow-checks"));
+    }
+    if codegen.gc != NO_GC {
+        args.push(String::from("--gc"));
+        args.push(codegen.gc.clone());
+    }
+    if codegen.validate {
+        args.push(String::from("--validate"));
+    }
+    if let Some(pages) = codegen.memory_initial {
+        args.push(String::from("--memory-initial"));
+        args.push(pages.to_string());
+    }
+    if let Some(pages) = codegen.memory_max {
+        args.push(String::from("--memory-max"));
+        args.push(pages.to_string());
+    }
+    if codegen.shared_memory {
+        args.push(String::from("--shared-memory"));
+    }
+    if let Some(threshold) = codegen.inline_threshold {
+        args.push(String::from("--inline-threshold"));
+        args.push(threshold.to_string());
+    }
+    if codegen.optimize {
+        args.push(String::from("--optimize"));
+    }
+    if codegen.opt_size {
+        args.push(String::from("--opt-size"));
+    }
+    if config.target != WASM_TARGET {
+        args.push(String::from("--target"));
+        args.push(config.target.clone());
+    }
+    for emit in &config.emit {
+        args.push(String::from("--emit"));
+        args.push(emit.clone());
+    }
+    if config.metadata {
+        args.push(String::from("--metadata"));
+    }
+    if let Some(post_process) = &config.post_process {
+        args.push(String::from("--post-process"));
+        args.push(post_process.clone());
+    }
+    if let Some(trap_handler) = &codegen.trap_handler {
+        args.push(String::from("--trap-handler"));
+        args.push(trap_handler.clone());
+    }
+    if let Some(panic_handler) = &codegen.panic_handler {
+        args.push(String::from("--panic-handler"));
+        args.push(panic_handler.clone());
+    }
+    if let Some(output) = &config.output {
+        args.push(String::from("--output"));
+        args.push(output.to_string_lossy().into_owned());
+    }
+    for lint in &package.warn {
+        args.push(String::from("--warn"));
+        args.push(lint.clone());
+    }
+    for lint in &package.allow {
+        args.push(String::from("--allow"));
+        args.push(lint.clone());
+    }
+    if package.deny_warnings {
+        args.push(String::from("--deny-warnings"));
+    }
+    args.push(String::from("--error-limit"));
+    args.push(package.error_limit.to_string());
+    args.push(package.input.to_string_lossy().into_owned());
+    args
+}
+
+/// Runs a shell command after a successful build.
+fn run(cmd: &str) {
+    let status = if cfg!(target_os = "windows") {
+        Command::new("cmd").args(["/C", cmd]).status()
+    } else {
+        Command::new("sh").args(["-c", cmd]).status()
+    };
+    match status {
+        Ok(status) if status.success() => (),
+        Ok(status) => println!("{}{}Command failed:{} {}", RED, BOLD, END, status),
+        Err(e) => println!("{}{}Could not run command:{} {}", RED, BOLD, END, e),
+    }
+}
+
+/// Returns true if the event concerns a Zephyr source file.
+fn is_source_change(event: &DebouncedEvent) -> bool {
+    match event {
+        DebouncedEvent::Create(path)
+        | DebouncedEvent::Write(path)
+        | DebouncedEvent::Remove(path) => is_source_file(path),
+        DebouncedEvent::Rename(from, to) => is_source_file(from) || is_source_file(to),
+        DebouncedEvent::Rescan => true,
+        _ => false,
+    }
+}
+
+fn is_source_file(path: &Path) -> bool {
+    match path.extension() {
+        Some(ext) => ext.eq(ZEPHYR_EXTENSION) || ext.eq(ASM_EXTENSION),
+        None => false,
+    }
+}
+
+fn exit_with_error(message: String) -> ! {
+    println!("{}{}Error:{}{} {}{}", RED, BOLD, END, RED, message, END);
+    std::process::exit(65);
+}
@@ -0,0 +1,49 @@
+(module
+  (type (;0;) (func (param i32) (result i32)))
+  (memory (;0;) 1)
+  (export "fib" (func 0))
+  (export "memory" (memory 0))
+  (func (;0;) (type 0) (param i32) (result i32)
+    (local i32 i32 i32 i32)
+    local.get 0
+    i32.const 2
+    i32.lt_s
+    if
+      i32.const 1
+      return
+    end
+    i32.const 1
+    local.set 1
+    i32.const 1
+    local.set 2
+    i32.const 2
+    local.set 3
+    block
+      loop
+        local.get 3
+        local.get 0
+        i32.le_s
+        i32.const 1
+        i32.xor
+        br_if 1
+        local.get 1
+        local.get 2
+        i32.add
+        local.set 4
+        local.get 2
+        local.set 1
+        local.get 4
+        local.set 2
+        local.get 3
+        i32.const 1
+        i32.add
+        local.set 3
+        br 0
+      end
+    end
+    local.get 2
+    return
+  )
+  (data (;0;) (i32.const 0) "\0c\00\00\00")
+  (data (;1;) (i32.const 8) "\ff\ff\ff\ff\f0\ff\00\00")
+)
@@ -0,0 +1,63 @@
+(module
+  (type (;0;) (func (param i32 i32) (result i32)))
+  (type (;1;) (func (param i32 i32 i32) (result i32)))
+  (type (;2;) (func (param i32) (result i32)))
+  (memory (;0;) 1)
+  (export "min" (func 0))
+  (export "max" (func 1))
+  (export "clamp" (func 2))
+  (export "sign" (func 3))
+  (export "memory" (memory 0))
+  (func (;0;) (type 0) (param i32 i32) (result i32)
+    local.get 0
+    local.get 1
+    i32.lt_s
+    if
+      local.get 0
+      return
+    end
+    local.get 1
+    return
+  )
+  (func (;1;) (type 0) (param i32 i32) (result i32)
+    local.get 0
+    local.get 1
+    i32.gt_s
+    if
+      local.get 0
+      return
+    end
+    local.get 1
+    return
+  )
+  (func (;2;) (type 1) (param i32 i32 i32) (result i32)
+    local.get 0
+    local.get 1
+    call 1
+    local.get 2
+    call 0
+    return
+  )
+  (func (;3;) (type 2) (param i32) (result i32)
+    local.get 0
+    i32.const 0
+    i32.lt_s
+    if
+      i32.const 0
+      i32.const 1
+      i32.sub
+      return
+    end
+    local.get 0
+    i32.const 0
+    i32.gt_s
+    if
+      i32.const 1
+      return
+    end
+    i32.const 0
+    return
+  )
+  (data (;0;) (i32.const 0) "\0c\00\00\00")
+  (data (;1;) (i32.const 8) "\ff\ff\ff\ff\f0\ff\00\00")
+)
@@ -0,0 +1,256 @@
+(module
+  (type (;0;) (func (param i32 i32) (result i32)))
+  (type (;1;) (func))
+  (type (;2;) (func (param i32) (result i32)))
+  (type (;3;) (func (param i32 i32)))
+  (type (;4;) (func (param i32)))
+  (table (;0;) 1 funcref)
+  (memory (;0;) 1)
+  (export "twice" (func 9))
+  (export "memory" (memory 0))
+  (elem (;0;) (i32.const 0) func 10)
+  (func (;0;) (type 1)
+    unreachable
+  )
+  (func (;1;) (type 2) (param i32) (result i32)
+    local.get 0
+    i32.load
+  )
+  (func (;2;) (type 3) (param i32 i32)
+    local.get 0
+    local.get 1
+    i32.store
+  )
+  (func (;3;) (type 2) (param i32) (result i32)
+    (local i32 i32 i32 i32)
+    i32.const 0
+    call 1
+    local.set 1
+    local.get 0
+    call 4
+    local.set 2
+    block
+      loop
+        i32.const 1
+        i32.const 1
+        i32.xor
+        br_if 1
+        local.get 1
+        i32.const 0
+        i32.eq
+        if
+          call 0
+        end
+        local.get 1
+        call 1
+        local.get 2
+        i32.ge_s
+        if
+          local.get 1
+          local.get 2
+          call 5
+          local.set 3
+          local.get 1
+          call 6
+          local.get 3
+          i32.const -2147483648
+          i32.or
+          local.set 4
+          local.get 1
+          local.get 4
+          call 2
+          local.get 1
+          local.get 3
+          i32.add
+          local.get 4
+          call 2
+          local.get 1
+          i32.const 4
+          i32.add
+          return
+        end
+        local.get 1
+        i32.const 4
+        i32.add
+        call 1
+        local.set 1
+        br 0
+      end
+    end
+    i32.const 0
+    return
+  )
+  (func (;4;) (type 2) (param i32) (result i32)
+    (local i32)
+    local.get 0
+    i32.const 8
+    i32.le_s
+    if
+      i32.const 12
+      return
+    end
+    local.get 0
+    i32.const 7
+    i32.add
+    i32.const -8
+    i32.and
+    local.set 1
+    local.get 1
+    i32.const 4
+    i32.add
+    return
+  )
+  (func (;5;) (type 0) (param i32 i32) (result i32)
+    (local i32 i32 i32 i32)
+    local.get 0
+    call 1
+    local.set 2
+    local.get 2
+    local.get 1
+    i32.sub
+    i32.const 16
+    i32.ge_s
+    if
+      local.get 0
+      i32.const 4
+      i32.add
+      local.get 1
+      i32.add
+      local.set 3
+      local.get 2
+      local.get 1
+      i32.sub
+      i32.const 4
+      i32.sub
+      local.set 4
+      local.get 0
+      i32.const 4
+      i32.add
+      call 1
+      local.set 5
+      local.get 3
+      i32.const 8
+      i32.add
+      local.get 0
+      call 2
+      local.get 3
+      i32.const 4
+      i32.add
+      local.get 5
+      call 2
+      local.get 5
+      i32.const 0
+      i32.ne
+      if
+        local.get 5
+        i32.const 8
+        i32.add
+        local.get 3
+        call 2
+      end
+      local.get 0
+      i32.const 4
+      i32.add
+      local.get 3
+      call 2
+      local.get 0
+      local.get 1
+      call 2
+      local.get 3
+      local.get 4
+      call 2
+      local.get 3
+      local.get 4
+      i32.add
+      local.get 4
+      call 2
+      local.get 1
+      return
+    end
+    local.get 2
+    return
+  )
+  (func (;6;) (type 4) (param i32)
+    (local i32 i32)
+    local.get 0
+    i32.const 4
+    i32.add
+    call 1
+    local.set 1
+    local.get 0
+    i32.const 8
+    i32.add
+    call 1
+    local.set 2
+    local.get 1
+    i32.const 0
+    i32.ne
+    if
+      local.get 1
+      i32.const 8
+      i32.add
+      local.get 2
+      call 2
+    end
+    local.get 2
+    i32.const 0
+    i32.ne
+    if
+      local.get 2
+      i32.const 4
+      i32.add
+      local.get 1
+      call 2
+    else
+      i32.const 0
+      local.get 1
+      call 2
+    end
+  )
+  (func (;7;) (type 2) (param i32) (result i32)
+    local.get 0
+    i32.const 2
+    i32.mul
+    return
+  )
+  (func (;8;) (type 0) (param i32 i32) (result i32)
+    (local i32)
+    local.get 0
+    local.set 2
+    local.get 1
+    local.get 2
+    local.get 2
+    i32.load
+    call_indirect (type 0)
+    return
+  )
+  (func (;9;) (type 2) (param i32) (result i32)
+    (local i32 i32 i32)
+    i32.const 4
+    call 3
+    local.set 2
+    local.get 2
+    i32.const 0
+    i32.store
+    local.get 2
+    local.set 1
+    local.get 1
+    i32.const 4
+    call 3
+    local.set 3
+    local.get 3
+    i32.const 0
+    i32.store
+    local.get 3
+    local.get 0
+    call 8
+    call 8
+    return
+  )
+  (func (;10;) (type 0) (param i32 i32) (result i32)
+    local.get 0
+    call 7
+  )
+  (data (;0;) (i32.const 0) "\0c\00\00\00")
+  (data (;1;) (i32.const 8) "\ff\ff\ff\ff\f0\ff\00\00")
+)
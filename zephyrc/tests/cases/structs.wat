@@ -0,0 +1,41 @@
+(module
+  (type (;0;) (func (param f64 f64) (result f64)))
+  (memory (;0;) 1)
+  (global (;0;) (mut i32) (i32.const 8200))
+  (export "area" (func 0))
+  (export "memory" (memory 0))
+  (func (;0;) (type 0) (param f64 f64) (result f64)
+    (local i32 i32 i32)
+    global.get 0
+    i32.const 16
+    i32.sub
+    local.tee 2
+    global.set 0
+    local.get 2
+    local.set 4
+    local.get 4
+    local.get 0
+    f64.store offset=8
+    local.get 4
+    local.get 1
+    f64.store
+    local.get 4
+    local.set 3
+    local.get 3
+    f64.load offset=8
+    local.get 3
+    f64.load
+    f64.mul
+    local.get 2
+    i32.const 16
+    i32.add
+    global.set 0
+    return
+    local.get 2
+    i32.const 16
+    i32.add
+    global.set 0
+  )
+  (data (;0;) (i32.const 0) "\0c \00\00")
+  (data (;1;) (i32.const 8200) "\ff\ff\ff\ff\f0\df\00\00")
+)
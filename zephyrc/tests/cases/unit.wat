@@ -0,0 +1,43 @@
+(module
+  (type (;0;) (func))
+  (type (;1;) (func (param i32)))
+  (type (;2;) (func (param i32) (result i32)))
+  (memory (;0;) 1)
+  (export "clamp" (func 3))
+  (export "memory" (memory 0))
+  (func (;0;) (type 0)
+  )
+  (func (;1;) (type 1) (param i32)
+    local.get 0
+    i32.const 100
+    i32.gt_s
+    if
+      return
+    end
+    call 0
+  )
+  (func (;2;) (type 1) (param i32)
+    local.get 0
+    call 1
+    local.get 0
+    i32.const 1
+    i32.add
+    call 1
+    return
+  )
+  (func (;3;) (type 2) (param i32) (result i32)
+    local.get 0
+    call 2
+    local.get 0
+    i32.const 100
+    i32.gt_s
+    if
+      i32.const 100
+      local.set 0
+    end
+    local.get 0
+    return
+  )
+  (data (;0;) (i32.const 0) "\0c\00\00\00")
+  (data (;1;) (i32.const 8) "\ff\ff\ff\ff\f0\ff\00\00")
+)
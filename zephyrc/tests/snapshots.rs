@@ -0,0 +1,167 @@
+//! Snapshot Tests
+//!
+//! Each `.zph` file of `tests/cases` is a standalone module compiled to WebAssembly, its AST, HIR,
+//! MIR and WebAssembly text are compared against the files sharing its name with the `.ast`,
+//! `.hir`, `.mir` and `.wat` extensions. Each case is compiled twice to check that the compiler
+//! produces the same module and representations from one compilation to another.
+//!
+//! Snapshots are updated by passing `--bless`:
+//!
+//! ```bash
+//! cargo test -p zephyrc --test snapshots -- --bless
+//! ```
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use zephyr::error::ErrorHandler;
+use zephyr::resolver::ModulePath;
+use zephyr::{Ctx, Snapshot};
+use zephyrc::error_handler::StandardErrorHandler;
+use zephyrc::resolver::StandardResolver;
+
+const CASES: &str = "tests/cases";
+const BLESS: &str = "--bless";
+
+fn main() {
+    let bless = std::env::args().any(|arg| arg == BLESS);
+    if std::env::var_os("ZEPHYR_LIB").is_none() {
+        std::env::set_var(
+            "ZEPHYR_LIB",
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("../lib"),
+        );
+    }
+
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join(CASES);
+    let mut cases = fs::read_dir(&dir)
+        .expect("Could not read the snapshot cases")
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| path.extension().map_or(false, |ext| ext == "zph"))
+        .collect::<Vec<_>>();
+    cases.sort();
+
+    let mut nb_failures = 0;
+    for case in &cases {
+        let name = case.file_stem().unwrap().to_string_lossy();
+        match check(case, bless) {
+            Ok(()) => println!("snapshot {} ... ok", name),
+            Err(message) => {
+                println!("snapshot {} ... FAILED\n{}", name, message);
+                nb_failures += 1;
+            }
+        }
+    }
+    println!(
+        "\nsnapshot result: {} passed; {} failed",
+        cases.len() - nb_failures,
+        nb_failures
+    );
+    if nb_failures > 0 {
+        println!("Run with `{}` to update the snapshots", BLESS);
+        std::process::exit(1);
+    }
+}
+
+/// Compiles a case and compares its intermediate representations with the snapshots, or
+/// overwrites the snapshots if `bless` is set.
+fn check(case: &Path, bless: bool) -> Result<(), String> {
+    let (snapshot, module) = compile(case)?;
+    // Hash maps are seeded differently in each compilation
+    if compile(case)? != (snapshot.clone(), module) {
+        return Err(String::from(
+            "The output differs from one compilation to another",
+        ));
+    }
+    let mut mismatches = String::new();
+    for (extension, content) in &[
+        ("ast", &snapshot.ast),
+        ("hir", &snapshot.hir),
+        ("mir", &snapshot.mir),
+        ("wat", &snapshot.wat),
+    ] {
+        let path = case.with_extension(extension);
+        let content = &format!("{}\n", content.trim_end());
+        if bless {
+            fs::write(&path, content).map_err(|e| e.to_string())?;
+            continue;
+        }
+        match fs::read_to_string(&path) {
+            Ok(expected) if &expected == content => (),
+            Ok(expected) => {
+                mismatches.push_str(&format!("--- {}\n", path.display()));
+                mismatches.push_str(&diff(&expected, content));
+            }
+            Err(_) => mismatches.push_str(&format!("missing snapshot {}\n", path.display())),
+        }
+    }
+    if mismatches.is_empty() {
+        Ok(())
+    } else {
+        Err(mismatches)
+    }
+}
+
+/// Compiles a standalone module and returns its intermediate representations along with the
+/// WebAssembly module.
+fn compile(case: &Path) -> Result<(Snapshot, Vec<u8>), String> {
+    let mut resolver = StandardResolver::new();
+    let mut ctx = Ctx::new();
+    let mut err = StandardErrorHandler::new(ctx.sources().clone());
+    let messages = |err: &StandardErrorHandler| {
+        let errors = err.errors().into_iter().map(|(_, e)| e.message.clone());
+        errors.collect::<Vec<_>>().join("\n")
+    };
+
+    let path = PathBuf::from(case);
+    let module_name = resolver
+        .prepare_files(&path, &mut err)
+        .and_then(|(files, _)| ctx.get_module_name(files, &mut err))
+        .map_err(|_| messages(&err))?;
+    resolver.add_package(module_name.clone(), path);
+    let module = ModulePath::from_root(module_name);
+    ctx.set_snapshot(Some(module.clone()));
+    let wasm = ctx
+        .add_module(module, &mut err, &resolver)
+        .and_then(|_| ctx.get_wasm(&mut err, &resolver))
+        .map_err(|_| messages(&err))?;
+    if err.has_error() {
+        return Err(messages(&err));
+    }
+    let snapshot = ctx
+        .take_snapshot()
+        .ok_or_else(|| String::from("Nothing was recorded"))?;
+    Ok((snapshot, wasm))
+}
+
+/// Returns the lines removed from `expected` and added to `actual`, following their longest
+/// common subsequence.
+fn diff(expected: &str, actual: &str) -> String {
+    let old = expected.lines().collect::<Vec<_>>();
+    let new = actual.lines().collect::<Vec<_>>();
+    // lcs[i][j] is the length of the longest common subsequence of old[i..] and new[j..]
+    let mut lcs = vec![vec![0; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    let mut diff = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            diff.push_str(&format!("{:>4} - {}\n", i + 1, old[i]));
+            i += 1;
+        } else {
+            diff.push_str(&format!("{:>4} + {}\n", j + 1, new[j]));
+            j += 1;
+        }
+    }
+    diff
+}